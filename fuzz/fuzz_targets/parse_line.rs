@@ -0,0 +1,36 @@
+//! `parse_line` on arbitrary input: it must not panic, an error must be
+//! safe to send back as one `ERR` line, and a parsed command must render
+//! to a line that parses back to the same command.
+//!
+//!     cargo +nightly fuzz run parse_line
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ouroboros_fs::codec::is_compressed;
+use ouroboros_fs::protocol::parse_line;
+
+fuzz_target!(|data: &[u8]| {
+    // The server reads lines as UTF-8 and rejects anything else first.
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    let cmd = match parse_line(line) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            let msg = e.to_string();
+            assert!(!msg.contains('\n'), "newline in error for {line:?}: {msg:?}");
+            return;
+        }
+    };
+    // A field that still looks compressed after expansion is expanded
+    // again on the way back; that ambiguity is by design.
+    if cmd.payload().is_some_and(is_compressed) {
+        return;
+    }
+    let rendered = format!("{cmd}\n");
+    match parse_line(&rendered) {
+        Ok(again) => assert_eq!(again, cmd, "{line:?} rendered as {rendered:?}"),
+        Err(e) => panic!("{line:?} rendered as {rendered:?}, which fails: {e}"),
+    }
+});
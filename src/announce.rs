@@ -0,0 +1,166 @@
+//! Multicast discovery (`run --announce`, the `discover` subcommand and
+//! `dev-network --auto-discover`).
+//!
+//! A node started with `--announce <group>` sends the UDP datagram
+//! `ANNOUNCE <node_addr> <name> <generation>` to that multicast group
+//! every `--announce-interval-secs`, starting as soon as it listens.
+//! Sending to a group needs no membership, so only [`discover`] joins it.
+//! Datagrams are unauthenticated: anything on the subnet can announce
+//! itself, so a ring wired from them should still run with
+//! `--auth-token`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::error::RingError;
+
+/// Announcement interval when `--announce-interval-secs` is not given.
+pub const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What one `ANNOUNCE` datagram says about its node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Announcement {
+    pub addr: String,
+    /// `<unset>` for an unnamed node.
+    pub name: String,
+    pub generation: u64,
+}
+
+impl fmt::Display for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ANNOUNCE {} {} {}",
+            self.addr, self.name, self.generation
+        )
+    }
+}
+
+impl FromStr for Announcement {
+    type Err = RingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let ["ANNOUNCE", addr, name, generation] = parts.as_slice() else {
+            return Err(RingError::ParseError(format!("malformed ANNOUNCE: {s}")));
+        };
+        Ok(Announcement {
+            addr: addr.to_string(),
+            name: name.to_string(),
+            generation: generation
+                .parse()
+                .map_err(|_| RingError::ParseError(format!("invalid generation: {generation}")))?,
+        })
+    }
+}
+
+/// Check that `group` is a multicast address with a port.
+pub fn check_group(group: SocketAddr) -> Result<(), String> {
+    if !group.ip().is_multicast() {
+        return Err(format!("{group} is not a multicast address"));
+    }
+    if group.port() == 0 {
+        return Err(format!("{group} needs a port"));
+    }
+    Ok(())
+}
+
+/// A socket to send announcements to `group` from.
+pub async fn sender(group: SocketAddr) -> std::io::Result<UdpSocket> {
+    let local: IpAddr = match group {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    UdpSocket::bind((local, 0)).await
+}
+
+/// Send `announcement` to `group` once.
+pub async fn announce(
+    socket: &UdpSocket,
+    group: SocketAddr,
+    announcement: &Announcement,
+) -> std::io::Result<()> {
+    socket
+        .send_to(format!("{announcement}\n").as_bytes(), group)
+        .await?;
+    Ok(())
+}
+
+/// Join `group` and collect announcements for `duration`. One entry per
+/// address, the latest generation heard winning, sorted by address.
+/// Datagrams that are not an `ANNOUNCE` are skipped.
+pub async fn discover(
+    group: SocketAddr,
+    duration: Duration,
+) -> Result<Vec<Announcement>, RingError> {
+    check_group(group)?;
+    let socket = match group.ip() {
+        IpAddr::V4(ip) => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, group.port())).await?;
+            socket.join_multicast_v4(ip, Ipv4Addr::UNSPECIFIED)?;
+            socket
+        }
+        IpAddr::V6(ip) => {
+            let socket = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, group.port())).await?;
+            socket.join_multicast_v6(&ip, 0)?;
+            socket
+        }
+    };
+
+    let mut found: BTreeMap<String, Announcement> = BTreeMap::new();
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut buf = [0u8; 1024];
+    while let Ok(read) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (n, from) = read?;
+        let text = String::from_utf8_lossy(&buf[..n]);
+        match text.trim_end().parse::<Announcement>() {
+            Ok(a) => {
+                if found
+                    .get(&a.addr)
+                    .is_none_or(|seen| seen.generation <= a.generation)
+                {
+                    found.insert(a.addr.clone(), a);
+                }
+            }
+            Err(e) => tracing::debug!(from = %from, error = %e, "Ignoring datagram"),
+        }
+    }
+    Ok(found.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announcement_round_trips() {
+        let a = Announcement {
+            addr: "10.0.0.5:7000".into(),
+            name: "node-00".into(),
+            generation: 3,
+        };
+        assert_eq!(a.to_string(), "ANNOUNCE 10.0.0.5:7000 node-00 3");
+        assert_eq!(a.to_string().parse::<Announcement>().unwrap(), a);
+        for bad in [
+            "",
+            "ANNOUNCE 10.0.0.5:7000 node-00",
+            "HELLO a b 1",
+            "ANNOUNCE a b x",
+        ] {
+            assert!(bad.parse::<Announcement>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn only_multicast_groups_are_accepted() {
+        assert!(check_group("224.0.0.251:9099".parse().unwrap()).is_ok());
+        assert!(check_group("[ff02::fb]:9099".parse().unwrap()).is_ok());
+        assert!(check_group("127.0.0.1:9099".parse().unwrap()).is_err());
+        assert!(check_group("239.1.2.3:0".parse().unwrap()).is_err());
+    }
+}
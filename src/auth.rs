@@ -39,6 +39,12 @@ impl std::fmt::Debug for AuthToken {
     }
 }
 
+impl Default for AuthToken {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
 impl AuthToken {
     pub fn disabled() -> Self {
         Self { secret: None }
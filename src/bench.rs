@@ -0,0 +1,204 @@
+//! Ring throughput and latency benchmark (`bench`, `bench-network`).
+//!
+//! Sends `RING ECHO <ttl> bench-<n>` to one node at a fixed rate for a
+//! fixed time. A request's latency runs from when it was due to go out to
+//! when the echo came back around the ring, so a slow ring shows up as
+//! latency rather than as a lower send rate. Requests share a small set of
+//! connections; a request due while its connection is busy waits for it
+//! and that wait counts too.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::client::{ClientOpts, RingClient};
+use crate::error::RingError;
+
+/// What to send, and how fast.
+#[derive(Clone, Debug)]
+pub struct BenchOpts {
+    /// Requests per second.
+    pub rps: u32,
+    pub duration: Duration,
+    /// Hops each echo makes; the ring size for one full lap.
+    pub ttl: u32,
+    /// Connections the requests are spread over.
+    pub connections: usize,
+}
+
+/// Latencies in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Latency {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BenchReport {
+    pub addr: String,
+    pub ttl: u32,
+    pub target_rps: u32,
+    pub sent: u64,
+    pub ok: u64,
+    pub errors: u64,
+    /// From the first request until the last reply, in seconds.
+    pub elapsed_secs: f64,
+    /// Successful echoes per second over `elapsed_secs`.
+    pub throughput: f64,
+    pub latency_ms: Latency,
+}
+
+impl BenchReport {
+    /// The report as an aligned two-column table.
+    pub fn table(&self) -> String {
+        let l = &self.latency_ms;
+        let rows = [
+            ("target", self.addr.clone()),
+            ("ttl", self.ttl.to_string()),
+            ("rate", format!("{} req/s", self.target_rps)),
+            ("sent", self.sent.to_string()),
+            ("ok", self.ok.to_string()),
+            ("errors", self.errors.to_string()),
+            ("elapsed", format!("{:.2} s", self.elapsed_secs)),
+            ("throughput", format!("{:.1} msg/s", self.throughput)),
+            ("latency min", format!("{:.3} ms", l.min)),
+            ("latency mean", format!("{:.3} ms", l.mean)),
+            ("latency p50", format!("{:.3} ms", l.p50)),
+            ("latency p95", format!("{:.3} ms", l.p95)),
+            ("latency p99", format!("{:.3} ms", l.p99)),
+            ("latency max", format!("{:.3} ms", l.max)),
+        ];
+        rows.iter()
+            .map(|(k, v)| format!("{k:<14}{v:>16}\n"))
+            .collect()
+    }
+}
+
+/// Benchmark the ring through the node at `addr`.
+pub async fn run_bench(
+    addr: &str,
+    client: ClientOpts,
+    opts: &BenchOpts,
+) -> Result<BenchReport, RingError> {
+    if opts.rps == 0 || opts.connections == 0 {
+        return Err(RingError::Other(
+            "rps and connections must be at least 1".into(),
+        ));
+    }
+    let clients: Vec<Arc<RingClient>> = (0..opts.connections)
+        .map(|_| Arc::new(RingClient::with_opts(addr, client.clone())))
+        .collect();
+    // Fail up front on an unreachable node rather than after `duration`.
+    clients[0].ping().await?;
+
+    let mut tick = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(opts.rps)));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let started = Instant::now();
+    let deadline = started + opts.duration;
+    let mut requests = JoinSet::new();
+    let mut sent = 0u64;
+    loop {
+        let due = tokio::select! {
+            due = tick.tick() => due,
+            _ = tokio::time::sleep_until(deadline) => break,
+        };
+        if due >= deadline {
+            break;
+        }
+        let client = Arc::clone(&clients[sent as usize % clients.len()]);
+        let (ttl, msg) = (opts.ttl, format!("bench-{sent}"));
+        requests.spawn(async move {
+            match client.echo(ttl, &msg).await {
+                Ok(echo) if echo == msg => Ok(due.elapsed()),
+                Ok(echo) => Err(format!("echo mismatch: sent '{msg}', got '{echo}'")),
+                Err(e) => Err(e.to_string()),
+            }
+        });
+        sent += 1;
+    }
+
+    let mut latencies = Vec::with_capacity(sent as usize);
+    let mut errors = 0u64;
+    while let Some(res) = requests.join_next().await {
+        match res {
+            Ok(Ok(latency)) => latencies.push(latency),
+            Ok(Err(e)) => {
+                tracing::debug!(addr = %addr, error = %e, "Bench request failed");
+                errors += 1;
+            }
+            Err(e) => {
+                tracing::debug!(addr = %addr, error = ?e, "Bench request panicked");
+                errors += 1;
+            }
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let ok = latencies.len() as u64;
+    Ok(BenchReport {
+        addr: addr.to_string(),
+        ttl: opts.ttl,
+        target_rps: opts.rps,
+        sent,
+        ok,
+        errors,
+        elapsed_secs: elapsed,
+        throughput: if elapsed > 0.0 {
+            ok as f64 / elapsed
+        } else {
+            0.0
+        },
+        latency_ms: summarize(latencies),
+    })
+}
+
+/// Nearest-rank percentiles of `samples`; all zero when there are none.
+fn summarize(mut samples: Vec<Duration>) -> Latency {
+    if samples.is_empty() {
+        return Latency::default();
+    }
+    samples.sort();
+    let ms = |d: Duration| d.as_secs_f64() * 1e3;
+    let rank = |p: f64| {
+        let i = (p / 100.0 * samples.len() as f64).ceil() as usize;
+        ms(samples[i.clamp(1, samples.len()) - 1])
+    };
+    let total: Duration = samples.iter().sum();
+    Latency {
+        min: ms(samples[0]),
+        mean: ms(total) / samples.len() as f64,
+        p50: rank(50.0),
+        p95: rank(95.0),
+        p99: rank(99.0),
+        max: ms(samples[samples.len() - 1]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let l = summarize(samples);
+        assert_eq!(
+            (l.min, l.p50, l.p95, l.p99, l.max),
+            (1.0, 50.0, 95.0, 99.0, 100.0)
+        );
+        assert!((l.mean - 50.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn few_samples_and_none() {
+        let l = summarize(vec![Duration::from_millis(7), Duration::from_millis(3)]);
+        assert_eq!((l.p50, l.p99), (3.0, 7.0));
+        assert_eq!(summarize(Vec::new()), Latency::default());
+    }
+}
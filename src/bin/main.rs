@@ -1,12 +1,47 @@
-use clap::{Parser, Subcommand, ValueEnum};
-use ouroboros_fs::{AuthToken, FsyncMode, run};
-use serde::Deserialize;
-use std::{env, error::Error, fs, path::Path, path::PathBuf, sync::Arc, time::Duration};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ouroboros_fs::{
+    AuthToken, ClientOpts, FsyncMode, NodeOpts, RingClient, RingError, TlsConfig,
+    announce::{self, DEFAULT_ANNOUNCE_INTERVAL},
+    bench::{BenchOpts, BenchReport, run_bench},
+    chaos::ChaosMode,
+    chring::RoutingMode,
+    client::NodeInfo,
+    config::{Config, GatewayConfig, NetworkConfig, RunConfig, Section},
+    devnet::{
+        Member, NetworkAction, NetworkSpec, members, parse_listening, plan_discovered, plan_leave,
+        plan_network, plan_restart, plan_resume,
+    },
+    diagnose::diagnose,
+    events::EventFilter,
+    heartbeat::{DEFAULT_HEARTBEAT_INTERVAL, HeartbeatCoordinator, MembershipChange},
+    lint,
+    node::{
+        DEFAULT_BIND_HOST, DEFAULT_RING, join_host_port, normalize_addr_on, port_str,
+        resolve_listen_addr, split_label, validate_node_name, validate_ring_name,
+    },
+    protocol::{Edge, now_unix_ms, render_history_table, roundtrip_history},
+    repair::RepairCoordinator,
+    repl, replay, run,
+    runtime::{self, RuntimeOpts},
+    script,
+    snapshot::NodeSnapshot,
+    topology::parse_history,
+    topology_diff::{self, HOT_RELOAD_POLL},
+    trace::OtlpEndpoint,
+    transport::UNIX_SCHEME,
+    tui,
+    util::{BackoffConfig, backoff_sleep},
+};
+use std::{
+    collections::HashMap, env, error::Error, fs, io::IsTerminal, net::SocketAddr, path::Path,
+    path::PathBuf, process::Stdio, time::Duration,
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, BufReader},
     net::TcpStream,
     process::{Child, Command},
-    time::sleep,
+    sync::watch,
+    task::JoinHandle,
 };
 use tracing_subscriber::{EnvFilter, fmt};
 
@@ -18,6 +53,27 @@ struct Cli {
     #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
     log_format: LogFormat,
 
+    /// Log filter: a level (`error`, `warn`, `info`, `debug`, `trace`) or
+    /// any `RUST_LOG`-style directive such as `ouroboros_fs::server=debug`.
+    /// Overrides `RUST_LOG`; defaults to `info` when neither is set.
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    /// Tokio worker threads. Defaults to one per CPU; 1 runs everything
+    /// on the main thread (current-thread runtime).
+    #[arg(long, global = true)]
+    worker_threads: Option<usize>,
+
+    /// Stack size of each runtime thread, in KiB. Defaults to 128 (1024 in
+    /// a debug build).
+    #[arg(long, global = true, default_value_t = runtime::DEFAULT_STACK_SIZE_KB)]
+    stack_size_kb: usize,
+
+    /// Cap on the threads that run blocking file I/O. Defaults to Tokio's
+    /// 512.
+    #[arg(long, global = true)]
+    max_blocking_threads: Option<usize>,
+
     #[command(subcommand)]
     command: Cmd,
 }
@@ -28,84 +84,50 @@ enum LogFormat {
     Json,
 }
 
-/// TOML schema for `--config` on `Cmd::Run`. Every field is `Option<T>` so
-/// the file may set any subset; missing fields fall through to built-in
-/// defaults. CLI flags override file values. (NEXT_STEPS.md §4.5.)
-#[derive(Default, Deserialize)]
-struct RunConfig {
-    addr: Option<String>,
-    wait_time: Option<u64>,
-    file_size: Option<u64>,
-    storage_root: Option<PathBuf>,
-    fsync_mode: Option<CliFsyncMode>,
-    auth_token: Option<String>,
-    idle_timeout: Option<u64>,
-    max_conns: Option<u32>,
-    shutdown_timeout: Option<u64>,
-}
-
-/// TOML schema for `--config` on `Cmd::Gateway`. The file's top-level
-/// table is `[gateway]` so a single config file can describe both run and
-/// gateway sections; we only deserialize `[gateway]` here.
-#[derive(Default, Deserialize)]
-struct GatewayConfig {
-    listen: Option<String>,
-    #[serde(default)]
-    nodes: Vec<String>,
-    auth_token: Option<String>,
+/// CLI mirror of `RoutingMode` for `--routing`, like [`CliFsyncMode`].
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum CliRoutingMode {
+    Sequential,
+    ConsistentHash,
 }
 
-#[derive(Default, Deserialize)]
-struct GatewayConfigWrapper {
-    #[serde(default)]
-    gateway: GatewayConfig,
+/// CLI mirror of `FsyncMode` so clap can derive a `--fsync-mode` value parser
+/// without adding a `clap` dep to the library crate.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum CliFsyncMode {
+    None,
+    Data,
+    Full,
 }
 
-#[derive(Default, Deserialize)]
-struct RunConfigWrapper {
-    #[serde(default)]
-    run: RunConfig,
+/// Output of the `topology` subcommand.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum TopologyFormat {
+    Dot,
+    Json,
 }
 
-fn load_run_config(path: &Path) -> Result<RunConfig, Box<dyn Error + Send + Sync>> {
-    let raw =
-        fs::read_to_string(path).map_err(|e| format!("read config {}: {e}", path.display()))?;
-    // Accept either `[run]` table or top-level keys for back-compat with
-    // simple deployments.
-    if raw.contains("[run]") {
-        let w: RunConfigWrapper =
-            toml::from_str(&raw).map_err(|e| format!("parse {}: {e}", path.display()))?;
-        Ok(w.run)
-    } else {
-        let cfg: RunConfig =
-            toml::from_str(&raw).map_err(|e| format!("parse {}: {e}", path.display()))?;
-        Ok(cfg)
-    }
+/// Output of the `diagnose` subcommand.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum DiagnoseOutput {
+    Text,
+    Json,
 }
 
-fn load_gateway_config(path: &Path) -> Result<GatewayConfig, Box<dyn Error + Send + Sync>> {
-    let raw =
-        fs::read_to_string(path).map_err(|e| format!("read config {}: {e}", path.display()))?;
-    if raw.contains("[gateway]") {
-        let w: GatewayConfigWrapper =
-            toml::from_str(&raw).map_err(|e| format!("parse {}: {e}", path.display()))?;
-        Ok(w.gateway)
-    } else {
-        let cfg: GatewayConfig =
-            toml::from_str(&raw).map_err(|e| format!("parse {}: {e}", path.display()))?;
-        Ok(cfg)
-    }
+/// Output of `dev-network --dry-run`.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum PlanFormat {
+    Text,
+    Json,
 }
 
-/// CLI mirror of `FsyncMode` so clap can derive a `--fsync-mode` value parser
-/// without adding a `clap` dep to the library crate. Also Deserialize so
-/// the same enum works in TOML config files.
-#[derive(Copy, Clone, Debug, ValueEnum, Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum CliFsyncMode {
-    None,
-    Data,
-    Full,
+impl From<CliRoutingMode> for RoutingMode {
+    fn from(m: CliRoutingMode) -> Self {
+        match m {
+            CliRoutingMode::Sequential => RoutingMode::Sequential,
+            CliRoutingMode::ConsistentHash => RoutingMode::ConsistentHash,
+        }
+    }
 }
 
 impl From<CliFsyncMode> for FsyncMode {
@@ -118,6 +140,53 @@ impl From<CliFsyncMode> for FsyncMode {
     }
 }
 
+/// Load settings shared by `bench` and `bench-network`.
+#[derive(Args, Clone, Debug)]
+struct BenchArgs {
+    /// Requests per second.
+    #[arg(long, default_value_t = 100)]
+    rps: u32,
+    /// How long to send for.
+    #[arg(long, default_value_t = 10)]
+    duration_secs: u64,
+    /// Hops per echo. Defaults to one lap of the ring.
+    #[arg(long)]
+    ttl: Option<u32>,
+    /// Connections to spread the requests over.
+    #[arg(long, default_value_t = 8)]
+    connections: usize,
+    /// Also write the results as JSON to this file.
+    #[arg(long)]
+    json_out: Option<PathBuf>,
+}
+
+/// Connection settings shared by `set-next`, `get`, `snapshot`,
+/// `restore`, `ring` and `walk`.
+#[derive(Args, Clone, Debug)]
+struct CallArgs {
+    /// Node to send the command to.
+    #[arg(long, default_value = "127.0.0.1:7000")]
+    addr: String,
+    /// Give up after this long. Defaults to 5 s, or 35 s for `walk`.
+    #[arg(long)]
+    timeout_secs: Option<u64>,
+    /// Also write the reply as JSON to this file.
+    #[arg(long)]
+    json_out: Option<PathBuf>,
+    /// Pre-shared AUTH token (64-char hex). Falls back to the
+    /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+    #[arg(long, alias = "auth-key")]
+    auth_token: Option<String>,
+    /// PEM CA bundle; when set, the request uses TLS.
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+    /// Expect the `HELLO` version greeting; see `run --hello`.
+    #[arg(long)]
+    hello: bool,
+}
+
+// Parsed once at startup; boxing `Run`'s flags would buy nothing.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Cmd {
     /// Run a single node (server). Any flag may also be set via
@@ -131,9 +200,19 @@ enum Cmd {
         /// Address to bind. If omitted, see --port, then $PORT, then default.
         #[arg(long)]
         addr: Option<String>,
-        /// Provide only the port, and host defaults to 127.0.0.1
+        /// Provide only the port; the host comes from --bind-host. 0 lets
+        /// the OS pick one; it is printed as `LISTENING <port>` on stdout.
         #[arg(short, long)]
         port: Option<u16>,
+        /// Host a bare port (--port, $PORT, a port-only --addr) binds on.
+        /// Use 0.0.0.0 or `::` to listen on every interface. Defaults to
+        /// 127.0.0.1.
+        #[arg(long)]
+        bind_host: Option<String>,
+        /// Listen on a Unix domain socket at this path instead of TCP.
+        /// Peers reach the node as `unix:<path>`. For nodes sharing a host.
+        #[arg(long, conflicts_with_all = ["addr", "port", "bind_host"])]
+        socket_path: Option<PathBuf>,
         /// Time (ms) between health checks to the next node. 0 to disable. Defaults to 5000.
         #[arg(long)]
         wait_time: Option<u64>,
@@ -152,17 +231,267 @@ enum Cmd {
         /// Pre-shared key (64 hex chars / 32 bytes) for the wire-protocol
         /// AUTH handshake. Falls back to the OUROBOROS_AUTH_TOKEN env var
         /// and then to the config file. Disabled if none of those is set.
-        #[arg(long)]
+        #[arg(long, alias = "auth-key")]
         auth_token: Option<String>,
         /// Per-connection idle timeout in seconds. 0 disables. Defaults to 60.
         #[arg(long)]
         idle_timeout: Option<u64>,
-        /// Max concurrent client connections. 0 disables. Defaults to 1024.
+        /// Milliseconds a new connection may take to send its first
+        /// command, after AUTH; later commands get --idle-timeout. 0 uses
+        /// --idle-timeout for the first one too. Defaults to 5000.
         #[arg(long)]
-        max_conns: Option<u32>,
-        /// Graceful-shutdown drain timeout in seconds. Defaults to 30.
+        accept_timeout_ms: Option<u64>,
+        /// Largest TTL a RING command may carry; larger ones get `ERR ttl
+        /// exceeds max <n>`. 0 disables the cap. Defaults to 1024.
+        #[arg(long)]
+        max_ring_ttl: Option<u32>,
+        /// Most hops a forwarded RING message may take; past it the
+        /// message is dropped and a RING_STORM event raised. 0 disables.
+        /// Defaults to 10 times --max-ring-ttl.
         #[arg(long)]
+        max_ring_hops: Option<u32>,
+        /// Most edges a TOPOLOGY WALK reports, and the cap on WALK-MAX's
+        /// own limit. 0 disables the cap. Defaults to 1024.
+        #[arg(long)]
+        max_walk_hops: Option<u32>,
+        /// Most nodes a chain of nested NODE FORWARD-TO commands may pass
+        /// through. 0 disables the check. Defaults to 3.
+        #[arg(long)]
+        forward_max_hops: Option<u32>,
+        /// TTL a RING ALL uses until a TOPOLOGY WALK has counted the ring.
+        /// Defaults to 64.
+        #[arg(long)]
+        default_ring_ttl: Option<u32>,
+        /// Max concurrent client connections; past it, a new connection
+        /// gets `ERR server busy` and is closed. 0 disables. Defaults to
+        /// 1024.
+        #[arg(long, visible_alias = "max-connections")]
+        max_conns: Option<u32>,
+        /// Graceful-shutdown drain timeout in seconds, for SIGTERM/SIGINT
+        /// and `NODE SHUTDOWN`. Defaults to 30.
+        #[arg(long, alias = "shutdown-timeout-secs")]
         shutdown_timeout: Option<u64>,
+        /// PEM certificate chain for the listener. Enables TLS on inbound
+        /// connections; requires --tls-key and --tls-ca.
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key matching --tls-cert.
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// PEM CA bundle used to verify peers on outbound ring connections.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Human-readable node label, reported by NODE STATUS and shown
+        /// as `name@port` in walk output.
+        #[arg(long)]
+        name: Option<String>,
+        /// JSON file to persist NEXT/PREV to on every change and restore
+        /// them from on startup, so a restarted node rejoins the ring.
+        #[arg(long)]
+        state_file: Option<PathBuf>,
+        /// Seconds a `RING ACK` start node waits for the seen list.
+        /// Defaults to 30.
+        #[arg(long)]
+        ring_ack_timeout: Option<u64>,
+        /// Idle connections kept per neighbor for hop forwards. 0 opens a
+        /// fresh connection per hop. Defaults to 4.
+        #[arg(long)]
+        pool_size: Option<usize>,
+        /// Send hop forwards as channels of one multiplexed connection per
+        /// neighbor (`MUX`) instead of pooled connections. Every node the
+        /// ring forwards to must run with `--mux` too; others refuse `MUX`.
+        #[arg(long)]
+        mux: bool,
+        /// Greet every connection with `HELLO ouroboros/<version>` and
+        /// require the same from every peer dialled. Use on every node of
+        /// the ring or none.
+        #[arg(long)]
+        hello: bool,
+        /// Longest protocol line accepted, newline included. Longer lines
+        /// get `ERR message too large` and the connection is closed. 0
+        /// disables the limit. Defaults to 1048576 (1 MiB).
+        #[arg(long)]
+        max_line_bytes: Option<usize>,
+        /// Shell command supplying this node's value for a `TOPOLOGY
+        /// PAY-WALK` key it has no built-in value for. Runs as `sh -c
+        /// <cmd> sh <key>`; the first line of stdout is used.
+        #[arg(long)]
+        walk_payload_cmd: Option<String>,
+        /// Store `NODE NEXT` hostnames as given instead of resolving them
+        /// to the first `ip:port` that accepts a connection.
+        #[arg(long)]
+        no_dns_resolve: bool,
+        /// Refuse a `NODE NEXT` target that does not accept a connection
+        /// within a second.
+        #[arg(long)]
+        verify_next_reachable: bool,
+        /// Leave Nagle's algorithm on for ring connections instead of
+        /// setting TCP_NODELAY. For benchmarking.
+        #[arg(long)]
+        no_nodelay: bool,
+        /// Consecutive failed forwards to a peer before its circuit opens
+        /// and forwards to it fail immediately. 0 disables. Defaults to 5.
+        #[arg(long)]
+        cb_failure_threshold: Option<u32>,
+        /// Seconds an open circuit fails fast before one probe forward is
+        /// let through. Defaults to 30.
+        #[arg(long)]
+        cb_reset_timeout_secs: Option<u64>,
+        /// Commands per second each client connection may send; extra
+        /// commands get `ERR rate limited` and are dropped. 0 disables.
+        /// Defaults to 0.
+        #[arg(long)]
+        rate_limit_rps: Option<u32>,
+        /// Set SO_REUSEPORT on the listener so a restarted node can bind
+        /// while the old process still holds the port. Another process can
+        /// then share the port too, so only use it under a supervisor.
+        #[arg(long)]
+        reuse_port: bool,
+        /// Length of the listener's accept queue. Raise it when many
+        /// clients connect at once. Defaults to 128.
+        #[arg(long)]
+        bind_backlog: Option<u32>,
+        /// SO_RCVBUF for the listener and the connections it accepts, in
+        /// bytes. Defaults to the OS setting.
+        #[arg(long)]
+        recv_buf_size: Option<u32>,
+        /// SO_SNDBUF for the listener and the connections it accepts, in
+        /// bytes. Defaults to the OS setting.
+        #[arg(long)]
+        send_buf_size: Option<u32>,
+        /// Longest key `KV SET` accepts, in bytes. Defaults to 256.
+        #[arg(long)]
+        kv_max_key_bytes: Option<usize>,
+        /// Longest value `KV SET` accepts, in bytes. Defaults to 4096.
+        #[arg(long)]
+        kv_max_value_bytes: Option<usize>,
+        /// Serve Prometheus metrics at `http://<host>:<port>/metrics`,
+        /// on the node's own host. Off unless set.
+        #[arg(long)]
+        metrics_port: Option<u16>,
+        /// Export spans for `RING FORWARD` and `TOPOLOGY WALK` traversals to
+        /// this OpenTelemetry collector, as OTLP/HTTP JSON
+        /// (`http://host:4318`). Off unless set.
+        #[arg(long)]
+        otlp_endpoint: Option<String>,
+        /// `RING FORWARD` lines each node remembers (as SHA-256 hashes)
+        /// so a repeat of one is dropped instead of forwarded again. 0
+        /// disables. Defaults to 1000.
+        #[arg(long)]
+        dedup_cache_size: Option<usize>,
+        /// Seconds a remembered line counts as a repeat. Defaults to 60.
+        #[arg(long)]
+        dedup_ttl_secs: Option<u64>,
+        /// `RING` messages and `TOPOLOGY PAY-WALK` payloads longer than
+        /// this many bytes are sent compressed (`z:<base64>`, a zstd
+        /// frame). 0 disables.
+        /// Defaults to 4096.
+        #[arg(long)]
+        compress_threshold_bytes: Option<usize>,
+        /// When a `RING FORWARD` to the next node fails, retries included,
+        /// send `PROBE <next>` to `--repair-coordinator` so it can stitch
+        /// the ring around the dead node.
+        #[arg(long)]
+        repair_ring: bool,
+        /// Address of the repair coordinator, e.g. a `dev-network
+        /// --repair-port`. Required by `--repair-ring`.
+        #[arg(long)]
+        repair_coordinator: Option<String>,
+        /// Append every `RING FORWARD` this node handles to this file, one
+        /// JSON object per line, for `replay-log` to re-send later.
+        #[arg(long)]
+        replay_log: Option<PathBuf>,
+        /// Every this many seconds, send a probe round the ring; one that
+        /// is not back by the next is logged and counted as a partition
+        /// and sent to `partition` subscribers. 0 disables, the default.
+        #[arg(long)]
+        probe_interval_secs: Option<u64>,
+        /// Answer a client's TOPOLOGY WALK from the last result for this
+        /// many seconds, under a `CACHED <age_ms>` line; NODE NEXT clears
+        /// it and TOPOLOGY WALK-FRESH always walks. 0 disables, the default.
+        #[arg(long)]
+        walk_cache_ttl_secs: Option<u64>,
+        /// Send `HEARTBEAT <addr> <name> <generation> <next>` to this
+        /// `coordinator` every --heartbeat-interval-secs. Off unless set.
+        #[arg(long)]
+        heartbeat_addr: Option<String>,
+        /// Seconds between heartbeats. Defaults to 5.
+        #[arg(long)]
+        heartbeat_interval_secs: Option<u64>,
+        /// Milliseconds TOPOLOGY WALK-PARALLEL waits for the heartbeat
+        /// members to answer; any still silent are reported unreachable.
+        /// Defaults to 5000.
+        #[arg(long)]
+        parallel_walk_timeout_ms: Option<u64>,
+        /// Send `ANNOUNCE <addr> <name> <generation>` to this multicast
+        /// group (e.g. `224.0.0.251:9099`) every --announce-interval-secs,
+        /// for `discover` and `dev-network --auto-discover`. Off unless set.
+        #[arg(long)]
+        announce: Option<SocketAddr>,
+        /// Seconds between announcements. Defaults to 5.
+        #[arg(long)]
+        announce_interval_secs: Option<u64>,
+        /// Count the ring with a walk every --monitor-interval-secs; a
+        /// count that differs from the last is logged as TOPOLOGY_CHANGE
+        /// and sent to `topology` subscribers. `NODE STAT` reports the
+        /// last count as RING_SIZE.
+        #[arg(long)]
+        monitor_topology: bool,
+        /// Seconds between --monitor-topology counts. Defaults to 30.
+        #[arg(long)]
+        monitor_interval_secs: Option<u64>,
+        /// Seconds between SPLIT-BRAIN CHECK walks from this node; an
+        /// error is logged whenever the ring names more than one leader.
+        /// 0 (the default) disables.
+        #[arg(long)]
+        split_brain_check_interval_secs: Option<u64>,
+        /// How RING KEY is routed. `consistent-hash` sends each key to its
+        /// owner on a hash ring of the --heartbeat-addr members, which it
+        /// needs; `sequential` (the default) refuses RING KEY.
+        #[arg(long, value_enum)]
+        routing: Option<CliRoutingMode>,
+        /// File of protocol commands, one per line, to send the node
+        /// before it accepts connections. Blank lines and `#` comments are
+        /// skipped; a failing command stops the node from starting.
+        #[arg(long)]
+        script: Option<PathBuf>,
+        /// Keep going past a failing --script command.
+        #[arg(long)]
+        continue_on_error: bool,
+    },
+
+    /// Collect heartbeats from nodes started with `run --heartbeat-addr`
+    /// and print `JOIN <addr> <name> <generation>` and `DEAD <addr>` as
+    /// members come and go, until Ctrl-C. Answers `MEMBERS` with the live
+    /// ones.
+    Coordinator {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:7800")]
+        listen: String,
+        /// Seconds between the nodes' heartbeats; a node is dead after
+        /// missing three.
+        #[arg(long, default_value_t = DEFAULT_HEARTBEAT_INTERVAL.as_secs())]
+        heartbeat_interval_secs: u64,
+        /// Pre-shared AUTH token (64-char hex) the nodes use. Falls back
+        /// to the OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// Greet every connection with `HELLO`, for nodes started with
+        /// `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Listen on a multicast group for the `ANNOUNCE`s of nodes started
+    /// with `run --announce` and print one `<addr> <name> <generation>`
+    /// line per node found.
+    Discover {
+        /// The group the nodes announce to.
+        #[arg(long, default_value = "224.0.0.251:9099")]
+        group: SocketAddr,
+        /// How long to listen.
+        #[arg(long, default_value_t = 10)]
+        duration_secs: u64,
     },
 
     /// Run a standalone gateway pointed at one or more existing ring
@@ -182,8 +511,15 @@ enum Cmd {
         nodes: Vec<String>,
         /// Pre-shared bearer/AUTH token (64-char hex). Falls back to the
         /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
-        #[arg(long)]
+        #[arg(long, alias = "auth-key")]
         auth_token: Option<String>,
+        /// PEM CA bundle; when set, gateway → ring connections use TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting from ring nodes started
+        /// with `run --hello`.
+        #[arg(long)]
+        hello: bool,
     },
 
     /// Spawn N nodes and stitch them into a ring. Development helper —
@@ -191,54 +527,541 @@ enum Cmd {
     /// the entry point. The legacy `set-network` name still works.
     #[command(alias = "set-network")]
     DevNetwork {
-        /// Number of nodes to start
-        #[arg(short = 'n', long = "nodes", default_value_t = 3)]
-        nodes: u16,
-        /// Base port to use (ports are base, base+1, ..., base+N-1)
-        #[arg(short = 'p', long = "base-port", default_value_t = 7000)]
-        base_port: u16,
-        /// Interface to bind and to use when wiring SET_NEXT
-        #[arg(long, default_value = "127.0.0.1")]
-        host: String,
+        /// Path to a TOML config file whose `[network]` table (or bare
+        /// keys) provides defaults for any flag not passed.
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Number of nodes to start. Defaults to 3.
+        #[arg(short = 'n', long = "nodes")]
+        nodes: Option<u16>,
+        /// Base port to use (ports are base, base+1, ..., base+N-1).
+        /// Defaults to 7000. 0 lets the OS pick each node's port.
+        #[arg(short = 'p', long = "base-port")]
+        base_port: Option<u16>,
+        /// Interface to bind and to use when wiring SET_NEXT. IPv6
+        /// literals are accepted bare (`::1`). Defaults to 127.0.0.1.
+        #[arg(long)]
+        host: Option<String>,
         /// Do not block, just start and wire nodes, then return
         #[arg(long)]
         no_block: bool,
-        /// Extra wait after spawning children before wiring (ms)
-        #[arg(long, default_value_t = 200u64)]
-        wait_ms: u64,
+        /// Once the ring is wired, return to the terminal and keep running
+        /// in the background (Unix only). Stop it with `stop --pid-file`.
+        #[arg(long, requires = "pid_file", conflicts_with = "no_block")]
+        detach: bool,
+        /// Where `--detach` writes the background process's PID.
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+        /// Extra wait after spawning children before wiring (ms). Defaults
+        /// to 200.
+        #[arg(long)]
+        wait_ms: Option<u64>,
         /// Time (ms) between health checks for each node. 0 to disable.
-        #[arg(short = 'w', long = "wait-time", default_value_t = 5000u64)]
-        wait_time: u64,
+        /// Defaults to 5000.
+        #[arg(short = 'w', long = "wait-time")]
+        wait_time: Option<u64>,
         /// Inform if the "nodes" directory should be reused.
         #[arg(short, long)]
         overwrite_nodes_dir: bool,
         /// Run the DNS Gateway on this port
         #[arg(long = "dns-port")]
         dns_port: Option<u16>,
+        /// Run a repair coordinator on this port and start every node with
+        /// `--repair-ring` pointed at it; see `run --repair-ring`.
+        #[arg(long)]
+        repair_port: Option<u16>,
+        /// Name of the ring to create. The nodes are wired with
+        /// `NODE NEXT-RING` on it instead of `NODE NEXT`; PREV, the netmap
+        /// and the topology walk are skipped. Defaults to the `default`
+        /// ring.
+        #[arg(long)]
+        ring_name: Option<String>,
+        /// Once the ring is wired, write each node's address, PID and NEXT
+        /// to this JSON file for `resume-network`.
+        #[arg(long)]
+        membership_file: Option<PathBuf>,
+        /// Restart a node that exits while the ring is up, on the same
+        /// port, and wire it and its predecessor again.
+        #[arg(long)]
+        supervise: bool,
+        /// Restarts each node gets under `--supervise` before it is left
+        /// down. Defaults to 5.
+        #[arg(long)]
+        max_restarts: Option<u32>,
+        /// While the ring is up, watch `--config` and rewire it to match
+        /// each change to its `[ring]` table, sending `NODE NEXT` only to
+        /// the nodes whose NEXT moved. No node is started or stopped.
+        #[arg(long, requires = "config", conflicts_with_all = ["no_block", "auto_discover"])]
+        hot_reload: bool,
         /// Max file size in bytes. 0 to disable. Defaults to 1 gigabyte.
-        #[arg(short, long, default_value_t = 1_000_000_000u64)]
-        file_size: u64,
+        #[arg(short, long)]
+        file_size: Option<u64>,
+        /// Pre-shared AUTH token (64-char hex) handed to every child and
+        /// used for the wiring commands. Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// Passed through to every child; see `run --tls-cert`.
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// Passed through to every child; see `run --tls-key`.
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// Passed through to every child and used for the wiring commands.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// On exit, send `NODE SHUTDOWN` to every child and wait for it to
+        /// drain and exit before the process group is signalled.
+        #[arg(long)]
+        graceful: bool,
+        /// Passed through to every child and expected on the wiring
+        /// connections; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+        /// Print the steps that would start and wire the ring, then exit
+        /// without spawning anything.
+        #[arg(long)]
+        dry_run: bool,
+        /// How `--dry-run` prints the plan.
+        #[arg(long, value_enum, default_value_t = PlanFormat::Text)]
+        dry_run_format: PlanFormat,
+        /// Spawn nothing: listen on this multicast group for
+        /// --discover-secs and wire every node that announces itself
+        /// there (`run --announce`) into one ring, in address order.
+        #[arg(long, conflicts_with_all = ["nodes", "base_port", "detach", "supervise"])]
+        auto_discover: Option<SocketAddr>,
+        /// How long --auto-discover listens. Defaults to 10.
+        #[arg(long, requires = "auto_discover")]
+        discover_secs: Option<u64>,
+    },
+
+    /// Benchmark a running ring: send `RING ECHO` at `--rps` for
+    /// `--duration-secs`, then print throughput and p50/p95/p99 latency.
+    Bench {
+        /// Ring node to send to; every echo starts and ends there.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        #[command(flatten)]
+        bench: BenchArgs,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, requests use TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Start a `dev-network` ring, benchmark it as `bench` does, then stop
+    /// it.
+    BenchNetwork {
+        /// Number of nodes to start.
+        #[arg(short = 'n', long = "nodes", default_value_t = 3)]
+        nodes: u16,
+        /// Base port to use (ports are base, base+1, ..., base+N-1).
+        #[arg(short = 'p', long = "base-port", default_value_t = 7000)]
+        base_port: u16,
+        /// Interface to bind the nodes on.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Start from an empty "nodes" directory.
+        #[arg(short, long)]
+        overwrite_nodes_dir: bool,
+        #[command(flatten)]
+        bench: BenchArgs,
+        /// Pre-shared AUTH token (64-char hex) for the nodes and the
+        /// benchmark. Falls back to the OUROBOROS_AUTH_TOKEN env var.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// Passed through to every child and expected by the benchmark;
+        /// see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Query `NODE STAT` on every node of a running `dev-network` ring
+    /// and print the counters as a table.
+    StatNetwork {
+        /// Number of nodes in the ring
+        #[arg(short = 'n', long = "nodes", default_value_t = 3)]
+        nodes: u16,
+        /// Base port of the ring (ports are base, base+1, ..., base+N-1)
+        #[arg(short = 'p', long = "base-port", default_value_t = 7000)]
+        base_port: u16,
+        /// Interface the nodes listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, queries use TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Re-wire a `dev-network` ring from its `--membership-file`: every
+    /// node that is still running but has no NEXT, or a dead one, is
+    /// pointed at the next live member.
+    ResumeNetwork {
+        /// The file `dev-network --membership-file` wrote.
+        #[arg(long)]
+        membership_file: PathBuf,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the wiring commands use TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Check that the ring is whole: walk it from one node, then check
+    /// that the walk came back there and that every node it reached
+    /// answers and reports the NEXT the walk followed. Problems are
+    /// printed as `WARN` lines and make the exit status non-zero.
+    Diagnose {
+        /// Any ring node; the walk starts and must end there.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// text or json.
+        #[arg(long, value_enum, default_value_t = DiagnoseOutput::Text)]
+        output: DiagnoseOutput,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, every request uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Walk the ring from one node and print its topology as a Graphviz
+    /// DOT graph (pipe into `dot -Tsvg`) or a JSON adjacency list.
+    Topology {
+        /// Any ring node; it becomes the walk's (highlighted) start node.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// dot or json.
+        #[arg(long, value_enum, default_value_t = TopologyFormat::Dot)]
+        format: TopologyFormat,
+        /// Print the walk's edges as a FROM / TO table instead.
+        #[arg(long, conflicts_with = "format")]
+        pretty: bool,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the walk request uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+    /// Collect one value from every node in the ring and print an
+    /// `addr=value` line per node.
+    Gather {
+        /// port, next, uptime or msg_count.
+        key: String,
+        /// Any ring node; the walk starts and ends there.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the request uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Print one node's events (`EVENT <type> <data>`) as they happen,
+    /// until Ctrl-C.
+    Watch {
+        /// Node to watch.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// ring, walk, set_next, partition, topology or all.
+        #[arg(long, default_value = "all")]
+        events: EventFilter,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the connection uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Show the ring live in the terminal: every node the walk from
+    /// --addr reaches with its NODE STAT counters, the ring as a loop, and
+    /// the node's events. Arrows select a node, Enter pings it, `s` logs
+    /// its STAT, `w` walks again, `q` quits.
+    Tui {
+        /// Node to walk from and watch.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// How often the counters are refreshed, in milliseconds.
+        #[arg(long, default_value_t = 500)]
+        refresh_ms: u64,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, every connection uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Send a file of protocol commands to one node, one at a time, and
+    /// print each reply. Blank lines and `#` comments are skipped. Stops
+    /// at the first failing command unless --continue-on-error.
+    RunScript {
+        /// Node to send them to.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// The script: one command per line, as on the wire.
+        #[arg(long)]
+        script: PathBuf,
+        /// Keep going past a failing command; the exit status still
+        /// reports the failures.
+        #[arg(long)]
+        continue_on_error: bool,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the connection uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Type protocol commands at one node and see its replies, until
+    /// `quit` or end of input. Walks print as a chain, `NODE STAT` as a
+    /// table; a line ending in Tab lists matching commands.
+    Repl {
+        /// Node to talk to.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the connection uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Inject faults at one node for resilience testing: `drop <p>`,
+    /// `delay <mean_ms> <stddev_ms>`, `corrupt <p>`, or `off`. The node
+    /// mistreats the RING hops it forwards until turned off. Never point
+    /// this at a production ring.
+    Chaos {
+        /// Node to switch.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// The mode and its parameters, or `off`.
+        #[arg(required = true, num_args = 1..)]
+        mode: Vec<String>,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, the connection uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Stop a `dev-network --detach` ring: send SIGTERM to the PID in
+    /// `--pid-file` and wait for it to stop its nodes and exit.
+    Stop {
+        /// The file `dev-network --detach --pid-file` wrote.
+        #[arg(long)]
+        pid_file: PathBuf,
+        /// Give up waiting after this many seconds.
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+    },
+
+    /// Check the `[[ring.nodes]]` of a config file without starting
+    /// anything: duplicate ports, self-loops, pointers out of the ring,
+    /// unreachable or disconnected nodes, and well-known ports. Prints
+    /// `OK`, or one problem per line and exits nonzero.
+    LintRing {
+        /// TOML file with a `[ring]` table.
+        #[arg(long)]
+        config: PathBuf,
+    },
+
+    /// Re-send the messages in a `run --replay-log` file into the ring,
+    /// e.g. once a partition has healed. Each message goes out once, as
+    /// the node that logged it forwarded it (TTL one lower); nodes whose
+    /// dedup cache still holds it drop it, so only the nodes that missed
+    /// it see it.
+    ReplayLog {
+        /// The log a node wrote with `--replay-log`.
+        path: PathBuf,
+        /// The NEXT of the node that wrote the log.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        addr: String,
+        /// Only replay entries logged at or after this Unix time, in ms.
+        #[arg(long, default_value_t = 0)]
+        since_ms: u64,
+        /// Pre-shared AUTH token (64-char hex). Falls back to the
+        /// OUROBOROS_AUTH_TOKEN env var. Disabled if neither is set.
+        #[arg(long, alias = "auth-key")]
+        auth_token: Option<String>,
+        /// PEM CA bundle; when set, every request uses TLS.
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Expect the `HELLO` version greeting; see `run --hello`.
+        #[arg(long)]
+        hello: bool,
+    },
+
+    /// Point one node's NEXT at another (`NODE NEXT`) and print the
+    /// pointer it stored.
+    SetNext {
+        /// The new NEXT.
+        #[arg(long)]
+        next: String,
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Print one node's `NODE STATUS`.
+    Get {
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Print one node's state as JSON (`SNAPSHOT`): ring pointers, KV
+    /// store, counters, leader and role, for `restore`.
+    Snapshot {
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Replace one node's state with a `snapshot` file (`RESTORE`). The
+    /// file is checked before anything is sent.
+    Restore {
+        /// JSON written by `snapshot`.
+        #[arg(long)]
+        file: PathBuf,
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Send a message around the ring (`RING FORWARD`).
+    Ring {
+        /// Hops the message travels past the first node.
+        #[arg(long)]
+        ttl: u32,
+        #[arg(long)]
+        msg: String,
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Walk the ring from one node and print its edges, one `from->to`
+    /// per line. See `topology` for a graph.
+    Walk {
+        #[command(flatten)]
+        call: CallArgs,
+    },
+    /// Take one node out of the ring: point its predecessor at its NEXT,
+    /// then `NODE SHUTDOWN` it. The predecessor is the node's PREV.
+    Leave {
+        /// The node that points at `--addr`, for a node whose PREV is
+        /// unset.
+        #[arg(long)]
+        predecessor: Option<String>,
+        /// Afterwards, walk the ring from the predecessor and fail unless
+        /// the walk comes back without the node that left.
+        #[arg(long)]
+        verify: bool,
+        #[command(flatten)]
+        call: CallArgs,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let cli = Cli::parse();
+    // Forked before the runtime starts: a fork only carries the calling
+    // thread along.
+    let detached = match &cli.command {
+        Cmd::DevNetwork {
+            detach: true,
+            dry_run: false,
+            pid_file: Some(pid_file),
+            ..
+        } => Some(detach(pid_file)?),
+        _ => None,
+    };
+    let report = detached.as_ref().map(Detached::reporter).transpose()?;
+    let res = RuntimeOpts {
+        worker_threads: cli.worker_threads,
+        stack_size_kb: cli.stack_size_kb,
+        max_blocking_threads: cli.max_blocking_threads,
+    }
+    .build()
+    .map_err(|e| format!("starting the runtime: {e}"))?
+    .block_on(run_cli(cli, detached));
+    if let Some(report) = report {
+        report.finish(&res);
+    }
+    res
+}
 
+async fn run_cli(cli: Cli, detached: Option<Detached>) -> Result<(), Box<dyn Error + Send + Sync>> {
     // Initialize tracing subscriber. JSON suits log shippers; text is for
-    // a human reading `journalctl` or stdout. (NEXT_STEPS.md §4.1.)
+    // a human reading `journalctl` or the terminal. (NEXT_STEPS.md §4.1.)
+    // Logs go to stderr so `topology` / `stat-network` output on stdout
+    // stays clean.
+    let filter = match &cli.log_level {
+        Some(level) => EnvFilter::try_new(level).map_err(|e| format!("--log-level: {e}"))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
     match cli.log_format {
         LogFormat::Text => {
             fmt()
                 .with_timer(fmt::time::UtcTime::rfc_3339())
-                .with_env_filter(EnvFilter::from_default_env())
+                .with_env_filter(filter)
+                .with_writer(std::io::stderr)
                 .with_target(true)
                 .init();
         }
         LogFormat::Json => {
             fmt()
                 .with_timer(fmt::time::UtcTime::rfc_3339())
-                .with_env_filter(EnvFilter::from_default_env())
+                .with_env_filter(filter)
+                .with_writer(std::io::stderr)
                 .with_target(true)
                 .json()
                 .init();
@@ -250,44 +1073,148 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             config,
             addr,
             port,
+            bind_host,
+            socket_path,
             wait_time,
             file_size,
             storage_root,
             fsync_mode,
             auth_token,
             idle_timeout,
+            accept_timeout_ms,
+            max_ring_ttl,
+            max_ring_hops,
+            max_walk_hops,
+            forward_max_hops,
+            default_ring_ttl,
             max_conns,
             shutdown_timeout,
+            tls_cert,
+            tls_key,
+            tls_ca,
+            name,
+            state_file,
+            ring_ack_timeout,
+            pool_size,
+            mux,
+            hello,
+            max_line_bytes,
+            walk_payload_cmd,
+            no_dns_resolve,
+            verify_next_reachable,
+            no_nodelay,
+            cb_failure_threshold,
+            cb_reset_timeout_secs,
+            rate_limit_rps,
+            reuse_port,
+            bind_backlog,
+            recv_buf_size,
+            send_buf_size,
+            kv_max_key_bytes,
+            kv_max_value_bytes,
+            metrics_port,
+            otlp_endpoint,
+            dedup_cache_size,
+            dedup_ttl_secs,
+            compress_threshold_bytes,
+            repair_ring,
+            repair_coordinator,
+            replay_log,
+            probe_interval_secs,
+            walk_cache_ttl_secs,
+            heartbeat_addr,
+            heartbeat_interval_secs,
+            parallel_walk_timeout_ms,
+            announce,
+            announce_interval_secs,
+            monitor_topology,
+            monitor_interval_secs,
+            split_brain_check_interval_secs,
+            routing,
+            script,
+            continue_on_error,
         } => {
             // Load config file if --config was passed; otherwise an empty
             // (all-None) struct fills nothing and the built-in defaults
             // apply for everything.
-            let cfg: RunConfig = if let Some(p) = &config {
-                load_run_config(p)?
-            } else {
-                RunConfig::default()
+            let cfg = match &config {
+                Some(p) => Config::load(p, Section::Run)?.run,
+                None => RunConfig::default(),
             };
             // Precedence: CLI > config > built-in default.
             let addr_or_port = addr.is_some() || port.is_some();
-            let bind_str = if addr_or_port {
-                resolve_listen_addr(addr, port)
+            // A CLI --addr/--port overrides a config-file socket path too.
+            let socket_path = match socket_path {
+                Some(p) => Some(p),
+                None if addr_or_port => None,
+                None => cfg.socket_path.clone(),
+            };
+            let bind_host = bind_host
+                .or(cfg.bind_host.clone())
+                .unwrap_or_else(|| DEFAULT_BIND_HOST.to_string());
+            let env_port = env::var("PORT").ok();
+            let bind_str = if let Some(path) = socket_path {
+                format!("{UNIX_SCHEME}{}", path.display())
+            } else if addr_or_port {
+                resolve_listen_addr(addr.as_deref(), port, None, &bind_host)
             } else if let Some(a) = cfg.addr.clone() {
-                normalize_addr(a)
+                normalize_addr_on(&a, &bind_host)
             } else {
-                resolve_listen_addr(None, None) // env or default
+                // env or default
+                resolve_listen_addr(None, None, env_port.as_deref(), &bind_host)
             };
             let wait_time = wait_time.or(cfg.wait_time).unwrap_or(5000);
             let file_size = file_size.or(cfg.file_size).unwrap_or(1_000_000_000);
             let storage_root = storage_root
                 .or(cfg.storage_root.clone())
                 .unwrap_or_else(|| PathBuf::from("nodes"));
-            let fsync_mode_cli: CliFsyncMode =
-                fsync_mode.or(cfg.fsync_mode).unwrap_or(CliFsyncMode::Full);
+            let fsync_mode = fsync_mode
+                .map(FsyncMode::from)
+                .or(cfg.fsync_mode)
+                .unwrap_or_default();
+            let heartbeat_addr = heartbeat_addr.or(cfg.heartbeat_addr.clone());
+            let routing = routing
+                .map(RoutingMode::from)
+                .or(cfg.routing)
+                .unwrap_or_default();
+            if routing == RoutingMode::ConsistentHash && heartbeat_addr.is_none() {
+                return Err("--routing consistent-hash needs --heartbeat-addr".into());
+            }
             let token_str = auth_token.or(cfg.auth_token.clone());
             let idle_timeout = idle_timeout.or(cfg.idle_timeout).unwrap_or(60);
+            let ring_ack_timeout = ring_ack_timeout.or(cfg.ring_ack_timeout).unwrap_or(30);
             let max_conns = max_conns.or(cfg.max_conns).unwrap_or(1024);
             let shutdown_timeout = shutdown_timeout.or(cfg.shutdown_timeout).unwrap_or(30);
 
+            let tls = resolve_node_tls(
+                tls_cert.or(cfg.tls_cert.clone()),
+                tls_key.or(cfg.tls_key.clone()),
+                tls_ca.or(cfg.tls_ca.clone()),
+            )?;
+            let name = match name.or(cfg.name.clone()) {
+                Some(n) => validate_node_name(&n)?.to_string(),
+                None => String::new(),
+            };
+
+            let otlp_endpoint = otlp_endpoint
+                .or(cfg.otlp_endpoint.clone())
+                .map(|url| OtlpEndpoint::parse(&url))
+                .transpose()?;
+            let repair_coordinator = if repair_ring || cfg.repair_ring.unwrap_or(false) {
+                Some(
+                    repair_coordinator
+                        .or(cfg.repair_coordinator.clone())
+                        .ok_or("--repair-ring needs --repair-coordinator")?,
+                )
+            } else {
+                None
+            };
+
+            let max_ring_ttl = max_ring_ttl.or(cfg.max_ring_ttl).unwrap_or(1024);
+            let max_ring_hops = max_ring_hops
+                .or(cfg.max_ring_hops)
+                .unwrap_or(max_ring_ttl.saturating_mul(10));
+
             let gossip_interval = Duration::from_millis(wait_time);
             let token = resolve_auth_token(token_str)?;
             run(
@@ -295,24 +1222,165 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 gossip_interval,
                 file_size,
                 storage_root,
-                fsync_mode_cli.into(),
+                fsync_mode,
                 token,
                 Duration::from_secs(idle_timeout),
                 max_conns,
                 Duration::from_secs(shutdown_timeout),
+                NodeOpts {
+                    tls,
+                    name,
+                    state_file: state_file.or(cfg.state_file.clone()),
+                    ring_ack_timeout: Duration::from_secs(ring_ack_timeout),
+                    pool_size: pool_size
+                        .or(cfg.pool_size)
+                        .unwrap_or(ouroboros_fs::pool::DEFAULT_POOL_SIZE),
+                    mux: mux || cfg.mux.unwrap_or(false),
+                    hello: hello || cfg.hello.unwrap_or(false),
+                    max_line_bytes: max_line_bytes
+                        .or(cfg.max_line_bytes)
+                        .unwrap_or(ouroboros_fs::io::DEFAULT_MAX_LINE_BYTES),
+                    walk_payload_cmd: walk_payload_cmd.or(cfg.walk_payload_cmd.clone()),
+                    no_dns_resolve: no_dns_resolve || cfg.no_dns_resolve.unwrap_or(false),
+                    verify_next_reachable: verify_next_reachable
+                        || cfg.verify_next_reachable.unwrap_or(false),
+                    no_nodelay: no_nodelay || cfg.no_nodelay.unwrap_or(false),
+                    cb_failure_threshold: cb_failure_threshold
+                        .or(cfg.cb_failure_threshold)
+                        .unwrap_or(ouroboros_fs::node::DEFAULT_CB_FAILURE_THRESHOLD),
+                    cb_reset_timeout: cb_reset_timeout_secs.or(cfg.cb_reset_timeout_secs).map_or(
+                        ouroboros_fs::node::DEFAULT_CB_RESET_TIMEOUT,
+                        Duration::from_secs,
+                    ),
+                    rate_limit_rps: rate_limit_rps.or(cfg.rate_limit_rps).unwrap_or(0),
+                    reuse_port: reuse_port || cfg.reuse_port.unwrap_or(false),
+                    bind_backlog: bind_backlog
+                        .or(cfg.bind_backlog)
+                        .unwrap_or(ouroboros_fs::node::DEFAULT_BIND_BACKLOG),
+                    recv_buf_size: recv_buf_size.or(cfg.recv_buf_size).unwrap_or(0),
+                    send_buf_size: send_buf_size.or(cfg.send_buf_size).unwrap_or(0),
+                    kv_max_key_bytes: kv_max_key_bytes
+                        .or(cfg.kv_max_key_bytes)
+                        .unwrap_or(ouroboros_fs::node::DEFAULT_KV_MAX_KEY_BYTES),
+                    kv_max_value_bytes: kv_max_value_bytes
+                        .or(cfg.kv_max_value_bytes)
+                        .unwrap_or(ouroboros_fs::node::DEFAULT_KV_MAX_VALUE_BYTES),
+                    metrics_port: metrics_port.or(cfg.metrics_port),
+                    otlp_endpoint,
+                    dedup_cache_size: dedup_cache_size
+                        .or(cfg.dedup_cache_size)
+                        .unwrap_or(ouroboros_fs::dedup::DEFAULT_DEDUP_CACHE_SIZE),
+                    dedup_ttl: dedup_ttl_secs
+                        .or(cfg.dedup_ttl_secs)
+                        .map_or(ouroboros_fs::dedup::DEFAULT_DEDUP_TTL, Duration::from_secs),
+                    repair_coordinator,
+                    compress_threshold_bytes: compress_threshold_bytes
+                        .or(cfg.compress_threshold_bytes)
+                        .unwrap_or(ouroboros_fs::codec::DEFAULT_COMPRESS_THRESHOLD_BYTES),
+                    accept_timeout: Duration::from_millis(
+                        accept_timeout_ms.or(cfg.accept_timeout_ms).unwrap_or(5000),
+                    ),
+                    max_ring_ttl,
+                    max_ring_hops,
+                    max_walk_hops: max_walk_hops.or(cfg.max_walk_hops).unwrap_or(1024),
+                    forward_max_hops: forward_max_hops.or(cfg.forward_max_hops).unwrap_or(3),
+                    default_ring_ttl: default_ring_ttl
+                        .or(cfg.default_ring_ttl)
+                        .unwrap_or(ouroboros_fs::node::DEFAULT_RING_TTL),
+                    replay_log: replay_log.or(cfg.replay_log.clone()),
+                    probe_interval: Duration::from_secs(
+                        probe_interval_secs.or(cfg.probe_interval_secs).unwrap_or(0),
+                    ),
+                    walk_cache_ttl: Duration::from_secs(
+                        walk_cache_ttl_secs.or(cfg.walk_cache_ttl_secs).unwrap_or(0),
+                    ),
+                    heartbeat_addr,
+                    heartbeat_interval: heartbeat_interval_secs
+                        .or(cfg.heartbeat_interval_secs)
+                        .map_or(DEFAULT_HEARTBEAT_INTERVAL, Duration::from_secs),
+                    parallel_walk_timeout: parallel_walk_timeout_ms
+                        .or(cfg.parallel_walk_timeout_ms)
+                        .map_or(
+                            ouroboros_fs::node::DEFAULT_PARALLEL_WALK_TIMEOUT,
+                            Duration::from_millis,
+                        ),
+                    announce: announce
+                        .or(cfg.announce)
+                        .map(|group| announce::check_group(group).map(|()| group))
+                        .transpose()?,
+                    announce_interval: announce_interval_secs
+                        .or(cfg.announce_interval_secs)
+                        .map_or(DEFAULT_ANNOUNCE_INTERVAL, Duration::from_secs),
+                    monitor_interval: if monitor_topology || cfg.monitor_topology.unwrap_or(false) {
+                        Duration::from_secs(
+                            monitor_interval_secs
+                                .or(cfg.monitor_interval_secs)
+                                .unwrap_or(30),
+                        )
+                    } else {
+                        Duration::ZERO
+                    },
+                    split_brain_check_interval: Duration::from_secs(
+                        split_brain_check_interval_secs
+                            .or(cfg.split_brain_check_interval_secs)
+                            .unwrap_or(0),
+                    ),
+                    routing,
+                    startup_script: script.or(cfg.script),
+                    script_continue_on_error: continue_on_error
+                        || cfg.continue_on_error.unwrap_or(false),
+                },
             )
             .await
+            .map_err(Into::into)
         }
-        Cmd::Gateway {
-            config,
+        Cmd::Coordinator {
             listen,
-            nodes,
+            heartbeat_interval_secs,
             auth_token,
+            hello,
         } => {
-            let cfg: GatewayConfig = if let Some(p) = &config {
-                load_gateway_config(p)?
-            } else {
-                GatewayConfig::default()
+            let token = resolve_auth_token(auth_token)?;
+            let coordinator = HeartbeatCoordinator::new(
+                Duration::from_secs(heartbeat_interval_secs),
+                client_opts(&token, &TlsConfig::default(), hello),
+            );
+            let mut changes = coordinator.subscribe();
+            let mut server = tokio::spawn(coordinator.run_server(listen));
+            loop {
+                tokio::select! {
+                    change = changes.recv() => match change {
+                        Ok(MembershipChange::Joined(m)) => {
+                            println!("JOIN {} {} {}", m.addr, m.name, m.generation)
+                        }
+                        Ok(MembershipChange::Dead(addr)) => println!("DEAD {addr}"),
+                        Err(e) => tracing::warn!(error = %e, "Missed membership changes"),
+                    },
+                    res = &mut server => return Ok(res??),
+                    _ = tokio::signal::ctrl_c() => return Ok(()),
+                }
+            }
+        }
+        Cmd::Discover {
+            group,
+            duration_secs,
+        } => {
+            for a in announce::discover(group, Duration::from_secs(duration_secs)).await? {
+                println!("{} {} {}", a.addr, a.name, a.generation);
+            }
+            Ok(())
+        }
+        Cmd::Gateway {
+            config,
+            listen,
+            nodes,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let cfg = match &config {
+                Some(p) => Config::load(p, Section::Gateway)?.gateway,
+                None => GatewayConfig::default(),
             };
             let listen = listen
                 .or(cfg.listen.clone())
@@ -332,67 +1400,628 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 );
             };
             let token = resolve_auth_token(auth_token.or(cfg.auth_token))?;
-            let gateway = ouroboros_fs::Gateway::with_auth(node_addrs, token);
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.or(cfg.tls_ca).as_deref())?;
+            let hello = hello || cfg.hello.unwrap_or(false);
+            let gateway =
+                ouroboros_fs::Gateway::with_auth_tls_and_hello(node_addrs, token, tls, hello);
             tracing::info!(addr = %listen, "Starting standalone gateway");
             gateway.run_server(listen).await?;
             Ok(())
         }
         Cmd::DevNetwork {
+            config,
             nodes,
             base_port,
             host,
             no_block,
+            detach: _,
+            pid_file: _,
             wait_ms,
             wait_time,
             overwrite_nodes_dir,
             dns_port,
+            repair_port,
+            ring_name,
+            membership_file,
+            supervise,
+            max_restarts,
+            hot_reload,
             file_size,
+            auth_token,
+            tls_cert,
+            tls_key,
+            tls_ca,
+            graceful,
+            hello,
+            dry_run,
+            dry_run_format,
+            auto_discover,
+            discover_secs,
         } => {
-            set_network(
+            let cfg = match &config {
+                Some(p) => Config::load(p, Section::Network)?.network,
+                None => NetworkConfig::default(),
+            };
+            // Precedence: CLI > config > built-in default.
+            let token = resolve_auth_token(auth_token.or(cfg.auth_token))?;
+            let tls = resolve_node_tls(
+                tls_cert.or(cfg.tls_cert),
+                tls_key.or(cfg.tls_key),
+                tls_ca.or(cfg.tls_ca),
+            )?;
+            if let Some(group) = auto_discover {
+                let hello = hello || cfg.hello.unwrap_or(false);
+                let found =
+                    announce::discover(group, Duration::from_secs(discover_secs.unwrap_or(10)))
+                        .await?;
+                if found.is_empty() {
+                    return Err(format!("no node announced itself on {group}").into());
+                }
+                let addrs: Vec<String> = found.into_iter().map(|a| a.addr).collect();
+                tracing::info!(nodes = addrs.len(), group = %group, "Discovered nodes");
+                let plan = plan_discovered(&addrs);
+                if dry_run {
+                    return print_plan(&plan, dry_run_format);
+                }
+                for action in &plan {
+                    wire(action, &token, &tls, hello).await?;
+                }
+                return Ok(());
+            }
+            let ring_name = match ring_name.or(cfg.ring_name) {
+                Some(r) if r == DEFAULT_RING => None,
+                Some(r) => Some(validate_ring_name(&r)?.to_string()),
+                None => None,
+            };
+            let repair_port = repair_port.or(cfg.repair_port);
+            if repair_port.is_some() && ring_name.is_some() {
+                return Err("--repair-port only repairs the default ring; drop --ring-name".into());
+            }
+            let spec = NetworkSpec {
+                nodes: nodes.or(cfg.nodes).unwrap_or(3),
+                base_port: base_port.or(cfg.base_port).unwrap_or(7000),
+                host: host.or(cfg.host).unwrap_or_else(|| "127.0.0.1".to_string()),
+                extra_wait: Duration::from_millis(wait_ms.or(cfg.wait_ms).unwrap_or(200)),
+                wait_time: wait_time.or(cfg.wait_time).unwrap_or(5000),
+                overwrite_nodes_dir: overwrite_nodes_dir
+                    || cfg.overwrite_nodes_dir.unwrap_or(false),
+                dns_port: dns_port.or(cfg.dns_port),
+                repair_port,
+                max_file_size: file_size.or(cfg.file_size).unwrap_or(1_000_000_000),
+                tls_args: tls
+                    .cli_args()
+                    .iter()
+                    .map(|a| a.to_string_lossy().into_owned())
+                    .collect(),
+                hello: hello || cfg.hello.unwrap_or(false),
+                ring_name,
+                membership_file: membership_file.or(cfg.membership_file),
+                supervise: supervise || cfg.supervise.unwrap_or(false),
+                max_restarts: max_restarts.or(cfg.max_restarts).unwrap_or(5),
+            };
+            if dry_run {
+                return print_plan(&plan_network(&spec), dry_run_format);
+            }
+            let then = if let Some(detached) = detached {
+                AfterWiring::Detach(detached)
+            } else if no_block || cfg.no_block.unwrap_or(false) {
+                AfterWiring::Stop
+            } else {
+                AfterWiring::WaitForQuit
+            };
+            let graceful = graceful || cfg.graceful.unwrap_or(false);
+            let hot_reload = if hot_reload || cfg.hot_reload.unwrap_or(false) {
+                config
+            } else {
+                None
+            };
+            set_network(spec, token, tls, graceful, hot_reload, then).await
+        }
+        Cmd::Bench {
+            addr,
+            bench,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let client = ring_client(&addr, &token, &tls, hello);
+            let ttl = match bench.ttl {
+                Some(ttl) => ttl,
+                None => client.walk().await?.len() as u32,
+            };
+            let report = run_bench(
+                &addr,
+                client_opts(&token, &tls, hello),
+                &bench_opts(&bench, ttl),
+            )
+            .await?;
+            print_bench(&report, bench.json_out.as_deref())
+        }
+        Cmd::BenchNetwork {
+            nodes,
+            base_port,
+            host,
+            overwrite_nodes_dir,
+            bench,
+            auth_token,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let spec = NetworkSpec {
                 nodes,
                 base_port,
-                &host,
-                !no_block,
-                Duration::from_millis(wait_ms),
-                wait_time,
+                host,
+                extra_wait: Duration::from_millis(200),
+                wait_time: 5000,
                 overwrite_nodes_dir,
-                dns_port,
-                file_size,
+                max_file_size: 1_000_000_000,
+                hello,
+                ..NetworkSpec::default()
+            };
+            set_network(
+                spec,
+                token,
+                TlsConfig::default(),
+                /*graceful=*/ true,
+                /*hot_reload=*/ None,
+                AfterWiring::Bench(bench),
+            )
+            .await
+        }
+        Cmd::Stop {
+            pid_file,
+            timeout_secs,
+        } => stop_detached(&pid_file, Duration::from_secs(timeout_secs)).await,
+        Cmd::LintRing { config } => {
+            let raw = fs::read_to_string(&config)
+                .map_err(|e| format!("read config {}: {e}", config.display()))?;
+            let cfg = Config::parse(&raw, Section::Network)
+                .map_err(|e| format!("config {}: {e}", config.display()))?;
+            let problems = lint::lint(&cfg, &raw);
+            if problems.is_empty() {
+                println!("OK");
+                return Ok(());
+            }
+            for problem in &problems {
+                println!("{problem}");
+            }
+            Err(format!("{} problem(s) in {}", problems.len(), config.display()).into())
+        }
+        Cmd::ResumeNetwork {
+            membership_file,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            resume_network(&membership_file, &token, &tls, hello).await
+        }
+        Cmd::StatNetwork {
+            nodes,
+            base_port,
+            host,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            stat_network(nodes, base_port, &host, &token, &tls, hello).await
+        }
+        Cmd::Topology {
+            addr,
+            format,
+            pretty,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let edges: Vec<Edge> = ring_client(&addr, &token, &tls, hello)
+                .walk()
+                .await?
+                .into_iter()
+                .map(|(from, to)| Edge { from, to })
+                .collect();
+            if pretty {
+                print!("{}", render_history_table(&edges));
+                return Ok(());
+            }
+            let topology = parse_history(&roundtrip_history(&edges))?;
+            match format {
+                TopologyFormat::Dot => print!("{}", topology.to_dot()),
+                TopologyFormat::Json => println!("{}", topology.to_json()),
+            }
+            Ok(())
+        }
+        Cmd::Diagnose {
+            addr,
+            output,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let diagnosis = diagnose(&addr, &client_opts(&token, &tls, hello)).await;
+            match output {
+                DiagnoseOutput::Text => print!("{}", diagnosis.to_text()),
+                DiagnoseOutput::Json => println!("{}", diagnosis.to_json()),
+            }
+            if diagnosis.healthy {
+                Ok(())
+            } else {
+                Err(format!("ring at {addr} is unhealthy").into())
+            }
+        }
+        Cmd::Gather {
+            key,
+            addr,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            for (node, value) in ring_client(&addr, &token, &tls, hello).gather(&key).await? {
+                println!("{node}={value}");
+            }
+            Ok(())
+        }
+        Cmd::Watch {
+            addr,
+            events,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let mut stream = ring_client(&addr, &token, &tls, hello)
+                .subscribe(events)
+                .await?;
+            let ctrl_c = tokio::signal::ctrl_c();
+            tokio::pin!(ctrl_c);
+            loop {
+                let event = tokio::select! {
+                    event = stream.next_event() => event?,
+                    _ = &mut ctrl_c => return stream.unsubscribe().await.map_err(Into::into),
+                };
+                match event {
+                    Some(line) => println!("{line}"),
+                    None => {
+                        tracing::warn!(addr = %addr, "Node closed the event stream");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Cmd::Tui {
+            addr,
+            refresh_ms,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            tui::run(
+                &addr,
+                client_opts(&token, &tls, hello),
+                Duration::from_millis(refresh_ms),
+            )
+            .await?;
+            Ok(())
+        }
+        Cmd::Repl {
+            addr,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let client = ring_client(&addr, &token, &tls, hello);
+            let color = std::io::stdout().is_terminal();
+            repl::run(
+                &client,
+                BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+                color,
             )
+            .await?;
+            Ok(())
+        }
+        Cmd::Chaos {
+            addr,
+            mode,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let mode = mode.join(" ");
+            let mode = if mode.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                Some(mode.parse::<ChaosMode>()?)
+            };
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            ring_client(&addr, &token, &tls, hello)
+                .chaos(mode.as_ref())
+                .await?;
+            match mode {
+                Some(mode) => println!("Chaos mode on at {addr}: {mode}"),
+                None => println!("Chaos mode off at {addr}"),
+            }
+            Ok(())
+        }
+        Cmd::RunScript {
+            addr,
+            script: script_path,
+            continue_on_error,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let commands = script::load(&script_path)?;
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let client = ring_client(&addr, &token, &tls, hello);
+            script::run(&client, &commands, continue_on_error, |step, reply| {
+                println!("> {}", step.command);
+                match reply {
+                    Ok(lines) => lines.iter().for_each(|l| println!("{l}")),
+                    Err(e) => eprintln!("line {}: {e}", step.line),
+                }
+            })
+            .await
+            .map_err(|e| format!("{}: {e}", script_path.display()))?;
+            Ok(())
+        }
+        Cmd::ReplayLog {
+            path,
+            addr,
+            since_ms,
+            auth_token,
+            tls_ca,
+            hello,
+        } => {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| format!("reading {}: {e}", path.display()))?;
+            let entries =
+                replay::parse_log(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+            let plan = replay::replay_plan(&entries, since_ms);
+            let token = resolve_auth_token(auth_token)?;
+            let tls = TlsConfig::from_pem_files(None, None, tls_ca.as_deref())?;
+            let client = ring_client(&addr, &token, &tls, hello);
+            for entry in &plan {
+                match &entry.ring {
+                    None => client.ring(entry.ttl, &entry.msg).await?,
+                    Some(ring) => client.ring_on(ring, entry.ttl, &entry.msg).await?,
+                }
+            }
+            println!(
+                "replayed {} of {} logged messages",
+                plan.len(),
+                entries.len()
+            );
+            Ok(())
+        }
+        Cmd::SetNext { next, call } => {
+            run_call(&call, |client| async move {
+                let stored = client.set_next(&next).await?;
+                let json = serde_json::json!({ "addr": client.addr(), "next": stored });
+                Ok((format!("OK next={stored}"), json))
+            })
+            .await
+        }
+        Cmd::Get { call } => {
+            run_call(&call, |client| async move {
+                let info = client.get().await?;
+                Ok((
+                    render_node_info(&info),
+                    node_info_json(client.addr(), &info),
+                ))
+            })
+            .await
+        }
+        Cmd::Snapshot { call } => {
+            run_call(&call, |client| async move {
+                let snapshot = client.snapshot().await?;
+                let json = serde_json::to_value(&snapshot)
+                    .map_err(|e| RingError::ParseError(e.to_string()))?;
+                let text = serde_json::to_string_pretty(&json)
+                    .map_err(|e| RingError::ParseError(e.to_string()))?;
+                Ok((text, json))
+            })
+            .await
+        }
+        Cmd::Restore { file, call } => {
+            let text =
+                fs::read_to_string(&file).map_err(|e| format!("read {}: {e}", file.display()))?;
+            let snapshot =
+                NodeSnapshot::parse(&text).map_err(|e| format!("{}: {e}", file.display()))?;
+            run_call(&call, |client| async move {
+                client.restore(&snapshot).await?;
+                let json = serde_json::json!({
+                    "addr": client.addr(),
+                    "from": snapshot.addr,
+                    "taken_at_ms": snapshot.taken_at_ms,
+                });
+                Ok((format!("OK restored from {}", snapshot.addr), json))
+            })
             .await
         }
+        Cmd::Ring { ttl, msg, call } => {
+            run_call(&call, |client| async move {
+                client.ring(ttl, &msg).await?;
+                let json = serde_json::json!({ "addr": client.addr(), "ttl": ttl, "msg": msg });
+                Ok(("OK".to_string(), json))
+            })
+            .await
+        }
+        Cmd::Walk { call } => {
+            run_call(&call, |client| async move {
+                let edges = client.walk().await?;
+                let text = edges
+                    .iter()
+                    .map(|(from, to)| format!("{from}->{to}"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let json = serde_json::json!({
+                    "addr": client.addr(),
+                    "edges": edges
+                        .iter()
+                        .map(|(from, to)| serde_json::json!({ "from": from, "to": to }))
+                        .collect::<Vec<_>>(),
+                });
+                Ok((text, json))
+            })
+            .await
+        }
+        Cmd::Leave {
+            predecessor,
+            verify,
+            call,
+        } => run_call(&call, |client| leave(client, predecessor, verify)).await,
     }
 }
 
-// --- run
+// --- set-next, get, snapshot, restore, ring, walk, leave
 
-fn resolve_listen_addr(addr: Option<String>, port: Option<u16>) -> String {
-    // Priority:
-    // 1. --addr
-    // 2. --port
-    // 3. PORT env
-    // 4. default
-    if let Some(a) = addr {
-        return normalize_addr(a);
-    }
-    if let Some(p) = port {
-        return format!("127.0.0.1:{p}");
+/// Connect as `call` says, run `f`, print the text it returns and write
+/// its JSON to `--json-out` if set.
+async fn run_call<F, Fut>(call: &CallArgs, f: F) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    F: FnOnce(RingClient) -> Fut,
+    Fut: std::future::Future<Output = Result<(String, serde_json::Value), RingError>>,
+{
+    let token = resolve_auth_token(call.auth_token.clone())?;
+    let tls = TlsConfig::from_pem_files(None, None, call.tls_ca.as_deref())?;
+    let timeout = call.timeout_secs.map(Duration::from_secs);
+    let opts = ClientOpts {
+        timeout: timeout.unwrap_or_default(),
+        ..client_opts(&token, &tls, call.hello)
+    };
+    let reply = f(RingClient::with_opts(&call.addr, opts));
+    // Walks have their own, longer reply timeout; `--timeout-secs` caps
+    // those too.
+    let (text, json) = match timeout {
+        Some(t) => tokio::time::timeout(t, reply)
+            .await
+            .map_err(|_| format!("{}: no reply within {}s", call.addr, t.as_secs()))??,
+        None => reply.await?,
+    };
+    if !text.is_empty() {
+        println!("{text}");
     }
-    if let Ok(from_env) = env::var("PORT") {
-        return normalize_addr(from_env);
+    if let Some(path) = &call.json_out {
+        fs::write(path, serde_json::to_string_pretty(&json)? + "\n")
+            .map_err(|e| format!("writing {}: {e}", path.display()))?;
     }
-    "127.0.0.1:9000".to_string()
+    Ok(())
+}
+
+/// `get`'s output: one `key value` line per `NODE STATUS` field.
+fn render_node_info(info: &NodeInfo) -> String {
+    let unset = |v: &Option<String>| v.clone().unwrap_or_else(|| "<unset>".to_string());
+    [
+        ("port", info.port.clone()),
+        ("name", unset(&info.name)),
+        ("next", unset(&info.next)),
+        ("prev", unset(&info.prev)),
+        ("leader", unset(&info.leader)),
+        ("fan_out", info.fan_out.to_string()),
+        ("generation", info.generation.to_string()),
+    ]
+    .iter()
+    .map(|(k, v)| format!("{k:<10} {v}"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn node_info_json(addr: &str, info: &NodeInfo) -> serde_json::Value {
+    serde_json::json!({
+        "addr": addr,
+        "port": info.port,
+        "name": info.name,
+        "next": info.next,
+        "prev": info.prev,
+        "leader": info.leader,
+        "fan_out": info.fan_out,
+        "generation": info.generation,
+    })
 }
 
-/// Accept "7001" or "127.0.0.1:7001"
-fn normalize_addr(raw: String) -> String {
-    if raw.contains(':') {
-        raw
-    } else {
-        format!("127.0.0.1:{raw}")
+/// `leave`: read the node's NEXT and PREV (or `--predecessor`), carry
+/// out [`plan_leave`], and with `--verify` walk the ring from the
+/// predecessor, failing if the walk still passes the node that left.
+async fn leave(
+    client: RingClient,
+    predecessor: Option<String>,
+    verify: bool,
+) -> Result<(String, serde_json::Value), RingError> {
+    let leaving = client.addr().to_string();
+    let info = client.get().await?;
+    let Some(next) = info.next else {
+        return Err(RingError::Other(format!("{leaving} has no NEXT").into()));
+    };
+    let Some(prev) = predecessor.or(info.prev) else {
+        return Err(RingError::Other(
+            format!("{leaving} has no PREV; pass --predecessor").into(),
+        ));
+    };
+    let plan = plan_leave(&leaving, &prev, &next);
+    if plan.is_empty() {
+        return Err(RingError::Other(
+            format!("{leaving} is the only node in its ring").into(),
+        ));
+    }
+    for action in &plan {
+        match action {
+            NetworkAction::SetNext { node, next, .. } => {
+                client.peer(node).set_next(next).await?;
+            }
+            NetworkAction::SetPrev { node, prev } => client.peer(node).set_prev(prev).await?,
+            NetworkAction::Shutdown { node } => client.peer(node).shutdown().await?,
+            _ => {}
+        }
+        tracing::info!(step = %action, "Leave step done");
     }
+
+    let mut lines: Vec<String> = plan.iter().map(ToString::to_string).collect();
+    let mut json = serde_json::json!({
+        "addr": leaving,
+        "prev": prev,
+        "next": next,
+        "steps": lines,
+    });
+    if verify {
+        // The walk only returns once it is back at `prev`, so the ring is
+        // closed; it must also no longer pass through the node that left.
+        let edges = client.peer(&prev).walk().await?;
+        let gone = port_str(&info.port);
+        if edges
+            .iter()
+            .any(|(from, to)| split_label(from).1 == gone || split_label(to).1 == gone)
+        {
+            return Err(RingError::Other(
+                format!("walk from {prev} still passes {leaving}").into(),
+            ));
+        }
+        lines.push(format!("verified: ring closed with {} edges", edges.len()));
+        json["verified_edges"] = edges.len().into();
+    }
+    Ok((lines.join("\n"), json))
 }
 
+// --- run
+
 /// Resolve the auth token: --auth-token > $OUROBOROS_AUTH_TOKEN > disabled.
 /// Disabled auth is documented as development-only; we log a warning so it
 /// shows up in production deployments by accident-detection.
@@ -411,32 +2040,121 @@ fn resolve_auth_token(flag: Option<String>) -> Result<AuthToken, Box<dyn Error +
     }
 }
 
+/// Load the listener + dialer TLS config for a ring node. A node that
+/// serves TLS must also dial TLS (its peers are TLS listeners too), so a
+/// certificate without a CA is rejected up front.
+fn resolve_node_tls(
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    ca: Option<PathBuf>,
+) -> Result<TlsConfig, Box<dyn Error + Send + Sync>> {
+    if cert.is_some() && ca.is_none() {
+        return Err("--tls-cert requires --tls-ca to verify TLS peers".into());
+    }
+    Ok(TlsConfig::from_pem_files(
+        cert.as_deref(),
+        key.as_deref(),
+        ca.as_deref(),
+    )?)
+}
+
 // --- set-network
 
-#[allow(clippy::too_many_arguments)]
+/// What `set_network` does once the ring is wired.
+enum AfterWiring {
+    /// Stop the ring straight away (`--no-block`).
+    Stop,
+    /// Keep it up until `quit` or Ctrl-C.
+    WaitForQuit,
+    /// Benchmark it, then stop it (`bench-network`).
+    Bench(BenchArgs),
+    /// Tell the terminal it is up, then keep it up until SIGTERM
+    /// (`--detach`).
+    Detach(Detached),
+}
+
+/// Start `spec`'s ring, then do `then` with it, then stop it. With
+/// `hot_reload`, that config file is watched meanwhile; see
+/// [`topology_diff::watch`].
 async fn set_network(
-    nodes: u16,
-    base_port: u16,
-    host: &str,
-    block: bool,
-    extra_wait: Duration,
-    wait_time: u64,
-    overwrite_nodes_dir: bool,
-    dns_port: Option<u16>,
-    max_file_size: u64,
+    spec: NetworkSpec,
+    token: AuthToken,
+    tls: TlsConfig,
+    graceful: bool,
+    hot_reload: Option<PathBuf>,
+    then: AfterWiring,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    if nodes == 0 {
+    if spec.nodes == 0 {
         tracing::warn!("--nodes must be >= 1");
         return Ok(());
     }
+    let plan = plan_network(&spec);
+    execute_network(&spec, &plan, token, tls, graceful, hot_reload, then).await
+}
+
+/// Print `plan` instead of carrying it out (`--dry-run`).
+fn print_plan(
+    plan: &[NetworkAction],
+    format: PlanFormat,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match format {
+        PlanFormat::Text => {
+            for action in plan {
+                println!("{action}");
+            }
+        }
+        PlanFormat::Json => println!("{}", serde_json::to_string_pretty(plan)?),
+    }
+    Ok(())
+}
+
+/// Carry out `plan` in order, then `then`, then stop every spawned node.
+/// Read `child`'s stdout up to its `LISTENING <port>` line and return the
+/// port. The rest of its output is passed through to ours, so the child
+/// never blocks on a full pipe.
+async fn read_listening_port(child: &mut Child) -> Result<u16, Box<dyn Error + Send + Sync>> {
+    let stdout = child.stdout.take().ok_or("node stdout was not captured")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let port = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(line) = lines.next_line().await? {
+            match parse_listening(&line) {
+                Some(port) => return Ok(port),
+                None => println!("{line}"),
+            }
+        }
+        Err::<u16, Box<dyn Error + Send + Sync>>("node exited before reporting its port".into())
+    })
+    .await
+    .map_err(|_| "timed out waiting for the node to report its port")??;
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = lines.next_line().await {
+            println!("{line}");
+        }
+    });
+    Ok(port)
+}
+
+async fn execute_network(
+    spec: &NetworkSpec,
+    plan: &[NetworkAction],
+    token: AuthToken,
+    tls: TlsConfig,
+    graceful: bool,
+    hot_reload: Option<PathBuf>,
+    then: AfterWiring,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let hello = spec.hello;
 
     // Make this parent `set-network` process a new process group leader, then
     // all children spawned by it (and their children) will inherit this PGID.
     #[cfg(unix)]
     let pgid = std::process::id();
+    // A `--detach` process already leads its own session and group.
     #[cfg(unix)]
     unsafe {
-        if libc::setpgid(0, 0) == -1 {
+        if libc::getpgrp() == pgid as libc::pid_t {
+            tracing::info!(pgid = %pgid, "Already process group leader");
+        } else if libc::setpgid(0, 0) == -1 {
             tracing::warn!(
                 error = ?std::io::Error::last_os_error(),
                 "Could not set process group"
@@ -446,118 +2164,203 @@ async fn set_network(
         }
     }
 
-    // Prepare a fresh "nodes/" directory
-    let nodes_root = Path::new("nodes");
-    if nodes_root.exists() && overwrite_nodes_dir {
-        fs::remove_dir_all(nodes_root)?;
-        tracing::info!("Created a fresh 'nodes' directory");
-    }
-    fs::create_dir_all(nodes_root)?;
-
     let exe = current_exe()?;
     tracing::info!(
-        nodes,
-        host,
-        base_port,
-        end_port = base_port + nodes - 1,
+        nodes = spec.nodes,
+        host = %spec.host,
+        base_port = spec.base_port,
+        end_port = spec.port(spec.nodes - 1),
         exe = ?exe,
         "Starting network"
     );
 
-    // 1. Spawn children
-    let mut children: Vec<Child> = Vec::with_capacity(nodes as usize);
-    for i in 0..nodes {
-        let port = base_port + i;
-        let addr = format!("{host}:{port}");
-        let mut cmd = Command::new(&exe);
-        cmd.arg("run")
-            .arg("--addr")
-            .arg(&addr)
-            .arg("--wait-time")
-            .arg(wait_time.to_string())
-            .arg("--file-size")
-            .arg(max_file_size.to_string());
-
-        let child = cmd.spawn()?;
-        children.push(child);
-        tracing::info!(addr = %addr, "Spawned node");
-    }
-
-    // 2. Give nodes a moment to bind
-    if extra_wait > Duration::from_millis(0) {
-        tokio::time::sleep(extra_wait).await;
-    }
-
-    // 3. Wait until all ports are listening
-    for i in 0..nodes {
-        let port = base_port + i;
-        wait_until_listening(host, port, Duration::from_secs(5)).await?;
-        tracing::info!(host, port, "Node is listening");
-    }
-
-    // 4. Wire the ring
-    for i in 0..nodes {
-        let this_port = base_port + i;
-        let next_port = if i + 1 == nodes {
-            base_port
-        } else {
-            base_port + i + 1
-        };
-        let this_addr = format!("{host}:{this_port}");
-        let next_addr = format!("{host}:{next_port}");
-        send_node_next(&this_addr, &next_addr).await?;
-        tracing::info!(from = %this_addr, to = %next_addr, "Wired node");
+    // With `--base-port 0` the plan names nodes by placeholder; each is
+    // swapped for the address the node reports once it is spawned.
+    let auto_ports = spec.base_port == 0;
+    let mut resolved: HashMap<String, String> = HashMap::new();
+    let resolve = |resolved: &HashMap<String, String>, addr: &str| {
+        resolved
+            .get(addr)
+            .cloned()
+            .unwrap_or_else(|| addr.to_string())
+    };
+    let mut executed: Vec<NetworkAction> = Vec::with_capacity(plan.len());
+    let mut children: Vec<Child> = Vec::with_capacity(spec.nodes as usize);
+    for action in plan {
+        let action = action.clone().map_addrs(|a| resolve(&resolved, a));
+        match &action {
+            NetworkAction::PrepareDir { path, fresh } => {
+                if path.exists() && *fresh {
+                    fs::remove_dir_all(path)?;
+                    tracing::info!("Created a fresh 'nodes' directory");
+                }
+                fs::create_dir_all(path)?;
+            }
+            NetworkAction::Spawn { addr, args, .. } => {
+                let mut cmd = node_command(&exe, args, &token);
+                if auto_ports {
+                    cmd.stdout(Stdio::piped());
+                }
+                let mut child = cmd.spawn()?;
+                if auto_ports {
+                    let port = read_listening_port(&mut child).await?;
+                    let actual = join_host_port(&spec.host, port);
+                    tracing::info!(addr = %actual, "Spawned node");
+                    resolved.insert(addr.clone(), actual);
+                } else {
+                    tracing::info!(addr = %addr, "Spawned node");
+                }
+                children.push(child);
+            }
+            NetworkAction::Sleep { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+            }
+            NetworkAction::WaitListening { addr } => {
+                wait_until_listening(addr, Duration::from_secs(5)).await?;
+                tracing::info!(addr = %addr, "Node is listening");
+            }
+            NetworkAction::SetNext { .. } | NetworkAction::SetPrev { .. } => {
+                wire(&action, &token, &tls, hello).await?;
+            }
+            NetworkAction::WriteMembership { path } => {
+                let pids: Vec<u32> = children.iter().map(|c| c.id().unwrap_or(0)).collect();
+                let json = serde_json::to_string_pretty(&members(&executed, &pids))?;
+                fs::write(path, json + "\n")
+                    .map_err(|e| format!("write {}: {e}", path.display()))?;
+                tracing::info!(path = %path.display(), "Wrote ring membership");
+            }
+            NetworkAction::StartGateway { listen, nodes } => {
+                let gateway = ouroboros_fs::Gateway::with_auth_tls_and_hello(
+                    nodes.clone(),
+                    token.clone(),
+                    tls.clone(),
+                    hello,
+                );
+                let listen = listen.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = gateway.run_server(listen).await {
+                        tracing::error!(error = ?e, "Gateway server failed");
+                    }
+                });
+            }
+            NetworkAction::StartRepairCoordinator { listen, nodes } => {
+                let coordinator =
+                    RepairCoordinator::new(nodes.clone(), client_opts(&token, &tls, hello));
+                let listen = listen.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = coordinator.run_server(listen).await {
+                        tracing::error!(error = ?e, "Repair coordinator failed");
+                    }
+                });
+            }
+            NetworkAction::NetmapDiscover { node } => {
+                match ring_client(node, &token, &tls, hello)
+                    .netmap_discover()
+                    .await
+                {
+                    Ok(()) => tracing::info!(start_addr = %node, "Started netmap discover"),
+                    Err(e) => {
+                        tracing::warn!(start_addr = %node, error = ?e, "Failed to start netmap discover")
+                    }
+                }
+            }
+            NetworkAction::TopologyWalk { node } => {
+                match ring_client(node, &token, &tls, hello).walk().await {
+                    Ok(_) => tracing::info!(start_addr = %node, "Started topology walk"),
+                    Err(e) => {
+                        tracing::warn!(start_addr = %node, error = ?e, "Failed to start topology walk")
+                    }
+                }
+            }
+            NetworkAction::Shutdown { node } => {
+                ring_client(node, &token, &tls, hello).shutdown().await?;
+            }
+        }
+        // Again, so a spawn is recorded under the port it just reported.
+        executed.push(action.map_addrs(|a| resolve(&resolved, a)));
     }
-
     tracing::info!("Ring wired successfully.");
 
-    // 5. Start the DNS Gateway if requested
-    if let Some(port) = dns_port {
-        // Create the list of all node addresses
-        let node_addrs: Vec<String> = (0..nodes)
-            .map(|i| format!("{}:{}", host, base_port + i))
-            .collect();
-
-        let gateway = ouroboros_fs::Gateway::new(node_addrs);
-
-        // Spawn the main gateway server
-        let server_gateway = Arc::clone(&gateway);
-        let dns_listen_addr = format!("{}:{}", host, port);
-        tokio::spawn(async move {
-            if let Err(e) = server_gateway.run_server(dns_listen_addr).await {
-                tracing::error!(error = ?e, "Gateway server failed");
-            }
+    // Each supervisor owns its node until `stop` fires, then hands back
+    // whichever process it last started.
+    let (stop, stopped) = watch::channel(false);
+    let mut supervisors: Vec<JoinHandle<Child>> = Vec::new();
+    if spec.supervise {
+        let spawned = executed.iter().filter_map(|action| match action {
+            NetworkAction::Spawn { addr, .. } => Some(addr.clone()),
+            _ => None,
         });
+        for (child, addr) in children.drain(..).zip(spawned).collect::<Vec<_>>() {
+            let supervisor = Supervisor {
+                restart: plan_restart(&executed, &addr),
+                addr,
+                exe: exe.clone(),
+                token: token.clone(),
+                tls: tls.clone(),
+                hello,
+                max_restarts: spec.max_restarts,
+                stop: stopped.clone(),
+            };
+            supervisors.push(tokio::spawn(supervisor.run(child)));
+        }
+        tracing::info!(max_restarts = spec.max_restarts, "Supervising nodes");
     }
+    let reloader = hot_reload.map(|path| {
+        tokio::spawn(topology_diff::watch(
+            path,
+            client_opts(&token, &tls, hello),
+            HOT_RELOAD_POLL,
+        ))
+    });
 
-    // 6. Start a full investigation from the first node
-    let start_addr = format!("{host}:{base_port}");
-    if let Err(e) = send_netmap_discover(&start_addr).await {
-        tracing::warn!(start_addr = %start_addr, error = ?e, "Failed to start netmap discover");
-    } else {
-        tracing::info!(start_addr = %start_addr, "Started netmap discover");
+    // Block until user quits / Ctrl-C, or benchmark the ring
+    let addrs: Vec<String> = spec.addrs().iter().map(|a| resolve(&resolved, a)).collect();
+    let start_addr = resolve(&resolved, &spec.addr(0));
+    let mut result = Ok(());
+    match then {
+        AfterWiring::Stop => {}
+        AfterWiring::WaitForQuit => {
+            tracing::info!("Type 'quit' or press Ctrl-C to stop…");
+            wait_for_quit_or_ctrl_c().await;
+            tracing::info!("Stopping nodes…");
+        }
+        AfterWiring::Detach(detached) => {
+            detached.ready();
+            wait_for_term().await;
+            tracing::info!("Stopping nodes…");
+        }
+        AfterWiring::Bench(args) => {
+            let opts = bench_opts(&args, u32::from(spec.nodes));
+            tracing::info!(start_addr = %start_addr, rps = opts.rps, ttl = opts.ttl, "Benchmarking ring");
+            result = match run_bench(&start_addr, client_opts(&token, &tls, hello), &opts).await {
+                Ok(report) => print_bench(&report, args.json_out.as_deref()),
+                Err(e) => Err(e.into()),
+            };
+            tracing::info!("Stopping nodes…");
+        }
     }
 
-    // 7. Start a topology walk to populate topology maps
-    if let Err(e) = send_topology_walk(&start_addr).await {
-        tracing::warn!(start_addr = %start_addr, error = ?e, "Failed to start topology walk");
-    } else {
-        tracing::info!(start_addr = %start_addr, "Started topology walk");
+    // Cleanup
+    if let Some(reloader) = reloader {
+        reloader.abort();
     }
-
-    // 8. Optionally block until user quits / Ctrl-C
-    if block {
-        tracing::info!("Type 'quit' or press Ctrl-C to stop…");
-        wait_for_quit_or_ctrl_c().await;
-        tracing::info!("Stopping nodes…");
+    let _ = stop.send(true);
+    for supervisor in supervisors {
+        if let Ok(child) = supervisor.await {
+            children.push(child);
+        }
+    }
+    if graceful {
+        shutdown_children(&addrs, &token, &tls, hello, &mut children).await;
     }
-
-    // 9. Cleanup
     #[cfg(unix)]
     {
         tracing::info!(pgid = %pgid, "Stopping process group");
-        // Send SIGTERM to the entire process group
+        // Send SIGTERM to the entire process group. This process is in it
+        // too; ignore the signal so it can wait for the children and exit
+        // with its own status.
         unsafe {
+            libc::signal(libc::SIGTERM, libc::SIG_IGN);
             libc::kill(-(pgid as i32), libc::SIGTERM);
         }
         // Wait for all children we know about to exit
@@ -573,72 +2376,501 @@ async fn set_network(
             let _ = child.wait().await;
         }
     }
+    result
+}
+
+// --- detach
+
+/// The background process of `dev-network --detach`. It holds the write
+/// end of a pipe the terminal's process waits on for one status line.
+struct Detached {
+    pipe: fs::File,
+    pid_file: PathBuf,
+}
+
+impl Detached {
+    /// A second handle on the pipe, for `main` to report an error that
+    /// ends the run before the ring is wired.
+    fn reporter(&self) -> std::io::Result<DetachReport> {
+        Ok(DetachReport {
+            pipe: self.pipe.try_clone()?,
+            pid_file: self.pid_file.clone(),
+        })
+    }
+
+    /// The ring is wired: let the terminal go.
+    fn ready(self) {
+        use std::io::Write;
+        let _ = (&self.pipe).write_all(b"OK\n");
+    }
+}
+
+struct DetachReport {
+    pipe: fs::File,
+    pid_file: PathBuf,
+}
+
+impl DetachReport {
+    /// Pass a failure on to the terminal, if it is still waiting, and
+    /// remove the PID file.
+    fn finish(mut self, res: &Result<(), Box<dyn Error + Send + Sync>>) {
+        use std::io::Write;
+        if let Err(e) = res {
+            let _ = writeln!(self.pipe, "ERR {e}");
+        }
+        let _ = fs::remove_file(&self.pid_file);
+    }
+}
+
+/// Fork. The child starts a new session, writes its PID to `pid_file`,
+/// points its stdio at `/dev/null` and returns. The parent waits for the
+/// child to report the ring wired, then exits; it never returns `Ok`.
+#[cfg(unix)]
+fn detach(pid_file: &Path) -> Result<Detached, Box<dyn Error + Send + Sync>> {
+    use std::io::{BufRead, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: `pipe` just opened both descriptors and nothing else owns them.
+    let (read, write) = unsafe { (fs::File::from_raw_fd(fds[0]), fs::File::from_raw_fd(fds[1])) };
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            drop(read);
+            let setup = || -> std::io::Result<()> {
+                if unsafe { libc::setsid() } == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                fs::write(pid_file, format!("{}\n", std::process::id()))?;
+                let null = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open("/dev/null")?;
+                for fd in 0..=2 {
+                    if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            };
+            if let Err(e) = setup() {
+                let _ = writeln!(&write, "ERR detaching: {e}");
+                std::process::exit(1);
+            }
+            Ok(Detached {
+                pipe: write,
+                pid_file: pid_file.to_path_buf(),
+            })
+        }
+        pid => {
+            drop(write);
+            let mut line = String::new();
+            std::io::BufReader::new(read).read_line(&mut line)?;
+            match line.trim_end() {
+                "OK" => {
+                    println!(
+                        "Ring running in the background as PID {pid}; stop it with `stop --pid-file {}`",
+                        pid_file.display()
+                    );
+                    std::process::exit(0);
+                }
+                status => match status.strip_prefix("ERR ") {
+                    Some(msg) => Err(msg.to_string().into()),
+                    None => Err(format!(
+                        "background process {pid} exited before the ring was wired"
+                    )
+                    .into()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn detach(_pid_file: &Path) -> Result<Detached, Box<dyn Error + Send + Sync>> {
+    Err("--detach is only supported on Unix".into())
+}
+
+/// Wait for SIGTERM (`stop`) or Ctrl-C.
+async fn wait_for_term() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = term.recv() => {},
+                _ = tokio::signal::ctrl_c() => {},
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// `stop`: SIGTERM the `--detach` process named in `pid_file` and wait
+/// for it to stop its nodes and exit.
+#[cfg(unix)]
+async fn stop_detached(
+    pid_file: &Path,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let text =
+        fs::read_to_string(pid_file).map_err(|e| format!("read {}: {e}", pid_file.display()))?;
+    let pid: u32 = text
+        .trim()
+        .parse()
+        .map_err(|e| format!("{}: {e}", pid_file.display()))?;
+    if !pid_alive(pid) {
+        let _ = fs::remove_file(pid_file);
+        return Err(format!("no process {pid}; removed stale {}", pid_file.display()).into());
+    }
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let deadline = tokio::time::Instant::now() + timeout;
+    while pid_alive(pid) {
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("process {pid} still running after {}s", timeout.as_secs()).into());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    println!("Stopped {pid}");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn stop_detached(
+    _pid_file: &Path,
+    _timeout: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    Err("stop is only supported on Unix".into())
+}
+
+/// `ouroboros_fs <args>` for a ring node, with the AUTH token in its
+/// environment.
+fn node_command(exe: &Path, args: &[String], token: &AuthToken) -> Command {
+    let mut cmd = Command::new(exe);
+    cmd.args(args);
+    // Via env rather than argv so the secret doesn't show up in `ps`.
+    if let Some(bearer) = token.bearer_value() {
+        cmd.env("OUROBOROS_AUTH_TOKEN", bearer);
+    }
+    cmd
+}
+
+/// Carry out a `SetNext` or `SetPrev` action; anything else is a no-op.
+async fn wire(
+    action: &NetworkAction,
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match action {
+        NetworkAction::SetNext { node, next, ring } => {
+            send_set_next(node, next, ring.as_deref(), token, tls, hello).await?;
+            tracing::info!(from = %node, to = %next, ring = ?ring, "Wired node");
+        }
+        NetworkAction::SetPrev { node, prev } => {
+            ring_client(node, token, tls, hello).set_prev(prev).await?;
+        }
+        _ => {}
+    }
     Ok(())
 }
 
+/// Point `node`'s NEXT at `next`. On the default ring this reads `next`'s
+/// generation first and wires with `NODE NEXT-GEN`, so a `next` that
+/// restarts in between is refused rather than silently wired.
+async fn send_set_next(
+    node: &str,
+    next: &str,
+    ring: Option<&str>,
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = ring_client(node, token, tls, hello);
+    match ring {
+        Some(ring) => client.set_next_ring(ring, next).await?,
+        None => {
+            let generation = ring_client(next, token, tls, hello).get().await?.generation;
+            client.set_next_gen(next, generation).await?
+        }
+    };
+    Ok(())
+}
+
+/// Keeps one `dev-network` node up under `--supervise`.
+struct Supervisor {
+    addr: String,
+    /// [`plan_restart`] for `addr`.
+    restart: Vec<NetworkAction>,
+    exe: PathBuf,
+    token: AuthToken,
+    tls: TlsConfig,
+    hello: bool,
+    max_restarts: u32,
+    stop: watch::Receiver<bool>,
+}
+
+impl Supervisor {
+    /// Wait on `child` and carry out `restart` each time it exits, up to
+    /// `max_restarts` times, until `stop` fires. Returns the last process
+    /// started, for the caller to stop.
+    async fn run(mut self, mut child: Child) -> Child {
+        let addr = self.addr.clone();
+        let mut restarts = 0;
+        loop {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = self.stop.changed() => return child,
+            };
+            if *self.stop.borrow() {
+                return child;
+            }
+            if restarts == self.max_restarts {
+                tracing::error!(addr = %addr, status = ?status, restarts, at_unix_ms = now_unix_ms(), "Node exited; out of restarts, leaving it down");
+                return child;
+            }
+            tracing::warn!(addr = %addr, status = ?status, restart = restarts + 1, max_restarts = self.max_restarts, at_unix_ms = now_unix_ms(), "Node exited; restarting it");
+            backoff_sleep(&BackoffConfig::default(), restarts).await;
+            restarts += 1;
+            match self.restart(&mut child).await {
+                Ok(()) => {
+                    tracing::info!(addr = %addr, pid = ?child.id(), restart = restarts, at_unix_ms = now_unix_ms(), "Node restarted and rewired")
+                }
+                Err(e) => {
+                    tracing::error!(addr = %addr, restart = restarts, error = %e, at_unix_ms = now_unix_ms(), "Restarting node failed")
+                }
+            }
+        }
+    }
+
+    /// Carry out `restart`, replacing `child` with the new process.
+    async fn restart(&self, child: &mut Child) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for action in &self.restart {
+            match action {
+                NetworkAction::Spawn { args, .. } => {
+                    *child = node_command(&self.exe, args, &self.token).spawn()?;
+                }
+                NetworkAction::WaitListening { addr } => {
+                    wait_until_listening(addr, Duration::from_secs(5)).await?;
+                }
+                action => wire(action, &self.token, &self.tls, self.hello).await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Children get the default 30 s drain; allow a little on top before
+/// falling back to a kill.
+const GRACEFUL_EXIT_TIMEOUT: Duration = Duration::from_secs(35);
+
+/// Send `NODE SHUTDOWN` to every child, then wait for each to exit. A
+/// child that can't be reached or doesn't exit in time is killed.
+async fn shutdown_children(
+    addrs: &[String],
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
+    children: &mut [Child],
+) {
+    for addr in addrs {
+        if let Err(e) = ring_client(addr, token, tls, hello).shutdown().await {
+            tracing::warn!(addr = %addr, error = ?e, "NODE SHUTDOWN failed");
+        }
+    }
+    for child in children.iter_mut() {
+        let pid = child.id();
+        match tokio::time::timeout(GRACEFUL_EXIT_TIMEOUT, child.wait()).await {
+            Ok(Ok(status)) => tracing::info!(pid = ?pid, %status, "Node exited"),
+            Ok(Err(e)) => tracing::warn!(pid = ?pid, error = ?e, "Waiting for node failed"),
+            Err(_) => {
+                tracing::warn!(pid = ?pid, "Node did not exit in time; killing");
+                let _ = child.kill().await;
+            }
+        }
+    }
+}
+
 fn current_exe() -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
     Ok(env::current_exe()?)
 }
 
 async fn wait_until_listening(
-    host: &str,
-    port: u16,
+    addr: &str,
     deadline: Duration,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     let start = tokio::time::Instant::now();
-    let addr = format!("{host}:{port}");
-    loop {
-        match TcpStream::connect(&addr).await {
+    let backoff = BackoffConfig::default();
+    for attempt in 0.. {
+        match TcpStream::connect(addr).await {
             Ok(_) => return Ok(()),
             Err(_) => {
                 if start.elapsed() > deadline {
-                    return Err(format!("timed out while waiting for {addr}").into());
+                    break;
                 }
-                sleep(Duration::from_millis(50)).await;
+                backoff_sleep(&backoff, attempt).await;
             }
         }
     }
+    Err(format!("timed out while waiting for {addr}").into())
+}
+
+/// A client for the node at `addr` with the CLI's auth, TLS and `HELLO`
+/// settings. Either end may be a `unix:<path>` address.
+fn ring_client(addr: &str, token: &AuthToken, tls: &TlsConfig, hello: bool) -> RingClient {
+    RingClient::with_opts(addr, client_opts(token, tls, hello))
+}
+
+fn client_opts(token: &AuthToken, tls: &TlsConfig, hello: bool) -> ClientOpts {
+    ClientOpts {
+        auth_token: token.clone(),
+        tls: tls.clone(),
+        hello,
+        ..Default::default()
+    }
 }
 
-async fn send_node_next(
-    this_addr: &str,
-    next_addr: &str,
+// --- resume-network
+
+/// Re-wire the live members of the ring recorded in `path`; see
+/// [`plan_resume`].
+async fn resume_network(
+    path: &Path,
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut s = TcpStream::connect(this_addr).await?;
-    let line = format!("NODE NEXT {next_addr}\n");
-    s.write_all(line.as_bytes()).await?;
-
-    // Accept "OK" or "OK <anything>"
-    let mut reader = BufReader::new(s);
-    let mut buf = String::new();
-    let read = tokio::time::timeout(Duration::from_millis(150), reader.read_line(&mut buf)).await;
-    if read.is_err() {
-        // It's okay if the ACK races, we still consider the wiring successful
-        return Ok(());
+    let raw = fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    let members: Vec<Member> =
+        serde_json::from_str(&raw).map_err(|e| format!("parse {}: {e}", path.display()))?;
+
+    let mut alive = Vec::with_capacity(members.len());
+    let mut current_next = Vec::with_capacity(members.len());
+    for member in &members {
+        let client = ring_client(&member.addr, token, tls, hello);
+        let next = if !pid_alive(member.pid) {
+            Err("process is gone".to_string())
+        } else {
+            client
+                .get_ring(member.ring.as_deref().unwrap_or(DEFAULT_RING))
+                .await
+                .map_err(|e| e.to_string())
+        };
+        match next {
+            Ok(next) => {
+                alive.push(true);
+                current_next.push(next);
+            }
+            Err(e) => {
+                tracing::warn!(addr = %member.addr, pid = member.pid, error = %e, "Node is down");
+                alive.push(false);
+                current_next.push(None);
+            }
+        }
     }
-    let ack = buf.trim();
-    let upper = ack.to_ascii_uppercase();
-    if !(upper == "OK" || upper.starts_with("OK ")) {
-        return Err(format!("unexpected response to NODE NEXT from {this_addr}: {buf}").into());
+
+    let plan = plan_resume(&members, &alive, &current_next);
+    if plan.is_empty() {
+        tracing::info!("Every live node still has a live NEXT; nothing to re-wire");
+    }
+    for action in &plan {
+        if let NetworkAction::SetNext { node, next, ring } = action {
+            send_set_next(node, next, ring.as_deref(), token, tls, hello).await?;
+            tracing::info!(from = %node, to = %next, ring = ?ring, "Re-wired node");
+        }
     }
     Ok(())
 }
 
-async fn send_netmap_discover(start_addr: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut s = TcpStream::connect(start_addr).await?;
-    s.write_all(b"NETMAP DISCOVER\n").await?;
-    let mut reader = BufReader::new(s);
-    let mut buf = String::new();
-    let _ = tokio::time::timeout(Duration::from_millis(100), reader.read_line(&mut buf)).await;
+/// Whether process `pid` still exists (`kill -0`). A process we may not
+/// signal still counts.
+#[cfg(unix)]
+fn pid_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    let sent = unsafe { libc::kill(pid, 0) } == 0;
+    sent || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without `kill -0`, reachability alone decides.
+#[cfg(not(unix))]
+fn pid_alive(_pid: u32) -> bool {
+    true
+}
+
+// --- bench
+
+fn bench_opts(args: &BenchArgs, default_ttl: u32) -> BenchOpts {
+    BenchOpts {
+        rps: args.rps,
+        duration: Duration::from_secs(args.duration_secs),
+        ttl: args.ttl.unwrap_or(default_ttl),
+        connections: args.connections,
+    }
+}
+
+/// Print `report` as a table, and write it as JSON to `json_out` if set.
+fn print_bench(
+    report: &BenchReport,
+    json_out: Option<&Path>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    print!("{}", report.table());
+    if let Some(path) = json_out {
+        fs::write(path, serde_json::to_string_pretty(report)? + "\n")
+            .map_err(|e| format!("write {}: {e}", path.display()))?;
+    }
     Ok(())
 }
 
-async fn send_topology_walk(start_addr: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut s = TcpStream::connect(start_addr).await?;
-    s.write_all(b"TOPOLOGY WALK\n").await?;
-    let mut reader = BufReader::new(s);
-    let mut buf = String::new();
-    let _ = tokio::time::timeout(Duration::from_millis(100), reader.read_line(&mut buf)).await;
+// --- stat-network
+
+const STAT_KEYS: [&str; 9] = [
+    "UPTIME_SECS",
+    "MSGS_RECEIVED",
+    "MSGS_FORWARDED",
+    "WALK_COUNT",
+    "WALK_CACHE_HITS",
+    "RING_COUNT",
+    "ERRORS",
+    "CONNECTIONS",
+    "RING_SIZE",
+];
+
+/// Print one row per node. An unreachable node gets its own row rather
+/// than aborting the whole table.
+async fn stat_network(
+    nodes: u16,
+    base_port: u16,
+    host: &str,
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let header: String = STAT_KEYS.iter().map(|k| format!(" {k:>15}")).collect();
+    println!("{:<21}{header}", "NODE");
+    for i in 0..nodes {
+        let addr = join_host_port(host, base_port + i);
+        match ring_client(&addr, token, tls, hello).stat().await {
+            Ok(stats) => {
+                let row: String = STAT_KEYS
+                    .iter()
+                    .map(|k| format!(" {:>15}", stats.get(*k).map_or("-", String::as_str)))
+                    .collect();
+                println!("{addr:<21}{row}");
+            }
+            Err(e) => println!("{addr:<21} unreachable: {e}"),
+        }
+    }
     Ok(())
 }
 
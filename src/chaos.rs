@@ -0,0 +1,185 @@
+//! Fault injection for `CHAOS MODE` and the `chaos` subcommand.
+//!
+//! A node in chaos mode mistreats the `RING FORWARD` and `RING ON` hops it
+//! sends: it drops a fraction of them, holds each back for a random
+//! delay, or flips a bit in the message. A dropped hop is reported to the
+//! caller as sent, the way a lost packet would look. `CHAOS OFF` puts the
+//! node back to normal.
+//!
+//! This is for resilience tests only. Any client that can reach a node
+//! can switch it on, so a production ring should not expose its nodes to
+//! untrusted clients without `--auth-token`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::protocol::Probability;
+
+/// What a node in chaos mode does to the hops it forwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosMode {
+    /// Drop each hop with this probability.
+    Drop(Probability),
+    /// Hold each hop back for a delay drawn from a normal distribution,
+    /// cut off at zero.
+    Delay { mean_ms: u64, stddev_ms: u64 },
+    /// Flip one bit of the message with this probability.
+    Corrupt(Probability),
+}
+
+impl fmt::Display for ChaosMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChaosMode::Drop(p) => write!(f, "drop {p}"),
+            ChaosMode::Delay { mean_ms, stddev_ms } => write!(f, "delay {mean_ms} {stddev_ms}"),
+            ChaosMode::Corrupt(p) => write!(f, "corrupt {p}"),
+        }
+    }
+}
+
+impl FromStr for ChaosMode {
+    type Err = String;
+
+    /// `drop <probability>`, `delay <mean_ms> <stddev_ms>` or
+    /// `corrupt <probability>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let ms = |v: &str| {
+            v.parse::<u64>()
+                .map_err(|_| format!("invalid chaos delay '{v}'"))
+        };
+        match parts.as_slice() {
+            [mode, p] if mode.eq_ignore_ascii_case("drop") => Ok(ChaosMode::Drop(p.parse()?)),
+            [mode, p] if mode.eq_ignore_ascii_case("corrupt") => Ok(ChaosMode::Corrupt(p.parse()?)),
+            [mode, mean, stddev] if mode.eq_ignore_ascii_case("delay") => Ok(ChaosMode::Delay {
+                mean_ms: ms(mean)?,
+                stddev_ms: ms(stddev)?,
+            }),
+            _ => Err(format!(
+                "unknown chaos mode '{s}': want drop <p>, delay <mean_ms> <stddev_ms> or corrupt <p>"
+            )),
+        }
+    }
+}
+
+/// A node's chaos setting; `mode` is `None` when it behaves.
+#[derive(Debug, Default)]
+pub struct ChaosConfig {
+    pub mode: Option<ChaosMode>,
+}
+
+impl ChaosConfig {
+    /// What becomes of a hop carrying `msg`: `None` to drop it, otherwise
+    /// how long to hold it back and the message to send.
+    pub fn apply<'a>(&self, msg: &'a str) -> Option<(Duration, Cow<'a, str>)> {
+        let mut rng = rand::thread_rng();
+        match self.mode {
+            None => Some((Duration::ZERO, Cow::Borrowed(msg))),
+            Some(ChaosMode::Drop(p)) => (!p.flip()).then_some((Duration::ZERO, Cow::Borrowed(msg))),
+            Some(ChaosMode::Delay { mean_ms, stddev_ms }) => {
+                let ms = gaussian(&mut rng, mean_ms as f64, stddev_ms as f64).max(0.0);
+                Some((Duration::from_millis(ms as u64), Cow::Borrowed(msg)))
+            }
+            Some(ChaosMode::Corrupt(p)) if p.flip() => {
+                Some((Duration::ZERO, Cow::Owned(corrupt(&mut rng, msg))))
+            }
+            Some(ChaosMode::Corrupt(_)) => Some((Duration::ZERO, Cow::Borrowed(msg))),
+        }
+    }
+}
+
+/// A draw from N(`mean`, `stddev`²), by the Box-Muller transform.
+fn gaussian<R: Rng>(rng: &mut R, mean: f64, stddev: f64) -> f64 {
+    let u1: f64 = 1.0 - rng.r#gen::<f64>();
+    let u2: f64 = rng.r#gen();
+    mean + stddev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// `msg` with one bit flipped in one printable character, chosen so the
+/// result is still printable and the line still parses. Unchanged if it
+/// has no such character.
+fn corrupt<R: Rng>(rng: &mut R, msg: &str) -> String {
+    let mut bytes = msg.as_bytes().to_vec();
+    let candidates: Vec<usize> = (0..bytes.len())
+        .filter(|&i| bytes[i].is_ascii_graphic())
+        .collect();
+    if candidates.is_empty() {
+        return msg.to_string();
+    }
+    let i = candidates[rng.gen_range(0..candidates.len())];
+    let first = rng.gen_range(0..7);
+    for bit in (0..7).map(|b| (first + b) % 7) {
+        let flipped = bytes[i] ^ (1 << bit);
+        if flipped.is_ascii_graphic() {
+            bytes[i] = flipped;
+            break;
+        }
+    }
+    // Only one ASCII byte changed, to another ASCII byte.
+    String::from_utf8(bytes).unwrap_or_else(|_| msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip() {
+        for s in ["drop 0.25", "delay 100 20", "corrupt 1"] {
+            assert_eq!(s.parse::<ChaosMode>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "DELAY 5 0".parse::<ChaosMode>().unwrap(),
+            ChaosMode::Delay {
+                mean_ms: 5,
+                stddev_ms: 0
+            }
+        );
+        for bad in ["", "drop", "drop 2", "delay 5", "delay -1 0", "jitter 1"] {
+            assert!(bad.parse::<ChaosMode>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn extremes_are_deterministic() {
+        let off = ChaosConfig::default();
+        assert_eq!(off.apply("hi"), Some((Duration::ZERO, Cow::Borrowed("hi"))));
+        let drop_all = ChaosConfig {
+            mode: Some(ChaosMode::Drop(Probability::new(1.0).unwrap())),
+        };
+        assert_eq!(drop_all.apply("hi"), None);
+        let fixed_delay = ChaosConfig {
+            mode: Some(ChaosMode::Delay {
+                mean_ms: 40,
+                stddev_ms: 0,
+            }),
+        };
+        assert_eq!(
+            fixed_delay.apply("hi").unwrap().0,
+            Duration::from_millis(40)
+        );
+    }
+
+    #[test]
+    fn corruption_flips_one_bit_and_keeps_the_line_printable() {
+        let corrupt_all = ChaosConfig {
+            mode: Some(ChaosMode::Corrupt(Probability::new(1.0).unwrap())),
+        };
+        for _ in 0..200 {
+            let (_, out) = corrupt_all.apply("hello world").unwrap();
+            assert_eq!(out.len(), "hello world".len());
+            let diff: u32 = out
+                .bytes()
+                .zip("hello world".bytes())
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum();
+            assert_eq!(diff, 1, "{out}");
+            assert!(out.bytes().all(|b| b == b' ' || b.is_ascii_graphic()));
+        }
+        assert_eq!(corrupt_all.apply("  ").unwrap().1, "  ");
+    }
+}
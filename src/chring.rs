@@ -0,0 +1,157 @@
+//! Consistent-hash routing (`run --routing consistent-hash`).
+//!
+//! Each member sits on a 64-bit ring at the first eight bytes of
+//! `SHA256(<addr>)`; a key belongs to the first member at or after
+//! `SHA256(<key>)`, wrapping past the top. `RING KEY <key> <msg>` sends a
+//! message straight to that owner instead of around the ring, so keys
+//! spread over the members and only move when the membership changes.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// How a node routes `RING KEY` (`--routing`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoutingMode {
+    /// Messages only travel around the ring; `RING KEY` is refused.
+    #[default]
+    Sequential,
+    /// `RING KEY` goes to the key's owner on a [`ConsistentHashRing`] of
+    /// the heartbeat members.
+    ConsistentHash,
+}
+
+impl fmt::Display for RoutingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sequential => "sequential",
+            Self::ConsistentHash => "consistent-hash",
+        })
+    }
+}
+
+impl FromStr for RoutingMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sequential" => Ok(Self::Sequential),
+            "consistent-hash" => Ok(Self::ConsistentHash),
+            other => Err(format!(
+                "invalid routing mode '{other}': use sequential|consistent-hash"
+            )),
+        }
+    }
+}
+
+/// Position of `s` on the hash ring.
+pub fn position(s: &str) -> u64 {
+    let digest = Sha256::digest(s.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("SHA256 is 32 bytes"))
+}
+
+/// Members by ring position, lowest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistentHashRing {
+    positions: Vec<(u64, String)>,
+}
+
+impl ConsistentHashRing {
+    /// A ring of `addrs`; duplicates are kept once.
+    pub fn new<I, S>(addrs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut positions: Vec<(u64, String)> = addrs
+            .into_iter()
+            .map(Into::into)
+            .map(|addr| (position(&addr), addr))
+            .collect();
+        positions.sort();
+        positions.dedup();
+        Self { positions }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Members with their positions, lowest first.
+    pub fn members(&self) -> &[(u64, String)] {
+        &self.positions
+    }
+
+    /// The member `key` belongs to; `None` on an empty ring.
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        let at = position(key);
+        let i = self.positions.partition_point(|(p, _)| *p < at);
+        self.positions
+            .get(i)
+            .or_else(|| self.positions.first())
+            .map(|(_, addr)| addr.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_is_the_next_position_wrapping() {
+        let ring = ConsistentHashRing::new(["127.0.0.1:7000", "127.0.0.1:7001", "127.0.0.1:7002"]);
+        assert_eq!(ring.len(), 3);
+        let members = ring.members();
+        assert!(members.windows(2).all(|w| w[0].0 < w[1].0));
+
+        for key in ["a", "b", "user:42", "z", ""] {
+            let at = position(key);
+            let expected = members
+                .iter()
+                .find(|(p, _)| *p >= at)
+                .unwrap_or(&members[0]);
+            assert_eq!(ring.owner(key), Some(expected.1.as_str()), "{key}");
+        }
+        // A member's own address hashes onto itself.
+        for (_, addr) in members {
+            assert_eq!(ring.owner(addr), Some(addr.as_str()));
+        }
+        assert_eq!(ConsistentHashRing::default().owner("a"), None);
+    }
+
+    #[test]
+    fn only_keys_of_a_departed_member_move() {
+        let addrs: Vec<String> = (0..5).map(|i| format!("127.0.0.1:{}", 7000 + i)).collect();
+        let full = ConsistentHashRing::new(addrs.clone());
+        let gone = &addrs[2];
+        let smaller = ConsistentHashRing::new(addrs.iter().filter(|a| *a != gone).cloned());
+        for i in 0..200 {
+            let key = format!("key-{i}");
+            let before = full.owner(&key).unwrap();
+            if before != gone {
+                assert_eq!(smaller.owner(&key), Some(before), "{key}");
+            }
+        }
+        assert_eq!(ConsistentHashRing::new(["x", "x"]).len(), 1);
+    }
+
+    #[test]
+    fn routing_mode_parses() {
+        assert_eq!(
+            "consistent-hash".parse::<RoutingMode>(),
+            Ok(RoutingMode::ConsistentHash)
+        );
+        assert_eq!(
+            "Sequential".parse::<RoutingMode>(),
+            Ok(RoutingMode::Sequential)
+        );
+        assert!("hash".parse::<RoutingMode>().is_err());
+        assert_eq!(RoutingMode::ConsistentHash.to_string(), "consistent-hash");
+    }
+}
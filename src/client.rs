@@ -0,0 +1,799 @@
+//! Typed async client for a single ring node.
+//!
+//! Wraps the line protocol so a program can call `set_next` or `walk`
+//! instead of writing `NODE NEXT ...` to a socket and parsing the reply by
+//! hand. One [`RingClient`] holds one connection to one node, opened on
+//! first use with the `HELLO` check and `AUTH` line the node expects, and
+//! reused for later commands. A reused connection that the node has since
+//! closed (idle timeout, restart) is reopened and the command sent again.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+
+use crate::auth::AuthToken;
+use crate::chaos::ChaosMode;
+use crate::error::RingError;
+use crate::events::EventFilter;
+use crate::protocol::{
+    MIN_SUPPORTED_VERSION, Response, negotiate_version, now_unix_ms, parse_response,
+};
+
+pub use crate::protocol::NodeInfo;
+use crate::snapshot::NodeSnapshot;
+use crate::topology::ParallelTopology;
+use crate::transport::{Stream, TlsConfig};
+
+/// Reply timeout when [`ClientOpts::timeout`] is zero.
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reply timeout for walks and gathers: a little past the node's own 30 s
+/// walk timeout, so the node's `ERR walk timeout` is what gets reported.
+const WALK_REPLY_TIMEOUT: Duration = Duration::from_secs(35);
+
+/// The directed `from->to` edges of a `TOPOLOGY WALK`, in walk order.
+/// Endpoints are as the node reports them: a port, or `name@port`.
+pub type WalkResult = Vec<(String, String)>;
+
+/// [`parse_response`] over reply lines as the client reads them.
+fn response(lines: &[String]) -> Result<Response, RingError> {
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    parse_response(&lines)
+}
+
+/// The edges of a walk reply's lines; none if the node sent only `OK`.
+fn walk_edges(lines: &[String]) -> Result<WalkResult, RingError> {
+    if lines.is_empty() {
+        return Ok(Vec::new());
+    }
+    match response(lines)? {
+        Response::WalkResult { edges } => Ok(edges.into_iter().map(|e| (e.from, e.to)).collect()),
+        other => Err(RingError::ParseError(format!(
+            "expected walk edges, got {other:?}"
+        ))),
+    }
+}
+
+/// Connection settings for a [`RingClient`]. The default is plaintext,
+/// unauthenticated, no `HELLO`, and [`DEFAULT_CLIENT_TIMEOUT`].
+#[derive(Clone, Debug, Default)]
+pub struct ClientOpts {
+    /// Sent as the `AUTH` line when enabled; must match the node's token.
+    pub auth_token: AuthToken,
+    /// Client-side TLS; must match the node's listener.
+    pub tls: TlsConfig,
+    /// Expect and check the node's `HELLO` greeting (`--hello`).
+    pub hello: bool,
+    /// How long to wait for a reply. Zero means [`DEFAULT_CLIENT_TIMEOUT`].
+    pub timeout: Duration,
+}
+
+pub struct RingClient {
+    addr: String,
+    opts: ClientOpts,
+    conn: Mutex<Option<BufReader<Stream>>>,
+}
+
+impl RingClient {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self::with_opts(addr, ClientOpts::default())
+    }
+
+    pub fn with_opts(addr: impl Into<String>, opts: ClientOpts) -> Self {
+        Self {
+            addr: addr.into(),
+            opts,
+            conn: Mutex::new(None),
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// A client for `addr` with this client's options.
+    pub fn peer(&self, addr: impl Into<String>) -> Self {
+        Self::with_opts(addr, self.opts.clone())
+    }
+
+    /// `NODE NEXT <addr>`. Returns the pointer the node stored, which is
+    /// the resolved `ip:port` when `addr` was a hostname.
+    pub async fn set_next(&self, addr: &str) -> Result<String, RingError> {
+        let reply = self.ok_reply(&format!("NODE NEXT {addr}\n")).await?;
+        let reply = reply.strip_suffix(" (unchanged)").unwrap_or(&reply);
+        Ok(reply
+            .strip_prefix("next=")
+            .map_or_else(|| addr.to_string(), str::to_string))
+    }
+
+    /// `NODE NEXT-GEN <addr> <generation>`: [`RingClient::set_next`],
+    /// refused with [`RingError::Remote`] unless `addr` still reports
+    /// `generation`.
+    pub async fn set_next_gen(&self, addr: &str, generation: u64) -> Result<String, RingError> {
+        let reply = self
+            .ok_reply(&format!("NODE NEXT-GEN {addr} {generation}\n"))
+            .await?;
+        Ok(reply
+            .strip_prefix("next=")
+            .map_or_else(|| addr.to_string(), str::to_string))
+    }
+
+    /// `NODE NEXT-MULTI <addr>...`. Returns the hops the node stored.
+    pub async fn set_next_multi(&self, addrs: &[&str]) -> Result<Vec<String>, RingError> {
+        let reply = self
+            .ok_reply(&format!("NODE NEXT-MULTI {}\n", addrs.join(" ")))
+            .await?;
+        Ok(match reply.strip_prefix("next=") {
+            Some(hops) => hops.split(',').map(str::to_string).collect(),
+            None => addrs.iter().map(|a| a.to_string()).collect(),
+        })
+    }
+
+    /// `NODE NEXT-RING <ring> <addr>`: [`RingClient::set_next`] on a
+    /// named ring.
+    pub async fn set_next_ring(&self, ring: &str, addr: &str) -> Result<String, RingError> {
+        let reply = self
+            .ok_reply(&format!("NODE NEXT-RING {ring} {addr}\n"))
+            .await?;
+        Ok(reply
+            .strip_prefix("next=")
+            .map_or_else(|| addr.to_string(), str::to_string))
+    }
+
+    /// `NODE NEXT-RINGS`: [`RingClient::set_next_ring`] for every
+    /// `(ring, addr)` pair at once. Returns how many the node set; if any
+    /// address is refused, none are.
+    pub async fn set_next_rings(&self, hops: &[(&str, &str)]) -> Result<usize, RingError> {
+        let pairs: Vec<String> = hops
+            .iter()
+            .map(|(ring, addr)| format!("{ring}={addr}"))
+            .collect();
+        let reply = self
+            .ok_reply(&format!("NODE NEXT-RINGS {}\n", pairs.join(";")))
+            .await?;
+        reply
+            .strip_prefix("next_count=")
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| {
+                RingError::ParseError(format!(
+                    "{}: unexpected NODE NEXT-RINGS reply {reply:?}",
+                    self.addr
+                ))
+            })
+    }
+
+    /// `NODE RING <ring>`: the node's NEXT on `ring`, if set.
+    pub async fn get_ring(&self, ring: &str) -> Result<Option<String>, RingError> {
+        let lines = self
+            .request(&format!("NODE RING {ring}\n"), self.timeout())
+            .await?;
+        match response(&lines)? {
+            Response::NodeInfo(info) => Ok(info.next),
+            other => Err(RingError::ParseError(format!(
+                "{}: expected NEXT, got {other:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `NODE PREV <addr>`.
+    pub async fn set_prev(&self, addr: &str) -> Result<(), RingError> {
+        self.ok_reply(&format!("NODE PREV {addr}\n")).await?;
+        Ok(())
+    }
+
+    /// `NODE STATUS`.
+    pub async fn get(&self) -> Result<NodeInfo, RingError> {
+        let lines = self.request("NODE STATUS\n", self.timeout()).await?;
+        match response(&lines)? {
+            Response::NodeInfo(info) => Ok(*info),
+            other => Err(RingError::ParseError(format!(
+                "{}: expected NODE STATUS, got {other:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `NODE INFO`: [`RingClient::get`] plus the node's uptime, counters
+    /// and version, in one round trip.
+    pub async fn node_info(&self) -> Result<NodeInfo, RingError> {
+        let lines = self.request("NODE INFO\n", self.timeout()).await?;
+        match response(&lines)? {
+            Response::NodeInfo(info) => Ok(*info),
+            other => Err(RingError::ParseError(format!(
+                "{}: expected NODE INFO, got {other:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `NODE SHUTDOWN`: the node drains its connections and exits.
+    pub async fn shutdown(&self) -> Result<(), RingError> {
+        self.ok_reply("NODE SHUTDOWN\n").await?;
+        Ok(())
+    }
+
+    /// `NODE PING`; `Ok` once the node answers `PONG`.
+    pub async fn ping(&self) -> Result<(), RingError> {
+        let timeout = self.timeout();
+        self.exchange("NODE PING\n", timeout, |l| l == "PONG")
+            .await?;
+        Ok(())
+    }
+
+    /// `NODE STAT`: the node's runtime counters by name.
+    pub async fn stat(&self) -> Result<HashMap<String, String>, RingError> {
+        let lines = self.request("NODE STAT\n", self.timeout()).await?;
+        Ok(lines
+            .iter()
+            .filter_map(|l| l.split_once(' '))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect())
+    }
+
+    /// `RING FORWARD <ttl> <msg>`.
+    pub async fn ring(&self, ttl: u32, msg: &str) -> Result<(), RingError> {
+        self.ok_reply(&format!("RING FORWARD {ttl} {msg}\n"))
+            .await?;
+        Ok(())
+    }
+
+    /// `RING ALL <msg>`: [`RingClient::ring`] with the TTL the node
+    /// derives from the ring size it last counted.
+    pub async fn ring_all(&self, msg: &str) -> Result<(), RingError> {
+        self.ok_reply(&format!("RING ALL {msg}\n")).await?;
+        Ok(())
+    }
+
+    /// `RING TIMED <expiry> <msg>` with the expiry `ttl` from now. Returns
+    /// `false` when the first node already found it expired.
+    pub async fn ring_timed(&self, ttl: Duration, msg: &str) -> Result<bool, RingError> {
+        let expiry_ms = now_unix_ms().saturating_add(ttl.as_millis() as u64);
+        let rest = self
+            .ok_reply(&format!("RING TIMED {expiry_ms} {msg}\n"))
+            .await?;
+        Ok(rest != "EXPIRED")
+    }
+
+    /// `RING ON <ring> <ttl> <msg>`: [`RingClient::ring`] along a named
+    /// ring.
+    pub async fn ring_on(&self, ring: &str, ttl: u32, msg: &str) -> Result<(), RingError> {
+        self.ok_reply(&format!("RING ON {ring} {ttl} {msg}\n"))
+            .await?;
+        Ok(())
+    }
+
+    /// `RING ECHO <ttl> <msg>`: the message goes `ttl` hops around the
+    /// ring and back. Returns the echoed message.
+    pub async fn echo(&self, ttl: u32, msg: &str) -> Result<String, RingError> {
+        let lines = self
+            .request(&format!("RING ECHO {ttl} {msg}\n"), WALK_REPLY_TIMEOUT)
+            .await?;
+        Ok(lines
+            .iter()
+            .find_map(|l| l.strip_prefix("ECHO "))
+            .unwrap_or("")
+            .to_string())
+    }
+
+    /// `RING ONCE <msg>`: the message goes once round the ring. Returns
+    /// once it is back at this node.
+    pub async fn ring_once(&self, msg: &str) -> Result<(), RingError> {
+        self.exchange(&format!("RING ONCE {msg}\n"), WALK_REPLY_TIMEOUT, is_ok)
+            .await?;
+        Ok(())
+    }
+
+    /// `CHAOS MODE <mode>`, or `CHAOS OFF` for `None`. For tests only;
+    /// see [`crate::chaos`].
+    pub async fn chaos(&self, mode: Option<&ChaosMode>) -> Result<(), RingError> {
+        let line = match mode {
+            Some(mode) => format!("CHAOS MODE {mode}\n"),
+            None => "CHAOS OFF\n".to_string(),
+        };
+        self.ok_reply(&line).await?;
+        Ok(())
+    }
+
+    /// `BARRIER ENTER <barrier_id> <n>`: one arrival, counted by every node.
+    pub async fn barrier(&self, barrier_id: &str, n: u32) -> Result<(), RingError> {
+        self.ok_reply(&format!("BARRIER ENTER {barrier_id} {n}\n"))
+            .await?;
+        Ok(())
+    }
+
+    /// `BARRIER WAIT <barrier_id> <n>`: returns once the node has counted
+    /// `n` arrivals.
+    pub async fn barrier_wait(&self, barrier_id: &str, n: u32) -> Result<(), RingError> {
+        self.exchange(
+            &format!("BARRIER WAIT {barrier_id} {n}\n"),
+            WALK_REPLY_TIMEOUT,
+            |l| l.starts_with("BARRIER DONE "),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// `INCR START <counter_name>`: the counter's value once the increment has
+    /// been round the ring.
+    pub async fn incr(&self, counter_name: &str) -> Result<u64, RingError> {
+        let lines = self
+            .request(&format!("INCR START {counter_name}\n"), WALK_REPLY_TIMEOUT)
+            .await?;
+        lines
+            .iter()
+            .find_map(|l| l.strip_prefix("INCR_RESULT "))
+            .and_then(|l| l.rsplit(' ').next())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| {
+                RingError::ParseError(format!("{}: unexpected INCR reply {lines:?}", self.addr))
+            })
+    }
+
+    /// `TOPOLOGY WALK` from this node.
+    pub async fn walk(&self) -> Result<WalkResult, RingError> {
+        let lines = self.request("TOPOLOGY WALK\n", WALK_REPLY_TIMEOUT).await?;
+        walk_edges(&lines)
+    }
+
+    /// `TOPOLOGY RING-WALK <ring>` from this node.
+    pub async fn walk_ring(&self, ring: &str) -> Result<WalkResult, RingError> {
+        let lines = self
+            .request(&format!("TOPOLOGY RING-WALK {ring}\n"), WALK_REPLY_TIMEOUT)
+            .await?;
+        walk_edges(&lines)
+    }
+
+    /// `GATHER <key>`: one `(addr, value)` per node, in ring order.
+    pub async fn gather(&self, key: &str) -> Result<Vec<(String, String)>, RingError> {
+        let lines = self
+            .request(&format!("GATHER {key}\n"), WALK_REPLY_TIMEOUT)
+            .await?;
+        Ok(lines
+            .iter()
+            .filter_map(|l| l.split_once('='))
+            .map(|(a, v)| (a.to_string(), v.to_string()))
+            .collect())
+    }
+
+    /// `GATHER STREAM <key>`: like [`RingClient::gather`], but the
+    /// `(addr, value)` pairs are read from the returned stream as the
+    /// nodes report them rather than all at the end.
+    pub async fn gather_stream(&self, key: &str) -> Result<GatherStream, RingError> {
+        let lines = self
+            .request(&format!("GATHER STREAM {key}\n"), self.timeout())
+            .await?;
+        let port = lines
+            .first()
+            .and_then(|l| l.strip_prefix("STREAM_PORT "))
+            .and_then(|p| p.parse::<u16>().ok())
+            .ok_or_else(|| {
+                RingError::ParseError(format!(
+                    "{}: unexpected GATHER STREAM reply {lines:?}",
+                    self.addr
+                ))
+            })?;
+        let host = self.addr.rsplit_once(':').map_or("", |(host, _)| host);
+        let stream = RingClient::with_opts(format!("{host}:{port}"), self.opts.clone());
+        Ok(GatherStream {
+            conn: stream.connect().await?,
+            addr: stream.addr,
+        })
+    }
+
+    /// `RING COLLECT <ttl> <key>`: one `(addr, value)` per node the walk
+    /// reached, in ring order; the value is `-` where `key` is unset.
+    pub async fn collect(&self, ttl: u32, key: &str) -> Result<Vec<(String, String)>, RingError> {
+        let lines = self
+            .request(&format!("RING COLLECT {ttl} {key}\n"), WALK_REPLY_TIMEOUT)
+            .await?;
+        Ok(lines
+            .iter()
+            .filter_map(|l| l.split_once('='))
+            .map(|(a, v)| (a.to_string(), v.to_string()))
+            .collect())
+    }
+
+    /// `TOPOLOGY WALK-FIND <key> <value>`: the first node round the ring
+    /// from this one whose KV store holds `key` = `value`, with the edges
+    /// walked to reach it; `None` if no node does.
+    pub async fn walk_find(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<(String, WalkResult)>, RingError> {
+        let lines = self
+            .request(
+                &format!("TOPOLOGY WALK-FIND {key} {value}\n"),
+                WALK_REPLY_TIMEOUT,
+            )
+            .await?;
+        match lines.split_first() {
+            Some((first, _)) if first == "NOT FOUND" => Ok(None),
+            Some((first, edges)) if first.starts_with("FOUND ") => Ok(Some((
+                first["FOUND ".len()..].to_string(),
+                walk_edges(edges)?,
+            ))),
+            _ => Err(RingError::ParseError(format!(
+                "{}: unexpected TOPOLOGY WALK-FIND reply {lines:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `SPLIT-BRAIN CHECK`: the distinct leaders the nodes round the ring
+    /// from this one name, sorted. Empty before any election; more than
+    /// one is a split brain.
+    pub async fn split_brain_check(&self) -> Result<Vec<String>, RingError> {
+        let lines = self
+            .exchange("SPLIT-BRAIN CHECK\n", WALK_REPLY_TIMEOUT, |l| {
+                is_ok(l) || l.starts_with("SPLIT_BRAIN ")
+            })
+            .await?;
+        let last = lines.last().map(String::as_str).unwrap_or_default();
+        if let Some(leader) = last.strip_prefix("OK leader=") {
+            return Ok(match leader {
+                "<unset>" => Vec::new(),
+                leader => vec![leader.to_string()],
+            });
+        }
+        match last.strip_prefix("SPLIT_BRAIN detected leaders=") {
+            Some(list) => Ok(list.split(',').map(str::to_string).collect()),
+            None => Err(RingError::ParseError(format!(
+                "{}: unexpected SPLIT-BRAIN CHECK reply {lines:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `NODE FORWARD-TO`: have the node run `command` on `target` and return
+    /// the target's reply lines, as [`RingClient::raw`] would.
+    pub async fn forward_to(&self, target: &str, command: &str) -> Result<Vec<String>, RingError> {
+        self.raw(&format!("NODE FORWARD-TO {target} {command}"))
+            .await
+    }
+
+    /// `RING KEY`: deliver `msg` to the node that owns `key` on the
+    /// node's hash ring; `Ok` once the owner has acknowledged it.
+    pub async fn ring_key(&self, key: &str, msg: &str) -> Result<(), RingError> {
+        let lines = self
+            .exchange(&format!("RING KEY {key} {msg}\n"), self.timeout(), |l| {
+                l.starts_with("KEY_ACK ")
+            })
+            .await?;
+        match lines.last().and_then(|l| l.strip_prefix("KEY_ACK ")) {
+            Some(acked) if acked == key => Ok(()),
+            _ => Err(RingError::ParseError(format!(
+                "{}: unexpected RING KEY reply {lines:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `SNAPSHOT`: the node's portable state.
+    pub async fn snapshot(&self) -> Result<NodeSnapshot, RingError> {
+        let lines = self.request("SNAPSHOT\n", self.timeout()).await?;
+        match lines.as_slice() {
+            [line] => NodeSnapshot::parse(line),
+            _ => Err(RingError::ParseError(format!(
+                "{}: unexpected SNAPSHOT reply {lines:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// `RESTORE`: replace the node's state with `snapshot`.
+    pub async fn restore(&self, snapshot: &NodeSnapshot) -> Result<(), RingError> {
+        self.ok_reply(&format!("RESTORE {}\n", snapshot.to_line()))
+            .await?;
+        Ok(())
+    }
+
+    /// `TOPOLOGY WALK-PARALLEL`: every member of the node's heartbeat
+    /// coordinator with its NEXT, asked at once. `partial` is set when some
+    /// member did not answer in time.
+    pub async fn walk_parallel(&self) -> Result<ParallelTopology, RingError> {
+        let lines = self
+            .exchange("TOPOLOGY WALK-PARALLEL\n", WALK_REPLY_TIMEOUT, is_ok)
+            .await?;
+        let json = lines
+            .last()
+            .and_then(|l| l.strip_prefix("OK "))
+            .ok_or_else(|| {
+                RingError::ParseError(format!(
+                    "{}: unexpected TOPOLOGY WALK-PARALLEL reply {lines:?}",
+                    self.addr
+                ))
+            })?;
+        serde_json::from_str(json).map_err(|e| {
+            RingError::ParseError(format!(
+                "{}: bad TOPOLOGY WALK-PARALLEL reply: {e}",
+                self.addr
+            ))
+        })
+    }
+
+    /// `NETMAP DISCOVER`: start a netmap walk from this node.
+    pub async fn netmap_discover(&self) -> Result<(), RingError> {
+        self.ok_reply("NETMAP DISCOVER\n").await?;
+        Ok(())
+    }
+
+    /// `SUBSCRIBE <events>` on a connection of its own, which then carries
+    /// only events until [`EventStream::unsubscribe`].
+    pub async fn subscribe(&self, events: EventFilter) -> Result<EventStream, RingError> {
+        let conn = self.connect().await?;
+        match self
+            .exchange_on(
+                conn,
+                &format!("SUBSCRIBE {events}\n"),
+                self.timeout(),
+                is_ok,
+            )
+            .await
+        {
+            Ok((conn, _)) => Ok(EventStream {
+                addr: self.addr.clone(),
+                conn,
+                timeout: self.timeout(),
+            }),
+            Err(Retry::Stale) => Err(RingError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{}: connection closed before reply", self.addr),
+            ))),
+            Err(Retry::Fail(e)) => Err(e),
+        }
+    }
+
+    /// Send one protocol line as typed and return every reply line up to
+    /// and including the closing `OK`, `PONG`, `CAS_FAIL`, `BARRIER DONE`,
+    /// `SPLIT_BRAIN` or `KEY_ACK`. Waits as long
+    /// as a walk. For the `repl` subcommand; commands whose reply has no
+    /// closing line (`FILE LIST`, `FILE PULL`) are not supported.
+    pub async fn raw(&self, line: &str) -> Result<Vec<String>, RingError> {
+        let line = format!("{}\n", line.trim_end_matches(['\r', '\n']));
+        let timeout = self.timeout().max(WALK_REPLY_TIMEOUT);
+        self.exchange(&line, timeout, |l| {
+            is_ok(l)
+                || l == "PONG"
+                || l.starts_with("CAS_FAIL ")
+                || l.starts_with("BARRIER DONE ")
+                || l.starts_with("SPLIT_BRAIN ")
+                || l.starts_with("KEY_ACK ")
+        })
+        .await
+    }
+
+    fn timeout(&self) -> Duration {
+        if self.opts.timeout.is_zero() {
+            DEFAULT_CLIENT_TIMEOUT
+        } else {
+            self.opts.timeout
+        }
+    }
+
+    /// Send a command answered by a single `OK` line and return whatever
+    /// followed `OK ` on it.
+    async fn ok_reply(&self, line: &str) -> Result<String, RingError> {
+        let timeout = self.timeout();
+        let lines = self.exchange(line, timeout, is_ok).await?;
+        match response(&lines)? {
+            Response::Ok(detail) => Ok(detail),
+            other => Err(RingError::ParseError(format!(
+                "{}: expected OK, got {other:?}",
+                self.addr
+            ))),
+        }
+    }
+
+    /// Send a command answered by some lines and then `OK`; return the
+    /// lines before the `OK`.
+    async fn request(&self, line: &str, timeout: Duration) -> Result<Vec<String>, RingError> {
+        let mut lines = self.exchange(line, timeout, is_ok).await?;
+        lines.pop();
+        Ok(lines)
+    }
+
+    /// Write `line` and read reply lines up to and including the one
+    /// `is_last` accepts. An `ERR` line ends the reply as
+    /// [`RingError::Remote`]. If a reused connection turns out to be closed
+    /// before any reply arrives, the node never read the command, so it is
+    /// sent once more on a fresh connection.
+    async fn exchange(
+        &self,
+        line: &str,
+        timeout: Duration,
+        is_last: fn(&str) -> bool,
+    ) -> Result<Vec<String>, RingError> {
+        let mut conn = self.conn.lock().await;
+        let reused = conn.is_some();
+        let res = match conn.take() {
+            Some(c) => self.exchange_on(c, line, timeout, is_last).await,
+            None => {
+                let c = self.connect().await?;
+                self.exchange_on(c, line, timeout, is_last).await
+            }
+        };
+        let res = match res {
+            Err(Retry::Stale) if reused => {
+                tracing::debug!(addr = %self.addr, "Client connection closed; reconnecting");
+                let c = self.connect().await?;
+                self.exchange_on(c, line, timeout, is_last).await
+            }
+            res => res,
+        };
+        match res {
+            Ok((c, lines)) => {
+                *conn = Some(c);
+                Ok(lines)
+            }
+            Err(Retry::Stale) => Err(RingError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{}: connection closed before reply", self.addr),
+            ))),
+            Err(Retry::Fail(e)) => Err(e),
+        }
+    }
+
+    async fn exchange_on(
+        &self,
+        mut conn: BufReader<Stream>,
+        line: &str,
+        timeout: Duration,
+        is_last: fn(&str) -> bool,
+    ) -> Result<(BufReader<Stream>, Vec<String>), Retry> {
+        if conn.get_mut().write_all(line.as_bytes()).await.is_err() {
+            return Err(Retry::Stale);
+        }
+        let mut lines = Vec::new();
+        let read = async {
+            loop {
+                let mut buf = String::new();
+                match conn.read_line(&mut buf).await {
+                    Ok(0) if lines.is_empty() => return Err(Retry::Stale),
+                    Ok(0) => {
+                        return Err(Retry::Fail(RingError::Io(std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            format!("{}: connection closed mid-reply", self.addr),
+                        ))));
+                    }
+                    Ok(_) => {}
+                    Err(_) if lines.is_empty() => return Err(Retry::Stale),
+                    Err(e) => return Err(Retry::Fail(e.into())),
+                }
+                let reply = buf.trim_end_matches(['\r', '\n']).to_string();
+                // The node's parting line when it drops an idle connection;
+                // it closed without reading the command.
+                if lines.is_empty() && reply == "ERR idle timeout" {
+                    return Err(Retry::Stale);
+                }
+                if let Some(msg) = reply.strip_prefix("ERR ") {
+                    return Err(Retry::Fail(RingError::Remote {
+                        addr: self.addr.clone(),
+                        msg: msg.to_string(),
+                    }));
+                }
+                let done = is_last(&reply);
+                lines.push(reply);
+                if done {
+                    return Ok(());
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, read).await {
+            Ok(Ok(())) => Ok((conn, lines)),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(Retry::Fail(e.into())),
+        }
+    }
+
+    /// Open a connection, check the `HELLO` greeting if configured, and
+    /// send the `AUTH` line when the token is enabled.
+    async fn connect(&self) -> Result<BufReader<Stream>, RingError> {
+        let mut s = self.opts.tls.connect(&self.addr).await?;
+        if self.opts.hello {
+            let version = negotiate_version(&mut s, MIN_SUPPORTED_VERSION)
+                .await
+                .map_err(|e| format!("{}: {e}", self.addr))?;
+            tracing::debug!(addr = %self.addr, %version, "Protocol version negotiated");
+        }
+        if let Some(line) = self.opts.auth_token.make_auth_line() {
+            s.write_all(line.as_bytes()).await?;
+        }
+        Ok(BufReader::new(s))
+    }
+}
+
+/// A subscribed connection from [`RingClient::subscribe`].
+pub struct EventStream {
+    addr: String,
+    conn: BufReader<Stream>,
+    timeout: Duration,
+}
+
+impl EventStream {
+    /// The next `EVENT <type> <data>` line, without its newline; `None`
+    /// once the node closes the connection. Waits as long as it takes.
+    pub async fn next_event(&mut self) -> Result<Option<String>, RingError> {
+        let mut buf = String::new();
+        if self.conn.read_line(&mut buf).await? == 0 {
+            return Ok(None);
+        }
+        let line = buf.trim_end_matches(['\r', '\n']);
+        match line.strip_prefix("ERR ") {
+            Some(msg) => Err(RingError::Remote {
+                addr: self.addr.clone(),
+                msg: msg.to_string(),
+            }),
+            None => Ok(Some(line.to_string())),
+        }
+    }
+
+    /// `UNSUBSCRIBE`, skipping events still in flight until the node's
+    /// `OK`.
+    pub async fn unsubscribe(mut self) -> Result<(), RingError> {
+        self.conn.get_mut().write_all(b"UNSUBSCRIBE\n").await?;
+        let timeout = self.timeout;
+        let read = async {
+            while let Some(line) = self.next_event().await? {
+                if is_ok(&line) {
+                    return Ok(());
+                }
+            }
+            Err(RingError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{}: connection closed before reply", self.addr),
+            )))
+        };
+        tokio::time::timeout(timeout, read).await?
+    }
+}
+
+/// The stream port of a [`RingClient::gather_stream`].
+pub struct GatherStream {
+    addr: String,
+    conn: BufReader<Stream>,
+}
+
+impl GatherStream {
+    /// The next node's `(addr, value)`; `None` after the closing `END`.
+    /// Waits as long as the node takes, up to its 30 s walk timeout.
+    pub async fn next_entry(&mut self) -> Result<Option<(String, String)>, RingError> {
+        let mut buf = String::new();
+        if self.conn.read_line(&mut buf).await? == 0 {
+            return Err(RingError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{}: stream closed before END", self.addr),
+            )));
+        }
+        let line = buf.trim_end_matches(['\r', '\n']);
+        if line == "END" {
+            return Ok(None);
+        }
+        if let Some(msg) = line.strip_prefix("ERR ") {
+            return Err(RingError::Remote {
+                addr: self.addr.clone(),
+                msg: msg.to_string(),
+            });
+        }
+        line.strip_prefix("DATA ")
+            .and_then(|entry| entry.split_once('='))
+            .map(|(a, v)| Some((a.to_string(), v.to_string())))
+            .ok_or_else(|| {
+                RingError::ParseError(format!("{}: unexpected stream line {line:?}", self.addr))
+            })
+    }
+}
+
+/// Why an exchange failed: `Stale` means the connection was closed before
+/// any reply, so the command can be safely resent.
+enum Retry {
+    Stale,
+    Fail(RingError),
+}
+
+fn is_ok(line: &str) -> bool {
+    line == "OK" || line.starts_with("OK ")
+}
@@ -0,0 +1,233 @@
+//! Compressed payload fields (`--compress-threshold-bytes`).
+//!
+//! A `RING` message or `TOPOLOGY PAY-*` payload list longer than the
+//! threshold is sent as `z:<base64>`: the field as a zstd frame (see
+//! [`crate::zstd`]), base64-encoded so it stays one space-free token.
+//! The receiving node expands it before dispatch
+//! ([`crate::protocol::expand_payload`]), so handlers only see plain text.
+//!
+//! A `z:` field is always expanded, and one that does not decode (bad
+//! base64, a corrupt frame, output past the limit, or text with a line
+//! break) is refused rather than passed on as given. So that a plain
+//! field which itself starts with `z:` survives the trip,
+//! [`compress_field`] always compresses one, whatever its length and the
+//! threshold.
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// `--compress-threshold-bytes` when the flag is not given.
+pub const DEFAULT_COMPRESS_THRESHOLD_BYTES: usize = 4096;
+
+/// Largest field [`decompress_field`] will produce when
+/// `--max-line-bytes` is off, so a tiny line can't expand into an
+/// unbounded allocation.
+pub const MAX_DECOMPRESSED_BYTES: usize = 16 << 20;
+
+/// Marks a compressed field.
+pub const COMPRESSED_PREFIX: &str = "z:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    Base64,
+    Corrupt,
+    Dictionary,
+    TooLarge,
+    NotText,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CodecError::Base64 => "compressed field is not valid base64",
+            CodecError::Corrupt => "compressed field is corrupt",
+            CodecError::Dictionary => "compressed field needs a zstd dictionary",
+            CodecError::TooLarge => "compressed field expands past the size limit",
+            CodecError::NotText => "compressed field is not single-line UTF-8",
+        })
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+pub fn is_compressed(field: &str) -> bool {
+    field.starts_with(COMPRESSED_PREFIX)
+}
+
+/// How far a compressed field may expand on a node run with
+/// `--max-line-bytes max_line_bytes`: no further than a plain line could
+/// be, or [`MAX_DECOMPRESSED_BYTES`] when lines are unlimited.
+pub fn expansion_limit(max_line_bytes: usize) -> usize {
+    match max_line_bytes {
+        0 => MAX_DECOMPRESSED_BYTES,
+        n => n,
+    }
+}
+
+/// `field` as `z:<base64>` if it is longer than `threshold` bytes and
+/// that form is shorter; otherwise `field` unchanged. A zero `threshold`
+/// never compresses, except that a field starting with `z:` is always
+/// compressed, so it can't be read as compressed itself.
+pub fn compress_field(field: &str, threshold: usize) -> Cow<'_, str> {
+    let escape = is_compressed(field);
+    if !escape && (threshold == 0 || field.len() <= threshold) {
+        return Cow::Borrowed(field);
+    }
+    let encoded = format!(
+        "{COMPRESSED_PREFIX}{}",
+        base64_encode(&crate::zstd::compress(field.as_bytes()))
+    );
+    if escape || encoded.len() < field.len() {
+        Cow::Owned(encoded)
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// The plain text of a `z:` field, at most `max_len` bytes of it; any
+/// other field is returned unchanged.
+pub fn decompress_field(field: &str, max_len: usize) -> Result<Cow<'_, str>, CodecError> {
+    let Some(encoded) = field.strip_prefix(COMPRESSED_PREFIX) else {
+        return Ok(Cow::Borrowed(field));
+    };
+    let frame = base64_decode(encoded).ok_or(CodecError::Base64)?;
+    let raw = crate::zstd::decompress(&frame, max_len)?;
+    let text = String::from_utf8(raw).map_err(|_| CodecError::NotText)?;
+    if text.contains(['\n', '\r']) {
+        return Err(CodecError::NotText);
+    }
+    Ok(Cow::Owned(text))
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (k, &b)| n | (b as u32) << (16 - 8 * k));
+        for k in 0..4 {
+            if k <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * k) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    for (q, quad) in s.chunks(4).enumerate() {
+        let last = q == s.len() / 4 - 1;
+        let pad = quad.iter().rev().take_while(|&&c| c == b'=').count();
+        if pad > 2 || (pad > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &quad[..4 - pad] {
+            let v = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            n = n << 6 | v;
+        }
+        n <<= 6 * pad as u32;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - pad]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_fields_of_every_size() {
+        let long_repetitive = "7000=alive;7001=alive;7002=alive;".repeat(400);
+        let long_varied: String = (0..6000u32).map(|i| format!("{:x}", i * 7919)).collect();
+        for field in [
+            "",
+            "hi",
+            "z:hi",
+            "x".repeat(4096).as_str(),
+            "x".repeat(4097).as_str(),
+            long_repetitive.as_str(),
+            long_varied.as_str(),
+        ] {
+            let packed = compress_field(field, DEFAULT_COMPRESS_THRESHOLD_BYTES);
+            assert_eq!(
+                decompress_field(&packed, MAX_DECOMPRESSED_BYTES).unwrap(),
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn only_long_fields_are_compressed() {
+        let short = "a".repeat(DEFAULT_COMPRESS_THRESHOLD_BYTES);
+        assert!(matches!(
+            compress_field(&short, DEFAULT_COMPRESS_THRESHOLD_BYTES),
+            Cow::Borrowed(_)
+        ));
+        let long = "a".repeat(DEFAULT_COMPRESS_THRESHOLD_BYTES + 1);
+        let packed = compress_field(&long, DEFAULT_COMPRESS_THRESHOLD_BYTES);
+        assert!(is_compressed(&packed));
+        assert!(packed.len() < 100, "{}", packed.len());
+        assert!(!packed.contains(' '));
+        assert!(matches!(compress_field(&long, 0), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn fields_that_look_compressed_are_always_compressed() {
+        for threshold in [0, DEFAULT_COMPRESS_THRESHOLD_BYTES] {
+            let packed = compress_field("z:not really", threshold);
+            assert_ne!(packed, "z:not really");
+            assert_eq!(
+                decompress_field(&packed, MAX_DECOMPRESSED_BYTES).unwrap(),
+                "z:not really"
+            );
+        }
+        assert_eq!(
+            decompress_field("plain text", MAX_DECOMPRESSED_BYTES).unwrap(),
+            "plain text"
+        );
+    }
+
+    #[test]
+    fn bad_fields_are_rejected() {
+        let decompress = |field: &str| decompress_field(field, 1000).map(Cow::into_owned);
+        assert_eq!(decompress("z:not base64!"), Err(CodecError::Base64));
+        assert_eq!(
+            decompress(&format!("z:{}", base64_encode(b"not a zstd frame"))),
+            Err(CodecError::Corrupt)
+        );
+        let frame = |text: &[u8]| format!("z:{}", base64_encode(&crate::zstd::compress(text)));
+        assert_eq!(
+            decompress(&frame(b"RING\nNODE SHUTDOWN")),
+            Err(CodecError::NotText)
+        );
+        // Up to the limit expands; one byte past it does not.
+        assert_eq!(decompress(&frame(&[b'a'; 1000])), Ok("a".repeat(1000)));
+        assert_eq!(decompress(&frame(&[b'a'; 1001])), Err(CodecError::TooLarge));
+        assert_eq!(
+            decompress(&frame(&vec![b'a'; 64 << 20])),
+            Err(CodecError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn base64_matches_the_standard_alphabet() {
+        assert_eq!(base64_encode(b"ouroboros"), "b3Vyb2Jvcm9z");
+        assert_eq!(base64_encode(b"ring"), "cmluZw==");
+        assert_eq!(base64_decode("cmluZw==").unwrap(), b"ring");
+        assert_eq!(base64_decode("b3Vyb2Jvcm9z").unwrap(), b"ouroboros");
+        assert!(base64_decode("cmluZw=").is_none());
+        assert!(base64_decode("cm==Zw==").is_none());
+    }
+}
@@ -0,0 +1,308 @@
+//! TOML config files for `run`, `dev-network` and `gateway` (`--config`).
+//!
+//! A file has one table per subcommand: `[run]` (alias `[node]`),
+//! `[network]` and `[gateway]`, so one file can describe a whole
+//! deployment. A `[ring]` table may also spell out the ring itself, one
+//! `[[ring.nodes]]` entry per node, for `lint-ring` to check. A file of bare top-level keys is also accepted and read as
+//! the table of the subcommand that loads it, which is how the older
+//! single-purpose files look. Every field is optional; CLI flags override
+//! file values, which override built-in defaults. Unknown keys are an
+//! error rather than silently ignored.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::chring::RoutingMode;
+use crate::node::FsyncMode;
+
+/// `[run]`: one node. Keys mirror `run`'s long flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    pub addr: Option<String>,
+    pub socket_path: Option<PathBuf>,
+    pub wait_time: Option<u64>,
+    pub file_size: Option<u64>,
+    pub storage_root: Option<PathBuf>,
+    pub fsync_mode: Option<FsyncMode>,
+    #[serde(alias = "auth_key")]
+    pub auth_token: Option<String>,
+    pub idle_timeout: Option<u64>,
+    pub accept_timeout_ms: Option<u64>,
+    pub max_ring_ttl: Option<u32>,
+    pub max_ring_hops: Option<u32>,
+    pub max_walk_hops: Option<u32>,
+    pub forward_max_hops: Option<u32>,
+    pub default_ring_ttl: Option<u32>,
+    #[serde(alias = "max_connections")]
+    pub max_conns: Option<u32>,
+    pub shutdown_timeout: Option<u64>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_ca: Option<PathBuf>,
+    pub name: Option<String>,
+    pub state_file: Option<PathBuf>,
+    pub ring_ack_timeout: Option<u64>,
+    pub pool_size: Option<usize>,
+    pub mux: Option<bool>,
+    pub hello: Option<bool>,
+    pub max_line_bytes: Option<usize>,
+    pub walk_payload_cmd: Option<String>,
+    pub no_dns_resolve: Option<bool>,
+    pub verify_next_reachable: Option<bool>,
+    pub no_nodelay: Option<bool>,
+    pub cb_failure_threshold: Option<u32>,
+    pub cb_reset_timeout_secs: Option<u64>,
+    pub rate_limit_rps: Option<u32>,
+    pub bind_host: Option<String>,
+    pub reuse_port: Option<bool>,
+    pub bind_backlog: Option<u32>,
+    pub recv_buf_size: Option<u32>,
+    pub send_buf_size: Option<u32>,
+    pub kv_max_key_bytes: Option<usize>,
+    pub kv_max_value_bytes: Option<usize>,
+    pub metrics_port: Option<u16>,
+    pub otlp_endpoint: Option<String>,
+    pub dedup_cache_size: Option<usize>,
+    pub dedup_ttl_secs: Option<u64>,
+    pub repair_ring: Option<bool>,
+    pub repair_coordinator: Option<String>,
+    pub compress_threshold_bytes: Option<usize>,
+    pub replay_log: Option<PathBuf>,
+    pub probe_interval_secs: Option<u64>,
+    pub walk_cache_ttl_secs: Option<u64>,
+    pub heartbeat_addr: Option<String>,
+    pub heartbeat_interval_secs: Option<u64>,
+    pub parallel_walk_timeout_ms: Option<u64>,
+    pub announce: Option<std::net::SocketAddr>,
+    pub announce_interval_secs: Option<u64>,
+    pub monitor_topology: Option<bool>,
+    pub monitor_interval_secs: Option<u64>,
+    pub split_brain_check_interval_secs: Option<u64>,
+    pub routing: Option<RoutingMode>,
+    pub script: Option<PathBuf>,
+    pub continue_on_error: Option<bool>,
+}
+
+/// `[network]`: a `dev-network` ring. Keys mirror its long flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub nodes: Option<u16>,
+    pub base_port: Option<u16>,
+    pub host: Option<String>,
+    pub no_block: Option<bool>,
+    pub wait_ms: Option<u64>,
+    pub wait_time: Option<u64>,
+    pub overwrite_nodes_dir: Option<bool>,
+    pub dns_port: Option<u16>,
+    pub file_size: Option<u64>,
+    #[serde(alias = "auth_key")]
+    pub auth_token: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_ca: Option<PathBuf>,
+    pub graceful: Option<bool>,
+    pub hello: Option<bool>,
+    pub repair_port: Option<u16>,
+    pub ring_name: Option<String>,
+    pub membership_file: Option<PathBuf>,
+    pub supervise: Option<bool>,
+    pub max_restarts: Option<u32>,
+    pub hot_reload: Option<bool>,
+}
+
+/// `[gateway]`: a standalone gateway.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GatewayConfig {
+    pub listen: Option<String>,
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(alias = "auth_key")]
+    pub auth_token: Option<String>,
+    pub tls_ca: Option<PathBuf>,
+    pub hello: Option<bool>,
+}
+
+/// `[ring]`: the nodes of a ring and where each one points.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RingConfig {
+    #[serde(default)]
+    pub nodes: Vec<RingNodeConfig>,
+}
+
+/// One `[[ring.nodes]]` entry. `addr` and `next` keep their place in the
+/// file so problems can be reported by line.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RingNodeConfig {
+    pub addr: toml::Spanned<String>,
+    pub next: toml::Spanned<String>,
+    pub name: Option<String>,
+}
+
+/// Which table a file of bare top-level keys stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Run,
+    Network,
+    Gateway,
+}
+
+impl Section {
+    fn table(self) -> &'static str {
+        match self {
+            Section::Run => "run",
+            Section::Network => "network",
+            Section::Gateway => "gateway",
+        }
+    }
+}
+
+const SECTION_TABLES: [&str; 5] = ["run", "node", "network", "gateway", "ring"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, alias = "node")]
+    pub run: RunConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub gateway: GatewayConfig,
+    #[serde(default)]
+    pub ring: RingConfig,
+}
+
+impl Config {
+    /// Read and validate the file at `path`; see [`Config::parse`].
+    pub fn load(path: &Path, flat: Section) -> Result<Self, String> {
+        let raw =
+            fs::read_to_string(path).map_err(|e| format!("read config {}: {e}", path.display()))?;
+        Self::parse(&raw, flat).map_err(|e| format!("config {}: {e}", path.display()))
+    }
+
+    /// Parse and validate a config. Without any subcommand table the
+    /// top-level keys are read as the `flat` table.
+    pub fn parse(raw: &str, flat: Section) -> Result<Self, String> {
+        let table: toml::Table = toml::from_str(raw).map_err(|e| e.to_string())?;
+        let sectioned = table
+            .iter()
+            .any(|(k, v)| SECTION_TABLES.contains(&k.as_str()) && v.is_table());
+        // A sectioned file is read from the text itself: going through
+        // `toml::Value` would drop the spans `[ring]` keeps.
+        let cfg: Config = if sectioned {
+            toml::from_str(raw)
+        } else {
+            let table = toml::Table::from_iter([(flat.table().to_string(), table.into())]);
+            toml::Value::Table(table).try_into()
+        }
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    /// Reject combinations no flag set could produce: a TLS certificate
+    /// without its key (or the reverse), both `addr` and `socket_path`,
+    /// and a `[network]` ring that is empty or runs past port 65535.
+    pub fn validate(&self) -> Result<(), String> {
+        check_tls_pair("run", &self.run.tls_cert, &self.run.tls_key)?;
+        if self.run.addr.is_some() && self.run.socket_path.is_some() {
+            return Err("[run]: set either `addr` or `socket_path`, not both".into());
+        }
+        let net = &self.network;
+        check_tls_pair("network", &net.tls_cert, &net.tls_key)?;
+        if net.nodes == Some(0) {
+            return Err("[network]: `nodes` must be at least 1".into());
+        }
+        let last_port = u32::from(net.base_port.unwrap_or(0)) + u32::from(net.nodes.unwrap_or(1));
+        if last_port - 1 > u32::from(u16::MAX) {
+            return Err(format!(
+                "[network]: `base_port` + `nodes` runs past port {}",
+                u16::MAX
+            ));
+        }
+        if self.gateway.nodes.iter().any(|n| n.trim().is_empty()) {
+            return Err("[gateway]: `nodes` has an empty address".into());
+        }
+        Ok(())
+    }
+}
+
+fn check_tls_pair(
+    section: &str,
+    cert: &Option<PathBuf>,
+    key: &Option<PathBuf>,
+) -> Result<(), String> {
+    match (cert, key) {
+        (Some(_), None) => Err(format!(
+            "[{section}]: `tls_cert` is set but `tls_key` is missing"
+        )),
+        (None, Some(_)) => Err(format!(
+            "[{section}]: `tls_key` is set but `tls_cert` is missing"
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_keys_belong_to_the_loading_subcommand() {
+        let raw = "addr = \"127.0.0.1:7000\"\nfsync_mode = \"data\"\n";
+        let cfg = Config::parse(raw, Section::Run).unwrap();
+        assert_eq!(cfg.run.addr.as_deref(), Some("127.0.0.1:7000"));
+        assert_eq!(cfg.run.fsync_mode, Some(FsyncMode::Data));
+
+        let cfg = Config::parse("nodes = 5\nbase_port = 9000\n", Section::Network).unwrap();
+        assert_eq!(cfg.network.nodes, Some(5));
+        assert_eq!(cfg.network.base_port, Some(9000));
+    }
+
+    #[test]
+    fn one_file_holds_every_section() {
+        let raw = r#"
+[node]
+addr = "127.0.0.1:7000"
+name = "node-00"
+auth_key = "abc"
+
+[network]
+nodes = 5
+base_port = 7000
+
+[gateway]
+listen = "127.0.0.1:8000"
+nodes = ["127.0.0.1:7000"]
+"#;
+        let cfg = Config::parse(raw, Section::Gateway).unwrap();
+        assert_eq!(cfg.run.name.as_deref(), Some("node-00"));
+        assert_eq!(cfg.run.auth_token.as_deref(), Some("abc"));
+        assert_eq!(cfg.network.nodes, Some(5));
+        assert_eq!(cfg.gateway.nodes, vec!["127.0.0.1:7000".to_string()]);
+    }
+
+    #[test]
+    fn unknown_and_stray_keys_are_errors() {
+        let err = Config::parse("adr = \"x\"\n", Section::Run).unwrap_err();
+        assert!(err.contains("adr"), "{err}");
+        let err = Config::parse("addr = \"x\"\n[network]\nnodes = 3\n", Section::Run).unwrap_err();
+        assert!(err.contains("addr"), "{err}");
+    }
+
+    #[test]
+    fn validation_names_the_missing_field() {
+        let err = Config::parse("tls_cert = \"c.pem\"\n", Section::Run).unwrap_err();
+        assert_eq!(err, "[run]: `tls_cert` is set but `tls_key` is missing");
+        let err = Config::parse("[network]\nnodes = 0\n", Section::Network).unwrap_err();
+        assert_eq!(err, "[network]: `nodes` must be at least 1");
+        let err = Config::parse("base_port = 65535\nnodes = 2\n", Section::Network).unwrap_err();
+        assert!(err.contains("65535"), "{err}");
+    }
+}
@@ -0,0 +1,143 @@
+//! Recently-seen `RING FORWARD` lines.
+//!
+//! A ring wired with two edges into the same node hands that node every
+//! message twice, and each copy is forwarded again, so a single message can
+//! multiply long before its TTL runs out. Each node remembers the SHA-256 of
+//! the lines it has forwarded; a line it has already seen within the TTL is
+//! acknowledged and dropped. Only the hash is kept, never the message.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+/// `--dedup-cache-size` when the flag is not given.
+pub const DEFAULT_DEDUP_CACHE_SIZE: usize = 1000;
+
+/// Fallback for [`crate::NodeOpts::dedup_ttl`].
+pub const DEFAULT_DEDUP_TTL: Duration = Duration::from_secs(60);
+
+type Hash = [u8; 32];
+
+/// The last `capacity` line hashes, each forgotten `ttl` after it was
+/// first seen. When full, the oldest entry makes room.
+#[derive(Debug)]
+pub struct DedupCache {
+    capacity: usize,
+    ttl: Duration,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    seen: HashMap<Hash, Instant>,
+    // Oldest first. May hold stale pairs for hashes seen again after
+    // expiring; those are skipped on eviction.
+    order: VecDeque<(Hash, Instant)>,
+}
+
+impl DedupCache {
+    /// A zero `capacity` remembers nothing, so nothing is a duplicate.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Record `line`; `true` if it was already seen within the TTL.
+    pub fn check(&self, line: &str) -> bool {
+        self.check_at(line, Instant::now())
+    }
+
+    fn check_at(&self, line: &str, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let hash: Hash = Sha256::digest(line.as_bytes()).into();
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        entries.evict(now, self.ttl, self.capacity);
+        if entries.seen.contains_key(&hash) {
+            return true;
+        }
+        if entries.seen.len() == self.capacity {
+            entries.evict(now, self.ttl, self.capacity - 1);
+        }
+        entries.seen.insert(hash, now);
+        entries.order.push_back((hash, now));
+        false
+    }
+
+    /// Hashes currently remembered.
+    pub fn len(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .seen
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Entries {
+    /// Drop expired entries, then the oldest until at most `keep` remain.
+    fn evict(&mut self, now: Instant, ttl: Duration, keep: usize) {
+        while let Some(&(hash, at)) = self.order.front() {
+            let expired = now.saturating_duration_since(at) >= ttl;
+            if !expired && self.seen.len() <= keep {
+                break;
+            }
+            self.order.pop_front();
+            if self.seen.get(&hash) == Some(&at) {
+                self.seen.remove(&hash);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_within_ttl_are_duplicates() {
+        let cache = DedupCache::new(10, Duration::from_secs(60));
+        let t0 = Instant::now();
+        assert!(!cache.check_at("RING FORWARD 2 hi", t0));
+        assert!(cache.check_at("RING FORWARD 2 hi", t0 + Duration::from_secs(59)));
+        assert!(!cache.check_at("RING FORWARD 1 hi", t0));
+        // Expired: seen afresh, and remembered again from then.
+        assert!(!cache.check_at("RING FORWARD 2 hi", t0 + Duration::from_secs(60)));
+        assert!(cache.check_at("RING FORWARD 2 hi", t0 + Duration::from_secs(61)));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn full_cache_forgets_the_oldest() {
+        let cache = DedupCache::new(2, Duration::from_secs(60));
+        let t0 = Instant::now();
+        for line in ["a", "b", "c"] {
+            assert!(!cache.check_at(line, t0));
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.check_at("c", t0));
+        assert!(!cache.check_at("a", t0));
+    }
+
+    #[test]
+    fn zero_capacity_disables() {
+        let cache = DedupCache::new(0, Duration::from_secs(60));
+        assert!(!cache.check("x"));
+        assert!(!cache.check("x"));
+        assert!(cache.is_empty());
+    }
+}
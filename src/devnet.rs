@@ -0,0 +1,808 @@
+//! The steps `dev-network` takes to start and wire a local ring.
+//!
+//! [`plan_network`] turns the subcommand's settings into a list of
+//! [`NetworkAction`]s without touching the system; the binary either
+//! carries them out or, with `--dry-run`, prints them. The plan is a pure
+//! function of its input, so the same flags always print the same plan.
+//!
+//! With `--membership-file`, the wired ring is also recorded as a list of
+//! [`Member`]s, and [`plan_resume`] works out which of the surviving nodes
+//! `resume-network` has to wire again. With `--supervise`,
+//! [`plan_restart`] gives the steps that bring back a node that exited.
+//! [`plan_leave`] gives the steps `leave` takes to remove a node from a
+//! running ring.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::node::join_host_port;
+
+/// Where nodes keep their storage, relative to the working directory.
+pub const NODES_DIR: &str = "nodes";
+
+/// What a `dev-network` ring looks like.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkSpec {
+    pub nodes: u16,
+    /// Node `i` listens on `base_port + i`; 0 lets the OS pick every port.
+    pub base_port: u16,
+    /// Interface the nodes bind and are wired on.
+    pub host: String,
+    /// Pause after spawning, before waiting for the ports.
+    pub extra_wait: Duration,
+    /// Each node's `--wait-time` (ms between health checks).
+    pub wait_time: u64,
+    /// Empty the nodes directory first.
+    pub overwrite_nodes_dir: bool,
+    /// Also run a gateway on this port.
+    pub dns_port: Option<u16>,
+    /// Also run a repair coordinator on this port and start every node
+    /// with `--repair-ring` pointed at it.
+    pub repair_port: Option<u16>,
+    /// Each node's `--file-size`.
+    pub max_file_size: u64,
+    /// `--tls-*` flags handed to every node.
+    pub tls_args: Vec<String>,
+    /// Pass `--hello` to every node.
+    pub hello: bool,
+    /// Wire the nodes on this ring (`NODE NEXT-RING`) instead of the
+    /// default one. PREV, the netmap and the topology walk all follow the
+    /// default ring, so those steps are left out.
+    pub ring_name: Option<String>,
+    /// Record the wired ring here (see [`Member`]).
+    pub membership_file: Option<PathBuf>,
+    /// Restart a node that exits while the ring is up (see
+    /// [`plan_restart`]).
+    pub supervise: bool,
+    /// Restarts each node gets under `supervise` before it is left down.
+    pub max_restarts: u32,
+}
+
+impl NetworkSpec {
+    /// The `--port` node `i` is started with.
+    pub fn port(&self, i: u16) -> u16 {
+        if self.base_port == 0 {
+            0
+        } else {
+            self.base_port + i
+        }
+    }
+
+    /// Address of node `i`. With `base_port` 0 the port is not known until
+    /// the node reports it, so this is a `<node-NN>` placeholder for
+    /// [`NetworkAction::map_addrs`] to swap out.
+    pub fn addr(&self, i: u16) -> String {
+        if self.base_port == 0 {
+            join_host_port(&self.host, format!("<node-{i:02}>"))
+        } else {
+            join_host_port(&self.host, self.base_port + i)
+        }
+    }
+
+    /// Every node's address, in ring order.
+    pub fn addrs(&self) -> Vec<String> {
+        (0..self.nodes).map(|i| self.addr(i)).collect()
+    }
+}
+
+/// One step of bringing a ring up.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum NetworkAction {
+    /// Create the storage directory, emptying it first if `fresh`.
+    PrepareDir {
+        path: PathBuf,
+        fresh: bool,
+    },
+    /// Start `ouroboros_fs` with `args` as a child process.
+    Spawn {
+        name: String,
+        addr: String,
+        args: Vec<String>,
+    },
+    Sleep {
+        ms: u64,
+    },
+    /// Wait until `addr` accepts connections.
+    WaitListening {
+        addr: String,
+    },
+    /// `NODE NEXT <next>` to `node`, or `NODE NEXT-RING <ring> <next>`.
+    SetNext {
+        node: String,
+        next: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ring: Option<String>,
+    },
+    /// `NODE PREV <prev>` to `node`.
+    SetPrev {
+        node: String,
+        prev: String,
+    },
+    /// Write every spawned node's address, PID and NEXT to `path`.
+    WriteMembership {
+        path: PathBuf,
+    },
+    /// Run a gateway in front of `nodes`.
+    StartGateway {
+        listen: String,
+        nodes: Vec<String>,
+    },
+    /// Run a repair coordinator for the ring `nodes`.
+    StartRepairCoordinator {
+        listen: String,
+        nodes: Vec<String>,
+    },
+    /// `NETMAP DISCOVER` from `node`.
+    NetmapDiscover {
+        node: String,
+    },
+    /// `TOPOLOGY WALK` from `node`.
+    TopologyWalk {
+        node: String,
+    },
+    /// `NODE SHUTDOWN` to `node`.
+    Shutdown {
+        node: String,
+    },
+}
+
+impl NetworkAction {
+    /// This action with every node address passed through `f`; used to
+    /// swap placeholder addresses for the ports nodes actually got.
+    pub fn map_addrs(self, f: impl Fn(&str) -> String) -> Self {
+        let all = |nodes: Vec<String>| nodes.iter().map(|n| f(n)).collect();
+        match self {
+            NetworkAction::Spawn { name, addr, args } => NetworkAction::Spawn {
+                name,
+                addr: f(&addr),
+                args,
+            },
+            NetworkAction::WaitListening { addr } => {
+                NetworkAction::WaitListening { addr: f(&addr) }
+            }
+            NetworkAction::SetNext { node, next, ring } => NetworkAction::SetNext {
+                node: f(&node),
+                next: f(&next),
+                ring,
+            },
+            NetworkAction::SetPrev { node, prev } => NetworkAction::SetPrev {
+                node: f(&node),
+                prev: f(&prev),
+            },
+            NetworkAction::StartGateway { listen, nodes } => NetworkAction::StartGateway {
+                listen,
+                nodes: all(nodes),
+            },
+            NetworkAction::StartRepairCoordinator { listen, nodes } => {
+                NetworkAction::StartRepairCoordinator {
+                    listen,
+                    nodes: all(nodes),
+                }
+            }
+            NetworkAction::NetmapDiscover { node } => {
+                NetworkAction::NetmapDiscover { node: f(&node) }
+            }
+            NetworkAction::TopologyWalk { node } => NetworkAction::TopologyWalk { node: f(&node) },
+            NetworkAction::Shutdown { node } => NetworkAction::Shutdown { node: f(&node) },
+            other => other,
+        }
+    }
+}
+
+/// The port in a node's `LISTENING <port>` stdout line (see
+/// [`crate::server::LISTENING_PREFIX`]).
+pub fn parse_listening(line: &str) -> Option<u16> {
+    line.trim_end()
+        .strip_prefix(crate::server::LISTENING_PREFIX)?
+        .parse()
+        .ok()
+}
+
+impl fmt::Display for NetworkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkAction::PrepareDir { path, fresh } => {
+                write!(f, "prepare-dir {} fresh={fresh}", path.display())
+            }
+            NetworkAction::Spawn { name, addr, args } => {
+                write!(f, "spawn {name} {addr}: ouroboros_fs {}", args.join(" "))
+            }
+            NetworkAction::Sleep { ms } => write!(f, "sleep {ms}ms"),
+            NetworkAction::WaitListening { addr } => write!(f, "wait-listening {addr}"),
+            NetworkAction::SetNext { node, next, ring } => {
+                write!(f, "set-next {node} -> {next}")?;
+                match ring {
+                    Some(ring) => write!(f, " ring={ring}"),
+                    None => Ok(()),
+                }
+            }
+            NetworkAction::SetPrev { node, prev } => write!(f, "set-prev {node} -> {prev}"),
+            NetworkAction::WriteMembership { path } => {
+                write!(f, "write-membership {}", path.display())
+            }
+            NetworkAction::StartGateway { listen, nodes } => {
+                write!(f, "start-gateway {listen} nodes={}", nodes.join(","))
+            }
+            NetworkAction::StartRepairCoordinator { listen, nodes } => {
+                write!(
+                    f,
+                    "start-repair-coordinator {listen} nodes={}",
+                    nodes.join(",")
+                )
+            }
+            NetworkAction::NetmapDiscover { node } => write!(f, "netmap-discover {node}"),
+            NetworkAction::TopologyWalk { node } => write!(f, "topology-walk {node}"),
+            NetworkAction::Shutdown { node } => write!(f, "shutdown {node}"),
+        }
+    }
+}
+
+/// The actions that start `spec`'s ring, in order: spawn every node, wait
+/// for each to listen, wire `NEXT`/`PREV` around the ring, start the
+/// membership file, gateway and repair coordinator if asked, then seed the netmap and
+/// topology from node 0. A named ring only gets its `NEXT` pointers.
+pub fn plan_network(spec: &NetworkSpec) -> Vec<NetworkAction> {
+    let mut plan = vec![NetworkAction::PrepareDir {
+        path: PathBuf::from(NODES_DIR),
+        fresh: spec.overwrite_nodes_dir,
+    }];
+    let addrs = spec.addrs();
+    let repair_addr = spec
+        .repair_port
+        .map(|port| join_host_port(&spec.host, port));
+
+    for (i, addr) in addrs.iter().enumerate() {
+        let port = spec.port(i as u16);
+        let mut args: Vec<String> = [
+            "run",
+            "--bind-host",
+            &spec.host,
+            "--port",
+            &port.to_string(),
+            "--wait-time",
+            &spec.wait_time.to_string(),
+            "--file-size",
+            &spec.max_file_size.to_string(),
+            "--name",
+            &format!("node-{i:02}"),
+        ]
+        .map(String::from)
+        .into();
+        args.extend(spec.tls_args.iter().cloned());
+        if spec.hello {
+            args.push("--hello".into());
+        }
+        if let Some(coordinator) = &repair_addr {
+            args.extend(["--repair-ring".into(), "--repair-coordinator".into()]);
+            args.push(coordinator.clone());
+        }
+        plan.push(NetworkAction::Spawn {
+            name: format!("node-{i:02}"),
+            addr: addr.clone(),
+            args,
+        });
+    }
+
+    if !spec.extra_wait.is_zero() {
+        plan.push(NetworkAction::Sleep {
+            ms: spec.extra_wait.as_millis() as u64,
+        });
+    }
+    for addr in &addrs {
+        plan.push(NetworkAction::WaitListening { addr: addr.clone() });
+    }
+
+    push_ring_wiring(&mut plan, &addrs, spec.ring_name.as_deref());
+
+    if let Some(path) = &spec.membership_file {
+        plan.push(NetworkAction::WriteMembership { path: path.clone() });
+    }
+    if let Some(port) = spec.dns_port {
+        plan.push(NetworkAction::StartGateway {
+            listen: join_host_port(&spec.host, port),
+            nodes: addrs.clone(),
+        });
+    }
+    if let Some(listen) = repair_addr {
+        plan.push(NetworkAction::StartRepairCoordinator {
+            listen,
+            nodes: addrs.clone(),
+        });
+    }
+    if let Some(start) = addrs.first().filter(|_| spec.ring_name.is_none()) {
+        plan.push(NetworkAction::NetmapDiscover {
+            node: start.clone(),
+        });
+        plan.push(NetworkAction::TopologyWalk {
+            node: start.clone(),
+        });
+    }
+    plan
+}
+
+/// One node of a ring started by `dev-network`, as written to
+/// `--membership-file`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    pub addr: String,
+    pub pid: u32,
+    /// The NEXT the node was wired with.
+    pub next: String,
+    /// Set when the ring was started with `--ring-name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ring: Option<String>,
+}
+
+/// The members of the ring `plan` wires, given the PID of each spawned
+/// node in spawn order.
+pub fn members(plan: &[NetworkAction], pids: &[u32]) -> Vec<Member> {
+    let spawned = plan.iter().filter_map(|action| match action {
+        NetworkAction::Spawn { addr, .. } => Some(addr),
+        _ => None,
+    });
+    spawned
+        .zip(pids)
+        .filter_map(|(addr, &pid)| {
+            plan.iter().find_map(|action| match action {
+                NetworkAction::SetNext { node, next, ring } if node == addr => Some(Member {
+                    addr: addr.clone(),
+                    pid,
+                    next: next.clone(),
+                    ring: ring.clone(),
+                }),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// Point each of `addrs` at the one after it, the last back at the first.
+/// On the default ring (`ring` is `None`) each also gets its PREV.
+fn push_ring_wiring(plan: &mut Vec<NetworkAction>, addrs: &[String], ring: Option<&str>) {
+    for (i, addr) in addrs.iter().enumerate() {
+        let next = &addrs[(i + 1) % addrs.len()];
+        plan.push(NetworkAction::SetNext {
+            node: addr.clone(),
+            next: next.clone(),
+            ring: ring.map(str::to_string),
+        });
+        if ring.is_none() {
+            plan.push(NetworkAction::SetPrev {
+                node: next.clone(),
+                prev: addr.clone(),
+            });
+        }
+    }
+}
+
+/// The actions that wire already running nodes found by
+/// `dev-network --auto-discover` into one ring, in the order given.
+pub fn plan_discovered(addrs: &[String]) -> Vec<NetworkAction> {
+    let mut plan = Vec::new();
+    push_ring_wiring(&mut plan, addrs, None);
+    plan
+}
+
+/// The `SetNext` actions that re-wire `members` after some of them died.
+/// `alive[i]` says whether member `i` is still up and `current_next[i]` is
+/// the NEXT it reports now. Only live members whose NEXT is unset or is a
+/// dead member are wired: each is pointed at the first live member from
+/// its recorded NEXT onwards, so dead nodes are stepped over.
+pub fn plan_resume(
+    members: &[Member],
+    alive: &[bool],
+    current_next: &[Option<String>],
+) -> Vec<NetworkAction> {
+    let mut plan = Vec::new();
+    for (i, member) in members.iter().enumerate() {
+        let lost = match &current_next[i] {
+            None => true,
+            Some(next) => members
+                .iter()
+                .zip(alive)
+                .any(|(m, &up)| !up && &m.addr == next),
+        };
+        if !alive[i] || !lost {
+            continue;
+        }
+        let next = match members.iter().position(|m| m.addr == member.next) {
+            Some(start) => (0..members.len())
+                .map(|step| (start + step) % members.len())
+                .find(|&j| alive[j])
+                .map_or(&member.addr, |j| &members[j].addr),
+            // Not one of ours; trust the record.
+            None => &member.next,
+        };
+        plan.push(NetworkAction::SetNext {
+            node: member.addr.clone(),
+            next: next.clone(),
+            ring: member.ring.clone(),
+        });
+    }
+    plan
+}
+
+/// The actions that bring back the node at `addr` after it exited, taken
+/// from the `executed` plan with real addresses: spawn it again on the
+/// port it had, wait for it to listen, then repeat every `SetNext` and
+/// `SetPrev` that involves it, so both it and its predecessor are wired
+/// again. Empty when `addr` was never spawned.
+pub fn plan_restart(executed: &[NetworkAction], addr: &str) -> Vec<NetworkAction> {
+    let Some(spawn) = executed.iter().find_map(|action| match action {
+        NetworkAction::Spawn {
+            name,
+            addr: a,
+            args,
+        } if a == addr => {
+            let mut args = args.clone();
+            // A `--port 0` node has to come back on the port it got.
+            if let (Some(i), Some((_, port))) = (
+                args.iter().position(|a| a == "--port"),
+                addr.rsplit_once(':'),
+            ) && i + 1 < args.len()
+            {
+                args[i + 1] = port.to_string();
+            }
+            Some(NetworkAction::Spawn {
+                name: name.clone(),
+                addr: addr.to_string(),
+                args,
+            })
+        }
+        _ => None,
+    }) else {
+        return Vec::new();
+    };
+    let mut plan = vec![
+        spawn,
+        NetworkAction::WaitListening {
+            addr: addr.to_string(),
+        },
+    ];
+    plan.extend(
+        executed
+            .iter()
+            .filter(|action| match action {
+                NetworkAction::SetNext { node, next, .. } => node == addr || next == addr,
+                NetworkAction::SetPrev { node, prev } => node == addr || prev == addr,
+                _ => false,
+            })
+            .cloned(),
+    );
+    plan
+}
+
+/// The actions that take `leaving` out of the ring between `prev` and
+/// `next`: point `prev` past it at `next`, make `prev` the PREV of `next`,
+/// then shut `leaving` down. Empty when `leaving` is the ring's only node.
+pub fn plan_leave(leaving: &str, prev: &str, next: &str) -> Vec<NetworkAction> {
+    if next == leaving || prev == leaving {
+        return Vec::new();
+    }
+    vec![
+        NetworkAction::SetNext {
+            node: prev.to_string(),
+            next: next.to_string(),
+            ring: None,
+        },
+        NetworkAction::SetPrev {
+            node: next.to_string(),
+            prev: prev.to_string(),
+        },
+        NetworkAction::Shutdown {
+            node: leaving.to_string(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> NetworkSpec {
+        NetworkSpec {
+            nodes: 2,
+            base_port: 7000,
+            host: "127.0.0.1".into(),
+            extra_wait: Duration::from_millis(200),
+            wait_time: 5000,
+            max_file_size: 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn discovered_nodes_are_wired_in_order() {
+        let addrs: Vec<String> = ["10.0.0.1:7000", "10.0.0.2:7000", "10.0.0.3:7000"]
+            .map(String::from)
+            .into();
+        let lines: Vec<String> = plan_discovered(&addrs)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "set-next 10.0.0.1:7000 -> 10.0.0.2:7000",
+                "set-prev 10.0.0.2:7000 -> 10.0.0.1:7000",
+                "set-next 10.0.0.2:7000 -> 10.0.0.3:7000",
+                "set-prev 10.0.0.3:7000 -> 10.0.0.2:7000",
+                "set-next 10.0.0.3:7000 -> 10.0.0.1:7000",
+                "set-prev 10.0.0.1:7000 -> 10.0.0.3:7000",
+            ]
+        );
+        assert!(plan_discovered(&[]).is_empty());
+    }
+
+    #[test]
+    fn plan_spawns_waits_then_wires_in_a_ring() {
+        let lines: Vec<String> = plan_network(&spec())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "prepare-dir nodes fresh=false",
+                "spawn node-00 127.0.0.1:7000: ouroboros_fs run --bind-host 127.0.0.1 \
+                 --port 7000 --wait-time 5000 --file-size 1024 --name node-00",
+                "spawn node-01 127.0.0.1:7001: ouroboros_fs run --bind-host 127.0.0.1 \
+                 --port 7001 --wait-time 5000 --file-size 1024 --name node-01",
+                "sleep 200ms",
+                "wait-listening 127.0.0.1:7000",
+                "wait-listening 127.0.0.1:7001",
+                "set-next 127.0.0.1:7000 -> 127.0.0.1:7001",
+                "set-prev 127.0.0.1:7001 -> 127.0.0.1:7000",
+                "set-next 127.0.0.1:7001 -> 127.0.0.1:7000",
+                "set-prev 127.0.0.1:7000 -> 127.0.0.1:7001",
+                "netmap-discover 127.0.0.1:7000",
+                "topology-walk 127.0.0.1:7000",
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_steps_follow_the_spec() {
+        let plan = plan_network(&NetworkSpec {
+            nodes: 1,
+            host: "::1".into(),
+            extra_wait: Duration::ZERO,
+            dns_port: Some(8000),
+            repair_port: Some(8100),
+            hello: true,
+            tls_args: vec!["--tls-ca".into(), "ca.pem".into()],
+            ..spec()
+        });
+        assert!(
+            !plan
+                .iter()
+                .any(|a| matches!(a, NetworkAction::Sleep { .. }))
+        );
+        let NetworkAction::Spawn { addr, args, .. } = &plan[1] else {
+            panic!("{plan:?}");
+        };
+        assert_eq!(addr, "[::1]:7000");
+        let tail: Vec<String> = [
+            "--tls-ca",
+            "ca.pem",
+            "--hello",
+            "--repair-ring",
+            "--repair-coordinator",
+            "[::1]:8100",
+        ]
+        .map(String::from)
+        .into();
+        assert!(args.ends_with(&tail), "{args:?}");
+        // A one-node ring points at itself.
+        assert!(plan.contains(&NetworkAction::SetNext {
+            node: "[::1]:7000".into(),
+            next: "[::1]:7000".into(),
+            ring: None,
+        }));
+        assert!(plan.contains(&NetworkAction::StartGateway {
+            listen: "[::1]:8000".into(),
+            nodes: vec!["[::1]:7000".into()],
+        }));
+        assert!(plan.contains(&NetworkAction::StartRepairCoordinator {
+            listen: "[::1]:8100".into(),
+            nodes: vec!["[::1]:7000".into()],
+        }));
+    }
+
+    #[test]
+    fn named_ring_only_wires_next() {
+        let lines: Vec<String> = plan_network(&NetworkSpec {
+            ring_name: Some("backup".into()),
+            ..spec()
+        })
+        .iter()
+        .skip(6)
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "set-next 127.0.0.1:7000 -> 127.0.0.1:7001 ring=backup",
+                "set-next 127.0.0.1:7001 -> 127.0.0.1:7000 ring=backup",
+            ]
+        );
+    }
+
+    #[test]
+    fn base_port_zero_plans_placeholders_until_nodes_report() {
+        let plan = plan_network(&NetworkSpec {
+            base_port: 0,
+            ..spec()
+        });
+        let NetworkAction::Spawn { addr, args, .. } = &plan[2] else {
+            panic!("{plan:?}");
+        };
+        assert_eq!(addr, "127.0.0.1:<node-01>");
+        assert!(args.windows(2).any(|w| w == ["--port", "0"]), "{args:?}");
+
+        let resolve = |a: &str| match a {
+            "127.0.0.1:<node-00>" => "127.0.0.1:40000".to_string(),
+            "127.0.0.1:<node-01>" => "127.0.0.1:40001".to_string(),
+            other => other.to_string(),
+        };
+        let lines: Vec<String> = plan
+            .into_iter()
+            .skip(6)
+            .map(|a| a.map_addrs(resolve).to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "set-next 127.0.0.1:40000 -> 127.0.0.1:40001",
+                "set-prev 127.0.0.1:40001 -> 127.0.0.1:40000",
+                "set-next 127.0.0.1:40001 -> 127.0.0.1:40000",
+                "set-prev 127.0.0.1:40000 -> 127.0.0.1:40001",
+                "netmap-discover 127.0.0.1:40000",
+                "topology-walk 127.0.0.1:40000",
+            ]
+        );
+    }
+
+    #[test]
+    fn listening_line_carries_the_port() {
+        assert_eq!(parse_listening("LISTENING 40123\n"), Some(40123));
+        assert_eq!(parse_listening("LISTENING"), None);
+        assert_eq!(parse_listening("listening on 7000"), None);
+    }
+
+    #[test]
+    fn membership_records_each_spawned_node() {
+        let plan = plan_network(&NetworkSpec {
+            membership_file: Some("ring.json".into()),
+            ..spec()
+        });
+        assert_eq!(
+            plan[10],
+            NetworkAction::WriteMembership {
+                path: "ring.json".into()
+            }
+        );
+        let members = members(&plan, &[41, 42]);
+        assert_eq!(
+            serde_json::to_string(&members).unwrap(),
+            r#"[{"addr":"127.0.0.1:7000","pid":41,"next":"127.0.0.1:7001"},"#.to_owned()
+                + r#"{"addr":"127.0.0.1:7001","pid":42,"next":"127.0.0.1:7000"}]"#
+        );
+    }
+
+    #[test]
+    fn resume_wires_live_nodes_around_dead_ones() {
+        let addrs: Vec<String> = (0..4).map(|i| format!("127.0.0.1:700{i}")).collect();
+        let members: Vec<Member> = (0..4)
+            .map(|i| Member {
+                addr: addrs[i].clone(),
+                pid: 100 + i as u32,
+                next: addrs[(i + 1) % 4].clone(),
+                ring: None,
+            })
+            .collect();
+        // Node 1 died under node 0; node 3 lost its NEXT; node 2 is fine.
+        let alive = [true, false, true, true];
+        let current = [Some(addrs[1].clone()), None, Some(addrs[3].clone()), None];
+        assert_eq!(
+            plan_resume(&members, &alive, &current),
+            vec![
+                NetworkAction::SetNext {
+                    node: addrs[0].clone(),
+                    next: addrs[2].clone(),
+                    ring: None,
+                },
+                NetworkAction::SetNext {
+                    node: addrs[3].clone(),
+                    next: addrs[0].clone(),
+                    ring: None,
+                },
+            ]
+        );
+        // The last node standing points at itself.
+        let alive = [true, false, false, false];
+        assert_eq!(
+            plan_resume(&members, &alive, &[None, None, None, None]),
+            vec![NetworkAction::SetNext {
+                node: addrs[0].clone(),
+                next: addrs[0].clone(),
+                ring: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn json_names_each_action() {
+        let json = serde_json::to_value(plan_network(&spec())).unwrap();
+        assert_eq!(json[0]["action"], "prepare_dir");
+        assert_eq!(json[6]["action"], "set_next");
+        assert_eq!(json[6]["node"], "127.0.0.1:7000");
+        assert_eq!(json[6]["next"], "127.0.0.1:7001");
+    }
+
+    #[test]
+    fn restart_respawns_on_the_same_port_and_rewires_both_sides() {
+        let spec = NetworkSpec {
+            nodes: 3,
+            base_port: 0,
+            ..spec()
+        };
+        let ports = ["41000", "41001", "41002"];
+        let executed: Vec<NetworkAction> = plan_network(&spec)
+            .into_iter()
+            .map(|a| {
+                a.map_addrs(|addr| {
+                    (0..3)
+                        .find(|&i| spec.addr(i) == addr)
+                        .map_or(addr.to_string(), |i| {
+                            format!("127.0.0.1:{}", ports[i as usize])
+                        })
+                })
+            })
+            .collect();
+
+        let lines: Vec<String> = plan_restart(&executed, "127.0.0.1:41001")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "spawn node-01 127.0.0.1:41001: ouroboros_fs run --bind-host 127.0.0.1 \
+                 --port 41001 --wait-time 5000 --file-size 1024 --name node-01",
+                "wait-listening 127.0.0.1:41001",
+                "set-next 127.0.0.1:41000 -> 127.0.0.1:41001",
+                "set-prev 127.0.0.1:41001 -> 127.0.0.1:41000",
+                "set-next 127.0.0.1:41001 -> 127.0.0.1:41002",
+                "set-prev 127.0.0.1:41002 -> 127.0.0.1:41001",
+            ]
+        );
+        assert!(plan_restart(&executed, "127.0.0.1:9").is_empty());
+    }
+
+    #[test]
+    fn leave_stitches_prev_to_next_before_shutting_down() {
+        let lines: Vec<String> = plan_leave("127.0.0.1:7001", "127.0.0.1:7000", "127.0.0.1:7002")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "set-next 127.0.0.1:7000 -> 127.0.0.1:7002",
+                "set-prev 127.0.0.1:7002 -> 127.0.0.1:7000",
+                "shutdown 127.0.0.1:7001",
+            ]
+        );
+        // A two-node ring closes on the survivor.
+        assert_eq!(
+            plan_leave("127.0.0.1:7001", "127.0.0.1:7000", "127.0.0.1:7000")[0].to_string(),
+            "set-next 127.0.0.1:7000 -> 127.0.0.1:7000"
+        );
+        assert!(plan_leave("127.0.0.1:7000", "127.0.0.1:7000", "127.0.0.1:7000").is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! One-shot ring health check for the `diagnose` subcommand.
+//!
+//! [`diagnose`] walks the ring from a seed node, then asks every node the
+//! walk reached for `NODE INFO`. [`check`] turns the
+//! answers into warnings: a walk that does not close back on the seed, a
+//! node that does not answer, a node whose NEXT is not the hop the walk
+//! took out of it, or a ring without exactly one `LEADER`. Walk endpoints carry only a port, so nodes are dialled
+//! on the seed's host and NEXT is compared by port. A `SPLIT-BRAIN CHECK`
+//! from the seed then warns if the nodes name more than one leader.
+
+use serde::Serialize;
+
+use crate::client::{ClientOpts, RingClient, WalkResult};
+use crate::node::{NodeRole, normalize_addr_on, port_str, split_label};
+
+/// What one node the walk reached said for itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct NodeReport {
+    /// Where the node was dialled.
+    pub addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The walk's hop out of this node, as a port.
+    pub walk_next: String,
+    /// Answered `NODE INFO`.
+    pub alive: bool,
+    /// NEXT from `NODE INFO`; `None` when unset or the node is down.
+    pub next: Option<String>,
+    /// ROLE from `NODE INFO`; `Unknown` when the node is down.
+    pub role: NodeRole,
+}
+
+/// Everything `diagnose` found; healthy when there are no warnings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Diagnosis {
+    pub seed: String,
+    pub healthy: bool,
+    pub nodes: Vec<NodeReport>,
+    pub warnings: Vec<String>,
+}
+
+impl Diagnosis {
+    /// One line per node, a `WARN <problem>` line per warning, then `OK`
+    /// or `FAIL` with a count.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for node in &self.nodes {
+            let label = match &node.name {
+                Some(name) => format!("{name}@{}", node.addr),
+                None => node.addr.clone(),
+            };
+            let state = match (&node.next, node.alive) {
+                (_, false) => "down".to_string(),
+                (Some(next), true) => format!("next={next} role={}", node.role),
+                (None, true) => format!("next=<unset> role={}", node.role),
+            };
+            out.push_str(&format!("NODE {label} {state}\n"));
+        }
+        for warning in &self.warnings {
+            out.push_str(&format!("WARN {warning}\n"));
+        }
+        if self.healthy {
+            out.push_str(&format!("OK {} nodes\n", self.nodes.len()));
+        } else {
+            out.push_str(&format!("FAIL {} problem(s)\n", self.warnings.len()));
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("diagnosis serializes")
+    }
+}
+
+/// The problems with a ring whose walk from `seed` gave `edges` and whose
+/// nodes, in walk order, answered `nodes`.
+pub fn check(seed: &str, edges: &WalkResult, nodes: &[NodeReport]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let Some((last_from, last_to)) = edges.last() else {
+        warnings.push(format!("walk from {seed} returned no edges"));
+        return warnings;
+    };
+    if port_str(split_label(last_to).1) != port_str(seed) {
+        warnings.push(format!(
+            "ring is not closed: the walk from {seed} ended with {last_from}->{last_to}"
+        ));
+    }
+    for node in nodes {
+        match &node.next {
+            _ if !node.alive => warnings.push(format!("{} does not answer NODE INFO", node.addr)),
+            None => warnings.push(format!(
+                "{} has no NEXT, but the walk went on to {}",
+                node.addr, node.walk_next
+            )),
+            Some(next) if port_str(next) != port_str(&node.walk_next) => warnings.push(format!(
+                "{} reports NEXT {next}, but the walk went on to {}",
+                node.addr, node.walk_next
+            )),
+            Some(_) => {}
+        }
+    }
+    let leaders: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.role == NodeRole::Leader)
+        .map(|n| n.addr.as_str())
+        .collect();
+    match leaders.as_slice() {
+        [] => warnings.push("no node has role LEADER; run ELECT START".to_string()),
+        [_] => {}
+        many => warnings.push(format!(
+            "{} nodes have role LEADER: {}",
+            many.len(),
+            many.join(", ")
+        )),
+    }
+    warnings
+}
+
+/// Walk the ring from `seed`, probe every node the walk reached, and
+/// [`check`] the result, then run a `SPLIT-BRAIN CHECK` from `seed`. A
+/// failed walk or check is reported as a warning.
+pub async fn diagnose(seed: &str, opts: &ClientOpts) -> Diagnosis {
+    let mut diagnosis = Diagnosis {
+        seed: seed.to_string(),
+        ..Diagnosis::default()
+    };
+    let edges = match RingClient::with_opts(seed, opts.clone()).walk().await {
+        Ok(edges) => edges,
+        Err(e) => {
+            diagnosis
+                .warnings
+                .push(format!("walk from {seed} failed: {e}"));
+            return diagnosis;
+        }
+    };
+
+    let host = seed.rsplit_once(':').map_or(seed, |(host, _)| host);
+    for (from, to) in &edges {
+        let (name, endpoint) = split_label(from);
+        let addr = normalize_addr_on(endpoint, host);
+        let status = RingClient::with_opts(&addr, opts.clone())
+            .node_info()
+            .await
+            .ok();
+        let role = status.as_ref().map(|info| info.role).unwrap_or_default();
+        diagnosis.nodes.push(NodeReport {
+            addr,
+            name: name.map(str::to_string),
+            walk_next: split_label(to).1.to_string(),
+            alive: status.is_some(),
+            next: status.and_then(|info| info.next),
+            role,
+        });
+    }
+    diagnosis.warnings = check(seed, &edges, &diagnosis.nodes);
+    match RingClient::with_opts(seed, opts.clone())
+        .split_brain_check()
+        .await
+    {
+        Ok(leaders) if leaders.len() > 1 => diagnosis.warnings.push(format!(
+            "split brain: nodes name {} leaders: {}",
+            leaders.len(),
+            leaders.join(", ")
+        )),
+        Ok(_) => {}
+        Err(e) => diagnosis
+            .warnings
+            .push(format!("SPLIT-BRAIN CHECK from {seed} failed: {e}")),
+    }
+    diagnosis.healthy = diagnosis.warnings.is_empty();
+    diagnosis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(pairs: &[(&str, &str)]) -> WalkResult {
+        pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    fn report(port: u16, walk_next: u16, next: Option<u16>) -> NodeReport {
+        NodeReport {
+            addr: format!("127.0.0.1:{port}"),
+            name: None,
+            walk_next: walk_next.to_string(),
+            alive: true,
+            next: next.map(|p| format!("127.0.0.1:{p}")),
+            role: NodeRole::Follower,
+        }
+    }
+
+    fn leader(mut report: NodeReport) -> NodeReport {
+        report.role = NodeRole::Leader;
+        report
+    }
+
+    #[test]
+    fn closed_ring_with_matching_next_is_healthy() {
+        let walk = edges(&[("n0@7000", "7001"), ("7001", "7000")]);
+        let nodes = [
+            report(7000, 7001, Some(7001)),
+            leader(report(7001, 7000, Some(7000))),
+        ];
+        assert_eq!(check("127.0.0.1:7000", &walk, &nodes), Vec::<String>::new());
+    }
+
+    #[test]
+    fn ring_needs_exactly_one_leader() {
+        let walk = edges(&[("7000", "7001"), ("7001", "7000")]);
+        let nodes = [
+            report(7000, 7001, Some(7001)),
+            report(7001, 7000, Some(7000)),
+        ];
+        assert_eq!(
+            check("127.0.0.1:7000", &walk, &nodes),
+            ["no node has role LEADER; run ELECT START"]
+        );
+        let nodes = nodes.map(leader);
+        assert_eq!(
+            check("127.0.0.1:7000", &walk, &nodes),
+            ["2 nodes have role LEADER: 127.0.0.1:7000, 127.0.0.1:7001"]
+        );
+    }
+
+    #[test]
+    fn open_walk_dead_nodes_and_stale_next_are_reported() {
+        let walk = edges(&[("7000", "7001"), ("7001", "7002"), ("7002", "7001")]);
+        let mut down = report(7001, 7002, None);
+        down.alive = false;
+        let nodes = [
+            leader(report(7000, 7001, Some(7009))),
+            down,
+            report(7002, 7001, None),
+        ];
+        assert_eq!(
+            check("127.0.0.1:7000", &walk, &nodes),
+            [
+                "ring is not closed: the walk from 127.0.0.1:7000 ended with 7002->7001",
+                "127.0.0.1:7000 reports NEXT 127.0.0.1:7009, but the walk went on to 7001",
+                "127.0.0.1:7001 does not answer NODE INFO",
+                "127.0.0.1:7002 has no NEXT, but the walk went on to 7001",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_walk_is_a_problem() {
+        assert_eq!(
+            check("127.0.0.1:7000", &Vec::new(), &[]),
+            ["walk from 127.0.0.1:7000 returned no edges"]
+        );
+    }
+
+    #[test]
+    fn text_ends_with_the_verdict() {
+        let diagnosis = Diagnosis {
+            seed: "127.0.0.1:7000".into(),
+            healthy: false,
+            nodes: vec![report(7000, 7000, Some(7000))],
+            warnings: vec!["something".into()],
+        };
+        assert_eq!(
+            diagnosis.to_text(),
+            "NODE 127.0.0.1:7000 next=127.0.0.1:7000 role=FOLLOWER\nWARN something\nFAIL 1 problem(s)\n"
+        );
+    }
+}
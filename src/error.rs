@@ -0,0 +1,169 @@
+//! The error type returned by the node, server and protocol layers.
+//!
+//! Most failures used to surface as a boxed `dyn Error`, so a caller could
+//! only tell a dead neighbour from a bad command by matching on the message.
+//! [`RingError`] names the conditions worth reacting to; anything else is
+//! carried in [`RingError::Io`] or [`RingError::Other`].
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An error from binding, parsing, forwarding or walking the ring.
+#[derive(Debug)]
+pub enum RingError {
+    /// The listener could not be bound to `addr`.
+    BindFailed { addr: String, source: io::Error },
+    /// A protocol line could not be parsed.
+    ParseError(String),
+    /// A protocol line named a namespace this node does not know.
+    UnknownCommand(String),
+    /// A line could not be delivered to the ring neighbour at `addr`.
+    ForwardFailed { addr: String, source: io::Error },
+    /// A ring walk got no `DONE` within its timeout.
+    WalkTimeout,
+    /// A ring walk was canceled before it completed.
+    WalkCanceled,
+    /// A peer rejected, or failed to present, the shared auth token.
+    AuthFailed,
+    /// The node at `addr` answered `ERR <msg>`.
+    Remote { addr: String, msg: String },
+    /// Any other I/O failure.
+    Io(io::Error),
+    /// Anything that does not fit the variants above.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl RingError {
+    /// Wrap a failed send to the neighbour at `addr`.
+    pub fn forward(addr: &str, source: io::Error) -> Self {
+        RingError::ForwardFailed {
+            addr: addr.to_string(),
+            source,
+        }
+    }
+
+    /// True for failures a caller may reasonably retry: a neighbour that
+    /// could not be reached or a walk that ran out of time.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            RingError::ForwardFailed { .. } | RingError::WalkTimeout
+        )
+    }
+}
+
+impl fmt::Display for RingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RingError::BindFailed { addr, source } => write!(f, "bind {addr}: {source}"),
+            RingError::ParseError(msg) => f.write_str(msg),
+            RingError::UnknownCommand(noun) => {
+                write!(f, "unknown command namespace: '{noun}'")
+            }
+            RingError::ForwardFailed { addr, source } => {
+                write!(f, "forward to {addr} failed: {source}")
+            }
+            RingError::WalkTimeout => f.write_str("walk timed out"),
+            RingError::WalkCanceled => f.write_str("walk canceled"),
+            RingError::AuthFailed => f.write_str("unauthorized"),
+            RingError::Remote { addr, msg } => write!(f, "{addr}: {msg}"),
+            RingError::Io(e) => e.fmt(f),
+            RingError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for RingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RingError::BindFailed { source, .. } | RingError::ForwardFailed { source, .. } => {
+                Some(source)
+            }
+            RingError::Io(e) => Some(e),
+            RingError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RingError {
+    fn from(e: io::Error) -> Self {
+        RingError::Io(e)
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for RingError {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        RingError::Other(e)
+    }
+}
+
+impl From<String> for RingError {
+    fn from(msg: String) -> Self {
+        RingError::Other(msg.into())
+    }
+}
+
+impl From<&str> for RingError {
+    fn from(msg: &str) -> Self {
+        RingError::Other(msg.into())
+    }
+}
+
+impl From<std::net::AddrParseError> for RingError {
+    fn from(e: std::net::AddrParseError) -> Self {
+        RingError::Other(Box::new(e))
+    }
+}
+
+impl From<std::num::ParseIntError> for RingError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        RingError::ParseError(e.to_string())
+    }
+}
+
+/// A `tokio::time::timeout` on a connect or read: an I/O timeout, not a
+/// [`RingError::WalkTimeout`].
+impl From<tokio::time::error::Elapsed> for RingError {
+    fn from(e: tokio::time::error::Elapsed) -> Self {
+        RingError::Io(io::Error::new(io::ErrorKind::TimedOut, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_command_keeps_the_wire_text() {
+        let e = RingError::UnknownCommand("BOGUS".into());
+        assert_eq!(e.to_string(), "unknown command namespace: 'BOGUS'");
+    }
+
+    #[test]
+    fn forward_failed_exposes_its_source() {
+        let e = RingError::forward(
+            "127.0.0.1:7001",
+            io::Error::from(io::ErrorKind::ConnectionRefused),
+        );
+        assert!(e.is_transient());
+        assert!(
+            e.to_string()
+                .starts_with("forward to 127.0.0.1:7001 failed")
+        );
+        let src = e.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(src.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn conversions_pick_the_matching_variant() {
+        let e: RingError = io::Error::other("x").into();
+        assert!(matches!(e, RingError::Io(_)));
+        let e: RingError = "x".parse::<u32>().unwrap_err().into();
+        assert!(matches!(e, RingError::ParseError(_)));
+        let e: RingError = "boom".into();
+        assert!(matches!(e, RingError::Other(_)));
+        assert!(!e.is_transient());
+    }
+}
@@ -0,0 +1,222 @@
+//! Ring events for `SUBSCRIBE` connections and the `watch` subcommand.
+//!
+//! After a command has been handled, the node turns it into at most one
+//! event and hands `EVENT <type> <data>` to every subscribed connection;
+//! each connection writes the lines its filter asks for. Subscriber
+//! queues are bounded, and a subscriber that falls behind loses events
+//! rather than slowing the ring down.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::protocol::Command;
+
+/// Events a subscriber's queue holds before newer ones are dropped.
+pub const SUBSCRIBER_QUEUE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A `RING` message passed through the node, or one past
+    /// `--max-ring-hops` was dropped: `RING_STORM hop_count=<n> max=<m>`.
+    Ring,
+    /// A walk started, hopped through or finished on the node.
+    Walk,
+    /// The node's NEXT was set, on any ring.
+    SetNext,
+    /// A `--probe-interval-secs` probe did not make it round the ring:
+    /// `PARTITION_DETECTED <round>`.
+    Partition,
+    /// A `--monitor-topology` walk counted a different number of nodes
+    /// than the one before: `TOPOLOGY_CHANGE old=<n> new=<m>`.
+    Topology,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Ring => "ring",
+            EventKind::Walk => "walk",
+            EventKind::SetNext => "set_next",
+            EventKind::Partition => "partition",
+            EventKind::Topology => "topology",
+        }
+    }
+}
+
+/// Which events a `SUBSCRIBE` connection receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFilter {
+    All,
+    Only(EventKind),
+}
+
+impl EventFilter {
+    /// Whether the `EVENT <type> ...` line `event` passes the filter.
+    pub fn matches(self, event: &str) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::Only(kind) => event
+                .strip_prefix("EVENT ")
+                .and_then(|rest| rest.strip_prefix(kind.as_str()))
+                .is_some_and(|rest| rest.starts_with(' ')),
+        }
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(EventFilter::All),
+            "ring" => Ok(EventFilter::Only(EventKind::Ring)),
+            "walk" => Ok(EventFilter::Only(EventKind::Walk)),
+            "set_next" => Ok(EventFilter::Only(EventKind::SetNext)),
+            "partition" => Ok(EventFilter::Only(EventKind::Partition)),
+            "topology" => Ok(EventFilter::Only(EventKind::Topology)),
+            other => Err(format!(
+                "unknown event type '{other}': use ring, walk, set_next, partition, topology or all"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventFilter::All => f.write_str("all"),
+            EventFilter::Only(kind) => f.write_str(kind.as_str()),
+        }
+    }
+}
+
+/// The `EVENT` line for `data`, newline included.
+pub fn format_event(kind: EventKind, data: &str) -> String {
+    format!("EVENT {} {data}\n", kind.as_str())
+}
+
+/// The event a handled command produces, if any. The data is the
+/// command's verb and its interesting fields: `FORWARD 2 hello`,
+/// `HOP 7000-3`, `backup 127.0.0.1:7001`.
+pub fn command_event(cmd: &Command) -> Option<(EventKind, String)> {
+    use EventKind::{Ring, SetNext, Walk};
+    Some(match cmd {
+        Command::RingForward { ttl, msg, .. } => (Ring, format!("FORWARD {ttl} {msg}")),
+        Command::RingOn { ring, ttl, msg, .. } => (Ring, format!("ON {ring} {ttl} {msg}")),
+        Command::RingAck { ttl, msg } => (Ring, format!("ACK {ttl} {msg}")),
+        Command::RingAckHop { ttl, msg, .. } => (Ring, format!("ACK-HOP {ttl} {msg}")),
+        Command::RingEcho { ttl, msg } => (Ring, format!("ECHO {ttl} {msg}")),
+        Command::RingEchoHop { ttl, msg, .. } => (Ring, format!("ECHO-HOP {ttl} {msg}")),
+        Command::RingCollect { ttl, key } => (Ring, format!("COLLECT {ttl} {key}")),
+        Command::RingCollectHop { ttl, key, .. } => (Ring, format!("COLLECT-HOP {ttl} {key}")),
+        Command::RingReq { ttl, msg, .. } => (Ring, format!("REQ {ttl} {msg}")),
+        Command::RingTimed { expiry_ms, msg } => (Ring, format!("TIMED {expiry_ms} {msg}")),
+        Command::RingOnce { msg } => (Ring, format!("ONCE {msg}")),
+        Command::RingAll { msg } => (Ring, format!("ALL {msg}")),
+        Command::RingOnceHop { msg, .. } => (Ring, format!("ONCE-HOP {msg}")),
+
+        Command::TopologyWalk => (Walk, "WALK".into()),
+        Command::TopologyWalkFresh => (Walk, "WALK-FRESH".into()),
+        Command::TopologyWalkMeta { key } => (Walk, format!("WALK-META {key}")),
+        Command::TopologyHop { token, .. } => (Walk, format!("HOP {token}")),
+        Command::TopologyDone { token, .. } => (Walk, format!("DONE {token}")),
+        Command::TopologyReverseWalk => (Walk, "REVERSE-WALK".into()),
+        Command::TopologyBidirWalk => (Walk, "BIDIR-WALK".into()),
+        Command::TopologyReverseHop { token, .. } => (Walk, format!("REVERSE-HOP {token}")),
+        Command::TopologyReverseDone { token, .. } => (Walk, format!("REVERSE-DONE {token}")),
+        Command::WalkPayStart { payload_key } => (Walk, format!("PAY-WALK {payload_key}")),
+        Command::WalkPayHop { token, .. } => (Walk, format!("PAY-HOP {token}")),
+        Command::WalkPayDone { token, .. } => (Walk, format!("PAY-DONE {token}")),
+        Command::WalkMax { max_hops } => (Walk, format!("WALK-MAX {max_hops}")),
+        Command::WalkMaxHop { token, .. } => (Walk, format!("MAX-HOP {token}")),
+        Command::WalkMaxDone { token, .. } => (Walk, format!("MAX-DONE {token}")),
+        Command::WalkSample { probability } => (Walk, format!("WALK-SAMPLE {probability}")),
+        Command::WalkSampleHop { token, .. } => (Walk, format!("SAMPLE-HOP {token}")),
+        Command::WalkSampleDone { token, .. } => (Walk, format!("SAMPLE-DONE {token}")),
+        Command::WalkRing { ring } => (Walk, format!("RING-WALK {ring}")),
+        Command::WalkRingHop { token, .. } => (Walk, format!("RING-HOP {token}")),
+        Command::WalkRingDone { token, .. } => (Walk, format!("RING-DONE {token}")),
+        Command::WalkFind { key, .. } => (Walk, format!("FIND {key}")),
+        Command::WalkFindHop { token, .. } => (Walk, format!("FIND-HOP {token}")),
+        Command::WalkFound { token, addr, .. } => (Walk, format!("FOUND {token} {addr}")),
+        Command::WalkNotFound { token } => (Walk, format!("NOT-FOUND {token}")),
+        Command::WalkParallel => (Walk, "PARALLEL-WALK".into()),
+
+        Command::NodeNext(addr) => (SetNext, format!("{} {addr}", crate::node::DEFAULT_RING)),
+        Command::NodeNextRing { ring, addr } => (SetNext, format!("{ring} {addr}")),
+        Command::NodeNextRings(hops) => (
+            SetNext,
+            hops.iter()
+                .map(|(ring, addr)| format!("{ring} {addr}"))
+                .collect::<Vec<_>>()
+                .join(";"),
+        ),
+        Command::NodeNextGen { addr, .. } => {
+            (SetNext, format!("{} {addr}", crate::node::DEFAULT_RING))
+        }
+        Command::NodeNextIf { new_addr, .. } => {
+            (SetNext, format!("{} {new_addr}", crate::node::DEFAULT_RING))
+        }
+        Command::NodeNextMulti(addrs) => (
+            SetNext,
+            format!("{} {}", crate::node::DEFAULT_RING, addrs.join(",")),
+        ),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parse_line;
+
+    fn event_line(line: &str) -> Option<String> {
+        let (kind, data) = command_event(&parse_line(line).unwrap())?;
+        Some(format_event(kind, &data))
+    }
+
+    #[test]
+    fn commands_map_to_events() {
+        assert_eq!(
+            event_line("RING FORWARD 2 hello there").as_deref(),
+            Some("EVENT ring FORWARD 2 hello there\n")
+        );
+        assert_eq!(
+            event_line("TOPOLOGY HOP 7000-3 127.0.0.1:7000 7000->7001 127.0.0.1:7000").as_deref(),
+            Some("EVENT walk HOP 7000-3\n")
+        );
+        assert_eq!(
+            event_line("NODE NEXT-RING backup 127.0.0.1:7001").as_deref(),
+            Some("EVENT set_next backup 127.0.0.1:7001\n")
+        );
+        assert_eq!(event_line("NODE STATUS"), None);
+    }
+
+    #[test]
+    fn filters_match_their_type_only() {
+        let ring = format_event(EventKind::Ring, "FORWARD 1 hi");
+        let set_next = format_event(EventKind::SetNext, "default 127.0.0.1:7001");
+        let only_ring: EventFilter = "ring".parse().unwrap();
+        assert!(only_ring.matches(&ring));
+        assert!(!only_ring.matches(&set_next));
+        assert!("ALL".parse::<EventFilter>().unwrap().matches(&set_next));
+        assert!("rings".parse::<EventFilter>().is_err());
+        assert_eq!(only_ring.to_string(), "ring");
+        let partition = format_event(EventKind::Partition, "PARTITION_DETECTED 3");
+        assert!(
+            "partition"
+                .parse::<EventFilter>()
+                .unwrap()
+                .matches(&partition)
+        );
+        assert!(!only_ring.matches(&partition));
+        let topology = format_event(EventKind::Topology, "TOPOLOGY_CHANGE old=3 new=2");
+        assert!(
+            "topology"
+                .parse::<EventFilter>()
+                .unwrap()
+                .matches(&topology)
+        );
+        assert!(!only_ring.matches(&topology));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::NodeStatus;
 use crate::auth::AuthToken;
 use crate::node::port_str;
+use crate::protocol::{MIN_SUPPORTED_VERSION, negotiate_version};
+use crate::transport::{Stream, TlsConfig};
 use serde::Serialize;
 use serde_json;
 use std::collections::HashMap;
@@ -31,6 +33,12 @@ pub struct Gateway {
     /// Bearer <hex>`, and the gateway uses the same secret to AUTH onto
     /// every ring node. (See `auth::AuthToken`.)
     auth_token: AuthToken,
+    /// Client-side TLS for gateway → ring connections. The HTTP listener
+    /// itself stays plaintext; terminate TLS in front of it.
+    tls: TlsConfig,
+    /// Expect the `HELLO` version greeting on every ring connection; must
+    /// match the nodes' `--hello`.
+    hello: bool,
 }
 
 /// HTTP Response Struct
@@ -46,13 +54,34 @@ impl Gateway {
         Arc::new(Self {
             node_addrs,
             auth_token: AuthToken::disabled(),
+            tls: TlsConfig::disabled(),
+            hello: false,
         })
     }
 
     pub fn with_auth(node_addrs: Vec<String>, auth_token: AuthToken) -> Arc<Self> {
+        Self::with_auth_and_tls(node_addrs, auth_token, TlsConfig::disabled())
+    }
+
+    pub fn with_auth_and_tls(
+        node_addrs: Vec<String>,
+        auth_token: AuthToken,
+        tls: TlsConfig,
+    ) -> Arc<Self> {
+        Self::with_auth_tls_and_hello(node_addrs, auth_token, tls, false)
+    }
+
+    pub fn with_auth_tls_and_hello(
+        node_addrs: Vec<String>,
+        auth_token: AuthToken,
+        tls: TlsConfig,
+        hello: bool,
+    ) -> Arc<Self> {
         Arc::new(Self {
             node_addrs,
             auth_token,
+            tls,
+            hello,
         })
     }
 
@@ -282,7 +311,10 @@ impl Gateway {
         let mut tasks: Vec<ScrapeTask> = Vec::new();
         for addr in self.node_addrs.clone() {
             let token = self.auth_token.clone();
-            tasks.push(tokio::spawn(Self::scrape_node_metrics(addr, token)));
+            let tls = self.tls.clone();
+            tasks.push(tokio::spawn(Self::scrape_node_metrics(
+                addr, token, tls, self.hello,
+            )));
         }
         let mut out = Vec::new();
         for t in tasks {
@@ -297,16 +329,15 @@ impl Gateway {
     async fn scrape_node_metrics(
         addr: String,
         token: AuthToken,
+        tls: TlsConfig,
+        hello: bool,
     ) -> Option<(String, Vec<(String, u64)>)> {
         let timeout = Duration::from_millis(500);
         let port = port_str(&addr).to_string();
         let lines = tokio::time::timeout(timeout, async {
-            let mut s = TcpStream::connect(&addr).await.ok()?;
-            if let Some(line) = token.make_auth_line() {
-                s.write_all(line.as_bytes()).await.ok()?;
-            }
+            let mut s = dial_node(&addr, &token, &tls, hello).await.ok()?;
             s.write_all(b"NODE METRICS\n").await.ok()?;
-            let (r, _w) = s.split();
+            let (r, _w) = tokio::io::split(s);
             let mut reader = BufReader::new(r);
             let mut accum: Vec<(String, u64)> = Vec::new();
             let mut line = String::new();
@@ -468,8 +499,8 @@ impl Gateway {
         filename: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // 1. Connect to a node in the ring
-        let mut node_stream = self.connect_to_ring().await?;
-        let (mut node_read, mut node_write) = node_stream.split();
+        let node_stream = self.connect_to_ring().await?;
+        let (mut node_read, mut node_write) = tokio::io::split(node_stream);
 
         // 2. Send TCP FILE PULL to the node
         let header = format!("FILE PULL {}\n", filename);
@@ -639,7 +670,7 @@ impl Gateway {
     {
         // 1. Connect to node (with AUTH already sent by connect_to_ring).
         let mut node_stream = self.connect_to_ring().await?;
-        tracing::debug!(addr = ?node_stream.peer_addr(), "Gateway connected to ring node");
+        tracing::debug!(addr = ?node_stream.tcp().map(TcpStream::peer_addr), "Gateway connected to ring node");
 
         // 2. Send the first line (the request the client sent us).
         node_stream.write_all(first_line.as_bytes()).await?;
@@ -655,7 +686,7 @@ impl Gateway {
         // half, signaling "request done"), explicitly shut down our
         // node_write half. The ring then sees EOF, exits its read loop,
         // closes its write half, and the server→client copy returns.
-        let (mut node_read, mut node_write) = node_stream.split();
+        let (mut node_read, mut node_write) = tokio::io::split(node_stream);
         let client_to_server = async {
             let r = copy(&mut client_reader, &mut node_write).await;
             // Shut down the write half regardless of copy result so the
@@ -685,20 +716,21 @@ impl Gateway {
     /// Sends a "NODE PING" to a single address and returns its status.
     ///
     /// This is a lightweight, best-effort check with a short timeout.
-    async fn ping_node(addr: String, token: AuthToken) -> (String, NodeStatus) {
+    async fn ping_node(
+        addr: String,
+        token: AuthToken,
+        tls: TlsConfig,
+        hello: bool,
+    ) -> (String, NodeStatus) {
         let port = port_str(&addr).to_string();
         let timeout = Duration::from_millis(500);
 
         type AnyErr = Box<dyn std::error::Error + Send + Sync>;
 
         let check = async {
-            // Connect with timeout
-            let mut stream = tokio::time::timeout(timeout, TcpStream::connect(&addr)).await??;
-
-            // Authenticate before any protocol command (no-op when disabled).
-            if let Some(line) = token.make_auth_line() {
-                stream.write_all(line.as_bytes()).await?;
-            }
+            // Connect (and authenticate) with timeout
+            let mut stream =
+                tokio::time::timeout(timeout, dial_node(&addr, &token, &tls, hello)).await??;
 
             // Send the PING command
             stream.write_all(b"NODE PING\n").await?;
@@ -732,7 +764,8 @@ impl Gateway {
         // 1. Spawn a concurrent ping task for every node address we know
         for addr in self.node_addrs.clone() {
             let token = self.auth_token.clone();
-            tasks.push(tokio::spawn(Self::ping_node(addr, token)));
+            let tls = self.tls.clone();
+            tasks.push(tokio::spawn(Self::ping_node(addr, token, tls, self.hello)));
         }
 
         let mut map = HashMap::new();
@@ -814,16 +847,13 @@ impl Gateway {
 
     /// Tries all node addresses and returns a stream to the first one that
     /// connects, having already sent the wire-protocol AUTH line on it.
-    async fn connect_to_ring(&self) -> Result<TcpStream, Box<dyn std::error::Error + Send + Sync>> {
+    async fn connect_to_ring(&self) -> Result<Stream, Box<dyn std::error::Error + Send + Sync>> {
         for addr in &self.node_addrs {
-            if let Ok(mut stream) = TcpStream::connect(addr).await {
-                if let Some(line) = self.auth_token.make_auth_line()
-                    && let Err(e) = stream.write_all(line.as_bytes()).await
-                {
-                    tracing::warn!(node = %addr, error = ?e, "Gateway: failed to send AUTH; trying next node");
-                    continue;
+            match dial_node(addr, &self.auth_token, &self.tls, self.hello).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    tracing::debug!(node = %addr, error = ?e, "Gateway: node unreachable; trying next node");
                 }
-                return Ok(stream);
             }
         }
         Err("Could not connect to any node in the ring".into())
@@ -899,3 +929,23 @@ impl Gateway {
         writer.write_all(response.as_bytes()).await
     }
 }
+
+/// Dial a ring node (TLS when configured), check its version greeting when
+/// `hello` is set, and send the AUTH line.
+async fn dial_node(
+    addr: &str,
+    token: &AuthToken,
+    tls: &TlsConfig,
+    hello: bool,
+) -> io::Result<Stream> {
+    let mut stream = tls.connect(addr).await?;
+    if hello {
+        negotiate_version(&mut stream, MIN_SUPPORTED_VERSION)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(line) = token.make_auth_line() {
+        stream.write_all(line.as_bytes()).await?;
+    }
+    Ok(stream)
+}
@@ -0,0 +1,305 @@
+//! Heartbeat membership (`run --heartbeat-addr`, the `coordinator`
+//! subcommand).
+//!
+//! A node started with `--heartbeat-addr` sends `HEARTBEAT <node_addr>
+//! <name> <generation> [<next>]` to the coordinator every
+//! `--heartbeat-interval-secs`, starting as soon as it listens. The
+//! coordinator keeps one entry per address: the first heartbeat from an
+//! address, or the first after it was marked dead, is a join. A node
+//! whose last heartbeat is more than [`MISSED_HEARTBEATS`] intervals old
+//! is marked dead; it stays in the table so its next heartbeat counts as
+//! a join again. `MEMBERS` lists the live ones.
+//!
+//! Connections to the coordinator go through the same TLS, `HELLO` and
+//! `AUTH` steps as connections to a node.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, broadcast};
+
+use crate::client::ClientOpts;
+use crate::error::RingError;
+use crate::events::SUBSCRIBER_QUEUE;
+use crate::io::{DEFAULT_MAX_LINE_BYTES, LimitedLineReader};
+use crate::protocol::{Command, PROTOCOL_VERSION, parse_line};
+use crate::transport::Stream;
+
+/// Heartbeat interval when `--heartbeat-interval-secs` is not given.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Intervals without a heartbeat before a member is marked dead.
+pub const MISSED_HEARTBEATS: u32 = 3;
+
+/// What a node last reported about itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub addr: String,
+    /// `<unset>` for an unnamed node.
+    pub name: String,
+    pub generation: u64,
+    /// `None` while the node has no NEXT.
+    pub next: Option<String>,
+}
+
+impl Member {
+    /// The `MEMBER` line `MEMBERS` answers with for this node, without
+    /// its newline.
+    pub fn line(&self) -> String {
+        format!(
+            "MEMBER {} {} {} {}",
+            self.addr,
+            self.name,
+            self.generation,
+            self.next.as_deref().unwrap_or("<unset>")
+        )
+    }
+}
+
+impl FromStr for Member {
+    type Err = RingError;
+
+    /// The inverse of [`Member::line`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let ["MEMBER", addr, name, generation, next] = parts.as_slice() else {
+            return Err(RingError::ParseError(format!("malformed MEMBER line: {s}")));
+        };
+        Ok(Member {
+            addr: addr.to_string(),
+            name: name.to_string(),
+            generation: generation
+                .parse()
+                .map_err(|_| RingError::ParseError(format!("invalid generation: {generation}")))?,
+            next: (*next != "<unset>").then(|| next.to_string()),
+        })
+    }
+}
+
+/// A change to the membership table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MembershipChange {
+    /// First heartbeat from a node, or the first since it was marked dead.
+    Joined(Member),
+    /// No heartbeat from this address for [`MISSED_HEARTBEATS`] intervals.
+    Dead(String),
+}
+
+struct Entry {
+    member: Member,
+    last_seen: Instant,
+    alive: bool,
+}
+
+pub struct HeartbeatCoordinator {
+    /// The interval the nodes send at; a member is dead after
+    /// [`MISSED_HEARTBEATS`] of them.
+    interval: Duration,
+    /// Every address ever heard from, dead ones included.
+    members: Mutex<BTreeMap<String, Entry>>,
+    changes: broadcast::Sender<MembershipChange>,
+    /// TLS, `HELLO` and `AUTH` for the listener, as a node would use them.
+    client: ClientOpts,
+}
+
+impl HeartbeatCoordinator {
+    /// A coordinator for nodes sending every `interval`. Zero means
+    /// [`DEFAULT_HEARTBEAT_INTERVAL`].
+    pub fn new(interval: Duration, client: ClientOpts) -> Arc<Self> {
+        let interval = if interval.is_zero() {
+            DEFAULT_HEARTBEAT_INTERVAL
+        } else {
+            interval
+        };
+        Arc::new(Self {
+            interval,
+            members: Mutex::new(BTreeMap::new()),
+            changes: broadcast::channel(SUBSCRIBER_QUEUE).0,
+            client,
+        })
+    }
+
+    /// Joins and deaths from now on. A receiver that falls behind loses
+    /// the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<MembershipChange> {
+        self.changes.subscribe()
+    }
+
+    /// The live members, by address.
+    pub async fn members(&self) -> Vec<Member> {
+        self.members
+            .lock()
+            .await
+            .values()
+            .filter(|e| e.alive)
+            .map(|e| e.member.clone())
+            .collect()
+    }
+
+    /// Record a heartbeat. Returns the join it caused, if any.
+    pub async fn record(&self, member: Member) -> Option<MembershipChange> {
+        let mut members = self.members.lock().await;
+        let now = Instant::now();
+        let joined = match members.get_mut(&member.addr) {
+            Some(entry) => {
+                if entry.member.generation != member.generation {
+                    tracing::info!(node = %member.addr, generation = member.generation, "Member restarted");
+                }
+                let joined = !entry.alive;
+                entry.member = member.clone();
+                entry.last_seen = now;
+                entry.alive = true;
+                joined
+            }
+            None => {
+                members.insert(
+                    member.addr.clone(),
+                    Entry {
+                        member: member.clone(),
+                        last_seen: now,
+                        alive: true,
+                    },
+                );
+                true
+            }
+        };
+        drop(members);
+        joined.then(|| {
+            tracing::info!(node = %member.addr, name = %member.name, "Member joined");
+            let change = MembershipChange::Joined(member);
+            let _ = self.changes.send(change.clone());
+            change
+        })
+    }
+
+    /// Mark dead every live member not heard from for
+    /// [`MISSED_HEARTBEATS`] intervals. Returns their addresses.
+    pub async fn sweep(&self) -> Vec<String> {
+        let deadline = self.interval * MISSED_HEARTBEATS;
+        let mut dead = Vec::new();
+        for entry in self.members.lock().await.values_mut() {
+            if entry.alive && entry.last_seen.elapsed() > deadline {
+                entry.alive = false;
+                dead.push(entry.member.addr.clone());
+            }
+        }
+        for addr in &dead {
+            tracing::warn!(node = %addr, "Member missed {MISSED_HEARTBEATS} heartbeats; marking dead");
+            let _ = self.changes.send(MembershipChange::Dead(addr.clone()));
+        }
+        dead
+    }
+
+    /// Listen on `listen_addr` for `HEARTBEAT`s and `MEMBERS`.
+    pub async fn run_server(self: Arc<Self>, listen_addr: String) -> std::io::Result<()> {
+        let listener = TcpListener::bind(&listen_addr).await?;
+        tracing::info!(addr = %listen_addr, interval = ?self.interval, "Heartbeat coordinator listening");
+        self.serve(listener).await
+    }
+
+    /// [`HeartbeatCoordinator::run_server`] on an already bound listener.
+    /// Members are swept once per interval for as long as it runs.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        let sweeper = tokio::spawn({
+            let this = Arc::clone(&self);
+            async move {
+                let mut ticker = tokio::time::interval(this.interval);
+                loop {
+                    ticker.tick().await;
+                    this.sweep().await;
+                }
+            }
+        });
+        let res = self.accept_loop(listener).await;
+        sweeper.abort();
+        res
+    }
+
+    async fn accept_loop(self: &Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let this = Arc::clone(self);
+            tokio::spawn(async move {
+                if let Err(e) = this.handle_connection(Stream::Plain(stream)).await {
+                    tracing::warn!(peer = %peer, error = ?e, "Heartbeat coordinator client error");
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: Stream) -> Result<(), RingError> {
+        let mut stream = match tokio::time::timeout(
+            Duration::from_secs(5),
+            self.client.tls.accept(stream),
+        )
+        .await
+        {
+            Ok(r) => r?,
+            Err(_) => return Err("TLS handshake timed out".into()),
+        };
+        if self.client.hello {
+            stream
+                .write_all(PROTOCOL_VERSION.greeting().as_bytes())
+                .await?;
+        }
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = LimitedLineReader::new(BufReader::new(reader), DEFAULT_MAX_LINE_BYTES);
+
+        if self.client.auth_token.is_enabled() {
+            let mut auth_line = String::new();
+            let read =
+                tokio::time::timeout(Duration::from_secs(1), reader.read_line(&mut auth_line))
+                    .await;
+            if !matches!(read, Ok(Ok(n)) if n > 0)
+                || !self.client.auth_token.verify_auth_line(&auth_line)
+            {
+                let _ = writer.write_all(b"ERR auth required\n").await;
+                return Ok(());
+            }
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            let reply = match parse_line(&line) {
+                Ok(Command::Heartbeat {
+                    addr,
+                    name,
+                    generation,
+                    next,
+                }) => {
+                    self.record(Member {
+                        addr,
+                        name,
+                        generation,
+                        next,
+                    })
+                    .await;
+                    "OK\n".to_string()
+                }
+                Ok(Command::Members) => {
+                    let mut reply = String::new();
+                    for member in self.members().await {
+                        reply.push_str(&member.line());
+                        reply.push('\n');
+                    }
+                    reply.push_str("OK\n");
+                    reply
+                }
+                Ok(_) => "ERR only HEARTBEAT and MEMBERS are accepted here\n".to_string(),
+                Err(e) => format!("ERR {e}\n"),
+            };
+            // A node sends its HEARTBEAT and hangs up without waiting.
+            if writer.write_all(reply.as_bytes()).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Size-limited line reads.
+//!
+//! `AsyncBufReadExt::read_line` buffers until it sees `\n`, however far
+//! away that is, so a peer that never sends one can grow a node's memory
+//! without bound. [`LimitedLineReader`] fails the read with
+//! [`LineTooLong`] once a line passes its limit instead.
+
+use std::fmt;
+use std::io;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/// Default `--max-line-bytes`: far above any legitimate command or walk
+/// history, far below anything that threatens memory.
+pub const DEFAULT_MAX_LINE_BYTES: usize = 1 << 20;
+
+/// Carried inside the `io::Error` a [`LimitedLineReader`] returns for an
+/// over-long line; test for it with [`is_line_too_long`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTooLong {
+    pub limit: usize,
+}
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "message too large (limit {} bytes)", self.limit)
+    }
+}
+
+impl std::error::Error for LineTooLong {}
+
+/// Whether `e` came from a line exceeding a [`LimitedLineReader`]'s limit.
+pub fn is_line_too_long(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<LineTooLong>())
+}
+
+/// A `BufReader` whose `read_line` stops at `max` bytes, newline included.
+/// Raw reads (chunk bodies) go through [`LimitedLineReader::get_mut`] and
+/// are not limited.
+pub struct LimitedLineReader<R> {
+    inner: BufReader<R>,
+    max: usize,
+}
+
+impl<R: AsyncRead + Unpin> LimitedLineReader<R> {
+    /// `max == 0` disables the limit.
+    pub fn new(inner: BufReader<R>, max: usize) -> Self {
+        Self { inner, max }
+    }
+
+    pub fn get_mut(&mut self) -> &mut BufReader<R> {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> BufReader<R> {
+        self.inner
+    }
+
+    /// Like `AsyncBufReadExt::read_line`: append one line (with its `\n`,
+    /// if any) to `buf` and return its length, 0 at EOF. A line longer than
+    /// the limit fails with [`LineTooLong`]; the stream is then mid-line
+    /// and should be closed.
+    pub async fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut line = Vec::new();
+        loop {
+            let available = self.inner.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            let (take, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            if self.max > 0 && line.len() + take > self.max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    LineTooLong { limit: self.max },
+                ));
+            }
+            line.extend_from_slice(&available[..take]);
+            self.inner.consume(take);
+            if done {
+                break;
+            }
+        }
+        let line =
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        buf.push_str(&line);
+        Ok(line.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(input: &[u8], max: usize) -> LimitedLineReader<&[u8]> {
+        LimitedLineReader::new(BufReader::new(input), max)
+    }
+
+    #[tokio::test]
+    async fn reads_lines_then_eof() {
+        let mut r = reader(b"NODE PING\nNODE STATUS", 64);
+        let mut line = String::new();
+        assert_eq!(r.read_line(&mut line).await.unwrap(), 10);
+        assert_eq!(line, "NODE PING\n");
+        line.clear();
+        assert_eq!(r.read_line(&mut line).await.unwrap(), 11);
+        assert_eq!(line, "NODE STATUS");
+        line.clear();
+        assert_eq!(r.read_line(&mut line).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn limit_counts_the_newline() {
+        let mut line = String::new();
+        assert!(reader(b"abcd\n", 5).read_line(&mut line).await.is_ok());
+        let err = reader(b"abcde\n", 5)
+            .read_line(&mut String::new())
+            .await
+            .unwrap_err();
+        assert!(is_line_too_long(&err), "{err}");
+    }
+
+    #[tokio::test]
+    async fn overflow_spanning_buffer_refills_is_caught() {
+        let input = vec![b'x'; 10_000];
+        let mut r = LimitedLineReader::new(BufReader::with_capacity(16, &input[..]), 100);
+        let err = r.read_line(&mut String::new()).await.unwrap_err();
+        assert!(is_line_too_long(&err), "{err}");
+    }
+
+    #[tokio::test]
+    async fn zero_disables_the_limit() {
+        let input = [vec![b'x'; 10_000], vec![b'\n']].concat();
+        let mut line = String::new();
+        let n = reader(&input, 0).read_line(&mut line).await.unwrap();
+        assert_eq!(n, 10_001);
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_invalid_data_not_too_long() {
+        let err = reader(b"\xff\n", 64)
+            .read_line(&mut String::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!is_line_too_long(&err));
+    }
+}
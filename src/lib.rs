@@ -1,16 +1,58 @@
+pub mod announce;
 pub mod auth;
+pub mod bench;
+pub mod chaos;
+pub mod chring;
+pub mod client;
+pub mod codec;
+pub mod config;
+pub mod dedup;
+pub mod devnet;
+pub mod diagnose;
+pub mod error;
+pub mod events;
 pub mod gateway;
+pub mod heartbeat;
+pub mod io;
+pub mod lint;
+pub mod metrics;
+pub mod mux;
+pub mod net;
 pub mod node;
 pub mod node_status;
+pub mod pool;
 pub mod protocol;
+pub mod ratelimit;
+pub mod repair;
+pub mod repl;
+pub mod replay;
+pub mod runtime;
+pub mod script;
 pub mod server;
+pub mod snapshot;
+pub mod state;
+pub mod topology;
+pub mod topology_diff;
+pub mod trace;
+pub mod transport;
+pub mod tui;
+pub mod util;
+pub mod vclock;
+pub mod walk_registry;
+pub mod zstd;
 
 pub use auth::AuthToken;
+pub use client::{ClientOpts, RingClient};
+pub use error::RingError;
 pub use gateway::Gateway;
-pub use node::{FsyncMode, Node};
+pub use node::{FsyncMode, Node, NodeOpts};
 pub use node_status::NodeStatus;
 pub use protocol::{Command, parse_line};
 pub use server::run;
+pub use transport::TlsConfig;
 
+#[cfg(unix)]
 #[doc(hidden)]
-pub use server::{bind, serve, serve_with_shutdown};
+pub use server::bind_unix_with_opts;
+#[doc(hidden)]
+pub use server::{bind, bind_with_opts, serve, serve_with_shutdown};
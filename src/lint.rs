@@ -0,0 +1,288 @@
+//! Static checks on a `[ring]` table for the `lint-ring` subcommand.
+//!
+//! Everything here works on the parsed [`Config`]; no node is started or
+//! dialled, so a ring description can be checked in CI before it is
+//! deployed. The source text is only used to turn the spans `[ring]`
+//! keeps into line numbers.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::{Config, RingNodeConfig};
+
+/// Ports other services are expected to hold, by name.
+const WELL_KNOWN_PORTS: &[(u16, &str)] = &[
+    (22, "SSH"),
+    (25, "SMTP"),
+    (53, "DNS"),
+    (80, "HTTP"),
+    (443, "HTTPS"),
+    (2375, "Docker"),
+    (3306, "MySQL"),
+    (5432, "PostgreSQL"),
+    (6379, "Redis"),
+    (8080, "HTTP alternate"),
+    (9090, "Prometheus"),
+    (11211, "memcached"),
+    (27017, "MongoDB"),
+];
+
+/// One thing wrong with the ring, at the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// 1-based line in the config file; `None` for the ring as a whole.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Check `config.ring` and return its problems, ordered by line; empty
+/// when the ring is sound. `source` is the text `config` was parsed from.
+pub fn lint(config: &Config, source: &str) -> Vec<Problem> {
+    let nodes = &config.ring.nodes;
+    if nodes.is_empty() {
+        return vec![Problem {
+            line: None,
+            message: "no [[ring.nodes]] entries".into(),
+        }];
+    }
+    let line_of = |span: std::ops::Range<usize>| {
+        let start = span.start.min(source.len());
+        source[..start].matches('\n').count() + 1
+    };
+    let mut problems = Vec::new();
+    let mut problem = |line: usize, message: String| {
+        problems.push(Problem {
+            line: Some(line),
+            message,
+        })
+    };
+
+    // First entry for each address; later duplicates point back at it.
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut ports: HashMap<(&str, u16), usize> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        let addr = node.addr.get_ref().as_str();
+        let line = line_of(node.addr.span());
+        if let Some(&first) = index.get(addr) {
+            problem(
+                line,
+                format!(
+                    "duplicate node {addr} (first at line {})",
+                    line_of(nodes[first].addr.span())
+                ),
+            );
+            continue;
+        }
+        index.insert(addr, i);
+
+        let Some((host, port)) = split_port(addr) else {
+            problem(line, format!("{addr} is not a host:port address"));
+            continue;
+        };
+        if let Some(&first) = ports.get(&(host, port)) {
+            problem(
+                line,
+                format!(
+                    "port {port} is already used by the node at line {}",
+                    line_of(nodes[first].addr.span())
+                ),
+            );
+        } else {
+            ports.insert((host, port), i);
+        }
+        if let Some(conflict) = port_conflict(port) {
+            problem(line, conflict);
+        }
+    }
+
+    // Where each entry's `next` leads, by index; `None` when it leaves
+    // the ring.
+    let next: Vec<Option<usize>> = nodes
+        .iter()
+        .map(|n| index.get(n.next.get_ref().as_str()).copied())
+        .collect();
+    let mut pointed_at = vec![false; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        let line = line_of(node.next.span());
+        let target = node.next.get_ref();
+        match next[i] {
+            None => problem(line, format!("next {target} is not a node in [ring]")),
+            Some(j) if j == i && index.len() > 1 => {
+                problem(line, format!("{target} points at itself"))
+            }
+            Some(j) if j != i => pointed_at[j] = true,
+            Some(_) => {}
+        }
+    }
+    for (&addr, &i) in &index {
+        if !pointed_at[i] && index.len() > 1 {
+            problem(
+                line_of(nodes[i].addr.span()),
+                format!("no node points at {addr}, so nothing reaches it"),
+            );
+        }
+    }
+
+    // Every part beyond the one holding the first node is cut off.
+    let parts = components(nodes, &index, &next);
+    for part in parts.iter().skip(1) {
+        let lead = &nodes[part[0]];
+        problem(
+            line_of(lead.addr.span()),
+            format!(
+                "{} is in a part of {} node(s) disconnected from the ring at line {}",
+                lead.addr.get_ref(),
+                part.len(),
+                line_of(nodes[0].addr.span())
+            ),
+        );
+    }
+
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// `(host, port)` of a `host:port` address.
+fn split_port(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = addr.rsplit_once(':')?;
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+fn port_conflict(port: u16) -> Option<String> {
+    if port == 0 {
+        return Some("port 0 asks the OS for any port; a ring needs fixed ones".into());
+    }
+    if let Some((_, service)) = WELL_KNOWN_PORTS.iter().find(|(p, _)| *p == port) {
+        return Some(format!("port {port} is {service}'s well-known port"));
+    }
+    (port < 1024).then(|| format!("port {port} is in the well-known range below 1024"))
+}
+
+/// The entries grouped into connected parts, ignoring direction, each in
+/// file order and the parts ordered by their first entry.
+fn components(
+    nodes: &[RingNodeConfig],
+    index: &HashMap<&str, usize>,
+    next: &[Option<usize>],
+) -> Vec<Vec<usize>> {
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    for &i in index.values() {
+        if let Some(j) = next[i] {
+            neighbours[i].push(j);
+            neighbours[j].push(i);
+        }
+    }
+    let mut seen = vec![false; nodes.len()];
+    let mut parts = Vec::new();
+    let mut firsts: Vec<usize> = index.values().copied().collect();
+    firsts.sort_unstable();
+    for start in firsts {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let (mut part, mut stack) = (Vec::new(), vec![start]);
+        while let Some(i) = stack.pop() {
+            part.push(i);
+            for &j in &neighbours[i] {
+                if !seen[j] {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        part.sort_unstable();
+        parts.push(part);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Section;
+
+    fn lint_str(raw: &str) -> Vec<String> {
+        let cfg = Config::parse(raw, Section::Network).unwrap();
+        lint(&cfg, raw).iter().map(Problem::to_string).collect()
+    }
+
+    fn ring(pairs: &[(&str, &str)]) -> String {
+        pairs
+            .iter()
+            .map(|(addr, next)| format!("[[ring.nodes]]\naddr = \"{addr}\"\nnext = \"{next}\"\n"))
+            .collect()
+    }
+
+    #[test]
+    fn sound_rings_pass() {
+        let raw = ring(&[
+            ("127.0.0.1:7000", "127.0.0.1:7001"),
+            ("127.0.0.1:7001", "127.0.0.1:7002"),
+            ("127.0.0.1:7002", "127.0.0.1:7000"),
+        ]);
+        assert!(lint_str(&raw).is_empty());
+        // A ring of one points at itself by design.
+        assert!(lint_str(&ring(&[("127.0.0.1:7000", "127.0.0.1:7000")])).is_empty());
+    }
+
+    #[test]
+    fn duplicates_and_ports_are_reported_by_line() {
+        let raw = ring(&[
+            ("127.0.0.1:7000", "127.0.0.1:5432"),
+            ("127.0.0.1:5432", "127.0.0.1:7000"),
+            ("127.0.0.1:7000", "127.0.0.1:7000"),
+        ]);
+        assert_eq!(
+            lint_str(&raw),
+            [
+                "line 5: port 5432 is PostgreSQL's well-known port",
+                "line 8: duplicate node 127.0.0.1:7000 (first at line 2)",
+            ]
+        );
+        let problems = lint_str(&ring(&[("127.0.0.1:80", "bad"), ("bad", "127.0.0.1:80")]));
+        assert!(problems.contains(&"line 2: port 80 is HTTP's well-known port".to_string()));
+        assert!(problems.contains(&"line 5: bad is not a host:port address".to_string()));
+    }
+
+    #[test]
+    fn broken_pointers_are_reported() {
+        let raw = ring(&[
+            ("127.0.0.1:7000", "127.0.0.1:7001"),
+            ("127.0.0.1:7001", "127.0.0.1:7000"),
+            ("127.0.0.1:7002", "127.0.0.1:7002"),
+            ("127.0.0.1:7003", "127.0.0.1:9999"),
+        ]);
+        assert_eq!(
+            lint_str(&raw),
+            [
+                "line 8: no node points at 127.0.0.1:7002, so nothing reaches it",
+                "line 8: 127.0.0.1:7002 is in a part of 1 node(s) disconnected from the ring at line 2",
+                "line 9: 127.0.0.1:7002 points at itself",
+                "line 11: no node points at 127.0.0.1:7003, so nothing reaches it",
+                "line 11: 127.0.0.1:7003 is in a part of 1 node(s) disconnected from the ring at line 2",
+                "line 12: next 127.0.0.1:9999 is not a node in [ring]",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_ring_is_a_problem() {
+        assert_eq!(
+            lint_str("[network]\nnodes = 3\n"),
+            ["no [[ring.nodes]] entries"]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Per-node Prometheus metrics on a secondary HTTP port (`--metrics-port`).
+//!
+//! The gateway's `/metrics` aggregates `NODE METRICS` from every node it
+//! can reach; this endpoint is for scraping each node directly, without a
+//! gateway in front. It serves the node's live counters from the shared
+//! `Node`, so a scrape always reflects current state. The HTTP side is the
+//! same hand-rolled HTTP/1.1 the gateway speaks: one request per
+//! connection, `GET /metrics` only.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::node::Node;
+use crate::protocol::Command;
+
+/// `command` label values of `ouroboros_messages_total`. Commands outside
+/// the first four count as `OTHER`.
+const COMMAND_LABELS: [&str; 5] = ["RING", "WALK", "GET", "SET_NEXT", "OTHER"];
+
+/// Upper bounds (seconds) of the `ouroboros_walk_duration_seconds`
+/// buckets. Walks end within the node's 30 s walk timeout.
+const WALK_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0];
+
+/// Longest request head the metrics server reads before giving up.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters that exist only for the metrics endpoint. The rest of what it
+/// reports (`ouroboros_ring_hops_total`) comes from the `NODE STAT`
+/// counters already on [`Node`].
+#[derive(Debug, Default)]
+pub struct NodeMetrics {
+    commands: [AtomicU64; COMMAND_LABELS.len()],
+    walk_buckets: [AtomicU64; WALK_BUCKETS.len()],
+    walk_sum_micros: AtomicU64,
+    walk_count: AtomicU64,
+    active_connections: AtomicU64,
+    partitions_detected: AtomicU64,
+    addr: OnceLock<SocketAddr>,
+}
+
+impl NodeMetrics {
+    /// Count one parsed command.
+    pub fn record_command(&self, cmd: &Command) {
+        let idx = match cmd {
+            Command::RingForward { .. } => 0,
+            Command::TopologyWalk | Command::TopologyWalkFresh => 1,
+            Command::NodeStatus => 2,
+            Command::NodeNext(_) => 3,
+            _ => 4,
+        };
+        self.commands[idx].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a completed client-started `TOPOLOGY WALK`.
+    pub fn observe_walk(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = WALK_BUCKETS.iter().position(|&le| secs <= le) {
+            self.walk_buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.walk_sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.walk_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a client connection as active until the guard drops.
+    pub fn track_connection(&self) -> ConnectionGuard<'_> {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(&self.active_connections)
+    }
+
+    pub fn active_connections(&self) -> u64 {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Count a `--probe-interval-secs` probe that did not come back.
+    pub fn record_partition(&self) {
+        self.partitions_detected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn partitions_detected(&self) -> u64 {
+        self.partitions_detected.load(Ordering::Relaxed)
+    }
+
+    /// Where the metrics server is listening, once it is.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr.get().copied()
+    }
+}
+
+/// Returned by [`NodeMetrics::track_connection`].
+pub struct ConnectionGuard<'a>(&'a AtomicU64);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// `node`'s metrics in the Prometheus text exposition format.
+pub fn render(node: &Node) -> String {
+    let m = &node.metrics;
+    let mut out = String::new();
+
+    out.push_str("# HELP ouroboros_messages_total Commands handled, by command.\n");
+    out.push_str("# TYPE ouroboros_messages_total counter\n");
+    for (label, count) in COMMAND_LABELS.iter().zip(&m.commands) {
+        out.push_str(&format!(
+            "ouroboros_messages_total{{command=\"{label}\"}} {}\n",
+            count.load(Ordering::Relaxed)
+        ));
+    }
+
+    out.push_str(
+        "# HELP ouroboros_walk_duration_seconds Duration of client-started TOPOLOGY WALKs.\n",
+    );
+    out.push_str("# TYPE ouroboros_walk_duration_seconds histogram\n");
+    let mut cumulative = 0;
+    for (le, count) in WALK_BUCKETS.iter().zip(&m.walk_buckets) {
+        cumulative += count.load(Ordering::Relaxed);
+        out.push_str(&format!(
+            "ouroboros_walk_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}\n"
+        ));
+    }
+    let walks = m.walk_count.load(Ordering::Relaxed);
+    out.push_str(&format!(
+        "ouroboros_walk_duration_seconds_bucket{{le=\"+Inf\"}} {walks}\n"
+    ));
+    out.push_str(&format!(
+        "ouroboros_walk_duration_seconds_sum {}\n",
+        m.walk_sum_micros.load(Ordering::Relaxed) as f64 / 1e6
+    ));
+    out.push_str(&format!("ouroboros_walk_duration_seconds_count {walks}\n"));
+
+    out.push_str("# HELP ouroboros_ring_hops_total Hops forwarded to a ring neighbor.\n");
+    out.push_str("# TYPE ouroboros_ring_hops_total counter\n");
+    out.push_str(&format!(
+        "ouroboros_ring_hops_total {}\n",
+        node.msgs_forwarded.load(Ordering::Relaxed)
+    ));
+
+    out.push_str("# HELP ouroboros_active_connections Open client connections.\n");
+    out.push_str("# TYPE ouroboros_active_connections gauge\n");
+    out.push_str(&format!(
+        "ouroboros_active_connections {}\n",
+        m.active_connections()
+    ));
+
+    out.push_str(
+        "# HELP ouroboros_partitions_detected_total Ring probes that did not come back.\n",
+    );
+    out.push_str("# TYPE ouroboros_partitions_detected_total counter\n");
+    out.push_str(&format!(
+        "ouroboros_partitions_detected_total {}\n",
+        m.partitions_detected()
+    ));
+    out
+}
+
+/// Bind the metrics listener for `node` on `host:port`.
+pub async fn bind_metrics(host: &str, port: u16) -> std::io::Result<TcpListener> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    TcpListener::bind((host, port)).await
+}
+
+/// Serve `GET /metrics` on `listener` until the task is dropped.
+pub async fn serve_metrics(node: Arc<Node>, listener: TcpListener) {
+    if let Ok(addr) = listener.local_addr() {
+        let _ = node.metrics.addr.set(addr);
+        tracing::info!(node = %node.port, metrics = %addr, "Metrics server listening");
+    }
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!(node = %node.port, error = ?e, "Metrics accept failed; metrics server exiting");
+                return;
+            }
+        };
+        let node = Arc::clone(&node);
+        tokio::spawn(async move {
+            if let Err(e) = handle_metrics_conn(&node, stream).await {
+                tracing::debug!(node = %node.port, peer = %peer, error = ?e, "Metrics request failed");
+            }
+        });
+    }
+}
+
+async fn handle_metrics_conn(node: &Node, mut stream: TcpStream) -> std::io::Result<()> {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await {
+        Ok(r) => r?,
+        Err(_) => return Ok(()),
+    };
+    let mut parts = head.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", render(node)),
+        ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read up to the blank line ending the request head; return the request
+/// line.
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_REQUEST_BYTES {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let head = String::from_utf8_lossy(&buf);
+    Ok(head.lines().next().unwrap_or("").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_fall_into_their_labels() {
+        let m = NodeMetrics::default();
+        m.record_command(&Command::TopologyWalk);
+        m.record_command(&Command::NodeNext("127.0.0.1:7001".into()));
+        m.record_command(&Command::NodePing);
+        m.record_command(&Command::NodePing);
+        let counts: Vec<u64> = m
+            .commands
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(counts, vec![0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn walk_histogram_buckets_are_cumulative_at_render() {
+        let m = NodeMetrics::default();
+        m.observe_walk(Duration::from_millis(3));
+        m.observe_walk(Duration::from_millis(40));
+        m.observe_walk(Duration::from_secs(60));
+        assert_eq!(m.walk_buckets[0].load(Ordering::Relaxed), 1);
+        assert_eq!(m.walk_buckets[3].load(Ordering::Relaxed), 1);
+        // Past the last bucket: only +Inf and the count see it.
+        assert_eq!(
+            m.walk_buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .sum::<u64>(),
+            2
+        );
+        assert_eq!(m.walk_count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn connection_guard_tracks_the_gauge() {
+        let m = NodeMetrics::default();
+        let a = m.track_connection();
+        let b = m.track_connection();
+        assert_eq!(m.active_connections(), 2);
+        drop(a);
+        assert_eq!(m.active_connections(), 1);
+        drop(b);
+        assert_eq!(m.active_connections(), 0);
+    }
+}
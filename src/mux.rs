@@ -0,0 +1,527 @@
+//! Several logical streams over one connection (`run --mux`).
+//!
+//! After the usual TLS / `HELLO` / `AUTH` preamble, a dialler sends `MUX`
+//! and, once the node answers `OK`, everything on the connection in
+//! either direction is a frame:
+//!
+//! ```text
+//! FRAME <stream_id> <length>\n<payload>
+//! ```
+//!
+//! A [`MuxStream`] opens [`Channel`]s, each taking the next `stream_id`
+//! when it first writes, so new ids reach the node in increasing order;
+//! the node side ([`serve`]) starts a new channel whenever a frame
+//! arrives for an id above the highest it has seen, and runs the ordinary
+//! line protocol on it. A zero-length frame closes that direction of a
+//! channel, as EOF does on a socket. Frames for channels that are gone
+//! are dropped.
+//!
+//! A channel queues at most [`CHANNEL_QUEUE_FRAMES`] incoming frames.
+//! Once a channel's queue is full the connection stops reading until
+//! that channel catches up, so a peer cannot grow a node's memory by
+//! writing to a channel nothing reads; hop forwards always read their
+//! `OK` straight away.
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+    ReadBuf,
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// The largest payload one frame carries. Longer writes are split.
+pub const MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// How many incoming frames one channel holds before the connection
+/// waits for it to be read.
+pub const CHANNEL_QUEUE_FRAMES: usize = 16;
+
+/// Longest `FRAME <stream_id> <length>` header line, newline included.
+const MAX_HEADER_BYTES: u64 = 32;
+
+type Frame = (u32, Vec<u8>);
+
+/// What a connection's channels and its driver share.
+struct Shared {
+    /// Where each open channel's incoming payloads go.
+    routes: Mutex<HashMap<u32, mpsc::Sender<Vec<u8>>>>,
+    /// Frames waiting for the driver to write them.
+    outbound: mpsc::UnboundedSender<Frame>,
+    /// The id the next channel opened on this side takes.
+    next_id: Mutex<u32>,
+    /// Set once the driver has stopped.
+    closed: AtomicBool,
+}
+
+impl Shared {
+    fn new(outbound: mpsc::UnboundedSender<Frame>) -> Arc<Self> {
+        Arc::new(Self {
+            routes: Mutex::new(HashMap::new()),
+            outbound,
+            next_id: Mutex::new(1),
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    /// A channel the peer opened as `id`.
+    fn channel(self: &Arc<Self>, id: u32) -> Channel {
+        let (tx, rx) = mpsc::channel(CHANNEL_QUEUE_FRAMES);
+        self.routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, tx);
+        Channel {
+            id: Some(id),
+            unrouted: None,
+            shared: Arc::clone(self),
+            incoming: rx,
+            buf: Vec::new(),
+            pos: 0,
+            fin_sent: false,
+        }
+    }
+
+    /// Drop every route, so each channel reads EOF.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// The dialling side of a multiplexed connection. Dropping it closes the
+/// connection, and with it every channel still open.
+pub struct MuxStream {
+    shared: Arc<Shared>,
+    driver: JoinHandle<()>,
+}
+
+impl MuxStream {
+    /// Take over `stream`, which must already be past `MUX` / `OK`.
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let shared = Shared::new(tx);
+        let (reader, writer) = tokio::io::split(stream);
+        let driver = tokio::spawn({
+            let shared = Arc::clone(&shared);
+            async move {
+                if let Err(e) = drive(BufReader::new(reader), writer, &shared, rx, None).await {
+                    tracing::debug!(error = ?e, "Mux connection failed");
+                }
+            }
+        });
+        Self { shared, driver }
+    }
+
+    /// Start a new logical stream. Fails once the connection has closed.
+    pub fn open(&self) -> io::Result<Channel> {
+        if self.is_closed() {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        let (tx, rx) = mpsc::channel(CHANNEL_QUEUE_FRAMES);
+        Ok(Channel {
+            id: None,
+            unrouted: Some(tx),
+            shared: Arc::clone(&self.shared),
+            incoming: rx,
+            buf: Vec::new(),
+            pos: 0,
+            fin_sent: false,
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
+
+/// Serve the node side of a connection that has just answered `MUX`:
+/// hand each new channel to `accepted` and carry frames until the peer
+/// closes the connection.
+pub async fn serve<R, W>(
+    reader: R,
+    writer: W,
+    accepted: mpsc::UnboundedSender<Channel>,
+) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    let shared = Shared::new(tx);
+    drive(reader, writer, &shared, rx, Some(accepted)).await
+}
+
+/// Move frames between the connection and its channels until either
+/// direction fails or the peer closes the connection.
+async fn drive<R, W>(
+    mut reader: R,
+    mut writer: W,
+    shared: &Arc<Shared>,
+    mut outbound: mpsc::UnboundedReceiver<Frame>,
+    accepted: Option<mpsc::UnboundedSender<Channel>>,
+) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let read = async {
+        // Ids at or below this one were opened before; frames for them
+        // never start a new channel.
+        let mut highest = 0u32;
+        while let Some((id, payload)) = read_frame(&mut reader).await? {
+            if payload.is_empty() {
+                // The peer is done writing to this channel.
+                shared
+                    .routes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&id);
+                continue;
+            }
+            let route = shared
+                .routes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&id)
+                .cloned();
+            if let Some(tx) = route {
+                // Waits while the channel's queue is full.
+                let _ = tx.send(payload).await;
+                continue;
+            }
+            match &accepted {
+                Some(accepted) if id > highest => {
+                    highest = id;
+                    let channel = shared.channel(id);
+                    let _ = channel.deliver(payload);
+                    if accepted.send(channel).is_err() {
+                        return Err(io::Error::other("no one accepts mux channels"));
+                    }
+                }
+                _ => tracing::trace!(stream_id = id, "Dropping frame for a closed channel"),
+            }
+        }
+        Ok(())
+    };
+    let write = async {
+        while let Some((id, payload)) = outbound.recv().await {
+            write_frame(&mut writer, id, &payload).await?;
+        }
+        Ok(())
+    };
+    let res = tokio::select! {
+        r = read => r,
+        r = write => r,
+    };
+    shared.close();
+    res
+}
+
+/// Read one frame; `None` at a clean EOF between frames.
+pub async fn read_frame<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<Frame>> {
+    let mut header = String::new();
+    if (&mut *reader)
+        .take(MAX_HEADER_BYTES)
+        .read_line(&mut header)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    let bad = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad frame header {header:?}"),
+        )
+    };
+    let Some(fields) = header
+        .strip_suffix('\n')
+        .and_then(|h| h.strip_prefix("FRAME "))
+    else {
+        return Err(bad());
+    };
+    let (id, len) = fields.split_once(' ').ok_or_else(bad)?;
+    let id: u32 = id.parse().map_err(|_| bad())?;
+    let len: usize = len.parse().map_err(|_| bad())?;
+    if len > MAX_FRAME_BYTES {
+        return Err(bad());
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some((id, payload)))
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    id: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = format!("FRAME {id} {}\n", payload.len()).into_bytes();
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+/// One logical stream of a multiplexed connection.
+pub struct Channel {
+    /// Taken on the first write by a channel this side opened.
+    id: Option<u32>,
+    /// The route for incoming payloads, until `id` is taken.
+    unrouted: Option<mpsc::Sender<Vec<u8>>>,
+    shared: Arc<Shared>,
+    incoming: mpsc::Receiver<Vec<u8>>,
+    /// The payload being read, and how far into it.
+    buf: Vec<u8>,
+    pos: usize,
+    fin_sent: bool,
+}
+
+impl Channel {
+    /// `None` for a channel this side opened that has not written yet.
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    /// Queue `payload` for this channel's reader, as if it had just
+    /// arrived. False once the peer has closed the channel, or if the
+    /// queue is full.
+    fn deliver(&self, payload: Vec<u8>) -> bool {
+        let Some(id) = self.id else {
+            return false;
+        };
+        match self
+            .shared
+            .routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+        {
+            Some(tx) => tx.try_send(payload).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Queue one frame. The first from a channel this side opened takes
+    /// the next id, under the lock so that ids go out in order.
+    fn send(&mut self, payload: Vec<u8>) -> io::Result<()> {
+        let sent = match self.id {
+            Some(id) => self.shared.outbound.send((id, payload)),
+            None => {
+                let mut next_id = self
+                    .shared
+                    .next_id
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                let id = *next_id;
+                *next_id += 1;
+                self.id = Some(id);
+                if let Some(tx) = self.unrouted.take() {
+                    self.shared
+                        .routes
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(id, tx);
+                }
+                self.shared.outbound.send((id, payload))
+            }
+        };
+        sent.map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn send_fin(&mut self) {
+        if !self.fin_sent {
+            self.fin_sent = true;
+            // A channel that never wrote was never seen by the peer.
+            if let Some(id) = self.id {
+                let _ = self.shared.outbound.send((id, Vec::new()));
+            }
+        }
+    }
+}
+
+impl AsyncRead for Channel {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos == this.buf.len() {
+            match this.incoming.poll_recv(cx) {
+                Poll::Ready(Some(payload)) => {
+                    this.buf = payload;
+                    this.pos = 0;
+                }
+                // Closed by the peer, or the connection is gone: EOF.
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = out.remaining().min(this.buf.len() - this.pos);
+        out.put_slice(&this.buf[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Channel {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if data.is_empty() {
+            // An empty frame would read as a close.
+            return Poll::Ready(Ok(0));
+        }
+        if self.fin_sent {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let n = data.len().min(MAX_FRAME_BYTES);
+        Poll::Ready(self.get_mut().send(data[..n].to_vec()).map(|()| n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().send_fin();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.send_fin();
+        if let Some(id) = self.id {
+            self.shared
+                .routes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, 7, b"NODE PING\n").await.unwrap();
+        write_frame(&mut wire, 8, b"").await.unwrap();
+        assert_eq!(wire, b"FRAME 7 10\nNODE PING\nFRAME 8 0\n");
+        let mut reader = &wire[..];
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Some((7, b"NODE PING\n".to_vec()))
+        );
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Some((8, Vec::new()))
+        );
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+        for bad in [
+            "FRAME 1\n",
+            "FRAME x 1\nA",
+            "DATA 1 1\nA",
+            "FRAME 1 99999999\n",
+        ] {
+            assert!(read_frame(&mut bad.as_bytes()).await.is_err(), "{bad:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn channels_are_independent_streams() {
+        let (dialler, node) = tokio::io::duplex(1024);
+        let mux = MuxStream::new(dialler);
+        let (accepted_tx, mut accepted) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (r, w) = tokio::io::split(node);
+            serve(BufReader::new(r), w, accepted_tx).await
+        });
+        // Echo every accepted channel back, upper-cased.
+        tokio::spawn(async move {
+            while let Some(channel) = accepted.recv().await {
+                tokio::spawn(async move {
+                    let (r, mut w) = tokio::io::split(channel);
+                    let mut lines = BufReader::new(r).lines();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        w.write_all(format!("{}\n", line.to_uppercase()).as_bytes())
+                            .await
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut a = BufReader::new(mux.open().unwrap());
+        let mut b = BufReader::new(mux.open().unwrap());
+        // Opened first, written second: ids follow the writes.
+        b.get_mut().write_all(b"second\n").await.unwrap();
+        a.get_mut().write_all(b"first\n").await.unwrap();
+        assert_eq!((a.get_ref().id(), b.get_ref().id()), (Some(2), Some(1)));
+        let mut line = String::new();
+        a.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "FIRST\n");
+        line.clear();
+        b.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "SECOND\n");
+
+        // Closing one channel leaves the other working.
+        drop(a);
+        b.get_mut().write_all(b"again\n").await.unwrap();
+        line.clear();
+        b.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "AGAIN\n");
+        assert!(!mux.is_closed());
+    }
+
+    #[tokio::test]
+    async fn a_full_channel_holds_up_the_connection() {
+        let (dialler, node) = tokio::io::duplex(1 << 20);
+        let (accepted_tx, mut accepted) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (r, w) = tokio::io::split(node);
+            serve(BufReader::new(r), w, accepted_tx).await
+        });
+        let (_r, mut w) = tokio::io::split(dialler);
+        // One frame more than channel 1 can queue, then a new channel.
+        for _ in 0..=CHANNEL_QUEUE_FRAMES {
+            write_frame(&mut w, 1, b"x").await.unwrap();
+        }
+        write_frame(&mut w, 2, b"y").await.unwrap();
+        let mut first = accepted.recv().await.unwrap();
+        let early =
+            tokio::time::timeout(std::time::Duration::from_millis(100), accepted.recv()).await;
+        assert!(early.is_err(), "channel 2 opened past a full channel 1");
+
+        // Reading one frame makes room, and the connection moves on.
+        let mut byte = [0; 1];
+        first.read_exact(&mut byte).await.unwrap();
+        let second = accepted.recv().await.unwrap();
+        assert_eq!(second.id(), Some(2));
+    }
+}
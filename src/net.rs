@@ -0,0 +1,173 @@
+//! Name resolution for ring addresses.
+//!
+//! A `NODE NEXT` target given by hostname (`node-1.ring:7000` in Docker or
+//! Kubernetes) would otherwise be re-resolved on every dial, with a lookup
+//! failure surfacing only as a failed forward much later. The node
+//! resolves it once, when the pointer is set, and stores the `ip:port` that
+//! actually answered.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, lookup_host};
+
+use crate::transport::{TlsConfig, unix_path};
+
+/// How long each resolved address gets to accept a connection.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Resolve `addr` and return the first resolved address that accepts a
+/// TCP connection. IP literals are returned as-is without being dialled;
+/// `unix:` addresses are not resolvable and are an error.
+pub async fn resolve_and_verify(addr: &str) -> io::Result<SocketAddr> {
+    if let Ok(sa) = addr.parse::<SocketAddr>() {
+        return Ok(sa);
+    }
+    if unix_path(addr).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{addr} is not a host:port address"),
+        ));
+    }
+
+    let candidates: Vec<SocketAddr> = lookup_host(addr).await?.collect();
+    if candidates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{addr} resolved to no addresses"),
+        ));
+    }
+    for sa in &candidates {
+        if let Ok(Ok(_)) = tokio::time::timeout(VERIFY_TIMEOUT, TcpStream::connect(sa)).await {
+            return Ok(*sa);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!("no resolved address of {addr} is reachable: {candidates:?}"),
+    ))
+}
+
+/// Check that `addr` could name a peer at all: an `ip:port`, a `unix:`
+/// path, or a `host:port` whose host is a plausible DNS name. Port 0 and
+/// dotted numbers that are not an IP (`999.999.999.999:7000`) are refused.
+pub fn check_peer_addr(addr: &str) -> Result<(), String> {
+    let invalid = || Err(format!("invalid address: {addr}"));
+    if unix_path(addr).is_some() {
+        return Ok(());
+    }
+    if let Ok(sa) = addr.parse::<SocketAddr>() {
+        return if sa.port() == 0 { invalid() } else { Ok(()) };
+    }
+    let Some((host, port)) = addr.rsplit_once(':') else {
+        return invalid();
+    };
+    let hostname = host.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    let numeric = host.chars().all(|c| c.is_ascii_digit() || c == '.');
+    match port.parse::<u16>() {
+        Ok(port) if port != 0 && hostname && !numeric => Ok(()),
+        _ => invalid(),
+    }
+}
+
+/// Whether `addr` accepts a connection within [`VERIFY_TIMEOUT`]. IP
+/// literals are dialled too, unlike [`resolve_and_verify`].
+pub async fn probe(addr: &str) -> io::Result<()> {
+    match tokio::time::timeout(VERIFY_TIMEOUT, TlsConfig::disabled().connect(addr)).await {
+        Ok(res) => res.map(drop),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{addr} did not accept a connection in {VERIFY_TIMEOUT:?}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn ip_literal_passes_through_undialled() {
+        // Nothing listens on port 1; a literal is still returned.
+        let sa = resolve_and_verify("127.0.0.1:1").await.unwrap();
+        assert_eq!(sa, "127.0.0.1:1".parse().unwrap());
+        let sa = resolve_and_verify("[::1]:7000").await.unwrap();
+        assert_eq!(sa, "[::1]:7000".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn hostname_resolves_to_the_listening_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sa = resolve_and_verify(&format!("localhost:{port}"))
+            .await
+            .unwrap();
+        assert_eq!(sa, listener.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn unreachable_or_unresolvable_errs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        assert!(
+            resolve_and_verify(&format!("localhost:{port}"))
+                .await
+                .is_err()
+        );
+        assert!(
+            resolve_and_verify("no-such-host.invalid:7000")
+                .await
+                .is_err()
+        );
+        assert!(resolve_and_verify("unix:/tmp/n0.sock").await.is_err());
+    }
+
+    #[test]
+    fn peer_addrs_must_look_routable() {
+        for ok in [
+            "127.0.0.1:7001",
+            "[::1]:7001",
+            "localhost:7001",
+            "node-1.ring:7000",
+            "unix:/tmp/n0.sock",
+        ] {
+            assert_eq!(check_peer_addr(ok), Ok(()), "{ok}");
+        }
+        for bad in [
+            "hello",
+            "999.999.999.999:0",
+            "999.999.999.999:7000",
+            "127.0.0.1:0",
+            "localhost:",
+            "localhost:99999",
+            ":7000",
+            "bad_host:7000",
+            "-node:7000",
+            "::1:7000",
+        ] {
+            assert_eq!(
+                check_peer_addr(bad),
+                Err(format!("invalid address: {bad}")),
+                "{bad}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_dials_even_ip_literals() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        probe(&addr).await.unwrap();
+        drop(listener);
+        assert!(probe(&addr).await.is_err());
+    }
+}
@@ -1,19 +1,44 @@
 use crate::NodeStatus;
+use crate::announce::{Announcement, DEFAULT_ANNOUNCE_INTERVAL};
 use crate::auth::AuthToken;
-use serde::Serialize;
+use crate::chaos::{ChaosConfig, ChaosMode};
+use crate::chring::{ConsistentHashRing, RoutingMode};
+use crate::client::{ClientOpts, RingClient};
+use crate::codec::{compress_field, expansion_limit};
+use crate::dedup::{DEFAULT_DEDUP_TTL, DedupCache};
+use crate::error::RingError;
+use crate::events::{EventKind, SUBSCRIBER_QUEUE, format_event};
+use crate::heartbeat::{DEFAULT_HEARTBEAT_INTERVAL, Member};
+use crate::io::LimitedLineReader;
+use crate::metrics::NodeMetrics;
+use crate::mux::{Channel, MuxStream};
+use crate::pool::{ConnectionPool, DEFAULT_POOL_IDLE_TIMEOUT, MuxPool};
+use crate::protocol::{
+    Command, MIN_SUPPORTED_VERSION, Probability, expand_payload, negotiate_version, now_unix_ms,
+};
+use crate::ratelimit::SharedLimiter;
+use crate::replay::ReplayLog;
+use crate::snapshot::{NodeSnapshot, SNAPSHOT_VERSION};
+use crate::state::{NodeState, load_state, save_state};
+use crate::trace::{OtlpEndpoint, Tracer};
+use crate::transport::{Stream, TlsConfig};
+use crate::util::{BackoffConfig, backoff_sleep};
+use crate::vclock::VectorClock;
+use crate::walk_registry::WalkRegistry;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     path::PathBuf,
     sync::{
         Arc,
         atomic::{AtomicBool, AtomicU64, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
-    io::AsyncWriteExt,
-    net::TcpStream,
-    sync::{RwLock, oneshot},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::{Mutex, Notify, RwLock, mpsc, oneshot, watch},
 };
 use tracing;
 
@@ -26,7 +51,8 @@ use tracing;
 ///   still be lost.
 /// - `Full`: `fsync(file)` plus `fsync(dir)` after each rename, so the
 ///   directory entry is also durably committed. Production default.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FsyncMode {
     None,
     Data,
@@ -62,9 +88,432 @@ pub struct FileTag {
     pub parts: u32,
 }
 
+/// Per-node knobs that postdate the positional `Node::new` / `bind`
+/// argument list. Every field defaults to "off", so `NodeOpts::default()`
+/// reproduces the behaviour of a node built with the positional API.
+#[derive(Clone, Debug, Default)]
+pub struct NodeOpts {
+    /// TLS for the listener and for every outbound ring connection.
+    pub tls: TlsConfig,
+
+    /// Human-readable label (`--name`). Empty means unnamed; walk edges
+    /// then carry the bare port exactly as before.
+    pub name: String,
+
+    /// JSON file the NEXT/PREV pointers are persisted to (`--state-file`);
+    /// see [`crate::state`]. `None` keeps them in memory only.
+    pub state_file: Option<PathBuf>,
+
+    /// How long the start node of a `RING ACK` waits for the seen list.
+    /// Zero means [`DEFAULT_RING_ACK_TIMEOUT`].
+    pub ring_ack_timeout: Duration,
+
+    /// Idle outbound connections kept per peer for hop forwards
+    /// (`--pool-size`); see [`crate::pool`]. Zero opens a fresh connection
+    /// per forward.
+    pub pool_size: usize,
+
+    /// Send hop forwards as channels of one multiplexed connection per
+    /// peer (`--mux`) instead of through the pool; see [`crate::mux`].
+    /// Also the only way a node accepts `MUX` connections.
+    pub mux: bool,
+
+    /// Speak the `HELLO` version greeting (`--hello`): send it on every
+    /// accepted connection and require a compatible one from every peer
+    /// dialled. All nodes in a ring must agree on this flag.
+    pub hello: bool,
+
+    /// Longest protocol line accepted from a client or read back from a
+    /// peer, newline included (`--max-line-bytes`); see [`crate::io`].
+    /// Zero disables the limit.
+    pub max_line_bytes: usize,
+
+    /// Shell command answering `TOPOLOGY PAY-WALK` keys the node has no
+    /// built-in value for (`--walk-payload-cmd`). Run as `sh -c <cmd> sh
+    /// <key>`; the first line of its stdout is the value.
+    pub walk_payload_cmd: Option<String>,
+
+    /// Store `NODE NEXT` hostnames verbatim instead of resolving them to
+    /// the `ip:port` that answers (`--no-dns-resolve`); see [`crate::net`].
+    pub no_dns_resolve: bool,
+
+    /// Refuse a `NODE NEXT` target that does not accept a TCP connection
+    /// within a second (`--verify-next-reachable`).
+    pub verify_next_reachable: bool,
+
+    /// Leave Nagle's algorithm on for ring connections (`--no-nodelay`).
+    /// By default every accepted and outbound TCP connection sets
+    /// `TCP_NODELAY`, so a one-line command is sent at once instead of
+    /// waiting on the peer's delayed ACK.
+    pub no_nodelay: bool,
+
+    /// Consecutive failed forwards to one peer before its circuit opens
+    /// (`--cb-failure-threshold`); see [`CircuitBreaker`]. Zero disables
+    /// the breaker.
+    pub cb_failure_threshold: u32,
+
+    /// How long an open circuit fails fast before letting one probe
+    /// through (`--cb-reset-timeout-secs`). Zero means
+    /// [`DEFAULT_CB_RESET_TIMEOUT`].
+    pub cb_reset_timeout: Duration,
+
+    /// Commands per second each client connection may send
+    /// (`--rate-limit-rps`); see [`crate::ratelimit`]. Zero disables the
+    /// limit.
+    pub rate_limit_rps: u32,
+
+    /// Set `SO_REUSEPORT` on the TCP listener (`--reuse-port`), so a
+    /// replacement process can bind while the old one is still draining.
+    /// `SO_REUSEADDR` is always set. Ignored off Unix.
+    pub reuse_port: bool,
+
+    /// Length of the TCP listener's accept queue (`--bind-backlog`).
+    /// Zero means [`DEFAULT_BIND_BACKLOG`].
+    pub bind_backlog: u32,
+
+    /// `SO_RCVBUF` for the TCP listener, inherited by the connections it
+    /// accepts (`--recv-buf-size`). Zero keeps the OS default.
+    pub recv_buf_size: u32,
+
+    /// `SO_SNDBUF` for the TCP listener, inherited by the connections it
+    /// accepts (`--send-buf-size`). Zero keeps the OS default.
+    pub send_buf_size: u32,
+
+    /// Longest key `KV SET` accepts, in bytes (`--kv-max-key-bytes`).
+    /// Zero means [`DEFAULT_KV_MAX_KEY_BYTES`].
+    pub kv_max_key_bytes: usize,
+
+    /// Longest value `KV SET` accepts, in bytes (`--kv-max-value-bytes`).
+    /// Zero means [`DEFAULT_KV_MAX_VALUE_BYTES`].
+    pub kv_max_value_bytes: usize,
+
+    /// Serve Prometheus `/metrics` over HTTP on this port of the node's
+    /// host (`--metrics-port`); see [`crate::metrics`]. `Some(0)` binds an
+    /// ephemeral port. `None` disables it.
+    pub metrics_port: Option<u16>,
+
+    /// Record `RING FORWARD` / `TOPOLOGY` spans and export them here
+    /// (`--otlp-endpoint`); see [`crate::trace`]. `None` records nothing.
+    pub otlp_endpoint: Option<OtlpEndpoint>,
+
+    /// `RING FORWARD` lines remembered to drop repeats
+    /// (`--dedup-cache-size`); see [`crate::dedup`]. Zero disables.
+    pub dedup_cache_size: usize,
+
+    /// How long a remembered line counts as a repeat (`--dedup-ttl-secs`).
+    /// Zero means [`DEFAULT_DEDUP_TTL`].
+    pub dedup_ttl: Duration,
+
+    /// Where to send `PROBE <next>` when a `RING FORWARD` to NEXT fails
+    /// (`--repair-ring --repair-coordinator`); see [`crate::repair`].
+    /// `None` only logs the failure.
+    pub repair_coordinator: Option<String>,
+
+    /// `RING` messages and walk payloads longer than this many bytes are
+    /// sent compressed (`--compress-threshold-bytes`); see
+    /// [`crate::codec`]. Zero disables.
+    pub compress_threshold_bytes: usize,
+
+    /// How long a new connection may take to send its first line, after
+    /// any AUTH (`--accept-timeout-ms`); later lines get `idle_timeout`.
+    /// Zero means `idle_timeout` for the first line too.
+    pub accept_timeout: Duration,
+
+    /// Largest TTL a `RING` command may carry (`--max-ring-ttl`); one
+    /// above it gets `ERR ttl exceeds max <n>` and goes nowhere. Zero
+    /// disables the cap.
+    pub max_ring_ttl: u32,
+
+    /// Most hops a forwarded `RING FORWARD` may have taken
+    /// (`--max-ring-hops`); one that arrives with more is dropped and
+    /// reported as a `RING_STORM`. Zero disables the check.
+    pub max_ring_hops: u32,
+
+    /// Most edges a `TOPOLOGY WALK` reports before the hop that reaches
+    /// the limit ends it early, and the cap on `WALK-MAX`'s own limit
+    /// (`--max-walk-hops`). Zero disables the cap.
+    pub max_walk_hops: u32,
+
+    /// Most nodes a chain of nested `NODE FORWARD-TO`s may pass through
+    /// (`--forward-max-hops`); a longer chain is refused. Zero disables
+    /// the check.
+    pub forward_max_hops: u32,
+
+    /// TTL `RING ALL` sends with before this node has counted the ring
+    /// (`--default-ring-ttl`). Zero means [`DEFAULT_RING_TTL`].
+    pub default_ring_ttl: u32,
+
+    /// File every handled `RING FORWARD` is appended to as a JSON line
+    /// (`--replay-log`); see [`crate::replay`]. `None` logs nothing.
+    pub replay_log: Option<PathBuf>,
+
+    /// How often to send a `TOPOLOGY PROBE-HOP` round the ring
+    /// (`--probe-interval-secs`); one not back within the interval is a
+    /// detected partition. Zero disables.
+    pub probe_interval: Duration,
+
+    /// How long a client `TOPOLOGY WALK` result is served from the cache
+    /// instead of walking again (`--walk-cache-ttl-secs`). Zero disables.
+    pub walk_cache_ttl: Duration,
+
+    /// Heartbeat coordinator to send `HEARTBEAT` to (`--heartbeat-addr`);
+    /// see [`crate::heartbeat`]. `None` sends nothing.
+    pub heartbeat_addr: Option<String>,
+
+    /// How often to send it (`--heartbeat-interval-secs`). Zero means
+    /// [`DEFAULT_HEARTBEAT_INTERVAL`].
+    pub heartbeat_interval: Duration,
+
+    /// How long `TOPOLOGY WALK-PARALLEL` waits for the members to answer
+    /// before it reports what it has as partial (`--parallel-walk-timeout-ms`).
+    /// Zero means [`DEFAULT_PARALLEL_WALK_TIMEOUT`].
+    pub parallel_walk_timeout: Duration,
+
+    /// Multicast group to send `ANNOUNCE` datagrams to (`--announce`); see
+    /// [`crate::announce`]. `None` sends nothing.
+    pub announce: Option<std::net::SocketAddr>,
+
+    /// How often to send them (`--announce-interval-secs`). Zero means
+    /// [`DEFAULT_ANNOUNCE_INTERVAL`].
+    pub announce_interval: Duration,
+
+    /// How often to count the ring with a walk and report a change in its
+    /// size (`--monitor-topology --monitor-interval-secs`). Zero disables.
+    pub monitor_interval: Duration,
+
+    /// How often to run `SPLIT-BRAIN CHECK` from this node and log an
+    /// error when the ring has more than one leader
+    /// (`--split-brain-check-interval-secs`). Zero disables.
+    pub split_brain_check_interval: Duration,
+
+    /// How `RING KEY` is routed (`--routing`); see [`crate::chring`].
+    pub routing: RoutingMode,
+
+    /// Commands to send the node before it accepts any connection
+    /// (`--script`); see [`crate::script`]. Only [`crate::server::run`]
+    /// reads it.
+    pub startup_script: Option<PathBuf>,
+
+    /// Carry on past a `--script` command that fails
+    /// (`--continue-on-error`) instead of refusing to start.
+    pub script_continue_on_error: bool,
+}
+
+/// Called with a `RING REQ` message on every node it reaches; `Some`
+/// payload is sent to the request's callback address as `RING RESP`.
+pub type RingReqHook = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// A channel of an accepted `MUX` connection, with that connection's
+/// rate limiter.
+pub type MuxChannel = (Channel, Option<SharedLimiter>);
+
+pub use crate::walk_registry::{WalkAborted, WalkResult};
+
+/// The ring `NODE NEXT`, `RING FORWARD` and every walk follow. Other
+/// rings are wired with `NODE NEXT-RING` and used by `RING ON` and
+/// `TOPOLOGY RING-WALK`.
+pub const DEFAULT_RING: &str = "default";
+
+/// The line that carries a forwarded message one hop on `ring`, without
+/// its newline: `RING FORWARD` on [`DEFAULT_RING`], `RING ON` elsewhere.
+pub fn ring_forward_line(ring: &str, ttl: u32, msg: &str) -> String {
+    if ring == DEFAULT_RING {
+        format!("RING FORWARD {ttl} {msg}")
+    } else {
+        format!("RING ON {ring} {ttl} {msg}")
+    }
+}
+
+/// Connect attempts for a `RING FORWARD` hop whose neighbor refuses the
+/// connection, spaced by [`BackoffConfig::default`].
+const RING_FORWARD_ATTEMPTS: u32 = 3;
+
+/// Fallback for [`NodeOpts::ring_ack_timeout`]; matches the walk timeout.
+pub const DEFAULT_RING_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fallback for [`NodeOpts::default_ring_ttl`].
+pub const DEFAULT_RING_TTL: u32 = 64;
+
+/// How long `BARRIER WAIT` blocks before giving up; matches the walk
+/// timeout.
+pub const BARRIER_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fallback for [`NodeOpts::kv_max_key_bytes`].
+pub const DEFAULT_KV_MAX_KEY_BYTES: usize = 256;
+
+/// Accept queue length when [`NodeOpts::bind_backlog`] is zero.
+pub const DEFAULT_BIND_BACKLOG: u32 = 128;
+
+/// Fallback for [`NodeOpts::kv_max_value_bytes`].
+pub const DEFAULT_KV_MAX_VALUE_BYTES: usize = 4096;
+
+/// Default `--cb-failure-threshold`.
+pub const DEFAULT_CB_FAILURE_THRESHOLD: u32 = 5;
+
+/// Default and fallback for [`NodeOpts::cb_reset_timeout`].
+pub const DEFAULT_CB_RESET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default and fallback for [`NodeOpts::parallel_walk_timeout`].
+pub const DEFAULT_PARALLEL_WALK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of one peer's circuit, as reported by `NODE CIRCUIT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Forwards go through.
+    Closed,
+    /// Forwards fail immediately without connecting.
+    Open,
+    /// The reset timeout has passed; the next forward is a probe whose
+    /// outcome closes or re-opens the circuit.
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CircuitState::Closed => "CLOSED",
+            CircuitState::Open => "OPEN",
+            CircuitState::HalfOpen => "HALF-OPEN",
+        })
+    }
+}
+
+/// What a node knows of its part in the last election, as `NODE STATUS`
+/// reports it on its `ROLE` line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeRole {
+    /// No election has reached the node.
+    #[default]
+    Unknown,
+    /// The last `ELECT` chose this node.
+    Leader,
+    /// The last `ELECT` chose another node.
+    Follower,
+    /// The node has started an `ELECT` that has not finished.
+    Candidate,
+}
+
+impl std::fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NodeRole::Unknown => "UNKNOWN",
+            NodeRole::Leader => "LEADER",
+            NodeRole::Follower => "FOLLOWER",
+            NodeRole::Candidate => "CANDIDATE",
+        })
+    }
+}
+
+impl std::str::FromStr for NodeRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "UNKNOWN" => Ok(NodeRole::Unknown),
+            "LEADER" => Ok(NodeRole::Leader),
+            "FOLLOWER" => Ok(NodeRole::Follower),
+            "CANDIDATE" => Ok(NodeRole::Candidate),
+            _ => Err(format!("unknown role '{s}'")),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PeerCircuit {
+    failures: u32,
+    opened_at: Option<Instant>,
+    /// A half-open probe is in flight; everything else still fails fast.
+    probing: bool,
+}
+
+/// Per-peer circuit breaker for hop forwards. After `threshold`
+/// consecutive failures to a peer its circuit opens and forwards to it
+/// fail at once instead of each waiting out a connect; after
+/// `reset_timeout` one forward is let through as a probe.
+pub struct CircuitBreaker {
+    threshold: u32,
+    reset_timeout: Duration,
+    peers: Mutex<HashMap<String, PeerCircuit>>,
+}
+
+impl CircuitBreaker {
+    /// `threshold == 0` disables the breaker: every forward is allowed.
+    pub fn new(threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            threshold,
+            reset_timeout,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a forward to `addr` may go ahead. Claims the probe when the
+    /// circuit is due for one.
+    pub async fn allow(&self, addr: &str) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+        let mut peers = self.peers.lock().await;
+        let Some(peer) = peers.get_mut(addr) else {
+            return true;
+        };
+        match peer.opened_at {
+            None => true,
+            Some(at) if !peer.probing && at.elapsed() >= self.reset_timeout => {
+                peer.probing = true;
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Record the outcome of a forward that [`CircuitBreaker::allow`] let
+    /// through. Returns true when this failure opened the circuit.
+    pub async fn record(&self, addr: &str, ok: bool) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let mut peers = self.peers.lock().await;
+        if ok {
+            peers.remove(addr);
+            return false;
+        }
+        let peer = peers.entry(addr.to_string()).or_default();
+        peer.failures = peer.failures.saturating_add(1);
+        let was_open = peer.opened_at.is_some();
+        if peer.probing || peer.failures >= self.threshold {
+            peer.opened_at = Some(Instant::now());
+        }
+        peer.probing = false;
+        !was_open && peer.opened_at.is_some()
+    }
+
+    /// Every peer with a failure on record, sorted by address:
+    /// `(addr, state, consecutive failures)`.
+    pub async fn snapshot(&self) -> Vec<(String, CircuitState, u32)> {
+        let peers = self.peers.lock().await;
+        let mut out: Vec<_> = peers
+            .iter()
+            .map(|(addr, p)| {
+                let state = match p.opened_at {
+                    None => CircuitState::Closed,
+                    Some(_) if p.probing => CircuitState::HalfOpen,
+                    Some(at) if at.elapsed() >= self.reset_timeout => CircuitState::HalfOpen,
+                    Some(_) => CircuitState::Open,
+                };
+                (addr.clone(), state, p.failures)
+            })
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+}
+
 /// Shared node state & actions.
 ///
-/// - `next_port`: configured next hop (if any).
+/// - `next_ports`: configured next hop per ring (if any).
+/// - `prev_port`: configured previous hop (if any); only used by the
+///   reverse walk, so a ring wired with NEXT alone keeps working.
 /// - WALK uses a token->oneshot table at the start node.
 /// - FILE push also uses token->oneshot at the start node (to confirm loop).
 ///
@@ -76,16 +525,40 @@ pub struct Node {
     /// Where this node is listening
     pub port: String,
 
-    /// Address of the next node in the ring, one until set via NODE NEXT
-    pub next_port: RwLock<Option<String>>,
+    /// Address of the next node on each ring this node is part of, keyed
+    /// by ring name. [`DEFAULT_RING`]'s entry is set via NODE NEXT, the
+    /// others via NODE NEXT-RING; none until set.
+    pub next_ports: RwLock<HashMap<String, Option<String>>>,
+
+    /// Every next hop set by NODE NEXT-MULTI; the first is also the
+    /// default ring's NEXT. Empty unless the node fans out; NODE NEXT
+    /// clears it.
+    pub next_multi: RwLock<Vec<String>>,
 
-    // WALK pending acks (start node only)
-    pending_walks: RwLock<HashMap<String, oneshot::Sender<String>>>,
+    /// Address of the previous node in the ring, none until set via NODE PREV
+    pub prev_port: RwLock<Option<String>>,
+
+    // Serializes state-file saves so two pointer updates can't race on
+    // the shared `.tmp` file.
+    state_lock: Mutex<()>,
+
+    /// Walks this node started that are waiting for their DONE.
+    pub walks: WalkRegistry,
     walk_counter: AtomicU64,
 
+    // Latest `--probe-interval-secs` round started (start node only)
+    probe_round: AtomicU64,
+
+    // Last client walk's history and when it finished; cleared when NEXT
+    // changes (`--walk-cache-ttl-secs`)
+    walk_cache: RwLock<Option<(Instant, String)>>,
+
     // HEAL pending acks (start node only)
     pending_heals: RwLock<HashMap<String, oneshot::Sender<()>>>,
 
+    // GATHER STREAM entries on their way to the client (start node only)
+    gather_streams: RwLock<HashMap<String, mpsc::UnboundedSender<String>>>,
+
     /// Status of all nodes on the network
     network_nodes: RwLock<HashMap<String, NodeStatus>>,
 
@@ -101,6 +574,9 @@ pub struct Node {
     /// Map of `port -> next_port` for the entire ring
     pub topology_map: RwLock<HashMap<String, String>>,
 
+    /// Map of `port -> name` learned from labelled walk edges
+    pub node_names: RwLock<HashMap<String, String>>,
+
     /// Filesystem root under which `<port>/content/` and `<port>/backup/` live.
     /// Binary defaults to `PathBuf::from("nodes")`; tests pass a tempdir.
     pub storage_root: PathBuf,
@@ -125,6 +601,52 @@ pub struct Node {
     /// `ERR server busy\n` and a prompt close. Zero disables.
     pub max_conns: u32,
 
+    /// Grouped optional knobs; see [`NodeOpts`].
+    pub opts: NodeOpts,
+
+    /// Outbound hop connections, sized by `opts.pool_size`.
+    pub pool: ConnectionPool,
+
+    /// Outbound multiplexed connections, used instead of `pool` with
+    /// `opts.mux`.
+    pub mux_pool: MuxPool,
+
+    /// Channels of accepted `MUX` connections, on their way to the accept
+    /// loop, which takes the receiver.
+    mux_accepted: mpsc::UnboundedSender<MuxChannel>,
+    mux_incoming: std::sync::Mutex<Option<mpsc::UnboundedReceiver<MuxChannel>>>,
+
+    /// Fails hop forwards fast to a peer that keeps failing; sized by
+    /// `opts.cb_failure_threshold` / `opts.cb_reset_timeout`.
+    pub circuit: CircuitBreaker,
+
+    /// Which incarnation of the node at this address this is; see
+    /// [`Node::generation`].
+    generation: AtomicU64,
+
+    /// Winner of the last `ELECT` this node heard about.
+    leader: Mutex<Option<String>>,
+
+    /// This node's part in that election; follows `leader`.
+    role: Mutex<NodeRole>,
+
+    /// `KV SET` entries; persisted with the ring pointers when
+    /// `opts.state_file` is set.
+    kv: RwLock<HashMap<String, String>>,
+
+    /// Heartbeat members by hash-ring position, refreshed after every
+    /// heartbeat when `opts.routing` is consistent-hash. Holds only this
+    /// node until the first refresh.
+    hash_ring: std::sync::RwLock<ConsistentHashRing>,
+
+    /// Answers `RING REQ`s passing through this node; see
+    /// [`Node::set_ring_req_hook`]. Unset means never reply.
+    on_ring_req: RwLock<Option<RingReqHook>>,
+
+    // Flipped to `true` by `NODE SHUTDOWN`; the accept loop and idle
+    // client connections watch it.
+    shutdown: watch::Sender<bool>,
+
     /// Counts how many times this node has called `broadcast_netmap_update`.
     /// Useful for tests that want to assert "exactly one broadcast per dead
     /// host"; also provides a cheap debug signal in production.
@@ -136,6 +658,53 @@ pub struct Node {
     pub pulls_total: AtomicU64,
     pub chunk_bytes_written_total: AtomicU64,
     pub chunk_bytes_read_total: AtomicU64,
+
+    /// Runtime counters reported by `NODE STAT`: lines read from clients,
+    /// hops handed to a neighbor, client-started walks, walks answered
+    /// from the cache, `RING FORWARD`s handled, parse or handler errors,
+    /// the client connections open right now (the one asking included),
+    /// against `--max-conns`, and the ring size `--monitor-topology` last
+    /// counted (0 until it has).
+    pub started_at: Instant,
+    pub msgs_received: AtomicU64,
+    pub msgs_forwarded: AtomicU64,
+    pub walks_total: AtomicU64,
+    pub walk_cache_hits: AtomicU64,
+    pub ring_msgs_total: AtomicU64,
+    pub errors_total: AtomicU64,
+    pub ring_size: AtomicU64,
+    /// The ring size the last `RING ALL` went by; 0 before the first.
+    ring_all_size: AtomicU64,
+
+    /// Counters only the `--metrics-port` endpoint reports.
+    pub metrics: NodeMetrics,
+
+    pub tracer: Tracer,
+
+    /// `--replay-log` entries on their way to the writer task.
+    pub replay: ReplayLog,
+
+    /// Recently forwarded `RING FORWARD` lines.
+    pub dedup: DedupCache,
+
+    /// One queue per `SUBSCRIBE` connection; see [`crate::events`].
+    subscribers: std::sync::Mutex<Vec<mpsc::Sender<String>>>,
+
+    /// This node's vector clock, and the clock on the last `RING` message
+    /// it received with one; see [`crate::vclock`].
+    vclock: std::sync::Mutex<(VectorClock, Option<VectorClock>)>,
+
+    /// Ring-wide `BARRIER` arrivals seen so far, per barrier id.
+    barrier_registry: std::sync::Mutex<HashMap<String, u32>>,
+    /// Woken whenever a barrier's count changes, for its `BARRIER WAIT`
+    /// connections.
+    barrier_notify: std::sync::Mutex<HashMap<String, Arc<Notify>>>,
+
+    /// This node's copy of each `INCR` counter.
+    counters: std::sync::RwLock<HashMap<String, AtomicU64>>,
+
+    /// `CHAOS MODE` fault injection on `RING` hops; see [`crate::chaos`].
+    chaos_config: std::sync::Mutex<ChaosConfig>,
 }
 
 impl std::fmt::Debug for Node {
@@ -146,8 +715,9 @@ impl std::fmt::Debug for Node {
             .field("fsync_mode", &self.fsync_mode)
             .field("idle_timeout", &self.idle_timeout)
             .field("max_conns", &self.max_conns)
+            .field("tls", &self.opts.tls)
             // Sensitive fields (storage_root, network_nodes, file_tags,
-            // topology_map, pending_walks, pending_heals) are deliberately
+            // topology_map, walks, pending_heals) are deliberately
             // omitted; see the type doc for the rationale.
             .finish_non_exhaustive()
     }
@@ -169,46 +739,663 @@ impl Node {
         auth_token: AuthToken,
         idle_timeout: Duration,
         max_conns: u32,
+    ) -> Arc<Self> {
+        Self::with_opts(
+            port,
+            gossip_interval,
+            file_size,
+            storage_root,
+            respawn_dead,
+            fsync_mode,
+            auth_token,
+            idle_timeout,
+            max_conns,
+            NodeOpts::default(),
+        )
+    }
+
+    /// [`Node::new`] plus the grouped [`NodeOpts`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_opts(
+        port: String,
+        gossip_interval: Duration,
+        file_size: u64,
+        storage_root: PathBuf,
+        respawn_dead: bool,
+        fsync_mode: FsyncMode,
+        auth_token: AuthToken,
+        idle_timeout: Duration,
+        max_conns: u32,
+        opts: NodeOpts,
     ) -> Arc<Self> {
         let network_nodes = RwLock::new(HashMap::new());
+        let (mux_accepted, mux_incoming) = mpsc::unbounded_channel();
+        let hash_ring = std::sync::RwLock::new(ConsistentHashRing::new([port.clone()]));
 
         Arc::new(Self {
             port,
-            next_port: RwLock::new(None),
-            pending_walks: RwLock::new(HashMap::new()),
+            next_ports: RwLock::new(HashMap::new()),
+            next_multi: RwLock::new(Vec::new()),
+            prev_port: RwLock::new(None),
+            state_lock: Mutex::new(()),
+            walks: WalkRegistry::new(),
             walk_counter: AtomicU64::new(1),
+            probe_round: AtomicU64::new(0),
+            walk_cache: RwLock::new(None),
             pending_heals: RwLock::new(HashMap::new()),
+            gather_streams: RwLock::new(HashMap::new()),
             network_nodes,
             file_tags: RwLock::new(HashMap::new()),
             gossip_interval,
             file_size,
             topology_map: RwLock::new(HashMap::new()),
+            node_names: RwLock::new(HashMap::new()),
             storage_root,
             respawn_dead: AtomicBool::new(respawn_dead),
             fsync_mode,
             auth_token,
             idle_timeout,
             max_conns,
+            pool: ConnectionPool::new(opts.pool_size, DEFAULT_POOL_IDLE_TIMEOUT),
+            mux_pool: MuxPool::default(),
+            mux_accepted,
+            mux_incoming: std::sync::Mutex::new(Some(mux_incoming)),
+            circuit: CircuitBreaker::new(
+                opts.cb_failure_threshold,
+                if opts.cb_reset_timeout.is_zero() {
+                    DEFAULT_CB_RESET_TIMEOUT
+                } else {
+                    opts.cb_reset_timeout
+                },
+            ),
+            generation: AtomicU64::new(now_unix_ms()),
+            leader: Mutex::new(None),
+            role: Mutex::new(NodeRole::Unknown),
+            kv: RwLock::new(HashMap::new()),
+            hash_ring,
+            on_ring_req: RwLock::new(None),
+            shutdown: watch::Sender::new(false),
+            subscribers: std::sync::Mutex::new(Vec::new()),
+            vclock: std::sync::Mutex::new((VectorClock::default(), None)),
+            barrier_registry: std::sync::Mutex::new(HashMap::new()),
+            barrier_notify: std::sync::Mutex::new(HashMap::new()),
+            counters: std::sync::RwLock::new(HashMap::new()),
+            chaos_config: std::sync::Mutex::new(ChaosConfig::default()),
+            tracer: Tracer::new(opts.otlp_endpoint.clone()),
+            replay: ReplayLog::new(opts.replay_log.clone()),
+            dedup: DedupCache::new(
+                opts.dedup_cache_size,
+                if opts.dedup_ttl.is_zero() {
+                    DEFAULT_DEDUP_TTL
+                } else {
+                    opts.dedup_ttl
+                },
+            ),
+            opts,
             netmap_broadcasts: AtomicU64::new(0),
             pushes_total: AtomicU64::new(0),
             pulls_total: AtomicU64::new(0),
             chunk_bytes_written_total: AtomicU64::new(0),
             chunk_bytes_read_total: AtomicU64::new(0),
+            started_at: Instant::now(),
+            msgs_received: AtomicU64::new(0),
+            msgs_forwarded: AtomicU64::new(0),
+            walks_total: AtomicU64::new(0),
+            walk_cache_hits: AtomicU64::new(0),
+            ring_msgs_total: AtomicU64::new(0),
+            errors_total: AtomicU64::new(0),
+            ring_size: AtomicU64::new(0),
+            ring_all_size: AtomicU64::new(0),
+            metrics: NodeMetrics::default(),
         })
     }
 
-    pub async fn set_next(&self, addr: String) {
-        *self.next_port.write().await = Some(addr);
+    /// Point the default ring's NEXT at `addr`. Returns `false`, leaving
+    /// the pointers alone, when it already points there and nowhere else;
+    /// the walk cache is cleared either way.
+    pub async fn set_next(&self, addr: String) -> bool {
+        self.set_next_on(DEFAULT_RING, addr).await
     }
 
     pub async fn get_next(&self) -> Option<String> {
-        self.next_port.read().await.clone()
+        self.get_next_on(DEFAULT_RING).await
+    }
+
+    /// [`Node::set_next`] on any ring.
+    pub async fn set_next_on(&self, ring_name: &str, addr: String) -> bool {
+        if self.get_next_on(ring_name).await.as_deref() == Some(addr.as_str())
+            && (ring_name != DEFAULT_RING || self.next_multi.read().await.len() <= 1)
+        {
+            if ring_name == DEFAULT_RING {
+                self.clear_walk_cache().await;
+            }
+            return false;
+        }
+        if ring_name == DEFAULT_RING {
+            self.next_multi.write().await.clear();
+            self.clear_walk_cache().await;
+        }
+        self.next_ports
+            .write()
+            .await
+            .insert(ring_name.to_string(), Some(addr));
+        self.persist_state().await;
+        true
+    }
+
+    /// Compare-and-swap the default ring's NEXT: point it at `addr` only
+    /// if it is `expected` now (`<unset>` matching no NEXT). On a
+    /// mismatch, returns the current NEXT and changes nothing.
+    pub async fn set_next_if(&self, expected: &str, addr: String) -> Result<(), Option<String>> {
+        // Same lock order as `set_next_on`.
+        let mut multi = self.next_multi.write().await;
+        let mut next_ports = self.next_ports.write().await;
+        let current = next_ports.get(DEFAULT_RING).cloned().flatten();
+        if current.as_deref().unwrap_or("<unset>") != expected {
+            return Err(current);
+        }
+        multi.clear();
+        next_ports.insert(DEFAULT_RING.to_string(), Some(addr));
+        drop((multi, next_ports));
+        self.clear_walk_cache().await;
+        self.persist_state().await;
+        Ok(())
+    }
+
+    /// [`Node::set_next_on`] for every `(ring, addr)` in `hops` under one
+    /// write lock, so a forward sees either none of them or all of them.
+    pub async fn set_next_for_rings(&self, hops: &[(String, String)]) {
+        // Same lock order as `set_next_on`.
+        let mut multi = self.next_multi.write().await;
+        let mut next_ports = self.next_ports.write().await;
+        for (ring, addr) in hops {
+            if ring == DEFAULT_RING {
+                multi.clear();
+            }
+            next_ports.insert(ring.clone(), Some(addr.clone()));
+        }
+        drop((multi, next_ports));
+        if hops.iter().any(|(ring, _)| ring == DEFAULT_RING) {
+            self.clear_walk_cache().await;
+        }
+        self.persist_state().await;
+    }
+
+    pub async fn get_next_on(&self, ring_name: &str) -> Option<String> {
+        self.next_ports
+            .read()
+            .await
+            .get(ring_name)
+            .cloned()
+            .flatten()
+    }
+
+    /// Fan the default ring out to every address in `addrs`: `RING
+    /// FORWARD` goes to all of them, everything else follows the first.
+    pub async fn set_next_multi(&self, addrs: Vec<String>) {
+        self.clear_walk_cache().await;
+        self.next_ports
+            .write()
+            .await
+            .insert(DEFAULT_RING.to_string(), addrs.first().cloned());
+        *self.next_multi.write().await = addrs;
+        self.persist_state().await;
+    }
+
+    /// The cached history of the last client walk and its age, if the
+    /// cache is on and the entry is younger than `--walk-cache-ttl-secs`.
+    pub async fn cached_walk(&self) -> Option<(Duration, String)> {
+        let ttl = self.opts.walk_cache_ttl;
+        if ttl.is_zero() {
+            return None;
+        }
+        let cache = self.walk_cache.read().await;
+        let (at, history) = cache.as_ref()?;
+        let age = at.elapsed();
+        (age < ttl).then(|| (age, history.clone()))
+    }
+
+    /// Remember `history` as the latest client walk; a no-op with the
+    /// cache off.
+    pub async fn cache_walk(&self, history: &str) {
+        if !self.opts.walk_cache_ttl.is_zero() {
+            *self.walk_cache.write().await = Some((Instant::now(), history.to_string()));
+        }
+    }
+
+    pub async fn clear_walk_cache(&self) {
+        *self.walk_cache.write().await = None;
+    }
+
+    /// The default ring's next hops: the NODE NEXT-MULTI list, or NEXT
+    /// alone. Its length is the `FAN_OUT` in NODE STATUS.
+    pub async fn next_hops(&self) -> Vec<String> {
+        let multi = self.next_multi.read().await.clone();
+        if multi.is_empty() {
+            self.get_next().await.into_iter().collect()
+        } else {
+            multi
+        }
+    }
+
+    pub async fn set_prev(&self, addr: String) {
+        *self.prev_port.write().await = Some(addr);
+        self.persist_state().await;
+    }
+
+    /// Save the ring pointers to `opts.state_file`, if configured. A failed
+    /// save is logged rather than failing the wiring command: the in-memory
+    /// pointer is already live.
+    async fn persist_state(&self) {
+        let Some(path) = &self.opts.state_file else {
+            return;
+        };
+        let _guard = self.state_lock.lock().await;
+        let (next, rings) = self.ring_pointers().await;
+        let state = NodeState {
+            next,
+            prev: self.get_prev().await,
+            rings,
+            next_multi: self.next_multi.read().await.clone(),
+            kv: self
+                .kv
+                .read()
+                .await
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            generation: self.generation(),
+        };
+        if let Err(e) = save_state(path, &state).await {
+            tracing::warn!(node = %self.port, path = %path.display(), error = ?e, "Failed to save state file");
+        }
+    }
+
+    /// The default ring's NEXT, and every other ring's by name.
+    async fn ring_pointers(&self) -> (Option<String>, BTreeMap<String, String>) {
+        let next_ports = self.next_ports.read().await;
+        let rings = next_ports
+            .iter()
+            .filter(|(ring, _)| ring.as_str() != DEFAULT_RING)
+            .filter_map(|(ring, next)| Some((ring.clone(), next.clone()?)))
+            .collect();
+        (next_ports.get(DEFAULT_RING).cloned().flatten(), rings)
+    }
+
+    /// Everything `SNAPSHOT` reports about this node; see
+    /// [`crate::snapshot`].
+    pub async fn snapshot(&self) -> NodeSnapshot {
+        let (next, rings) = self.ring_pointers().await;
+        let counters = self
+            .counters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, c)| (name.clone(), c.load(Ordering::Relaxed)))
+            .collect();
+        NodeSnapshot {
+            version: SNAPSHOT_VERSION,
+            addr: self.port.clone(),
+            generation: self.generation(),
+            next,
+            prev: self.get_prev().await,
+            rings,
+            next_multi: self.next_multi.read().await.clone(),
+            kv: self
+                .kv
+                .read()
+                .await
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            counters,
+            leader: self.get_leader().await,
+            role: self.role().await,
+            walks: self.walks.tokens().await,
+            stats: self
+                .stat_counters()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            taken_at_ms: now_unix_ms(),
+        }
+    }
+
+    /// Replace this node's ring pointers, KV store, `INCR` counters,
+    /// leader and role with `snapshot`'s, under the state lock so a
+    /// concurrent save sees all or none of it, then save the state file.
+    pub async fn restore_snapshot(&self, snapshot: NodeSnapshot) {
+        tracing::info!(node = %self.port, from = %snapshot.addr, next = ?snapshot.next, prev = ?snapshot.prev, kv_entries = snapshot.kv.len(), "Restoring snapshot");
+        {
+            let _guard = self.state_lock.lock().await;
+            let mut next_ports: HashMap<String, Option<String>> = snapshot
+                .rings
+                .into_iter()
+                .map(|(ring, next)| (ring, Some(next)))
+                .collect();
+            next_ports.insert(DEFAULT_RING.to_string(), snapshot.next);
+            *self.next_ports.write().await = next_ports;
+            *self.next_multi.write().await = snapshot.next_multi;
+            *self.prev_port.write().await = snapshot.prev;
+            *self.kv.write().await = snapshot.kv.into_iter().collect();
+            *self.counters.write().unwrap_or_else(|e| e.into_inner()) = snapshot
+                .counters
+                .into_iter()
+                .map(|(name, value)| (name, AtomicU64::new(value)))
+                .collect();
+            *self.leader.lock().await = snapshot.leader;
+            *self.role.lock().await = snapshot.role;
+        }
+        self.clear_walk_cache().await;
+        self.persist_state().await;
+    }
+
+    /// Load the ring pointers from `opts.state_file`, if configured and
+    /// present, and save the file back with the generation one higher.
+    /// Called by `bind` before the listener is served.
+    pub async fn restore_state(&self) -> std::io::Result<()> {
+        let Some(path) = &self.opts.state_file else {
+            return Ok(());
+        };
+        let state = load_state(path).await?;
+        let generation = state.as_ref().map_or(0, |s| s.generation) + 1;
+        self.generation.store(generation, Ordering::Relaxed);
+        if let Some(state) = state {
+            self.apply_state(state).await;
+        }
+        self.persist_state().await;
+        Ok(())
+    }
+
+    async fn apply_state(&self, state: NodeState) {
+        tracing::info!(node = %self.port, next = ?state.next, prev = ?state.prev, kv_entries = state.kv.len(), "Restored ring pointers from state file");
+        let mut next_ports: HashMap<String, Option<String>> = state
+            .rings
+            .into_iter()
+            .map(|(ring, next)| (ring, Some(next)))
+            .collect();
+        next_ports.insert(DEFAULT_RING.to_string(), state.next);
+        *self.next_ports.write().await = next_ports;
+        *self.next_multi.write().await = state.next_multi;
+        *self.prev_port.write().await = state.prev;
+        *self.kv.write().await = state.kv.into_iter().collect();
+    }
+
+    /// Which incarnation of the node at this address this is. With
+    /// `--state-file` it counts the node's starts; without one it is the
+    /// start time in Unix milliseconds. Either way a restarted node gets a
+    /// larger one, so `NODE NEXT-GEN` can refuse wiring meant for the
+    /// process it replaced.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Ask `addr` for its generation (the `GENERATION` line of its
+    /// `NODE STATUS`).
+    pub async fn peer_generation(&self, addr: &str) -> Result<u64, RingError> {
+        let timeout = Duration::from_secs(2);
+        let mut s = tokio::time::timeout(timeout, self.connect(addr)).await??;
+        s.write_all(b"NODE STATUS\n").await?;
+        let mut reader = LimitedLineReader::new(BufReader::new(s), self.opts.max_line_bytes);
+        loop {
+            let mut line = String::new();
+            let n = tokio::time::timeout(timeout, reader.read_line(&mut line)).await??;
+            let line = line.trim();
+            if n == 0 || line == "OK" {
+                return Err(format!("{addr} reported no generation").into());
+            }
+            if let Some(generation) = line.strip_prefix("GENERATION ") {
+                return generation
+                    .parse()
+                    .map_err(|_| format!("bad generation from {addr}: {generation}").into());
+            }
+        }
+    }
+
+    /// Store `value` under `key`, replacing any previous value, and persist
+    /// it like the ring pointers. Keys and values over the configured
+    /// limits are refused with the reason.
+    pub async fn kv_set(&self, key: String, value: String) -> Result<(), String> {
+        self.check_kv_entry(&key, &value)?;
+        self.kv.write().await.insert(key, value);
+        self.persist_state().await;
+        Ok(())
+    }
+
+    /// Refuse a KV key or value over `--kv-max-key-bytes` /
+    /// `--kv-max-value-bytes`, with the reason.
+    pub fn check_kv_entry(&self, key: &str, value: &str) -> Result<(), String> {
+        let max_key = match self.opts.kv_max_key_bytes {
+            0 => DEFAULT_KV_MAX_KEY_BYTES,
+            n => n,
+        };
+        let max_value = match self.opts.kv_max_value_bytes {
+            0 => DEFAULT_KV_MAX_VALUE_BYTES,
+            n => n,
+        };
+        if key.len() > max_key {
+            return Err(format!("key too long (limit {max_key} bytes)"));
+        }
+        if value.len() > max_value {
+            return Err(format!("value too long (limit {max_value} bytes)"));
+        }
+        Ok(())
+    }
+
+    pub async fn kv_get(&self, key: &str) -> Option<String> {
+        self.kv.read().await.get(key).cloned()
+    }
+
+    pub async fn get_prev(&self) -> Option<String> {
+        self.prev_port.read().await.clone()
+    }
+
+    /// Ask the serve loop to stop accepting and drain (`NODE SHUTDOWN`).
+    pub fn request_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Register a `SUBSCRIBE` connection. Events arrive on the receiver;
+    /// the sender identifies the subscription to [`Node::unsubscribe`].
+    pub fn subscribe(&self) -> (mpsc::Sender<String>, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE);
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(tx.clone());
+        (tx, rx)
+    }
+
+    pub fn unsubscribe(&self, tx: &mpsc::Sender<String>) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|s| !s.same_channel(tx));
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /// Queue an event for every subscriber. A full queue loses this event;
+    /// a closed one is dropped from the list.
+    pub fn publish(&self, kind: EventKind, data: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        if subscribers.is_empty() {
+            return;
+        }
+        let line = format_event(kind, data);
+        subscribers.retain(|tx| match tx.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::debug!(node = %self.port, "Subscriber queue full; dropping event");
+                true
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+    }
+
+    /// Resolves once [`Node::request_shutdown`] has been called.
+    pub async fn shutdown_requested(&self) {
+        let mut rx = self.shutdown.subscribe();
+        let _ = rx.wait_for(|down| *down).await;
+    }
+
+    /// Open an outbound ring connection: TLS handshake when a CA is
+    /// configured, `TCP_NODELAY` unless `--no-nodelay`, then the AUTH line
+    /// when the token is enabled.
+    pub async fn connect(&self, addr: &str) -> std::io::Result<Stream> {
+        let mut s = self.opts.tls.connect(addr).await?;
+        if !self.opts.no_nodelay {
+            s.set_nodelay(true)?;
+        }
+        if self.opts.hello {
+            negotiate_version(&mut s, MIN_SUPPORTED_VERSION)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        }
+        self.write_auth(&mut s).await?;
+        Ok(s)
+    }
+
+    /// Fail fast with an error when `addr`'s circuit is open.
+    async fn check_circuit(&self, addr: &str) -> std::io::Result<()> {
+        if self.circuit.allow(addr).await {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(format!("circuit open for {addr}")))
+        }
+    }
+
+    /// Feed a forward's outcome to the breaker, logging when it trips.
+    async fn record_circuit<T>(&self, addr: &str, res: &std::io::Result<T>) {
+        if self.circuit.record(addr, res.is_ok()).await {
+            tracing::warn!(node = %self.port, target = %addr, "Circuit opened; failing forwards fast");
+        }
+    }
+
+    /// [`Node::send_pooled`] behind the circuit breaker.
+    async fn send_guarded(&self, addr: &str, line: &str) -> std::io::Result<()> {
+        self.check_circuit(addr).await?;
+        let res = self.send_pooled(addr, line).await;
+        self.record_circuit(addr, &res).await;
+        res
+    }
+
+    /// Connect to `addr`, write `line` and close: how `DONE` replies travel.
+    async fn send_line_to(&self, addr: &str, line: &str) -> Result<(), RingError> {
+        let line = crate::vclock::with_vector_clock(line);
+        let line = crate::trace::with_correlation_id(&line);
+        let mut s = self
+            .connect(addr)
+            .await
+            .map_err(|e| RingError::forward(addr, e))?;
+        s.write_all(line.as_bytes())
+            .await
+            .map_err(|e| RingError::forward(addr, e))
+    }
+
+    /// Send one hop line to `addr`. With pooling enabled the line goes over
+    /// a pooled connection and the peer's `OK` is awaited before the
+    /// connection is returned; a pooled connection that turns out to be
+    /// dead (EOF, reset, or any reply other than `OK`) is dropped and the
+    /// line retried once on a fresh one. Without pooling this is the old
+    /// connect-write-close. The `CID:` and `VC:` headers of the command
+    /// being handled go in front of the line.
+    async fn send_pooled(&self, addr: &str, line: &str) -> std::io::Result<()> {
+        let line = crate::vclock::with_vector_clock(line);
+        let line = &*crate::trace::with_correlation_id(&line);
+        if self.opts.mux {
+            return self.send_muxed(addr, line).await;
+        }
+        if !self.pool.is_enabled() {
+            let mut s = self.connect(addr).await?;
+            return s.write_all(line.as_bytes()).await;
+        }
+        if let Some(s) = self.pool.checkout(addr).await {
+            match request_ack(s, line, self.opts.max_line_bytes).await {
+                Ok(s) => {
+                    self.pool.checkin(addr, s).await;
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::debug!(node = %self.port, target = %addr, error = ?e, "Dropping dead pooled connection");
+                }
+            }
+        }
+        let s = request_ack(self.connect(addr).await?, line, self.opts.max_line_bytes).await?;
+        self.pool.checkin(addr, s).await;
+        Ok(())
+    }
+
+    /// [`Node::send_pooled`] with `--mux`: the line goes over a channel of
+    /// the one connection kept to `addr`, and the peer's `OK` is awaited
+    /// on it. A connection that turns out to be dead is dropped and the
+    /// line retried once on a fresh one.
+    async fn send_muxed(&self, addr: &str, line: &str) -> std::io::Result<()> {
+        let max_line_bytes = self.opts.max_line_bytes;
+        if let Some(mux) = self.mux_pool.get(addr).await {
+            let sent = match mux.open() {
+                Ok(channel) => request_ack(channel, line, max_line_bytes).await.map(drop),
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    tracing::debug!(node = %self.port, target = %addr, error = ?e, "Dropping dead mux connection");
+                    self.mux_pool.remove(addr, &mux).await;
+                }
+            }
+        }
+        let mux = Arc::new(self.connect_mux(addr).await?);
+        self.mux_pool.insert(addr, Arc::clone(&mux)).await;
+        request_ack(mux.open()?, line, max_line_bytes).await?;
+        Ok(())
+    }
+
+    /// [`Node::connect`] to `addr` and switch the connection to `MUX`
+    /// framing.
+    async fn connect_mux(&self, addr: &str) -> std::io::Result<MuxStream> {
+        let s = request_ack(self.connect(addr).await?, "MUX\n", self.opts.max_line_bytes).await?;
+        Ok(MuxStream::new(s))
+    }
+
+    /// Where `MUX` connections hand over their new channels.
+    pub fn mux_channels(&self) -> mpsc::UnboundedSender<MuxChannel> {
+        self.mux_accepted.clone()
+    }
+
+    /// The receiving end of [`Node::mux_channels`], for the accept loop.
+    /// `None` once taken.
+    pub fn take_mux_channels(&self) -> Option<mpsc::UnboundedReceiver<MuxChannel>> {
+        self.mux_incoming
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+
+    /// `field` as it should go on the wire: compressed when it is over
+    /// `opts.compress_threshold_bytes`.
+    fn compress<'a>(&self, field: &'a str) -> Cow<'a, str> {
+        compress_field(field, self.opts.compress_threshold_bytes)
+    }
+
+    /// `cmd` with its compressed field, if any, expanded to plain text;
+    /// an error if it does not decode or passes `opts.max_line_bytes`.
+    pub fn expand(&self, cmd: Command) -> Result<Command, RingError> {
+        expand_payload(cmd, expansion_limit(self.opts.max_line_bytes))
     }
 
     /// Send the wire-protocol AUTH line on a freshly-opened outbound stream.
-    /// No-op when the token is disabled. Mirrors `server::send_auth` so the
-    /// node's own `forward_*` and broadcast methods can authenticate without
-    /// pulling a server-only helper in here.
+    /// No-op when the token is disabled.
     async fn write_auth<W>(&self, w: &mut W) -> std::io::Result<()>
     where
         W: AsyncWriteExt + Unpin,
@@ -219,24 +1406,518 @@ impl Node {
         Ok(())
     }
 
+    /// Send a message one hop on `ring_name`; `hop_count` is the count
+    /// the next node receives. A no-op when the ring has no NEXT.
     pub async fn forward_ring_forward(
         &self,
+        ring_name: &str,
+        hop_count: u32,
+        ttl: u32,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        let Some(next) = self.get_next_on(ring_name).await else {
+            return Ok(());
+        };
+        self.forward_ring_forward_to(&next, ring_name, hop_count, ttl, msg)
+            .await
+    }
+
+    /// [`Node::forward_ring_forward`] to `next` rather than the ring's
+    /// NEXT; used for each hop of a NODE NEXT-MULTI fan-out.
+    pub async fn forward_ring_forward_to(
+        &self,
+        next: &str,
+        ring_name: &str,
+        hop_count: u32,
         ttl: u32,
         msg: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(), RingError> {
+        let next = next.to_string();
+        let chaos = self
+            .chaos_config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .apply(msg);
+        let Some((delay, msg)) = chaos else {
+            tracing::debug!(node = %self.port, target = %next, "CHAOS MODE dropped a RING hop");
+            return Ok(());
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        let msg = self.compress(&msg);
+        let line = match ring_name {
+            DEFAULT_RING => format!("RING FORWARD-HOP {hop_count} {ttl} {msg}"),
+            _ => format!("RING ON-HOP {ring_name} {hop_count} {ttl} {msg}"),
+        };
+        let line = crate::trace::with_context(format!("{line}\n"));
+        self.check_circuit(&next)
+            .await
+            .map_err(|e| RingError::forward(&next, e))?;
+        // Only a refused connect is retried: the line never left, so a
+        // retry can't deliver it twice. Covers a neighbor mid-restart.
+        // The retries count as one forward for the breaker.
+        let mut attempt = 0;
+        let res = loop {
+            match self.send_pooled(&next, &line).await {
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::ConnectionRefused
+                        && attempt + 1 < RING_FORWARD_ATTEMPTS =>
+                {
+                    tracing::debug!(node = %self.port, target = %next, attempt, "RING FORWARD refused; backing off");
+                    backoff_sleep(&BackoffConfig::default(), attempt).await;
+                    attempt += 1;
+                }
+                res => break res,
+            }
+        };
+        self.record_circuit(&next, &res).await;
+        res.map_err(|e| RingError::forward(&next, e))
+    }
+
+    pub async fn forward_ring_timed(&self, expiry_ms: u64, msg: &str) -> Result<(), RingError> {
         if let Some(next) = self.get_next().await {
-            let mut s = TcpStream::connect(&next).await?;
-            self.write_auth(&mut s).await?;
-            let line = format!("RING FORWARD {} {}\n", ttl, msg);
-            s.write_all(line.as_bytes()).await?;
+            let msg = self.compress(msg);
+            let line = format!("RING TIMED {expiry_ms} {msg}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
         }
         Ok(())
     }
 
-    // File Tags
-
-    pub async fn set_file_tag(&self, name: &str, start_port: u16, size: u64, parts: u32) {
-        self.file_tags.write().await.insert(
+    pub async fn forward_ring_ack_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        ttl: u32,
+        seen: &str,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let msg = self.compress(msg);
+            let line = format!("RING ACK-HOP {token} {start_addr} {ttl} {seen} {msg}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_ring_ack_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        seen: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("RING ACK-DONE {token} {seen}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_ring_collect_hop(
+        &self,
+        token: &str,
+        start_addr: &str,
+        ttl: u32,
+        key: &str,
+        results: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("RING COLLECT-HOP {token} {start_addr} {ttl} {key} {results}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_ring_collect_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        results: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("RING COLLECT-DONE {token} {results}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_ring_echo_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        ttl: u32,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let msg = self.compress(msg);
+            let line = format!("RING ECHO-HOP {token} {start_addr} {ttl} {msg}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_ring_echo_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        let msg = self.compress(msg);
+        let line = format!("RING ECHO-DONE {token} {msg}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_ring_once_hop(
+        &self,
+        token: &str,
+        hop_count: u32,
+        start_addr: &str,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let msg = self.compress(msg);
+            let line = format!("RING ONCE-HOP {token} {hop_count} {start_addr} {msg}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_ring_once_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("RING ONCE-DONE {token}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn get_leader(&self) -> Option<String> {
+        self.leader.lock().await.clone()
+    }
+
+    /// Record the elected leader; this node becomes `Leader` if it is the
+    /// one, `Follower` otherwise.
+    pub async fn set_leader(&self, addr: String) {
+        let role = if port_str(&addr) == port_str(&self.port) {
+            NodeRole::Leader
+        } else {
+            NodeRole::Follower
+        };
+        *self.leader.lock().await = Some(addr);
+        *self.role.lock().await = role;
+    }
+
+    pub async fn role(&self) -> NodeRole {
+        *self.role.lock().await
+    }
+
+    /// Set this node's role and return the one it replaces.
+    pub async fn set_role(&self, role: NodeRole) -> NodeRole {
+        std::mem::replace(&mut *self.role.lock().await, role)
+    }
+
+    /// Add one to this node's copy of `counter_name` and return the new
+    /// value.
+    pub fn counter_incr(&self, counter_name: &str) -> u64 {
+        {
+            let counters = self.counters.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(counter) = counters.get(counter_name) {
+                return counter.fetch_add(1, Ordering::Relaxed) + 1;
+            }
+        }
+        self.counters
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(counter_name.to_string())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    /// This node's copy of `counter_name`; 0 if it never saw an `INCR`.
+    pub fn counter_value(&self, counter_name: &str) -> u64 {
+        self.counters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(counter_name)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    /// Switch `CHAOS MODE` on with `mode`, or off with `None`.
+    pub fn set_chaos(&self, mode: Option<ChaosMode>) {
+        if let Some(mode) = mode {
+            tracing::warn!(node = %self.port, mode = %mode, "CHAOS MODE on; RING hops will be mistreated");
+        }
+        self.chaos_config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .mode = mode;
+    }
+
+    /// Count one arrival at `barrier_id` and wake its waiters. Returns
+    /// the count so far.
+    pub fn barrier_arrive(&self, barrier_id: &str) -> u32 {
+        let count = {
+            let mut registry = self
+                .barrier_registry
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            let count = registry.entry(barrier_id.to_string()).or_insert(0);
+            *count = count.saturating_add(1);
+            *count
+        };
+        self.barrier_notify(barrier_id).notify_waiters();
+        count
+    }
+
+    /// Release every waiter on `barrier_id`, whatever its count.
+    pub fn barrier_release(&self, barrier_id: &str) {
+        self.barrier_registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(barrier_id.to_string(), u32::MAX);
+        self.barrier_notify(barrier_id).notify_waiters();
+    }
+
+    /// Arrivals counted at `barrier_id`; `u32::MAX` once released.
+    pub fn barrier_count(&self, barrier_id: &str) -> u32 {
+        self.barrier_registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(barrier_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Wait until `barrier_id` has `n` arrivals. `Err` with the count
+    /// reached if `timeout` runs out first.
+    pub async fn barrier_wait(
+        &self,
+        barrier_id: &str,
+        n: u32,
+        timeout: Duration,
+    ) -> Result<(), u32> {
+        let notify = self.barrier_notify(barrier_id);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Registered before the count is read, so an arrival in
+            // between still wakes it.
+            let notified = notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let count = self.barrier_count(barrier_id);
+            if count >= n {
+                return Ok(());
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(count);
+            }
+        }
+    }
+
+    fn barrier_notify(&self, barrier_id: &str) -> Arc<Notify> {
+        self.barrier_notify
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(barrier_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// The `ELECT` candidate after this node: whichever of `candidate` and
+    /// this node's own address sorts higher.
+    pub fn elect_candidate(&self, candidate: &str) -> String {
+        if self.port.as_str() > candidate {
+            self.port.clone()
+        } else {
+            candidate.to_string()
+        }
+    }
+
+    pub async fn forward_elect_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        candidate: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let line = format!("ELECT HOP {token} {start_addr} {candidate}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_elect_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        candidate: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("ELECT DONE {token} {candidate}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_elect_leader(
+        &self,
+        ring_name: &str,
+        start_addr: &str,
+        leader: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let line = format!("ELECT LEADER {start_addr} {leader}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn forward_barrier_hop(
+        &self,
+        barrier_id: &str,
+        n: u32,
+        start_addr: &str,
+        visited: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("BARRIER HOP {barrier_id} {n} {start_addr} {visited}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn forward_incr_hop(
+        &self,
+        counter_name: &str,
+        token: &str,
+        hop_count: u32,
+        origin: &str,
+        current_value: u64,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line =
+                format!("INCR HOP {counter_name} {token} {hop_count} {origin} {current_value}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn forward_walk_find_hop(
+        &self,
+        token: &str,
+        start_addr: &str,
+        history: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("TOPOLOGY FIND-HOP {token} {start_addr} {history} {key} {value}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_walk_found(
+        &self,
+        start_addr: &str,
+        token: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("TOPOLOGY FIND-FOUND {token} {} {history}\n", self.port);
+        self.send_line_to(start_addr, &line).await
+    }
+
+    pub async fn send_walk_not_found(
+        &self,
+        start_addr: &str,
+        token: &str,
+    ) -> Result<(), RingError> {
+        self.send_line_to(start_addr, &format!("TOPOLOGY FIND-NOT-FOUND {token}\n"))
+            .await
+    }
+
+    pub async fn forward_ring_req(
+        &self,
+        ring_name: &str,
+        callback_addr: &str,
+        ttl: u32,
+        msg: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let msg = self.compress(msg);
+            let line = format!("RING REQ {callback_addr} {ttl} {msg}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    /// Install the hook that answers `RING REQ`s on this node, replacing
+    /// any previous one.
+    pub async fn set_ring_req_hook(&self, hook: RingReqHook) {
+        *self.on_ring_req.write().await = Some(hook);
+    }
+
+    /// This node's reply to a `RING REQ` message, if its hook has one.
+    /// Kept to a single line so it fits in `RING RESP`.
+    pub async fn on_ring_req(&self, msg: &str) -> Option<String> {
+        let hook = self.on_ring_req.read().await.clone()?;
+        let payload = hook(msg)?;
+        Some(payload.lines().next().unwrap_or("").trim().to_string())
+    }
+
+    pub async fn send_ring_resp(
+        &self,
+        callback_addr: &str,
+        payload: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("RING RESP {callback_addr} {payload}\n");
+        self.send_line_to(callback_addr, &line).await?;
+        Ok(())
+    }
+
+    pub fn ring_ack_timeout(&self) -> Duration {
+        if self.opts.ring_ack_timeout.is_zero() {
+            DEFAULT_RING_ACK_TIMEOUT
+        } else {
+            self.opts.ring_ack_timeout
+        }
+    }
+
+    /// How long a client may take to send its first line.
+    pub fn accept_timeout(&self) -> Duration {
+        if self.opts.accept_timeout.is_zero() {
+            self.idle_timeout
+        } else {
+            self.opts.accept_timeout
+        }
+    }
+
+    // File Tags
+
+    pub async fn set_file_tag(&self, name: &str, start_port: u16, size: u64, parts: u32) {
+        self.file_tags.write().await.insert(
             name.to_string(),
             FileTag {
                 start: start_port,
@@ -288,112 +1969,1095 @@ impl Node {
         }
     }
 
-    // --- Topology (WALK) helpers
+    // --- Topology (WALK) helpers
+
+    fn next_token(&self) -> String {
+        let n = self.walk_counter.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}", self.port, n)
+    }
+
+    pub fn make_walk_token(&self) -> String {
+        self.next_token()
+    }
+
+    /// [`WalkRegistry::register`] on this node's walks.
+    pub async fn register_walk(&self, token: &str) -> oneshot::Receiver<WalkResult> {
+        self.walks.register(token).await
+    }
+
+    /// Start the next probe round: its number, and its token registered
+    /// like a walk's. The token carries the round (`probe-<round>-...`).
+    pub async fn start_probe(&self) -> (u64, String, oneshot::Receiver<WalkResult>) {
+        let round = self.probe_round.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = format!("probe-{round}-{}", self.next_token());
+        let rx = self.register_walk(&token).await;
+        (round, token, rx)
+    }
+
+    /// Finish probe `token`. False, leaving the walk registry alone, if
+    /// the token is from an earlier round than the current one (or is not
+    /// a probe token at all).
+    pub async fn finish_probe(&self, token: &str) -> bool {
+        if probe_round(token) != Some(self.probe_round.load(Ordering::Relaxed)) {
+            return false;
+        }
+        self.finish_walk(token, String::new()).await
+    }
+
+    pub async fn register_heal_walk(&self, token: &str) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.pending_heals
+            .write()
+            .await
+            .insert(token.to_string(), tx);
+        rx
+    }
+
+    pub async fn forward_topology_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        history: &str,
+        visited: &str,
+        meta_key: Option<&str>,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let meta = meta_key.map(|k| format!(" meta={k}")).unwrap_or_default();
+            let line = crate::trace::with_context(format!(
+                "TOPOLOGY HOP {token} {start_addr} {history} {visited}{meta}\n"
+            ));
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    /// [`WalkRegistry::deliver`] on this node's walks.
+    pub async fn finish_walk(&self, token: &str, history: String) -> bool {
+        self.walks.deliver(token, history).await
+    }
+
+    /// Cancel a pending walk: its waiter gets [`WalkAborted`] and a late
+    /// DONE for the token is ignored. False if the token is unknown.
+    pub async fn abort_walk(&self, token: &str) -> bool {
+        self.walks.abort(token).await
+    }
+
+    pub async fn finish_heal_walk(&self, token: &str) -> bool {
+        if let Some(tx) = self.pending_heals.write().await.remove(token) {
+            let _ = tx.send(());
+            true
+        } else {
+            false
+        }
+    }
+
+    pub async fn forward_walk_pay_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        payload_key: &str,
+        history: &str,
+        payloads: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let payloads = self.compress(payloads);
+            let line = format!(
+                "TOPOLOGY PAY-HOP {token} {start_addr} {payload_key} {history} {payloads}\n"
+            );
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_walk_pay_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        history: &str,
+        payloads: &str,
+    ) -> Result<(), RingError> {
+        let payloads = self.compress(payloads);
+        let line = format!("TOPOLOGY PAY-DONE {token} {history} {payloads}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    /// Tell the repair coordinator, if there is one, that `dead` stopped
+    /// answering. Failures are logged; the caller carries on either way.
+    pub async fn report_dead_next(&self, dead: &str) {
+        let Some(coordinator) = &self.opts.repair_coordinator else {
+            return;
+        };
+        tracing::warn!(node = %self.port, dead = %dead, coordinator = %coordinator, "Reporting dead NEXT");
+        if let Err(e) = self
+            .send_line_to(coordinator, &format!("PROBE {dead}\n"))
+            .await
+        {
+            tracing::warn!(node = %self.port, coordinator = %coordinator, error = ?e, "PROBE send failed");
+        }
+    }
+
+    /// Tell the heartbeat coordinator, if there is one, that this node is
+    /// alive, under which name and generation, and where its NEXT points.
+    pub async fn send_heartbeat(&self) -> Result<(), RingError> {
+        let Some(coordinator) = &self.opts.heartbeat_addr else {
+            return Ok(());
+        };
+        let name = if self.name().is_empty() {
+            "<unset>"
+        } else {
+            self.name()
+        };
+        let mut line = format!("HEARTBEAT {} {name} {}", self.port, self.generation());
+        if let Some(next) = self.get_next().await {
+            line.push(' ');
+            line.push_str(&next);
+        }
+        line.push('\n');
+        self.send_line_to(coordinator, &line).await
+    }
+
+    /// Interval between heartbeats, with the zero default resolved.
+    pub fn heartbeat_interval(&self) -> Duration {
+        if self.opts.heartbeat_interval.is_zero() {
+            DEFAULT_HEARTBEAT_INTERVAL
+        } else {
+            self.opts.heartbeat_interval
+        }
+    }
+
+    /// What this node's `ANNOUNCE` datagrams say.
+    pub fn announcement(&self) -> Announcement {
+        let name = if self.name().is_empty() {
+            "<unset>"
+        } else {
+            self.name()
+        };
+        Announcement {
+            addr: self.port.clone(),
+            name: name.to_string(),
+            generation: self.generation(),
+        }
+    }
+
+    /// Interval between announcements, with the zero default resolved.
+    pub fn announce_interval(&self) -> Duration {
+        if self.opts.announce_interval.is_zero() {
+            DEFAULT_ANNOUNCE_INTERVAL
+        } else {
+            self.opts.announce_interval
+        }
+    }
+
+    /// `TOPOLOGY WALK-PARALLEL`'s timeout, with the zero default resolved.
+    pub fn parallel_walk_timeout(&self) -> Duration {
+        if self.opts.parallel_walk_timeout.is_zero() {
+            DEFAULT_PARALLEL_WALK_TIMEOUT
+        } else {
+            self.opts.parallel_walk_timeout
+        }
+    }
+
+    /// Settings for a [`RingClient`] this node opens to a peer or the
+    /// heartbeat coordinator: its own token, TLS and `HELLO`.
+    pub fn client_opts(&self) -> ClientOpts {
+        ClientOpts {
+            auth_token: self.auth_token.clone(),
+            tls: self.opts.tls.clone(),
+            hello: self.opts.hello,
+            ..Default::default()
+        }
+    }
+
+    /// The live members the heartbeat coordinator lists under `MEMBERS`.
+    /// An error without `--heartbeat-addr`.
+    pub async fn members(&self) -> Result<Vec<Member>, RingError> {
+        let Some(coordinator) = &self.opts.heartbeat_addr else {
+            return Err("no membership list: run with --heartbeat-addr".into());
+        };
+        let lines = RingClient::with_opts(coordinator.as_str(), self.client_opts())
+            .raw("MEMBERS")
+            .await?;
+        lines
+            .iter()
+            .filter(|l| l.starts_with("MEMBER "))
+            .map(|l| l.parse())
+            .collect()
+    }
+
+    /// Rebuild the hash ring from the heartbeat members, this node
+    /// included whether or not the coordinator lists it yet.
+    pub async fn refresh_hash_ring(&self) -> Result<(), RingError> {
+        let members = self.members().await?;
+        let ring = ConsistentHashRing::new(
+            members
+                .into_iter()
+                .map(|m| m.addr)
+                .chain(std::iter::once(self.port.clone())),
+        );
+        *self.hash_ring.write().unwrap_or_else(|e| e.into_inner()) = ring;
+        Ok(())
+    }
+
+    /// The member `key` belongs to on this node's hash ring.
+    pub fn hash_ring_owner(&self, key: &str) -> String {
+        let ring = self.hash_ring.read().unwrap_or_else(|e| e.into_inner());
+        ring.owner(key).unwrap_or(&self.port).to_string()
+    }
+
+    /// Send `line` to `target` as a client would, behind the `CID:`
+    /// header of the command being handled, and return its reply lines.
+    pub async fn relay_command(&self, target: &str, line: &str) -> Result<Vec<String>, RingError> {
+        let line = crate::trace::with_correlation_id(line);
+        RingClient::with_opts(target, self.client_opts())
+            .raw(&line)
+            .await
+    }
+
+    /// Send `RING KEY-HOP` to `owner` and return its `KEY_ACK` line.
+    pub async fn forward_ring_key(
+        &self,
+        owner: &str,
+        key: &str,
+        msg: &str,
+    ) -> Result<String, RingError> {
+        let lines = RingClient::with_opts(owner, self.client_opts())
+            .raw(&format!("RING KEY-HOP {key} {msg}"))
+            .await?;
+        lines
+            .into_iter()
+            .next_back()
+            .ok_or_else(|| RingError::ParseError(format!("no reply from {owner}")))
+    }
+
+    pub async fn forward_walk_max_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        hop_count: u32,
+        max_hops: u32,
+        start_addr: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let line =
+                format!("TOPOLOGY MAX-HOP {token} {hop_count} {max_hops} {start_addr} {history}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_walk_max_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        truncated: bool,
+        history: &str,
+    ) -> Result<(), RingError> {
+        let status = if truncated { "TRUNCATED" } else { "COMPLETE" };
+        let line = format!("TOPOLOGY MAX-DONE {token} {status} {history}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_walk_sample_hop(
+        &self,
+        token: &str,
+        hop_count: u32,
+        probability: Probability,
+        start_addr: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!(
+                "TOPOLOGY SAMPLE-HOP {token} {hop_count} {probability} {start_addr} {history}\n"
+            );
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_walk_sample_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        hop_count: u32,
+        history: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("TOPOLOGY SAMPLE-DONE {token} {hop_count} {history}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_gather_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        key: &str,
+        results: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let line = format!("GATHER HOP {token} {start_addr} {key} {results}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn forward_gather_stream_hop(
+        &self,
+        token: &str,
+        start_addr: &str,
+        key: &str,
+        results: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("GATHER STREAM-HOP {token} {start_addr} {key} {results}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_gather_stream_data(
+        &self,
+        start_addr: &str,
+        token: &str,
+        entry: &str,
+    ) -> Result<(), RingError> {
+        self.send_line_to(start_addr, &format!("GATHER STREAM-DATA {token} {entry}\n"))
+            .await
+    }
+
+    /// Route the `GATHER STREAM-DATA` entries for `token` to the returned
+    /// receiver until [`Node::close_gather_stream`].
+    pub async fn open_gather_stream(&self, token: &str) -> mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.gather_streams
+            .write()
+            .await
+            .insert(token.to_string(), tx);
+        rx
+    }
+
+    /// Hand `entry` to the stream open for `token`. False if none is.
+    pub async fn push_gather_stream(&self, token: &str, entry: String) -> bool {
+        match self.gather_streams.read().await.get(token) {
+            Some(tx) => tx.send(entry).is_ok(),
+            None => false,
+        }
+    }
+
+    pub async fn close_gather_stream(&self, token: &str) {
+        self.gather_streams.write().await.remove(token);
+    }
+
+    pub async fn forward_split_brain_hop(
+        &self,
+        token: &str,
+        start_addr: &str,
+        leaders: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("SPLIT-BRAIN HOP {token} {start_addr} {leaders}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_split_brain_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        leaders: &str,
+    ) -> Result<(), RingError> {
+        self.send_line_to(start_addr, &format!("SPLIT-BRAIN DONE {token} {leaders}\n"))
+            .await
+    }
+
+    /// Append this node's `<addr>=<leader>` entry to a `SPLIT-BRAIN CHECK`
+    /// walk's `leaders`; `<unset>` before any election.
+    pub async fn append_split_brain_leader(&self, leaders: String) -> String {
+        let leader = self
+            .get_leader()
+            .await
+            .unwrap_or_else(|| "<unset>".to_string());
+        let entry = format!("{}={leader}", self.port);
+        if leaders.is_empty() {
+            entry
+        } else {
+            format!("{leaders};{entry}")
+        }
+    }
+
+    pub async fn send_gather_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        results: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("GATHER DONE {token} {results}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_ring_walk_hop(
+        &self,
+        ring_name: &str,
+        token: &str,
+        start_addr: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
+            let line = format!("TOPOLOGY RING-HOP {token} {ring_name} {start_addr} {history}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn forward_probe_hop(
+        &self,
+        token: &str,
+        hop_count: u32,
+        start_addr: &str,
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next().await {
+            let line = format!("TOPOLOGY PROBE-HOP {token} {hop_count} {start_addr}\n");
+            self.send_guarded(&next, &line)
+                .await
+                .map_err(|e| RingError::forward(&next, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_probe_done(&self, start_addr: &str, token: &str) -> Result<(), RingError> {
+        let line = format!("TOPOLOGY PROBE-DONE {token}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn send_ring_walk_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("TOPOLOGY RING-DONE {token} {history}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn send_topology_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        history: &str,
+        meta_key: Option<&str>,
+    ) -> Result<(), RingError> {
+        let meta = meta_key.map(|k| format!(" meta={k}")).unwrap_or_default();
+        let line = format!("TOPOLOGY DONE {token} {history}{meta}\n");
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+
+    pub async fn forward_topology_reverse_hop(
+        &self,
+        token: &str,
+        start_addr: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        if let Some(prev) = self.get_prev().await {
+            let line = format!(
+                "TOPOLOGY REVERSE-HOP {} {} {}\n",
+                token, start_addr, history
+            );
+            self.send_guarded(&prev, &line)
+                .await
+                .map_err(|e| RingError::forward(&prev, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_topology_reverse_done(
+        &self,
+        start_addr: &str,
+        token: &str,
+        history: &str,
+    ) -> Result<(), RingError> {
+        let line = format!("TOPOLOGY REVERSE-DONE {} {}\n", token, history);
+        self.send_line_to(start_addr, &line).await?;
+        Ok(())
+    }
+}
+
+// --- WALK utility
+
+/// How long a pooled hop waits for the peer's `OK`. Hop handlers ack on
+/// receipt, before doing their own forward, so this only covers one hop.
+const HOP_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Write `line` and read the one-line reply byte by byte, so nothing past
+/// the `\n` is consumed and the stream can go back to the pool. A reply
+/// longer than `max_line_bytes` is an error.
+async fn request_ack<S>(mut s: S, line: &str, max_line_bytes: usize) -> std::io::Result<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    s.write_all(line.as_bytes()).await?;
+    let mut ack = String::new();
+    // A one-byte buffer never reads ahead of the `\n`.
+    let mut reader = LimitedLineReader::new(BufReader::with_capacity(1, &mut s), max_line_bytes);
+    match tokio::time::timeout(HOP_ACK_TIMEOUT, reader.read_line(&mut ack)).await {
+        Ok(Ok(0)) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+        Ok(r) => r?,
+        Err(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "timed out waiting for hop ack",
+            ));
+        }
+    };
+    // `OK EXPIRED` (an expired `RING TIMED`) is an ack too.
+    let ack = ack.trim();
+    if ack != "OK" && !ack.starts_with("OK ") {
+        return Err(std::io::Error::other(format!("unexpected hop ack: {ack}")));
+    }
+    Ok(s)
+}
+
+/// The round a [`Node::start_probe`] token belongs to.
+pub fn probe_round(token: &str) -> Option<u64> {
+    let rest = token.strip_prefix("probe-")?;
+    rest.split_once('-')?.0.parse().ok()
+}
+
+/// The node key of an address: its port, or the socket's file name for a
+/// `unix:` address (so it also names the node's storage directory).
+pub fn port_str(addr: &str) -> &str {
+    if let Some(path) = crate::transport::unix_path(addr) {
+        return path.rsplit('/').next().unwrap_or(path);
+    }
+    addr.rsplit(':').next().unwrap_or(addr)
+}
+
+/// `host:port`, bracketing an IPv6 literal host (`::1` -> `[::1]:7000`).
+pub fn join_host_port(host: &str, port: impl std::fmt::Display) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Port used when a listen address names a host but no port.
+pub const DEFAULT_LISTEN_PORT: u16 = 9000;
+
+/// Host a bare port binds on unless `--bind-host` says otherwise.
+pub const DEFAULT_BIND_HOST: &str = "127.0.0.1";
+
+/// Normalize a `--addr` / `$PORT` value to `host:port`:
+/// - a bare port (`7001`) binds on [`DEFAULT_BIND_HOST`];
+/// - a bare IP (`::1`, `127.0.0.1`) gets [`DEFAULT_LISTEN_PORT`];
+/// - socket addresses (`[::1]:7001`) and `hostname:port` pass through.
+pub fn normalize_addr(raw: &str) -> String {
+    normalize_addr_on(raw, DEFAULT_BIND_HOST)
+}
+
+/// [`normalize_addr`] with a bare port bound on `bind_host` instead.
+pub fn normalize_addr_on(raw: &str, bind_host: &str) -> String {
+    if let Ok(sa) = raw.parse::<std::net::SocketAddr>() {
+        return sa.to_string();
+    }
+    if let Ok(port) = raw.parse::<u16>() {
+        return join_host_port(bind_host, port);
+    }
+    if let Ok(ip) = raw.parse::<std::net::IpAddr>() {
+        return std::net::SocketAddr::new(ip, DEFAULT_LISTEN_PORT).to_string();
+    }
+    raw.to_string()
+}
+
+/// The address `run` listens on, by priority:
+/// 1. `addr` (`--addr`), normalized;
+/// 2. `port` (`--port`) on `bind_host`;
+/// 3. `env_port` (`$PORT`), normalized, a bare port on `bind_host`;
+/// 4. [`DEFAULT_LISTEN_PORT`] on `bind_host`.
+pub fn resolve_listen_addr(
+    addr: Option<&str>,
+    port: Option<u16>,
+    env_port: Option<&str>,
+    bind_host: &str,
+) -> String {
+    if let Some(a) = addr {
+        return normalize_addr_on(a, bind_host);
+    }
+    if let Some(p) = port {
+        return join_host_port(bind_host, p);
+    }
+    if let Some(from_env) = env_port {
+        return normalize_addr_on(from_env, bind_host);
+    }
+    join_host_port(bind_host, DEFAULT_LISTEN_PORT)
+}
+
+/// Append the edge `from_addr -> to_addr` to a walk history.
+///
+/// Histories follow this grammar:
+///
+/// ```text
+/// history  = [ edge *( ";" edge ) ]
+/// edge     = endpoint "->" endpoint
+/// endpoint = [ name "@" ] label
+/// label    = port_str(addr), with "%", ";", ">", newline and space
+///            written as "%25", "%3B", "%3E", "%0A" and "%20"
+/// ```
+///
+/// A `TOPOLOGY WALK-META` history annotates every endpoint as
+/// `endpoint ":" value`, the value escaped the same way.
+///
+/// TCP addresses reduce to a port and never need escaping; a Unix socket
+/// whose file name holds `;` or `->` would otherwise split the history in
+/// the wrong place. [`decode_edge`] reverses the escaping.
+pub fn append_edge(history: String, from_addr: &str, to_addr: &str) -> String {
+    append_named_edge(history, "", from_addr, to_addr)
+}
+
+/// Like [`append_edge`], but the source endpoint is written as
+/// `name@port` when `from_name` is set. The target stays a bare port
+/// until its own node labels it via [`label_last_target`].
+pub fn append_named_edge(
+    mut history: String,
+    from_name: &str,
+    from_addr: &str,
+    to_addr: &str,
+) -> String {
+    let from = edge_label(from_name, from_addr);
+    let to = encode_endpoint(port_str(to_addr));
+    let edge = format!("{from}->{to}");
+    if history.is_empty() {
+        edge
+    } else {
+        history.push(';');
+        history.push_str(&edge);
+        history
+    }
+}
+
+/// Like [`append_named_edge`], with the source endpoint annotated
+/// `:<value>` for a `TOPOLOGY WALK-META`. The target is annotated by its
+/// own node via [`annotate_last_target`].
+pub fn append_meta_edge(
+    history: String,
+    from_name: &str,
+    from_addr: &str,
+    value: &str,
+    to_addr: &str,
+) -> String {
+    let mut history = append_named_edge(history, from_name, from_addr, to_addr);
+    let from_end = history.rfind("->").unwrap_or(history.len());
+    history.insert_str(from_end, &format!(":{value}"));
+    history
+}
+
+/// Append `:<value>` to the trailing target of `history`.
+pub fn annotate_last_target(mut history: String, value: &str) -> String {
+    if history.contains("->") {
+        history.push(':');
+        history.push_str(value);
+    }
+    history
+}
+
+/// What a `TOPOLOGY WALK-SAMPLE` history holds in place of unrecorded edges.
+pub const SKIPPED_EDGE: &str = "...";
 
-    fn next_token(&self) -> String {
-        let n = self.walk_counter.fetch_add(1, Ordering::Relaxed);
-        format!("{}-{}", self.port, n)
+/// Append `...` for an edge a `TOPOLOGY WALK-SAMPLE` hop did not record.
+/// A run of skipped edges shares one `...`.
+pub fn append_skipped(mut history: String) -> String {
+    if history.is_empty() {
+        return SKIPPED_EDGE.to_string();
     }
-
-    pub fn make_walk_token(&self) -> String {
-        self.next_token()
+    if !history.ends_with(SKIPPED_EDGE) {
+        history.push(';');
+        history.push_str(SKIPPED_EDGE);
     }
+    history
+}
 
-    pub async fn register_walk(&self, token: &str) -> oneshot::Receiver<String> {
-        let (tx, rx) = oneshot::channel();
-        self.pending_walks
-            .write()
-            .await
-            .insert(token.to_string(), tx);
-        rx
+/// [`label_last_target`] unless the last segment is a skipped edge.
+pub fn label_last_sampled(history: String, name: &str) -> String {
+    if history.rsplit(';').next() == Some(SKIPPED_EDGE) {
+        return history;
     }
+    label_last_target(history, name)
+}
 
-    pub async fn register_heal_walk(&self, token: &str) -> oneshot::Receiver<()> {
-        let (tx, rx) = oneshot::channel();
-        self.pending_heals
-            .write()
-            .await
-            .insert(token.to_string(), tx);
-        rx
+/// Rewrite the trailing `->port` of `history` to `->name@port`. No-op when
+/// `name` is empty or the target is already labelled.
+pub fn label_last_target(mut history: String, name: &str) -> String {
+    if name.is_empty() {
+        return history;
     }
-
-    pub async fn forward_topology_hop(
-        &self,
-        token: &str,
-        start_addr: &str,
-        history: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(next) = self.get_next().await {
-            let mut s = TcpStream::connect(&next).await?;
-            self.write_auth(&mut s).await?;
-            let line = format!("TOPOLOGY HOP {} {} {}\n", token, start_addr, history);
-            s.write_all(line.as_bytes()).await?;
-        }
-        Ok(())
+    let Some(idx) = history.rfind("->") else {
+        return history;
+    };
+    let target_at = idx + 2;
+    if history[target_at..].contains('@') {
+        return history;
     }
+    history.insert_str(target_at, &format!("{name}@"));
+    history
+}
 
-    pub async fn finish_walk(&self, token: &str, history: String) -> bool {
-        if let Some(tx) = self.pending_walks.write().await.remove(token) {
-            let _ = tx.send(history);
-            true
-        } else {
-            false
+/// Split one edge of a history into its `(from, to)` endpoints, undoing
+/// the escaping [`append_edge`] applies. `None` if `edge` has no `->` or
+/// holds a malformed `%` escape.
+pub fn decode_edge(edge: &str) -> Option<(String, String)> {
+    let (from, to) = edge.split_once("->")?;
+    Some((decode_endpoint(from)?, decode_endpoint(to)?))
+}
+
+/// Escape the characters that delimit edges and histories.
+fn encode_endpoint(s: &str) -> std::borrow::Cow<'_, str> {
+    if !s.contains(['%', ';', '>', '\n', ' ']) {
+        return std::borrow::Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 6);
+    for c in s.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            ';' => out.push_str("%3B"),
+            '>' => out.push_str("%3E"),
+            '\n' => out.push_str("%0A"),
+            ' ' => out.push_str("%20"),
+            c => out.push(c),
         }
     }
+    std::borrow::Cow::Owned(out)
+}
 
-    pub async fn finish_heal_walk(&self, token: &str) -> bool {
-        if let Some(tx) = self.pending_heals.write().await.remove(token) {
-            let _ = tx.send(());
-            true
-        } else {
-            false
-        }
+fn decode_endpoint(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(idx) = rest.find('%') {
+        out.push_str(&rest[..idx]);
+        let c = match rest.get(idx + 1..idx + 3)? {
+            "25" => '%',
+            "3B" | "3b" => ';',
+            "3E" | "3e" => '>',
+            "0A" | "0a" => '\n',
+            "20" => ' ',
+            _ => return None,
+        };
+        out.push(c);
+        rest = &rest[idx + 3..];
     }
+    out.push_str(rest);
+    Some(out)
+}
 
-    pub async fn send_topology_done(
-        &self,
-        start_addr: &str,
-        token: &str,
-        history: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut s = TcpStream::connect(start_addr).await?;
-        self.write_auth(&mut s).await?;
-        let line = format!("TOPOLOGY DONE {} {}\n", token, history);
-        s.write_all(line.as_bytes()).await?;
-        Ok(())
+/// `name@port` for a named node, the bare port otherwise.
+pub fn edge_label(name: &str, addr: &str) -> String {
+    let port = encode_endpoint(port_str(addr));
+    if name.is_empty() {
+        port.to_string()
+    } else {
+        format!("{name}@{port}")
     }
 }
 
-// --- WALK utility
+/// Split a walk endpoint into `(name, port)`; plain `port` endpoints
+/// (unnamed nodes, pre-label peers) yield `None` for the name.
+pub fn split_label(endpoint: &str) -> (Option<&str>, &str) {
+    match endpoint.split_once('@') {
+        Some((name, port)) => (Some(name), port),
+        None => (None, endpoint),
+    }
+}
 
-pub fn port_str(addr: &str) -> &str {
-    addr.rsplit(':').next().unwrap_or(addr)
+/// Node names go into walk histories, so they must avoid the history
+/// separators (`;`, `@`, `->`) and whitespace.
+pub fn validate_node_name(name: &str) -> Result<&str, String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("node name must be 1-64 characters".into());
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+    {
+        return Err(format!(
+            "invalid node name '{name}': use ASCII letters, digits, '-', '_' or '.'"
+        ));
+    }
+    Ok(name)
 }
 
-pub fn append_edge(mut history: String, from_addr: &str, to_addr: &str) -> String {
-    let from = port_str(from_addr);
-    let to = port_str(to_addr);
-    let edge = format!("{from}->{to}");
-    if history.is_empty() {
-        edge
-    } else {
-        history.push(';');
-        history.push_str(&edge);
-        history
+/// Ring names travel as one protocol token, so they use the node-name
+/// alphabet.
+pub fn validate_ring_name(ring: &str) -> Result<&str, String> {
+    if ring.is_empty() || ring.len() > 64 {
+        return Err("ring name must be 1-64 characters".into());
+    }
+    if !ring
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+    {
+        return Err(format!(
+            "invalid ring name '{ring}': use ASCII letters, digits, '-', '_' or '.'"
+        ));
     }
+    Ok(ring)
 }
 
 impl Node {
+    pub fn name(&self) -> &str {
+        &self.opts.name
+    }
+
     pub async fn first_walk_history(&self) -> Option<String> {
+        self.first_walk_history_on(DEFAULT_RING).await
+    }
+
+    /// [`Node::first_walk_history`] along `ring_name`.
+    pub async fn first_walk_history_on(&self, ring_name: &str) -> Option<String> {
+        let next = self.get_next_on(ring_name).await?;
+        Some(append_named_edge(
+            String::new(),
+            self.name(),
+            &self.port,
+            &next,
+        ))
+    }
+
+    /// This node's KV value for a `TOPOLOGY WALK-META` key, escaped for a
+    /// history; `?` when unset.
+    pub async fn walk_meta_value(&self, key: &str) -> String {
+        match self.kv_get(key).await {
+            Some(value) => encode_endpoint(&value).into_owned(),
+            None => "?".to_string(),
+        }
+    }
+
+    /// [`Node::first_walk_history`] with this node's endpoint annotated
+    /// `:<value>` for `meta_key`.
+    pub async fn first_walk_meta_history(&self, meta_key: &str) -> Option<String> {
         let next = self.get_next().await?;
-        Some(append_edge(String::new(), &self.port, &next))
+        let value = self.walk_meta_value(meta_key).await;
+        Some(append_meta_edge(
+            String::new(),
+            self.name(),
+            &self.port,
+            &value,
+            &next,
+        ))
+    }
+
+    /// Store the ring size a finished `TOPOLOGY WALK` counted. Returns
+    /// the size before it if that was known and differs.
+    pub fn record_ring_size(&self, size: u64) -> Option<u64> {
+        let old = self.ring_size.swap(size, Ordering::Relaxed);
+        (old != 0 && old != size).then_some(old)
+    }
+
+    /// The TTL a `RING ALL` goes out with: one hop fewer than the last
+    /// counted ring size, so the message reaches every other node once,
+    /// or `--default-ring-ttl` while no walk has counted the ring. Warns
+    /// when the size differs from the one the last `RING ALL` used.
+    pub fn ring_all_ttl(&self) -> u32 {
+        let size = self.ring_size.load(Ordering::Relaxed);
+        if size == 0 {
+            return match self.opts.default_ring_ttl {
+                0 => DEFAULT_RING_TTL,
+                ttl => ttl,
+            };
+        }
+        let last = self.ring_all_size.swap(size, Ordering::Relaxed);
+        if last != 0 && last != size {
+            tracing::warn!(node = %self.port, old = last, new = size, "Ring size changed since the last RING ALL");
+        }
+        u32::try_from(size - 1).unwrap_or(u32::MAX)
+    }
+
+    /// Take in the clock a `RING` message arrived with: log
+    /// `CONCURRENT_EVENTS` if it is concurrent with the last one received,
+    /// merge it, and count the receipt. Returns the clock to send on with
+    /// the message.
+    pub fn observe_clock(&self, received: VectorClock) -> VectorClock {
+        let mut guard = self.vclock.lock().unwrap_or_else(|e| e.into_inner());
+        let (clock, last) = &mut *guard;
+        if let Some(last) = last.as_ref()
+            && last.compare(&received).is_none()
+        {
+            tracing::warn!(
+                node = %self.port,
+                previous = %last.to_wire(),
+                received = %received.to_wire(),
+                "CONCURRENT_EVENTS"
+            );
+        }
+        clock.merge(&received);
+        clock.increment(&self.port);
+        *last = Some(received);
+        clock.clone()
+    }
+
+    /// This node's vector clock, as `NODE STAT` reports it.
+    pub fn vector_clock(&self) -> VectorClock {
+        self.vclock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .clone()
+    }
+
+    /// Runtime counters as reported by `NODE STAT`, in that order.
+    pub fn stat_counters(&self) -> [(&'static str, u64); 9] {
+        [
+            ("UPTIME_SECS", self.started_at.elapsed().as_secs()),
+            ("MSGS_RECEIVED", self.msgs_received.load(Ordering::Relaxed)),
+            (
+                "MSGS_FORWARDED",
+                self.msgs_forwarded.load(Ordering::Relaxed),
+            ),
+            ("WALK_COUNT", self.walks_total.load(Ordering::Relaxed)),
+            (
+                "WALK_CACHE_HITS",
+                self.walk_cache_hits.load(Ordering::Relaxed),
+            ),
+            ("RING_COUNT", self.ring_msgs_total.load(Ordering::Relaxed)),
+            ("ERRORS", self.errors_total.load(Ordering::Relaxed)),
+            ("CONNECTIONS", self.metrics.active_connections()),
+            ("RING_SIZE", self.ring_size.load(Ordering::Relaxed)),
+        ]
+    }
+
+    /// This node's value for a `TOPOLOGY PAY-WALK` key: any `NODE STAT`
+    /// counter (case-insensitive) or `NAME`, else the output of
+    /// `--walk-payload-cmd`. `-` when there is no value.
+    pub async fn walk_payload(&self, key: &str) -> String {
+        if key.eq_ignore_ascii_case("NAME") {
+            return payload_value(self.name());
+        }
+        if let Some((_, v)) = self
+            .stat_counters()
+            .into_iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            return v.to_string();
+        }
+        let Some(cmd) = &self.opts.walk_payload_cmd else {
+            return payload_value("");
+        };
+        let out = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .arg("sh")
+            .arg(key)
+            .kill_on_drop(true)
+            .output();
+        match tokio::time::timeout(WALK_PAYLOAD_CMD_TIMEOUT, out).await {
+            Ok(Ok(out)) => payload_value(&String::from_utf8_lossy(&out.stdout)),
+            Ok(Err(e)) => {
+                tracing::warn!(node = %self.port, key, error = ?e, "walk payload command failed");
+                payload_value("")
+            }
+            Err(_) => {
+                tracing::warn!(node = %self.port, key, "walk payload command timed out");
+                payload_value("")
+            }
+        }
+    }
+
+    /// `payloads` with this node's `<port>=<value>` appended.
+    pub async fn append_walk_payload(&self, mut payloads: String, key: &str) -> String {
+        if !payloads.is_empty() {
+            payloads.push(';');
+        }
+        payloads.push_str(port_str(&self.port));
+        payloads.push('=');
+        payloads.push_str(&self.walk_payload(key).await);
+        payloads
+    }
+
+    /// This node's value for a `GATHER` key (one of
+    /// [`crate::protocol::GATHER_KEYS`]); `-` for an unknown key.
+    pub async fn gather_value(&self, key: &str) -> String {
+        match key {
+            "port" => self.port.clone(),
+            "next" => self.get_next().await.unwrap_or_else(|| "<unset>".into()),
+            "uptime" => self.started_at.elapsed().as_secs().to_string(),
+            "msg_count" => self.msgs_received.load(Ordering::Relaxed).to_string(),
+            _ => payload_value(""),
+        }
+    }
+
+    /// `results` with this node's `<addr>=<value>` appended.
+    pub async fn append_gather_result(&self, mut results: String, key: &str) -> String {
+        if !results.is_empty() {
+            results.push(';');
+        }
+        results.push_str(&self.port);
+        results.push('=');
+        results.push_str(&self.gather_value(key).await);
+        results
+    }
+
+    /// `results` with this node's `<addr>=<value>` for KV `key` appended;
+    /// `-` when the key is unset.
+    pub async fn append_collect_result(&self, mut results: String, key: &str) -> String {
+        if !results.is_empty() {
+            results.push(';');
+        }
+        results.push_str(&self.port);
+        results.push('=');
+        results.push_str(&payload_value(&self.kv_get(key).await.unwrap_or_default()));
+        results
     }
+
+    pub async fn first_reverse_walk_history(&self) -> Option<String> {
+        let prev = self.get_prev().await?;
+        Some(append_named_edge(
+            String::new(),
+            self.name(),
+            &self.port,
+            &prev,
+        ))
+    }
+}
+
+/// How long `--walk-payload-cmd` may run before the hop moves on without it.
+const WALK_PAYLOAD_CMD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First line of `raw`, trimmed, with the payload list's `;` separator
+/// replaced; `-` when nothing is left.
+fn payload_value(raw: &str) -> String {
+    let v = raw.lines().next().unwrap_or("").trim().replace(';', ",");
+    if v.is_empty() { "-".to_string() } else { v }
 }
 
 // --- NETMAP (INVESTIGATION) helpers
 
+/// Everything before the port, brackets included for IPv6 (`[::1]`), so
+/// `format!("{host}:{port}")` rebuilds a dialable address.
 fn host_str(addr: &str) -> &str {
-    addr.split(':').next().unwrap_or("127.0.0.1")
+    addr.rsplit_once(':').map_or(addr, |(host, _)| host)
 }
 
 fn parse_entries(entries: &str) -> HashMap<String, NodeStatus> {
@@ -478,15 +3142,14 @@ impl Node {
 
     pub async fn forward_netmap_hop(
         &self,
+        ring_name: &str,
         token: &str,
         start_addr: &str,
         entries: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(next) = self.get_next().await {
-            let mut s = TcpStream::connect(&next).await?;
-            self.write_auth(&mut s).await?;
+    ) -> Result<(), RingError> {
+        if let Some(next) = self.get_next_on(ring_name).await {
             let line = format!("NETMAP HOP {} {} {}\n", token, start_addr, entries);
-            s.write_all(line.as_bytes()).await?;
+            self.send_line_to(&next, &line).await?;
         }
         Ok(())
     }
@@ -496,11 +3159,9 @@ impl Node {
         start_addr: &str,
         token: &str,
         entries: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut s = TcpStream::connect(start_addr).await?;
-        self.write_auth(&mut s).await?;
+    ) -> Result<(), RingError> {
         let line = format!("NETMAP DONE {} {}\n", token, entries);
-        s.write_all(line.as_bytes()).await?;
+        self.send_line_to(start_addr, &line).await?;
         Ok(())
     }
 
@@ -512,8 +3173,7 @@ impl Node {
             if addr == self.port {
                 continue;
             } // Don't broadcast to self
-            if let Ok(mut s) = TcpStream::connect(&addr).await {
-                let _ = self.write_auth(&mut s).await;
+            if let Ok(mut s) = self.connect(&addr).await {
                 let line = format!("NETMAP SET {}\n", entries);
                 let _ = s.write_all(line.as_bytes()).await;
             }
@@ -556,9 +3216,17 @@ impl Node {
     /// Parses "7000->7001;7001->7002" and stores it
     pub async fn set_topology_from_history(&self, history: &str) {
         let mut map = self.topology_map.write().await;
+        let mut names = self.node_names.write().await;
         map.clear();
         for edge in history.split(';').filter(|s| !s.is_empty()) {
             if let Some((from, to)) = edge.split_once("->") {
+                let (from_name, from) = split_label(from);
+                let (to_name, to) = split_label(to);
+                for (name, port) in [(from_name, from), (to_name, to)] {
+                    if let Some(name) = name {
+                        names.insert(port.to_string(), name.to_string());
+                    }
+                }
                 map.insert(from.to_string(), to.to_string());
             }
         }
@@ -568,10 +3236,18 @@ impl Node {
     /// Serializes topology map back to "7000->7001;7001->7002"
     pub async fn get_topology_history(&self) -> String {
         let map = self.topology_map.read().await;
+        let names = self.node_names.read().await;
+        let label = |port: &str| match names.get(port) {
+            Some(name) => format!("{name}@{port}"),
+            None => port.to_string(),
+        };
         let mut keys: Vec<_> = map.keys().cloned().collect();
         keys.sort_unstable();
         keys.into_iter()
-            .map(|k| format!("{}->{}", k, map.get(&k).unwrap_or(&"".to_string())))
+            .map(|k| {
+                let to = map.get(&k).map(String::as_str).unwrap_or("");
+                format!("{}->{}", label(&k), label(to))
+            })
             .collect::<Vec<_>>()
             .join(";")
     }
@@ -591,8 +3267,7 @@ impl Node {
             if addr == self.port {
                 continue;
             }
-            if let Ok(mut s) = TcpStream::connect(&addr).await {
-                let _ = self.write_auth(&mut s).await;
+            if let Ok(mut s) = self.connect(&addr).await {
                 let line = format!("TOPOLOGY SET {}\n", history);
                 let _ = s.write_all(line.as_bytes()).await;
             }
@@ -608,7 +3283,11 @@ impl Node {
 #[cfg(test)]
 mod tests {
     use super::{
-        FsyncMode, Node, append_edge, host_str, parse_entries, port_str, serialize_entries,
+        CircuitBreaker, CircuitState, DEFAULT_BIND_HOST, DEFAULT_RING, FsyncMode, Node, NodeOpts,
+        annotate_last_target, append_edge, append_meta_edge, append_named_edge, decode_edge,
+        encode_endpoint, host_str, join_host_port, label_last_target, normalize_addr,
+        normalize_addr_on, parse_entries, payload_value, port_str, probe_round,
+        resolve_listen_addr, serialize_entries, split_label, validate_node_name,
     };
     use crate::NodeStatus;
     use crate::auth::AuthToken;
@@ -634,6 +3313,29 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn outbound_connections_set_nodelay() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let node = test_node("127.0.0.1:7000");
+        let s = node.connect(&addr).await.unwrap();
+        assert!(s.tcp().unwrap().nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn late_probe_from_an_earlier_round_is_ignored() {
+        let node = test_node("127.0.0.1:7000");
+        let (round, old, mut old_rx) = node.start_probe().await;
+        assert_eq!(probe_round(&old), Some(round));
+        let (_, current, current_rx) = node.start_probe().await;
+
+        assert!(!node.finish_probe(&old).await);
+        assert!(old_rx.try_recv().is_err(), "earlier round was finished");
+        assert!(node.finish_probe(&current).await);
+        assert_eq!(current_rx.await.unwrap(), Ok(String::new()));
+        assert_eq!(probe_round("7000-1"), None);
+    }
+
     #[test]
     fn port_str_ipv4() {
         assert_eq!(port_str("127.0.0.1:7000"), "7000");
@@ -658,6 +3360,12 @@ mod tests {
         assert_eq!(port_str("[::1]:7000"), "7000");
     }
 
+    #[test]
+    fn port_str_unix_socket_is_file_name() {
+        assert_eq!(port_str("unix:/tmp/ring/n0.sock"), "n0.sock");
+        assert_eq!(port_str("unix:n0.sock"), "n0.sock");
+    }
+
     #[test]
     fn append_edge_first() {
         let h = append_edge(String::new(), "127.0.0.1:7000", "127.0.0.1:7001");
@@ -670,8 +3378,104 @@ mod tests {
         assert_eq!(h, "7000->7001;7001->7002");
     }
 
+    #[test]
+    fn append_edge_to_empty_history_is_just_the_edge() {
+        assert_eq!(append_edge(String::new(), "7000", "7001"), "7000->7001");
+        assert_eq!(
+            decode_edge("7000->7001"),
+            Some(("7000".into(), "7001".into()))
+        );
+    }
+
+    #[test]
+    fn append_edge_many() {
+        let mut h = String::new();
+        for (from, to) in [(7000, 7001), (7001, 7002), (7002, 7000)] {
+            h = append_edge(h, &format!("127.0.0.1:{from}"), &format!("127.0.0.1:{to}"));
+        }
+        assert_eq!(h, "7000->7001;7001->7002;7002->7000");
+        let edges: Vec<_> = h.split(';').map(|e| decode_edge(e).unwrap()).collect();
+        assert_eq!(edges[2], ("7002".into(), "7000".into()));
+    }
+
+    #[test]
+    fn append_edge_ipv6_and_unix_sockets() {
+        let h = append_edge(String::new(), "[::1]:7000", "[fe80::1%eth0]:7001");
+        assert_eq!(h, "7000->7001");
+        let h = append_edge(h, "unix:/tmp/ring/n0.sock", "unix:/tmp/ring/n1.sock");
+        assert_eq!(h, "7000->7001;n0.sock->n1.sock");
+    }
+
+    #[test]
+    fn append_edge_escapes_delimiters_in_addresses() {
+        let h = append_edge(String::new(), "unix:/tmp/a;b.sock", "unix:/tmp/c->d%.sock");
+        assert_eq!(h, "a%3Bb.sock->c-%3Ed%25.sock");
+        assert_eq!(h.split(';').count(), 1);
+        assert_eq!(h.matches("->").count(), 1);
+        assert_eq!(
+            decode_edge(&h),
+            Some(("a;b.sock".into(), "c->d%.sock".into()))
+        );
+        let named = append_named_edge(String::new(), "n0", "unix:x;y", "7001");
+        assert_eq!(decode_edge(&named), Some(("n0@x;y".into(), "7001".into())));
+    }
+
+    #[test]
+    fn decode_edge_rejects_malformed_input() {
+        assert_eq!(decode_edge("7000"), None);
+        assert_eq!(decode_edge("70%2->7001"), None);
+        assert_eq!(decode_edge("7000->7001%"), None);
+        assert_eq!(decode_edge("7000->7001%ZZ"), None);
+    }
+
+    #[test]
+    fn named_edges_label_both_ends_as_the_walk_progresses() {
+        let h = append_named_edge(String::new(), "node-00", "127.0.0.1:7000", "127.0.0.1:7001");
+        assert_eq!(h, "node-00@7000->7001");
+        let h = label_last_target(h, "node-01");
+        let h = append_named_edge(h, "node-01", "127.0.0.1:7001", "127.0.0.1:7000");
+        let h = label_last_target(h, "node-00");
+        assert_eq!(h, "node-00@7000->node-01@7001;node-01@7001->node-00@7000");
+    }
+
+    #[test]
+    fn meta_edges_annotate_both_ends() {
+        let h = append_meta_edge(String::new(), "", "127.0.0.1:7000", "red", "127.0.0.1:7001");
+        assert_eq!(h, "7000:red->7001");
+        let h = annotate_last_target(label_last_target(h, "b"), "?");
+        let h = append_meta_edge(h, "b", "127.0.0.1:7001", "?", "127.0.0.1:7000");
+        let h = annotate_last_target(h, "red");
+        assert_eq!(h, "7000:red->b@7001:?;b@7001:?->7000:red");
+        assert_eq!(annotate_last_target(String::new(), "x"), "");
+        assert_eq!(encode_endpoint("dark blue;x"), "dark%20blue%3Bx");
+    }
+
+    #[test]
+    fn label_last_target_noop_cases() {
+        assert_eq!(label_last_target("7000->7001".into(), ""), "7000->7001");
+        assert_eq!(label_last_target("".into(), "a"), "");
+        assert_eq!(
+            label_last_target("7000->b@7001".into(), "a"),
+            "7000->b@7001"
+        );
+    }
+
+    #[test]
+    fn split_label_named_and_bare() {
+        assert_eq!(split_label("node-00@7000"), (Some("node-00"), "7000"));
+        assert_eq!(split_label("7000"), (None, "7000"));
+    }
+
+    #[test]
+    fn validate_node_name_rejects_separators() {
+        assert!(validate_node_name("node-00").is_ok());
+        for bad in ["", "a;b", "a@b", "a b", "a>b", &"x".repeat(65)] {
+            assert!(validate_node_name(bad).is_err(), "{bad:?}");
+        }
+    }
+
     // --- host_str (private fn): pinned for documentation parity with
-    //     server.rs::host_of. host_str splits on the first colon.
+    //     server.rs::host_of. host_str splits on the last colon.
 
     #[test]
     fn host_str_ipv4() {
@@ -680,27 +3484,81 @@ mod tests {
 
     #[test]
     fn host_str_no_colon_returns_input() {
-        // `addr.split(':').next()` always yields the input on non-empty
-        // strings; the `unwrap_or("127.0.0.1")` fallback is only reached
-        // when split returns None — which never happens. So a colon-less
-        // input is returned verbatim.
+        // No port separator: the input is returned verbatim.
         assert_eq!(host_str("foo"), "foo");
     }
 
     #[test]
     fn host_str_empty_returns_empty() {
-        // Documents that the "127.0.0.1" fallback path in the source is
-        // effectively unreachable: split on an empty string yields a
-        // single empty chunk, not None, so we get "" not "127.0.0.1".
         assert_eq!(host_str(""), "");
     }
 
     #[test]
-    fn host_str_ipv6_brackets_known_gap() {
-        // Mirrors the `host_of_ipv6_bracket_pin` in server.rs — the simple
-        // `split(':')` strategy doesn't handle IPv6 brackets. Pinning the
-        // current behavior so a future fix has a visible regression target.
-        assert_eq!(host_str("[::1]:7000"), "[");
+    fn host_str_ipv6_brackets() {
+        // Splitting on the last colon keeps the bracketed literal intact.
+        assert_eq!(host_str("[::1]:7000"), "[::1]");
+        assert_eq!(host_str("[fe80::1]:7000"), "[fe80::1]");
+    }
+
+    // --- join_host_port / normalize_addr
+
+    #[test]
+    fn join_host_port_brackets_ipv6() {
+        assert_eq!(join_host_port("127.0.0.1", 7000), "127.0.0.1:7000");
+        assert_eq!(join_host_port("localhost", 7000), "localhost:7000");
+        assert_eq!(join_host_port("::1", 7000), "[::1]:7000");
+        assert_eq!(join_host_port("[::1]", 7000), "[::1]:7000");
+    }
+
+    #[test]
+    fn normalize_addr_bare_port() {
+        assert_eq!(normalize_addr("7001"), "127.0.0.1:7001");
+    }
+
+    #[test]
+    fn normalize_addr_socket_addrs_pass_through() {
+        assert_eq!(normalize_addr("127.0.0.1:7001"), "127.0.0.1:7001");
+        assert_eq!(normalize_addr("[::1]:7000"), "[::1]:7000");
+        assert_eq!(normalize_addr("localhost:7000"), "localhost:7000");
+    }
+
+    #[test]
+    fn normalize_addr_bare_ipv6_gets_default_port() {
+        // Used to be wrapped as "127.0.0.1:::1".
+        assert_eq!(normalize_addr("::1"), "[::1]:9000");
+        assert_eq!(normalize_addr("10.0.0.5"), "10.0.0.5:9000");
+    }
+
+    #[test]
+    fn normalize_addr_on_binds_bare_port_on_host() {
+        assert_eq!(normalize_addr_on("7001", "0.0.0.0"), "0.0.0.0:7001");
+        assert_eq!(normalize_addr_on("7001", "::"), "[::]:7001");
+        assert_eq!(
+            normalize_addr_on("10.0.0.5:7001", "0.0.0.0"),
+            "10.0.0.5:7001"
+        );
+    }
+
+    #[test]
+    fn listen_addr_priority() {
+        let host = "0.0.0.0";
+        assert_eq!(
+            resolve_listen_addr(Some("10.0.0.5:7001"), Some(7002), Some("7003"), host),
+            "10.0.0.5:7001"
+        );
+        assert_eq!(
+            resolve_listen_addr(None, Some(7002), Some("7003"), host),
+            "0.0.0.0:7002"
+        );
+        assert_eq!(
+            resolve_listen_addr(None, None, Some("7003"), host),
+            "0.0.0.0:7003"
+        );
+        assert_eq!(resolve_listen_addr(None, None, None, host), "0.0.0.0:9000");
+        assert_eq!(
+            resolve_listen_addr(None, Some(7002), None, DEFAULT_BIND_HOST),
+            "127.0.0.1:7002"
+        );
     }
 
     // --- parse_entries / serialize_entries
@@ -860,6 +3718,18 @@ mod tests {
         assert_eq!(s, original);
     }
 
+    #[tokio::test]
+    async fn topology_history_keeps_names() {
+        let node = test_node("127.0.0.1:7000");
+        let original = "a@7000->b@7001;b@7001->a@7000";
+        node.set_topology_from_history(original).await;
+        assert_eq!(
+            node.topology_map.read().await.get("7000"),
+            Some(&"7001".to_string())
+        );
+        assert_eq!(node.get_topology_history().await, original);
+    }
+
     // --- file_tags round-trip
 
     #[tokio::test]
@@ -927,10 +3797,23 @@ mod tests {
         // No next set; forward should silently succeed without attempting
         // any TCP connection.
         let node = test_node("127.0.0.1:7000");
-        let res = node.forward_ring_forward(0, "msg").await;
+        let res = node.forward_ring_forward(DEFAULT_RING, 1, 0, "msg").await;
         assert!(res.is_ok());
     }
 
+    #[tokio::test]
+    async fn prev_pointer_independent_of_next() {
+        let node = test_node("127.0.0.1:7001");
+        assert!(node.first_reverse_walk_history().await.is_none());
+        node.set_prev("127.0.0.1:7000".into()).await;
+        assert_eq!(node.get_next().await, None);
+        assert_eq!(node.get_prev().await.as_deref(), Some("127.0.0.1:7000"));
+        assert_eq!(
+            node.first_reverse_walk_history().await.as_deref(),
+            Some("7001->7000")
+        );
+    }
+
     #[tokio::test]
     async fn broadcast_netmap_no_other_hosts_is_noop() {
         // The only entry is self; the loop's self-skip means no TCP attempts.
@@ -992,4 +3875,164 @@ mod tests {
         assert!(dbg.contains("port"), "missing port in debug: {dbg}");
         assert!(dbg.contains("7000"), "missing port value: {dbg}");
     }
+
+    #[test]
+    fn payload_value_keeps_first_line_and_escapes_separator() {
+        assert_eq!(payload_value("  12 GB free\nsecond\n"), "12 GB free");
+        assert_eq!(payload_value("a;b"), "a,b");
+        assert_eq!(payload_value("\n"), "-");
+    }
+
+    #[tokio::test]
+    async fn walk_payload_builtins_and_command() {
+        let n = test_node("127.0.0.1:7000");
+        n.errors_total.store(4, Ordering::Relaxed);
+        assert_eq!(n.walk_payload("errors").await, "4");
+        assert_eq!(n.walk_payload("NAME").await, "-");
+        assert_eq!(n.walk_payload("disk").await, "-");
+
+        let n = Node::with_opts(
+            "127.0.0.1:7001".into(),
+            Duration::ZERO,
+            1 << 30,
+            PathBuf::from("/tmp/ouroboros_unit_unused"),
+            false,
+            FsyncMode::None,
+            AuthToken::disabled(),
+            Duration::ZERO,
+            0,
+            NodeOpts {
+                walk_payload_cmd: Some("echo \"got $1\"".into()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(n.walk_payload("disk").await, "got disk");
+        let pays = n.append_walk_payload("7000=1".into(), "disk").await;
+        assert_eq!(pays, "7000=1;7001=got disk");
+    }
+
+    #[tokio::test]
+    async fn ring_req_hook_reply_is_one_line() {
+        let n = test_node("127.0.0.1:7000");
+        assert_eq!(n.on_ring_req("q").await, None);
+        n.set_ring_req_hook(std::sync::Arc::new(|msg| {
+            (msg == "q").then(|| " answer\nsecond line".to_string())
+        }))
+        .await;
+        assert_eq!(n.on_ring_req("q").await.as_deref(), Some("answer"));
+        assert_eq!(n.on_ring_req("other").await, None);
+    }
+
+    #[test]
+    fn elect_candidate_keeps_the_higher_address() {
+        let n = test_node("127.0.0.1:7001");
+        assert_eq!(n.elect_candidate("127.0.0.1:7000"), "127.0.0.1:7001");
+        assert_eq!(n.elect_candidate("127.0.0.1:7002"), "127.0.0.1:7002");
+        assert_eq!(n.elect_candidate("127.0.0.1:7001"), "127.0.0.1:7001");
+    }
+
+    #[tokio::test]
+    async fn kv_set_enforces_limits() {
+        let n = Node::with_opts(
+            "127.0.0.1:7000".into(),
+            Duration::ZERO,
+            1 << 30,
+            PathBuf::from("/tmp/ouroboros_unit_unused"),
+            false,
+            FsyncMode::None,
+            AuthToken::disabled(),
+            Duration::ZERO,
+            0,
+            NodeOpts {
+                kv_max_key_bytes: 3,
+                kv_max_value_bytes: 5,
+                ..Default::default()
+            },
+        );
+        n.kv_set("abc".into(), "hello".into()).await.unwrap();
+        assert_eq!(n.kv_get("abc").await.as_deref(), Some("hello"));
+        let err = n.kv_set("abcd".into(), "x".into()).await.unwrap_err();
+        assert!(err.contains("key too long"), "{err}");
+        let err = n.kv_set("abc".into(), "hello!".into()).await.unwrap_err();
+        assert!(err.contains("value too long"), "{err}");
+        assert_eq!(n.kv_get("abc").await.as_deref(), Some("hello"));
+        assert_eq!(n.kv_get("nope").await, None);
+    }
+
+    #[tokio::test]
+    async fn gather_values_and_results() {
+        let n = test_node("127.0.0.1:7000");
+        n.msgs_received.store(3, Ordering::Relaxed);
+        assert_eq!(n.gather_value("port").await, "127.0.0.1:7000");
+        assert_eq!(n.gather_value("next").await, "<unset>");
+        assert_eq!(n.gather_value("msg_count").await, "3");
+        assert_eq!(n.gather_value("uptime").await, "0");
+        let results = n
+            .append_gather_result("127.0.0.1:7001=9".into(), "msg_count")
+            .await;
+        assert_eq!(results, "127.0.0.1:7001=9;127.0.0.1:7000=3");
+    }
+
+    #[tokio::test]
+    async fn collect_results_read_the_kv_store() {
+        let n = test_node("127.0.0.1:7000");
+        assert_eq!(
+            n.append_collect_result(String::new(), "color").await,
+            "127.0.0.1:7000=-"
+        );
+        n.kv_set("color".into(), "red;blue".into()).await.unwrap();
+        let results = n
+            .append_collect_result("127.0.0.1:7001=green".into(), "color")
+            .await;
+        assert_eq!(results, "127.0.0.1:7001=green;127.0.0.1:7000=red,blue");
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_after_threshold_and_probes_after_reset() {
+        let cb = CircuitBreaker::new(2, Duration::from_millis(50));
+        assert!(cb.allow("a:1").await);
+        assert!(!cb.record("a:1", false).await);
+        assert!(cb.allow("a:1").await, "one failure is below threshold");
+        assert!(cb.record("a:1", false).await, "second failure opens");
+        assert!(!cb.allow("a:1").await);
+        assert!(cb.allow("b:2").await, "circuits are per peer");
+        assert_eq!(
+            cb.snapshot().await,
+            vec![("a:1".to_string(), CircuitState::Open, 2)]
+        );
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cb.allow("a:1").await, "reset timeout lets a probe through");
+        assert!(!cb.allow("a:1").await, "only one probe at a time");
+        assert_eq!(cb.snapshot().await[0].1, CircuitState::HalfOpen);
+
+        // A failed probe re-opens without reporting a fresh trip.
+        assert!(!cb.record("a:1", false).await);
+        assert!(!cb.allow("a:1").await);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cb.allow("a:1").await);
+        cb.record("a:1", true).await;
+        assert!(cb.allow("a:1").await);
+        assert!(cb.snapshot().await.is_empty(), "success forgets the peer");
+    }
+
+    #[tokio::test]
+    async fn success_resets_consecutive_failures() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(30));
+        cb.record("a:1", false).await;
+        cb.record("a:1", true).await;
+        cb.record("a:1", false).await;
+        assert!(cb.allow("a:1").await);
+    }
+
+    #[tokio::test]
+    async fn zero_threshold_disables_breaker() {
+        let cb = CircuitBreaker::new(0, Duration::from_secs(30));
+        for _ in 0..10 {
+            assert!(!cb.record("a:1", false).await);
+        }
+        assert!(cb.allow("a:1").await);
+        assert!(cb.snapshot().await.is_empty());
+    }
 }
@@ -0,0 +1,183 @@
+//! Bounded pool of idle outbound ring connections, keyed by remote address.
+//!
+//! Hop forwards (`RING FORWARD`, `RING ACK-HOP`, `TOPOLOGY HOP`,
+//! `TOPOLOGY REVERSE-HOP`) check a connection out, send one line, wait for
+//! the peer's `OK`, and check it back in. The receiving `client_loop`
+//! already serves any number of lines per connection, so a pooled stream
+//! needs nothing special on the server side. See `Node::send_pooled` for
+//! the dead-connection retry.
+//!
+//! With `--mux` the pool is bypassed: [`MuxPool`] keeps one multiplexed
+//! connection per remote address instead, and each forward opens a
+//! channel on it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::mux::MuxStream;
+use crate::transport::Stream;
+
+/// Default `--pool-size`.
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// How long a connection may sit idle before it is dropped instead of
+/// reused. Kept under the server's default 60 s `--idle-timeout` so the
+/// peer rarely closes a connection the pool still holds.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Idle<S> {
+    stream: S,
+    since: Instant,
+}
+
+pub struct ConnectionPool<S = Stream> {
+    max_per_addr: usize,
+    idle_timeout: Duration,
+    idle: Mutex<HashMap<String, Vec<Idle<S>>>>,
+
+    /// Checkouts served from the pool. Lets tests assert reuse.
+    pub reused: AtomicU64,
+}
+
+impl<S> ConnectionPool<S> {
+    /// `max_per_addr == 0` disables pooling: `checkout` always misses and
+    /// `checkin` drops the stream.
+    pub fn new(max_per_addr: usize, idle_timeout: Duration) -> Self {
+        Self {
+            max_per_addr,
+            idle_timeout,
+            idle: Mutex::new(HashMap::new()),
+            reused: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_per_addr > 0
+    }
+
+    /// Take the most recently returned live connection to `addr`, if any.
+    pub async fn checkout(&self, addr: &str) -> Option<S> {
+        let mut idle = self.idle.lock().await;
+        let conns = idle.get_mut(addr)?;
+        while let Some(conn) = conns.pop() {
+            if conn.since.elapsed() < self.idle_timeout {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                return Some(conn.stream);
+            }
+        }
+        None
+    }
+
+    /// Return a healthy connection. Dropped if `addr` already has
+    /// `max_per_addr` idle connections.
+    pub async fn checkin(&self, addr: &str, stream: S) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut idle = self.idle.lock().await;
+        let conns = idle.entry(addr.to_string()).or_default();
+        conns.retain(|c| c.since.elapsed() < self.idle_timeout);
+        if conns.len() < self.max_per_addr {
+            conns.push(Idle {
+                stream,
+                since: Instant::now(),
+            });
+        }
+    }
+
+    pub async fn idle_count(&self, addr: &str) -> usize {
+        self.idle.lock().await.get(addr).map_or(0, Vec::len)
+    }
+}
+
+/// One [`MuxStream`] per remote address, for `--mux`.
+#[derive(Default)]
+pub struct MuxPool {
+    conns: Mutex<HashMap<String, Arc<MuxStream>>>,
+
+    /// Connections opened. Lets tests assert sharing.
+    pub opened: AtomicU64,
+}
+
+impl MuxPool {
+    /// The open connection to `addr`, if any. One that has closed is
+    /// forgotten.
+    pub async fn get(&self, addr: &str) -> Option<Arc<MuxStream>> {
+        let mut conns = self.conns.lock().await;
+        match conns.get(addr) {
+            Some(mux) if !mux.is_closed() => Some(Arc::clone(mux)),
+            Some(_) => {
+                conns.remove(addr);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Keep `mux` as the connection to `addr`, replacing any other.
+    pub async fn insert(&self, addr: &str, mux: Arc<MuxStream>) {
+        self.opened.fetch_add(1, Ordering::Relaxed);
+        self.conns.lock().await.insert(addr.to_string(), mux);
+    }
+
+    /// Forget `mux` if it is still the connection to `addr`.
+    pub async fn remove(&self, addr: &str, mux: &Arc<MuxStream>) {
+        let mut conns = self.conns.lock().await;
+        if conns.get(addr).is_some_and(|m| Arc::ptr_eq(m, mux)) {
+            conns.remove(addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checkout_returns_checked_in_stream() {
+        let pool = ConnectionPool::new(4, DEFAULT_POOL_IDLE_TIMEOUT);
+        assert!(pool.checkout("a:1").await.is_none());
+        pool.checkin("a:1", 7u32).await;
+        assert_eq!(pool.checkout("a:1").await, Some(7));
+        assert_eq!(pool.reused.load(Ordering::Relaxed), 1);
+        assert!(pool.checkout("a:1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn pools_are_per_address() {
+        let pool = ConnectionPool::new(4, DEFAULT_POOL_IDLE_TIMEOUT);
+        pool.checkin("a:1", 1u32).await;
+        assert!(pool.checkout("b:2").await.is_none());
+        assert_eq!(pool.idle_count("a:1").await, 1);
+    }
+
+    #[tokio::test]
+    async fn checkin_beyond_max_drops_stream() {
+        let pool = ConnectionPool::new(2, DEFAULT_POOL_IDLE_TIMEOUT);
+        for i in 0..3u32 {
+            pool.checkin("a:1", i).await;
+        }
+        assert_eq!(pool.idle_count("a:1").await, 2);
+    }
+
+    #[tokio::test]
+    async fn expired_streams_are_not_reused() {
+        let pool = ConnectionPool::new(4, Duration::from_millis(10));
+        pool.checkin("a:1", 1u32).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(pool.checkout("a:1").await.is_none());
+        assert_eq!(pool.reused.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn zero_size_disables_pooling() {
+        let pool = ConnectionPool::new(0, DEFAULT_POOL_IDLE_TIMEOUT);
+        assert!(!pool.is_enabled());
+        pool.checkin("a:1", 1u32).await;
+        assert!(pool.checkout("a:1").await.is_none());
+    }
+}
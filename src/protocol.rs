@@ -4,9 +4,24 @@
 //!
 //! NODE
 //!   - "NODE NEXT <addr>" (client -> any node)
+//!   - "NODE PREV <addr>" (client -> any node)
+//!   - "NODE NEXT-RING <ring> <addr>" (client -> any node; NEXT on a named ring)
+//!   - "NODE RING <ring>" (client -> any node; `NEXT <addr>` on that ring)
+//!   - "NODE NEXT-MULTI <addr> <addr>..." (client -> any node; RING FORWARD fans out to all)
+//!   - "NODE NEXT-GEN <addr> <generation>" (client -> any node; NODE NEXT, refused unless
+//!     <addr> reports that generation)
+//!   - "NODE NEXT-IF <expected> <new_addr>" (client -> any node; NODE NEXT, refused with
+//!     `CAS_FAIL current=<addr>` unless NEXT is <expected> now; `<unset>` matches no NEXT)
+//!   - "NODE NEXT-RINGS <ring>=<addr>;<ring>=<addr>..." (client -> any node; NODE NEXT-RING for
+//!     every pair at once, `OK next_count=<n>`, or `ERR partial_failure: ...` and none)
 //!   - "NODE STATUS"      (client -> any node)
+//!   - "NODE INFO"        (client -> any node; NODE STATUS plus uptime, counters and version
+//!     in one `<KEY> <value>` block ending `OK`)
 //!   - "NODE PING"        (node -> node)
 //!   - "NODE METRICS"     (gateway -> node; aggregated /metrics source)
+//!   - "NODE STAT"        (client -> any node; runtime counters)
+//!   - "NODE CIRCUIT"     (client -> any node; per-peer circuit breaker state)
+//!   - "NODE SHUTDOWN"    (client -> any node; drain and exit)
 //!   - "NODE HEAL"        (client -> any node)
 //!   - "NODE HEAL-HOP <token> <start_addr>" (node -> node)
 //!   - "NODE HEAL-DONE <token>"             (last node -> start node)
@@ -14,12 +29,73 @@
 //!
 //! RING
 //!   - "RING FORWARD <ttl> <message...>"
+//!   - "RING FORWARD-HOP <hop_count> <ttl> <message...>"          (node -> node; RING FORWARD after
+//!     <hop_count> hops, dropped past `--max-ring-hops`)
+//!   - "RING ON <ring> <ttl> <message...>"                        (FORWARD along a named ring)
+//!   - "RING ON-HOP <ring> <hop_count> <ttl> <message...>"        (node -> node; FORWARD-HOP on a named ring)
+//!   - "RING ACK <ttl> <message...>"                              (client -> start node)
+//!   - "RING ACK-HOP <token> <start> <ttl> <seen> <message...>"   (node -> node)
+//!   - "RING ACK-DONE <token> <seen>"                             (last node -> start node)
+//!   - "RING ECHO <ttl> <message...>"                             (client -> start node)
+//!   - "RING ECHO-HOP <token> <start> <ttl> <message...>"         (node -> node)
+//!   - "RING ECHO-DONE <token> <message...>"                      (last node -> start node)
+//!   - "RING COLLECT <ttl> <key>"                                 (client -> start node; "COLLECT_RESULT" first)
+//!   - "RING COLLECT-HOP <token> <start> <ttl> <key> <results>"   (node -> node; `results` is `addr=value;...`)
+//!   - "RING COLLECT-DONE <token> <results>"                      (last node -> start node)
+//!   - "RING REQ <callback> <ttl> <message...>"                   (any -> node; forwarded like FORWARD)
+//!   - "RING RESP <callback> <payload...>"                        (node -> callback address)
+//!   - "RING TIMED <expiry_unix_ms> <message...>"                 (forwarded like FORWARD until the expiry; `OK EXPIRED` after)
+//!   - "RING ONCE <message...>"                                   (client -> start node; `OK DONE` after one lap)
+//!   - "RING ONCE-HOP <token> <hop_count> <start> <message...>"   (node -> node; dropped past
+//!     `--max-ring-hops`)
+//!   - "RING ONCE-DONE <token>"                                   (last node -> start node)
+//!   - "RING ALL <message...>"                                    (client -> any node; FORWARD with the TTL
+//!     that reaches every node of the last counted ring, or `--default-ring-ttl`)
 //!
 //! TOPOLOGY
-//!   - "TOPOLOGY WALK"                       (client -> start node)
-//!   - "TOPOLOGY HOP <token> <start> <hist>" (node -> node; single line)
-//!   - "TOPOLOGY DONE <token> <hist>"        (last node -> start node)
+//!   - "TOPOLOGY WALK"                       (client -> start node; "TOKEN <token>" first, or "CACHED <age_ms>")
+//!   - "TOPOLOGY WALK-FRESH"                 (client -> start node; WALK, never from the cache)
+//!   - "TOPOLOGY ABORT <token>"              (client -> start node)
+//!   - "TOPOLOGY WALK-META <key>"            (client -> start node; "TOKEN <token>" first, each
+//!     endpoint annotated `<label>:<value>` with the node's KV value for `key`, `?` if unset)
+//!   - "TOPOLOGY HOP <token> <start> <hist> <visited> [meta=<key>]" (node -> node; `visited` is `addr,addr,...`)
+//!   - "TOPOLOGY DONE <token> <hist> [meta=<key>]" (last node -> start node)
 //!   - "TOPOLOGY SET <hist>"                 (node -> all nodes)
+//!   - "TOPOLOGY REVERSE-WALK"                       (client -> start node)
+//!   - "TOPOLOGY REVERSE-HOP <token> <start> <hist>" (node -> prev node)
+//!   - "TOPOLOGY REVERSE-DONE <token> <hist>"        (last node -> start node)
+//!   - "TOPOLOGY BIDIR-WALK"                         (client -> start node; a WALK and a REVERSE-WALK at once)
+//!   - "TOPOLOGY PAY-WALK <key>"                           (client -> start node; "TOKEN <token>" first)
+//!   - "TOPOLOGY PAY-HOP <token> <start> <key> <hist> <pays>" (node -> node; `pays` is `addr=value;...`)
+//!   - "TOPOLOGY PAY-DONE <token> <hist> <pays>"             (last node -> start node)
+//!   - "TOPOLOGY WALK-MAX <max_hops>"                                (client -> start node; "TOKEN <token>" first)
+//!   - "TOPOLOGY MAX-HOP <token> <hop_count> <max_hops> <start> <hist>" (node -> node)
+//!   - "TOPOLOGY MAX-DONE <token> <TRUNCATED|COMPLETE> <hist>"       (last node -> start node)
+//!   - "TOPOLOGY WALK-SAMPLE <probability>"                     (client -> start node; "TOKEN <token>" first)
+//!   - "TOPOLOGY SAMPLE-HOP <token> <hop_count> <probability> <start> <hist>" (node -> node; `...` for skipped edges)
+//!   - "TOPOLOGY SAMPLE-DONE <token> <hop_count> <hist>"        (last node -> start node)
+//!   - "TOPOLOGY RING-WALK <ring>"                      (client -> start node)
+//!   - "TOPOLOGY RING-HOP <token> <ring> <start> <hist>" (node -> node on `ring`)
+//!   - "TOPOLOGY RING-DONE <token> <hist>"              (last node -> start node)
+//!   - "TOPOLOGY PROBE-HOP <token> <hop_count> <start>" (node -> node; `run --probe-interval-secs`)
+//!   - "TOPOLOGY PROBE-DONE <token>"       (last node -> start node)
+//!   - "TOPOLOGY WALK-FIND <key> <value...>" (client -> any node; `FOUND <addr>`, the edges walked to
+//!     it and `OK`, or `NOT FOUND` and `OK`)
+//!   - "TOPOLOGY FIND-HOP <token> <start> <hist> <key> <value...>" (node -> node)
+//!   - "TOPOLOGY FIND-FOUND <token> <addr> [<hist>]"              (matching node -> start node)
+//!   - "TOPOLOGY FIND-NOT-FOUND <token>"                          (last node -> start node)
+//!   - "TOPOLOGY WALK-PARALLEL" (client -> any node with `--heartbeat-addr`; `OK <json>`, a
+//!     `crate::topology::ParallelTopology` built from every member's `NODE STATUS`)
+//!
+//! GATHER
+//!   - "GATHER <key>"                              (client -> start node; `addr=value` lines)
+//!   - "GATHER HOP <token> <start> <key> <results>" (node -> node; `results` is `addr=value;...`)
+//!   - "GATHER DONE <token> <results>"             (last node -> start node)
+//!   - "GATHER KEYS"                               (client -> any node)
+//!   - "GATHER STREAM <key>"                       (client -> start node; `STREAM_PORT <port>` and
+//!     `OK` at once, then `DATA <addr>=<value>` lines and `END` on that port)
+//!   - "GATHER STREAM-HOP <token> <start> <key> <results>" (node -> node)
+//!   - "GATHER STREAM-DATA <token> <addr>=<value>" (each node -> start node; ends with `GATHER DONE`)
 //!
 //! NETMAP
 //!   - "NETMAP DISCOVER"                           (client -> start node)
@@ -28,6 +104,18 @@
 //!   - "NETMAP SET <entries>"                      (start node -> every node)
 //!   - "NETMAP GET"                                (client -> any node)
 //!
+//! ELECT
+//!   - "ELECT START"                            (client -> start node; `LEADER <addr>`)
+//!   - "ELECT HOP <token> <start> <candidate>"  (node -> node)
+//!   - "ELECT DONE <token> <candidate>"         (last node -> start node)
+//!   - "ELECT LEADER <start> <addr>"            (start node -> every node)
+//!   - "ELECT CMD <command...>"                (client -> leader; runs the command, or
+//!     `ERR not leader, leader=<addr>` on any other node)
+//!
+//! KV
+//!   - "KV SET <key> <value...>" (client -> any node; node-local)
+//!   - "KV GET <key>"            (client -> any node; `VALUE <key> <value>`)
+//!
 //! FILE
 //!   - "FILE PUSH <size> <name>" (client -> start)
 //!   - "FILE PULL <name>"        (client -> any node)
@@ -48,9 +136,107 @@
 //!     re-fills missing content/ chunks after respawn from the backup/
 //!     the predecessor already holds)
 //!
+//! PROBE (with `run --repair-ring`)
+//!   - "PROBE <dead_addr>" (node -> repair coordinator; the node's NEXT
+//!     stopped answering; see `crate::repair`)
+//!
+//! HEARTBEAT (with `run --heartbeat-addr`)
+//!   - "HEARTBEAT <node_addr> <name> <generation> [<next>]" (node ->
+//!     heartbeat coordinator, every `--heartbeat-interval-secs`; an unnamed
+//!     node sends `<unset>`; see `crate::heartbeat`)
+//!   - "MEMBERS" (client -> heartbeat coordinator; one `MEMBER <addr>
+//!     <name> <generation> <next>` line per live node, then `OK`)
+//!
+//! BARRIER
+//!   - "BARRIER ENTER <barrier_id> <n>"            (client -> any node; one arrival, counted by every node)
+//!   - "BARRIER HOP <barrier_id> <n> <start_addr> <visited>" (node -> node)
+//!   - "BARRIER WAIT <barrier_id> <n>"             (client -> any node; `BARRIER DONE <barrier_id>` once
+//!     the node has counted `n` arrivals, or `ERR` at the timeout)
+//!   - "BARRIER DONE <barrier_id>"                 (client -> any node; releases its waiters now)
+//!
+//! INCR
+//!   - "INCR START <counter_name>" (client -> any node; `INCR_RESULT <counter_name> <value>` and
+//!     `OK` once the increment has been round the ring)
+//!   - "INCR HOP <counter_name> <token> <hop_count> <origin> <current_value>" (node -> node;
+//!     dropped past `--max-walk-hops`)
+//!
+//! SPLIT-BRAIN
+//!   - "SPLIT-BRAIN CHECK" (client -> any node; `OK leader=<addr>`, `<unset>` when no node has one,
+//!     or `SPLIT_BRAIN detected leaders=<addr>,<addr>...` when the nodes disagree)
+//!   - "SPLIT-BRAIN HOP <token> <start_addr> <leaders>" (node -> node)
+//!   - "SPLIT-BRAIN DONE <token> <leaders>"             (last node -> start node)
+//!
+//! CHAOS (fault injection; testing only, see `crate::chaos`)
+//!   - "CHAOS MODE drop <probability>"          (client -> any node; drop that share of RING hops)
+//!   - "CHAOS MODE delay <mean_ms> <stddev_ms>" (client -> any node; hold each RING hop back)
+//!   - "CHAOS MODE corrupt <probability>"       (client -> any node; flip a bit in that share)
+//!   - "CHAOS OFF"                              (client -> any node)
+//!
+//! SUBSCRIBE
+//!   - "SUBSCRIBE <ring|walk|set_next|partition|topology|all>" (client -> any node; `OK`, then
+//!     `EVENT <type> <data>` lines as commands are handled; see
+//!     `crate::events`)
+//!   - "UNSUBSCRIBE" (client -> subscribed node; `OK`, then commands again)
+//!
+//! RING KEY (with `run --routing consistent-hash`; see `crate::chring`)
+//!   - "RING KEY <key> <message...>"     (client -> any node; `KEY_ACK <key>` from the node that
+//!     owns `key` on the hash ring, relayed by the node asked)
+//!   - "RING KEY-HOP <key> <message...>" (node -> owner; delivered there as is)
+//!
+//! NODE FORWARD-TO
+//!   - "NODE FORWARD-TO <target_addr> <command...>" (client -> any node; the node sends the command
+//!     to `target_addr` with the client's `CID:` and answers with the target's reply lines.
+//!     A chain of nested `NODE FORWARD-TO`s longer than `--forward-max-hops` is refused)
+//!
+//! SNAPSHOT
+//!   - "SNAPSHOT"       (client -> any node; the node's state as one line of JSON, then `OK`;
+//!     see `crate::snapshot`)
+//!   - "RESTORE <json>" (client -> any node; apply a SNAPSHOT line whole, or refuse it)
+//!
+//! MUX (sent and accepted by `run --mux` nodes only)
+//!   - "MUX" (node -> node; `OK`, then `FRAME <stream_id> <length>` frames both ways, each
+//!     stream a connection of its own; see `crate::mux`)
+//!
+//! GREETING (opt-in, `run --hello`)
+//!   - "HELLO ouroboros/<major>.<minor> <generation>" (node -> client,
+//!     first line on every accepted connection, before AUTH; see
+//!     `negotiate_version`)
+//!
+//! TRACE CONTEXT (with `run --otlp-endpoint`)
+//!   - "<RING FORWARD | TOPOLOGY HOP line> X-Trace-Context: <traceparent>"
+//!     (node -> node; W3C traceparent, stripped before parsing; see
+//!     `crate::trace`)
+//!
+//! CORRELATION ID (optional)
+//!   - "CID: <id>" (client -> any node, and node -> node on every hop line
+//!     sent while handling the command; a header line tagging the command
+//!     that follows it, with no reply of its own; see `CorrelationId`)
+//!   - "VC: <node>:<count>,..." (client -> any node, and node -> node on
+//!     every hop line sent while handling a `RING` command; a header line
+//!     like `CID:`; see `crate::vclock`)
+//!
+//! COMPRESSED FIELDS (with `run --compress-threshold-bytes`)
+//!   - "z:<base64>" in place of a RING message or a PAY-HOP / PAY-DONE
+//!     payload list (node -> node; a zstd frame, expanded by
+//!     `expand_payload` before dispatch; see `crate::codec`)
+//!
 //! IMPORTANT: the protocol is line-delimited. Any binary payload *follows*
 //! the header line and is exactly <size> bytes long.
 
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::chaos::ChaosMode;
+use crate::codec::decompress_field;
+use crate::error::RingError;
+use crate::events::EventFilter;
+use crate::node::{NodeRole, validate_ring_name};
+use crate::snapshot::NodeSnapshot;
+
 /// Strict filename validator. Allowlist: ASCII alphanumerics, `.`, `-`, `_`.
 /// Empty rejected; length capped at 255 bytes. Names that consist only of
 /// dots (`.`, `..`, `...`) are also rejected — they're either path-special
@@ -83,14 +269,150 @@ pub fn validate_filename(name: &str) -> Result<&str, &'static str> {
     Ok(name)
 }
 
+/// `<major>.<minor>` of the wire protocol. A major bump is a breaking
+/// change; minors only add commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+/// The version this build speaks and announces in its greeting. 2.0:
+/// default-ring hops go out as `RING FORWARD-HOP`, which 1.x nodes can't parse.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 2, minor: 0 };
+
+/// Oldest server version a client of this build will talk to. Raise it
+/// when a server-side change makes older peers unusable.
+pub const MIN_SUPPORTED_VERSION: ProtocolVersion = ProtocolVersion { major: 2, minor: 0 };
+
+const GREETING_PREFIX: &str = "HELLO ouroboros/";
+const GREETING_MAX_LEN: usize = 64;
+const GREETING_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl ProtocolVersion {
+    /// `HELLO ouroboros/<major>.<minor>\n`
+    pub fn greeting(&self) -> String {
+        format!("{GREETING_PREFIX}{self}\n")
+    }
+
+    /// A node's greeting: [`ProtocolVersion::greeting`] plus the node's
+    /// generation, so a client can tell a restarted node from the old one.
+    pub fn node_greeting(&self, generation: u64) -> String {
+        format!("{GREETING_PREFIX}{self} {generation}\n")
+    }
+
+    /// The version in a greeting; a trailing generation is ignored.
+    pub fn parse_greeting(line: &str) -> Result<Self, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let Some(rest) = line.strip_prefix(GREETING_PREFIX) else {
+            return Err(format!("not an ouroboros greeting: {line:?}"));
+        };
+        let version = rest.split(' ').next().unwrap_or(rest);
+        let (major, minor) = version
+            .split_once('.')
+            .ok_or_else(|| format!("malformed protocol version: {version:?}"))?;
+        let parse = |v: &str| {
+            v.parse::<u16>()
+                .map_err(|_| format!("malformed protocol version: {version:?}"))
+        };
+        Ok(Self {
+            major: parse(major)?,
+            minor: parse(minor)?,
+        })
+    }
+
+    /// The generation a node's greeting carries, if any.
+    pub fn greeting_generation(line: &str) -> Option<u64> {
+        let rest = line.trim_end().strip_prefix(GREETING_PREFIX)?;
+        rest.split_once(' ')?.1.parse().ok()
+    }
+
+    /// A server is usable if it is at least `min` and shares our major
+    /// version (a newer major may have dropped commands we send).
+    pub fn is_compatible(&self, min: ProtocolVersion, ours: ProtocolVersion) -> bool {
+        *self >= min && self.major <= ours.major
+    }
+}
+
+/// Client side of the greeting: read the server's `HELLO` line and check
+/// it against `[min, PROTOCOL_VERSION.major]`. Reads byte by byte so nothing
+/// past the greeting is consumed from `reader`.
+pub async fn negotiate_version<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    min: ProtocolVersion,
+) -> Result<ProtocolVersion, String> {
+    let read = async {
+        let mut line = Vec::new();
+        loop {
+            let b = reader
+                .read_u8()
+                .await
+                .map_err(|e| format!("reading greeting: {e}"))?;
+            if b == b'\n' {
+                return Ok(line);
+            }
+            if line.len() >= GREETING_MAX_LEN {
+                return Err("greeting too long".to_string());
+            }
+            line.push(b);
+        }
+    };
+    let line = tokio::time::timeout(GREETING_TIMEOUT, read)
+        .await
+        .map_err(|_| "timed out waiting for greeting".to_string())??;
+    let version = ProtocolVersion::parse_greeting(&String::from_utf8_lossy(&line))?;
+    if !version.is_compatible(min, PROTOCOL_VERSION) {
+        return Err(format!(
+            "incompatible protocol version {version} (need >= {min}, major <= {})",
+            PROTOCOL_VERSION.major
+        ));
+    }
+    Ok(version)
+}
+
 /// Parsed representation of a command line.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     // NODE
     NodeNext(String), // NODE NEXT <addr>
+    NodePrev(String), // NODE PREV <addr>
+    NodeNextRing {
+        ring: String,
+        addr: String,
+    }, // NODE NEXT-RING <ring> <addr>
+    NodeRing {
+        ring: String,
+    }, // NODE RING <ring>
+    /// Several default-ring next hops: `RING FORWARD` goes to each, with
+    /// its own copy of the TTL; everything else follows the first.
+    NodeNextMulti(Vec<String>), // NODE NEXT-MULTI <addr> <addr>...
+    /// `NODE NEXT`, refused unless `addr` reports this generation.
+    NodeNextGen {
+        addr: String,
+        generation: u64,
+    }, // NODE NEXT-GEN <addr> <generation>
+    /// `NODE NEXT`, refused with `CAS_FAIL current=<addr>` unless the
+    /// NEXT is `expected` now.
+    NodeNextIf {
+        expected: String,
+        new_addr: String,
+    }, // NODE NEXT-IF <expected> <new_addr>
+    /// `NODE NEXT-RING` for each `(ring, addr)` pair, applied together or
+    /// not at all. Ring names are distinct.
+    NodeNextRings(Vec<(String, String)>), // NODE NEXT-RINGS <ring>=<addr>;<ring>=<addr>...
     NodeStatus,       // NODE STATUS
+    NodeInfo,         // NODE INFO
     NodePing,         // NODE PING
     NodeMetrics,      // NODE METRICS
+    NodeStat,         // NODE STAT
+    NodeCircuit,      // NODE CIRCUIT
+    NodeShutdown,     // NODE SHUTDOWN
     NodeHeal,         // "NODE HEAL" (client)
     NodeHealHop {
         token: String,
@@ -101,25 +423,280 @@ pub enum Command {
     }, // "NODE HEAL-DONE <token>" (internal)
 
     // RING
+    /// `hop_count` is how many times the message has been forwarded; a
+    /// client's `RING FORWARD` carries 0, and each hop sends it on as
+    /// `RING FORWARD-HOP` with one more.
     RingForward {
+        hop_count: u32,
+        ttl: u32,
+        msg: String,
+    }, // RING FORWARD <ttl> <message...> | RING FORWARD-HOP <hop_count> <ttl> <message...>
+    /// `RingForward` along the named ring; on `"default"` it is the same
+    /// thing. Hops go on as `RING ON-HOP` with `hop_count` one more.
+    RingOn {
+        ring: String,
+        hop_count: u32,
+        ttl: u32,
+        msg: String,
+    }, // RING ON <ring> <ttl> <message...> | RING ON-HOP <ring> <hop_count> <ttl> <message...>
+    /// `RingForward` that reports back: every hop appends its address to a
+    /// `;`-separated `seen` list and the node where the TTL runs out sends
+    /// it to the start node, which answers the client.
+    RingAck {
+        ttl: u32,
+        msg: String,
+    }, // RING ACK <ttl> <message...>
+    RingAckHop {
+        token: String,
+        start_addr: String,
+        ttl: u32,
+        seen: String,
+        msg: String,
+    },
+    RingAckDone {
+        token: String,
+        seen: Vec<String>,
+    },
+    /// `RingForward` whose last hop returns the message to the start
+    /// node, which answers the client with `ECHO <message>`.
+    RingEcho {
+        ttl: u32,
+        msg: String,
+    }, // RING ECHO <ttl> <message...>
+    RingEchoHop {
+        token: String,
+        start_addr: String,
+        ttl: u32,
+        msg: String,
+    },
+    RingEchoDone {
+        token: String,
+        msg: String,
+    },
+    /// `RingAck` that collects each node's `KV GET <key>` value as
+    /// `addr=value` instead of its address; the start node answers
+    /// `COLLECT_RESULT`, one line per node, then `OK`.
+    RingCollect {
+        ttl: u32,
+        key: String,
+    }, // RING COLLECT <ttl> <key>
+    RingCollectHop {
+        token: String,
+        start_addr: String,
         ttl: u32,
+        key: String,
+        results: String,
+    },
+    RingCollectDone {
+        token: String,
+        results: String,
+    },
+
+    /// `RingForward` that every node may answer: a node whose
+    /// `on_ring_req` hook returns a payload sends it to `callback_addr`
+    /// as `RingResp`.
+    RingReq {
+        callback_addr: String,
+        ttl: u32,
+        msg: String,
+    }, // RING REQ <callback> <ttl> <message...>
+    RingResp {
+        callback_addr: String,
+        payload: String,
+    }, // RING RESP <callback> <payload...>
+    /// `RingForward` bounded by a deadline instead of a TTL: every node
+    /// forwards it until `expiry_ms` (Unix milliseconds) has passed.
+    RingTimed {
+        expiry_ms: u64,
+        msg: String,
+    }, // RING TIMED <expiry_unix_ms> <message...>
+    /// `RingForward` bounded by the ring itself instead of a TTL: every
+    /// node forwards it until the next hop would be the start node, which
+    /// then answers the client `OK DONE`.
+    RingOnce {
+        msg: String,
+    }, // RING ONCE <message...>
+    /// `RingForward` with the TTL taken from the ring size the node last
+    /// counted, so every node sees the message once.
+    RingAll {
         msg: String,
-    }, // RING FORWARD <ttl> <message...>
+    }, // RING ALL <message...>
+    /// `hop_count` starts at 1 on the hop the start node sends.
+    RingOnceHop {
+        token: String,
+        hop_count: u32,
+        start_addr: String,
+        msg: String,
+    },
+    RingOnceDone {
+        token: String,
+    },
 
     // TOPOLOGY
     TopologyWalk, // "TOPOLOGY WALK"
+    /// `TopologyWalk` that always walks the ring, even when
+    /// `--walk-cache-ttl-secs` holds a recent result.
+    TopologyWalkFresh, // "TOPOLOGY WALK-FRESH"
+    /// `TopologyWalk` that annotates every endpoint with the node's KV
+    /// value for `key`; the topology is left alone.
+    TopologyWalkMeta {
+        key: String,
+    }, // "TOPOLOGY WALK-META <key>"
+    /// `visited` lists every node the walk has passed through; a node that
+    /// finds itself in it ends the walk instead of looping. Absent from
+    /// older senders, in which case it is empty. `meta_key` is set on a
+    /// `TOPOLOGY WALK-META`.
     TopologyHop {
         token: String,
         start_addr: String,
         history: String,
+        visited: Vec<String>,
+        meta_key: Option<String>,
     },
     TopologyDone {
         token: String,
         history: String,
+        meta_key: Option<String>,
     },
     TopologySet {
         history: String,
     },
+    /// Cancel an in-flight walk on its start node; the waiting client gets
+    /// `ERR walk aborted` instead of sitting out the 30 s timeout.
+    TopologyAbort {
+        token: String,
+    }, // "TOPOLOGY ABORT <token>"
+    /// Same token/oneshot dance as `TopologyWalk`, but each hop follows
+    /// the node's PREV pointer. The result is only reported back to the
+    /// client; it never replaces the forward `topology_map`.
+    TopologyReverseWalk, // "TOPOLOGY REVERSE-WALK"
+    TopologyReverseHop {
+        token: String,
+        start_addr: String,
+        history: String,
+    },
+    TopologyReverseDone {
+        token: String,
+        history: String,
+    },
+    /// A `TopologyWalk` and a `TopologyReverseWalk` started together under
+    /// one token, suffixed `-next` and `-prev` per direction. The client
+    /// gets both merged into an undirected DOT graph.
+    TopologyBidirWalk, // "TOPOLOGY BIDIR-WALK"
+    /// A forward walk that also collects one value per node: each hop
+    /// appends `<port>=<value for payload_key>` to `payloads`. See
+    /// `Node::walk_payload` for the keys a node understands.
+    WalkPayStart {
+        payload_key: String,
+    }, // "TOPOLOGY PAY-WALK <key>"
+    WalkPayHop {
+        token: String,
+        start_addr: String,
+        payload_key: String,
+        history: String,
+        payloads: String,
+    },
+    WalkPayDone {
+        token: String,
+        history: String,
+        payloads: String,
+    },
+    /// A forward walk that stops after `max_hops` edges. It only answers
+    /// the client; a cut-short history never replaces the `topology_map`.
+    WalkMax {
+        max_hops: u32,
+    }, // "TOPOLOGY WALK-MAX <max_hops>"
+    /// `hop_count` is the number of edges already in `history`.
+    WalkMaxHop {
+        token: String,
+        hop_count: u32,
+        max_hops: u32,
+        start_addr: String,
+        history: String,
+    },
+    WalkMaxDone {
+        token: String,
+        truncated: bool,
+        history: String,
+    },
+    /// A forward walk on which each node records its edge with
+    /// `probability` and otherwise leaves a `...`. It only answers the
+    /// client.
+    WalkSample {
+        probability: Probability,
+    }, // "TOPOLOGY WALK-SAMPLE <probability>"
+    /// `hop_count` is the number of edges walked so far, recorded or not.
+    WalkSampleHop {
+        token: String,
+        hop_count: u32,
+        probability: Probability,
+        start_addr: String,
+        history: String,
+    },
+    WalkSampleDone {
+        token: String,
+        hop_count: u32,
+        history: String,
+    },
+    /// `TopologyWalk` along a named ring's NEXT pointers. Like
+    /// `TopologyReverseWalk`, the result only answers the client.
+    WalkRing {
+        ring: String,
+    }, // "TOPOLOGY RING-WALK <ring>"
+    WalkRingHop {
+        token: String,
+        ring: String,
+        start_addr: String,
+        history: String,
+    },
+    WalkRingDone {
+        token: String,
+        history: String,
+    },
+    /// A background partition probe: a walk that carries no history.
+    WalkProbeHop {
+        token: String,
+        hop_count: u32,
+        start_addr: String,
+    }, // "TOPOLOGY PROBE-HOP <token> <hop_count> <start>"
+    WalkProbeDone {
+        token: String,
+    }, // "TOPOLOGY PROBE-DONE <token>"
+
+    // GATHER
+    /// Collect one built-in value (see [`GATHER_KEYS`]) from every node,
+    /// riding the walk token machinery like `TopologyWalk`.
+    GatherStart {
+        key: String,
+    }, // "GATHER <key>"
+    GatherHop {
+        token: String,
+        start_addr: String,
+        key: String,
+        results: String,
+    },
+    GatherDone {
+        token: String,
+        results: String,
+    },
+    GatherKeys, // "GATHER KEYS"
+    /// [`Command::GatherStart`] with the results pushed to a port of
+    /// their own as each node's arrives, instead of all at once.
+    GatherStream {
+        key: String,
+    }, // "GATHER STREAM <key>"
+    /// A `GatherHop` whose node also sends its own entry straight back to
+    /// the start node as [`Command::GatherStreamData`].
+    GatherStreamHop {
+        token: String,
+        start_addr: String,
+        key: String,
+        results: String,
+    }, // "GATHER STREAM-HOP <token> <start> <key> <results>"
+    GatherStreamData {
+        token: String,
+        entry: String,
+    }, // "GATHER STREAM-DATA <token> <addr>=<value>"
 
     // NETMAP
     NetmapDiscover, // "NETMAP DISCOVER"
@@ -137,6 +714,40 @@ pub enum Command {
     }, // "NETMAP SET <entries>"
     NetmapGet, // "NETMAP GET"
 
+    // ELECT
+    /// Leader election: the candidate travels the ring once, each node
+    /// replacing it with its own address if that sorts higher; the
+    /// survivor is announced to every node with `ElectLeader`.
+    ElectStart, // "ELECT START"
+    ElectHop {
+        token: String,
+        start_addr: String,
+        candidate: String,
+    },
+    ElectDone {
+        token: String,
+        candidate: String,
+    },
+    ElectLeader {
+        start_addr: String,
+        leader: String,
+    }, // "ELECT LEADER <start> <addr>"
+    /// Run `cmd` only if this node is the elected leader. Kept as the raw
+    /// line; the leader parses it when it runs it.
+    LeaderCmd {
+        cmd: String,
+    }, // "ELECT CMD <command...>"
+
+    // KV
+    /// Node-local key-value store; see `Node::kv_set`.
+    KvSet {
+        key: String,
+        value: String,
+    }, // "KV SET <key> <value...>"
+    KvGet {
+        key: String,
+    }, // "KV GET <key>"
+
     // FILE
     FilePush {
         size: u64,
@@ -195,625 +806,4378 @@ pub enum Command {
         name: String,
         size: u64,
     }, // "FILE CONTENT-PUSH <name> <size>"
-}
 
-/// Parse one incoming line from the wire into a Command.
-pub fn parse_line(line: &str) -> Result<Command, String> {
-    let trimmed = line.trim_end_matches(['\r', '\n']);
-    let mut parts = trimmed.splitn(2, ' ');
-    let noun = parts.next().unwrap_or("").to_ascii_uppercase();
-    let rest = parts.next().unwrap_or("");
+    // PROBE
+    /// Sent to the repair coordinator, not to a ring node: the sender's
+    /// NEXT stopped answering. See [`crate::repair`].
+    Probe {
+        dead_addr: String,
+    }, // "PROBE <dead_addr>"
 
-    match noun.as_str() {
-        "NODE" => parse_node_cmd(rest),
-        "RING" => parse_ring_cmd(rest),
-        "TOPOLOGY" => parse_topology_cmd(rest),
-        "NETMAP" => parse_netmap_cmd(rest),
-        "FILE" => parse_file_cmd(rest),
-        _ => Err(format!("unknown command namespace: '{}'", noun)),
-    }
-}
+    // HEARTBEAT
+    /// Sent to the heartbeat coordinator, not to a ring node: the sender
+    /// is alive. See [`crate::heartbeat`].
+    Heartbeat {
+        addr: String,
+        name: String,
+        generation: u64,
+        next: Option<String>,
+    }, // "HEARTBEAT <node_addr> <name> <generation> [<next>]"
+    /// Asks the heartbeat coordinator for its live members.
+    Members, // "MEMBERS"
 
-// --- Noun parsers
+    // BARRIER
+    /// One participant reached the barrier. The node counts it and sends
+    /// it round the ring as [`Command::BarrierHop`], so every node counts
+    /// every arrival.
+    Barrier {
+        barrier_id: String,
+        n: u32,
+    }, // "BARRIER ENTER <barrier_id> <n>"
+    /// `visited` lists the nodes that have counted the arrival,
+    /// `;`-separated, so one that comes round again knows it is looping.
+    BarrierHop {
+        barrier_id: String,
+        n: u32,
+        start_addr: String,
+        visited: String,
+    }, // "BARRIER HOP <barrier_id> <n> <start_addr> <visited>"
+    /// Block until the node has counted `n` arrivals at the barrier.
+    BarrierWait {
+        barrier_id: String,
+        n: u32,
+    }, // "BARRIER WAIT <barrier_id> <n>"
+    /// The line a waiter gets once the barrier is reached. Sent to a node,
+    /// it releases that node's waiters whatever the count.
+    BarrierDone {
+        barrier_id: String,
+    }, // "BARRIER DONE <barrier_id>"
 
-fn parse_node_cmd(rest: &str) -> Result<Command, String> {
-    if let Some(addr) = rest.strip_prefix("NEXT ") {
-        let addr = addr.trim();
-        if addr.is_empty() {
-            return Err("missing address for NODE NEXT".into());
+    // INCR
+    /// Add one to a ring-wide counter. Every node bumps its copy as the
+    /// increment passes as [`Command::IncrHop`]; the start node answers
+    /// `INCR_RESULT <counter_name> <value>` and `OK` once it is back.
+    Incr {
+        counter_name: String,
+    }, // "INCR START <counter_name>"
+    /// `token` ties the hop to the start node's pending request, so
+    /// several increments may be in flight. `current_value` is the
+    /// highest count any node on the way reached; `hop_count` starts at 1
+    /// on the hop the start node sends.
+    IncrHop {
+        counter_name: String,
+        token: String,
+        hop_count: u32,
+        origin: String,
+        current_value: u64,
+    }, // "INCR HOP <counter_name> <token> <hop_count> <origin> <current_value>"
+
+    // TOPOLOGY WALK-FIND
+    /// Walk the ring until a node whose KV store holds `key` = `value`.
+    /// The search passes as [`Command::WalkFindHop`] and stops at the
+    /// first match, which reports straight back to the start node.
+    WalkFind {
+        key: String,
+        value: String,
+    }, // "TOPOLOGY WALK-FIND <key> <value...>"
+    /// `history` is the edges walked so far, ending at the receiver.
+    WalkFindHop {
+        token: String,
+        start_addr: String,
+        history: String,
+        key: String,
+        value: String,
+    }, // "TOPOLOGY FIND-HOP <token> <start_addr> <history> <key> <value...>"
+    /// `addr` matched; `history` is the edges from the start node to it.
+    WalkFound {
+        token: String,
+        addr: String,
+        history: String,
+    }, // "TOPOLOGY FIND-FOUND <token> <addr> [<history>]"
+    /// The search got back to the start node without a match.
+    WalkNotFound {
+        token: String,
+    }, // "TOPOLOGY FIND-NOT-FOUND <token>"
+
+    // TOPOLOGY WALK-PARALLEL
+    /// Ask every member the heartbeat coordinator lists for its
+    /// `NODE STATUS` at once, rather than walking the ring.
+    WalkParallel, // "TOPOLOGY WALK-PARALLEL"
+
+    // SPLIT-BRAIN
+    /// Walk the ring collecting every node's LEADER and report whether
+    /// they agree. Passes as [`Command::SplitBrainHop`], each node
+    /// appending `<addr>=<leader>` to `leaders`.
+    SplitBrainCheck, // "SPLIT-BRAIN CHECK"
+    SplitBrainHop {
+        token: String,
+        start_addr: String,
+        leaders: String,
+    }, // "SPLIT-BRAIN HOP <token> <start_addr> <leaders>"
+    SplitBrainDone {
+        token: String,
+        leaders: String,
+    }, // "SPLIT-BRAIN DONE <token> <leaders>"
+
+    // CHAOS
+    /// Mistreat the `RING` hops this node forwards from now on. See
+    /// [`crate::chaos`].
+    ChaosMode {
+        mode: ChaosMode,
+    }, // "CHAOS MODE <mode> <params...>"
+    ChaosOff, // "CHAOS OFF"
+
+    // SUBSCRIBE
+    /// Turn the connection into a stream of `EVENT` lines. See
+    /// [`crate::events`].
+    Subscribe {
+        events: EventFilter,
+    }, // "SUBSCRIBE <ring|walk|set_next|partition|topology|all>"
+    /// Only meaningful on a subscribed connection, where the subscribe
+    /// handler reads it itself.
+    Unsubscribe, // "UNSUBSCRIBE"
+
+    // RING KEY
+    /// Deliver `msg` to the member that owns `key` on the hash ring. See
+    /// [`crate::chring`].
+    RingKey {
+        key: String,
+        msg: String,
+    }, // "RING KEY <key> <msg...>"
+    /// `RING KEY` relayed to the owner, which delivers it whatever its own
+    /// ring says, so a message never bounces between two views.
+    RingKeyHop {
+        key: String,
+        msg: String,
+    }, // "RING KEY-HOP <key> <msg...>"
+
+    // NODE FORWARD-TO
+    /// Run `command` on `target` and relay its reply, for a client that
+    /// can only reach this node.
+    ForwardTo {
+        target: String,
+        command: String,
+    }, // "NODE FORWARD-TO <target> <command...>"
+
+    // SNAPSHOT
+    Snapshot, // "SNAPSHOT"
+    /// Checked in full when parsed, so a bad snapshot never reaches the
+    /// node.
+    Restore(Box<NodeSnapshot>), // "RESTORE <json>"
+
+    // MUX
+    /// Turn the connection into frames carrying many logical streams. See
+    /// [`crate::mux`].
+    Mux, // "MUX"
+}
+
+impl Command {
+    /// Length of the raw body that follows the command line on the wire,
+    /// if the command carries one.
+    pub fn body_len(&self) -> Option<u64> {
+        match self {
+            Command::FilePush { size, .. }
+            | Command::FileBackupPush { size, .. }
+            | Command::FileContentPush { size, .. } => Some(*size),
+            Command::FilePushChunk { chunk_size, .. } => Some(*chunk_size),
+            _ => None,
         }
-        return Ok(Command::NodeNext(addr.to_string()));
-    }
-    if rest.eq_ignore_ascii_case("STATUS") {
-        return Ok(Command::NodeStatus);
-    }
-    if rest.eq_ignore_ascii_case("PING") {
-        return Ok(Command::NodePing);
-    }
-    if rest.eq_ignore_ascii_case("METRICS") {
-        return Ok(Command::NodeMetrics);
     }
-    if rest.eq_ignore_ascii_case("HEAL") {
-        return Ok(Command::NodeHeal);
-    }
-    if let Some(rest) = rest.strip_prefix("HEAL-HOP ") {
-        let mut parts = rest.splitn(2, ' ');
-        let token = parts.next().unwrap_or("").trim();
-        let start_addr = parts.next().unwrap_or("").trim();
-        if token.is_empty() || start_addr.is_empty() {
-            return Err("malformed NODE HEAL-HOP".into());
+
+    /// The TTL of a `RING` command that carries one; `--max-ring-ttl`
+    /// caps it.
+    pub fn ring_ttl(&self) -> Option<u32> {
+        match self {
+            Command::RingForward { ttl, .. }
+            | Command::RingOn { ttl, .. }
+            | Command::RingAck { ttl, .. }
+            | Command::RingAckHop { ttl, .. }
+            | Command::RingEcho { ttl, .. }
+            | Command::RingEchoHop { ttl, .. }
+            | Command::RingCollect { ttl, .. }
+            | Command::RingCollectHop { ttl, .. }
+            | Command::RingReq { ttl, .. } => Some(*ttl),
+            _ => None,
         }
-        return Ok(Command::NodeHealHop {
-            token: token.to_string(),
-            start_addr: start_addr.to_string(),
-        });
     }
-    if let Some(token) = rest.strip_prefix("HEAL-DONE ") {
-        let token = token.trim();
-        if token.is_empty() {
-            return Err("malformed NODE HEAL-DONE".into());
+
+    /// The message or payload-list field, for the commands whose field
+    /// `expand_payload` expands from `z:` form.
+    pub fn payload(&self) -> Option<&str> {
+        match self {
+            Command::RingForward { msg, .. }
+            | Command::RingOn { msg, .. }
+            | Command::RingAck { msg, .. }
+            | Command::RingAckHop { msg, .. }
+            | Command::RingEcho { msg, .. }
+            | Command::RingEchoHop { msg, .. }
+            | Command::RingEchoDone { msg, .. }
+            | Command::RingReq { msg, .. }
+            | Command::RingTimed { msg, .. }
+            | Command::RingOnce { msg }
+            | Command::RingAll { msg }
+            | Command::RingOnceHop { msg, .. } => Some(msg),
+            Command::WalkPayHop { payloads, .. } | Command::WalkPayDone { payloads, .. } => {
+                Some(payloads)
+            }
+            _ => None,
         }
-        return Ok(Command::NodeHealDone {
-            token: token.to_string(),
-        });
     }
-
-    Err("unknown NODE command".into())
 }
 
-fn parse_ring_cmd(rest: &str) -> Result<Command, String> {
-    if let Some(rest) = rest.strip_prefix("FORWARD ") {
-        let mut parts = rest.splitn(2, ' ');
-        let ttl_str = parts.next().unwrap_or("").trim();
-        let msg = parts.next().unwrap_or("").to_string();
-        let ttl = ttl_str
-            .parse::<u32>()
-            .map_err(|_| "invalid ttl for RING FORWARD")?;
-        return Ok(Command::RingForward { ttl, msg });
-    }
-    Err("unknown RING command".into())
-}
+/// The command's line as it goes on the wire, without the trailing
+/// newline. [`parse_line`] reads it back as the same command.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::NodeNext(addr) => write!(f, "NODE NEXT {addr}"),
+            Command::NodePrev(addr) => write!(f, "NODE PREV {addr}"),
+            Command::NodeNextMulti(addrs) => write!(f, "NODE NEXT-MULTI {}", addrs.join(" ")),
+            Command::NodeNextRing { ring, addr } => write!(f, "NODE NEXT-RING {ring} {addr}"),
+            Command::NodeNextGen { addr, generation } => {
+                write!(f, "NODE NEXT-GEN {addr} {generation}")
+            }
+            Command::NodeNextIf { expected, new_addr } => {
+                write!(f, "NODE NEXT-IF {expected} {new_addr}")
+            }
+            Command::NodeNextRings(hops) => {
+                f.write_str("NODE NEXT-RINGS ")?;
+                for (i, (ring, addr)) in hops.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ";" };
+                    write!(f, "{sep}{ring}={addr}")?;
+                }
+                Ok(())
+            }
+            Command::NodeRing { ring } => write!(f, "NODE RING {ring}"),
+            Command::NodeStatus => f.write_str("NODE STATUS"),
+            Command::NodeInfo => f.write_str("NODE INFO"),
+            Command::NodePing => f.write_str("NODE PING"),
+            Command::NodeMetrics => f.write_str("NODE METRICS"),
+            Command::NodeStat => f.write_str("NODE STAT"),
+            Command::NodeCircuit => f.write_str("NODE CIRCUIT"),
+            Command::NodeShutdown => f.write_str("NODE SHUTDOWN"),
+            Command::NodeHeal => f.write_str("NODE HEAL"),
+            Command::NodeHealHop { token, start_addr } => {
+                write!(f, "NODE HEAL-HOP {token} {start_addr}")
+            }
+            Command::NodeHealDone { token } => write!(f, "NODE HEAL-DONE {token}"),
 
-fn parse_topology_cmd(rest: &str) -> Result<Command, String> {
-    if rest.eq_ignore_ascii_case("WALK") {
-        return Ok(Command::TopologyWalk);
-    }
-    if let Some(rest) = rest.strip_prefix("HOP ") {
-        let mut parts = rest.splitn(3, ' ');
-        let token = parts.next().unwrap_or("").trim();
-        let start_addr = parts.next().unwrap_or("").trim();
-        let history = parts.next().unwrap_or("").to_string();
-        if token.is_empty() || start_addr.is_empty() {
-            return Err("malformed TOPOLOGY HOP".into());
-        }
-        return Ok(Command::TopologyHop {
-            token: token.to_string(),
-            start_addr: start_addr.to_string(),
-            history,
-        });
-    }
-    if let Some(rest) = rest.strip_prefix("DONE ") {
-        let mut parts = rest.splitn(2, ' ');
-        let token = parts.next().unwrap_or("").trim();
-        let history = parts.next().unwrap_or("").to_string();
-        if token.is_empty() {
-            return Err("malformed TOPOLOGY DONE".into());
+            Command::RingForward {
+                hop_count: 0,
+                ttl,
+                msg,
+            } => write!(f, "RING FORWARD {ttl} {msg}"),
+            Command::RingForward {
+                hop_count,
+                ttl,
+                msg,
+            } => write!(f, "RING FORWARD-HOP {hop_count} {ttl} {msg}"),
+            Command::RingTimed { expiry_ms, msg } => write!(f, "RING TIMED {expiry_ms} {msg}"),
+            Command::RingOnce { msg } => write!(f, "RING ONCE {msg}"),
+            Command::RingAll { msg } => write!(f, "RING ALL {msg}"),
+            Command::RingOnceHop {
+                token,
+                hop_count,
+                start_addr,
+                msg,
+            } => write!(f, "RING ONCE-HOP {token} {hop_count} {start_addr} {msg}"),
+            Command::RingOnceDone { token } => write!(f, "RING ONCE-DONE {token}"),
+            Command::RingOn {
+                ring,
+                hop_count: 0,
+                ttl,
+                msg,
+            } => write!(f, "RING ON {ring} {ttl} {msg}"),
+            Command::RingOn {
+                ring,
+                hop_count,
+                ttl,
+                msg,
+            } => write!(f, "RING ON-HOP {ring} {hop_count} {ttl} {msg}"),
+            Command::RingAck { ttl, msg } => write!(f, "RING ACK {ttl} {msg}"),
+            Command::RingAckHop {
+                token,
+                start_addr,
+                ttl,
+                seen,
+                msg,
+            } => write!(f, "RING ACK-HOP {token} {start_addr} {ttl} {seen} {msg}"),
+            Command::RingAckDone { token, seen } => {
+                write!(f, "RING ACK-DONE {token} {}", seen.join(";"))
+            }
+            Command::RingEcho { ttl, msg } => write!(f, "RING ECHO {ttl} {msg}"),
+            Command::RingEchoHop {
+                token,
+                start_addr,
+                ttl,
+                msg,
+            } => write!(f, "RING ECHO-HOP {token} {start_addr} {ttl} {msg}"),
+            Command::RingEchoDone { token, msg } => write!(f, "RING ECHO-DONE {token} {msg}"),
+            Command::RingCollect { ttl, key } => write!(f, "RING COLLECT {ttl} {key}"),
+            Command::RingCollectHop {
+                token,
+                start_addr,
+                ttl,
+                key,
+                results,
+            } => write!(
+                f,
+                "RING COLLECT-HOP {token} {start_addr} {ttl} {key} {results}"
+            ),
+            Command::RingCollectDone { token, results } => {
+                write!(f, "RING COLLECT-DONE {token} {results}")
+            }
+            Command::RingReq {
+                callback_addr,
+                ttl,
+                msg,
+            } => write!(f, "RING REQ {callback_addr} {ttl} {msg}"),
+            Command::RingResp {
+                callback_addr,
+                payload,
+            } => write!(f, "RING RESP {callback_addr} {payload}"),
+
+            Command::TopologyWalk => f.write_str("TOPOLOGY WALK"),
+            Command::TopologyWalkFresh => f.write_str("TOPOLOGY WALK-FRESH"),
+            Command::TopologyWalkMeta { key } => write!(f, "TOPOLOGY WALK-META {key}"),
+            Command::TopologyHop {
+                token,
+                start_addr,
+                history,
+                visited,
+                meta_key,
+            } => {
+                write!(
+                    f,
+                    "TOPOLOGY HOP {token} {start_addr} {history} {}",
+                    visited.join(",")
+                )?;
+                write_meta_key(f, meta_key)
+            }
+            Command::TopologyDone {
+                token,
+                history,
+                meta_key,
+            } => {
+                write!(f, "TOPOLOGY DONE {token} {history}")?;
+                write_meta_key(f, meta_key)
+            }
+            Command::TopologySet { history } => write!(f, "TOPOLOGY SET {history}"),
+            Command::TopologyAbort { token } => write!(f, "TOPOLOGY ABORT {token}"),
+            Command::TopologyReverseWalk => f.write_str("TOPOLOGY REVERSE-WALK"),
+            Command::TopologyBidirWalk => f.write_str("TOPOLOGY BIDIR-WALK"),
+            Command::TopologyReverseHop {
+                token,
+                start_addr,
+                history,
+            } => write!(f, "TOPOLOGY REVERSE-HOP {token} {start_addr} {history}"),
+            Command::TopologyReverseDone { token, history } => {
+                write!(f, "TOPOLOGY REVERSE-DONE {token} {history}")
+            }
+            Command::WalkPayStart { payload_key } => write!(f, "TOPOLOGY PAY-WALK {payload_key}"),
+            Command::WalkPayHop {
+                token,
+                start_addr,
+                payload_key,
+                history,
+                payloads,
+            } => write!(
+                f,
+                "TOPOLOGY PAY-HOP {token} {start_addr} {payload_key} {history} {payloads}"
+            ),
+            Command::WalkPayDone {
+                token,
+                history,
+                payloads,
+            } => write!(f, "TOPOLOGY PAY-DONE {token} {history} {payloads}"),
+            Command::WalkMax { max_hops } => write!(f, "TOPOLOGY WALK-MAX {max_hops}"),
+            Command::WalkMaxHop {
+                token,
+                hop_count,
+                max_hops,
+                start_addr,
+                history,
+            } => write!(
+                f,
+                "TOPOLOGY MAX-HOP {token} {hop_count} {max_hops} {start_addr} {history}"
+            ),
+            Command::WalkMaxDone {
+                token,
+                truncated,
+                history,
+            } => {
+                let end = if *truncated { "TRUNCATED" } else { "COMPLETE" };
+                write!(f, "TOPOLOGY MAX-DONE {token} {end} {history}")
+            }
+            Command::WalkSample { probability } => {
+                write!(f, "TOPOLOGY WALK-SAMPLE {probability}")
+            }
+            Command::WalkSampleHop {
+                token,
+                hop_count,
+                probability,
+                start_addr,
+                history,
+            } => write!(
+                f,
+                "TOPOLOGY SAMPLE-HOP {token} {hop_count} {probability} {start_addr} {history}"
+            ),
+            Command::WalkSampleDone {
+                token,
+                hop_count,
+                history,
+            } => write!(f, "TOPOLOGY SAMPLE-DONE {token} {hop_count} {history}"),
+            Command::WalkRing { ring } => write!(f, "TOPOLOGY RING-WALK {ring}"),
+            Command::WalkRingHop {
+                token,
+                ring,
+                start_addr,
+                history,
+            } => write!(f, "TOPOLOGY RING-HOP {token} {ring} {start_addr} {history}"),
+            Command::WalkRingDone { token, history } => {
+                write!(f, "TOPOLOGY RING-DONE {token} {history}")
+            }
+            Command::WalkProbeHop {
+                token,
+                hop_count,
+                start_addr,
+            } => {
+                write!(f, "TOPOLOGY PROBE-HOP {token} {hop_count} {start_addr}")
+            }
+            Command::WalkProbeDone { token } => write!(f, "TOPOLOGY PROBE-DONE {token}"),
+
+            Command::GatherStart { key } => write!(f, "GATHER {key}"),
+            Command::GatherHop {
+                token,
+                start_addr,
+                key,
+                results,
+            } => write!(f, "GATHER HOP {token} {start_addr} {key} {results}"),
+            Command::GatherDone { token, results } => write!(f, "GATHER DONE {token} {results}"),
+            Command::GatherKeys => f.write_str("GATHER KEYS"),
+            Command::GatherStream { key } => write!(f, "GATHER STREAM {key}"),
+            Command::GatherStreamHop {
+                token,
+                start_addr,
+                key,
+                results,
+            } => write!(f, "GATHER STREAM-HOP {token} {start_addr} {key} {results}"),
+            Command::GatherStreamData { token, entry } => {
+                write!(f, "GATHER STREAM-DATA {token} {entry}")
+            }
+
+            Command::NetmapDiscover => f.write_str("NETMAP DISCOVER"),
+            Command::NetmapHop {
+                token,
+                start_addr,
+                entries,
+            } => write!(f, "NETMAP HOP {token} {start_addr} {entries}"),
+            Command::NetmapDone { token, entries } => write!(f, "NETMAP DONE {token} {entries}"),
+            Command::NetmapSet { entries } => write!(f, "NETMAP SET {entries}"),
+            Command::NetmapGet => f.write_str("NETMAP GET"),
+
+            Command::ElectStart => f.write_str("ELECT START"),
+            Command::ElectHop {
+                token,
+                start_addr,
+                candidate,
+            } => write!(f, "ELECT HOP {token} {start_addr} {candidate}"),
+            Command::ElectDone { token, candidate } => {
+                write!(f, "ELECT DONE {token} {candidate}")
+            }
+            Command::ElectLeader { start_addr, leader } => {
+                write!(f, "ELECT LEADER {start_addr} {leader}")
+            }
+            Command::LeaderCmd { cmd } => write!(f, "ELECT CMD {cmd}"),
+
+            Command::KvSet { key, value } => write!(f, "KV SET {key} {value}"),
+            Command::KvGet { key } => write!(f, "KV GET {key}"),
+
+            Command::FilePush { size, name } => write!(f, "FILE PUSH {size} {name}"),
+            Command::FilePull { name } => write!(f, "FILE PULL {name}"),
+            Command::FileList => f.write_str("FILE LIST"),
+            Command::FileTagsSet { entries } => write!(f, "FILE TAGS-SET {entries}"),
+            Command::FilePushChunk {
+                name,
+                chunk_size,
+                file_size,
+                parts,
+                index,
+                start_port,
+            } => write!(
+                f,
+                "FILE PUSH-CHUNK {name} {chunk_size} {file_size} {parts} {index} {start_port}"
+            ),
+            Command::FileGetChunk { name } => write!(f, "FILE GET-CHUNK {name}"),
+            Command::FileBackupPush { name, size } => write!(f, "FILE BACKUP-PUSH {name} {size}"),
+            Command::FileGetBackupChunk { name } => write!(f, "FILE GET-BACKUP-CHUNK {name}"),
+            Command::FileContentPush { name, size } => {
+                write!(f, "FILE CONTENT-PUSH {name} {size}")
+            }
+
+            Command::Probe { dead_addr } => write!(f, "PROBE {dead_addr}"),
+            Command::Heartbeat {
+                addr,
+                name,
+                generation,
+                next,
+            } => {
+                write!(f, "HEARTBEAT {addr} {name} {generation}")?;
+                match next {
+                    Some(next) => write!(f, " {next}"),
+                    None => Ok(()),
+                }
+            }
+            Command::Members => f.write_str("MEMBERS"),
+            Command::Barrier { barrier_id, n } => write!(f, "BARRIER ENTER {barrier_id} {n}"),
+            Command::BarrierHop {
+                barrier_id,
+                n,
+                start_addr,
+                visited,
+            } => write!(f, "BARRIER HOP {barrier_id} {n} {start_addr} {visited}"),
+            Command::BarrierWait { barrier_id, n } => write!(f, "BARRIER WAIT {barrier_id} {n}"),
+            Command::BarrierDone { barrier_id } => write!(f, "BARRIER DONE {barrier_id}"),
+            Command::Incr { counter_name } => write!(f, "INCR START {counter_name}"),
+            Command::IncrHop {
+                counter_name,
+                token,
+                hop_count,
+                origin,
+                current_value,
+            } => write!(
+                f,
+                "INCR HOP {counter_name} {token} {hop_count} {origin} {current_value}"
+            ),
+            Command::WalkFind { key, value } => write!(f, "TOPOLOGY WALK-FIND {key} {value}"),
+            Command::WalkFindHop {
+                token,
+                start_addr,
+                history,
+                key,
+                value,
+            } => write!(
+                f,
+                "TOPOLOGY FIND-HOP {token} {start_addr} {history} {key} {value}"
+            ),
+            Command::WalkFound {
+                token,
+                addr,
+                history,
+            } if history.is_empty() => write!(f, "TOPOLOGY FIND-FOUND {token} {addr}"),
+            Command::WalkFound {
+                token,
+                addr,
+                history,
+            } => write!(f, "TOPOLOGY FIND-FOUND {token} {addr} {history}"),
+            Command::WalkNotFound { token } => write!(f, "TOPOLOGY FIND-NOT-FOUND {token}"),
+            Command::WalkParallel => f.write_str("TOPOLOGY WALK-PARALLEL"),
+            Command::SplitBrainCheck => f.write_str("SPLIT-BRAIN CHECK"),
+            Command::SplitBrainHop {
+                token,
+                start_addr,
+                leaders,
+            } => write!(f, "SPLIT-BRAIN HOP {token} {start_addr} {leaders}"),
+            Command::SplitBrainDone { token, leaders } => {
+                write!(f, "SPLIT-BRAIN DONE {token} {leaders}")
+            }
+            Command::ChaosMode { mode } => write!(f, "CHAOS MODE {mode}"),
+            Command::ChaosOff => f.write_str("CHAOS OFF"),
+            Command::Subscribe { events } => write!(f, "SUBSCRIBE {events}"),
+            Command::Unsubscribe => f.write_str("UNSUBSCRIBE"),
+            Command::RingKey { key, msg } => write!(f, "RING KEY {key} {msg}"),
+            Command::RingKeyHop { key, msg } => write!(f, "RING KEY-HOP {key} {msg}"),
+            Command::ForwardTo { target, command } => {
+                write!(f, "NODE FORWARD-TO {target} {command}")
+            }
+            Command::Snapshot => f.write_str("SNAPSHOT"),
+            Command::Restore(snapshot) => write!(f, "RESTORE {}", snapshot.to_line()),
+            Command::Mux => f.write_str("MUX"),
         }
-        return Ok(Command::TopologyDone {
-            token: token.to_string(),
-            history,
-        });
     }
-    if let Some(rest) = rest.strip_prefix("SET ") {
-        return Ok(Command::TopologySet {
-            history: rest.to_string(),
-        });
+}
+
+/// Prefix of the trailing field that marks a `TOPOLOGY WALK-META` hop.
+const META_FIELD: &str = "meta=";
+
+/// ` meta=<key>` after a `TOPOLOGY HOP` or `DONE` line's other fields.
+fn write_meta_key(f: &mut fmt::Formatter<'_>, meta_key: &Option<String>) -> fmt::Result {
+    match meta_key {
+        Some(key) => write!(f, " {META_FIELD}{key}"),
+        None => Ok(()),
     }
-    Err("unknown TOPOLOGY command".into())
 }
 
-fn parse_netmap_cmd(rest: &str) -> Result<Command, String> {
-    if rest.eq_ignore_ascii_case("DISCOVER") {
-        return Ok(Command::NetmapDiscover);
+/// Split a trailing ` meta=<key>` off the last field of a `TOPOLOGY HOP`
+/// or `DONE` line.
+fn split_meta_key(rest: &str) -> (&str, Option<String>) {
+    match rest.rsplit_once(&format!(" {META_FIELD}")) {
+        Some((before, key)) if !key.trim().is_empty() && !key.trim().contains(' ') => {
+            (before, Some(key.trim().to_string()))
+        }
+        _ => (rest, None),
     }
-    if let Some(rest) = rest.strip_prefix("HOP ") {
-        let mut parts = rest.splitn(3, ' ');
+}
+
+/// The chance, in `[0.0, 1.0]`, that a `TOPOLOGY WALK-SAMPLE` hop records
+/// its edge. Compared by bit pattern so `Command` can stay `Eq`.
+#[derive(Debug, Clone, Copy)]
+pub struct Probability(f64);
+
+impl Probability {
+    pub fn new(p: f64) -> Option<Self> {
+        (0.0..=1.0).contains(&p).then_some(Self(p))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// Flip a coin that comes up true with this probability.
+    pub fn flip(self) -> bool {
+        rand::random::<f64>() < self.0
+    }
+}
+
+impl PartialEq for Probability {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Probability {}
+
+impl fmt::Display for Probability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Probability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .ok()
+            .and_then(Self::new)
+            .ok_or_else(|| format!("probability must be between 0 and 1: {s}"))
+    }
+}
+
+/// Prefix of the header line that tags the next command with a
+/// correlation ID.
+pub const CID_HEADER: &str = "CID: ";
+
+/// Longest correlation ID accepted.
+pub const MAX_CID_LEN: usize = 64;
+
+/// The correlation ID a client sent ahead of a command, if any. The node
+/// logs it on every line about the command, passes it on with every hop
+/// the command causes, and echoes it before a `GET` reply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorrelationId(pub Option<String>);
+
+impl CorrelationId {
+    /// The `CID: <id>` line to send ahead of a command; empty when unset.
+    pub fn header(&self) -> String {
+        match &self.0 {
+            Some(id) => format!("{CID_HEADER}{id}\n"),
+            None => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_deref().unwrap_or("-"))
+    }
+}
+
+/// `Some` when `line` is a `CID:` header: the ID, or why it was rejected.
+/// IDs are 1-64 ASCII alphanumerics, `.`, `-` or `_`.
+pub fn parse_correlation_id(line: &str) -> Option<Result<CorrelationId, RingError>> {
+    let id = line
+        .trim_end_matches(['\r', '\n'])
+        .strip_prefix(CID_HEADER.trim_end())?
+        .trim();
+    let valid = !id.is_empty()
+        && id.len() <= MAX_CID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_'));
+    Some(if valid {
+        Ok(CorrelationId(Some(id.to_string())))
+    } else {
+        Err(RingError::ParseError(format!(
+            "invalid CID: want 1-{MAX_CID_LEN} of [A-Za-z0-9._-]"
+        )))
+    })
+}
+
+/// Parse one incoming line from the wire into a Command.
+pub fn parse_line(line: &str) -> Result<Command, RingError> {
+    let trimmed = line.trim_end_matches(['\r', '\n']);
+    // Error replies quote parts of the line; a stray line break in them
+    // would read as a second reply, and a field ending in one would not
+    // survive being sent on.
+    if trimmed.contains(['\r', '\n']) {
+        return Err(RingError::ParseError(
+            "line break inside a command line".into(),
+        ));
+    }
+    let mut parts = trimmed.splitn(2, ' ');
+    let noun = parts.next().unwrap_or("").to_ascii_uppercase();
+    let rest = parts.next().unwrap_or("");
+
+    let parsed = match noun.as_str() {
+        "NODE" => parse_node_cmd(rest),
+        "RING" => parse_ring_cmd(rest),
+        "TOPOLOGY" => parse_topology_cmd(rest),
+        "NETMAP" => parse_netmap_cmd(rest),
+        "GATHER" => parse_gather_cmd(rest),
+        "KV" => parse_kv_cmd(rest),
+        "ELECT" => parse_elect_cmd(rest),
+        "FILE" => parse_file_cmd(rest),
+        "PROBE" => parse_probe_cmd(rest),
+        "HEARTBEAT" => parse_heartbeat_cmd(rest),
+        "MEMBERS" if rest.trim().is_empty() => Ok(Command::Members),
+        "BARRIER" => parse_barrier_cmd(rest),
+        "INCR" => parse_incr_cmd(rest),
+        "SPLIT-BRAIN" => parse_split_brain_cmd(rest),
+        "CHAOS" => parse_chaos_cmd(rest),
+        "SUBSCRIBE" => rest
+            .trim()
+            .parse()
+            .map(|events| Command::Subscribe { events }),
+        "UNSUBSCRIBE" if rest.trim().is_empty() => Ok(Command::Unsubscribe),
+        "SNAPSHOT" if rest.trim().is_empty() => Ok(Command::Snapshot),
+        "RESTORE" => NodeSnapshot::parse(rest)
+            .map(|snapshot| Command::Restore(Box::new(snapshot)))
+            .map_err(|e| e.to_string()),
+        "MUX" if rest.trim().is_empty() => Ok(Command::Mux),
+        _ => return Err(RingError::UnknownCommand(noun)),
+    };
+    parsed.map_err(RingError::ParseError)
+}
+
+/// Expand the `z:` message or payload field of a `RING` or payload-walk
+/// command (see `crate::codec`) to at most `max_len` bytes, so handlers
+/// only see plain text. A field that does not decode is an error.
+pub fn expand_payload(mut cmd: Command, max_len: usize) -> Result<Command, RingError> {
+    let field = match &mut cmd {
+        Command::RingForward { msg, .. }
+        | Command::RingOn { msg, .. }
+        | Command::RingAck { msg, .. }
+        | Command::RingAckHop { msg, .. }
+        | Command::RingEcho { msg, .. }
+        | Command::RingEchoHop { msg, .. }
+        | Command::RingEchoDone { msg, .. }
+        | Command::RingReq { msg, .. }
+        | Command::RingTimed { msg, .. }
+        | Command::RingOnce { msg }
+        | Command::RingAll { msg }
+        | Command::RingOnceHop { msg, .. } => msg,
+        Command::WalkPayHop { payloads, .. } | Command::WalkPayDone { payloads, .. } => payloads,
+        _ => return Ok(cmd),
+    };
+    if let Cow::Owned(plain) =
+        decompress_field(field, max_len).map_err(|e| RingError::ParseError(e.to_string()))?
+    {
+        *field = plain;
+    }
+    Ok(cmd)
+}
+
+// --- Noun parsers
+
+fn parse_node_cmd(rest: &str) -> Result<Command, String> {
+    if let Some(rest) = rest.strip_prefix("FORWARD-TO ") {
+        return match rest.trim_start().split_once(' ') {
+            Some((target, command)) if !command.trim().is_empty() => Ok(Command::ForwardTo {
+                target: target.to_string(),
+                command: command.to_string(),
+            }),
+            _ => Err("malformed NODE FORWARD-TO: need <target_addr> <command>".into()),
+        };
+    }
+    if let Some(addr) = rest.strip_prefix("NEXT ") {
+        let addr = addr.trim();
+        if addr.is_empty() {
+            return Err("missing address for NODE NEXT".into());
+        }
+        return Ok(Command::NodeNext(addr.to_string()));
+    }
+    if let Some(addr) = rest.strip_prefix("PREV ") {
+        let addr = addr.trim();
+        if addr.is_empty() {
+            return Err("missing address for NODE PREV".into());
+        }
+        return Ok(Command::NodePrev(addr.to_string()));
+    }
+    if let Some(rest) = rest.strip_prefix("NEXT-MULTI ") {
+        let addrs: Vec<String> = rest.split_whitespace().map(str::to_string).collect();
+        if addrs.is_empty() {
+            return Err("missing address for NODE NEXT-MULTI".into());
+        }
+        return Ok(Command::NodeNextMulti(addrs));
+    }
+    if let Some(rest) = rest.strip_prefix("NEXT-GEN ") {
+        let mut parts = rest.split_whitespace();
+        let (Some(addr), Some(generation), None) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed NODE NEXT-GEN".into());
+        };
+        let generation = generation
+            .parse()
+            .map_err(|_| "malformed NODE NEXT-GEN generation".to_string())?;
+        return Ok(Command::NodeNextGen {
+            addr: addr.to_string(),
+            generation,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("NEXT-IF ") {
+        let mut parts = rest.split_whitespace();
+        let (Some(expected), Some(new_addr), None) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed NODE NEXT-IF".into());
+        };
+        return Ok(Command::NodeNextIf {
+            expected: expected.to_string(),
+            new_addr: new_addr.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("NEXT-RING ") {
+        let mut parts = rest.splitn(2, ' ');
+        let ring = parts.next().unwrap_or("").trim();
+        let addr = parts.next().unwrap_or("").trim();
+        if validate_ring_name(ring).is_err() || addr.is_empty() {
+            return Err("malformed NODE NEXT-RING".into());
+        }
+        return Ok(Command::NodeNextRing {
+            ring: ring.to_string(),
+            addr: addr.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("NEXT-RINGS ") {
+        return parse_next_rings(rest);
+    }
+    if let Some(ring) = rest.strip_prefix("RING ") {
+        let ring = ring.trim();
+        if validate_ring_name(ring).is_err() {
+            return Err("malformed NODE RING".into());
+        }
+        return Ok(Command::NodeRing {
+            ring: ring.to_string(),
+        });
+    }
+    if rest.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::NodeStatus);
+    }
+    if rest.eq_ignore_ascii_case("INFO") {
+        return Ok(Command::NodeInfo);
+    }
+    if rest.eq_ignore_ascii_case("PING") {
+        return Ok(Command::NodePing);
+    }
+    if rest.eq_ignore_ascii_case("METRICS") {
+        return Ok(Command::NodeMetrics);
+    }
+    if rest.eq_ignore_ascii_case("STAT") {
+        return Ok(Command::NodeStat);
+    }
+    if rest.eq_ignore_ascii_case("CIRCUIT") {
+        return Ok(Command::NodeCircuit);
+    }
+    if rest.eq_ignore_ascii_case("SHUTDOWN") {
+        return Ok(Command::NodeShutdown);
+    }
+    if rest.eq_ignore_ascii_case("HEAL") {
+        return Ok(Command::NodeHeal);
+    }
+    if let Some(rest) = rest.strip_prefix("HEAL-HOP ") {
+        let mut parts = rest.splitn(2, ' ');
         let token = parts.next().unwrap_or("").trim();
         let start_addr = parts.next().unwrap_or("").trim();
-        let entries = parts.next().unwrap_or("").to_string();
         if token.is_empty() || start_addr.is_empty() {
-            return Err("malformed NETMAP HOP".into());
+            return Err("malformed NODE HEAL-HOP".into());
         }
-        return Ok(Command::NetmapHop {
+        return Ok(Command::NodeHealHop {
             token: token.to_string(),
             start_addr: start_addr.to_string(),
-            entries,
         });
     }
-    if let Some(rest) = rest.strip_prefix("DONE ") {
-        let mut parts = rest.splitn(2, ' ');
-        let token = parts.next().unwrap_or("").trim();
-        let entries = parts.next().unwrap_or("").to_string();
+    if let Some(token) = rest.strip_prefix("HEAL-DONE ") {
+        let token = token.trim();
         if token.is_empty() {
-            return Err("malformed NETMAP DONE".into());
+            return Err("malformed NODE HEAL-DONE".into());
         }
-        return Ok(Command::NetmapDone {
+        return Ok(Command::NodeHealDone {
             token: token.to_string(),
-            entries,
-        });
-    }
-    if let Some(rest) = rest.strip_prefix("SET ") {
-        return Ok(Command::NetmapSet {
-            entries: rest.trim().to_string(),
         });
     }
-    if rest.eq_ignore_ascii_case("GET") {
-        return Ok(Command::NetmapGet);
-    }
-    Err("unknown NETMAP command".into())
+
+    Err("unknown NODE command".into())
 }
 
-fn parse_file_cmd(rest: &str) -> Result<Command, String> {
-    // PUSH
-    if let Some(rest) = rest.strip_prefix("PUSH ") {
+fn parse_ring_cmd(rest: &str) -> Result<Command, String> {
+    if let Some(rest) = rest.strip_prefix("FORWARD-HOP ") {
+        return parse_ring_forward_hop(rest);
+    }
+    if let Some(rest) = rest.strip_prefix("KEY-HOP ") {
+        return parse_ring_key_cmd("KEY-HOP", rest);
+    }
+    if let Some(rest) = rest.strip_prefix("KEY ") {
+        return parse_ring_key_cmd("KEY", rest);
+    }
+    if let Some(rest) = rest.strip_prefix("FORWARD ") {
         let mut parts = rest.splitn(2, ' ');
-        let size_str = parts.next().unwrap_or("").trim();
-        let name = parts.next().unwrap_or("").trim().to_string();
-        if name.is_empty() {
-            return Err("missing file name for FILE PUSH".into());
-        }
-        validate_filename(&name).map_err(|e| format!("FILE PUSH: {e}"))?;
-        let size = size_str
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING FORWARD")?;
+        return Ok(Command::RingForward {
+            hop_count: 0,
+            ttl,
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ON ") {
+        let mut parts = rest.splitn(3, ' ');
+        let ring = parts.next().unwrap_or("").trim();
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if validate_ring_name(ring).is_err() {
+            return Err("malformed RING ON".into());
+        }
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ON")?;
+        return Ok(Command::RingOn {
+            ring: ring.to_string(),
+            hop_count: 0,
+            ttl,
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ON-HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let ring = parts.next().unwrap_or("").trim();
+        if validate_ring_name(ring).is_err() {
+            return Err("malformed RING ON-HOP".into());
+        }
+        let hop_count = parts
+            .next()
+            .unwrap_or("")
+            .parse::<u32>()
+            .map_err(|_| "invalid hop count for RING ON-HOP")?;
+        let ttl = parts
+            .next()
+            .unwrap_or("")
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ON-HOP")?;
+        return Ok(Command::RingOn {
+            ring: ring.to_string(),
+            hop_count,
+            ttl,
+            msg: parts.next().unwrap_or("").to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ACK ") {
+        let mut parts = rest.splitn(2, ' ');
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ACK")?;
+        return Ok(Command::RingAck { ttl, msg });
+    }
+    if let Some(rest) = rest.strip_prefix("ACK-HOP ") {
+        let mut parts = rest.splitn(5, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let seen = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed RING ACK-HOP".into());
+        }
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ACK-HOP")?;
+        return Ok(Command::RingAckHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            ttl,
+            seen: seen.to_string(),
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ACK-DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let seen = parts.next().unwrap_or("").trim();
+        if token.is_empty() {
+            return Err("malformed RING ACK-DONE".into());
+        }
+        return Ok(Command::RingAckDone {
+            token: token.to_string(),
+            seen: seen
+                .split(';')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ECHO ") {
+        let mut parts = rest.splitn(2, ' ');
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ECHO")?;
+        return Ok(Command::RingEcho { ttl, msg });
+    }
+    if let Some(rest) = rest.strip_prefix("ECHO-HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed RING ECHO-HOP".into());
+        }
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING ECHO-HOP")?;
+        return Ok(Command::RingEchoHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            ttl,
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ECHO-DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed RING ECHO-DONE".into());
+        }
+        return Ok(Command::RingEchoDone {
+            token: token.to_string(),
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("COLLECT ") {
+        let (ttl_str, key) = rest.split_once(' ').unwrap_or((rest, ""));
+        let key = key.trim();
+        if key.is_empty() || key.contains(' ') {
+            return Err("RING COLLECT takes a ttl and one key".into());
+        }
+        let ttl = ttl_str
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING COLLECT")?;
+        return Ok(Command::RingCollect {
+            ttl,
+            key: key.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("COLLECT-HOP ") {
+        let mut parts = rest.splitn(5, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let key = parts.next().unwrap_or("").trim();
+        let results = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() || key.is_empty() {
+            return Err("malformed RING COLLECT-HOP".into());
+        }
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING COLLECT-HOP")?;
+        return Ok(Command::RingCollectHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            ttl,
+            key: key.to_string(),
+            results,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("COLLECT-DONE ") {
+        let (token, results) = rest.split_once(' ').unwrap_or((rest, ""));
+        let token = token.trim();
+        if token.is_empty() {
+            return Err("malformed RING COLLECT-DONE".into());
+        }
+        return Ok(Command::RingCollectDone {
+            token: token.to_string(),
+            results: results.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("REQ ") {
+        let mut parts = rest.splitn(3, ' ');
+        let callback_addr = parts.next().unwrap_or("").trim();
+        let ttl_str = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if callback_addr.is_empty() {
+            return Err("missing callback address for RING REQ".into());
+        }
+        let ttl = ttl_str
+            .parse::<u32>()
+            .map_err(|_| "invalid ttl for RING REQ")?;
+        return Ok(Command::RingReq {
+            callback_addr: callback_addr.to_string(),
+            ttl,
+            msg,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("TIMED ") {
+        let mut parts = rest.splitn(2, ' ');
+        let expiry_ms = parts
+            .next()
+            .unwrap_or("")
+            .trim()
             .parse::<u64>()
-            .map_err(|_| "invalid size for FILE PUSH")?;
-        return Ok(Command::FilePush { size, name });
+            .map_err(|_| "invalid expiry for RING TIMED")?;
+        let msg = parts.next().unwrap_or("").to_string();
+        return Ok(Command::RingTimed { expiry_ms, msg });
+    }
+    if let Some(msg) = rest.strip_prefix("ONCE ") {
+        return Ok(Command::RingOnce {
+            msg: msg.to_string(),
+        });
+    }
+    if rest == "ALL" {
+        return Ok(Command::RingAll { msg: String::new() });
+    }
+    if let Some(msg) = rest.strip_prefix("ALL ") {
+        return Ok(Command::RingAll {
+            msg: msg.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ONCE-HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let hop_count = parts
+            .next()
+            .unwrap_or("")
+            .parse::<u32>()
+            .map_err(|_| "invalid hop count for RING ONCE-HOP")?;
+        let start_addr = parts.next().unwrap_or("").trim();
+        let msg = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed RING ONCE-HOP".into());
+        }
+        return Ok(Command::RingOnceHop {
+            token: token.to_string(),
+            hop_count,
+            start_addr: start_addr.to_string(),
+            msg,
+        });
+    }
+    if let Some(token) = rest.strip_prefix("ONCE-DONE ") {
+        let token = token.trim();
+        if token.is_empty() || token.contains(' ') {
+            return Err("malformed RING ONCE-DONE".into());
+        }
+        return Ok(Command::RingOnceDone {
+            token: token.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("RESP ") {
+        let mut parts = rest.splitn(2, ' ');
+        let callback_addr = parts.next().unwrap_or("").trim();
+        let payload = parts.next().unwrap_or("").to_string();
+        if callback_addr.is_empty() {
+            return Err("malformed RING RESP".into());
+        }
+        return Ok(Command::RingResp {
+            callback_addr: callback_addr.to_string(),
+            payload,
+        });
+    }
+    Err("unknown RING command".into())
+}
+
+fn parse_topology_cmd(rest: &str) -> Result<Command, String> {
+    let (verb, args) = rest.split_once(' ').unwrap_or((rest, ""));
+    if matches!(
+        verb,
+        "WALK-FIND" | "FIND-HOP" | "FIND-FOUND" | "FIND-NOT-FOUND"
+    ) {
+        return parse_walk_find_cmd(verb, args);
+    }
+    if rest.eq_ignore_ascii_case("WALK") {
+        return Ok(Command::TopologyWalk);
+    }
+    if rest.eq_ignore_ascii_case("WALK-FRESH") {
+        return Ok(Command::TopologyWalkFresh);
+    }
+    if rest.eq_ignore_ascii_case("WALK-PARALLEL") {
+        return Ok(Command::WalkParallel);
+    }
+    if let Some(rest) = rest.strip_prefix("WALK-META ") {
+        let key = rest.trim();
+        if key.is_empty() || key.contains(' ') {
+            return Err("malformed TOPOLOGY WALK-META".into());
+        }
+        return Ok(Command::TopologyWalkMeta {
+            key: key.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        let (visited, meta_key) = split_meta_key(parts.next().unwrap_or(""));
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed TOPOLOGY HOP".into());
+        }
+        return Ok(Command::TopologyHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            history,
+            visited: visited
+                .trim()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+            meta_key,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let (history, meta_key) = split_meta_key(parts.next().unwrap_or(""));
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY DONE".into());
+        }
+        return Ok(Command::TopologyDone {
+            token: token.to_string(),
+            history: history.to_string(),
+            meta_key,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("ABORT ") {
+        let token = rest.trim();
+        if token.is_empty() || token.contains(' ') {
+            return Err("malformed TOPOLOGY ABORT".into());
+        }
+        return Ok(Command::TopologyAbort {
+            token: token.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("SET ") {
+        return Ok(Command::TopologySet {
+            history: rest.to_string(),
+        });
+    }
+    if rest.eq_ignore_ascii_case("REVERSE-WALK") {
+        return Ok(Command::TopologyReverseWalk);
+    }
+    if rest.eq_ignore_ascii_case("BIDIR-WALK") {
+        return Ok(Command::TopologyBidirWalk);
+    }
+    if let Some(rest) = rest.strip_prefix("REVERSE-HOP ") {
+        let mut parts = rest.splitn(3, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed TOPOLOGY REVERSE-HOP".into());
+        }
+        return Ok(Command::TopologyReverseHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("REVERSE-DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY REVERSE-DONE".into());
+        }
+        return Ok(Command::TopologyReverseDone {
+            token: token.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("PAY-WALK ") {
+        let key = rest.trim();
+        if !is_payload_key(key) {
+            return Err("malformed TOPOLOGY PAY-WALK".into());
+        }
+        return Ok(Command::WalkPayStart {
+            payload_key: key.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("PAY-HOP ") {
+        // Payload values may contain spaces, so they come last.
+        let mut parts = rest.splitn(5, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let key = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        let payloads = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() || !is_payload_key(key) {
+            return Err("malformed TOPOLOGY PAY-HOP".into());
+        }
+        return Ok(Command::WalkPayHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            payload_key: key.to_string(),
+            history,
+            payloads,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("PAY-DONE ") {
+        let mut parts = rest.splitn(3, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        let payloads = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY PAY-DONE".into());
+        }
+        return Ok(Command::WalkPayDone {
+            token: token.to_string(),
+            history,
+            payloads,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("WALK-MAX ") {
+        return match rest.trim().parse() {
+            Ok(max_hops) if max_hops > 0 => Ok(Command::WalkMax { max_hops }),
+            _ => Err("malformed TOPOLOGY WALK-MAX".into()),
+        };
+    }
+    if let Some(rest) = rest.strip_prefix("MAX-HOP ") {
+        let mut parts = rest.splitn(5, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let hop_count = parts.next().unwrap_or("").parse();
+        let max_hops = parts.next().unwrap_or("").parse();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        let (Ok(hop_count), Ok(max_hops)) = (hop_count, max_hops) else {
+            return Err("malformed TOPOLOGY MAX-HOP".into());
+        };
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed TOPOLOGY MAX-HOP".into());
+        }
+        return Ok(Command::WalkMaxHop {
+            token: token.to_string(),
+            hop_count,
+            max_hops,
+            start_addr: start_addr.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("MAX-DONE ") {
+        let mut parts = rest.splitn(3, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let truncated = match parts.next().unwrap_or("") {
+            "TRUNCATED" => true,
+            "COMPLETE" => false,
+            _ => return Err("malformed TOPOLOGY MAX-DONE".into()),
+        };
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY MAX-DONE".into());
+        }
+        return Ok(Command::WalkMaxDone {
+            token: token.to_string(),
+            truncated,
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("WALK-SAMPLE ") {
+        return match rest.trim().parse() {
+            Ok(probability) => Ok(Command::WalkSample { probability }),
+            Err(_) => Err("malformed TOPOLOGY WALK-SAMPLE".into()),
+        };
+    }
+    if let Some(rest) = rest.strip_prefix("SAMPLE-HOP ") {
+        let mut parts = rest.splitn(5, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let hop_count = parts.next().unwrap_or("").parse();
+        let probability = parts.next().unwrap_or("").parse();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        let (Ok(hop_count), Ok(probability)) = (hop_count, probability) else {
+            return Err("malformed TOPOLOGY SAMPLE-HOP".into());
+        };
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed TOPOLOGY SAMPLE-HOP".into());
+        }
+        return Ok(Command::WalkSampleHop {
+            token: token.to_string(),
+            hop_count,
+            probability,
+            start_addr: start_addr.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("SAMPLE-DONE ") {
+        let mut parts = rest.splitn(3, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let Ok(hop_count) = parts.next().unwrap_or("").parse() else {
+            return Err("malformed TOPOLOGY SAMPLE-DONE".into());
+        };
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY SAMPLE-DONE".into());
+        }
+        return Ok(Command::WalkSampleDone {
+            token: token.to_string(),
+            hop_count,
+            history,
+        });
+    }
+    if let Some(ring) = rest.strip_prefix("RING-WALK ") {
+        let ring = ring.trim();
+        if validate_ring_name(ring).is_err() {
+            return Err("malformed TOPOLOGY RING-WALK".into());
+        }
+        return Ok(Command::WalkRing {
+            ring: ring.to_string(),
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("RING-HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let ring = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || validate_ring_name(ring).is_err() || start_addr.is_empty() {
+            return Err("malformed TOPOLOGY RING-HOP".into());
+        }
+        return Ok(Command::WalkRingHop {
+            token: token.to_string(),
+            ring: ring.to_string(),
+            start_addr: start_addr.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("RING-DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let history = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed TOPOLOGY RING-DONE".into());
+        }
+        return Ok(Command::WalkRingDone {
+            token: token.to_string(),
+            history,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("PROBE-HOP ") {
+        let mut parts = rest.split_whitespace();
+        let (Some(token), Some(hop_count), Some(start_addr), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed TOPOLOGY PROBE-HOP".into());
+        };
+        let hop_count = hop_count
+            .parse::<u32>()
+            .map_err(|_| "invalid hop count for TOPOLOGY PROBE-HOP")?;
+        return Ok(Command::WalkProbeHop {
+            token: token.to_string(),
+            hop_count,
+            start_addr: start_addr.to_string(),
+        });
+    }
+    if let Some(token) = rest.strip_prefix("PROBE-DONE ") {
+        let token = token.trim();
+        if token.is_empty() || token.contains(' ') {
+            return Err("malformed TOPOLOGY PROBE-DONE".into());
+        }
+        return Ok(Command::WalkProbeDone {
+            token: token.to_string(),
+        });
+    }
+    Err("unknown TOPOLOGY command".into())
+}
+
+/// Payload keys travel as one token and may reach `--walk-payload-cmd`, so
+/// they are held to the filename alphabet.
+fn is_payload_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 64
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_'))
+}
+
+/// Milliseconds since the Unix epoch, the clock `RING TIMED` expiries
+/// are read against.
+pub fn now_unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Whether a `RING TIMED` expiry has passed.
+pub fn is_expired(expiry_ms: u64) -> bool {
+    now_unix_ms() >= expiry_ms
+}
+
+/// One `from->to` hop of a walk history. Endpoints are as the nodes wrote
+/// them: a port, a `host:port`, or either prefixed with `name@`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Split a walk history into its edges, in walk order. Edges are
+/// `;`-separated on the wire and newline-separated in a `TOPOLOGY WALK`
+/// reply; either works, and blank segments or ones without `->` are
+/// skipped. [`roundtrip_history`] is the inverse.
+pub fn parse_history(history: &str) -> Vec<Edge> {
+    history
+        .split([';', '\n'])
+        .filter_map(|seg| seg.trim().split_once("->"))
+        .map(|(from, to)| Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+        .collect()
+}
+
+/// `edges` in the `;`-joined wire form [`parse_history`] reads.
+pub fn roundtrip_history(edges: &[Edge]) -> String {
+    edges
+        .iter()
+        .map(|e| format!("{}->{}", e.from, e.to))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// `edges` as a two-column `FROM` / `TO` ASCII table, one row per edge.
+pub fn render_history_table(edges: &[Edge]) -> String {
+    let width = |header: &str, col: fn(&Edge) -> &str| {
+        edges
+            .iter()
+            .map(|e| col(e).chars().count())
+            .fold(header.len(), usize::max)
+    };
+    let (from_w, to_w) = (width("FROM", |e| &e.from), width("TO", |e| &e.to));
+    let rule = format!("+-{}-+-{}-+\n", "-".repeat(from_w), "-".repeat(to_w));
+    let row = |from: &str, to: &str| format!("| {from:<from_w$} | {to:<to_w$} |\n");
+
+    let mut out = rule.clone();
+    out.push_str(&row("FROM", "TO"));
+    out.push_str(&rule);
+    for e in edges {
+        out.push_str(&row(&e.from, &e.to));
+    }
+    out.push_str(&rule);
+    out
+}
+
+/// A node's `NODE STATUS` or `NODE INFO`. Pointers the node reports as
+/// `<unset>` are `None`, as is every field only `NODE INFO` reports when
+/// the reply came from `NODE STATUS`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub port: String,
+    pub name: Option<String>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+    pub leader: Option<String>,
+    /// Number of default-ring next hops; more than one after
+    /// `NODE NEXT-MULTI`.
+    pub fan_out: usize,
+    /// See [`crate::Node::generation`]; 0 from a node too old to report
+    /// one.
+    pub generation: u64,
+    /// `Unknown` from a node too old to report one.
+    pub role: NodeRole,
+    pub uptime_secs: Option<u64>,
+    /// Size of the ring at the last count; 0 before any.
+    pub ring_size: Option<u64>,
+    /// Commands the node has received.
+    pub msg_count: Option<u64>,
+    pub walk_count: Option<u64>,
+    /// Connections open on the node.
+    pub connections: Option<u64>,
+    /// The node's crate version.
+    pub version: Option<String>,
+}
+
+/// A node's reply to a client command, as [`parse_response`] reads it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// A bare `OK` line; holds whatever followed `OK ` (`next=...`,
+    /// `DONE`), empty for plain `OK`.
+    Ok(String),
+    /// `ERR <msg>`.
+    Error(String),
+    /// `NODE STATUS` or `NODE INFO`.
+    NodeInfo(Box<NodeInfo>),
+    /// `TOPOLOGY WALK` and the other walks that reply with edges, minus
+    /// their `TOKEN` / `CACHED` lead line.
+    WalkResult { edges: Vec<Edge> },
+}
+
+/// Read the lines of a node's reply. `lines` may stop short of the
+/// closing `OK`, as the client's own reader hands them over. A
+/// `NODE STATUS` or `NODE INFO` reply that leaves a key out gets `None`
+/// (or 0) for it,
+/// and each value runs to the end of its line, spaces included, so an
+/// odd address is not cut short.
+pub fn parse_response(lines: &[&str]) -> Result<Response, RingError> {
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|l| l.trim_end_matches(['\r', '\n']))
+        .filter(|l| !l.is_empty())
+        .collect();
+    if let Some(msg) = lines
+        .iter()
+        .find_map(|l| l.strip_prefix("ERR ").or((*l == "ERR").then_some("")))
+    {
+        return Ok(Response::Error(msg.to_string()));
+    }
+    let (body, ok) = match lines.split_last() {
+        Some((last, body)) if *last == "OK" || last.starts_with("OK ") => {
+            (body, Some(last.get(3..).unwrap_or("")))
+        }
+        _ => (&lines[..], None),
+    };
+    let Some(first) = body.first() else {
+        return match ok {
+            Some(detail) => Ok(Response::Ok(detail.to_string())),
+            None => Err(RingError::ParseError("empty response".into())),
+        };
+    };
+
+    match first.split_once(' ').map_or(*first, |(key, _)| key) {
+        "PORT" | "NAME" | "NEXT" | "PREV" | "FAN_OUT" | "GENERATION" | "LEADER" | "ROLE" => {
+            let set = |v: &str| (v != "<unset>").then(|| v.to_string());
+            let count = |v: &str| v.parse().ok();
+            let mut info = NodeInfo::default();
+            for (key, value) in body.iter().filter_map(|l| l.split_once(' ')) {
+                match key {
+                    "PORT" => info.port = value.to_string(),
+                    "NAME" => info.name = set(value),
+                    "NEXT" => info.next = set(value),
+                    "PREV" => info.prev = set(value),
+                    "LEADER" => info.leader = set(value),
+                    "FAN_OUT" => info.fan_out = value.parse().unwrap_or(0),
+                    "GENERATION" => info.generation = value.parse().unwrap_or(0),
+                    "ROLE" => info.role = value.parse().unwrap_or_default(),
+                    "UPTIME_SECS" => info.uptime_secs = count(value),
+                    "RING_SIZE" => info.ring_size = count(value),
+                    "MSG_COUNT" => info.msg_count = count(value),
+                    "WALK_COUNT" => info.walk_count = count(value),
+                    "CONNECTIONS" => info.connections = count(value),
+                    "VERSION" => info.version = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            Ok(Response::NodeInfo(Box::new(info)))
+        }
+        "TOKEN" | "CACHED" => Ok(Response::WalkResult {
+            edges: parse_history(&body[1..].join("\n")),
+        }),
+        _ => {
+            let edges = parse_history(&body.join("\n"));
+            if edges.is_empty() {
+                Err(RingError::ParseError(format!(
+                    "unrecognised response: {first}"
+                )))
+            } else {
+                Ok(Response::WalkResult { edges })
+            }
+        }
+    }
+}
+
+/// Keys `GATHER <key>` accepts; values come from `Node::gather_value`.
+pub const GATHER_KEYS: [&str; 4] = ["port", "next", "uptime", "msg_count"];
+
+fn parse_gather_key(key: &str) -> Result<String, String> {
+    let key = key.trim().to_ascii_lowercase();
+    if GATHER_KEYS.contains(&key.as_str()) {
+        Ok(key)
+    } else {
+        Err(format!(
+            "unknown GATHER key '{key}' (expected one of: {})",
+            GATHER_KEYS.join(", ")
+        ))
+    }
+}
+
+fn parse_gather_cmd(rest: &str) -> Result<Command, String> {
+    if rest.eq_ignore_ascii_case("KEYS") {
+        return Ok(Command::GatherKeys);
+    }
+    if let Some(rest) = rest.strip_prefix("HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let key = parts.next().unwrap_or("");
+        let results = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed GATHER HOP".into());
+        }
+        return Ok(Command::GatherHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            key: parse_gather_key(key)?,
+            results,
+        });
+    }
+    if let Some(key) = rest.strip_prefix("STREAM ") {
+        return parse_gather_key(key).map(|key| Command::GatherStream { key });
+    }
+    if let Some(rest) = rest.strip_prefix("STREAM-HOP ") {
+        let mut parts = rest.splitn(4, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let key = parts.next().unwrap_or("");
+        let results = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed GATHER STREAM-HOP".into());
+        }
+        return Ok(Command::GatherStreamHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            key: parse_gather_key(key)?,
+            results,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("STREAM-DATA ") {
+        return match rest.split_once(' ') {
+            Some((token, entry)) if !token.is_empty() && entry.contains('=') => {
+                Ok(Command::GatherStreamData {
+                    token: token.to_string(),
+                    entry: entry.to_string(),
+                })
+            }
+            _ => Err("malformed GATHER STREAM-DATA".into()),
+        };
+    }
+    if let Some(rest) = rest.strip_prefix("DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let results = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed GATHER DONE".into());
+        }
+        return Ok(Command::GatherDone {
+            token: token.to_string(),
+            results,
+        });
+    }
+    Ok(Command::GatherStart {
+        key: parse_gather_key(rest)?,
+    })
+}
+
+fn parse_elect_cmd(rest: &str) -> Result<Command, String> {
+    if rest.eq_ignore_ascii_case("START") {
+        return Ok(Command::ElectStart);
+    }
+    if let Some(cmd) = rest.strip_prefix("CMD ")
+        && !cmd.trim().is_empty()
+    {
+        return Ok(Command::LeaderCmd {
+            cmd: cmd.to_string(),
+        });
+    }
+    let (verb, args) = rest.split_once(' ').unwrap_or((rest, ""));
+    let args: Vec<&str> = args.split_whitespace().collect();
+    match (verb, args.as_slice()) {
+        ("HOP", [token, start_addr, candidate]) => Ok(Command::ElectHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            candidate: candidate.to_string(),
+        }),
+        ("DONE", [token, candidate]) => Ok(Command::ElectDone {
+            token: token.to_string(),
+            candidate: candidate.to_string(),
+        }),
+        ("LEADER", [start_addr, leader]) => Ok(Command::ElectLeader {
+            start_addr: start_addr.to_string(),
+            leader: leader.to_string(),
+        }),
+        ("HOP" | "DONE" | "LEADER", _) => Err(format!("malformed ELECT {verb}")),
+        _ => Err("unknown ELECT command".into()),
+    }
+}
+
+fn parse_split_brain_cmd(rest: &str) -> Result<Command, String> {
+    let mut args = rest.split_whitespace();
+    let verb = args.next().unwrap_or("").to_ascii_uppercase();
+    let args: Vec<&str> = args.collect();
+    match (verb.as_str(), args.as_slice()) {
+        ("CHECK", []) => Ok(Command::SplitBrainCheck),
+        ("HOP", [token, start_addr, leaders]) => Ok(Command::SplitBrainHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            leaders: leaders.to_string(),
+        }),
+        ("DONE", [token, leaders]) => Ok(Command::SplitBrainDone {
+            token: token.to_string(),
+            leaders: leaders.to_string(),
+        }),
+        _ => Err(format!("malformed SPLIT-BRAIN {verb}")),
+    }
+}
+
+fn parse_chaos_cmd(rest: &str) -> Result<Command, String> {
+    let (verb, args) = rest
+        .trim_start()
+        .split_once(' ')
+        .unwrap_or((rest.trim(), ""));
+    match verb.to_ascii_uppercase().as_str() {
+        "MODE" => args.parse().map(|mode| Command::ChaosMode { mode }),
+        "OFF" if args.trim().is_empty() => Ok(Command::ChaosOff),
+        verb => Err(format!("malformed CHAOS {verb}")),
+    }
+}
+
+/// `RING KEY <key> <msg>` or `RING KEY-HOP <key> <msg>`, `verb` being
+/// `KEY` or `KEY-HOP`.
+fn parse_ring_key_cmd(verb: &str, rest: &str) -> Result<Command, String> {
+    let Some((key, msg)) = rest.trim_start().split_once(' ') else {
+        return Err(format!("malformed RING {verb}: need <key> <message>"));
+    };
+    if msg.is_empty() {
+        return Err(format!("malformed RING {verb}: need <key> <message>"));
+    }
+    let (key, msg) = (key.to_string(), msg.to_string());
+    Ok(match verb {
+        "KEY" => Command::RingKey { key, msg },
+        _ => Command::RingKeyHop { key, msg },
+    })
+}
+
+fn parse_probe_cmd(rest: &str) -> Result<Command, String> {
+    let dead_addr = rest.trim();
+    if dead_addr.is_empty() || dead_addr.contains(' ') {
+        return Err("malformed PROBE".into());
+    }
+    Ok(Command::Probe {
+        dead_addr: dead_addr.to_string(),
+    })
+}
+
+/// `<hop_count> <ttl> <message...>`.
+fn parse_ring_forward_hop(rest: &str) -> Result<Command, String> {
+    let mut parts = rest.splitn(3, ' ');
+    let hop_count = parts
+        .next()
+        .unwrap_or("")
+        .parse::<u32>()
+        .map_err(|_| "invalid hop count for RING FORWARD-HOP")?;
+    let ttl = parts
+        .next()
+        .unwrap_or("")
+        .parse::<u32>()
+        .map_err(|_| "invalid ttl for RING FORWARD-HOP")?;
+    Ok(Command::RingForward {
+        hop_count,
+        ttl,
+        msg: parts.next().unwrap_or("").to_string(),
+    })
+}
+
+/// `<ring>=<addr>;<ring>=<addr>...`, each ring named once.
+fn parse_next_rings(rest: &str) -> Result<Command, String> {
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return Err("missing <ring>=<addr> pairs for NODE NEXT-RINGS".into());
+    }
+    let mut hops: Vec<(String, String)> = Vec::new();
+    for pair in rest.split(';') {
+        let Some((ring, addr)) = pair.split_once('=') else {
+            return Err(format!("malformed NODE NEXT-RINGS pair '{pair}'"));
+        };
+        validate_ring_name(ring)?;
+        if addr.is_empty() || addr.contains(char::is_whitespace) {
+            return Err(format!("malformed NODE NEXT-RINGS address for ring {ring}"));
+        }
+        if hops.iter().any(|(r, _)| r == ring) {
+            return Err(format!("ring {ring} named twice in NODE NEXT-RINGS"));
+        }
+        hops.push((ring.to_string(), addr.to_string()));
+    }
+    Ok(Command::NodeNextRings(hops))
+}
+
+fn parse_barrier_cmd(rest: &str) -> Result<Command, String> {
+    let mut parts = rest.split_whitespace();
+    let verb = parts.next().unwrap_or("").to_ascii_uppercase();
+    let parts: Vec<&str> = parts.collect();
+    let count = |n: &str| match n.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid BARRIER {verb} count '{n}'")),
+    };
+    match (verb.as_str(), parts.as_slice()) {
+        ("ENTER", [id, n]) => Ok(Command::Barrier {
+            barrier_id: id.to_string(),
+            n: count(n)?,
+        }),
+        ("HOP", [id, n, start_addr, visited]) => Ok(Command::BarrierHop {
+            barrier_id: id.to_string(),
+            n: count(n)?,
+            start_addr: start_addr.to_string(),
+            visited: visited.to_string(),
+        }),
+        ("WAIT", [id, n]) => Ok(Command::BarrierWait {
+            barrier_id: id.to_string(),
+            n: count(n)?,
+        }),
+        ("DONE", [id]) => Ok(Command::BarrierDone {
+            barrier_id: id.to_string(),
+        }),
+        _ => Err(format!("malformed BARRIER {verb}")),
+    }
+}
+
+fn parse_incr_cmd(rest: &str) -> Result<Command, String> {
+    let mut parts = rest.split_whitespace();
+    let verb = parts.next().unwrap_or("").to_ascii_uppercase();
+    let parts: Vec<&str> = parts.collect();
+    match (verb.as_str(), parts.as_slice()) {
+        ("START", [name]) => Ok(Command::Incr {
+            counter_name: name.to_string(),
+        }),
+        ("HOP", [name, token, hop_count, origin, value]) => Ok(Command::IncrHop {
+            counter_name: name.to_string(),
+            token: token.to_string(),
+            hop_count: hop_count
+                .parse()
+                .map_err(|_| format!("invalid INCR HOP hop count '{hop_count}'"))?,
+            origin: origin.to_string(),
+            current_value: value
+                .parse()
+                .map_err(|_| format!("invalid INCR HOP value '{value}'"))?,
+        }),
+        _ => Err(format!("malformed INCR {verb}")),
+    }
+}
+
+/// `TOPOLOGY <verb> <args>` for the `WALK-FIND` family.
+fn parse_walk_find_cmd(verb: &str, rest: &str) -> Result<Command, String> {
+    match verb {
+        "WALK-FIND" => {
+            let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+            if key.is_empty() {
+                return Err("missing key for TOPOLOGY WALK-FIND".into());
+            }
+            Ok(Command::WalkFind {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+        }
+        "FIND-HOP" => match rest.splitn(5, ' ').collect::<Vec<_>>().as_slice() {
+            [token, start_addr, history, key, value @ ..]
+                if [token, start_addr, history, key]
+                    .iter()
+                    .all(|f| !f.is_empty()) =>
+            {
+                Ok(Command::WalkFindHop {
+                    token: token.to_string(),
+                    start_addr: start_addr.to_string(),
+                    history: history.to_string(),
+                    key: key.to_string(),
+                    value: value.first().unwrap_or(&"").to_string(),
+                })
+            }
+            _ => Err("malformed TOPOLOGY FIND-HOP".into()),
+        },
+        _ => match (verb, rest.split_whitespace().collect::<Vec<_>>().as_slice()) {
+            ("FIND-FOUND", [token, addr, history @ ..]) if history.len() < 2 => {
+                Ok(Command::WalkFound {
+                    token: token.to_string(),
+                    addr: addr.to_string(),
+                    history: history.first().unwrap_or(&"").to_string(),
+                })
+            }
+            ("FIND-NOT-FOUND", [token]) => Ok(Command::WalkNotFound {
+                token: token.to_string(),
+            }),
+            _ => Err(format!("malformed TOPOLOGY {verb}")),
+        },
+    }
+}
+
+fn parse_heartbeat_cmd(rest: &str) -> Result<Command, String> {
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    let (addr, name, generation, next) = match parts.as_slice() {
+        [addr, name, generation] => (addr, name, generation, None),
+        [addr, name, generation, next] => (addr, name, generation, Some(next.to_string())),
+        _ => return Err("malformed HEARTBEAT".into()),
+    };
+    let generation = generation
+        .parse()
+        .map_err(|_| format!("invalid HEARTBEAT generation '{generation}'"))?;
+    Ok(Command::Heartbeat {
+        addr: addr.to_string(),
+        name: name.to_string(),
+        generation,
+        next,
+    })
+}
+
+fn parse_kv_cmd(rest: &str) -> Result<Command, String> {
+    if let Some(rest) = rest.strip_prefix("SET ") {
+        let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+        if key.is_empty() {
+            return Err("missing key for KV SET".into());
+        }
+        return Ok(Command::KvSet {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+    }
+    if let Some(key) = rest.strip_prefix("GET ") {
+        let key = key.trim();
+        if key.is_empty() || key.contains(' ') {
+            return Err("KV GET takes exactly one key".into());
+        }
+        return Ok(Command::KvGet {
+            key: key.to_string(),
+        });
+    }
+    Err("unknown KV command".into())
+}
+
+fn parse_netmap_cmd(rest: &str) -> Result<Command, String> {
+    if rest.eq_ignore_ascii_case("DISCOVER") {
+        return Ok(Command::NetmapDiscover);
+    }
+    if let Some(rest) = rest.strip_prefix("HOP ") {
+        let mut parts = rest.splitn(3, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let start_addr = parts.next().unwrap_or("").trim();
+        let entries = parts.next().unwrap_or("").to_string();
+        if token.is_empty() || start_addr.is_empty() {
+            return Err("malformed NETMAP HOP".into());
+        }
+        return Ok(Command::NetmapHop {
+            token: token.to_string(),
+            start_addr: start_addr.to_string(),
+            entries,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("DONE ") {
+        let mut parts = rest.splitn(2, ' ');
+        let token = parts.next().unwrap_or("").trim();
+        let entries = parts.next().unwrap_or("").to_string();
+        if token.is_empty() {
+            return Err("malformed NETMAP DONE".into());
+        }
+        return Ok(Command::NetmapDone {
+            token: token.to_string(),
+            entries,
+        });
+    }
+    if let Some(rest) = rest.strip_prefix("SET ") {
+        return Ok(Command::NetmapSet {
+            entries: rest.trim().to_string(),
+        });
+    }
+    if rest.eq_ignore_ascii_case("GET") {
+        return Ok(Command::NetmapGet);
+    }
+    Err("unknown NETMAP command".into())
+}
+
+fn parse_file_cmd(rest: &str) -> Result<Command, String> {
+    // PUSH
+    if let Some(rest) = rest.strip_prefix("PUSH ") {
+        let mut parts = rest.splitn(2, ' ');
+        let size_str = parts.next().unwrap_or("").trim();
+        let name = parts.next().unwrap_or("").trim().to_string();
+        if name.is_empty() {
+            return Err("missing file name for FILE PUSH".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE PUSH: {e}"))?;
+        let size = size_str
+            .parse::<u64>()
+            .map_err(|_| "invalid size for FILE PUSH")?;
+        return Ok(Command::FilePush { size, name });
+    }
+
+    // PULL
+    if let Some(rest) = rest.strip_prefix("PULL ") {
+        let name = rest.trim().to_string();
+        if name.is_empty() {
+            return Err("missing file name for FILE PULL".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE PULL: {e}"))?;
+        return Ok(Command::FilePull { name });
+    }
+
+    // LIST
+    if rest.eq_ignore_ascii_case("LIST") {
+        return Ok(Command::FileList);
+    }
+
+    // TAGS-SET
+    if let Some(rest) = rest.strip_prefix("TAGS-SET ") {
+        return Ok(Command::FileTagsSet {
+            entries: rest.to_string(),
+        });
+    }
+
+    // GET-CHUNK
+    if let Some(rest) = rest.strip_prefix("GET-CHUNK ") {
+        let name = rest.trim().to_string();
+        if name.is_empty() {
+            return Err("missing file name for FILE GET-CHUNK".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE GET-CHUNK: {e}"))?;
+        return Ok(Command::FileGetChunk { name });
+    }
+
+    // BACKUP-PUSH (saving node → predecessor; raw bytes follow)
+    if let Some(rest) = rest.strip_prefix("BACKUP-PUSH ") {
+        let mut parts = rest.splitn(2, ' ');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let size_str = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err("missing file name for FILE BACKUP-PUSH".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE BACKUP-PUSH: {e}"))?;
+        let size = size_str
+            .parse::<u64>()
+            .map_err(|_| "invalid size for FILE BACKUP-PUSH")?;
+        return Ok(Command::FileBackupPush { name, size });
+    }
+
+    // CONTENT-PUSH (predecessor → respawned successor; anti-entropy)
+    if let Some(rest) = rest.strip_prefix("CONTENT-PUSH ") {
+        let mut parts = rest.splitn(2, ' ');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let size_str = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err("missing file name for FILE CONTENT-PUSH".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE CONTENT-PUSH: {e}"))?;
+        let size = size_str
+            .parse::<u64>()
+            .map_err(|_| "invalid size for FILE CONTENT-PUSH")?;
+        return Ok(Command::FileContentPush { name, size });
+    }
+
+    // GET-BACKUP-CHUNK
+    if let Some(rest) = rest.strip_prefix("GET-BACKUP-CHUNK ") {
+        let name = rest.trim().to_string();
+        if name.is_empty() {
+            return Err("missing file name for FILE GET-BACKUP-CHUNK".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE GET-BACKUP-CHUNK: {e}"))?;
+        return Ok(Command::FileGetBackupChunk { name });
+    }
+
+    // PUSH-CHUNK (start node fans out to each chunk owner)
+    if let Some(rest) = rest.strip_prefix("PUSH-CHUNK ") {
+        let mut parts = rest.splitn(6, ' ');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let chunk_size_str = parts.next().unwrap_or("").trim();
+        let file_size_str = parts.next().unwrap_or("").trim();
+        let total_parts_str = parts.next().unwrap_or("").trim();
+        let index_str = parts.next().unwrap_or("").trim();
+        let start_port_str = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err("missing name for FILE PUSH-CHUNK".into());
+        }
+        validate_filename(&name).map_err(|e| format!("FILE PUSH-CHUNK: {e}"))?;
+        let chunk_size = chunk_size_str
+            .parse::<u64>()
+            .map_err(|_| "invalid chunk_size for FILE PUSH-CHUNK")?;
+        let file_size = file_size_str
+            .parse::<u64>()
+            .map_err(|_| "invalid file_size for FILE PUSH-CHUNK")?;
+        let parts_u = total_parts_str
+            .parse::<u32>()
+            .map_err(|_| "invalid parts for FILE PUSH-CHUNK")?;
+        let index = index_str
+            .parse::<u32>()
+            .map_err(|_| "invalid index for FILE PUSH-CHUNK")?;
+        let start_port = start_port_str
+            .parse::<u16>()
+            .map_err(|_| "invalid start_port for FILE PUSH-CHUNK")?;
+        return Ok(Command::FilePushChunk {
+            name,
+            chunk_size,
+            file_size,
+            parts: parts_u,
+            index,
+            start_port,
+        });
+    }
+
+    Err("unknown FILE command".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GREETING
+    #[test]
+    fn greeting_round_trips() {
+        let line = PROTOCOL_VERSION.greeting();
+        assert_eq!(line, "HELLO ouroboros/2.0\n");
+        assert_eq!(
+            ProtocolVersion::parse_greeting(&line).unwrap(),
+            PROTOCOL_VERSION
+        );
+        assert_eq!(ProtocolVersion::greeting_generation(&line), None);
+    }
+
+    #[test]
+    fn node_greeting_carries_the_generation() {
+        let line = PROTOCOL_VERSION.node_greeting(17);
+        assert_eq!(line, "HELLO ouroboros/2.0 17\n");
+        assert_eq!(
+            ProtocolVersion::parse_greeting(&line).unwrap(),
+            PROTOCOL_VERSION
+        );
+        assert_eq!(ProtocolVersion::greeting_generation(&line), Some(17));
+    }
+
+    #[test]
+    fn greeting_rejects_garbage() {
+        assert!(ProtocolVersion::parse_greeting("OK").is_err());
+        assert!(ProtocolVersion::parse_greeting("HELLO ouroboros/1").is_err());
+        assert!(ProtocolVersion::parse_greeting("HELLO ouroboros/x.0").is_err());
+    }
+
+    #[tokio::test]
+    async fn negotiate_accepts_current_version() {
+        let mut wire = PROTOCOL_VERSION.greeting().into_bytes();
+        wire.extend_from_slice(b"OK\n");
+        let mut reader = &wire[..];
+        let v = negotiate_version(&mut reader, MIN_SUPPORTED_VERSION)
+            .await
+            .unwrap();
+        assert_eq!(v, PROTOCOL_VERSION);
+        // Nothing past the greeting was consumed.
+        assert_eq!(reader, b"OK\n");
+    }
+
+    #[tokio::test]
+    async fn client_expecting_v2_rejects_v1_server() {
+        let min = ProtocolVersion { major: 2, minor: 0 };
+        let mut reader = &b"HELLO ouroboros/1.0\n"[..];
+        let err = negotiate_version(&mut reader, min).await.unwrap_err();
+        assert!(err.contains("incompatible"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn negotiate_rejects_a_peer_without_ring_hop() {
+        let mut reader = &b"HELLO ouroboros/1.0 3\n"[..];
+        let err = negotiate_version(&mut reader, MIN_SUPPORTED_VERSION)
+            .await
+            .unwrap_err();
+        assert!(err.contains("need >= 2.0"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn negotiate_rejects_newer_major() {
+        let mut reader = &b"HELLO ouroboros/9.0\n"[..];
+        let err = negotiate_version(&mut reader, MIN_SUPPORTED_VERSION)
+            .await
+            .unwrap_err();
+        assert!(err.contains("incompatible"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn negotiate_rejects_non_greeting() {
+        let mut reader = &b"OK next=127.0.0.1:7001\n"[..];
+        assert!(
+            negotiate_version(&mut reader, MIN_SUPPORTED_VERSION)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn node_next() {
+        assert_eq!(
+            parse_line("NODE NEXT 127.0.0.1:7001\n").unwrap(),
+            Command::NodeNext("127.0.0.1:7001".into())
+        );
+    }
+
+    #[test]
+    fn node_next_ipv6_brackets() {
+        assert_eq!(
+            parse_line("NODE NEXT [::1]:7001\n").unwrap(),
+            Command::NodeNext("[::1]:7001".into())
+        );
+        assert_eq!(
+            parse_line("NODE PREV [fe80::1]:7002").unwrap(),
+            Command::NodePrev("[fe80::1]:7002".into())
+        );
+    }
+
+    #[test]
+    fn node_next_multi() {
+        assert_eq!(
+            parse_line("NODE NEXT-MULTI 127.0.0.1:7001  127.0.0.1:7002\n").unwrap(),
+            Command::NodeNextMulti(vec!["127.0.0.1:7001".into(), "127.0.0.1:7002".into()])
+        );
+        assert!(parse_line("NODE NEXT-MULTI ").is_err());
+    }
+
+    #[test]
+    fn node_next_gen() {
+        assert_eq!(
+            parse_line("NODE NEXT-GEN 127.0.0.1:7001 42\n").unwrap(),
+            Command::NodeNextGen {
+                addr: "127.0.0.1:7001".into(),
+                generation: 42,
+            }
+        );
+        assert!(parse_line("NODE NEXT-GEN 127.0.0.1:7001").is_err());
+        assert!(parse_line("NODE NEXT-GEN 127.0.0.1:7001 x").is_err());
+        assert!(parse_line("NODE NEXT-GEN 127.0.0.1:7001 1 2").is_err());
+    }
+
+    #[test]
+    fn node_next_if() {
+        assert_eq!(
+            parse_line("NODE NEXT-IF <unset> 127.0.0.1:7001\n").unwrap(),
+            Command::NodeNextIf {
+                expected: "<unset>".into(),
+                new_addr: "127.0.0.1:7001".into(),
+            }
+        );
+        assert!(parse_line("NODE NEXT-IF 127.0.0.1:7001").is_err());
+        assert!(parse_line("NODE NEXT-IF a b c").is_err());
+    }
+
+    #[test]
+    fn node_prev() {
+        assert_eq!(
+            parse_line("NODE PREV 127.0.0.1:7002\n").unwrap(),
+            Command::NodePrev("127.0.0.1:7002".into())
+        );
+    }
+
+    #[test]
+    fn node_next_ring_and_ring() {
+        assert_eq!(
+            parse_line("NODE NEXT-RING backup 127.0.0.1:7001\n").unwrap(),
+            Command::NodeNextRing {
+                ring: "backup".into(),
+                addr: "127.0.0.1:7001".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("NODE RING backup").unwrap(),
+            Command::NodeRing {
+                ring: "backup".into()
+            }
+        );
+        assert!(parse_line("NODE NEXT-RING backup").is_err());
+        assert!(parse_line("NODE NEXT-RING b@d 127.0.0.1:7001").is_err());
+        assert!(parse_line("NODE RING ").is_err());
+    }
+
+    #[test]
+    fn node_next_rings_pairs() {
+        let cmd =
+            parse_line("NODE NEXT-RINGS default=127.0.0.1:7001;backup=127.0.0.1:7002\n").unwrap();
+        assert_eq!(
+            cmd,
+            Command::NodeNextRings(vec![
+                ("default".into(), "127.0.0.1:7001".into()),
+                ("backup".into(), "127.0.0.1:7002".into()),
+            ])
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "NODE NEXT-RINGS default=127.0.0.1:7001;backup=127.0.0.1:7002"
+        );
+        for bad in [
+            "NODE NEXT-RINGS",
+            "NODE NEXT-RINGS backup",
+            "NODE NEXT-RINGS backup=",
+            "NODE NEXT-RINGS b@d=127.0.0.1:7001",
+            "NODE NEXT-RINGS a=127.0.0.1:7001;",
+            "NODE NEXT-RINGS a=127.0.0.1:7001;a=127.0.0.1:7002",
+        ] {
+            assert!(parse_line(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn node_prev_missing_addr_errs() {
+        assert!(parse_line("NODE PREV ").is_err());
+        assert!(parse_line("NODE PREV").is_err());
+    }
+
+    #[test]
+    fn node_simple_verbs() {
+        assert_eq!(parse_line("NODE STATUS").unwrap(), Command::NodeStatus);
+        assert_eq!(parse_line("NODE STAT").unwrap(), Command::NodeStat);
+        assert_eq!(parse_line("NODE CIRCUIT").unwrap(), Command::NodeCircuit);
+        assert_eq!(parse_line("NODE SHUTDOWN").unwrap(), Command::NodeShutdown);
+        assert_eq!(parse_line("NODE PING").unwrap(), Command::NodePing);
+        assert_eq!(parse_line("NODE HEAL").unwrap(), Command::NodeHeal);
+    }
+
+    #[test]
+    fn node_heal_hop_and_done() {
+        assert_eq!(
+            parse_line("NODE HEAL-HOP tok 127.0.0.1:7000").unwrap(),
+            Command::NodeHealHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into()
+            }
+        );
+        assert_eq!(
+            parse_line("NODE HEAL-DONE tok").unwrap(),
+            Command::NodeHealDone {
+                token: "tok".into()
+            }
+        );
+    }
+
+    #[test]
+    fn ring_forward() {
+        let cmd = parse_line("RING FORWARD 5 hello world").unwrap();
+        match cmd {
+            Command::RingForward {
+                hop_count,
+                ttl,
+                msg,
+            } => {
+                assert_eq!(hop_count, 0);
+                assert_eq!(ttl, 5);
+                assert_eq!(msg, "hello world");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn ring_hop_carries_a_hop_count() {
+        let hop = Command::RingForward {
+            hop_count: 7,
+            ttl: 4,
+            msg: "hello world".into(),
+        };
+        assert_eq!(parse_line("RING FORWARD-HOP 7 4 hello world").unwrap(), hop);
+        assert_eq!(hop.to_string(), "RING FORWARD-HOP 7 4 hello world");
+        assert_eq!(
+            parse_line("RING FORWARD-HOP 0 4 hi").unwrap().to_string(),
+            "RING FORWARD 4 hi"
+        );
+        assert!(parse_line("RING FORWARD-HOP 7").is_err());
+        assert!(parse_line("RING FORWARD-HOP -1 4 hi").is_err());
+    }
+
+    #[test]
+    fn ring_on() {
+        assert_eq!(
+            parse_line("RING ON backup 2 hello world").unwrap(),
+            Command::RingOn {
+                ring: "backup".into(),
+                hop_count: 0,
+                ttl: 2,
+                msg: "hello world".into(),
+            }
+        );
+        assert!(parse_line("RING ON backup x hi").is_err());
+        assert!(parse_line("RING ON 2 hi").is_err());
+    }
+
+    #[test]
+    fn ring_on_hop_carries_a_hop_count() {
+        let hop = Command::RingOn {
+            ring: "backup".into(),
+            hop_count: 7,
+            ttl: 4,
+            msg: "hello world".into(),
+        };
+        assert_eq!(
+            parse_line("RING ON-HOP backup 7 4 hello world").unwrap(),
+            hop
+        );
+        assert_eq!(hop.to_string(), "RING ON-HOP backup 7 4 hello world");
+        assert_eq!(
+            parse_line("RING ON-HOP backup 0 4 hi").unwrap().to_string(),
+            "RING ON backup 4 hi"
+        );
+        assert!(parse_line("RING ON-HOP backup 7").is_err());
+        assert!(parse_line("RING ON-HOP 7 4 hi").is_err());
+    }
+
+    #[test]
+    fn ring_forward_bad_ttl() {
+        assert!(parse_line("RING FORWARD abc msg").is_err());
+    }
+
+    #[test]
+    fn topology_walk_hop_done_set() {
+        assert_eq!(parse_line("TOPOLOGY WALK").unwrap(), Command::TopologyWalk);
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-FRESH").unwrap(),
+            Command::TopologyWalkFresh
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY ABORT 7000-3").unwrap(),
+            Command::TopologyAbort {
+                token: "7000-3".into()
+            }
+        );
+        match parse_line("TOPOLOGY HOP tok 127.0.0.1:7000 a->b 127.0.0.1:7000,127.0.0.1:7001")
+            .unwrap()
+        {
+            Command::TopologyHop {
+                token,
+                start_addr,
+                history,
+                visited,
+                meta_key,
+            } => {
+                assert_eq!(token, "tok");
+                assert_eq!(start_addr, "127.0.0.1:7000");
+                assert_eq!(history, "a->b");
+                assert_eq!(visited, vec!["127.0.0.1:7000", "127.0.0.1:7001"]);
+                assert_eq!(meta_key, None);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("TOPOLOGY DONE tok a->b").unwrap() {
+            Command::TopologyDone {
+                token,
+                history,
+                meta_key,
+            } => {
+                assert_eq!(token, "tok");
+                assert_eq!(history, "a->b");
+                assert_eq!(meta_key, None);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("TOPOLOGY SET a->b;b->c").unwrap() {
+            Command::TopologySet { history } => assert_eq!(history, "a->b;b->c"),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn netmap_variants() {
+        assert_eq!(
+            parse_line("NETMAP DISCOVER").unwrap(),
+            Command::NetmapDiscover
+        );
+        assert_eq!(parse_line("NETMAP GET").unwrap(), Command::NetmapGet);
+        match parse_line("NETMAP HOP tok 127.0.0.1:7000 7000=Alive").unwrap() {
+            Command::NetmapHop {
+                token,
+                start_addr,
+                entries,
+            } => {
+                assert_eq!(token, "tok");
+                assert_eq!(start_addr, "127.0.0.1:7000");
+                assert_eq!(entries, "7000=Alive");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("NETMAP DONE tok 7000=Alive").unwrap() {
+            Command::NetmapDone { token, entries } => {
+                assert_eq!(token, "tok");
+                assert_eq!(entries, "7000=Alive");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("NETMAP SET 7000=Alive").unwrap() {
+            Command::NetmapSet { entries } => assert_eq!(entries, "7000=Alive"),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn file_push_and_pull() {
+        match parse_line("FILE PUSH 1024 myfile").unwrap() {
+            Command::FilePush { size, name } => {
+                assert_eq!(size, 1024);
+                assert_eq!(name, "myfile");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("FILE PULL myfile").unwrap() {
+            Command::FilePull { name } => assert_eq!(name, "myfile"),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn file_chunk_commands() {
+        match parse_line("FILE GET-CHUNK myfile.part-001-of-003").unwrap() {
+            Command::FileGetChunk { name } => assert_eq!(name, "myfile.part-001-of-003"),
+            other => panic!("unexpected: {other:?}"),
+        }
+        match parse_line("FILE GET-BACKUP-CHUNK foo").unwrap() {
+            Command::FileGetBackupChunk { name } => assert_eq!(name, "foo"),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn file_backup_push() {
+        match parse_line("FILE BACKUP-PUSH chunk.part-001-of-003 4096").unwrap() {
+            Command::FileBackupPush { name, size } => {
+                assert_eq!(name, "chunk.part-001-of-003");
+                assert_eq!(size, 4096);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        // Bad size
+        assert!(parse_line("FILE BACKUP-PUSH foo notanumber").is_err());
+        // Missing name
+        assert!(parse_line("FILE BACKUP-PUSH").is_err());
+    }
+
+    #[test]
+    fn file_push_chunk() {
+        // 6-field shape: name chunk_size file_size parts index start_port
+        match parse_line("FILE PUSH-CHUNK myfile.part-002-of-005 4096 20480 5 1 7000").unwrap() {
+            Command::FilePushChunk {
+                name,
+                chunk_size,
+                file_size,
+                parts,
+                index,
+                start_port,
+            } => {
+                assert_eq!(name, "myfile.part-002-of-005");
+                assert_eq!(chunk_size, 4096);
+                assert_eq!(file_size, 20480);
+                assert_eq!(parts, 5);
+                assert_eq!(index, 1);
+                assert_eq!(start_port, 7000);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn file_push_chunk_negative() {
+        // Bad numeric fields
+        assert!(parse_line("FILE PUSH-CHUNK n abc 1 1 0 7000").is_err());
+        // Missing fields
+        assert!(parse_line("FILE PUSH-CHUNK n 1").is_err());
+        // RELAY-STREAM was removed when the fan-out refactor landed; it must not parse.
+        assert!(parse_line("FILE RELAY-STREAM tok 7000 1024 3 0 0 myfile").is_err());
+    }
+
+    #[test]
+    fn negatives() {
+        assert!(parse_line("").is_err());
+        assert!(parse_line("BLAH").is_err());
+        assert!(parse_line("NODE NEXT").is_err()); // missing addr
+        assert!(parse_line("FILE PUSH abc xyz").is_err()); // non-numeric size
+        assert!(parse_line("FILE PUSH").is_err()); // no args
+        assert!(parse_line("FILE").is_err()); // missing verb
+    }
+
+    #[test]
+    fn crlf_tolerance() {
+        assert_eq!(parse_line("NODE STATUS\r\n").unwrap(), Command::NodeStatus);
+    }
+
+    #[test]
+    fn lowercase_noun_normalized() {
+        assert_eq!(parse_line("node status").unwrap(), Command::NodeStatus);
+    }
+
+    // --- Named negative cases. Each pins one specific failure mode so a
+    //     regression points at the offending input directly.
+
+    #[test]
+    fn parse_unknown_namespace_reports_namespace_in_err() {
+        let err = parse_line("BLAH foo").unwrap_err();
+        assert!(matches!(&err, RingError::UnknownCommand(n) if n == "BLAH"));
+        assert!(
+            err.to_string().contains("BLAH"),
+            "err missing namespace: {err}"
+        );
+    }
+
+    #[test]
+    fn whitespace_only_line_errs() {
+        assert!(parse_line("   \r\n").is_err());
+    }
+
+    #[test]
+    fn tab_separators_not_supported() {
+        // The parser splits on a literal space; tabs are not treated as
+        // delimiters. So `NODE\tNEXT addr` becomes noun = "NODE\tNEXT"
+        // (uppercased) and is rejected as an unknown namespace.
+        assert!(parse_line("NODE\tNEXT 127.0.0.1:7000").is_err());
+    }
+
+    // NODE
+    #[test]
+    fn node_next_missing_addr_errs() {
+        assert!(parse_line("NODE NEXT").is_err());
+    }
+
+    #[test]
+    fn node_next_only_whitespace_errs() {
+        assert!(parse_line("NODE NEXT   ").is_err());
+    }
+
+    #[test]
+    fn node_heal_hop_missing_start_addr_errs() {
+        assert!(parse_line("NODE HEAL-HOP tok").is_err());
+    }
+
+    #[test]
+    fn node_heal_hop_missing_token_errs() {
+        // Empty token before the addr → both fields blank/start_addr empty.
+        assert!(parse_line("NODE HEAL-HOP   addr").is_err());
+    }
+
+    #[test]
+    fn node_heal_done_missing_token_errs() {
+        assert!(parse_line("NODE HEAL-DONE").is_err());
+    }
+
+    #[test]
+    fn node_unknown_verb_errs() {
+        assert!(parse_line("NODE FROBNICATE 1 2").is_err());
+    }
+
+    // RING
+    #[test]
+    fn ring_forward_missing_ttl_errs() {
+        assert!(parse_line("RING FORWARD").is_err());
+    }
+
+    #[test]
+    fn ring_timed_parses() {
+        assert_eq!(
+            parse_line("RING TIMED 1700000000000 hello world").unwrap(),
+            Command::RingTimed {
+                expiry_ms: 1_700_000_000_000,
+                msg: "hello world".into()
+            }
+        );
+        assert!(parse_line("RING TIMED soon hi").is_err());
+        assert!(parse_line("RING TIMED -1 hi").is_err());
+        assert_eq!(parse_line("RING TIMED 1 hi").unwrap().ring_ttl(), None);
+    }
+
+    #[test]
+    fn expiry_is_read_against_the_wall_clock() {
+        assert!(is_expired(0));
+        assert!(is_expired(now_unix_ms()));
+        assert!(!is_expired(now_unix_ms() + 60_000));
+    }
+
+    #[test]
+    fn ring_forward_zero_ttl_parses() {
+        match parse_line("RING FORWARD 0 ").unwrap() {
+            Command::RingForward { ttl, msg, .. } => {
+                assert_eq!(ttl, 0);
+                assert_eq!(msg, "");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn ring_forward_overflow_ttl_errs() {
+        // 9999999999999999 > u32::MAX (~4.3e9).
+        assert!(parse_line("RING FORWARD 9999999999999999 m").is_err());
+    }
+
+    #[test]
+    fn ring_forward_msg_with_spaces_kept_intact() {
+        match parse_line("RING FORWARD 3 a b c d").unwrap() {
+            Command::RingForward { ttl, msg, .. } => {
+                assert_eq!(ttl, 3);
+                assert_eq!(msg, "a b c d");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn ring_ack_parses() {
+        assert_eq!(
+            parse_line("RING ACK 2 hello world").unwrap(),
+            Command::RingAck {
+                ttl: 2,
+                msg: "hello world".into()
+            }
+        );
+        assert!(parse_line("RING ACK x hi").is_err());
+    }
+
+    #[test]
+    fn ring_ack_hop_parses() {
+        assert_eq!(
+            parse_line("RING ACK-HOP tok 127.0.0.1:7000 1 a:1;b:2 hi there").unwrap(),
+            Command::RingAckHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                ttl: 1,
+                seen: "a:1;b:2".into(),
+                msg: "hi there".into(),
+            }
+        );
+        assert!(parse_line("RING ACK-HOP tok addr notanumber seen").is_err());
+        assert!(parse_line("RING ACK-HOP tok").is_err());
+    }
+
+    #[test]
+    fn ring_ack_done_splits_seen_list() {
+        assert_eq!(
+            parse_line("RING ACK-DONE tok a:1;b:2;").unwrap(),
+            Command::RingAckDone {
+                token: "tok".into(),
+                seen: vec!["a:1".into(), "b:2".into()],
+            }
+        );
+        assert!(parse_line("RING ACK-DONE ").is_err());
+    }
+
+    #[test]
+    fn ring_echo_round_trip() {
+        assert_eq!(
+            parse_line("RING ECHO 3 hello world").unwrap(),
+            Command::RingEcho {
+                ttl: 3,
+                msg: "hello world".into()
+            }
+        );
+        assert_eq!(
+            parse_line("RING ECHO-HOP tok 127.0.0.1:7000 0 hi there").unwrap(),
+            Command::RingEchoHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                ttl: 0,
+                msg: "hi there".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("RING ECHO-DONE tok hi there").unwrap(),
+            Command::RingEchoDone {
+                token: "tok".into(),
+                msg: "hi there".into(),
+            }
+        );
+        assert!(parse_line("RING ECHO x hi").is_err());
+        assert!(parse_line("RING ECHO-HOP tok addr notanumber hi").is_err());
+        assert!(parse_line("RING ECHO-DONE ").is_err());
+    }
+
+    #[test]
+    fn ring_all_takes_the_rest_of_the_line() {
+        assert_eq!(
+            parse_line("RING ALL hello world\n").unwrap(),
+            Command::RingAll {
+                msg: "hello world".into()
+            }
+        );
+        assert_eq!(
+            parse_line("RING ALL").unwrap(),
+            Command::RingAll { msg: String::new() }
+        );
+    }
+
+    #[test]
+    fn ring_once_round_trip() {
+        assert_eq!(
+            parse_line("RING ONCE hello world").unwrap(),
+            Command::RingOnce {
+                msg: "hello world".into()
+            }
+        );
+        assert_eq!(
+            parse_line("RING ONCE-HOP tok 3 127.0.0.1:7000 hi there").unwrap(),
+            Command::RingOnceHop {
+                token: "tok".into(),
+                hop_count: 3,
+                start_addr: "127.0.0.1:7000".into(),
+                msg: "hi there".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("RING ONCE-DONE tok").unwrap(),
+            Command::RingOnceDone {
+                token: "tok".into()
+            }
+        );
+        assert!(parse_line("RING ONCE-HOP tok").is_err());
+        assert!(parse_line("RING ONCE-HOP tok 127.0.0.1:7000 hi").is_err());
+        assert!(parse_line("RING ONCE-DONE ").is_err());
+        assert!(parse_line("RING ONCE-DONE a b").is_err());
+    }
+
+    #[test]
+    fn ring_req_resp_parse() {
+        assert_eq!(
+            parse_line("RING REQ 127.0.0.1:9100 2 who has x").unwrap(),
+            Command::RingReq {
+                callback_addr: "127.0.0.1:9100".into(),
+                ttl: 2,
+                msg: "who has x".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("RING RESP 127.0.0.1:9100 7001 has x").unwrap(),
+            Command::RingResp {
+                callback_addr: "127.0.0.1:9100".into(),
+                payload: "7001 has x".into(),
+            }
+        );
+        assert!(parse_line("RING REQ 127.0.0.1:9100 x hi").is_err());
+        assert!(parse_line("RING REQ ").is_err());
+        assert!(parse_line("RING RESP ").is_err());
+    }
+
+    // TOPOLOGY
+    #[test]
+    fn topology_hop_empty_history_ok() {
+        // First-hop degenerate case: history is empty when only token + start
+        // are provided.
+        match parse_line("TOPOLOGY HOP tok addr ").unwrap() {
+            Command::TopologyHop {
+                token,
+                start_addr,
+                history,
+                visited,
+                ..
+            } => {
+                assert_eq!(token, "tok");
+                assert_eq!(start_addr, "addr");
+                assert_eq!(history, "");
+                assert!(visited.is_empty());
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn topology_hop_and_done_carry_a_meta_key() {
+        assert_eq!(
+            parse_line("TOPOLOGY HOP tok addr 7000:blue->7001 7000 meta=color").unwrap(),
+            Command::TopologyHop {
+                token: "tok".into(),
+                start_addr: "addr".into(),
+                history: "7000:blue->7001".into(),
+                visited: vec!["7000".into()],
+                meta_key: Some("color".into()),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY DONE tok 7000:blue->7001:? meta=color").unwrap(),
+            Command::TopologyDone {
+                token: "tok".into(),
+                history: "7000:blue->7001:?".into(),
+                meta_key: Some("color".into()),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-META color").unwrap(),
+            Command::TopologyWalkMeta {
+                key: "color".into()
+            }
+        );
+        assert!(parse_line("TOPOLOGY WALK-META").is_err());
+    }
+
+    #[test]
+    fn topology_hop_without_visited_set_is_accepted() {
+        // Sent by nodes that predate cycle detection.
+        match parse_line("TOPOLOGY HOP tok addr a->b").unwrap() {
+            Command::TopologyHop {
+                history, visited, ..
+            } => {
+                assert_eq!(history, "a->b");
+                assert!(visited.is_empty());
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn topology_hop_missing_start_errs() {
+        assert!(parse_line("TOPOLOGY HOP tok").is_err());
+    }
+
+    #[test]
+    fn topology_done_missing_token_errs() {
+        assert!(parse_line("TOPOLOGY DONE").is_err());
+        assert!(parse_line("TOPOLOGY ABORT ").is_err());
+        assert!(parse_line("TOPOLOGY ABORT a b").is_err());
+    }
+
+    #[test]
+    fn topology_set_empty_history_parses() {
+        // The "SET " prefix matches with a trailing space; history is empty.
+        match parse_line("TOPOLOGY SET ").unwrap() {
+            Command::TopologySet { history } => assert_eq!(history, ""),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn topology_reverse_walk_hop_done() {
+        assert_eq!(
+            parse_line("TOPOLOGY REVERSE-WALK").unwrap(),
+            Command::TopologyReverseWalk
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY BIDIR-WALK\n").unwrap(),
+            Command::TopologyBidirWalk
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY REVERSE-HOP tok 127.0.0.1:7000 7000->7002").unwrap(),
+            Command::TopologyReverseHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                history: "7000->7002".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY REVERSE-DONE tok 7000->7002;7002->7001").unwrap(),
+            Command::TopologyReverseDone {
+                token: "tok".into(),
+                history: "7000->7002;7002->7001".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn ring_ttl_covers_every_ring_hop() {
+        for (line, ttl) in [
+            ("RING FORWARD 7 hi", Some(7)),
+            ("RING ON backup 6 hi", Some(6)),
+            ("RING ACK-HOP t 127.0.0.1:7000 5 a hi", Some(5)),
+            ("RING ECHO 4 hi", Some(4)),
+            ("RING COLLECT 3 color", Some(3)),
+            ("RING REQ 127.0.0.1:9 2 hi", Some(2)),
+            ("RING RESP 127.0.0.1:9 2", None),
+            ("TOPOLOGY WALK-MAX 9", None),
+        ] {
+            assert_eq!(parse_line(line).unwrap().ring_ttl(), ttl, "{line}");
+        }
+    }
+
+    #[test]
+    fn ring_collect_parses() {
+        assert_eq!(
+            parse_line("RING COLLECT 3 color").unwrap(),
+            Command::RingCollect {
+                ttl: 3,
+                key: "color".into()
+            }
+        );
+        assert!(parse_line("RING COLLECT 3").is_err());
+        assert!(parse_line("RING COLLECT x color").is_err());
+        assert!(parse_line("RING COLLECT 3 two keys").is_err());
+    }
+
+    #[test]
+    fn ring_collect_hop_and_done_parse() {
+        assert_eq!(
+            parse_line("RING COLLECT-HOP tok 127.0.0.1:7000 1 color 7000=red;7001=-").unwrap(),
+            Command::RingCollectHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                ttl: 1,
+                key: "color".into(),
+                results: "7000=red;7001=-".into(),
+            }
+        );
+        assert!(parse_line("RING COLLECT-HOP tok 127.0.0.1:7000 x color r").is_err());
+        assert!(parse_line("RING COLLECT-HOP tok").is_err());
+        assert_eq!(
+            parse_line("RING COLLECT-DONE tok 7000=dark red").unwrap(),
+            Command::RingCollectDone {
+                token: "tok".into(),
+                results: "7000=dark red".into(),
+            }
+        );
+        assert!(parse_line("RING COLLECT-DONE ").is_err());
     }
 
-    // PULL
-    if let Some(rest) = rest.strip_prefix("PULL ") {
-        let name = rest.trim().to_string();
-        if name.is_empty() {
-            return Err("missing file name for FILE PULL".into());
-        }
-        validate_filename(&name).map_err(|e| format!("FILE PULL: {e}"))?;
-        return Ok(Command::FilePull { name });
+    #[test]
+    fn topology_reverse_hop_missing_start_errs() {
+        assert!(parse_line("TOPOLOGY REVERSE-HOP tok").is_err());
+        assert!(parse_line("TOPOLOGY REVERSE-DONE ").is_err());
     }
 
-    // LIST
-    if rest.eq_ignore_ascii_case("LIST") {
-        return Ok(Command::FileList);
+    fn edge(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.into(),
+            to: to.into(),
+        }
     }
 
-    // TAGS-SET
-    if let Some(rest) = rest.strip_prefix("TAGS-SET ") {
-        return Ok(Command::FileTagsSet {
-            entries: rest.to_string(),
-        });
+    #[test]
+    fn history_parses_wire_and_reply_forms() {
+        let edges = vec![
+            edge("n0@127.0.0.1:7000", "7001"),
+            edge("7001", "7002"),
+            edge("7002", "7000"),
+        ];
+        assert_eq!(
+            parse_history("n0@127.0.0.1:7000->7001;7001->7002;;7002->7000"),
+            edges
+        );
+        assert_eq!(
+            parse_history("n0@127.0.0.1:7000->7001\n7001->7002\n7002->7000\n"),
+            edges
+        );
+        assert_eq!(parse_history(""), []);
+        assert_eq!(parse_history("7000-7001"), []);
     }
 
-    // GET-CHUNK
-    if let Some(rest) = rest.strip_prefix("GET-CHUNK ") {
-        let name = rest.trim().to_string();
-        if name.is_empty() {
-            return Err("missing file name for FILE GET-CHUNK".into());
+    #[test]
+    fn history_round_trips_through_its_wire_form() {
+        let histories = [
+            vec![],
+            vec![edge("7000", "7000")],
+            vec![
+                edge("a@[::1]:7000", "[::1]:7001"),
+                edge("[::1]:7001", "b@[::1]:7002"),
+                edge("b@[::1]:7002", "[::1]:7000"),
+            ],
+        ];
+        for edges in histories {
+            assert_eq!(parse_history(&roundtrip_history(&edges)), edges);
         }
-        validate_filename(&name).map_err(|e| format!("FILE GET-CHUNK: {e}"))?;
-        return Ok(Command::FileGetChunk { name });
+        let wire = "7000->7001;7001->7000";
+        assert_eq!(roundtrip_history(&parse_history(wire)), wire);
     }
 
-    // BACKUP-PUSH (saving node → predecessor; raw bytes follow)
-    if let Some(rest) = rest.strip_prefix("BACKUP-PUSH ") {
-        let mut parts = rest.splitn(2, ' ');
-        let name = parts.next().unwrap_or("").trim().to_string();
-        let size_str = parts.next().unwrap_or("").trim();
-        if name.is_empty() {
-            return Err("missing file name for FILE BACKUP-PUSH".into());
-        }
-        validate_filename(&name).map_err(|e| format!("FILE BACKUP-PUSH: {e}"))?;
-        let size = size_str
-            .parse::<u64>()
-            .map_err(|_| "invalid size for FILE BACKUP-PUSH")?;
-        return Ok(Command::FileBackupPush { name, size });
+    #[test]
+    fn history_table_pads_both_columns() {
+        let table = render_history_table(&[edge("node-00@7000", "7001"), edge("7001", "7000")]);
+        assert_eq!(
+            table,
+            "+--------------+------+\n\
+             | FROM         | TO   |\n\
+             +--------------+------+\n\
+             | node-00@7000 | 7001 |\n\
+             | 7001         | 7000 |\n\
+             +--------------+------+\n"
+        );
     }
 
-    // CONTENT-PUSH (predecessor → respawned successor; anti-entropy)
-    if let Some(rest) = rest.strip_prefix("CONTENT-PUSH ") {
-        let mut parts = rest.splitn(2, ' ');
-        let name = parts.next().unwrap_or("").trim().to_string();
-        let size_str = parts.next().unwrap_or("").trim();
-        if name.is_empty() {
-            return Err("missing file name for FILE CONTENT-PUSH".into());
+    #[test]
+    fn response_reads_ok_err_status_and_walks() {
+        assert_eq!(
+            parse_response(&["OK"]).unwrap(),
+            Response::Ok(String::new())
+        );
+        assert_eq!(
+            parse_response(&["OK next=127.0.0.1:7002\n"]).unwrap(),
+            Response::Ok("next=127.0.0.1:7002".into())
+        );
+        assert_eq!(
+            parse_response(&["TOKEN 7000-1", "ERR walk timeout"]).unwrap(),
+            Response::Error("walk timeout".into())
+        );
+
+        let walk = vec![edge("7000", "7001"), edge("7001", "7000")];
+        for lines in [
+            &["TOKEN 7000-1", "7000->7001", "7001->7000", "OK"][..],
+            &["CACHED 12", "7000->7001", "7001->7000"],
+            &["7000->7001", "7001->7000", "OK"],
+        ] {
+            assert_eq!(
+                parse_response(lines).unwrap(),
+                Response::WalkResult {
+                    edges: walk.clone()
+                },
+                "{lines:?}"
+            );
         }
-        validate_filename(&name).map_err(|e| format!("FILE CONTENT-PUSH: {e}"))?;
-        let size = size_str
-            .parse::<u64>()
-            .map_err(|_| "invalid size for FILE CONTENT-PUSH")?;
-        return Ok(Command::FileContentPush { name, size });
     }
 
-    // GET-BACKUP-CHUNK
-    if let Some(rest) = rest.strip_prefix("GET-BACKUP-CHUNK ") {
-        let name = rest.trim().to_string();
-        if name.is_empty() {
-            return Err("missing file name for FILE GET-BACKUP-CHUNK".into());
-        }
-        validate_filename(&name).map_err(|e| format!("FILE GET-BACKUP-CHUNK: {e}"))?;
-        return Ok(Command::FileGetBackupChunk { name });
+    #[test]
+    fn response_keeps_whole_values_and_fills_missing_status_keys() {
+        let status = [
+            "PORT 127.0.0.1:7001",
+            "NAME node 01",
+            "NEXT /tmp/ring sockets/7002.sock",
+            "PREV <unset>",
+            "FAN_OUT 1",
+            "GENERATION 4",
+            "ROLE FOLLOWER",
+            "OK",
+        ];
+        assert_eq!(
+            parse_response(&status).unwrap(),
+            Response::NodeInfo(Box::new(NodeInfo {
+                port: "127.0.0.1:7001".into(),
+                name: Some("node 01".into()),
+                next: Some("/tmp/ring sockets/7002.sock".into()),
+                fan_out: 1,
+                generation: 4,
+                role: NodeRole::Follower,
+                ..NodeInfo::default()
+            }))
+        );
+        // An older node, or one never wired: just PORT.
+        assert_eq!(
+            parse_response(&["PORT 127.0.0.1:7001"]).unwrap(),
+            Response::NodeInfo(Box::new(NodeInfo {
+                port: "127.0.0.1:7001".into(),
+                ..NodeInfo::default()
+            }))
+        );
+        assert!(parse_response(&[]).is_err());
+        assert!(parse_response(&["WHAT is this", "OK"]).is_err());
     }
 
-    // PUSH-CHUNK (start node fans out to each chunk owner)
-    if let Some(rest) = rest.strip_prefix("PUSH-CHUNK ") {
-        let mut parts = rest.splitn(6, ' ');
-        let name = parts.next().unwrap_or("").trim().to_string();
-        let chunk_size_str = parts.next().unwrap_or("").trim();
-        let file_size_str = parts.next().unwrap_or("").trim();
-        let total_parts_str = parts.next().unwrap_or("").trim();
-        let index_str = parts.next().unwrap_or("").trim();
-        let start_port_str = parts.next().unwrap_or("").trim();
-        if name.is_empty() {
-            return Err("missing name for FILE PUSH-CHUNK".into());
-        }
-        validate_filename(&name).map_err(|e| format!("FILE PUSH-CHUNK: {e}"))?;
-        let chunk_size = chunk_size_str
-            .parse::<u64>()
-            .map_err(|_| "invalid chunk_size for FILE PUSH-CHUNK")?;
-        let file_size = file_size_str
-            .parse::<u64>()
-            .map_err(|_| "invalid file_size for FILE PUSH-CHUNK")?;
-        let parts_u = total_parts_str
-            .parse::<u32>()
-            .map_err(|_| "invalid parts for FILE PUSH-CHUNK")?;
-        let index = index_str
-            .parse::<u32>()
-            .map_err(|_| "invalid index for FILE PUSH-CHUNK")?;
-        let start_port = start_port_str
-            .parse::<u16>()
-            .map_err(|_| "invalid start_port for FILE PUSH-CHUNK")?;
-        return Ok(Command::FilePushChunk {
-            name,
-            chunk_size,
-            file_size,
-            parts: parts_u,
-            index,
-            start_port,
-        });
+    #[test]
+    fn response_reads_every_node_info_key() {
+        let info = [
+            "PORT 127.0.0.1:7001",
+            "NEXT 127.0.0.1:7002",
+            "NAME <unset>",
+            "GENERATION 4",
+            "UPTIME_SECS 12",
+            "ROLE LEADER",
+            "LEADER 127.0.0.1:7001",
+            "RING_SIZE 3",
+            "MSG_COUNT 40",
+            "WALK_COUNT 2",
+            "CONNECTIONS 1",
+            "VERSION 0.1.0",
+            "OK",
+        ];
+        assert_eq!(
+            parse_response(&info).unwrap(),
+            Response::NodeInfo(Box::new(NodeInfo {
+                port: "127.0.0.1:7001".into(),
+                next: Some("127.0.0.1:7002".into()),
+                leader: Some("127.0.0.1:7001".into()),
+                generation: 4,
+                role: NodeRole::Leader,
+                uptime_secs: Some(12),
+                ring_size: Some(3),
+                msg_count: Some(40),
+                walk_count: Some(2),
+                connections: Some(1),
+                version: Some("0.1.0".into()),
+                ..NodeInfo::default()
+            }))
+        );
+        assert_eq!(parse_line("NODE INFO").unwrap(), Command::NodeInfo);
+        assert!(parse_line("NODE INFO now").is_err());
     }
 
-    Err("unknown FILE command".into())
-}
+    #[test]
+    fn body_len_covers_commands_with_a_payload() {
+        assert_eq!(
+            parse_line("FILE PUSH 12 a.txt").unwrap().body_len(),
+            Some(12)
+        );
+        assert_eq!(
+            parse_line("FILE CONTENT-PUSH a.txt 7").unwrap().body_len(),
+            Some(7)
+        );
+        assert_eq!(parse_line("FILE PULL a.txt").unwrap().body_len(), None);
+        assert_eq!(parse_line("NODE PING").unwrap().body_len(), None);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // ELECT
+    #[test]
+    fn elect_round_trip() {
+        assert_eq!(parse_line("ELECT START").unwrap(), Command::ElectStart);
+        assert_eq!(
+            parse_line("ELECT HOP tok 127.0.0.1:7000 127.0.0.1:7002").unwrap(),
+            Command::ElectHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                candidate: "127.0.0.1:7002".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("ELECT DONE tok 127.0.0.1:7002").unwrap(),
+            Command::ElectDone {
+                token: "tok".into(),
+                candidate: "127.0.0.1:7002".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("ELECT LEADER 127.0.0.1:7000 127.0.0.1:7002").unwrap(),
+            Command::ElectLeader {
+                start_addr: "127.0.0.1:7000".into(),
+                leader: "127.0.0.1:7002".into(),
+            }
+        );
+    }
 
     #[test]
-    fn node_next() {
+    fn elect_rejects_malformed() {
+        assert!(parse_line("ELECT HOP tok 127.0.0.1:7000").is_err());
+        assert!(parse_line("ELECT DONE tok").is_err());
+        assert!(parse_line("ELECT LEADER a b c").is_err());
         assert_eq!(
-            parse_line("NODE NEXT 127.0.0.1:7001\n").unwrap(),
-            Command::NodeNext("127.0.0.1:7001".into())
+            parse_line("ELECT CMD KV SET mode rw").unwrap(),
+            Command::LeaderCmd {
+                cmd: "KV SET mode rw".into()
+            }
         );
+        assert!(parse_line("ELECT CMD").is_err());
+        assert!(parse_line("ELECT").is_err());
+        assert!(parse_line("ELECT 127.0.0.1:7000").is_err());
     }
 
+    // PROBE
     #[test]
-    fn node_simple_verbs() {
-        assert_eq!(parse_line("NODE STATUS").unwrap(), Command::NodeStatus);
-        assert_eq!(parse_line("NODE PING").unwrap(), Command::NodePing);
-        assert_eq!(parse_line("NODE HEAL").unwrap(), Command::NodeHeal);
+    fn probe_takes_one_address() {
+        assert_eq!(
+            parse_line("PROBE 127.0.0.1:7001").unwrap(),
+            Command::Probe {
+                dead_addr: "127.0.0.1:7001".into(),
+            }
+        );
+        assert!(parse_line("PROBE").is_err());
+        assert!(parse_line("PROBE a b").is_err());
     }
 
+    // HEARTBEAT
     #[test]
-    fn node_heal_hop_and_done() {
+    fn heartbeat_next_is_optional() {
         assert_eq!(
-            parse_line("NODE HEAL-HOP tok 127.0.0.1:7000").unwrap(),
-            Command::NodeHealHop {
-                token: "tok".into(),
-                start_addr: "127.0.0.1:7000".into()
+            parse_line("HEARTBEAT 127.0.0.1:7000 alpha 3 127.0.0.1:7001").unwrap(),
+            Command::Heartbeat {
+                addr: "127.0.0.1:7000".into(),
+                name: "alpha".into(),
+                generation: 3,
+                next: Some("127.0.0.1:7001".into()),
             }
         );
         assert_eq!(
-            parse_line("NODE HEAL-DONE tok").unwrap(),
-            Command::NodeHealDone {
-                token: "tok".into()
+            parse_line("HEARTBEAT 127.0.0.1:7000 <unset> 1").unwrap(),
+            Command::Heartbeat {
+                addr: "127.0.0.1:7000".into(),
+                name: "<unset>".into(),
+                generation: 1,
+                next: None,
             }
         );
+        assert!(parse_line("HEARTBEAT 127.0.0.1:7000 alpha").is_err());
+        assert!(parse_line("HEARTBEAT 127.0.0.1:7000 alpha x").is_err());
+        assert!(parse_line("HEARTBEAT a b 1 c d").is_err());
+        assert_eq!(parse_line("MEMBERS").unwrap(), Command::Members);
+        assert!(parse_line("MEMBERS all").is_err());
     }
 
+    // INCR
     #[test]
-    fn ring_forward() {
-        let cmd = parse_line("RING FORWARD 5 hello world").unwrap();
-        match cmd {
-            Command::RingForward { ttl, msg } => {
-                assert_eq!(ttl, 5);
-                assert_eq!(msg, "hello world");
+    fn incr_and_its_hop() {
+        assert_eq!(
+            parse_line("INCR START hits\n").unwrap(),
+            Command::Incr {
+                counter_name: "hits".into()
             }
-            other => panic!("unexpected: {other:?}"),
-        }
+        );
+        assert_eq!(
+            parse_line("INCR HOP hits t1 2 127.0.0.1:7000 42").unwrap(),
+            Command::IncrHop {
+                counter_name: "hits".into(),
+                token: "t1".into(),
+                hop_count: 2,
+                origin: "127.0.0.1:7000".into(),
+                current_value: 42,
+            }
+        );
+        assert!(parse_line("INCR START").is_err());
+        assert!(parse_line("INCR START a b").is_err());
+        assert!(parse_line("INCR hits").is_err());
+        assert!(parse_line("INCR HOP hits t1 127.0.0.1:7000 42").is_err());
+        assert!(parse_line("INCR HOP hits t1 2 127.0.0.1:7000 -1").is_err());
     }
 
+    // TOPOLOGY WALK-FIND
     #[test]
-    fn ring_forward_bad_ttl() {
-        assert!(parse_line("RING FORWARD abc msg").is_err());
+    fn walk_find_and_its_replies() {
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-FIND role primary db").unwrap(),
+            Command::WalkFind {
+                key: "role".into(),
+                value: "primary db".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY FIND-HOP t1 127.0.0.1:7000 7000->7001 role primary db").unwrap(),
+            Command::WalkFindHop {
+                token: "t1".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                history: "7000->7001".into(),
+                key: "role".into(),
+                value: "primary db".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY FIND-FOUND t1 127.0.0.1:7001 7000->7001").unwrap(),
+            Command::WalkFound {
+                token: "t1".into(),
+                addr: "127.0.0.1:7001".into(),
+                history: "7000->7001".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY FIND-NOT-FOUND t1").unwrap(),
+            Command::WalkNotFound { token: "t1".into() }
+        );
+        assert!(parse_line("TOPOLOGY WALK-FIND").is_err());
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-PARALLEL").unwrap(),
+            Command::WalkParallel
+        );
+        assert!(parse_line("TOPOLOGY WALK-PARALLEL now").is_err());
+        assert!(parse_line("TOPOLOGY FIND-HOP t1 127.0.0.1:7000 7000->7001").is_err());
+        assert!(parse_line("TOPOLOGY FIND-FOUND t1").is_err());
+        assert!(parse_line("TOPOLOGY FIND-NOT-FOUND").is_err());
     }
 
+    // SPLIT-BRAIN
     #[test]
-    fn topology_walk_hop_done_set() {
-        assert_eq!(parse_line("TOPOLOGY WALK").unwrap(), Command::TopologyWalk);
-        match parse_line("TOPOLOGY HOP tok 127.0.0.1:7000 a->b").unwrap() {
-            Command::TopologyHop {
-                token,
-                start_addr,
-                history,
-            } => {
-                assert_eq!(token, "tok");
-                assert_eq!(start_addr, "127.0.0.1:7000");
-                assert_eq!(history, "a->b");
+    fn split_brain_check_and_its_hops() {
+        assert_eq!(
+            parse_line("SPLIT-BRAIN CHECK").unwrap(),
+            Command::SplitBrainCheck
+        );
+        assert_eq!(
+            parse_line("SPLIT-BRAIN HOP t1 127.0.0.1:7000 127.0.0.1:7000=<unset>").unwrap(),
+            Command::SplitBrainHop {
+                token: "t1".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                leaders: "127.0.0.1:7000=<unset>".into(),
             }
-            other => panic!("unexpected: {other:?}"),
-        }
-        match parse_line("TOPOLOGY DONE tok a->b").unwrap() {
-            Command::TopologyDone { token, history } => {
-                assert_eq!(token, "tok");
-                assert_eq!(history, "a->b");
+        );
+        assert_eq!(
+            parse_line("SPLIT-BRAIN DONE t1 a=b;b=b").unwrap(),
+            Command::SplitBrainDone {
+                token: "t1".into(),
+                leaders: "a=b;b=b".into(),
             }
-            other => panic!("unexpected: {other:?}"),
-        }
-        match parse_line("TOPOLOGY SET a->b;b->c").unwrap() {
-            Command::TopologySet { history } => assert_eq!(history, "a->b;b->c"),
-            other => panic!("unexpected: {other:?}"),
-        }
+        );
+        assert!(parse_line("SPLIT-BRAIN CHECK now").is_err());
+        assert!(parse_line("SPLIT-BRAIN HOP t1 127.0.0.1:7000").is_err());
+        assert!(parse_line("SPLIT-BRAIN DONE t1").is_err());
     }
 
+    // CHAOS
     #[test]
-    fn netmap_variants() {
+    fn chaos_mode_and_off() {
         assert_eq!(
-            parse_line("NETMAP DISCOVER").unwrap(),
-            Command::NetmapDiscover
+            parse_line("CHAOS MODE delay 10 2").unwrap(),
+            Command::ChaosMode {
+                mode: ChaosMode::Delay {
+                    mean_ms: 10,
+                    stddev_ms: 2,
+                },
+            }
         );
-        assert_eq!(parse_line("NETMAP GET").unwrap(), Command::NetmapGet);
-        match parse_line("NETMAP HOP tok 127.0.0.1:7000 7000=Alive").unwrap() {
-            Command::NetmapHop {
-                token,
-                start_addr,
-                entries,
-            } => {
-                assert_eq!(token, "tok");
-                assert_eq!(start_addr, "127.0.0.1:7000");
-                assert_eq!(entries, "7000=Alive");
+        assert_eq!(parse_line("chaos off").unwrap(), Command::ChaosOff);
+        assert!(parse_line("CHAOS MODE jitter 3").is_err());
+        assert!(parse_line("CHAOS OFF now").is_err());
+        assert!(parse_line("CHAOS").is_err());
+    }
+
+    // BARRIER
+    #[test]
+    fn barrier_commands_need_a_positive_count() {
+        assert_eq!(
+            parse_line("barrier wait sync-1 3").unwrap(),
+            Command::BarrierWait {
+                barrier_id: "sync-1".into(),
+                n: 3,
             }
-            other => panic!("unexpected: {other:?}"),
-        }
-        match parse_line("NETMAP DONE tok 7000=Alive").unwrap() {
-            Command::NetmapDone { token, entries } => {
-                assert_eq!(token, "tok");
-                assert_eq!(entries, "7000=Alive");
+        );
+        assert_eq!(
+            parse_line("BARRIER HOP sync-1 3 127.0.0.1:7000 127.0.0.1:7000;127.0.0.1:7001")
+                .unwrap(),
+            Command::BarrierHop {
+                barrier_id: "sync-1".into(),
+                n: 3,
+                start_addr: "127.0.0.1:7000".into(),
+                visited: "127.0.0.1:7000;127.0.0.1:7001".into(),
             }
-            other => panic!("unexpected: {other:?}"),
-        }
-        match parse_line("NETMAP SET 7000=Alive").unwrap() {
-            Command::NetmapSet { entries } => assert_eq!(entries, "7000=Alive"),
-            other => panic!("unexpected: {other:?}"),
-        }
+        );
+        assert!(parse_line("BARRIER HOP sync-1 3 127.0.0.1:7000").is_err());
+        assert_eq!(
+            parse_line("BARRIER ENTER sync-1 3").unwrap(),
+            Command::Barrier {
+                barrier_id: "sync-1".into(),
+                n: 3,
+            }
+        );
+        assert!(parse_line("BARRIER ENTER sync-1 0").is_err());
+        assert!(parse_line("BARRIER ENTER sync-1").is_err());
+        assert!(parse_line("BARRIER sync-1 3").is_err());
+        assert!(parse_line("BARRIER DONE a b").is_err());
     }
 
-    #[test]
-    fn file_push_and_pull() {
-        match parse_line("FILE PUSH 1024 myfile").unwrap() {
-            Command::FilePush { size, name } => {
-                assert_eq!(size, 1024);
-                assert_eq!(name, "myfile");
-            }
-            other => panic!("unexpected: {other:?}"),
-        }
-        match parse_line("FILE PULL myfile").unwrap() {
-            Command::FilePull { name } => assert_eq!(name, "myfile"),
-            other => panic!("unexpected: {other:?}"),
+    /// Generators for random commands whose fields are already in the form
+    /// `parse_line` produces, so rendering and re-parsing must give back the
+    /// same command.
+    mod arb {
+        use super::*;
+        use rand::seq::SliceRandom;
+        use std::collections::BTreeMap;
+
+        use rand::{Rng, RngCore};
+        use rand_chacha::ChaCha20Rng;
+
+        fn from(rng: &mut ChaCha20Rng, alphabet: &[u8], len: std::ops::Range<usize>) -> String {
+            let len = rng.gen_range(len);
+            (0..len)
+                .map(|_| *alphabet.choose(rng).unwrap() as char)
+                .collect()
         }
-    }
 
-    #[test]
-    fn file_chunk_commands() {
-        match parse_line("FILE GET-CHUNK myfile.part-001-of-003").unwrap() {
-            Command::FileGetChunk { name } => assert_eq!(name, "myfile.part-001-of-003"),
-            other => panic!("unexpected: {other:?}"),
+        /// One space-free field: tokens, addresses, histories.
+        pub fn word(rng: &mut ChaCha20Rng) -> String {
+            from(rng, b"abcxyzABC0189.:-_=>[]", 1..12)
         }
-        match parse_line("FILE GET-BACKUP-CHUNK foo").unwrap() {
-            Command::FileGetBackupChunk { name } => assert_eq!(name, "foo"),
-            other => panic!("unexpected: {other:?}"),
+
+        /// A name valid as a ring, file or payload key.
+        pub fn name(rng: &mut ChaCha20Rng) -> String {
+            format!("n{}", from(rng, b"abcz019.-_", 0..10))
         }
-    }
 
-    #[test]
-    fn file_backup_push() {
-        match parse_line("FILE BACKUP-PUSH chunk.part-001-of-003 4096").unwrap() {
-            Command::FileBackupPush { name, size } => {
-                assert_eq!(name, "chunk.part-001-of-003");
-                assert_eq!(size, 4096);
+        /// A trailing free-text field: spaces, separators and UTF-8 allowed.
+        pub fn text(rng: &mut ChaCha20Rng) -> String {
+            let mut t = String::new();
+            for _ in 0..rng.gen_range(0..6) {
+                t.push_str(
+                    ["", " ", "  ", ";", ",", "é", "\t", "→"]
+                        .choose(rng)
+                        .unwrap(),
+                );
+                t.push_str(&word(rng));
             }
-            other => panic!("unexpected: {other:?}"),
+            t
         }
-        // Bad size
-        assert!(parse_line("FILE BACKUP-PUSH foo notanumber").is_err());
-        // Missing name
-        assert!(parse_line("FILE BACKUP-PUSH").is_err());
-    }
 
-    #[test]
-    fn file_push_chunk() {
-        // 6-field shape: name chunk_size file_size parts index start_port
-        match parse_line("FILE PUSH-CHUNK myfile.part-002-of-005 4096 20480 5 1 7000").unwrap() {
-            Command::FilePushChunk {
-                name,
-                chunk_size,
-                file_size,
-                parts,
-                index,
-                start_port,
-            } => {
-                assert_eq!(name, "myfile.part-002-of-005");
-                assert_eq!(chunk_size, 4096);
-                assert_eq!(file_size, 20480);
-                assert_eq!(parts, 5);
-                assert_eq!(index, 1);
-                assert_eq!(start_port, 7000);
+        fn words(rng: &mut ChaCha20Rng) -> Vec<String> {
+            (0..rng.gen_range(0..4)).map(|_| word(rng)).collect()
+        }
+
+        pub fn command(rng: &mut ChaCha20Rng) -> Command {
+            let (w, t) = (word, text);
+            match rng.gen_range(0..116) {
+                0 => Command::NodeNext(w(rng)),
+                1 => Command::NodePrev(w(rng)),
+                71 => Command::NodeNextMulti(std::iter::once(w(rng)).chain(words(rng)).collect()),
+                72 => Command::NodeNextGen {
+                    addr: w(rng),
+                    generation: rng.next_u64(),
+                },
+                2 => Command::NodeNextRing {
+                    ring: name(rng),
+                    addr: w(rng),
+                },
+                3 => Command::NodeRing { ring: name(rng) },
+                4 => Command::NodeStatus,
+                112 => Command::NodeInfo,
+                113 => Command::Snapshot,
+                114 => Command::Restore(Box::new(NodeSnapshot {
+                    version: crate::snapshot::SNAPSHOT_VERSION,
+                    addr: w(rng),
+                    generation: rng.next_u64(),
+                    next: Some(w(rng)),
+                    kv: BTreeMap::from([(w(rng), t(rng))]),
+                    counters: BTreeMap::from([(w(rng), rng.next_u64())]),
+                    walks: words(rng),
+                    ..NodeSnapshot::default()
+                })),
+                5 => Command::NodePing,
+                6 => Command::NodeMetrics,
+                7 => Command::NodeStat,
+                8 => Command::NodeCircuit,
+                9 => Command::NodeShutdown,
+                10 => Command::NodeHeal,
+                11 => Command::NodeHealHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                },
+                12 => Command::NodeHealDone { token: w(rng) },
+                13 => Command::RingForward {
+                    hop_count: rng.gen_range(0..3),
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                14 => Command::RingOn {
+                    ring: name(rng),
+                    hop_count: rng.gen_range(0..3),
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                15 => Command::RingAck {
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                16 => Command::RingAckHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    ttl: rng.next_u32(),
+                    seen: w(rng),
+                    msg: t(rng),
+                },
+                17 => Command::RingAckDone {
+                    token: w(rng),
+                    seen: words(rng),
+                },
+                18 => Command::RingEcho {
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                19 => Command::RingEchoHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                20 => Command::RingEchoDone {
+                    token: w(rng),
+                    msg: t(rng),
+                },
+                21 => Command::RingReq {
+                    callback_addr: w(rng),
+                    ttl: rng.next_u32(),
+                    msg: t(rng),
+                },
+                22 => Command::RingResp {
+                    callback_addr: w(rng),
+                    payload: t(rng),
+                },
+                23 => Command::TopologyWalk,
+                24 => Command::TopologyHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    history: w(rng),
+                    visited: words(rng),
+                    meta_key: rng.gen_bool(0.5).then(|| w(rng)),
+                },
+                25 => Command::TopologyDone {
+                    token: w(rng),
+                    history: t(rng),
+                    meta_key: rng.gen_bool(0.5).then(|| w(rng)),
+                },
+                26 => Command::TopologySet { history: t(rng) },
+                27 => Command::TopologyAbort { token: w(rng) },
+                28 => Command::TopologyReverseWalk,
+                29 => Command::TopologyReverseHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    history: t(rng),
+                },
+                30 => Command::TopologyReverseDone {
+                    token: w(rng),
+                    history: t(rng),
+                },
+                31 => Command::WalkPayStart {
+                    payload_key: name(rng),
+                },
+                32 => Command::WalkPayHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    payload_key: name(rng),
+                    history: w(rng),
+                    payloads: t(rng),
+                },
+                33 => Command::WalkPayDone {
+                    token: w(rng),
+                    history: w(rng),
+                    payloads: t(rng),
+                },
+                34 => Command::WalkMax {
+                    max_hops: rng.gen_range(1..=u32::MAX),
+                },
+                35 => Command::WalkMaxHop {
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    max_hops: rng.next_u32(),
+                    start_addr: w(rng),
+                    history: t(rng),
+                },
+                36 => Command::WalkMaxDone {
+                    token: w(rng),
+                    truncated: rng.gen_bool(0.5),
+                    history: t(rng),
+                },
+                37 => Command::WalkRing { ring: name(rng) },
+                38 => Command::WalkRingHop {
+                    token: w(rng),
+                    ring: name(rng),
+                    start_addr: w(rng),
+                    history: t(rng),
+                },
+                39 => Command::WalkRingDone {
+                    token: w(rng),
+                    history: t(rng),
+                },
+                40 => Command::GatherStart {
+                    key: GATHER_KEYS.choose(rng).unwrap().to_string(),
+                },
+                41 => Command::GatherHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    key: GATHER_KEYS.choose(rng).unwrap().to_string(),
+                    results: t(rng),
+                },
+                42 => Command::GatherDone {
+                    token: w(rng),
+                    results: t(rng),
+                },
+                43 => Command::GatherKeys,
+                44 => Command::NetmapDiscover,
+                45 => Command::NetmapHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    entries: t(rng),
+                },
+                46 => Command::NetmapDone {
+                    token: w(rng),
+                    entries: t(rng),
+                },
+                47 => Command::NetmapSet { entries: w(rng) },
+                48 => Command::NetmapGet,
+                49 => Command::ElectStart,
+                50 => Command::ElectHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    candidate: w(rng),
+                },
+                51 => Command::ElectDone {
+                    token: w(rng),
+                    candidate: w(rng),
+                },
+                52 => Command::ElectLeader {
+                    start_addr: w(rng),
+                    leader: w(rng),
+                },
+                53 => Command::KvSet {
+                    key: w(rng),
+                    value: t(rng),
+                },
+                54 => Command::KvGet { key: w(rng) },
+                55 => Command::FilePush {
+                    size: rng.next_u64(),
+                    name: name(rng),
+                },
+                56 => Command::FilePull { name: name(rng) },
+                57 => Command::FileList,
+                58 => Command::FileTagsSet { entries: t(rng) },
+                59 => Command::FilePushChunk {
+                    name: name(rng),
+                    chunk_size: rng.next_u64(),
+                    file_size: rng.next_u64(),
+                    parts: rng.next_u32(),
+                    index: rng.next_u32(),
+                    start_port: rng.gen_range(0..=u16::MAX),
+                },
+                60 => Command::FileGetChunk { name: name(rng) },
+                61 => Command::FileBackupPush {
+                    name: name(rng),
+                    size: rng.next_u64(),
+                },
+                62 => Command::FileGetBackupChunk { name: name(rng) },
+                63 => Command::FileContentPush {
+                    name: name(rng),
+                    size: rng.next_u64(),
+                },
+                64 => Command::Probe { dead_addr: w(rng) },
+                65 => Command::Subscribe {
+                    events: ["all", "ring", "walk", "set_next"]
+                        .choose(rng)
+                        .unwrap()
+                        .parse()
+                        .unwrap(),
+                },
+                66 => Command::RingCollect {
+                    ttl: rng.next_u32(),
+                    key: w(rng),
+                },
+                67 => Command::RingCollectHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    ttl: rng.next_u32(),
+                    key: w(rng),
+                    results: t(rng),
+                },
+                68 => Command::RingCollectDone {
+                    token: w(rng),
+                    results: t(rng),
+                },
+                69 => Command::RingTimed {
+                    expiry_ms: rng.next_u64(),
+                    msg: t(rng),
+                },
+                70 => Command::TopologyBidirWalk,
+                73 => Command::WalkProbeHop {
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    start_addr: w(rng),
+                },
+                74 => Command::WalkProbeDone { token: w(rng) },
+                75 => Command::RingOnce { msg: t(rng) },
+                76 => Command::RingOnceHop {
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    start_addr: w(rng),
+                    msg: t(rng),
+                },
+                77 => Command::RingOnceDone { token: w(rng) },
+                78 => Command::TopologyWalkFresh,
+                79 => Command::Heartbeat {
+                    addr: w(rng),
+                    name: w(rng),
+                    generation: rng.next_u64(),
+                    next: rng.gen_bool(0.5).then(|| w(rng)),
+                },
+                80 => Command::Members,
+                81 => Command::NodeNextIf {
+                    expected: w(rng),
+                    new_addr: w(rng),
+                },
+                82 => Command::WalkSample {
+                    probability: Probability::new(rng.r#gen()).unwrap(),
+                },
+                83 => Command::WalkSampleHop {
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    probability: Probability::new(rng.r#gen()).unwrap(),
+                    start_addr: w(rng),
+                    history: t(rng),
+                },
+                84 => Command::WalkSampleDone {
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    history: t(rng),
+                },
+                85 => Command::Barrier {
+                    barrier_id: w(rng),
+                    n: rng.gen_range(1..=u32::MAX),
+                },
+                86 => Command::BarrierHop {
+                    barrier_id: w(rng),
+                    n: rng.gen_range(1..=u32::MAX),
+                    start_addr: w(rng),
+                    visited: format!("{};{}", w(rng), w(rng)),
+                },
+                87 => Command::BarrierWait {
+                    barrier_id: w(rng),
+                    n: rng.gen_range(1..=u32::MAX),
+                },
+                88 => Command::BarrierDone { barrier_id: w(rng) },
+                89 => Command::TopologyWalkMeta { key: w(rng) },
+                90 => Command::ChaosMode {
+                    mode: match rng.gen_range(0..3) {
+                        0 => ChaosMode::Drop(Probability::new(rng.r#gen()).unwrap()),
+                        1 => ChaosMode::Delay {
+                            mean_ms: rng.next_u64(),
+                            stddev_ms: rng.next_u64(),
+                        },
+                        _ => ChaosMode::Corrupt(Probability::new(rng.r#gen()).unwrap()),
+                    },
+                },
+                91 => Command::ChaosOff,
+                95 => Command::RingAll { msg: t(rng) },
+                96 => Command::WalkFind {
+                    key: w(rng),
+                    value: t(rng),
+                },
+                97 => Command::WalkFindHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    history: w(rng),
+                    key: w(rng),
+                    value: t(rng),
+                },
+                98 => Command::WalkFound {
+                    token: w(rng),
+                    addr: w(rng),
+                    history: if rng.gen_bool(0.5) {
+                        w(rng)
+                    } else {
+                        String::new()
+                    },
+                },
+                99 => Command::WalkNotFound { token: w(rng) },
+                100 => Command::LeaderCmd {
+                    cmd: format!("{} {}", w(rng), t(rng)),
+                },
+                101 => Command::WalkParallel,
+                102 => Command::GatherStream {
+                    key: GATHER_KEYS.choose(rng).unwrap().to_string(),
+                },
+                103 => Command::GatherStreamHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    key: GATHER_KEYS.choose(rng).unwrap().to_string(),
+                    results: t(rng),
+                },
+                104 => Command::GatherStreamData {
+                    token: w(rng),
+                    entry: format!("{}={}", w(rng), w(rng)),
+                },
+                105 => Command::SplitBrainCheck,
+                108 => Command::Mux,
+                109 => Command::RingKey {
+                    key: w(rng),
+                    msg: format!("{}{}", w(rng), t(rng)),
+                },
+                111 => Command::ForwardTo {
+                    target: w(rng),
+                    command: format!("{}{}", w(rng), t(rng)),
+                },
+                110 => Command::RingKeyHop {
+                    key: w(rng),
+                    msg: format!("{}{}", w(rng), t(rng)),
+                },
+                106 => Command::SplitBrainHop {
+                    token: w(rng),
+                    start_addr: w(rng),
+                    leaders: format!("{}={}", w(rng), w(rng)),
+                },
+                107 => Command::SplitBrainDone {
+                    token: w(rng),
+                    leaders: format!("{}={};{}={}", w(rng), w(rng), w(rng), w(rng)),
+                },
+                93 => Command::Incr {
+                    counter_name: w(rng),
+                },
+                94 => Command::IncrHop {
+                    counter_name: w(rng),
+                    token: w(rng),
+                    hop_count: rng.next_u32(),
+                    origin: w(rng),
+                    current_value: rng.next_u64(),
+                },
+                92 => {
+                    let mut hops: Vec<(String, String)> = Vec::new();
+                    for _ in 0..rng.gen_range(1..4) {
+                        let ring = name(rng);
+                        if !hops.iter().any(|(r, _)| *r == ring) {
+                            hops.push((ring, w(rng)));
+                        }
+                    }
+                    Command::NodeNextRings(hops)
+                }
+                _ => Command::Unsubscribe,
             }
-            other => panic!("unexpected: {other:?}"),
         }
     }
 
     #[test]
-    fn file_push_chunk_negative() {
-        // Bad numeric fields
-        assert!(parse_line("FILE PUSH-CHUNK n abc 1 1 0 7000").is_err());
-        // Missing fields
-        assert!(parse_line("FILE PUSH-CHUNK n 1").is_err());
-        // RELAY-STREAM was removed when the fan-out refactor landed; it must not parse.
-        assert!(parse_line("FILE RELAY-STREAM tok 7000 1024 3 0 0 myfile").is_err());
+    fn random_commands_round_trip_through_their_line() {
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0x0b0b);
+        for _ in 0..20_000 {
+            let cmd = arb::command(&mut rng);
+            let line = format!("{cmd}\n");
+            assert_eq!(parse_line(&line).ok(), Some(cmd), "{line:?}");
+        }
     }
 
     #[test]
-    fn negatives() {
-        assert!(parse_line("").is_err());
-        assert!(parse_line("BLAH").is_err());
-        assert!(parse_line("NODE NEXT").is_err()); // missing addr
-        assert!(parse_line("FILE PUSH abc xyz").is_err()); // non-numeric size
-        assert!(parse_line("FILE PUSH").is_err()); // no args
-        assert!(parse_line("FILE").is_err()); // missing verb
+    fn parsed_lines_render_back_to_the_same_command() {
+        for line in [
+            "node ping",
+            "RING FORWARD +3 hi  there ",
+            "TOPOLOGY HOP t 127.0.0.1:7000 7000->7001",
+            "GATHER  PORT",
+            "ELECT HOP  t   a b",
+            "KV SET k",
+            "RING ACK-DONE t a;;b",
+            "TOPOLOGY HOP t a h v\t,w",
+        ] {
+            let cmd = parse_line(line).unwrap();
+            assert_eq!(parse_line(&cmd.to_string()).unwrap(), cmd, "{line}");
+        }
+        assert!(parse_line("NODE\nPING").is_err());
+        assert!(parse_line("RING FORWARD 1 a\rb\r\n").is_err());
     }
 
     #[test]
-    fn crlf_tolerance() {
-        assert_eq!(parse_line("NODE STATUS\r\n").unwrap(), Command::NodeStatus);
+    fn cid_header_lines_are_recognised() {
+        assert_eq!(
+            parse_correlation_id("CID: abc-123\n").unwrap().unwrap(),
+            CorrelationId(Some("abc-123".into()))
+        );
+        assert_eq!(
+            parse_correlation_id("CID:req.7").unwrap().unwrap().header(),
+            "CID: req.7\n"
+        );
+        assert!(parse_correlation_id("RING FORWARD 1 CID: x").is_none());
+        assert!(parse_correlation_id("CID: ").unwrap().is_err());
+        assert!(parse_correlation_id("CID: a b").unwrap().is_err());
+        assert!(
+            parse_correlation_id(&format!("CID: {}", "x".repeat(65)))
+                .unwrap()
+                .is_err()
+        );
+        assert_eq!(CorrelationId::default().header(), "");
+        assert_eq!(CorrelationId::default().to_string(), "-");
     }
 
     #[test]
-    fn lowercase_noun_normalized() {
-        assert_eq!(parse_line("node status").unwrap(), Command::NodeStatus);
+    fn subscribe_takes_an_event_type() {
+        assert_eq!(
+            parse_line("SUBSCRIBE walk").unwrap(),
+            Command::Subscribe {
+                events: EventFilter::Only(crate::events::EventKind::Walk),
+            }
+        );
+        assert_eq!(
+            parse_line("SUBSCRIBE all\n").unwrap(),
+            Command::Subscribe {
+                events: EventFilter::All,
+            }
+        );
+        assert!(parse_line("SUBSCRIBE").is_err());
+        assert!(parse_line("SUBSCRIBE files").is_err());
+        assert_eq!(parse_line("UNSUBSCRIBE").unwrap(), Command::Unsubscribe);
+        assert!(parse_line("UNSUBSCRIBE all").is_err());
     }
 
-    // --- Named negative cases. Each pins one specific failure mode so a
-    //     regression points at the offending input directly.
-
+    // RING KEY
     #[test]
-    fn parse_unknown_namespace_reports_namespace_in_err() {
-        let err = parse_line("BLAH foo").unwrap_err();
-        assert!(err.contains("BLAH"), "err missing namespace: {err}");
+    fn ring_key_keeps_spaces_in_message() {
+        assert_eq!(
+            parse_line("RING KEY user:42 hello there").unwrap(),
+            Command::RingKey {
+                key: "user:42".into(),
+                msg: "hello there".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("RING KEY-HOP k v").unwrap(),
+            Command::RingKeyHop {
+                key: "k".into(),
+                msg: "v".into(),
+            }
+        );
+        assert!(parse_line("RING KEY").is_err());
+        assert!(parse_line("RING KEY k").is_err());
+        assert!(parse_line("RING KEY-HOP k").is_err());
     }
 
+    // NODE FORWARD-TO
     #[test]
-    fn whitespace_only_line_errs() {
-        assert!(parse_line("   \r\n").is_err());
+    fn forward_to_keeps_the_command_whole() {
+        assert_eq!(
+            parse_line("NODE FORWARD-TO 127.0.0.1:7001 KV SET color dark blue").unwrap(),
+            Command::ForwardTo {
+                target: "127.0.0.1:7001".into(),
+                command: "KV SET color dark blue".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("NODE FORWARD-TO a NODE FORWARD-TO b NODE PING").unwrap(),
+            Command::ForwardTo {
+                target: "a".into(),
+                command: "NODE FORWARD-TO b NODE PING".into(),
+            }
+        );
+        assert!(parse_line("NODE FORWARD-TO").is_err());
+        assert!(parse_line("NODE FORWARD-TO 127.0.0.1:7001").is_err());
+        assert!(parse_line("NODE FORWARD-TO 127.0.0.1:7001  ").is_err());
     }
 
+    // SNAPSHOT
     #[test]
-    fn tab_separators_not_supported() {
-        // The parser splits on a literal space; tabs are not treated as
-        // delimiters. So `NODE\tNEXT addr` becomes noun = "NODE\tNEXT"
-        // (uppercased) and is rejected as an unknown namespace.
-        assert!(parse_line("NODE\tNEXT 127.0.0.1:7000").is_err());
+    fn restore_checks_the_snapshot_when_parsed() {
+        assert_eq!(parse_line("SNAPSHOT").unwrap(), Command::Snapshot);
+        assert!(parse_line("SNAPSHOT now").is_err());
+        let snapshot = NodeSnapshot {
+            version: crate::snapshot::SNAPSHOT_VERSION,
+            addr: "127.0.0.1:7000".into(),
+            ..NodeSnapshot::default()
+        };
+        assert_eq!(
+            parse_line(&format!("RESTORE {}", snapshot.to_line())).unwrap(),
+            Command::Restore(Box::new(snapshot))
+        );
+        assert!(parse_line("RESTORE").is_err());
+        assert!(parse_line("RESTORE {\"version\":1}").is_err());
     }
 
-    // NODE
+    // MUX
     #[test]
-    fn node_next_missing_addr_errs() {
-        assert!(parse_line("NODE NEXT").is_err());
+    fn mux_takes_no_arguments() {
+        assert_eq!(parse_line("MUX").unwrap(), Command::Mux);
+        assert_eq!(parse_line("mux\n").unwrap(), Command::Mux);
+        assert!(parse_line("MUX 1").is_err());
     }
 
+    // KV
     #[test]
-    fn node_next_only_whitespace_errs() {
-        assert!(parse_line("NODE NEXT   ").is_err());
+    fn kv_set_keeps_spaces_in_value() {
+        assert_eq!(
+            parse_line("KV SET color dark blue").unwrap(),
+            Command::KvSet {
+                key: "color".into(),
+                value: "dark blue".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("KV SET empty").unwrap(),
+            Command::KvSet {
+                key: "empty".into(),
+                value: "".into(),
+            }
+        );
+        assert!(parse_line("KV SET ").is_err());
     }
 
     #[test]
-    fn node_heal_hop_missing_start_addr_errs() {
-        assert!(parse_line("NODE HEAL-HOP tok").is_err());
+    fn kv_get_takes_one_key() {
+        assert_eq!(
+            parse_line("KV GET color").unwrap(),
+            Command::KvGet {
+                key: "color".into()
+            }
+        );
+        assert!(parse_line("KV GET ").is_err());
+        assert!(parse_line("KV GET a b").is_err());
+        assert!(parse_line("KV DEL a").is_err());
     }
 
+    // GATHER
     #[test]
-    fn node_heal_hop_missing_token_errs() {
-        // Empty token before the addr → both fields blank/start_addr empty.
-        assert!(parse_line("NODE HEAL-HOP   addr").is_err());
+    fn gather_round_trip() {
+        assert_eq!(parse_line("GATHER KEYS").unwrap(), Command::GatherKeys);
+        assert_eq!(
+            parse_line("GATHER Uptime").unwrap(),
+            Command::GatherStart {
+                key: "uptime".into()
+            }
+        );
+        assert_eq!(
+            parse_line("GATHER HOP tok 127.0.0.1:7000 next 127.0.0.1:7000=127.0.0.1:7001").unwrap(),
+            Command::GatherHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                key: "next".into(),
+                results: "127.0.0.1:7000=127.0.0.1:7001".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("GATHER DONE tok a=1;b=2").unwrap(),
+            Command::GatherDone {
+                token: "tok".into(),
+                results: "a=1;b=2".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("GATHER STREAM Port").unwrap(),
+            Command::GatherStream { key: "port".into() }
+        );
+        assert_eq!(
+            parse_line("GATHER STREAM-HOP tok 127.0.0.1:7000 port 127.0.0.1:7000=7000").unwrap(),
+            Command::GatherStreamHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                key: "port".into(),
+                results: "127.0.0.1:7000=7000".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("GATHER STREAM-DATA tok 127.0.0.1:7001=7001").unwrap(),
+            Command::GatherStreamData {
+                token: "tok".into(),
+                entry: "127.0.0.1:7001=7001".into(),
+            }
+        );
     }
 
     #[test]
-    fn node_heal_done_missing_token_errs() {
-        assert!(parse_line("NODE HEAL-DONE").is_err());
+    fn gather_rejects_unknown_keys_and_malformed_hops() {
+        let err = parse_line("GATHER queue_depth").unwrap_err();
+        assert!(matches!(err, RingError::ParseError(_)));
+        assert!(err.to_string().contains("msg_count"), "{err}");
+        assert!(parse_line("GATHER HOP tok").is_err());
+        assert!(parse_line("GATHER HOP tok 127.0.0.1:7000 bogus a=1").is_err());
+        assert!(parse_line("GATHER DONE ").is_err());
+        assert!(parse_line("GATHER").is_err());
+        assert!(parse_line("GATHER STREAM bogus").is_err());
+        assert!(parse_line("GATHER STREAM-DATA tok").is_err());
+        assert!(parse_line("GATHER STREAM-DATA tok novalue").is_err());
     }
 
     #[test]
-    fn node_unknown_verb_errs() {
-        assert!(parse_line("NODE FROBNICATE 1 2").is_err());
+    fn walk_pay_round_trip() {
+        assert_eq!(
+            parse_line("TOPOLOGY PAY-WALK UPTIME_SECS").unwrap(),
+            Command::WalkPayStart {
+                payload_key: "UPTIME_SECS".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY PAY-HOP tok 127.0.0.1:7000 disk 7000->7001 7000=12 GB;7001=3")
+                .unwrap(),
+            Command::WalkPayHop {
+                token: "tok".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                payload_key: "disk".into(),
+                history: "7000->7001".into(),
+                payloads: "7000=12 GB;7001=3".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY PAY-DONE tok 7000->7001;7001->7000 7000=1;7001=2").unwrap(),
+            Command::WalkPayDone {
+                token: "tok".into(),
+                history: "7000->7001;7001->7000".into(),
+                payloads: "7000=1;7001=2".into(),
+            }
+        );
     }
 
-    // RING
     #[test]
-    fn ring_forward_missing_ttl_errs() {
-        assert!(parse_line("RING FORWARD").is_err());
+    fn walk_pay_rejects_bad_keys() {
+        assert!(parse_line("TOPOLOGY PAY-WALK ").is_err());
+        assert!(parse_line("TOPOLOGY PAY-WALK two keys").is_err());
+        assert!(parse_line("TOPOLOGY PAY-WALK $(reboot)").is_err());
+        assert!(parse_line("TOPOLOGY PAY-HOP tok 127.0.0.1:7000").is_err());
+        assert!(parse_line("TOPOLOGY PAY-DONE ").is_err());
     }
 
     #[test]
-    fn ring_forward_zero_ttl_parses() {
-        match parse_line("RING FORWARD 0 ").unwrap() {
-            Command::RingForward { ttl, msg } => {
-                assert_eq!(ttl, 0);
-                assert_eq!(msg, "");
+    fn walk_max_round_trip() {
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-MAX 3").unwrap(),
+            Command::WalkMax { max_hops: 3 }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY MAX-HOP tok 1 3 127.0.0.1:7000 7000->7001").unwrap(),
+            Command::WalkMaxHop {
+                token: "tok".into(),
+                hop_count: 1,
+                max_hops: 3,
+                start_addr: "127.0.0.1:7000".into(),
+                history: "7000->7001".into(),
             }
-            other => panic!("unexpected: {other:?}"),
-        }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY MAX-DONE tok TRUNCATED 7000->7001;7001->7002").unwrap(),
+            Command::WalkMaxDone {
+                token: "tok".into(),
+                truncated: true,
+                history: "7000->7001;7001->7002".into(),
+            }
+        );
     }
 
     #[test]
-    fn ring_forward_overflow_ttl_errs() {
-        // 9999999999999999 > u32::MAX (~4.3e9).
-        assert!(parse_line("RING FORWARD 9999999999999999 m").is_err());
+    fn walk_max_rejects_bad_counts() {
+        assert!(parse_line("TOPOLOGY WALK-MAX 0").is_err());
+        assert!(parse_line("TOPOLOGY WALK-MAX -1").is_err());
+        assert!(parse_line("TOPOLOGY WALK-MAX ").is_err());
+        assert!(parse_line("TOPOLOGY MAX-HOP tok x 3 127.0.0.1:7000").is_err());
+        assert!(parse_line("TOPOLOGY MAX-HOP tok 1 3").is_err());
+        assert!(parse_line("TOPOLOGY MAX-DONE tok MAYBE a->b").is_err());
     }
 
     #[test]
-    fn ring_forward_msg_with_spaces_kept_intact() {
-        match parse_line("RING FORWARD 3 a b c d").unwrap() {
-            Command::RingForward { ttl, msg } => {
-                assert_eq!(ttl, 3);
-                assert_eq!(msg, "a b c d");
+    fn walk_sample_round_trip() {
+        assert_eq!(
+            parse_line("TOPOLOGY WALK-SAMPLE 0.25").unwrap(),
+            Command::WalkSample {
+                probability: Probability::new(0.25).unwrap()
             }
-            other => panic!("unexpected: {other:?}"),
-        }
-    }
-
-    // TOPOLOGY
-    #[test]
-    fn topology_hop_empty_history_ok() {
-        // First-hop degenerate case: history is empty when only token + start
-        // are provided.
-        match parse_line("TOPOLOGY HOP tok addr ").unwrap() {
-            Command::TopologyHop {
-                token,
-                start_addr,
-                history,
-            } => {
-                assert_eq!(token, "tok");
-                assert_eq!(start_addr, "addr");
-                assert_eq!(history, "");
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY SAMPLE-HOP tok 2 1 127.0.0.1:7000 7000->7001;...").unwrap(),
+            Command::WalkSampleHop {
+                token: "tok".into(),
+                hop_count: 2,
+                probability: Probability::new(1.0).unwrap(),
+                start_addr: "127.0.0.1:7000".into(),
+                history: "7000->7001;...".into(),
             }
-            other => panic!("unexpected: {other:?}"),
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY SAMPLE-DONE tok 3 ...").unwrap(),
+            Command::WalkSampleDone {
+                token: "tok".into(),
+                hop_count: 3,
+                history: "...".into(),
+            }
+        );
+        for bad in ["1.5", "-0.1", "NaN", "half", ""] {
+            let line = format!("TOPOLOGY WALK-SAMPLE {bad}");
+            assert!(parse_line(&line).is_err(), "{line}");
         }
+        assert!(parse_line("TOPOLOGY SAMPLE-HOP tok 1 2 127.0.0.1:7000").is_err());
+        assert!(parse_line("TOPOLOGY SAMPLE-DONE tok x a->b").is_err());
     }
 
     #[test]
-    fn topology_hop_missing_start_errs() {
-        assert!(parse_line("TOPOLOGY HOP tok").is_err());
-    }
-
-    #[test]
-    fn topology_done_missing_token_errs() {
-        assert!(parse_line("TOPOLOGY DONE").is_err());
+    fn ring_walk_start_hop_done() {
+        assert_eq!(
+            parse_line("TOPOLOGY RING-WALK backup").unwrap(),
+            Command::WalkRing {
+                ring: "backup".into()
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY RING-HOP tok backup 127.0.0.1:7000 7000->7002").unwrap(),
+            Command::WalkRingHop {
+                token: "tok".into(),
+                ring: "backup".into(),
+                start_addr: "127.0.0.1:7000".into(),
+                history: "7000->7002".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY RING-DONE tok 7000->7002;7002->7000").unwrap(),
+            Command::WalkRingDone {
+                token: "tok".into(),
+                history: "7000->7002;7002->7000".into(),
+            }
+        );
+        assert!(parse_line("TOPOLOGY RING-WALK ").is_err());
+        assert!(parse_line("TOPOLOGY RING-WALK a;b").is_err());
+        assert!(parse_line("TOPOLOGY RING-HOP tok backup").is_err());
     }
 
     #[test]
-    fn topology_set_empty_history_parses() {
-        // The "SET " prefix matches with a trailing space; history is empty.
-        match parse_line("TOPOLOGY SET ").unwrap() {
-            Command::TopologySet { history } => assert_eq!(history, ""),
-            other => panic!("unexpected: {other:?}"),
-        }
+    fn probe_hop_done() {
+        assert_eq!(
+            parse_line("TOPOLOGY PROBE-HOP probe-3-7000-9 2 127.0.0.1:7000").unwrap(),
+            Command::WalkProbeHop {
+                token: "probe-3-7000-9".into(),
+                hop_count: 2,
+                start_addr: "127.0.0.1:7000".into(),
+            }
+        );
+        assert_eq!(
+            parse_line("TOPOLOGY PROBE-DONE probe-3-7000-9\n").unwrap(),
+            Command::WalkProbeDone {
+                token: "probe-3-7000-9".into(),
+            }
+        );
+        assert!(parse_line("TOPOLOGY PROBE-HOP tok").is_err());
+        assert!(parse_line("TOPOLOGY PROBE-HOP tok 127.0.0.1:7000").is_err());
+        assert!(parse_line("TOPOLOGY PROBE-DONE ").is_err());
     }
 
     #[test]
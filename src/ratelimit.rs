@@ -0,0 +1,97 @@
+//! Per-connection command rate limiting.
+//!
+//! Without a limit one client can keep a node busy with thousands of
+//! `RING FORWARD` or `TOPOLOGY WALK` commands a second. Each accepted
+//! connection gets its own [`RateLimiter`], so a flooding client only
+//! slows itself down; a command that finds the bucket empty gets
+//! `ERR rate limited` and is dropped. The channels of a `MUX`
+//! connection draw on that connection's bucket.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A [`RateLimiter`] shared by a connection and the `MUX` channels it
+/// carries, so multiplexing does not multiply the rate.
+pub type SharedLimiter = Arc<Mutex<RateLimiter>>;
+
+/// A token bucket refilled at `rps` tokens per second, holding at most one
+/// second's worth. It starts full, so a new connection may burst up to
+/// `rps` commands before the rate applies.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// `None` when `rps == 0`: no limit.
+    pub fn new(rps: u32) -> Option<Self> {
+        if rps == 0 {
+            return None;
+        }
+        let rate = f64::from(rps);
+        Some(Self {
+            rate,
+            capacity: rate,
+            tokens: rate,
+            last: Instant::now(),
+        })
+    }
+
+    /// [`RateLimiter::new`], for sharing.
+    pub fn shared(rps: u32) -> Option<SharedLimiter> {
+        Self::new(rps).map(|l| Arc::new(Mutex::new(l)))
+    }
+
+    /// Take one token if there is one.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn zero_is_unlimited() {
+        assert!(RateLimiter::new(0).is_none());
+    }
+
+    #[test]
+    fn bursts_to_capacity_then_refills_at_rate() {
+        let mut rl = RateLimiter::new(4).unwrap();
+        let t0 = rl.last;
+        for _ in 0..4 {
+            assert!(rl.try_acquire_at(t0));
+        }
+        assert!(!rl.try_acquire_at(t0));
+        // A quarter second buys exactly one more token at 4 rps.
+        assert!(rl.try_acquire_at(t0 + Duration::from_millis(250)));
+        assert!(!rl.try_acquire_at(t0 + Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn idle_time_does_not_bank_past_capacity() {
+        let mut rl = RateLimiter::new(2).unwrap();
+        let later = rl.last + Duration::from_secs(60);
+        assert!(rl.try_acquire_at(later));
+        assert!(rl.try_acquire_at(later));
+        assert!(!rl.try_acquire_at(later));
+    }
+}
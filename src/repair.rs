@@ -0,0 +1,167 @@
+//! Ring repair coordinator (`run --repair-ring`, `dev-network --repair-port`).
+//!
+//! A node started with `--repair-ring` that can't forward a `RING FORWARD`
+//! to its NEXT, retries included, sends `PROBE <next>` to its
+//! `--repair-coordinator`. The coordinator knows the ring order. It checks
+//! that the reported node really is down, then points the dead node's
+//! predecessor at its successor (`NODE NEXT`) and back (`NODE PREV`), and
+//! forgets the dead node. A second `PROBE` for a node already stitched
+//! around is answered `OK` and changes nothing; so is one for a node that
+//! still answers `NODE PING`.
+//!
+//! Connections to the coordinator go through the same TLS, `HELLO` and
+//! `AUTH` steps as connections to a node.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use crate::client::{ClientOpts, RingClient};
+use crate::error::RingError;
+use crate::io::{DEFAULT_MAX_LINE_BYTES, LimitedLineReader};
+use crate::protocol::{Command, PROTOCOL_VERSION, parse_line};
+use crate::transport::Stream;
+
+/// What a `PROBE` led to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// `prev` now points at `next`, skipping the dead node.
+    Stitched { prev: String, next: String },
+    /// The node answered a ping; nothing was changed.
+    StillAlive,
+    /// Not in the ring (any more), or the only node left; nothing was
+    /// changed.
+    Unknown,
+}
+
+pub struct RepairCoordinator {
+    /// Live nodes in ring order.
+    ring: Mutex<Vec<String>>,
+    /// How the coordinator talks to nodes. Its TLS also serves the
+    /// coordinator's own listener.
+    client: ClientOpts,
+}
+
+impl RepairCoordinator {
+    /// A coordinator for the ring `ring[0] -> ring[1] -> ... -> ring[0]`.
+    pub fn new(ring: Vec<String>, client: ClientOpts) -> Arc<Self> {
+        Arc::new(Self {
+            ring: Mutex::new(ring),
+            client,
+        })
+    }
+
+    /// The nodes still in the ring, in order.
+    pub async fn ring(&self) -> Vec<String> {
+        self.ring.lock().await.clone()
+    }
+
+    /// Listen on `listen_addr` for `PROBE`s.
+    pub async fn run_server(self: Arc<Self>, listen_addr: String) -> std::io::Result<()> {
+        let listener = TcpListener::bind(&listen_addr).await?;
+        tracing::info!(addr = %listen_addr, "Repair coordinator listening");
+        self.serve(listener).await
+    }
+
+    /// [`RepairCoordinator::run_server`] on an already bound listener.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let this = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = this.handle_connection(Stream::Plain(stream)).await {
+                    tracing::warn!(peer = %peer, error = ?e, "Repair coordinator client error");
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: Stream) -> Result<(), RingError> {
+        let mut stream = match tokio::time::timeout(
+            Duration::from_secs(5),
+            self.client.tls.accept(stream),
+        )
+        .await
+        {
+            Ok(r) => r?,
+            Err(_) => return Err("TLS handshake timed out".into()),
+        };
+        if self.client.hello {
+            stream
+                .write_all(PROTOCOL_VERSION.greeting().as_bytes())
+                .await?;
+        }
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = LimitedLineReader::new(BufReader::new(reader), DEFAULT_MAX_LINE_BYTES);
+
+        if self.client.auth_token.is_enabled() {
+            let mut auth_line = String::new();
+            let read =
+                tokio::time::timeout(Duration::from_secs(1), reader.read_line(&mut auth_line))
+                    .await;
+            if !matches!(read, Ok(Ok(n)) if n > 0)
+                || !self.client.auth_token.verify_auth_line(&auth_line)
+            {
+                let _ = writer.write_all(b"ERR auth required\n").await;
+                return Ok(());
+            }
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            let reply = match parse_line(&line) {
+                Ok(Command::Probe { dead_addr }) => match self.repair(&dead_addr).await {
+                    Ok(_) => "OK\n".to_string(),
+                    Err(e) => format!("ERR repair failed: {e}\n"),
+                },
+                Ok(_) => "ERR only PROBE is accepted here\n".to_string(),
+                Err(e) => format!("ERR {e}\n"),
+            };
+            // A node sends its PROBE and hangs up without waiting.
+            if writer.write_all(reply.as_bytes()).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stitch the ring around `dead_addr` if it is down.
+    pub async fn repair(&self, dead_addr: &str) -> Result<Repair, RingError> {
+        // Held throughout so two reports can't rewire at once.
+        let mut ring = self.ring.lock().await;
+        let Some(i) = ring.iter().position(|a| a == dead_addr) else {
+            tracing::debug!(dead = %dead_addr, "PROBE for a node not in the ring");
+            return Ok(Repair::Unknown);
+        };
+        if RingClient::with_opts(dead_addr, self.client.clone())
+            .ping()
+            .await
+            .is_ok()
+        {
+            tracing::info!(node = %dead_addr, "PROBE for a node that still answers; ignoring");
+            return Ok(Repair::StillAlive);
+        }
+        if ring.len() == 1 {
+            tracing::warn!(dead = %dead_addr, "Last node in the ring is down");
+            return Ok(Repair::Unknown);
+        }
+
+        let prev = ring[(i + ring.len() - 1) % ring.len()].clone();
+        let next = ring[(i + 1) % ring.len()].clone();
+        RingClient::with_opts(&prev, self.client.clone())
+            .set_next(&next)
+            .await?;
+        RingClient::with_opts(&next, self.client.clone())
+            .set_prev(&prev)
+            .await?;
+        ring.remove(i);
+        tracing::warn!(dead = %dead_addr, prev = %prev, next = %next, "Stitched ring around dead node");
+        Ok(Repair::Stitched { prev, next })
+    }
+}
@@ -0,0 +1,379 @@
+//! Interactive session for the `repl` subcommand.
+//!
+//! Each line typed is sent to the node as a protocol command over one
+//! [`RingClient`] connection. A connection the node has dropped (idle
+//! timeout, restart) is reopened before the next command, and a node that
+//! is down is tried again on the next one. Replies are printed as they
+//! come, except that a walk's edges are drawn as a chain, `NODE STAT` is a
+//! table, and errors are red on a terminal.
+//!
+//! Lines are read from stdin as the terminal hands them over, so line
+//! editing is the terminal's own. A line ending in a Tab lists the known
+//! commands it could complete to instead of being sent; `history` lists
+//! the commands sent so far and `!<n>` sends one of them again.
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::client::RingClient;
+use crate::error::RingError;
+use crate::protocol::{Edge, parse_history};
+
+/// Client commands offered for completion, in the order `help` lists them.
+pub const COMMANDS: &[&str] = &[
+    "NODE NEXT",
+    "NODE NEXT-IF",
+    "NODE NEXT-GEN",
+    "NODE NEXT-MULTI",
+    "NODE NEXT-RING",
+    "NODE NEXT-RINGS",
+    "NODE PREV",
+    "NODE RING",
+    "NODE STATUS",
+    "NODE INFO",
+    "NODE STAT",
+    "NODE CIRCUIT",
+    "NODE PING",
+    "NODE HEAL",
+    "NODE SHUTDOWN",
+    "RING FORWARD",
+    "RING ON",
+    "RING ACK",
+    "RING ECHO",
+    "RING COLLECT",
+    "RING TIMED",
+    "RING ONCE",
+    "RING ALL",
+    "TOPOLOGY WALK",
+    "TOPOLOGY WALK-FIND",
+    "TOPOLOGY WALK-FRESH",
+    "TOPOLOGY WALK-MAX",
+    "TOPOLOGY WALK-META",
+    "TOPOLOGY WALK-PARALLEL",
+    "TOPOLOGY WALK-SAMPLE",
+    "TOPOLOGY REVERSE-WALK",
+    "TOPOLOGY BIDIR-WALK",
+    "TOPOLOGY PAY-WALK",
+    "TOPOLOGY RING-WALK",
+    "TOPOLOGY ABORT",
+    "GATHER",
+    "GATHER KEYS",
+    "GATHER STREAM",
+    "NETMAP DISCOVER",
+    "NETMAP GET",
+    "ELECT START",
+    "ELECT CMD",
+    "KV SET",
+    "KV GET",
+    "BARRIER ENTER",
+    "BARRIER WAIT",
+    "BARRIER DONE",
+    "INCR START",
+    "SPLIT-BRAIN CHECK",
+    "RING KEY",
+    "NODE FORWARD-TO",
+    "SNAPSHOT",
+    "RESTORE",
+    "CHAOS MODE",
+    "CHAOS OFF",
+];
+
+/// Commands the session refuses: their replies have no closing line, or
+/// carry raw bytes or an event stream.
+pub(crate) const UNSUPPORTED: &[&str] = &["FILE", "SUBSCRIBE", "UNSUBSCRIBE"];
+
+/// The known commands that start with `prefix`, ignoring case.
+pub fn completions(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.trim_start().to_ascii_uppercase();
+    COMMANDS
+        .iter()
+        .copied()
+        .filter(|c| c.starts_with(&prefix))
+        .collect()
+}
+
+/// A walk's edges as a vertical chain, with a rail back to the top when
+/// the last edge returns to the first node. `None` unless each edge
+/// starts where the one before it ended.
+pub fn render_walk(edges: &[Edge]) -> Option<String> {
+    let first = edges.first()?;
+    let last = edges.last()?;
+    if edges.windows(2).any(|w| w[0].to != w[1].from) {
+        return None;
+    }
+    let closed = last.to == first.from;
+    let mut nodes: Vec<&str> = edges.iter().map(|e| e.from.as_str()).collect();
+    if !closed {
+        nodes.push(&last.to);
+    }
+    let (head, rail) = if closed { ("+-> ", "|   ") } else { ("", "") };
+
+    let mut out = String::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&format!("{rail}|\n{rail}v\n{rail}{node}\n"));
+        } else {
+            out.push_str(&format!("{head}{node}\n"));
+        }
+    }
+    if closed {
+        out.push_str(&format!("{rail}|\n+---+\n"));
+    }
+    Some(out)
+}
+
+/// `NODE STAT` lines (`KEY value`) as a two-column ASCII table.
+pub fn render_stat(lines: &[String]) -> String {
+    let rows: Vec<(&str, &str)> = lines
+        .iter()
+        .map(|l| l.split_once(' ').unwrap_or((l, "")))
+        .collect();
+    let width = |header: &str, cells: Vec<&str>| {
+        cells
+            .iter()
+            .map(|c| c.chars().count())
+            .fold(header.len(), usize::max)
+    };
+    let key_w = width("COUNTER", rows.iter().map(|r| r.0).collect());
+    let value_w = width("VALUE", rows.iter().map(|r| r.1).collect());
+    let rule = format!("+-{}-+-{}-+\n", "-".repeat(key_w), "-".repeat(value_w));
+    let row = |key: &str, value: &str| format!("| {key:<key_w$} | {value:>value_w$} |\n");
+
+    let mut out = rule.clone();
+    out.push_str(&row("COUNTER", "VALUE"));
+    out.push_str(&rule);
+    for (key, value) in &rows {
+        out.push_str(&row(key, value));
+    }
+    out.push_str(&rule);
+    out
+}
+
+/// A reply as the session prints it: `NODE STAT` as a table, walk edges
+/// as a chain when they form one, anything else line by line.
+pub fn render_reply(command: &str, lines: &[String]) -> String {
+    let (body, closing) = match lines.split_last() {
+        Some((last, body)) if last == "OK" || last.starts_with("OK ") => (body, Some(last)),
+        _ => (lines, None),
+    };
+    let verbatim = |lines: &[String]| lines.iter().map(|l| format!("{l}\n")).collect::<String>();
+    let closing = closing.map(|l| format!("{l}\n")).unwrap_or_default();
+
+    if command.trim().eq_ignore_ascii_case("NODE STAT") && !body.is_empty() {
+        return render_stat(body) + &closing;
+    }
+    // Lead lines (`TOKEN`, `CACHED`, `SAMPLED`, ...) stay as they are.
+    let split = body
+        .iter()
+        .position(|l| l.contains("->"))
+        .unwrap_or(body.len());
+    let (lead, edge_lines) = body.split_at(split);
+    if !edge_lines.is_empty() && edge_lines.iter().all(|l| l.contains("->")) {
+        let edges = parse_history(&edge_lines.join("\n"));
+        if let Some(graph) = render_walk(&edges) {
+            return verbatim(lead) + &graph + &closing;
+        }
+    }
+    verbatim(lines)
+}
+
+/// An error as the session prints it, red when `color` is set.
+pub fn render_error(e: &RingError, color: bool) -> String {
+    let text = match e {
+        RingError::Remote { msg, .. } => format!("ERR {msg}"),
+        other => format!("error: {other}"),
+    };
+    if color {
+        format!("\x1b[31m{text}\x1b[0m\n")
+    } else {
+        format!("{text}\n")
+    }
+}
+
+/// Read commands from `input` until it ends or `quit`, sending each to
+/// `client` and writing the prompt and replies to `output`.
+pub async fn run<R, W>(
+    client: &RingClient,
+    mut input: R,
+    mut output: W,
+    color: bool,
+) -> std::io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut history: Vec<String> = Vec::new();
+    let mut buf = String::new();
+    loop {
+        output
+            .write_all(format!("{}> ", client.addr()).as_bytes())
+            .await?;
+        output.flush().await?;
+        buf.clear();
+        if input.read_line(&mut buf).await? == 0 {
+            output.write_all(b"\n").await?;
+            return Ok(());
+        }
+        let raw = buf.trim_end_matches(['\r', '\n']);
+        if let Some(prefix) = raw.strip_suffix('\t') {
+            let mut out = completions(prefix).join("\n");
+            out.push('\n');
+            output.write_all(out.as_bytes()).await?;
+            continue;
+        }
+        let mut line = raw.trim().to_string();
+        if let Some(n) = line.strip_prefix('!') {
+            match n
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| history.get(n.wrapping_sub(1)))
+            {
+                Some(previous) => {
+                    line = previous.clone();
+                    output.write_all(format!("{line}\n").as_bytes()).await?;
+                }
+                None => {
+                    let err = RingError::from(format!("no command {n} in history"));
+                    output
+                        .write_all(render_error(&err, color).as_bytes())
+                        .await?;
+                    continue;
+                }
+            }
+        }
+        let first = line.split_whitespace().next().unwrap_or("");
+        match first.to_ascii_lowercase().as_str() {
+            "" => continue,
+            "quit" | "exit" => return Ok(()),
+            "help" => {
+                let mut out = COMMANDS.join("\n");
+                out.push_str("\nhistory, !<n>, quit\n");
+                output.write_all(out.as_bytes()).await?;
+                continue;
+            }
+            "history" => {
+                let out: String = history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, l)| format!("{:>4}  {l}\n", i + 1))
+                    .collect();
+                output.write_all(out.as_bytes()).await?;
+                continue;
+            }
+            _ => {}
+        }
+        history.push(line.clone());
+        if UNSUPPORTED.contains(&first.to_ascii_uppercase().as_str()) {
+            let err = RingError::from(format!("{first} is not supported in the repl"));
+            output
+                .write_all(render_error(&err, color).as_bytes())
+                .await?;
+            continue;
+        }
+        let out = match client.raw(&line).await {
+            Ok(lines) => render_reply(&line, &lines),
+            Err(e) => render_error(&e, color),
+        };
+        output.write_all(out.as_bytes()).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(pairs: &[(&str, &str)]) -> Vec<Edge> {
+        pairs
+            .iter()
+            .map(|(from, to)| Edge {
+                from: from.to_string(),
+                to: to.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closed_walk_draws_a_loop() {
+        let ring = edges(&[("7000", "7001"), ("7001", "a@7002"), ("a@7002", "7000")]);
+        assert_eq!(
+            render_walk(&ring).unwrap(),
+            "+-> 7000\n|   |\n|   v\n|   7001\n|   |\n|   v\n|   a@7002\n|   |\n+---+\n"
+        );
+        assert_eq!(
+            render_walk(&edges(&[("7000", "7000")])).unwrap(),
+            "+-> 7000\n|   |\n+---+\n"
+        );
+    }
+
+    #[test]
+    fn open_walk_draws_a_chain_and_a_fork_draws_nothing() {
+        assert_eq!(
+            render_walk(&edges(&[("7000", "7001"), ("7001", "7002")])).unwrap(),
+            "7000\n|\nv\n7001\n|\nv\n7002\n"
+        );
+        assert_eq!(
+            render_walk(&edges(&[("7000", "7001"), ("7000", "7002")])),
+            None
+        );
+        assert_eq!(render_walk(&[]), None);
+    }
+
+    #[test]
+    fn reply_keeps_lead_lines_and_falls_back_to_verbatim() {
+        let lines: Vec<String> = ["TOKEN t", "7000->7001", "7001->7000", "OK"]
+            .map(String::from)
+            .into();
+        assert_eq!(
+            render_reply("TOPOLOGY WALK", &lines),
+            "TOKEN t\n+-> 7000\n|   |\n|   v\n|   7001\n|   |\n+---+\nOK\n"
+        );
+        let sampled: Vec<String> = ["TOKEN t", "SAMPLED total_hops=2 recorded=0", "...", "OK"]
+            .map(String::from)
+            .into();
+        assert_eq!(
+            render_reply("TOPOLOGY WALK-SAMPLE 0", &sampled),
+            "TOKEN t\nSAMPLED total_hops=2 recorded=0\n...\nOK\n"
+        );
+    }
+
+    #[test]
+    fn stat_is_a_table() {
+        let lines: Vec<String> = ["UPTIME_SECS 12", "WALK_COUNT 3", "OK"]
+            .map(String::from)
+            .into();
+        assert_eq!(
+            render_reply("node stat", &lines),
+            "+-------------+-------+\n\
+             | COUNTER     | VALUE |\n\
+             +-------------+-------+\n\
+             | UPTIME_SECS |    12 |\n\
+             | WALK_COUNT  |     3 |\n\
+             +-------------+-------+\n\
+             OK\n"
+        );
+    }
+
+    #[test]
+    fn completion_and_error_color() {
+        assert_eq!(
+            completions("topology walk-"),
+            [
+                "TOPOLOGY WALK-FIND",
+                "TOPOLOGY WALK-FRESH",
+                "TOPOLOGY WALK-MAX",
+                "TOPOLOGY WALK-META",
+                "TOPOLOGY WALK-PARALLEL",
+                "TOPOLOGY WALK-SAMPLE"
+            ]
+        );
+        assert!(completions("NOPE").is_empty());
+        let err = RingError::Remote {
+            addr: "127.0.0.1:7000".into(),
+            msg: "unknown command".into(),
+        };
+        assert_eq!(render_error(&err, false), "ERR unknown command\n");
+        assert_eq!(
+            render_error(&err, true),
+            "\x1b[31mERR unknown command\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Message replay log (`--replay-log`, `replay-log`).
+//!
+//! A node started with `--replay-log <path>` appends every `RING FORWARD`
+//! (or `RING ON`) it handles to `<path>`, one JSON object per line:
+//! `{"ts":<unix ms>,"ttl":<ttl as received>,"msg":"..."}`, plus `"ring"`
+//! for a named ring. Repeats the dedup cache drops are not logged. The
+//! handler only queues the entry; a task running alongside the ring
+//! server does the file I/O, so logging adds no latency to forwarding.
+//!
+//! After a partition heals, the `replay-log` subcommand reads a log back
+//! and sends each message to the node after the one that wrote it, as
+//! that node forwarded it: with the TTL one lower, and not at all if the
+//! TTL was already zero. There are no message ids; the line itself is the
+//! id. A node still holding it in its dedup cache (`--dedup-ttl-secs`)
+//! drops it, so only nodes that missed the message while partitioned see
+//! it again.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, mpsc, oneshot};
+
+use crate::node::{DEFAULT_RING, ring_forward_line};
+use crate::protocol::now_unix_ms;
+
+/// One logged message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    /// When the node handled it, in Unix milliseconds.
+    pub ts: u64,
+    /// Named ring; absent for the default ring.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ring: Option<String>,
+    pub ttl: u32,
+    pub msg: String,
+}
+
+impl ReplayEntry {
+    /// An entry for a message handled now.
+    pub fn now(ring: &str, ttl: u32, msg: &str) -> Self {
+        Self {
+            ts: now_unix_ms(),
+            ring: (ring != DEFAULT_RING).then(|| ring.to_string()),
+            ttl,
+            msg: msg.to_string(),
+        }
+    }
+
+    pub fn ring(&self) -> &str {
+        self.ring.as_deref().unwrap_or(DEFAULT_RING)
+    }
+
+    /// The message as the logging node sent it on to its NEXT; `None` if
+    /// it stopped there.
+    pub fn forwarded(&self) -> Option<Self> {
+        Some(Self {
+            ttl: self.ttl.checked_sub(1)?,
+            ..self.clone()
+        })
+    }
+
+    /// The protocol line carrying this message, without newline.
+    pub fn line(&self) -> String {
+        ring_forward_line(self.ring(), self.ttl, &self.msg)
+    }
+}
+
+/// Parse a replay log. Blank lines are skipped; anything else that is
+/// not an entry is an error naming its line number.
+pub fn parse_log(text: &str) -> Result<Vec<ReplayEntry>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| format!("line {}: {e}", i + 1)))
+        .collect()
+}
+
+/// What to replay: the [`ReplayEntry::forwarded`] form of each entry
+/// logged at or after `since_ms`, each distinct line once, in log order.
+pub fn replay_plan(entries: &[ReplayEntry], since_ms: u64) -> Vec<ReplayEntry> {
+    let mut seen = std::collections::HashSet::new();
+    entries
+        .iter()
+        .filter(|e| e.ts >= since_ms)
+        .filter_map(ReplayEntry::forwarded)
+        .filter(|e| seen.insert(e.line()))
+        .collect()
+}
+
+/// Queues a node's entries for the writer task.
+#[derive(Debug, Default)]
+pub struct ReplayLog {
+    path: Option<PathBuf>,
+    tx: Option<mpsc::UnboundedSender<ReplayEntry>>,
+    rx: Mutex<Option<mpsc::UnboundedReceiver<ReplayEntry>>>,
+}
+
+impl ReplayLog {
+    /// A log appending to `path`; `None` records nothing.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let Some(path) = path else {
+            return Self::default();
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            path: Some(path),
+            tx: Some(tx),
+            rx: Mutex::new(Some(rx)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// Queue `entry`; never waits on the file.
+    pub fn record(&self, entry: ReplayEntry) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(entry);
+        }
+    }
+
+    /// Append queued entries for `node` until `stop` fires, then write
+    /// what is left. Returns at once if the log is disabled or already
+    /// being written.
+    pub async fn write(&self, node: &str, mut stop: oneshot::Receiver<()>) {
+        let (Some(path), Some(mut rx)) = (&self.path, self.rx.lock().await.take()) else {
+            return;
+        };
+        let mut file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+        {
+            Ok(f) => f,
+            Err(e) => {
+                tracing::error!(node = %node, path = %path.display(), error = ?e, "Cannot open replay log; not logging");
+                return;
+            }
+        };
+        tracing::info!(node = %node, path = %path.display(), "Writing replay log");
+        let mut buf = Vec::new();
+        loop {
+            tokio::select! {
+                entry = rx.recv() => {
+                    let Some(entry) = entry else { break };
+                    push_line(&mut buf, &entry);
+                    // Take whatever else is already queued in the same write.
+                    while let Ok(entry) = rx.try_recv() {
+                        push_line(&mut buf, &entry);
+                    }
+                }
+                _ = &mut stop => {
+                    while let Ok(entry) = rx.try_recv() {
+                        push_line(&mut buf, &entry);
+                    }
+                    flush(node, &mut file, &mut buf).await;
+                    break;
+                }
+            }
+            flush(node, &mut file, &mut buf).await;
+        }
+    }
+}
+
+fn push_line(buf: &mut Vec<u8>, entry: &ReplayEntry) {
+    // Serializing a plain struct of strings and integers cannot fail.
+    if let Ok(json) = serde_json::to_vec(entry) {
+        buf.extend_from_slice(&json);
+        buf.push(b'\n');
+    }
+}
+
+async fn flush(node: &str, file: &mut tokio::fs::File, buf: &mut Vec<u8>) {
+    if buf.is_empty() {
+        return;
+    }
+    if let Err(e) = file.write_all(buf).await {
+        tracing::warn!(node = %node, error = ?e, "Replay log write failed");
+    }
+    buf.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ts: u64, ring: Option<&str>, ttl: u32, msg: &str) -> ReplayEntry {
+        ReplayEntry {
+            ts,
+            ring: ring.map(str::to_string),
+            ttl,
+            msg: msg.to_string(),
+        }
+    }
+
+    #[test]
+    fn entries_round_trip_as_json_lines() {
+        let entries = vec![
+            entry(1, None, 3, "hello world"),
+            entry(2, Some("blue"), 0, "x"),
+        ];
+        let mut buf = Vec::new();
+        for e in &entries {
+            push_line(&mut buf, e);
+        }
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text.lines().next().unwrap(),
+            r#"{"ts":1,"ttl":3,"msg":"hello world"}"#
+        );
+        assert_eq!(parse_log(&format!("{text}\n")).unwrap(), entries);
+        assert_eq!(entries[0].line(), "RING FORWARD 3 hello world");
+        assert_eq!(entries[1].line(), "RING ON blue 0 x");
+        assert_eq!(entries[0].forwarded().unwrap().ttl, 2);
+        assert_eq!(entries[1].forwarded(), None);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_by_number() {
+        let err = parse_log("{\"ts\":1,\"ttl\":0,\"msg\":\"a\"}\nnot json\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+    }
+
+    #[test]
+    fn plan_skips_old_entries_spent_ttls_and_repeats() {
+        let plan = replay_plan(
+            &[
+                entry(5, None, 2, "old"),
+                entry(10, None, 2, "a"),
+                entry(11, None, 2, "a"),
+                entry(12, None, 1, "a"),
+                entry(13, None, 0, "b"),
+                entry(14, Some("blue"), 2, "a"),
+            ],
+            10,
+        );
+        let lines: Vec<String> = plan.iter().map(ReplayEntry::line).collect();
+        assert_eq!(
+            lines,
+            ["RING FORWARD 1 a", "RING FORWARD 0 a", "RING ON blue 1 a"]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! The Tokio runtime the binary runs on (`--worker-threads`,
+//! `--stack-size-kb`, `--max-blocking-threads`).
+//!
+//! - **Worker threads** poll every connection handler, forward and
+//!   background loop. A node spends most of its time waiting on sockets,
+//!   so one worker per CPU (the default) is plenty; more only adds context
+//!   switches. `1` runs everything on the main thread with Tokio's
+//!   current-thread scheduler: no work stealing and no cross-thread
+//!   wake-ups, which suits a single-core VM or container but means one
+//!   slow handler delays every other connection.
+//! - **Stack size** applies to worker and blocking threads alike. Futures
+//!   keep their state on the heap, so a worker's stack only holds the
+//!   frames of the `poll` currently running; 128 KiB keeps the per-thread
+//!   footprint small. Unoptimized frames are several times larger, and a
+//!   debug build overflows anything under 512 KiB on its first command,
+//!   so debug builds default to 1 MiB. The current-thread runtime runs on
+//!   the main thread, whose stack the OS sizes, so this setting does not
+//!   apply to it.
+//! - **Blocking threads** run `spawn_blocking` and `tokio::fs` calls, which
+//!   is where chunk reads and writes and fsyncs happen. They are started
+//!   on demand and exit when idle. Tokio's default cap of 512 is far more
+//!   than a node needs. Lowering it bounds memory under a burst of file
+//!   I/O; the price is that the extra calls queue behind the running ones.
+
+use std::io;
+
+use tokio::runtime::{Builder, Runtime};
+
+/// Default worker stack size, in KiB: 128, or 1024 in a debug build.
+pub const DEFAULT_STACK_SIZE_KB: usize = if cfg!(debug_assertions) { 1024 } else { 128 };
+
+/// Settings for [`RuntimeOpts::build`]. The default uses one worker per CPU,
+/// [`DEFAULT_STACK_SIZE_KB`] stacks and Tokio's own blocking-pool cap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuntimeOpts {
+    /// Worker threads; `None` means one per CPU, `Some(1)` the
+    /// current-thread runtime.
+    pub worker_threads: Option<usize>,
+    pub stack_size_kb: usize,
+    /// Cap on the blocking pool; `None` keeps Tokio's default.
+    pub max_blocking_threads: Option<usize>,
+}
+
+impl Default for RuntimeOpts {
+    fn default() -> Self {
+        Self {
+            worker_threads: None,
+            stack_size_kb: DEFAULT_STACK_SIZE_KB,
+            max_blocking_threads: None,
+        }
+    }
+}
+
+impl RuntimeOpts {
+    /// Whether [`RuntimeOpts::build`] makes a current-thread runtime.
+    pub fn is_current_thread(&self) -> bool {
+        self.worker_threads == Some(1)
+    }
+
+    /// Build the runtime, with I/O and timers enabled.
+    pub fn build(&self) -> io::Result<Runtime> {
+        if self.worker_threads == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--worker-threads must be at least 1",
+            ));
+        }
+        if self.max_blocking_threads == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--max-blocking-threads must be at least 1",
+            ));
+        }
+        let mut builder = if self.is_current_thread() {
+            Builder::new_current_thread()
+        } else {
+            let mut b = Builder::new_multi_thread();
+            if let Some(n) = self.worker_threads {
+                b.worker_threads(n);
+            }
+            b
+        };
+        builder
+            .enable_all()
+            .thread_stack_size(self.stack_size_kb * 1024);
+        if let Some(n) = self.max_blocking_threads {
+            builder.max_blocking_threads(n);
+        }
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_worker_is_the_current_thread_runtime() {
+        let opts = RuntimeOpts {
+            worker_threads: Some(1),
+            ..Default::default()
+        };
+        assert!(opts.is_current_thread());
+        let rt = opts.build().unwrap();
+        assert_eq!(rt.metrics().num_workers(), 1);
+        assert_eq!(rt.block_on(async { 7 }), 7);
+    }
+
+    #[test]
+    fn worker_count_is_honoured() {
+        let rt = RuntimeOpts {
+            worker_threads: Some(3),
+            max_blocking_threads: Some(2),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(rt.metrics().num_workers(), 3);
+        let n = rt.block_on(async { tokio::task::spawn_blocking(|| 5).await.unwrap() });
+        assert_eq!(n, 5);
+    }
+
+    #[test]
+    fn zero_counts_are_refused() {
+        for opts in [
+            RuntimeOpts {
+                worker_threads: Some(0),
+                ..Default::default()
+            },
+            RuntimeOpts {
+                max_blocking_threads: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                opts.build().unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Command scripts for `run --script` and the `run-script` subcommand.
+//!
+//! A script is the wire protocol itself, one command per line; blank
+//! lines and lines starting with `#` are skipped. Each command is sent
+//! with [`RingClient::raw`] and its reply handed back before the next one
+//! goes out. The commands the repl refuses (`FILE`, `SUBSCRIBE`) are
+//! refused here too, since their replies have no closing line.
+
+use std::path::Path;
+
+use crate::client::RingClient;
+use crate::error::RingError;
+
+/// One command of a script and the line it was on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLine {
+    /// 1-based line in the file.
+    pub line: usize,
+    pub command: String,
+}
+
+/// The commands in `source`, in order.
+pub fn parse(source: &str) -> Vec<ScriptLine> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(line, command)| ScriptLine {
+            line,
+            command: command.to_string(),
+        })
+        .collect()
+}
+
+/// [`parse`] the file at `path`.
+pub fn load(path: &Path) -> Result<Vec<ScriptLine>, RingError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| RingError::from(format!("cannot read script {}: {e}", path.display())))?;
+    Ok(parse(&source))
+}
+
+/// Send each command in `script` to `client` in turn, passing every reply
+/// or error to `report`. Stops at the first error unless
+/// `continue_on_error`, in which case the error counts the failures.
+pub async fn run(
+    client: &RingClient,
+    script: &[ScriptLine],
+    continue_on_error: bool,
+    mut report: impl FnMut(&ScriptLine, &Result<Vec<String>, RingError>),
+) -> Result<(), RingError> {
+    let mut failed = 0;
+    for step in script {
+        let first = step.command.split_whitespace().next().unwrap_or("");
+        let reply = if crate::repl::UNSUPPORTED.contains(&first.to_ascii_uppercase().as_str()) {
+            Err(RingError::from(format!(
+                "{first} is not supported in a script"
+            )))
+        } else {
+            client.raw(&step.command).await
+        };
+        report(step, &reply);
+        if let Err(e) = reply {
+            if !continue_on_error {
+                return Err(format!("line {}: {}: {e}", step.line, step.command).into());
+            }
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} of {} script commands failed", script.len()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let script = parse("# wire the ring\n\nNODE NEXT 7001\n  KV SET color red  \n#NODE PING\n");
+        assert_eq!(
+            script,
+            [
+                ScriptLine {
+                    line: 3,
+                    command: "NODE NEXT 7001".into()
+                },
+                ScriptLine {
+                    line: 4,
+                    command: "KV SET color red".into()
+                },
+            ]
+        );
+        assert!(parse("").is_empty());
+    }
+}
@@ -9,16 +9,26 @@ use tokio::io::{
 use tokio::net::{TcpListener, TcpSocket, TcpStream};
 use tokio::process::Command;
 use tokio::time::sleep;
-use tracing;
+use tracing::{self, Instrument};
 
 use crate::{
     auth::AuthToken,
-    node::{self, FsyncMode, Node, append_edge, port_str},
-    protocol::{self, validate_filename},
+    chring::RoutingMode,
+    error::RingError,
+    events::{EventFilter, EventKind, command_event},
+    io::{LimitedLineReader, is_line_too_long},
+    node::{
+        self, BARRIER_WAIT_TIMEOUT, DEFAULT_RING, FsyncMode, Node, NodeOpts, NodeRole,
+        SKIPPED_EDGE, WalkAborted, annotate_last_target, append_meta_edge, append_named_edge,
+        append_skipped, label_last_sampled, label_last_target, port_str, ring_forward_line,
+    },
+    protocol::{self, CorrelationId, PROTOCOL_VERSION, Probability, validate_filename},
+    ratelimit::{RateLimiter, SharedLimiter},
+    replay::ReplayEntry,
+    snapshot::NodeSnapshot,
+    transport::{Stream, Transport},
 };
 
-type AnyErr = Box<dyn Error + Send + Sync>;
-
 /// Bind a node to `bind_addr`, create its on-disk storage tree, and return the
 /// pieces a caller needs to wire it (the `Arc<Node>` and the resolved
 /// `SocketAddr`) plus the `TcpListener` to feed to [`serve`].
@@ -28,7 +38,7 @@ type AnyErr = Box<dyn Error + Send + Sync>;
 ///   2. wire the ring with `NODE NEXT` *before* any node starts accepting,
 ///   3. spawn `serve()` per node and abort that handle to "kill" a node.
 // Wide-by-design: same rationale as `Node::new` — every per-node knob is
-// exposed individually. A grouped `BindOpts` struct is a v1.1 ergonomics win.
+// exposed individually. Newer knobs live in `NodeOpts`; see `bind_with_opts`.
 #[allow(clippy::too_many_arguments)]
 pub async fn bind(
     bind_addr: &str,
@@ -40,7 +50,36 @@ pub async fn bind(
     auth_token: AuthToken,
     idle_timeout: Duration,
     max_conns: u32,
-) -> Result<(Arc<Node>, TcpListener, std::net::SocketAddr), AnyErr> {
+) -> Result<(Arc<Node>, TcpListener, std::net::SocketAddr), RingError> {
+    bind_with_opts(
+        bind_addr,
+        gossip_interval,
+        file_size,
+        storage_root,
+        respawn_dead,
+        fsync_mode,
+        auth_token,
+        idle_timeout,
+        max_conns,
+        NodeOpts::default(),
+    )
+    .await
+}
+
+/// [`bind`] plus the grouped [`NodeOpts`].
+#[allow(clippy::too_many_arguments)]
+pub async fn bind_with_opts(
+    bind_addr: &str,
+    gossip_interval: Duration,
+    file_size: u64,
+    storage_root: PathBuf,
+    respawn_dead: bool,
+    fsync_mode: FsyncMode,
+    auth_token: AuthToken,
+    idle_timeout: Duration,
+    max_conns: u32,
+    opts: NodeOpts,
+) -> Result<(Arc<Node>, TcpListener, std::net::SocketAddr), RingError> {
     let addr: std::net::SocketAddr = bind_addr.parse()?;
 
     let socket = if addr.is_ipv6() {
@@ -49,15 +88,34 @@ pub async fn bind(
         TcpSocket::new_v4()?
     };
 
+    // SO_REUSEADDR lets a restarted node rebind while its predecessor's
+    // connections sit in TIME_WAIT. SO_REUSEPORT also lets a second live
+    // listener share the port, so it is opt-in.
     socket.set_reuseaddr(true)?;
     #[cfg(unix)]
-    socket.set_reuseport(true)?;
+    if opts.reuse_port {
+        socket.set_reuseport(true)?;
+    }
+    if opts.recv_buf_size > 0 {
+        socket.set_recv_buffer_size(opts.recv_buf_size)?;
+    }
+    if opts.send_buf_size > 0 {
+        socket.set_send_buffer_size(opts.send_buf_size)?;
+    }
 
-    socket.bind(addr)?;
-    let listener = socket.listen(1024)?;
+    let bind_failed = |source| RingError::BindFailed {
+        addr: addr.to_string(),
+        source,
+    };
+    socket.bind(addr).map_err(bind_failed)?;
+    let backlog = match opts.bind_backlog {
+        0 => node::DEFAULT_BIND_BACKLOG,
+        n => n,
+    };
+    let listener = socket.listen(backlog).map_err(bind_failed)?;
     let local = listener.local_addr()?;
 
-    let node = Node::new(
+    let node = init_node(
         local.to_string(),
         gossip_interval,
         file_size,
@@ -67,8 +125,93 @@ pub async fn bind(
         auth_token,
         idle_timeout,
         max_conns,
+        opts,
+    )
+    .await?;
+    Ok((node, listener, local))
+}
+
+/// [`bind_with_opts`] on a Unix domain socket at `path` instead of a TCP
+/// port. The node's address becomes `unix:<path>` and its storage lives
+/// under the socket's file name. A stale socket file left by a previous
+/// run is removed first; any other file at `path` is an error.
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+pub async fn bind_unix_with_opts(
+    path: &std::path::Path,
+    gossip_interval: Duration,
+    file_size: u64,
+    storage_root: PathBuf,
+    respawn_dead: bool,
+    fsync_mode: FsyncMode,
+    auth_token: AuthToken,
+    idle_timeout: Duration,
+    max_conns: u32,
+    opts: NodeOpts,
+) -> Result<(Arc<Node>, tokio::net::UnixListener), RingError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(meta) = fs::symlink_metadata(path).await {
+        if !meta.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket", path.display()).into());
+        }
+        fs::remove_file(path).await?;
+    }
+    let listener =
+        tokio::net::UnixListener::bind(path).map_err(|source| RingError::BindFailed {
+            addr: path.display().to_string(),
+            source,
+        })?;
+
+    let node = init_node(
+        format!("{}{}", crate::transport::UNIX_SCHEME, path.display()),
+        gossip_interval,
+        file_size,
+        storage_root,
+        respawn_dead,
+        fsync_mode,
+        auth_token,
+        idle_timeout,
+        max_conns,
+        opts,
+    )
+    .await?;
+    Ok((node, listener))
+}
+
+/// Build the node for a bound listener at `local`, create its storage tree
+/// and restore its ring pointers.
+#[allow(clippy::too_many_arguments)]
+async fn init_node(
+    local: String,
+    gossip_interval: Duration,
+    file_size: u64,
+    storage_root: PathBuf,
+    respawn_dead: bool,
+    fsync_mode: FsyncMode,
+    auth_token: AuthToken,
+    idle_timeout: Duration,
+    max_conns: u32,
+    opts: NodeOpts,
+) -> Result<Arc<Node>, RingError> {
+    let node = Node::with_opts(
+        local,
+        gossip_interval,
+        file_size,
+        storage_root,
+        respawn_dead,
+        fsync_mode,
+        auth_token,
+        idle_timeout,
+        max_conns,
+        opts,
+    );
+    tracing::info!(
+        node = %node.port,
+        max_ring_ttl = node.opts.max_ring_ttl,
+        max_walk_hops = node.opts.max_walk_hops,
+        "Node listening"
     );
-    tracing::info!(node = %node.port, "Node listening");
 
     let port_only = port_str(&node.port);
     let per_node_dir = node.storage_root.join(port_only);
@@ -98,7 +241,14 @@ pub async fn bind(
 
     tracing::info!(node = %node.port, content_dir = %content_dir.display(), backup_dir = %backup_dir.display(), "Created node directories");
 
-    Ok((node, listener, local))
+    // Restore NEXT/PREV before anything is accepted. A file that exists but
+    // can't be read is left alone (the next wiring command overwrites it)
+    // rather than keeping the node from starting.
+    if let Err(e) = node.restore_state().await {
+        tracing::warn!(node = %node.port, error = ?e, "Ignoring unreadable state file");
+    }
+
+    Ok(node)
 }
 
 /// Remove `*.partial` files from a chunk directory. These are leftovers from
@@ -136,7 +286,7 @@ async fn enforce_storage_version(
     per_node_dir: &std::path::Path,
     content_dir: &std::path::Path,
     backup_dir: &std::path::Path,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let version_path = per_node_dir.join("VERSION");
     match fs::read_to_string(&version_path).await {
         Ok(raw) => {
@@ -178,7 +328,7 @@ async fn enforce_storage_version(
             }
             fs::write(&version_path, format!("{STORAGE_VERSION}\n"))
                 .await
-                .map_err(|e| -> AnyErr { e.into() })?;
+                .map_err(|e| -> RingError { e.into() })?;
             tracing::info!(
                 dir = %per_node_dir.display(),
                 version = STORAGE_VERSION,
@@ -197,7 +347,7 @@ async fn is_dir_empty(dir: &std::path::Path) -> std::io::Result<bool> {
 
 /// Drive a bound node: spawn the gossip loop and run the accept loop forever.
 /// Returns when the listener is dropped (e.g. the calling task is aborted).
-pub async fn serve(node: Arc<Node>, listener: TcpListener) {
+pub async fn serve(node: Arc<Node>, listener: impl Into<Transport>) {
     // No shutdown signal — the task runs until aborted by the caller.
     // Tests rely on this for fast teardown via `JoinHandle::abort`.
     let (_tx, rx) = tokio::sync::oneshot::channel::<()>();
@@ -217,21 +367,80 @@ pub async fn serve(node: Arc<Node>, listener: TcpListener) {
 /// (NEXT_STEPS.md §4.3.)
 pub async fn serve_with_shutdown(
     node: Arc<Node>,
-    listener: TcpListener,
+    listener: impl Into<Transport>,
     shutdown: tokio::sync::oneshot::Receiver<()>,
     drain_timeout: Duration,
 ) {
-    if node.gossip_interval > Duration::from_millis(0) {
+    let listener = listener.into();
+    // Gossip, heartbeats and announcements end on the shutdown watch,
+    // before the drain below.
+    let gossip_task = (node.gossip_interval > Duration::from_millis(0)).then(|| {
         let gossip_node = Arc::clone(&node);
-        tokio::spawn(async move {
+        tokio::spawn(until_shutdown(Arc::clone(&node), async move {
             tracing::info!(
                 node = %gossip_node.port,
                 interval = ?gossip_node.gossip_interval,
                 "Gossip loop starting"
             );
             spawn_gossip_loop(gossip_node).await;
-        });
-    }
+        }))
+    });
+
+    // Span exporter; flushed after the drain below.
+    let (stop_export, export_stopped) = tokio::sync::oneshot::channel();
+    let export_task = node.tracer.is_enabled().then(|| {
+        let node = Arc::clone(&node);
+        tokio::spawn(async move { node.tracer.export(&node.port, export_stopped).await })
+    });
+
+    // Replay log writer; flushed after the drain like the exporter.
+    let (stop_replay, replay_stopped) = tokio::sync::oneshot::channel();
+    let replay_task = node.replay.is_enabled().then(|| {
+        let node = Arc::clone(&node);
+        tokio::spawn(async move { node.replay.write(&node.port, replay_stopped).await })
+    });
+
+    // Partition probes; stopped with the metrics endpoint below.
+    let probe_task =
+        (!node.opts.probe_interval.is_zero()).then(|| tokio::spawn(probe_loop(Arc::clone(&node))));
+
+    // Heartbeats to the coordinator.
+    let heartbeat_task = node.opts.heartbeat_addr.is_some().then(|| {
+        tokio::spawn(until_shutdown(
+            Arc::clone(&node),
+            heartbeat_loop(Arc::clone(&node)),
+        ))
+    });
+
+    // Multicast announcements.
+    let announce_task = node.opts.announce.map(|group| {
+        tokio::spawn(until_shutdown(
+            Arc::clone(&node),
+            announce_loop(Arc::clone(&node), group),
+        ))
+    });
+
+    // Ring size monitor; stopped with the probes.
+    let monitor_task = (!node.opts.monitor_interval.is_zero())
+        .then(|| tokio::spawn(monitor_loop(Arc::clone(&node))));
+    let split_brain_task = (!node.opts.split_brain_check_interval.is_zero())
+        .then(|| tokio::spawn(split_brain_loop(Arc::clone(&node))));
+
+    // Prometheus endpoint on its own port; stopped once the drain below
+    // finishes so it goes down with the ring listener.
+    let metrics_task = match node.opts.metrics_port {
+        None => None,
+        Some(port) => match crate::metrics::bind_metrics(host_of(&node.port), port).await {
+            Ok(l) => Some(tokio::spawn(crate::metrics::serve_metrics(
+                Arc::clone(&node),
+                l,
+            ))),
+            Err(e) => {
+                tracing::error!(node = %node.port, port, error = ?e, "Metrics bind failed; serving without metrics");
+                None
+            }
+        },
+    };
 
     // Per-node concurrency cap. `max_conns == 0` disables it entirely
     // (test default). Production default is 1024; saturated clients
@@ -248,6 +457,11 @@ pub async fn serve_with_shutdown(
     let mut handlers: tokio::task::JoinSet<()> = tokio::task::JoinSet::new();
     tokio::pin!(shutdown);
 
+    // Channels of `MUX` connections, served alongside the connections
+    // themselves (and drained with them). Each takes a `max_conns` slot
+    // of its own, so one connection can't carry more than the cap.
+    let mut mux_channels = node.take_mux_channels();
+
     // Accept loop with a select between accept() and the shutdown channel.
     // On shutdown: stop accepting, fall through to drain. Dropping the
     // sender (back-compat) yields RecvError which we treat as shutdown.
@@ -258,6 +472,10 @@ pub async fn serve_with_shutdown(
                 tracing::info!(node = %node.port, "Shutdown signal received; stopping accept loop");
                 break;
             }
+            _ = node.shutdown_requested() => {
+                tracing::info!(node = %node.port, "NODE SHUTDOWN received; stopping accept loop");
+                break;
+            }
             accepted = listener.accept() => {
                 let (stream, peer) = match accepted {
                     Ok(v) => v,
@@ -266,6 +484,11 @@ pub async fn serve_with_shutdown(
                         break;
                     }
                 };
+                if !node.opts.no_nodelay
+                    && let Err(e) = stream.set_nodelay(true)
+                {
+                    tracing::debug!(node = %node.port, peer = %peer, error = ?e, "Could not set TCP_NODELAY");
+                }
                 let node = Arc::clone(&node);
                 let node_port = node.port.clone();
 
@@ -282,16 +505,63 @@ pub async fn serve_with_shutdown(
                     },
                 };
 
-                handlers.spawn(async move {
-                    let _permit = permit;
-                    if let Err(e) = handle_client(node, stream).await {
-                        tracing::error!(node = %node_port, peer = %peer, error = ?e, "Client connection error");
+                // Everything logged while serving this connection carries
+                // the node and peer, so concurrent connections can be told
+                // apart.
+                let span = tracing::info_span!("conn", node = %node_port, peer = %peer);
+                handlers.spawn(
+                    async move {
+                        let _permit = permit;
+                        if let Err(e) = handle_client(node, stream).await {
+                            tracing::error!(error = ?e, "Client connection error");
+                        }
                     }
-                });
+                    .instrument(span),
+                );
+            }
+            Some(channel) = async {
+                match &mut mux_channels {
+                    Some(rx) => rx.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let (mut channel, limiter) = channel;
+                let permit = match &conn_sem {
+                    None => None,
+                    Some(sem) => match Arc::clone(sem).try_acquire_owned() {
+                        Ok(p) => Some(p),
+                        Err(_) => {
+                            tracing::warn!(node = %node.port, stream = channel.id().unwrap_or_default(), "Refusing mux channel: max_conns saturated");
+                            let _ = channel.write_all(b"ERR server busy\n").await;
+                            continue;
+                        }
+                    },
+                };
+                let node = Arc::clone(&node);
+                let span = tracing::info_span!("mux", node = %node.port, stream = channel.id().unwrap_or_default());
+                handlers.spawn(
+                    async move {
+                        let _permit = permit;
+                        if let Err(e) = handle_mux_channel(node, channel, limiter).await {
+                            tracing::error!(error = ?e, "Mux channel error");
+                        }
+                    }
+                    .instrument(span),
+                );
             }
         }
     }
 
+    // A signal on the channel flips the watch too, so both ways in stop
+    // the gossip, heartbeat and announce loops before the drain.
+    node.request_shutdown();
+    for task in [gossip_task, heartbeat_task, announce_task]
+        .into_iter()
+        .flatten()
+    {
+        let _ = task.await;
+    }
+
     // Drain phase. `drain_timeout == ZERO` means wait indefinitely — used
     // by the back-compat `serve` path so test aborts still kill the task
     // instantly via JoinHandle::abort.
@@ -315,11 +585,37 @@ pub async fn serve_with_shutdown(
             }
         }
     }
+    if let Some(task) = metrics_task {
+        task.abort();
+    }
+    if let Some(task) = probe_task {
+        task.abort();
+    }
+    if let Some(task) = monitor_task {
+        task.abort();
+    }
+    if let Some(task) = split_brain_task {
+        task.abort();
+    }
+    if let Some(task) = export_task {
+        let _ = stop_export.send(());
+        let _ = task.await;
+    }
+    if let Some(task) = replay_task {
+        let _ = stop_replay.send(());
+        let _ = task.await;
+    }
 }
 
+/// What [`run`] prints on stdout once its TCP listener is bound, followed
+/// by the port. `--port 0` leaves the choice to the OS, so this is how a
+/// parent process learns it.
+pub const LISTENING_PREFIX: &str = "LISTENING ";
+
 /// Run a single ring node: bind, then serve until SIGTERM/SIGINT, then
 /// drain in-flight handlers up to `shutdown_timeout`. Used by the binary;
-/// tests use [`bind`] + [`serve`] directly.
+/// tests use [`bind`] + [`serve`] directly. A `unix:<path>` `bind_addr`
+/// listens on a Unix domain socket (see [`bind_unix_with_opts`]).
 // Wide-by-design: mirrors `bind`'s argument set. Same v1.1 plan.
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
@@ -332,19 +628,46 @@ pub async fn run(
     idle_timeout: Duration,
     max_conns: u32,
     shutdown_timeout: Duration,
-) -> Result<(), AnyErr> {
-    let (node, listener, _addr) = bind(
-        bind_addr,
-        gossip_interval,
-        file_size,
-        storage_root,
-        true,
-        fsync_mode,
-        auth_token,
-        idle_timeout,
-        max_conns,
-    )
-    .await?;
+    opts: NodeOpts,
+) -> Result<(), RingError> {
+    let (node, listener): (_, Transport) = match crate::transport::unix_path(bind_addr) {
+        #[cfg(unix)]
+        Some(path) => {
+            let (node, listener) = bind_unix_with_opts(
+                std::path::Path::new(path),
+                gossip_interval,
+                file_size,
+                storage_root,
+                true,
+                fsync_mode,
+                auth_token,
+                idle_timeout,
+                max_conns,
+                opts,
+            )
+            .await?;
+            (node, listener.into())
+        }
+        #[cfg(not(unix))]
+        Some(_) => return Err("unix sockets are not supported on this platform".into()),
+        None => {
+            let (node, listener, addr) = bind_with_opts(
+                bind_addr,
+                gossip_interval,
+                file_size,
+                storage_root,
+                true,
+                fsync_mode,
+                auth_token,
+                idle_timeout,
+                max_conns,
+                opts,
+            )
+            .await?;
+            println!("{LISTENING_PREFIX}{}", addr.port());
+            (node, listener.into())
+        }
+    };
 
     // Wire SIGTERM (orchestrator) and SIGINT (interactive Ctrl-C) into a
     // single oneshot. Whichever fires first wins; the other is dropped.
@@ -355,10 +678,50 @@ pub async fn run(
         let _ = tx.send(());
     });
 
+    if let Some(path) = node.opts.startup_script.clone() {
+        run_startup_script(&node, &path).await?;
+    }
     serve_with_shutdown(node, listener, rx, shutdown_timeout).await;
     Ok(())
 }
 
+/// Run `--script` against `node` while its own listener is still not
+/// accepting: the script's [`RingClient`](crate::client::RingClient)
+/// talks to a loopback listener served for it alone, closed afterwards.
+async fn run_startup_script(node: &Arc<Node>, path: &std::path::Path) -> Result<(), RingError> {
+    let script = crate::script::load(path)?;
+    let private = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = private.local_addr()?;
+    let private = Transport::from(private);
+    let server = {
+        let node = Arc::clone(node);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = private.accept().await {
+                tokio::spawn(handle_client(Arc::clone(&node), stream));
+            }
+        })
+    };
+    let client = crate::client::RingClient::with_opts(addr.to_string(), node.client_opts());
+    tracing::info!(node = %node.port, script = %path.display(), commands = script.len(), "Running startup script");
+    let res = crate::script::run(
+        &client,
+        &script,
+        node.opts.script_continue_on_error,
+        |step, reply| match reply {
+            Ok(lines) => {
+                tracing::info!(node = %node.port, line = step.line, command = %step.command, reply = %lines.join(" | "), "Script command done")
+            }
+            Err(e) => {
+                tracing::warn!(node = %node.port, line = step.line, command = %step.command, error = %e, "Script command failed")
+            }
+        },
+    )
+    .await;
+    drop(client);
+    server.abort();
+    res.map_err(|e| format!("script {}: {e}", path.display()).into())
+}
+
 #[cfg(unix)]
 async fn wait_for_shutdown_signal() {
     use tokio::signal::unix::{SignalKind, signal};
@@ -382,10 +745,108 @@ async fn wait_for_shutdown_signal() {
     tracing::info!("Ctrl-C received; beginning graceful shutdown");
 }
 
-async fn handle_client(node: Arc<Node>, stream: TcpStream) -> Result<(), AnyErr> {
+tokio::task_local! {
+    /// Address of the client whose connection is being served, for logs
+    /// that name where a command came from.
+    static PEER_ADDR: String;
+
+    /// Rate limiter of the connection being served, which `MUX` hands on
+    /// to the connection's channels.
+    static CONN_LIMITER: Option<SharedLimiter>;
+}
+
+async fn handle_client(node: Arc<Node>, stream: Stream) -> Result<(), RingError> {
+    let _active = node.metrics.track_connection();
+
+    // TLS handshake (plain passthrough when no certificate is configured).
+    // Bounded like the AUTH read below so a stalled peer can't pin the task.
+    let stream =
+        match tokio::time::timeout(Duration::from_secs(5), node.opts.tls.accept(stream)).await {
+            Ok(r) => r?,
+            Err(_) => return Err("TLS handshake timed out".into()),
+        };
+
+    // Version greeting goes out before AUTH so a client can refuse an
+    // incompatible node without ever sending its credentials.
+    let mut stream = stream;
+    if node.opts.hello {
+        stream
+            .write_all(PROTOCOL_VERSION.node_greeting(node.generation()).as_bytes())
+            .await?;
+    }
+
+    // Each connection gets its own bucket so one flooding client can't
+    // starve the others.
+    let limiter = RateLimiter::shared(node.opts.rate_limit_rps);
+
     // Set read and write streams
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    let peer = stream
+        .tcp()
+        .and_then(|s| s.peer_addr().ok())
+        .map_or_else(|| "-".to_string(), |a| a.to_string());
+    let (reader, mut writer) = tokio::io::split(stream);
+    let res = PEER_ADDR
+        .scope(
+            peer,
+            CONN_LIMITER.scope(
+                limiter,
+                client_loop(
+                    Arc::clone(&node),
+                    BufReader::new(reader),
+                    &mut writer,
+                    false,
+                ),
+            ),
+        )
+        .await;
+    if res.is_err() {
+        node.errors_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+    // Explicit shutdown so a TLS peer sees close_notify rather than a bare EOF.
+    let _ = writer.shutdown().await;
+    res
+}
+
+/// Serve one channel of a `MUX` connection. The connection went through
+/// TLS, `HELLO` and `AUTH` already, so the channel starts at commands;
+/// its commands draw on the connection's `limiter`.
+async fn handle_mux_channel(
+    node: Arc<Node>,
+    channel: crate::mux::Channel,
+    limiter: Option<SharedLimiter>,
+) -> Result<(), RingError> {
+    let (reader, mut writer) = tokio::io::split(channel);
+    let res = CONN_LIMITER
+        .scope(
+            limiter,
+            client_loop(Arc::clone(&node), BufReader::new(reader), &mut writer, true),
+        )
+        .await;
+    if res.is_err() {
+        node.errors_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+    let _ = writer.shutdown().await;
+    res
+}
+
+/// Serve commands from one connection until it closes. `authenticated`
+/// skips the `AUTH` line: set for a `MUX` channel, whose connection sent
+/// it already.
+async fn client_loop<R, W>(
+    node: Arc<Node>,
+    reader: BufReader<R>,
+    mut writer: W,
+    authenticated: bool,
+) -> Result<(), RingError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // Every line read from the client is capped at `--max-line-bytes`; an
+    // over-long one gets `ERR message too large` and the connection closes.
+    let mut reader = LimitedLineReader::new(reader, node.opts.max_line_bytes);
 
     // AUTH handshake (skipped if the node's token is disabled).
     //
@@ -396,12 +857,15 @@ async fn handle_client(node: Arc<Node>, stream: TcpStream) -> Result<(), AnyErr>
     // and sends nothing can't hold a tokio task forever. Failed auth gets
     // an explicit ERR line before close so a misconfigured operator can
     // diagnose it without packet captures.
-    if node.auth_token.is_enabled() {
+    if node.auth_token.is_enabled() && !authenticated {
         let mut auth_line = String::new();
         let read =
             tokio::time::timeout(Duration::from_secs(1), reader.read_line(&mut auth_line)).await;
         let n = match read {
             Ok(Ok(n)) => n,
+            Ok(Err(e)) if is_line_too_long(&e) => {
+                return reject_long_line(&node, &mut writer, &e).await;
+            }
             Ok(Err(e)) => return Err(e.into()),
             Err(_) => {
                 let _ = writer.write_all(b"ERR auth timeout\n").await;
@@ -418,6 +882,12 @@ async fn handle_client(node: Arc<Node>, stream: TcpStream) -> Result<(), AnyErr>
     // The protocol is line delimited, so we just need to read the first line
     // when figuring out how to handle the request
     let mut line = String::new();
+    // Set by a `CID:` header line; tags the command after it only.
+    let mut cid = CorrelationId::default();
+    // Set by a `VC:` header line; likewise.
+    let mut vclock = None;
+    // `--accept-timeout-ms` bounds the wait for the first line only.
+    let mut timeout = node.accept_timeout();
 
     loop {
         line.clear();
@@ -426,10 +896,27 @@ async fn handle_client(node: Arc<Node>, stream: TcpStream) -> Result<(), AnyErr>
         // task indefinitely. The OS-level TCP keepalive eventually closes
         // the socket but that can take hours; this is the application-
         // layer bound. (NEXT_STEPS.md §2.6.)
-        let read = if node.idle_timeout.is_zero() {
-            reader.read_line(&mut line).await
+        //
+        // While idle between commands the connection also watches for
+        // NODE SHUTDOWN, so a drain isn't held up by a peer's pooled
+        // connection. `fill_buf` is cancel-safe; a command that has
+        // started arriving is always read and served.
+        let next_line = async {
+            tokio::select! {
+                biased;
+                r = reader.get_mut().fill_buf() => {
+                    if let Err(e) = r {
+                        return Some(Err(e));
+                    }
+                }
+                _ = node.shutdown_requested() => return None,
+            }
+            Some(reader.read_line(&mut line).await)
+        };
+        let read = if timeout.is_zero() {
+            next_line.await
         } else {
-            match tokio::time::timeout(node.idle_timeout, reader.read_line(&mut line)).await {
+            match tokio::time::timeout(timeout, next_line).await {
                 Ok(r) => r,
                 Err(_) => {
                     let _ = writer.write_all(b"ERR idle timeout\n").await;
@@ -437,407 +924,4038 @@ async fn handle_client(node: Arc<Node>, stream: TcpStream) -> Result<(), AnyErr>
                 }
             }
         };
-        if read? == 0 {
+        let Some(read) = read else {
             break;
+        };
+        timeout = node.idle_timeout;
+        match read {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if is_line_too_long(&e) => {
+                return reject_long_line(&node, &mut writer, &e).await;
+            }
+            Err(e) => return Err(e.into()),
         }
-
-        // Parse the header and match it with a specific command
-        match protocol::parse_line(&line) {
-            Ok(cmd) => match cmd {
-                // NODE
-                protocol::Command::NodeNext(addr) => {
-                    handle_node_next(&node, &mut writer, addr).await?
+        if let Some(header) = protocol::parse_correlation_id(&line) {
+            match header {
+                Ok(id) => cid = id,
+                Err(e) => {
+                    node.errors_total
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    handle_error(&mut writer, e.to_string()).await?
                 }
-                protocol::Command::NodeStatus => handle_node_status(&node, &mut writer).await?,
-                protocol::Command::NodePing => handle_node_ping(&mut writer).await?,
-                protocol::Command::NodeMetrics => handle_node_metrics(&node, &mut writer).await?,
-                protocol::Command::NodeHeal => {
-                    handle_node_heal(Arc::clone(&node), &mut writer).await?
-                }
-                protocol::Command::NodeHealHop { token, start_addr } => {
-                    handle_node_heal_hop(Arc::clone(&node), &mut writer, token, start_addr).await?
-                }
-                protocol::Command::NodeHealDone { token } => {
-                    handle_node_heal_done(&node, &mut writer, token).await?
-                }
-
-                // RING
-                protocol::Command::RingForward { ttl, msg } => {
-                    handle_ring_forward(&node, &mut writer, ttl, msg).await?
+            }
+            continue;
+        }
+        if let Some(header) = crate::vclock::parse_vector_clock(&line) {
+            match header {
+                Ok(clock) => vclock = Some(clock),
+                Err(e) => {
+                    node.errors_total
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    handle_error(&mut writer, e.to_string()).await?
                 }
+            }
+            continue;
+        }
+        let cid = std::mem::take(&mut cid);
+        let vclock = vclock.take();
+        node.msgs_received
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-                // TOPOLOGY
-                protocol::Command::TopologyWalk => handle_topology_walk(&node, &mut writer).await?,
-                protocol::Command::TopologyHop {
-                    token,
-                    start_addr,
-                    history,
-                } => handle_topology_hop(&node, &mut writer, token, start_addr, history).await?,
-                protocol::Command::TopologyDone { token, history } => {
-                    // Pass an owned Arc so it can be moved into the new task
-                    handle_topology_done(Arc::clone(&node), &mut writer, token, history).await?
-                }
-                protocol::Command::TopologySet { history } => {
-                    handle_topology_set(&node, &mut writer, history).await?
-                }
+        // Hop lines may end with the sender's trace context.
+        let (cmd_line, parent) = crate::trace::split_context(&line);
 
-                // NETMAP
-                protocol::Command::NetmapDiscover => {
-                    handle_netmap_discover(&node, &mut writer).await?
+        // Parse the header and match it with a specific command
+        match protocol::parse_line(cmd_line).and_then(|cmd| node.expand(cmd)) {
+            Ok(cmd) if rate_limited() => {
+                // Dropped, body and all, so the next line parses cleanly.
+                if let Some(len) = cmd.body_len() {
+                    copy(&mut reader.get_mut().take(len), &mut tokio::io::sink()).await?;
                 }
-                protocol::Command::NetmapHop {
-                    token,
-                    start_addr,
-                    entries,
-                } => handle_netmap_hop(&node, &mut writer, token, start_addr, entries).await?,
-                protocol::Command::NetmapDone { token, entries } => {
-                    handle_netmap_done(&node, &mut writer, token, entries).await?
+                tracing::debug!(node = %node.port, cmd = %command_label(&line), "Rate limited");
+                writer.write_all(b"ERR rate limited\n").await?;
+            }
+            Ok(cmd) => {
+                node.metrics.record_command(&cmd);
+                let mut trace = node.tracer.start(&cmd, parent);
+                let span = tracing::debug_span!(
+                    "command",
+                    cmd = %command_label(cmd_line),
+                    trace_id = tracing::field::Empty,
+                    cid = tracing::field::Empty
+                );
+                if let Some(t) = &mut trace {
+                    span.record("trace_id", t.ctx.trace_id_hex());
+                    t.cid = cid.clone();
                 }
-                protocol::Command::NetmapSet { entries } => {
-                    handle_netmap_set(&node, &mut writer, entries).await?
+                if let Some(id) = &cid.0 {
+                    span.record("cid", id.as_str());
                 }
-                protocol::Command::NetmapGet => handle_netmap_get(&node, &mut writer).await?,
-
-                // FILE
-                protocol::Command::FilePush { size, name } => {
-                    handle_file_push(Arc::clone(&node), &mut reader, &mut writer, size, name)
-                        .await?
+                let event = if node.has_subscribers() {
+                    command_event(&cmd)
+                } else {
+                    None
+                };
+                // Only `RING` messages carry a clock on.
+                let vclock = vclock
+                    .filter(|_| is_ring_message(&cmd))
+                    .map(|clock| node.observe_clock(clock));
+                let flow = crate::trace::scope(
+                    trace.as_ref().map(|t| t.ctx),
+                    crate::trace::cid_scope(
+                        cid.clone(),
+                        crate::vclock::scope(
+                            vclock,
+                            dispatch(&node, reader.get_mut(), &mut writer, cmd, &cid),
+                        ),
+                    )
+                    .instrument(span),
+                )
+                .await;
+                if let Some(t) = trace {
+                    node.tracer.end(t);
                 }
-                protocol::Command::FilePull { name } => {
-                    handle_file_pull(&node, &mut writer, name).await?;
-                    break;
+                let flow = flow?;
+                if let Some((kind, data)) = event {
+                    node.publish(kind, &data);
                 }
-                protocol::Command::FileList => {
-                    handle_file_list_csv(&node, &mut writer).await?;
+                if flow == Flow::Close {
                     break;
                 }
-                protocol::Command::FileTagsSet { entries } => {
-                    handle_file_tags_set(&node, &mut writer, entries).await?
-                }
-
-                // FILE (internal)
-                protocol::Command::FilePushChunk {
-                    name,
-                    chunk_size,
-                    file_size,
-                    parts,
-                    index,
-                    start_port,
-                } => {
-                    handle_file_push_chunk(
-                        Arc::clone(&node),
-                        &mut reader,
-                        &mut writer,
-                        name,
-                        chunk_size,
-                        file_size,
-                        parts,
-                        index,
-                        start_port,
-                    )
-                    .await?
-                }
-                protocol::Command::FileGetChunk { name } => {
-                    handle_file_get_chunk(&node, &mut writer, name).await?
-                }
+            }
+            Err(e) => {
+                node.errors_total
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                handle_error(&mut writer, e.to_string()).await?
+            }
+        }
 
-                // FILE (backup)
-                protocol::Command::FileBackupPush { name, size } => {
-                    handle_file_backup_push(&node, &mut reader, &mut writer, name, size).await?
-                }
-                protocol::Command::FileGetBackupChunk { name } => {
-                    handle_file_get_backup_chunk(&node, &mut writer, name).await?
-                }
-                protocol::Command::FileContentPush { name, size } => {
-                    handle_file_content_push(&node, &mut reader, &mut writer, name, size).await?
-                }
-            },
-            Err(e) => handle_error(&mut writer, e).await?,
+        if node.is_shutting_down() {
+            break;
         }
     }
 
     Ok(())
 }
 
-// --- Command handlers
+/// Whether the connection being served is out of rate-limit tokens.
+fn rate_limited() -> bool {
+    CONN_LIMITER
+        .try_with(|limiter| {
+            limiter
+                .as_ref()
+                .is_some_and(|l| !l.lock().unwrap_or_else(|e| e.into_inner()).try_acquire())
+        })
+        .unwrap_or(false)
+}
 
-async fn handle_node_next<W: AsyncWrite + Unpin>(
+/// Answer an over-long line and end the connection: the rest of the line
+/// is still unread, so nothing after it could be parsed anyway.
+async fn reject_long_line<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-    addr: String,
-) -> Result<(), AnyErr> {
-    node.set_next(addr.clone()).await;
-    writer
-        .write_all(format!("OK next={}\n", addr).as_bytes())
-        .await?;
+    e: &std::io::Error,
+) -> Result<(), RingError> {
+    node.errors_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    tracing::warn!(node = %node.port, error = %e, "Closing connection after over-long line");
+    writer.write_all(b"ERR message too large\n").await?;
     Ok(())
 }
 
-async fn handle_node_status<W: AsyncWrite + Unpin>(
-    node: &Node,
-    writer: &mut W,
-) -> Result<(), AnyErr> {
-    let next = node
-        .get_next()
-        .await
-        .unwrap_or_else(|| "<unset>".to_string());
-    writer
-        .write_all(format!("PORT {}\nNEXT {}\nOK\n", node.port, next).as_bytes())
-        .await?;
-    Ok(())
+/// Ack a forwarded hop on receipt, before doing any of its work, so a
+/// pooled sender only waits for this hop and not for the rest of the ring.
+async fn ack_hop<W: AsyncWrite + Unpin>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(b"OK\n").await
 }
 
-async fn handle_node_ping<W: AsyncWrite + Unpin>(writer: &mut W) -> Result<(), AnyErr> {
-    writer.write_all(b"PONG\n").await?;
-    Ok(())
+/// The `RING` family, whose lines may carry a `VC:` clock.
+fn is_ring_message(cmd: &protocol::Command) -> bool {
+    use protocol::Command::*;
+    matches!(
+        cmd,
+        RingForward { .. }
+            | RingOn { .. }
+            | RingAck { .. }
+            | RingAckHop { .. }
+            | RingAckDone { .. }
+            | RingEcho { .. }
+            | RingEchoHop { .. }
+            | RingEchoDone { .. }
+            | RingCollect { .. }
+            | RingCollectHop { .. }
+            | RingCollectDone { .. }
+            | RingReq { .. }
+            | RingResp { .. }
+            | RingTimed { .. }
+            | RingOnce { .. }
+            | RingAll { .. }
+            | RingOnceHop { .. }
+            | RingOnceDone { .. }
+    )
 }
 
-/// Emit per-node Prometheus-friendly counters as a list of `<key>=<value>`
-/// lines, terminated by `OK`. The gateway reads these from each ring node
-/// and aggregates into the Prometheus text format at `GET /metrics`.
+/// Whether the connection stays open after a command.
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    /// FILE PULL / FILE LIST stream until EOF, so they end the connection.
+    Close,
+}
+
+/// `NOUN VERB` of a command line, for the per-command span. Arguments are
+/// left out: they can be long (histories) or sensitive (file names).
+fn command_label(line: &str) -> String {
+    line.split_whitespace()
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run one parsed command against `node`.
+async fn dispatch<R, W>(
+    node: &Arc<Node>,
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    cmd: protocol::Command,
+    cid: &CorrelationId,
+) -> Result<Flow, RingError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let max_ttl = node.opts.max_ring_ttl;
+    if let Some(ttl) = cmd.ring_ttl().filter(|&ttl| max_ttl > 0 && ttl > max_ttl) {
+        tracing::debug!(node = %node.port, ttl, max_ttl, "Rejected RING TTL over the cap");
+        writer
+            .write_all(format!("ERR ttl exceeds max {max_ttl}\n").as_bytes())
+            .await?;
+        return Ok(Flow::Continue);
+    }
+
+    match cmd {
+        // NODE
+        protocol::Command::NodeNext(addr) => handle_node_next(node, writer, addr).await?,
+        protocol::Command::NodeNextMulti(addrs) => {
+            handle_node_next_multi(node, writer, addrs).await?
+        }
+        protocol::Command::NodeNextIf { expected, new_addr } => {
+            handle_node_next_if(node, writer, expected, new_addr).await?
+        }
+        protocol::Command::NodeNextGen { addr, generation } => {
+            handle_node_next_gen(node, writer, addr, generation).await?
+        }
+        protocol::Command::NodePrev(addr) => handle_node_prev(node, writer, addr).await?,
+        protocol::Command::NodeNextRing { ring, addr } => {
+            handle_node_next_ring(node, writer, ring, addr).await?
+        }
+        protocol::Command::NodeNextRings(hops) => {
+            handle_node_next_rings(node, writer, hops).await?
+        }
+        protocol::Command::NodeRing { ring } => handle_node_ring(node, writer, ring).await?,
+        protocol::Command::NodeStatus => handle_node_status(node, writer).await?,
+        protocol::Command::NodeInfo => handle_node_info(node, writer).await?,
+        protocol::Command::NodePing => handle_node_ping(writer).await?,
+        protocol::Command::NodeMetrics => handle_node_metrics(node, writer).await?,
+        protocol::Command::NodeStat => handle_node_stat(node, writer).await?,
+        protocol::Command::NodeCircuit => handle_node_circuit(node, writer).await?,
+        protocol::Command::NodeShutdown => handle_node_shutdown(node, writer).await?,
+        protocol::Command::NodeHeal => handle_node_heal(Arc::clone(node), writer).await?,
+        protocol::Command::NodeHealHop { token, start_addr } => {
+            handle_node_heal_hop(Arc::clone(node), writer, token, start_addr).await?
+        }
+        protocol::Command::NodeHealDone { token } => {
+            handle_node_heal_done(node, writer, token).await?
+        }
+
+        // RING
+        protocol::Command::RingForward {
+            hop_count,
+            ttl,
+            msg,
+        } => handle_ring_forward(node, writer, DEFAULT_RING, hop_count, ttl, msg).await?,
+        protocol::Command::RingOn {
+            ring,
+            hop_count,
+            ttl,
+            msg,
+        } => handle_ring_forward(node, writer, &ring, hop_count, ttl, msg).await?,
+        protocol::Command::RingTimed { expiry_ms, msg } => {
+            handle_ring_timed(node, writer, expiry_ms, msg).await?
+        }
+
+        protocol::Command::RingAck { ttl, msg } => handle_ring_ack(node, writer, ttl, msg).await?,
+        protocol::Command::RingAckHop {
+            token,
+            start_addr,
+            ttl,
+            seen,
+            msg,
+        } => handle_ring_ack_hop(node, writer, token, start_addr, ttl, seen, msg).await?,
+        protocol::Command::RingEcho { ttl, msg } => {
+            handle_ring_echo(node, writer, ttl, msg).await?
+        }
+        protocol::Command::RingEchoHop {
+            token,
+            start_addr,
+            ttl,
+            msg,
+        } => handle_ring_echo_hop(node, writer, token, start_addr, ttl, msg).await?,
+        protocol::Command::RingEchoDone { token, msg } => {
+            handle_ring_echo_done(node, writer, token, msg).await?
+        }
+        protocol::Command::RingOnce { msg } => handle_ring_once(node, writer, msg).await?,
+        protocol::Command::RingAll { msg } => {
+            let mut ttl = node.ring_all_ttl();
+            if max_ttl > 0 {
+                ttl = ttl.min(max_ttl);
+            }
+            tracing::debug!(node = %node.port, ttl, "RING ALL");
+            handle_ring_forward(node, writer, DEFAULT_RING, 0, ttl, msg).await?
+        }
+        protocol::Command::RingOnceHop {
+            token,
+            hop_count,
+            start_addr,
+            msg,
+        } => handle_ring_once_hop(node, writer, token, hop_count, start_addr, msg).await?,
+        protocol::Command::RingOnceDone { token } => {
+            handle_ring_once_done(node, writer, token).await?
+        }
+        protocol::Command::RingCollect { ttl, key } => {
+            handle_ring_collect(node, writer, ttl, key).await?
+        }
+        protocol::Command::RingCollectHop {
+            token,
+            start_addr,
+            ttl,
+            key,
+            results,
+        } => handle_ring_collect_hop(node, writer, token, start_addr, ttl, key, results).await?,
+        protocol::Command::RingCollectDone { token, results } => {
+            let _ = node.finish_walk(&token, results).await;
+            writer.write_all(b"OK\n").await?;
+        }
+        protocol::Command::RingReq {
+            callback_addr,
+            ttl,
+            msg,
+        } => handle_ring_req(node, writer, callback_addr, ttl, msg).await?,
+        protocol::Command::RingResp {
+            callback_addr,
+            payload,
+        } => {
+            // A node named as the callback has nobody to hand the reply
+            // to; it only logs it.
+            tracing::info!(node = %node.port, callback = %callback_addr, payload = %payload, "RING RESP");
+            writer.write_all(b"OK\n").await?;
+        }
+        protocol::Command::RingAckDone { token, seen } => {
+            handle_ring_ack_done(node, writer, token, seen).await?
+        }
+
+        // TOPOLOGY
+        protocol::Command::TopologyWalk => handle_topology_walk(node, writer, false).await?,
+        protocol::Command::TopologyWalkFresh => handle_topology_walk(node, writer, true).await?,
+        protocol::Command::TopologyWalkMeta { key } => {
+            handle_topology_walk_meta(node, writer, key).await?
+        }
+        protocol::Command::TopologyHop {
+            token,
+            start_addr,
+            history,
+            visited,
+            meta_key,
+        } => {
+            handle_topology_hop(node, writer, token, start_addr, history, visited, meta_key).await?
+        }
+        protocol::Command::TopologyDone {
+            token,
+            history,
+            meta_key,
+        } => {
+            // Pass an owned Arc so it can be moved into the new task
+            handle_topology_done(Arc::clone(node), writer, token, history, meta_key).await?
+        }
+        protocol::Command::TopologyAbort { token } => {
+            handle_topology_abort(node, writer, token).await?
+        }
+        protocol::Command::TopologySet { history } => {
+            handle_topology_set(node, writer, history).await?
+        }
+        protocol::Command::TopologyReverseWalk => {
+            handle_topology_reverse_walk(node, writer).await?
+        }
+        protocol::Command::TopologyBidirWalk => handle_topology_bidir_walk(node, writer).await?,
+        protocol::Command::TopologyReverseHop {
+            token,
+            start_addr,
+            history,
+        } => handle_topology_reverse_hop(node, writer, token, start_addr, history).await?,
+        protocol::Command::TopologyReverseDone { token, history } => {
+            handle_topology_reverse_done(node, writer, token, history).await?
+        }
+        protocol::Command::WalkPayStart { payload_key } => {
+            handle_walk_pay_start(node, writer, payload_key).await?
+        }
+        protocol::Command::WalkPayHop {
+            token,
+            start_addr,
+            payload_key,
+            history,
+            payloads,
+        } => {
+            handle_walk_pay_hop(
+                node,
+                writer,
+                token,
+                start_addr,
+                payload_key,
+                history,
+                payloads,
+            )
+            .await?
+        }
+        protocol::Command::WalkPayDone {
+            token,
+            history,
+            payloads,
+        } => handle_walk_pay_done(node, writer, token, history, payloads).await?,
+        protocol::Command::WalkMax { max_hops } => handle_walk_max(node, writer, max_hops).await?,
+        protocol::Command::WalkMaxHop {
+            token,
+            hop_count,
+            max_hops,
+            start_addr,
+            history,
+        } => {
+            handle_walk_max_hop(
+                node, writer, token, hop_count, max_hops, start_addr, history,
+            )
+            .await?
+        }
+        protocol::Command::WalkMaxDone {
+            token,
+            truncated,
+            history,
+        } => handle_walk_max_done(node, writer, token, truncated, history).await?,
+        protocol::Command::WalkSample { probability } => {
+            handle_walk_sample(node, writer, probability).await?
+        }
+        protocol::Command::WalkSampleHop {
+            token,
+            hop_count,
+            probability,
+            start_addr,
+            history,
+        } => {
+            handle_walk_sample_hop(
+                node,
+                writer,
+                token,
+                hop_count,
+                probability,
+                start_addr,
+                history,
+            )
+            .await?
+        }
+        protocol::Command::WalkSampleDone {
+            token,
+            hop_count,
+            history,
+        } => handle_walk_sample_done(node, writer, token, hop_count, history).await?,
+        protocol::Command::WalkRing { ring } => handle_walk_ring(node, writer, ring).await?,
+        protocol::Command::WalkRingHop {
+            token,
+            ring,
+            start_addr,
+            history,
+        } => handle_walk_ring_hop(node, writer, token, ring, start_addr, history).await?,
+        protocol::Command::WalkRingDone { token, history } => {
+            handle_walk_ring_done(node, writer, token, history).await?
+        }
+        protocol::Command::WalkProbeHop {
+            token,
+            hop_count,
+            start_addr,
+        } => handle_probe_hop(node, writer, token, hop_count, start_addr).await?,
+        protocol::Command::WalkProbeDone { token } => {
+            handle_probe_done(node, writer, token).await?
+        }
+
+        // GATHER
+        protocol::Command::GatherStart { key } => handle_gather_start(node, writer, key).await?,
+        protocol::Command::GatherHop {
+            token,
+            start_addr,
+            key,
+            results,
+        } => handle_gather_hop(node, writer, token, start_addr, key, results).await?,
+        protocol::Command::GatherDone { token, results } => {
+            handle_gather_done(node, writer, token, results).await?
+        }
+        protocol::Command::GatherKeys => {
+            for key in protocol::GATHER_KEYS {
+                writer.write_all(format!("{key}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        protocol::Command::GatherStream { key } => handle_gather_stream(node, writer, key).await?,
+        protocol::Command::GatherStreamHop {
+            token,
+            start_addr,
+            key,
+            results,
+        } => handle_gather_stream_hop(node, writer, token, start_addr, key, results).await?,
+        protocol::Command::GatherStreamData { token, entry } => {
+            node.push_gather_stream(&token, entry).await;
+            writer.write_all(b"OK\n").await?
+        }
+
+        // ELECT
+        protocol::Command::ElectStart => handle_elect_start(node, writer).await?,
+        protocol::Command::ElectHop {
+            token,
+            start_addr,
+            candidate,
+        } => handle_elect_hop(node, writer, token, start_addr, candidate).await?,
+        protocol::Command::ElectDone { token, candidate } => {
+            let _ = node.finish_walk(&token, candidate).await;
+            writer.write_all(b"OK\n").await?;
+        }
+        protocol::Command::ElectLeader { start_addr, leader } => {
+            handle_elect_leader(node, writer, start_addr, leader).await?
+        }
+        protocol::Command::LeaderCmd { cmd } => {
+            if node.role().await != NodeRole::Leader {
+                let leader = node.get_leader().await;
+                let leader = leader.as_deref().unwrap_or("<unset>");
+                writer
+                    .write_all(format!("ERR not leader, leader={leader}\n").as_bytes())
+                    .await?;
+                return Ok(Flow::Continue);
+            }
+            match protocol::parse_line(&cmd).and_then(|cmd| node.expand(cmd)) {
+                Ok(inner) => {
+                    return Box::pin(dispatch(node, reader, writer, inner, cid)).await;
+                }
+                Err(e) => handle_error(writer, e.to_string()).await?,
+            }
+        }
+
+        // KV
+        protocol::Command::KvSet { key, value } => match node.kv_set(key, value).await {
+            Ok(()) => writer.write_all(b"OK\n").await?,
+            Err(e) => writer.write_all(format!("ERR {e}\n").as_bytes()).await?,
+        },
+        protocol::Command::KvGet { key } => {
+            let value = node.kv_get(&key).await;
+            let value = value.as_deref().unwrap_or("<nil>");
+            writer
+                .write_all(format!("{}VALUE {key} {value}\nOK\n", cid.header()).as_bytes())
+                .await?;
+        }
+
+        // NETMAP
+        protocol::Command::NetmapDiscover => handle_netmap_discover(node, writer).await?,
+        protocol::Command::NetmapHop {
+            token,
+            start_addr,
+            entries,
+        } => handle_netmap_hop(node, writer, token, start_addr, entries).await?,
+        protocol::Command::NetmapDone { token, entries } => {
+            handle_netmap_done(node, writer, token, entries).await?
+        }
+        protocol::Command::NetmapSet { entries } => {
+            handle_netmap_set(node, writer, entries).await?
+        }
+        protocol::Command::NetmapGet => handle_netmap_get(node, writer, cid).await?,
+
+        // FILE
+        protocol::Command::FilePush { size, name } => {
+            handle_file_push(Arc::clone(node), reader, writer, size, name).await?
+        }
+        protocol::Command::FilePull { name } => {
+            handle_file_pull(node, writer, name).await?;
+            return Ok(Flow::Close);
+        }
+        protocol::Command::FileList => {
+            handle_file_list_csv(node, writer).await?;
+            return Ok(Flow::Close);
+        }
+        protocol::Command::FileTagsSet { entries } => {
+            handle_file_tags_set(node, writer, entries).await?
+        }
+
+        // FILE (internal)
+        protocol::Command::FilePushChunk {
+            name,
+            chunk_size,
+            file_size,
+            parts,
+            index,
+            start_port,
+        } => {
+            handle_file_push_chunk(
+                Arc::clone(node),
+                reader,
+                writer,
+                name,
+                chunk_size,
+                file_size,
+                parts,
+                index,
+                start_port,
+            )
+            .await?
+        }
+        protocol::Command::FileGetChunk { name } => {
+            handle_file_get_chunk(node, writer, name).await?
+        }
+
+        // FILE (backup)
+        protocol::Command::FileBackupPush { name, size } => {
+            handle_file_backup_push(node, reader, writer, name, size).await?
+        }
+        protocol::Command::FileGetBackupChunk { name } => {
+            handle_file_get_backup_chunk(node, writer, name).await?
+        }
+        protocol::Command::FileContentPush { name, size } => {
+            handle_file_content_push(node, reader, writer, name, size).await?
+        }
+
+        // PROBE
+        protocol::Command::Probe { .. } => {
+            writer
+                .write_all(b"ERR PROBE is for the repair coordinator\n")
+                .await?
+        }
+
+        // HEARTBEAT
+        protocol::Command::Heartbeat { .. } | protocol::Command::Members => {
+            writer
+                .write_all(b"ERR HEARTBEAT and MEMBERS are for the heartbeat coordinator\n")
+                .await?
+        }
+
+        // BARRIER
+        protocol::Command::Barrier { barrier_id, n } => {
+            let start_addr = node.port.clone();
+            handle_barrier_hop(node, writer, barrier_id, n, start_addr, String::new()).await?
+        }
+        protocol::Command::BarrierHop {
+            barrier_id,
+            n,
+            start_addr,
+            visited,
+        } => handle_barrier_hop(node, writer, barrier_id, n, start_addr, visited).await?,
+        protocol::Command::BarrierWait { barrier_id, n } => {
+            handle_barrier_wait(node, writer, barrier_id, n).await?
+        }
+        protocol::Command::BarrierDone { barrier_id } => {
+            node.barrier_release(&barrier_id);
+            writer.write_all(b"OK\n").await?
+        }
+
+        // INCR
+        protocol::Command::Incr { counter_name } => handle_incr(node, writer, counter_name).await?,
+        protocol::Command::IncrHop {
+            counter_name,
+            token,
+            hop_count,
+            origin,
+            current_value,
+        } => {
+            handle_incr_hop(
+                node,
+                writer,
+                counter_name,
+                token,
+                hop_count,
+                origin,
+                current_value,
+            )
+            .await?
+        }
+
+        // TOPOLOGY WALK-FIND
+        protocol::Command::WalkFind { key, value } => {
+            handle_walk_find(node, writer, key, value).await?
+        }
+        protocol::Command::WalkFindHop {
+            token,
+            start_addr,
+            history,
+            key,
+            value,
+        } => handle_walk_find_hop(node, writer, token, start_addr, history, key, value).await?,
+        protocol::Command::WalkFound {
+            token,
+            addr,
+            history,
+        } => {
+            // `addr` never holds a space, so the waiter can split it off.
+            let _ = node.finish_walk(&token, format!("{addr} {history}")).await;
+            writer.write_all(b"OK\n").await?
+        }
+        protocol::Command::WalkNotFound { token } => {
+            let _ = node.finish_walk(&token, String::new()).await;
+            writer.write_all(b"OK\n").await?
+        }
+
+        // TOPOLOGY WALK-PARALLEL
+        protocol::Command::WalkParallel => handle_walk_parallel(node, writer).await?,
+
+        // SPLIT-BRAIN CHECK
+        protocol::Command::SplitBrainCheck => handle_split_brain_check(node, writer).await?,
+        protocol::Command::SplitBrainHop {
+            token,
+            start_addr,
+            leaders,
+        } => handle_split_brain_hop(node, writer, token, start_addr, leaders).await?,
+        protocol::Command::SplitBrainDone { token, leaders } => {
+            let _ = node.finish_walk(&token, leaders).await;
+            writer.write_all(b"OK\n").await?
+        }
+
+        // CHAOS
+        protocol::Command::ChaosMode { mode } => {
+            node.set_chaos(Some(mode));
+            writer.write_all(b"OK\n").await?
+        }
+        protocol::Command::ChaosOff => {
+            node.set_chaos(None);
+            writer.write_all(b"OK\n").await?
+        }
+
+        // SUBSCRIBE
+        protocol::Command::Subscribe { events } => {
+            return handle_subscribe(node, reader, writer, events).await;
+        }
+        protocol::Command::Unsubscribe => writer.write_all(b"ERR not subscribed\n").await?,
+
+        // RING KEY
+        protocol::Command::RingKey { key, msg } => handle_ring_key(node, writer, key, msg).await?,
+        protocol::Command::RingKeyHop { key, msg } => {
+            deliver_ring_key(node, &key, &msg);
+            writer
+                .write_all(format!("KEY_ACK {key}\n").as_bytes())
+                .await?
+        }
+
+        // NODE FORWARD-TO
+        protocol::Command::ForwardTo { target, command } => {
+            handle_forward_to(node, writer, target, command).await?
+        }
+
+        // SNAPSHOT
+        protocol::Command::Snapshot => {
+            let line = node.snapshot().await.to_line();
+            writer.write_all(format!("{line}\nOK\n").as_bytes()).await?
+        }
+        protocol::Command::Restore(snapshot) => match check_snapshot(node, *snapshot).await {
+            Ok(snapshot) => {
+                node.restore_snapshot(snapshot).await;
+                writer.write_all(b"OK\n").await?
+            }
+            Err(e) => {
+                writer
+                    .write_all(format!("ERR invalid snapshot: {e}\n").as_bytes())
+                    .await?
+            }
+        },
+
+        // MUX
+        protocol::Command::Mux => return handle_mux(node, reader, writer).await,
+    }
+    Ok(Flow::Continue)
+}
+
+/// Handle "MUX": answer `OK`, then carry frames on this connection until
+/// the peer closes it or the node shuts down. Each new channel goes to
+/// the accept loop and is served there like a connection of its own,
+/// sharing this connection's rate limiter. Refused unless the node runs
+/// with `--mux`.
+async fn handle_mux<R, W>(
+    node: &Node,
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+) -> Result<Flow, RingError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if !node.opts.mux {
+        writer.write_all(b"ERR mux not enabled\n").await?;
+        return Ok(Flow::Continue);
+    }
+    writer.write_all(b"OK\n").await?;
+    tracing::debug!(node = %node.port, "Connection switched to MUX");
+    let limiter = CONN_LIMITER.try_with(Clone::clone).ok().flatten();
+    let to_accept = node.mux_channels();
+    let (accepted, mut channels) = tokio::sync::mpsc::unbounded_channel();
+    let hand_over = async {
+        while let Some(channel) = channels.recv().await {
+            if to_accept.send((channel, limiter.clone())).is_err() {
+                break;
+            }
+        }
+    };
+    tokio::select! {
+        r = crate::mux::serve(&mut *reader, &mut *writer, accepted) => r?,
+        _ = hand_over => {}
+        _ = node.shutdown_requested() => {}
+    }
+    Ok(Flow::Close)
+}
+
+/// Handle "SUBSCRIBE <events>": answer `OK`, then write each matching
+/// `EVENT` line until the client sends UNSUBSCRIBE, after which the
+/// connection takes commands again. Any other line is refused while
+/// subscribed. Not subject to `--idle-timeout`: a quiet ring is not an
+/// idle subscriber.
+async fn handle_subscribe<R, W>(
+    node: &Node,
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    events: EventFilter,
+) -> Result<Flow, RingError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    enum Wake {
+        Event(String),
+        Input(std::io::Result<bool>),
+        Shutdown,
+    }
+
+    let (tx, mut rx) = node.subscribe();
+    tracing::info!(node = %node.port, events = %events, "Subscriber attached");
+    let max_line = match node.opts.max_line_bytes {
+        0 => u64::MAX,
+        n => n as u64,
+    };
+    let res = async {
+        writer.write_all(b"OK\n").await?;
+        let mut line = String::new();
+        loop {
+            // `recv` and `fill_buf` are both cancel-safe.
+            let wake = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => Wake::Event(event),
+                    None => Wake::Shutdown,
+                },
+                r = reader.fill_buf() => Wake::Input(r.map(|buf| buf.is_empty())),
+                _ = node.shutdown_requested() => Wake::Shutdown,
+            };
+            match wake {
+                Wake::Event(event) => {
+                    if events.matches(&event) {
+                        writer.write_all(event.as_bytes()).await?;
+                    }
+                }
+                Wake::Input(Ok(true)) | Wake::Shutdown => return Ok(Flow::Close),
+                Wake::Input(Err(e)) => return Err(e.into()),
+                Wake::Input(Ok(false)) => {
+                    line.clear();
+                    (&mut *reader).take(max_line).read_line(&mut line).await?;
+                    if !line.ends_with('\n') {
+                        writer.write_all(b"ERR message too large\n").await?;
+                        return Ok(Flow::Close);
+                    }
+                    if line.trim().eq_ignore_ascii_case("UNSUBSCRIBE") {
+                        writer.write_all(b"OK\n").await?;
+                        return Ok(Flow::Continue);
+                    }
+                    writer
+                        .write_all(b"ERR only UNSUBSCRIBE is accepted while subscribed\n")
+                        .await?;
+                }
+            }
+        }
+    }
+    .await;
+    node.unsubscribe(&tx);
+    tracing::info!(node = %node.port, "Subscriber detached");
+    res
+}
+
+// --- Command handlers
+
+async fn handle_node_next<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    addr: String,
+) -> Result<(), RingError> {
+    let addr = match check_next_addr(node, addr).await {
+        Ok(addr) => resolve_next_addr(node, addr).await,
+        Err(e) => {
+            writer.write_all(format!("ERR {e}\n").as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    let reply = if node.set_next(addr.clone()).await {
+        format!("OK next={addr}\n")
+    } else {
+        format!("OK next={addr} (unchanged)\n")
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// `NODE NEXT` as a compare-and-swap: only if NEXT is `expected` now, as
+/// `NODE STATUS` would report it. A bare port is read as this host's, like
+/// a `NODE NEXT` target.
+async fn handle_node_next_if<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    expected: String,
+    new_addr: String,
+) -> Result<(), RingError> {
+    let expected = match expected.parse::<u16>() {
+        Ok(_) => node::normalize_addr(&expected),
+        Err(_) => expected,
+    };
+    let addr = match check_next_addr(node, new_addr).await {
+        Ok(addr) => resolve_next_addr(node, addr).await,
+        Err(e) => {
+            writer.write_all(format!("ERR {e}\n").as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    let reply = match node.set_next_if(&expected, addr.clone()).await {
+        Ok(()) if expected == addr => format!("OK next={addr} (unchanged)\n"),
+        Ok(()) => format!("OK next={addr}\n"),
+        Err(current) => {
+            let current = current.unwrap_or_else(|| "<unset>".to_string());
+            tracing::info!(node = %node.port, expected = %expected, current = %current, "NEXT-IF lost the race");
+            format!("CAS_FAIL current={current}\n")
+        }
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// `NODE NEXT`, but only if the node answering at `addr` is the
+/// incarnation the caller saw: its `NODE STATUS` generation must equal
+/// `generation`. A node restarted on the same port since then reports a
+/// newer one and the wiring is refused.
+async fn handle_node_next_gen<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    addr: String,
+    generation: u64,
+) -> Result<(), RingError> {
+    let addr = match check_next_addr(node, addr).await {
+        Ok(addr) => resolve_next_addr(node, addr).await,
+        Err(e) => {
+            writer.write_all(format!("ERR {e}\n").as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    let reply = match node.peer_generation(&addr).await {
+        Ok(found) if found == generation => {
+            node.set_next(addr.clone()).await;
+            format!("OK next={addr}\n")
+        }
+        Ok(found) => {
+            tracing::warn!(node = %node.port, addr = %addr, expected = generation, found, "Refusing NEXT with stale generation");
+            format!("ERR stale generation: {addr} is {found}, not {generation}\n")
+        }
+        Err(e) => {
+            tracing::warn!(node = %node.port, addr = %addr, error = %e, "Could not read NEXT's generation");
+            format!("ERR no generation from {addr}\n")
+        }
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle_node_next_ring<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ring: String,
+    addr: String,
+) -> Result<(), RingError> {
+    let addr = match check_next_addr(node, addr).await {
+        Ok(addr) => resolve_next_addr(node, addr).await,
+        Err(e) => {
+            writer.write_all(format!("ERR {e}\n").as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    node.set_next_on(&ring, addr.clone()).await;
+    writer
+        .write_all(format!("OK next={}\n", addr).as_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn handle_node_next_multi<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    addrs: Vec<String>,
+) -> Result<(), RingError> {
+    let mut hops = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        match check_next_addr(node, addr).await {
+            Ok(addr) => hops.push(resolve_next_addr(node, addr).await),
+            Err(e) => {
+                writer.write_all(format!("ERR {e}\n").as_bytes()).await?;
+                return Ok(());
+            }
+        }
+    }
+    let reply = format!("OK next={}\n", hops.join(","));
+    node.set_next_multi(hops).await;
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// Check every address first; only when all pass are the rings rewired,
+/// together.
+async fn handle_node_next_rings<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    hops: Vec<(String, String)>,
+) -> Result<(), RingError> {
+    let mut checked = Vec::with_capacity(hops.len());
+    let mut failures = Vec::new();
+    for (ring, addr) in hops {
+        match check_next_addr(node, addr.clone()).await {
+            Ok(addr) => checked.push((ring, resolve_next_addr(node, addr).await)),
+            Err(e) => failures.push(format!("{ring}={addr}: {e}")),
+        }
+    }
+    if !failures.is_empty() {
+        let reply = format!("ERR partial_failure: {}\n", failures.join("; "));
+        writer.write_all(reply.as_bytes()).await?;
+        return Ok(());
+    }
+    node.set_next_for_rings(&checked).await;
+    writer
+        .write_all(format!("OK next_count={}\n", checked.len()).as_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn handle_node_ring<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ring: String,
+) -> Result<(), RingError> {
+    let next = node
+        .get_next_on(&ring)
+        .await
+        .unwrap_or_else(|| "<unset>".to_string());
+    writer
+        .write_all(format!("NEXT {next}\nOK\n").as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Refuse a NEXT that can't name a peer (see [`crate::net::check_peer_addr`])
+/// and, with `--verify-next-reachable`, one that does not accept a
+/// connection. A bare port is taken as a node on this host.
+async fn check_next_addr(node: &Node, addr: String) -> Result<String, String> {
+    let addr = match addr.parse::<u16>() {
+        Ok(_) => node::normalize_addr(&addr),
+        Err(_) => addr,
+    };
+    crate::net::check_peer_addr(&addr)?;
+    if node.opts.verify_next_reachable
+        && let Err(e) = crate::net::probe(&addr).await
+    {
+        tracing::warn!(node = %node.port, addr = %addr, error = %e, "Refusing unreachable NEXT");
+        return Err(format!("unreachable address: {addr}"));
+    }
+    Ok(addr)
+}
+
+/// Run the checks `NODE NEXT` and `KV SET` make on every pointer and KV
+/// entry of a `RESTORE`, giving back the snapshot with its addresses as
+/// `NODE NEXT` would store them. The first entry to fail refuses the
+/// whole snapshot.
+async fn check_snapshot(node: &Node, mut snapshot: NodeSnapshot) -> Result<NodeSnapshot, String> {
+    let check = async |what: &str, addr: String| {
+        check_next_addr(node, addr)
+            .await
+            .map_err(|e| format!("{what}: {e}"))
+    };
+    if let Some(next) = snapshot.next.take() {
+        snapshot.next = Some(check("next", next).await?);
+    }
+    if let Some(prev) = snapshot.prev.take() {
+        snapshot.prev = Some(check("prev", prev).await?);
+    }
+    for (ring, next) in &mut snapshot.rings {
+        *next = check(&format!("ring {ring}"), std::mem::take(next)).await?;
+    }
+    for next in &mut snapshot.next_multi {
+        *next = check("next_multi", std::mem::take(next)).await?;
+    }
+    for (key, value) in &snapshot.kv {
+        node.check_kv_entry(key, value)
+            .map_err(|e| format!("kv {key}: {e}"))?;
+    }
+    Ok(snapshot)
+}
+
+/// Pin a hostname NEXT to the `ip:port` that answers, unless
+/// `--no-dns-resolve` is set. IP literals and `unix:` addresses are kept
+/// as given; a name that can't be resolved or reached is stored unchanged
+/// so a neighbor that comes up later is still dialled by name.
+async fn resolve_next_addr(node: &Node, addr: String) -> String {
+    if node.opts.no_dns_resolve
+        || addr.parse::<std::net::SocketAddr>().is_ok()
+        || crate::transport::unix_path(&addr).is_some()
+    {
+        return addr;
+    }
+    match crate::net::resolve_and_verify(&addr).await {
+        Ok(resolved) => {
+            tracing::info!(node = %node.port, addr = %addr, resolved = %resolved, "Resolved NEXT address");
+            resolved.to_string()
+        }
+        Err(e) => {
+            tracing::warn!(node = %node.port, addr = %addr, error = %e, "Could not resolve NEXT address; storing it unresolved");
+            addr
+        }
+    }
+}
+
+async fn handle_node_prev<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    addr: String,
+) -> Result<(), RingError> {
+    node.set_prev(addr.clone()).await;
+    writer
+        .write_all(format!("OK prev={}\n", addr).as_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn handle_node_status<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let next = node
+        .get_next()
+        .await
+        .unwrap_or_else(|| "<unset>".to_string());
+    let prev = node
+        .get_prev()
+        .await
+        .unwrap_or_else(|| "<unset>".to_string());
+    let name = if node.name().is_empty() {
+        "<unset>"
+    } else {
+        node.name()
+    };
+    let fan_out = node.next_hops().await.len();
+    let leader = match node.get_leader().await {
+        Some(addr) => format!("LEADER {addr}\n"),
+        None => String::new(),
+    };
+    writer
+        .write_all(
+            format!(
+                "PORT {}\nNAME {}\nNEXT {}\nPREV {}\nFAN_OUT {fan_out}\nGENERATION {}\nROLE {}\n{leader}OK\n",
+                node.port,
+                name,
+                next,
+                prev,
+                node.generation(),
+                node.role().await
+            )
+            .as_bytes(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles "NODE INFO": what `NODE STATUS` and `NODE STAT` say about the
+/// node, in one block. PREV is left out while unset.
+async fn handle_node_info<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let unset = |v: Option<String>| v.unwrap_or_else(|| "<unset>".to_string());
+    let counter = |key: &str| {
+        node.stat_counters()
+            .into_iter()
+            .find_map(|(k, v)| (k == key).then_some(v))
+            .unwrap_or_default()
+    };
+    let mut out = format!(
+        "PORT {}\nNEXT {}\n",
+        node.port,
+        unset(node.get_next().await)
+    );
+    if let Some(prev) = node.get_prev().await {
+        out.push_str(&format!("PREV {prev}\n"));
+    }
+    let name = if node.name().is_empty() {
+        "<unset>"
+    } else {
+        node.name()
+    };
+    out.push_str(&format!(
+        "NAME {name}\nGENERATION {}\nUPTIME_SECS {}\nROLE {}\nLEADER {}\n",
+        node.generation(),
+        counter("UPTIME_SECS"),
+        node.role().await,
+        unset(node.get_leader().await)
+    ));
+    out.push_str(&format!(
+        "RING_SIZE {}\nMSG_COUNT {}\nWALK_COUNT {}\nCONNECTIONS {}\nVERSION {}\nOK\n",
+        counter("RING_SIZE"),
+        counter("MSGS_RECEIVED"),
+        counter("WALK_COUNT"),
+        counter("CONNECTIONS"),
+        env!("CARGO_PKG_VERSION")
+    ));
+    writer.write_all(out.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle_node_ping<W: AsyncWrite + Unpin>(writer: &mut W) -> Result<(), RingError> {
+    writer.write_all(b"PONG\n").await?;
+    Ok(())
+}
+
+/// Emit per-node Prometheus-friendly counters as a list of `<key>=<value>`
+/// lines, terminated by `OK`. The gateway reads these from each ring node
+/// and aggregates into the Prometheus text format at `GET /metrics`.
 async fn handle_node_metrics<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
-    use std::sync::atomic::Ordering;
-    let (alive_nodes, dead_nodes) = node.alive_dead_counts().await;
-    let port = port_str(&node.port);
-    let lines = [
-        format!("port={}", port),
-        format!("pushes_total={}", node.pushes_total.load(Ordering::Relaxed)),
-        format!("pulls_total={}", node.pulls_total.load(Ordering::Relaxed)),
-        format!(
-            "chunk_bytes_written_total={}",
-            node.chunk_bytes_written_total.load(Ordering::Relaxed)
-        ),
-        format!(
-            "chunk_bytes_read_total={}",
-            node.chunk_bytes_read_total.load(Ordering::Relaxed)
-        ),
-        format!(
-            "netmap_broadcasts_total={}",
-            node.netmap_broadcasts.load(Ordering::Relaxed)
-        ),
-        format!("alive_nodes={}", alive_nodes),
-        format!("dead_nodes={}", dead_nodes),
-    ];
-    for l in &lines {
-        writer.write_all(l.as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-    }
+) -> Result<(), RingError> {
+    use std::sync::atomic::Ordering;
+    let (alive_nodes, dead_nodes) = node.alive_dead_counts().await;
+    let port = port_str(&node.port);
+    let lines = [
+        format!("port={}", port),
+        format!("pushes_total={}", node.pushes_total.load(Ordering::Relaxed)),
+        format!("pulls_total={}", node.pulls_total.load(Ordering::Relaxed)),
+        format!(
+            "chunk_bytes_written_total={}",
+            node.chunk_bytes_written_total.load(Ordering::Relaxed)
+        ),
+        format!(
+            "chunk_bytes_read_total={}",
+            node.chunk_bytes_read_total.load(Ordering::Relaxed)
+        ),
+        format!(
+            "netmap_broadcasts_total={}",
+            node.netmap_broadcasts.load(Ordering::Relaxed)
+        ),
+        format!("alive_nodes={}", alive_nodes),
+        format!("dead_nodes={}", dead_nodes),
+    ];
+    for l in &lines {
+        writer.write_all(l.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// Emit the `NODE STAT` runtime counters as `<KEY> <value>` lines,
+/// terminated by `OK`.
+async fn handle_node_stat<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    for (key, value) in node.stat_counters() {
+        writer
+            .write_all(format!("{key} {value}\n").as_bytes())
+            .await?;
+    }
+    writer
+        .write_all(format!("VECTOR_CLOCK {}\nOK\n", node.vector_clock().to_wire()).as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Handles "NODE CIRCUIT": one `<addr> <state> <failures>` line per peer
+/// with a failed forward on record, then `OK`. Peers never seen failing
+/// are closed and not listed.
+async fn handle_node_circuit<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    for (addr, state, failures) in node.circuit.snapshot().await {
+        writer
+            .write_all(format!("{addr} {state} {failures}\n").as_bytes())
+            .await?;
+    }
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// Handles "NODE SHUTDOWN": ack, then flag the node so the accept loop
+/// stops and the drain in `serve_with_shutdown` begins. The requesting
+/// connection closes once the ack is written.
+async fn handle_node_shutdown<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    tracing::info!(node = %node.port, "Shutdown requested over the wire");
+    writer.write_all(b"OK SHUTDOWN\n").await?;
+    node.request_shutdown();
+    Ok(())
+}
+
+/// Handles "NODE HEAL"
+/// Starts a walk that forces every node to check and heal its neighbor.
+async fn handle_node_heal<W: AsyncWrite + Unpin>(
+    node: Arc<Node>,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let token = node.make_walk_token();
+    let rx = node.register_heal_walk(&token).await;
+
+    // Spawn a task to do the first check and start the walk
+    let start_addr = node.port.clone();
+    let node_clone = Arc::clone(&node);
+    tokio::spawn(async move {
+        if let Err(e) = check_and_heal_neighbor(node_clone, &token, &start_addr).await {
+            tracing::error!(
+                node = %start_addr,
+                token = %token,
+                error = ?e,
+                "Heal walk: First check failed"
+            );
+        }
+    });
+
+    // Wait for the walk to complete (or time out)
+    let walk_timeout = Duration::from_secs(60);
+    match tokio::time::timeout(walk_timeout, rx).await {
+        Ok(Ok(())) => {
+            writer.write_all(b"OK network healed\n").await?;
+        }
+        Ok(Err(_)) => {
+            writer.write_all(b"ERR heal walk canceled\n").await?;
+        }
+        Err(_) => {
+            writer.write_all(b"ERR heal walk timed out\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles "NODE HEAL-HOP <token> <start_addr>"
+/// This is received by a node, which then checks its neighbor.
+async fn handle_node_heal_hop<W: AsyncWrite + Unpin>(
+    node: Arc<Node>,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+) -> Result<(), RingError> {
+    // 1. ACK the hop request immediately
+    writer.write_all(b"OK\n").await?;
+
+    // 2. Spawn a task to do the actual work
+    tokio::spawn(async move {
+        let node_port = node.port.clone();
+        if let Err(e) = check_and_heal_neighbor(node, &token, &start_addr).await {
+            tracing::error!(
+                node = %node_port,
+                token = %token,
+                error = ?e,
+                "Heal walk: Check/forward failed"
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Handles "NODE HEAL-DONE <token>"
+/// This is received by the start node when the walk is complete.
+async fn handle_node_heal_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+) -> Result<(), RingError> {
+    // Signal the original "handle_node_heal" waiter
+    node.finish_heal_walk(&token).await;
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// Logic for one step of the heal walk.
+/// 1. Get neighbor.
+/// 2. Check if neighbor is start. If so, send HEAL-DONE.
+/// 3. If not, ping neighbor.
+/// 4. If ping OK, forward HEAL-HOP.
+/// 5. If ping FAIL, run `handle_node_death`, then forward HEAL-HOP.
+async fn check_and_heal_neighbor(
+    node: Arc<Node>,
+    token: &str,
+    start_addr: &str,
+) -> Result<(), RingError> {
+    let Some(next_addr) = node.get_next().await else {
+        tracing::warn!(node = %node.port, "Heal walk: No next node set, stopping walk.");
+        return Ok(()); // Stop the walk
+    };
+
+    // 1. Check if the ring was completed
+    if port_str(&next_addr) == port_str(start_addr) {
+        tracing::info!(node = %node.port, token = %token, "Heal walk: Completed ring, sending DONE.");
+        let mut s = node.connect(start_addr).await?;
+        s.write_all(format!("NODE HEAL-DONE {}\n", token).as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    // 2. Node is not the start, so check its health
+    match check_node_health(node.clone(), &next_addr).await {
+        Ok(_) => {
+            // 3. Node is ALIVE -> Forward the HEAL-HOP request
+            tracing::debug!(node = %node.port, target = %next_addr, "Heal walk: Node is alive, forwarding hop.");
+            let mut s = node.connect(&next_addr).await?;
+            s.write_all(format!("NODE HEAL-HOP {} {}\n", token, start_addr).as_bytes())
+                .await?;
+        }
+        Err(e) => {
+            // 3. Node is DEAD -> Heal it, then forward
+            tracing::warn!(
+                node = %node.port,
+                target = %next_addr,
+                error = ?e,
+                "Heal walk: Node is dead, starting healing process."
+            );
+
+            // This blocks until the node is respawned and synced
+            if let Err(heal_err) = handle_node_death(node.clone(), next_addr.clone()).await {
+                tracing::error!(
+                    node = %node.port,
+                    target = %next_addr,
+                    error = ?heal_err,
+                    "Heal walk: `handle_node_death` failed. Stopping walk."
+                );
+                return Err(heal_err); // Stop the walk
+            }
+
+            // 4. Forward the HEAL-HOP to the newly respawned node
+            tracing::info!(
+                node = %node.port,
+                target = %next_addr,
+                "Heal walk: Node healed, forwarding hop."
+            );
+            let mut s = node.connect(&next_addr).await?;
+            s.write_all(format!("NODE HEAL-HOP {} {}\n", token, start_addr).as_bytes())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a ring message that has taken `hop_count` hops is past
+/// `--max-ring-hops`, reporting it as a `RING_STORM` when it is. A TTL can
+/// only bound a message that keeps it; this catches one circling a
+/// miswired ring however it got there.
+fn ring_storm(node: &Node, ring: &str, hop_count: u32) -> bool {
+    let max_hops = node.opts.max_ring_hops;
+    if max_hops == 0 || hop_count <= max_hops {
+        return false;
+    }
+    tracing::warn!(node = %node.port, ring, hop_count, max_hops, "RING_STORM detected");
+    node.publish(
+        EventKind::Ring,
+        &format!("RING_STORM hop_count={hop_count} max={max_hops}"),
+    );
+    true
+}
+
+/// Handle "RING FORWARD" (on [`DEFAULT_RING`]) and "RING ON <ring>".
+async fn handle_ring_forward<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ring: &str,
+    hop_count: u32,
+    mut ttl: u32,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ring, hop_count, ttl, msg = %msg, "RING FORWARD");
+    ack_hop(writer).await?;
+    if ring_storm(node, ring, hop_count) {
+        return Ok(());
+    }
+    if node.dedup.check(&ring_forward_line(ring, ttl, &msg)) {
+        tracing::debug!(node = %node.port, ring, ttl, "Dropping repeated RING FORWARD");
+        return Ok(());
+    }
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    node.replay.record(ReplayEntry::now(ring, ttl, &msg));
+
+    if ttl > 0 {
+        ttl -= 1;
+        let fan_out = match ring {
+            DEFAULT_RING => node.next_multi.read().await.clone(),
+            _ => Vec::new(),
+        };
+        if fan_out.len() > 1 {
+            // Every branch gets the same TTL and is sent concurrently.
+            let msg = &msg;
+            let sends = fan_out.iter().map(|target| async move {
+                let res = node
+                    .forward_ring_forward_to(target, ring, hop_count.saturating_add(1), ttl, msg)
+                    .await;
+                record_forward(node, &res);
+                if let Err(e) = res {
+                    tracing::warn!(node = %node.port, target = %target, error = ?e, "RING FORWARD fan-out hop failed");
+                }
+            });
+            futures::future::join_all(sends).await;
+        } else if let Some(next_addr) = node.get_next_on(ring).await {
+            let res = node
+                .forward_ring_forward(ring, hop_count.saturating_add(1), ttl, &msg)
+                .await;
+            record_forward(node, &res);
+            if let Err(e) = res {
+                tracing::warn!(node = %node.port, ring, target = %next_addr, error = ?e, "RING FORWARD failed");
+                // The repair coordinator only knows the default ring.
+                if ring == DEFAULT_RING {
+                    node.report_dead_next(&next_addr).await;
+                }
+            }
+        } else {
+            tracing::warn!(node = %node.port, ring, "No next node set, dropping RING FORWARD");
+        }
+    }
+
+    Ok(())
+}
+
+/// Forward a `RING TIMED` message until its expiry passes; an expired one
+/// is dropped with `OK EXPIRED`. With dedup on the message makes one lap,
+/// since its line is the same at every hop.
+async fn handle_ring_timed<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    expiry_ms: u64,
+    msg: String,
+) -> Result<(), RingError> {
+    if protocol::is_expired(expiry_ms) {
+        tracing::debug!(node = %node.port, expiry_ms, "Dropping expired RING TIMED");
+        writer.write_all(b"OK EXPIRED\n").await?;
+        return Ok(());
+    }
+    writer.write_all(b"OK\n").await?;
+    if node.dedup.check(&format!("RING TIMED {expiry_ms} {msg}")) {
+        tracing::debug!(node = %node.port, expiry_ms, "Dropping repeated RING TIMED");
+        return Ok(());
+    }
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let Some(next_addr) = node.get_next().await else {
+        tracing::warn!(node = %node.port, "No next node set, dropping RING TIMED");
+        return Ok(());
+    };
+    let res = node.forward_ring_timed(expiry_ms, &msg).await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, target = %next_addr, error = ?e, "RING TIMED failed");
+        node.report_dead_next(&next_addr).await;
+    }
+    Ok(())
+}
+
+/// Handle "ELECT START" from the client on the start node.
+///
+/// Sends this node's address around the ring as the first candidate; when
+/// it comes back the survivor is the leader. The start node records it,
+/// announces it with `ELECT LEADER` and answers `LEADER <addr>`, then `OK`.
+async fn handle_elect_start<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let Some(next_addr) = node.get_next().await else {
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    let leader = if port_str(&next_addr) == port_str(&node.port) {
+        // A ring of one elects itself.
+        node.port.clone()
+    } else {
+        // A candidate until the result is back; a failed election leaves
+        // the node as it was.
+        let previous = node.set_role(NodeRole::Candidate).await;
+        let token = node.make_walk_token();
+        let rx = node.register_walk(token.as_str()).await;
+        let res = node
+            .forward_elect_hop(DEFAULT_RING, &token, &node.port, &node.port)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            node.abort_walk(&token).await;
+            node.set_role(previous).await;
+            writer
+                .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+                .await?;
+            return Ok(());
+        }
+
+        tracing::info!(token = %token, "Election started");
+        match tokio::time::timeout(Duration::from_secs(30), rx).await {
+            Ok(Ok(Ok(candidate))) => candidate,
+            Ok(Ok(Err(WalkAborted))) => {
+                tracing::info!(token = %token, "Election aborted");
+                node.set_role(previous).await;
+                writer.write_all(b"ERR election aborted\n").await?;
+                return Ok(());
+            }
+            Ok(Err(_)) => {
+                tracing::warn!(token = %token, "Election canceled");
+                node.set_role(previous).await;
+                writer.write_all(b"ERR election canceled\n").await?;
+                return Ok(());
+            }
+            Err(_) => {
+                tracing::warn!(token = %token, "Election timed out");
+                node.set_role(previous).await;
+                writer.write_all(b"ERR election timeout\n").await?;
+                return Ok(());
+            }
+        }
+    };
+
+    tracing::info!(node = %node.port, leader = %leader, "Leader elected");
+    node.set_leader(leader.clone()).await;
+    let res = node
+        .forward_elect_leader(DEFAULT_RING, &node.port, &leader)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "ELECT LEADER forward failed");
+    }
+    writer
+        .write_all(format!("LEADER {leader}\nOK\n").as_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn handle_elect_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    candidate: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+
+    let candidate = node.elect_candidate(&candidate);
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_elect_done(&start_addr, &token, &candidate).await
+    } else {
+        node.forward_elect_hop(DEFAULT_RING, &token, &start_addr, &candidate)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            target = %next_addr,
+            error = ?e,
+            "ELECT HOP forward failed"
+        );
+    }
+    Ok(())
+}
+
+/// Record the announced leader and pass the announcement on until it is
+/// back at the node that started the election.
+async fn handle_elect_leader<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    start_addr: String,
+    leader: String,
+) -> Result<(), RingError> {
+    let _ = writer.write_all(b"OK\n").await;
+    node.set_leader(leader.clone()).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+    if port_str(&next_addr) != port_str(&start_addr) {
+        let res = node
+            .forward_elect_leader(DEFAULT_RING, &start_addr, &leader)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %next_addr,
+                error = ?e,
+                "ELECT LEADER forward failed"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Handle "BARRIER ENTER" (with this node as `start_addr` and nothing
+/// `visited`) and "BARRIER HOP": count the arrival, then pass it on until
+/// it is back at the node it arrived at. A node already on `visited`
+/// means the hop is looping short of that node, so it stops uncounted.
+async fn handle_barrier_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    barrier_id: String,
+    n: u32,
+    start_addr: String,
+    visited: String,
+) -> Result<(), RingError> {
+    let _ = writer.write_all(b"OK\n").await;
+    if visited
+        .split(';')
+        .any(|addr| port_str(addr) == port_str(&node.port))
+    {
+        tracing::warn!(node = %node.port, barrier = %barrier_id, "BARRIER HOP came round again, dropping it");
+        return Ok(());
+    }
+    let visited = if visited.is_empty() {
+        node.port.clone()
+    } else {
+        format!("{visited};{}", node.port)
+    };
+    let count = node.barrier_arrive(&barrier_id);
+    if count == n {
+        tracing::info!(node = %node.port, barrier = %barrier_id, n, "BARRIER reached");
+    }
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+    if port_str(&next_addr) != port_str(&start_addr) {
+        let res = node
+            .forward_barrier_hop(&barrier_id, n, &start_addr, &visited)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %next_addr,
+                error = ?e,
+                "BARRIER forward failed"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Handle "INCR START" from the client: count it here, send it round the
+/// ring as INCR HOP, and answer `INCR_RESULT <counter_name> <value>` and `OK`
+/// once the hop is back. The hop carries a walk token, so the answer finds this
+/// request however many others are in flight.
+async fn handle_incr<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    counter_name: String,
+) -> Result<(), RingError> {
+    let value = node.counter_incr(&counter_name);
+    let alone = match node.get_next().await {
+        Some(next) => port_str(&next) == port_str(&node.port),
+        None => true,
+    };
+    if alone {
+        writer
+            .write_all(format!("INCR_RESULT {counter_name} {value}\nOK\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let res = node
+        .forward_incr_hop(&counter_name, &token, 1, &node.port, value)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    let reply = match tokio::time::timeout(node.ring_ack_timeout(), rx).await {
+        Ok(Ok(Ok(value))) => format!("INCR_RESULT {counter_name} {value}\nOK\n"),
+        Ok(Ok(Err(WalkAborted))) => "ERR incr aborted\n".to_string(),
+        Ok(Err(_)) => "ERR incr canceled\n".to_string(),
+        Err(_) => {
+            tracing::warn!(token = %token, counter = %counter_name, "INCR timed out");
+            "ERR incr timeout\n".to_string()
+        }
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// Handle "INCR HOP": count it and pass it on with the higher of the two
+/// values. Back at `origin`, hand the value to the waiting INCR. On a ring
+/// that never gets back to `origin`, the node it reaches after
+/// `--max-walk-hops` drops it uncounted and the INCR times out.
+async fn handle_incr_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    counter_name: String,
+    token: String,
+    hop_count: u32,
+    origin: String,
+    current_value: u64,
+) -> Result<(), RingError> {
+    let _ = writer.write_all(b"OK\n").await;
+    if port_str(&origin) == port_str(&node.port) {
+        if !node.finish_walk(&token, current_value.to_string()).await {
+            tracing::debug!(node = %node.port, token = %token, "INCR HOP for no pending INCR START");
+        }
+        return Ok(());
+    }
+    let max_hops = node.opts.max_walk_hops;
+    if max_hops > 0 && hop_count > max_hops {
+        tracing::warn!(node = %node.port, token = %token, hop_count, max_hops, "Dropping INCR HOP past --max-walk-hops");
+        return Ok(());
+    }
+    let value = node.counter_incr(&counter_name).max(current_value);
+    let res = node
+        .forward_incr_hop(
+            &counter_name,
+            &token,
+            hop_count.saturating_add(1),
+            &origin,
+            value,
+        )
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "INCR HOP forward failed");
+    }
+    Ok(())
+}
+
+/// Handle "TOPOLOGY WALK-FIND" from the client: answer at once if this
+/// node holds `key` = `value`, otherwise walk the ring as TOPOLOGY
+/// FIND-HOP until a node does. The reply is `FOUND <addr>`, the edges
+/// walked to it one per line and `OK`, or `NOT FOUND` and `OK` once the
+/// walk is back here.
+async fn handle_walk_find<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    key: String,
+    value: String,
+) -> Result<(), RingError> {
+    if node.kv_get(&key).await.as_deref() == Some(value.as_str()) {
+        writer
+            .write_all(format!("FOUND {}\nOK\n", node.port).as_bytes())
+            .await?;
+        return Ok(());
+    }
+    let Some(history) = node.first_walk_history().await else {
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    if node
+        .get_next()
+        .await
+        .is_some_and(|next| port_str(&next) == port_str(&node.port))
+    {
+        writer.write_all(b"NOT FOUND\nOK\n").await?;
+        return Ok(());
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_walk_find_hop(&token, &node.port, &history, &key, &value)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, key = %key, "Walk find started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(found))) => {
+            let Some((addr, history)) = found.split_once(' ') else {
+                tracing::info!(token = %token, "Walk find found no match");
+                writer.write_all(b"NOT FOUND\nOK\n").await?;
+                return Ok(());
+            };
+            tracing::info!(token = %token, addr = %addr, "Walk find matched");
+            writer
+                .write_all(format!("FOUND {addr}\n").as_bytes())
+                .await?;
+            for seg in history.split(';').filter(|s| !s.is_empty()) {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk find aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk find canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk find timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handle "TOPOLOGY FIND-HOP": on a match, report this node and the edges
+/// walked to it straight back to the start node; otherwise pass the
+/// search on, or report no match once the next hop would close the ring
+/// or the walk reaches `--max-walk-hops`.
+async fn handle_walk_find_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    history: String,
+    key: String,
+    value: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let history = label_last_target(history, node.name());
+
+    if node.kv_get(&key).await.as_deref() == Some(value.as_str()) {
+        let res = node.send_walk_found(&start_addr, &token, &history).await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(node = %node.port, target = %start_addr, error = ?e, "TOPOLOGY FIND-FOUND send failed");
+        }
+        return Ok(());
+    }
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+    let max_hops = node.opts.max_walk_hops as usize;
+    let hops = history.split(';').filter(|s| !s.is_empty()).count();
+    let res = if port_str(&next_addr) == port_str(&start_addr) || (max_hops > 0 && hops >= max_hops)
+    {
+        node.send_walk_not_found(&start_addr, &token).await
+    } else {
+        let history = append_named_edge(history, node.name(), &node.port, &next_addr);
+        node.forward_walk_find_hop(&token, &start_addr, &history, &key, &value)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, target = %next_addr, error = ?e, "TOPOLOGY FIND-HOP forward failed");
+    }
+    Ok(())
+}
+
+/// Handle "TOPOLOGY WALK-PARALLEL": `PING` and `NODE STATUS` every member
+/// the heartbeat coordinator lists, all at once, and answer `OK <json>`
+/// with the adjacency list of those that replied within
+/// `--parallel-walk-timeout-ms`. The rest are listed as unreachable and the
+/// result is marked partial.
+async fn handle_walk_parallel<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let members = match node.members().await {
+        Ok(members) => members,
+        Err(e) => return handle_error(writer, e.to_string()).await,
+    };
+    let deadline = tokio::time::Instant::now() + node.parallel_walk_timeout();
+    let mut probes = tokio::task::JoinSet::new();
+    for member in &members {
+        let client = crate::client::RingClient::with_opts(member.addr.as_str(), node.client_opts());
+        let addr = member.addr.clone();
+        probes.spawn(async move {
+            let status = match client.ping().await {
+                Ok(()) => client.get().await,
+                Err(e) => Err(e),
+            };
+            (addr, status)
+        });
+    }
+
+    let mut nodes = Vec::new();
+    loop {
+        match tokio::time::timeout_at(deadline, probes.join_next()).await {
+            Ok(Some(Ok((addr, Ok(info))))) => nodes.push(crate::topology::TopologyNode {
+                id: addr,
+                name: info.name,
+                next: info.next.into_iter().collect(),
+            }),
+            Ok(Some(Ok((addr, Err(e))))) => {
+                tracing::warn!(node = %node.port, member = %addr, error = ?e, "TOPOLOGY WALK-PARALLEL probe failed");
+            }
+            Ok(Some(Err(e))) => {
+                tracing::warn!(node = %node.port, error = ?e, "TOPOLOGY WALK-PARALLEL probe panicked");
+            }
+            Ok(None) => break,
+            Err(_) => {
+                tracing::warn!(node = %node.port, pending = probes.len(), "TOPOLOGY WALK-PARALLEL timed out");
+                probes.abort_all();
+                break;
+            }
+        }
+    }
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    let unreachable: Vec<String> = members
+        .into_iter()
+        .map(|m| m.addr)
+        .filter(|addr| !nodes.iter().any(|n| &n.id == addr))
+        .collect();
+    let topology = crate::topology::ParallelTopology {
+        partial: !unreachable.is_empty(),
+        nodes,
+        unreachable,
+    };
+    let json = serde_json::to_string(&topology).expect("topology serializes");
+    writer.write_all(format!("OK {json}\n").as_bytes()).await?;
+    Ok(())
+}
+
+/// Handle "SPLIT-BRAIN CHECK" from the client: answer `OK leader=<addr>`
+/// when every node that has a leader names the same one (`<unset>` when
+/// none does), or `SPLIT_BRAIN detected leaders=<list>` when they differ.
+async fn handle_split_brain_check<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let reply = match check_split_brain(node, Duration::from_secs(30)).await {
+        Ok(leaders) => match leaders.as_slice() {
+            [] => "OK leader=<unset>\n".to_string(),
+            [leader] => format!("OK leader={leader}\n"),
+            many => format!("SPLIT_BRAIN detected leaders={}\n", many.join(",")),
+        },
+        Err(reason) => format!("ERR {reason}\n"),
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// Handle "SPLIT-BRAIN HOP": add this node's leader and pass the walk on,
+/// or hand the list back to the start node once the ring closes. A node
+/// already on the list means the walk is looping short of the start
+/// node, so it ends there too.
+async fn handle_split_brain_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    leaders: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+    let looped = leaders
+        .split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .any(|(addr, _)| port_str(addr) == port_str(&node.port));
+    let leaders = if looped {
+        leaders
+    } else {
+        node.append_split_brain_leader(leaders).await
+    };
+    let res = if looped || port_str(&next_addr) == port_str(&start_addr) {
+        node.send_split_brain_done(&start_addr, &token, &leaders)
+            .await
+    } else {
+        node.forward_split_brain_hop(&token, &start_addr, &leaders)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, target = %next_addr, error = ?e, "SPLIT-BRAIN HOP forward failed");
+    }
+    Ok(())
+}
+
+/// Handle "RING KEY" from the client: deliver the message here when this
+/// node owns `key` on its hash ring, else relay it to the owner as
+/// `RING KEY-HOP`. Either way the client gets `KEY_ACK <key>`.
+async fn handle_ring_key<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    key: String,
+    msg: String,
+) -> Result<(), RingError> {
+    if node.opts.routing != RoutingMode::ConsistentHash {
+        writer
+            .write_all(b"ERR RING KEY needs --routing consistent-hash\n")
+            .await?;
+        return Ok(());
+    }
+    let owner = node.hash_ring_owner(&key);
+    let reply = if owner == node.port {
+        deliver_ring_key(node, &key, &msg);
+        format!("KEY_ACK {key}\n")
+    } else {
+        tracing::debug!(node = %node.port, key = %key, owner = %owner, "RING KEY relayed");
+        match node.forward_ring_key(&owner, &key, &msg).await {
+            Ok(ack) => format!("{ack}\n"),
+            Err(e) => {
+                tracing::warn!(node = %node.port, target = %owner, error = ?e, "RING KEY relay failed");
+                format!("ERR relay to {owner} failed: {e}\n")
+            }
+        }
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// A `RING KEY` message has reached its owner: count it like a delivered
+/// `RING FORWARD` and tell subscribers.
+fn deliver_ring_key(node: &Node, key: &str, msg: &str) {
+    tracing::info!(node = %node.port, key = %key, msg = %msg, "RING KEY delivered");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    node.publish(EventKind::Ring, &format!("KEY {key} {msg}"));
+}
+
+/// Handle "NODE FORWARD-TO": send `command` to `target` with this command's
+/// `CID:` and write back the reply lines, or `ERR` when `target` could
+/// not be asked. Commands without a closing reply line cannot be relayed.
+async fn handle_forward_to<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    target: String,
+    command: String,
+) -> Result<(), RingError> {
+    let source = PEER_ADDR
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| "-".to_string());
+    let hops = forward_chain_len(&command);
+    let max_hops = node.opts.forward_max_hops;
+    if max_hops > 0 && hops > max_hops {
+        tracing::warn!(node = %node.port, source = %source, target = %target, hops, max_hops, "NODE FORWARD-TO chain refused");
+        let reply = format!("ERR forward chain of {hops} hops exceeds max {max_hops}\n");
+        writer.write_all(reply.as_bytes()).await?;
+        return Ok(());
+    }
+    let noun = command
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    if crate::repl::UNSUPPORTED.contains(&noun.as_str()) || noun == "MUX" {
+        let reply = format!("ERR cannot forward {noun}: its reply has no closing line\n");
+        writer.write_all(reply.as_bytes()).await?;
+        return Ok(());
+    }
+
+    tracing::info!(node = %node.port, source = %source, target = %target, cmd = %command_label(&command), "NODE FORWARD-TO proxying");
+    let reply = match node.relay_command(&target, &command).await {
+        Ok(lines) => lines.iter().map(|l| format!("{l}\n")).collect(),
+        Err(RingError::Remote { msg, .. }) => format!("ERR {msg}\n"),
+        Err(e) => {
+            tracing::warn!(node = %node.port, source = %source, target = %target, error = ?e, "NODE FORWARD-TO failed");
+            format!("ERR forward to {target} failed: {e}\n")
+        }
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// How many nodes a `NODE FORWARD-TO` of `command` passes through: one, plus
+/// one for each `NODE FORWARD-TO` nested in it.
+fn forward_chain_len(command: &str) -> u32 {
+    let mut hops = 1;
+    let mut line = command.to_string();
+    while let Ok(protocol::Command::ForwardTo { command, .. }) = protocol::parse_line(&line) {
+        hops += 1;
+        line = command;
+    }
+    hops
+}
+
+/// Walk the default ring from this node collecting each node's leader,
+/// and return the distinct leaders named, sorted. More than one is logged
+/// as an error.
+async fn check_split_brain(node: &Node, timeout: Duration) -> Result<Vec<String>, String> {
+    let Some(next_addr) = node.get_next().await else {
+        return Err("no next hop set".into());
+    };
+    let leaders = node.append_split_brain_leader(String::new()).await;
+    let leaders = if port_str(&next_addr) == port_str(&node.port) {
+        // A ring of one: nothing to walk.
+        leaders
+    } else {
+        let token = node.make_walk_token();
+        let rx = node.register_walk(token.as_str()).await;
+        node.walks_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let res = node
+            .forward_split_brain_hop(&token, &node.port, &leaders)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            node.abort_walk(&token).await;
+            return Err(format!("forward failed: {e}"));
+        }
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(Ok(leaders))) => leaders,
+            Ok(Ok(Err(WalkAborted))) => return Err("walk aborted".into()),
+            Ok(Err(_)) => return Err("walk canceled".into()),
+            Err(_) => {
+                node.abort_walk(&token).await;
+                return Err("walk timeout".into());
+            }
+        }
+    };
+
+    let mut distinct: Vec<String> = leaders
+        .split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(_, leader)| leader)
+        .filter(|leader| *leader != "<unset>")
+        .map(str::to_string)
+        .collect();
+    distinct.sort();
+    distinct.dedup();
+    if distinct.len() > 1 {
+        tracing::error!(node = %node.port, leaders = %distinct.join(","), "SPLIT_BRAIN detected");
+    }
+    Ok(distinct)
+}
+
+/// Handle "BARRIER WAIT": reply `BARRIER DONE <barrier_id>` once this node
+/// has counted `n` arrivals, or `ERR` after [`BARRIER_WAIT_TIMEOUT`].
+async fn handle_barrier_wait<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    barrier_id: String,
+    n: u32,
+) -> Result<(), RingError> {
+    let reply = match node
+        .barrier_wait(&barrier_id, n, BARRIER_WAIT_TIMEOUT)
+        .await
+    {
+        Ok(()) => format!("{}\n", protocol::Command::BarrierDone { barrier_id }),
+        Err(count) => format!(
+            "ERR barrier {barrier_id} timed out at {count}/{n} after {}s\n",
+            BARRIER_WAIT_TIMEOUT.as_secs()
+        ),
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// Handle "RING REQ": forward like RING FORWARD, then send this node's
+/// `on_ring_req` answer, if any, to the callback address.
+async fn handle_ring_req<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    callback_addr: String,
+    ttl: u32,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING REQ");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    ack_hop(writer).await?;
+
+    if ttl > 0 {
+        let res = node
+            .forward_ring_req(DEFAULT_RING, &callback_addr, ttl - 1, &msg)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(node = %node.port, error = ?e, "RING REQ forward failed");
+        }
+    }
+
+    if let Some(payload) = node.on_ring_req(&msg).await
+        && let Err(e) = node.send_ring_resp(&callback_addr, &payload).await
+    {
+        node.errors_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        tracing::warn!(
+            node = %node.port,
+            callback = %callback_addr,
+            error = ?e,
+            "RING RESP send failed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Handle "RING ACK" from the client on the start node: the RING FORWARD
+/// TTL walk, but the start node waits for the list of nodes that saw the
+/// message and answers with one address per line, then `OK`.
+async fn handle_ring_ack<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ttl: u32,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING ACK");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let seen = node.port.clone();
+    if ttl == 0 || node.get_next().await.is_none() {
+        writer.write_all(format!("{seen}\nOK\n").as_bytes()).await?;
+        return Ok(());
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let res = node
+        .forward_ring_ack_hop(DEFAULT_RING, &token, &node.port, ttl - 1, &seen, &msg)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, ttl, "Ring ack started");
+    match tokio::time::timeout(node.ring_ack_timeout(), rx).await {
+        Ok(Ok(Ok(seen))) => {
+            tracing::info!(token = %token, seen = %seen, "Ring ack finished");
+            for addr in seen.split(';').filter(|s| !s.is_empty()) {
+                writer.write_all(format!("{addr}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Ring ack aborted");
+            writer.write_all(b"ERR ring ack aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Ring ack canceled");
+            writer.write_all(b"ERR ring ack canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Ring ack timed out");
+            writer.write_all(b"ERR ring ack timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Wide-by-design: one argument per field of the ACK-HOP line.
+#[allow(clippy::too_many_arguments)]
+async fn handle_ring_ack_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    ttl: u32,
+    seen: String,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING ACK-HOP");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let _ = ack_hop(writer).await;
+
+    let seen = format!("{seen};{}", node.port);
+    let res = if ttl == 0 || node.get_next().await.is_none() {
+        node.send_ring_ack_done(&start_addr, &token, &seen).await
+    } else {
+        node.forward_ring_ack_hop(DEFAULT_RING, &token, &start_addr, ttl - 1, &seen, &msg)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "RING ACK-HOP forward failed");
+    }
+
+    Ok(())
+}
+
+async fn handle_ring_ack_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    seen: Vec<String>,
+) -> Result<(), RingError> {
+    let _ = node.finish_walk(&token, seen.join(";")).await;
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+/// Handle "RING COLLECT" from the client on the start node: the RING ACK
+/// walk, but each node appends `<addr>=<value>` for KV `key`. The walk
+/// also stops where it would wrap back to this node. The reply is
+/// `COLLECT_RESULT`, one `<addr>=<value>` line per node starting with this
+/// one, then `OK`.
+async fn handle_ring_collect<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ttl: u32,
+    key: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, key = %key, "RING COLLECT");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let results = node.append_collect_result(String::new(), &key).await;
+    let last = match node.get_next().await {
+        Some(next) => ttl == 0 || port_str(&next) == port_str(&node.port),
+        None => true,
+    };
+    if last {
+        writer
+            .write_all(format!("COLLECT_RESULT\n{results}\nOK\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let res = node
+        .forward_ring_collect_hop(&token, &node.port, ttl - 1, &key, &results)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, ttl, key = %key, "Ring collect started");
+    match tokio::time::timeout(node.ring_ack_timeout(), rx).await {
+        Ok(Ok(Ok(results))) => {
+            let entries: Vec<&str> = results.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, nodes = entries.len(), "Ring collect finished");
+            writer.write_all(b"COLLECT_RESULT\n").await?;
+            for entry in entries {
+                writer.write_all(format!("{entry}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Ring collect aborted");
+            writer.write_all(b"ERR ring collect aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Ring collect canceled");
+            writer.write_all(b"ERR ring collect canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Ring collect timed out");
+            writer.write_all(b"ERR ring collect timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Wide-by-design: one argument per field of the COLLECT-HOP line.
+#[allow(clippy::too_many_arguments)]
+async fn handle_ring_collect_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    ttl: u32,
+    key: String,
+    results: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, key = %key, "RING COLLECT-HOP");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let _ = ack_hop(writer).await;
+
+    let results = node.append_collect_result(results, &key).await;
+    let last = match node.get_next().await {
+        Some(next) => ttl == 0 || port_str(&next) == port_str(&start_addr),
+        None => true,
+    };
+    let res = if last {
+        node.send_ring_collect_done(&start_addr, &token, &results)
+            .await
+    } else {
+        node.forward_ring_collect_hop(&token, &start_addr, ttl - 1, &key, &results)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "RING COLLECT-HOP forward failed");
+    }
+
+    Ok(())
+}
+
+/// Handle "RING ECHO" from the client on the start node: the RING FORWARD
+/// TTL walk, but the last hop sends the message back and the start node
+/// answers `ECHO <message>`, then `OK`.
+async fn handle_ring_echo<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ttl: u32,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING ECHO");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    if ttl == 0 || node.get_next().await.is_none() {
+        writer
+            .write_all(format!("ECHO {msg}\nOK\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let res = node
+        .forward_ring_echo_hop(DEFAULT_RING, &token, &node.port, ttl - 1, &msg)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, ttl, "Ring echo started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(echo))) => {
+            tracing::info!(token = %token, "Ring echo finished");
+            writer
+                .write_all(format!("ECHO {echo}\nOK\n").as_bytes())
+                .await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Ring echo aborted");
+            writer.write_all(b"ERR ring echo aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Ring echo canceled");
+            writer.write_all(b"ERR ring echo canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Ring echo timed out");
+            writer.write_all(b"ERR ring echo timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_ring_echo_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    ttl: u32,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING ECHO-HOP");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let _ = ack_hop(writer).await;
+
+    let res = if ttl == 0 || node.get_next().await.is_none() {
+        node.send_ring_echo_done(&start_addr, &token, &msg).await
+    } else {
+        node.forward_ring_echo_hop(DEFAULT_RING, &token, &start_addr, ttl - 1, &msg)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "RING ECHO-HOP forward failed");
+    }
+
+    Ok(())
+}
+
+async fn handle_ring_echo_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    msg: String,
+) -> Result<(), RingError> {
+    let _ = node.finish_walk(&token, msg).await;
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+/// Handle "RING ONCE" from the client on the start node: the message goes
+/// once round the ring, however long it is, and the node answers
+/// `OK DONE` when the hop before it reports back.
+async fn handle_ring_once<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::debug!(node = %node.port, msg = %msg, "RING ONCE");
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    match node.get_next().await {
+        Some(next) if port_str(&next) != port_str(&node.port) => {}
+        _ => {
+            writer.write_all(b"OK DONE\n").await?;
+            return Ok(());
+        }
+    }
+
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let res = node
+        .forward_ring_once_hop(&token, 1, &node.port, &msg)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, "Ring once started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(_))) => {
+            tracing::info!(token = %token, "Ring once finished");
+            writer.write_all(b"OK DONE\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Ring once aborted");
+            writer.write_all(b"ERR ring once aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Ring once canceled");
+            writer.write_all(b"ERR ring once canceled\n").await?;
+        }
+        Err(_) => {
+            node.abort_walk(&token).await;
+            tracing::warn!(token = %token, "Ring once timed out");
+            writer.write_all(b"ERR ring once timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle "RING ONCE-HOP": deliver the message and pass it on until the
+/// lap is done. A ring that never comes back to the start node is caught
+/// by `--max-ring-hops`, as for `RING FORWARD`.
+async fn handle_ring_once_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    hop_count: u32,
+    start_addr: String,
+    msg: String,
+) -> Result<(), RingError> {
+    tracing::info!(node = %node.port, token = %token, hop_count, msg = %msg, "RING ONCE-HOP");
+    let _ = ack_hop(writer).await;
+    if ring_storm(node, DEFAULT_RING, hop_count) {
+        return Ok(());
+    }
+    node.ring_msgs_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    // The lap ends at the node whose NEXT is the start node. A hop that
+    // reaches the start node itself (its NEXT was written differently)
+    // ends it there.
+    let res = if port_str(&start_addr) == port_str(&node.port) {
+        node.finish_walk(&token, String::new()).await;
+        Ok(())
+    } else {
+        match node.get_next().await {
+            Some(next) if port_str(&next) != port_str(&start_addr) => {
+                node.forward_ring_once_hop(&token, hop_count.saturating_add(1), &start_addr, &msg)
+                    .await
+            }
+            _ => node.send_ring_once_done(&start_addr, &token).await,
+        }
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, error = ?e, "RING ONCE-HOP forward failed");
+    }
+
+    Ok(())
+}
+
+async fn handle_ring_once_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+) -> Result<(), RingError> {
+    let _ = node.finish_walk(&token, String::new()).await;
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+/// Handle "TOPOLOGY WALK" (or "TOPOLOGY WALK-FRESH", `fresh`) from the
+/// client on the start node. With `--walk-cache-ttl-secs` set, a recent
+/// result is replayed under a `CACHED <age_ms>` line instead of walking.
+async fn handle_topology_walk<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    fresh: bool,
+) -> Result<(), RingError> {
+    if !fresh && let Some((age, history)) = node.cached_walk().await {
+        node.walk_cache_hits
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        writer
+            .write_all(format!("CACHED {}\n", age.as_millis()).as_bytes())
+            .await?;
+        for seg in history.split(';').filter(|s| !s.is_empty()) {
+            writer.write_all(format!("{seg}\n").as_bytes()).await?;
+        }
+        writer.write_all(b"OK\n").await?;
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_walk_history().await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    // Echo the token so the client can `TOPOLOGY ABORT` this walk.
+    writer
+        .write_all(format!("TOKEN {token}\n").as_bytes())
+        .await?;
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_topology_hop(DEFAULT_RING, &token, &node.port, &history, &node.port, None)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(final_history))) => {
+            let segs: Vec<&str> = final_history.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, edges = segs.len(), "Walk finished");
+            node.metrics.observe_walk(started.elapsed());
+            node.cache_walk(&final_history).await;
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle "TOPOLOGY WALK-META <key>" from the client on the start node: a
+/// fresh walk whose endpoints carry each node's KV value for `key`. The
+/// result is neither cached nor kept as the topology.
+async fn handle_topology_walk_meta<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    key: String,
+) -> Result<(), RingError> {
+    let started = Instant::now();
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_walk_meta_history(&key).await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    writer
+        .write_all(format!("TOKEN {token}\n").as_bytes())
+        .await?;
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_topology_hop(
+            DEFAULT_RING,
+            &token,
+            &node.port,
+            &history,
+            &node.port,
+            Some(&key),
+        )
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(final_history))) => {
+            node.metrics.observe_walk(started.elapsed());
+            for seg in final_history.split(';').filter(|s| !s.is_empty()) {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => writer.write_all(b"ERR walk aborted\n").await?,
+        Ok(Err(_)) => writer.write_all(b"ERR walk canceled\n").await?,
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handle "TOPOLOGY ABORT <token>" on the walk's start node.
+async fn handle_topology_abort<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+) -> Result<(), RingError> {
+    if node.abort_walk(&token).await {
+        writer.write_all(b"OK\n").await?;
+    } else {
+        writer.write_all(b"ERR unknown walk token\n").await?;
+    }
+    Ok(())
+}
+
+async fn handle_topology_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    history: String,
+    mut visited: Vec<String>,
+    meta_key: Option<String>,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+
+    let history = label_last_target(history, node.name());
+    let meta_value = match &meta_key {
+        Some(key) => Some(node.walk_meta_value(key).await),
+        None => None,
+    };
+    let history = match &meta_value {
+        Some(value) => annotate_last_target(history, value),
+        None => history,
+    };
+
+    // Seen this walk before: the NEXT pointers loop without passing the
+    // start node again. Report what was walked instead of circling until
+    // the start node times out.
+    if visited.contains(&node.port) {
+        tracing::warn!(
+            node = %node.port,
+            token = %token,
+            "TOPOLOGY HOP revisited a node; ending walk with a partial history"
+        );
+        let res = node
+            .send_topology_done(&start_addr, &token, &history, meta_key.as_deref())
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %start_addr,
+                error = ?e,
+                "TOPOLOGY DONE send failed"
+            );
+        }
+        return Ok(());
+    }
+    // `visited` holds one node per edge walked so far.
+    let max_hops = node.opts.max_walk_hops as usize;
+    if max_hops > 0 && visited.len() >= max_hops {
+        tracing::warn!(
+            node = %node.port,
+            token = %token,
+            max_hops,
+            "TOPOLOGY HOP reached --max-walk-hops; ending walk with a partial history"
+        );
+        let res = node
+            .send_topology_done(&start_addr, &token, &history, meta_key.as_deref())
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %start_addr,
+                error = ?e,
+                "TOPOLOGY DONE send failed"
+            );
+        }
+        return Ok(());
+    }
+    visited.push(node.port.clone());
+
+    let new_history = match &meta_value {
+        Some(value) => append_meta_edge(history, node.name(), &node.port, value, &next_addr),
+        None => append_named_edge(history, node.name(), &node.port, &next_addr),
+    };
+
+    if port_str(&next_addr) == port_str(&start_addr) {
+        let res = node
+            .send_topology_done(&start_addr, &token, &new_history, meta_key.as_deref())
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %start_addr,
+                error = ?e,
+                "TOPOLOGY DONE send failed"
+            );
+        }
+    } else {
+        let res = node
+            .forward_topology_hop(
+                DEFAULT_RING,
+                &token,
+                &start_addr,
+                &new_history,
+                &visited.join(","),
+                meta_key.as_deref(),
+            )
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %next_addr,
+                error = ?e,
+                "TOPOLOGY HOP forward failed"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_topology_done<W: AsyncWrite + Unpin>(
+    node: Arc<Node>,
+    writer: &mut W,
+    token: String,
+    history: String,
+    meta_key: Option<String>,
+) -> Result<(), RingError> {
+    // The start node closes the ring, so it labels the final target.
+    let history = label_last_target(history, node.name());
+
+    // An annotated walk only answers its client.
+    if let Some(key) = meta_key {
+        let history = annotate_last_target(history, &node.walk_meta_value(&key).await);
+        let _ = node.finish_walk(&token, history).await;
+        let _ = writer.write_all(b"OK\n").await;
+        return Ok(());
+    }
+
+    // Finish the client walk if we are the start node
+    let _ = node.finish_walk(&token, history.clone()).await;
+
+    // Persist and broadcast the completed topology
+    node.set_topology_from_history(&history).await;
+    note_ring_size(
+        &node,
+        history.split(';').filter(|s| !s.is_empty()).count() as u64,
+    );
+
+    let node_clone = Arc::clone(&node);
+    tokio::spawn(async move {
+        node_clone.broadcast_topology_set().await;
+    });
+
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+async fn handle_topology_set<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    history: String,
+) -> Result<(), RingError> {
+    node.set_topology_from_history(&history).await;
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// Handle "TOPOLOGY REVERSE-WALK" from the client on the start node.
+///
+/// Mirrors `handle_topology_walk` but follows PREV pointers, so an
+/// operator can still enumerate the ring when a forward link is broken.
+async fn handle_topology_reverse_walk<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_reverse_walk_history().await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no prev hop set\n").await?;
+        return Ok(());
+    };
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_topology_reverse_hop(&token, &node.port, &history)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(final_history))) => {
+            let segs: Vec<&str> = final_history.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, edges = segs.len(), "Walk finished");
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle "TOPOLOGY BIDIR-WALK" from the client on the start node.
+///
+/// Starts a forward walk as `<token>-next` and a reverse walk as
+/// `<token>-prev`, waits for both DONEs on their own oneshots, and answers
+/// with the merged undirected graph (see [`crate::topology::merge_bidir`]),
+/// then `OK`.
+async fn handle_topology_bidir_walk<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+) -> Result<(), RingError> {
+    let token = node.make_walk_token();
+    let (next_token, prev_token) = (format!("{token}-next"), format!("{token}-prev"));
+    let next_rx = node.register_walk(next_token.as_str()).await;
+    let prev_rx = node.register_walk(prev_token.as_str()).await;
+    let abort = || async {
+        node.abort_walk(&next_token).await;
+        node.abort_walk(&prev_token).await;
+    };
+
+    let (next_history, prev_history) = match (
+        node.first_walk_history().await,
+        node.first_reverse_walk_history().await,
+    ) {
+        (Some(next_history), Some(prev_history)) => (next_history, prev_history),
+        (next_history, prev_history) => {
+            abort().await;
+            let missing = match (next_history, prev_history) {
+                (None, None) => "next or prev",
+                (None, _) => "next",
+                _ => "prev",
+            };
+            writer
+                .write_all(format!("ERR no {missing} hop set\n").as_bytes())
+                .await?;
+            return Ok(());
+        }
+    };
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_topology_hop(
+            DEFAULT_RING,
+            &next_token,
+            &node.port,
+            &next_history,
+            &node.port,
+            None,
+        )
+        .await;
+    record_forward(node, &res);
+    let res = match res {
+        Ok(()) => {
+            let res = node
+                .forward_topology_reverse_hop(&prev_token, &node.port, &prev_history)
+                .await;
+            record_forward(node, &res);
+            res
+        }
+        err => err,
+    };
+    if let Err(e) = res {
+        abort().await;
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, "Bidirectional walk started");
+    let both = async { tokio::join!(next_rx, prev_rx) };
+    let (next, prev) = match tokio::time::timeout(Duration::from_secs(30), both).await {
+        Ok((Ok(Ok(next)), Ok(Ok(prev)))) => (next, prev),
+        Ok((Ok(Err(WalkAborted)), _) | (_, Ok(Err(WalkAborted)))) => {
+            abort().await;
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+            return Ok(());
+        }
+        Ok(_) => {
+            abort().await;
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+            return Ok(());
+        }
+        Err(_) => {
+            abort().await;
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+            return Ok(());
+        }
+    };
+    match crate::topology::merge_bidir(&next, &prev) {
+        Ok(merged) => {
+            tracing::info!(token = %token, edges = merged.edges.len(), symmetric = merged.symmetric, "Bidirectional walk finished");
+            writer.write_all(merged.to_dot().as_bytes()).await?;
+            writer.write_all(b"OK\n").await?;
+        }
+        Err(e) => {
+            writer
+                .write_all(format!("ERR bad walk history: {e}\n").as_bytes())
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_topology_reverse_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    history: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(prev_addr) = node.get_prev().await else {
+        return Ok(());
+    };
+
+    let history = label_last_target(history, node.name());
+    let new_history = append_named_edge(history, node.name(), &node.port, &prev_addr);
+
+    let res = if port_str(&prev_addr) == port_str(&start_addr) {
+        node.send_topology_reverse_done(&start_addr, &token, &new_history)
+            .await
+    } else {
+        node.forward_topology_reverse_hop(&token, &start_addr, &new_history)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            target = %prev_addr,
+            error = ?e,
+            "TOPOLOGY REVERSE-HOP forward failed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Handle "TOPOLOGY PAY-WALK <key>" from the client on the start node.
+///
+/// A forward walk that also gathers every node's value for `payload_key`.
+/// The reply lists the edges as for `TOPOLOGY WALK`, then one
+/// `PAY <port>=<value>` line per node, then `OK`.
+async fn handle_walk_pay_start<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    payload_key: String,
+) -> Result<(), RingError> {
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_walk_history().await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    writer
+        .write_all(format!("TOKEN {token}\n").as_bytes())
+        .await?;
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let payloads = node.append_walk_payload(String::new(), &payload_key).await;
+    let res = node
+        .forward_walk_pay_hop(
+            DEFAULT_RING,
+            &token,
+            &node.port,
+            &payload_key,
+            &history,
+            &payloads,
+        )
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, key = %payload_key, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(result))) => {
+            // `handle_walk_pay_done` hands over "<history> <payloads>".
+            let (history, payloads) = result.split_once(' ').unwrap_or((&result, ""));
+            let segs: Vec<&str> = history.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, edges = segs.len(), "Walk finished");
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            for pay in payloads.split(';').filter(|s| !s.is_empty()) {
+                writer.write_all(format!("PAY {pay}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_walk_pay_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    start_addr: String,
+    payload_key: String,
+    history: String,
+    payloads: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+
+    let history = label_last_target(history, node.name());
+    let new_history = append_named_edge(history, node.name(), &node.port, &next_addr);
+    let payloads = node.append_walk_payload(payloads, &payload_key).await;
+
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_walk_pay_done(&start_addr, &token, &new_history, &payloads)
+            .await
+    } else {
+        node.forward_walk_pay_hop(
+            DEFAULT_RING,
+            &token,
+            &start_addr,
+            &payload_key,
+            &new_history,
+            &payloads,
+        )
+        .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            target = %next_addr,
+            error = ?e,
+            "TOPOLOGY PAY-HOP forward failed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Like the reverse walk, a payload walk only reports back to the client;
+/// the history is not persisted as the ring's topology.
+async fn handle_walk_pay_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    history: String,
+    payloads: String,
+) -> Result<(), RingError> {
+    let history = label_last_target(history, node.name());
+    let _ = node
+        .finish_walk(&token, format!("{history} {payloads}"))
+        .await;
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// Handle "TOPOLOGY WALK-MAX <max_hops>" from the client on the start node.
+///
+/// A forward walk that reports at most `max_hops` edges. If the ring is
+/// longer, the reply starts with `WALK_TRUNCATED`; the edges and `OK`
+/// follow either way.
+async fn handle_walk_max<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    mut max_hops: u32,
+) -> Result<(), RingError> {
+    if node.opts.max_walk_hops > 0 {
+        max_hops = max_hops.min(node.opts.max_walk_hops);
+    }
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_walk_history().await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    writer
+        .write_all(format!("TOKEN {token}\n").as_bytes())
+        .await?;
+
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_walk_max_hop(DEFAULT_RING, &token, 1, max_hops, &node.port, &history)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, max_hops, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(result))) => {
+            // `handle_walk_max_done` packs this as "<status> <history>".
+            let (status, final_history) = result.split_once(' ').unwrap_or((&result, ""));
+            let segs: Vec<&str> = final_history.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, edges = segs.len(), status, "Walk finished");
+            if status == "TRUNCATED" {
+                writer.write_all(b"WALK_TRUNCATED\n").await?;
+            }
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_walk_max_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    hop_count: u32,
+    max_hops: u32,
+    start_addr: String,
+    history: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
+
+    let history = label_last_target(history, node.name());
+
+    // Back at the start (a ring of one) or out of hops: report what was
+    // walked so far.
+    let done = if port_str(&node.port) == port_str(&start_addr) {
+        Some((false, history))
+    } else if hop_count >= max_hops {
+        Some((true, history))
+    } else {
+        let new_history = append_named_edge(history, node.name(), &node.port, &next_addr);
+        if port_str(&next_addr) == port_str(&start_addr) {
+            Some((false, new_history))
+        } else {
+            let res = node
+                .forward_walk_max_hop(
+                    DEFAULT_RING,
+                    &token,
+                    hop_count + 1,
+                    max_hops,
+                    &start_addr,
+                    &new_history,
+                )
+                .await;
+            record_forward(node, &res);
+            if let Err(e) = res {
+                tracing::warn!(
+                    node = %node.port,
+                    target = %next_addr,
+                    error = ?e,
+                    "TOPOLOGY MAX-HOP forward failed"
+                );
+            }
+            None
+        }
+    };
+
+    if let Some((truncated, history)) = done {
+        let res = node
+            .send_walk_max_done(&start_addr, &token, truncated, &history)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %start_addr,
+                error = ?e,
+                "TOPOLOGY MAX-DONE send failed"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_walk_max_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    truncated: bool,
+    history: String,
+) -> Result<(), RingError> {
+    // Only a walk that closed the ring ends on the start node.
+    let history = if truncated {
+        history
+    } else {
+        label_last_target(history, node.name())
+    };
+    let status = if truncated { "TRUNCATED" } else { "COMPLETE" };
+    let _ = node
+        .finish_walk(&token, format!("{status} {history}"))
+        .await;
     writer.write_all(b"OK\n").await?;
     Ok(())
 }
 
-/// Handles "NODE HEAL"
-/// Starts a walk that forces every node to check and heal its neighbor.
-async fn handle_node_heal<W: AsyncWrite + Unpin>(
-    node: Arc<Node>,
+/// Handle "TOPOLOGY WALK-SAMPLE <probability>" from the client on the start
+/// node.
+///
+/// A forward walk on which every node, this one included, records its
+/// edge with `probability` and otherwise leaves a `...`. The reply is
+/// `SAMPLED total_hops=<n> recorded=<m>`, the history one segment per
+/// line, then `OK`.
+async fn handle_walk_sample<W: AsyncWrite + Unpin>(
+    node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
+    probability: Probability,
+) -> Result<(), RingError> {
     let token = node.make_walk_token();
-    let rx = node.register_heal_walk(&token).await;
+    let rx = node.register_walk(token.as_str()).await;
 
-    // Spawn a task to do the first check and start the walk
-    let start_addr = node.port.clone();
-    let node_clone = Arc::clone(&node);
-    tokio::spawn(async move {
-        if let Err(e) = check_and_heal_neighbor(node_clone, &token, &start_addr).await {
-            tracing::error!(
-                node = %start_addr,
-                token = %token,
-                error = ?e,
-                "Heal walk: First check failed"
-            );
-        }
-    });
+    let Some(edge) = node.first_walk_history().await else {
+        node.abort_walk(&token).await;
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    let history = if probability.flip() {
+        edge
+    } else {
+        append_skipped(String::new())
+    };
+    writer
+        .write_all(format!("TOKEN {token}\n").as_bytes())
+        .await?;
 
-    // Wait for the walk to complete (or time out)
-    let walk_timeout = Duration::from_secs(60);
-    match tokio::time::timeout(walk_timeout, rx).await {
-        Ok(Ok(())) => {
-            writer.write_all(b"OK network healed\n").await?;
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_walk_sample_hop(&token, 1, probability, &node.port, &history)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!(token = %token, %probability, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(result))) => {
+            // `handle_walk_sample_done` packs this as "<hop_count> <history>".
+            let (total, final_history) = result.split_once(' ').unwrap_or((&result, ""));
+            let segs: Vec<&str> = final_history.split(';').filter(|s| !s.is_empty()).collect();
+            let recorded = segs.iter().filter(|s| **s != SKIPPED_EDGE).count();
+            tracing::info!(token = %token, total_hops = total, recorded, "Walk finished");
+            writer
+                .write_all(format!("SAMPLED total_hops={total} recorded={recorded}\n").as_bytes())
+                .await?;
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
         }
         Ok(Err(_)) => {
-            writer.write_all(b"ERR heal walk canceled\n").await?;
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
         }
         Err(_) => {
-            writer.write_all(b"ERR heal walk timed out\n").await?;
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
         }
     }
 
     Ok(())
 }
 
-/// Handles "NODE HEAL-HOP <token> <start_addr>"
-/// This is received by a node, which then checks its neighbor.
-async fn handle_node_heal_hop<W: AsyncWrite + Unpin>(
-    node: Arc<Node>,
+async fn handle_walk_sample_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
     writer: &mut W,
     token: String,
+    hop_count: u32,
+    probability: Probability,
     start_addr: String,
-) -> Result<(), AnyErr> {
-    // 1. ACK the hop request immediately
-    writer.write_all(b"OK\n").await?;
+    history: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
 
-    // 2. Spawn a task to do the actual work
-    tokio::spawn(async move {
-        let node_port = node.port.clone();
-        if let Err(e) = check_and_heal_neighbor(node, &token, &start_addr).await {
-            tracing::error!(
-                node = %node_port,
-                token = %token,
+    let history = label_last_sampled(history, node.name());
+
+    // Back at the start (a ring of one), or past `--max-walk-hops` on a
+    // ring that never closes.
+    let capped = node.opts.max_walk_hops > 0 && hop_count >= node.opts.max_walk_hops;
+    let done = if port_str(&node.port) == port_str(&start_addr) || capped {
+        Some((hop_count, history))
+    } else {
+        let new_history = if probability.flip() {
+            append_named_edge(history, node.name(), &node.port, &next_addr)
+        } else {
+            append_skipped(history)
+        };
+        if port_str(&next_addr) == port_str(&start_addr) {
+            Some((hop_count.saturating_add(1), new_history))
+        } else {
+            let res = node
+                .forward_walk_sample_hop(
+                    &token,
+                    hop_count.saturating_add(1),
+                    probability,
+                    &start_addr,
+                    &new_history,
+                )
+                .await;
+            record_forward(node, &res);
+            if let Err(e) = res {
+                tracing::warn!(
+                    node = %node.port,
+                    target = %next_addr,
+                    error = ?e,
+                    "TOPOLOGY SAMPLE-HOP forward failed"
+                );
+            }
+            None
+        }
+    };
+
+    if let Some((hop_count, history)) = done {
+        let res = node
+            .send_walk_sample_done(&start_addr, &token, hop_count, &history)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            tracing::warn!(
+                node = %node.port,
+                target = %start_addr,
                 error = ?e,
-                "Heal walk: Check/forward failed"
+                "TOPOLOGY SAMPLE-DONE send failed"
             );
         }
-    });
+    }
 
     Ok(())
 }
 
-/// Handles "NODE HEAL-DONE <token>"
-/// This is received by the start node when the walk is complete.
-async fn handle_node_heal_done<W: AsyncWrite + Unpin>(
+async fn handle_walk_sample_done<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     token: String,
-) -> Result<(), AnyErr> {
-    // Signal the original "handle_node_heal" waiter
-    node.finish_heal_walk(&token).await;
+    hop_count: u32,
+    history: String,
+) -> Result<(), RingError> {
+    let history = label_last_sampled(history, node.name());
+    let _ = node
+        .finish_walk(&token, format!("{hop_count} {history}"))
+        .await;
     writer.write_all(b"OK\n").await?;
     Ok(())
 }
 
-/// Logic for one step of the heal walk.
-/// 1. Get neighbor.
-/// 2. Check if neighbor is start. If so, send HEAL-DONE.
-/// 3. If not, ping neighbor.
-/// 4. If ping OK, forward HEAL-HOP.
-/// 5. If ping FAIL, run `handle_node_death`, then forward HEAL-HOP.
-async fn check_and_heal_neighbor(
-    node: Arc<Node>,
-    token: &str,
-    start_addr: &str,
-) -> Result<(), AnyErr> {
-    let Some(next_addr) = node.get_next().await else {
-        tracing::warn!(node = %node.port, "Heal walk: No next node set, stopping walk.");
-        return Ok(()); // Stop the walk
+/// Handle "TOPOLOGY RING-WALK <ring>" from the client on the start node.
+///
+/// Follows `ring`'s NEXT pointers back to this node and answers with the
+/// edges, then `OK`. The default-ring `topology_map` is left alone.
+async fn handle_walk_ring<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    ring: String,
+) -> Result<(), RingError> {
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+
+    let Some(history) = node.first_walk_history_on(&ring).await else {
+        node.abort_walk(&token).await;
+        writer
+            .write_all(format!("ERR no next hop set on ring {ring}\n").as_bytes())
+            .await?;
+        return Ok(());
     };
 
-    // 1. Check if the ring was completed
-    if port_str(&next_addr) == port_str(start_addr) {
-        tracing::info!(node = %node.port, token = %token, "Heal walk: Completed ring, sending DONE.");
-        let mut s = TcpStream::connect(start_addr).await?;
-        send_auth(&mut s, &node.auth_token).await?;
-        s.write_all(format!("NODE HEAL-DONE {}\n", token).as_bytes())
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let res = node
+        .forward_ring_walk_hop(&ring, &token, &node.port, &history)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        writer
+            .write_all(format!("ERR forward failed: {e}\n").as_bytes())
             .await?;
         return Ok(());
     }
 
-    // 2. Node is not the start, so check its health
-    match check_node_health(node.clone(), &next_addr).await {
-        Ok(_) => {
-            // 3. Node is ALIVE -> Forward the HEAL-HOP request
-            tracing::debug!(node = %node.port, target = %next_addr, "Heal walk: Node is alive, forwarding hop.");
-            let mut s = TcpStream::connect(&next_addr).await?;
-            send_auth(&mut s, &node.auth_token).await?;
-            s.write_all(format!("NODE HEAL-HOP {} {}\n", token, start_addr).as_bytes())
-                .await?;
+    tracing::info!(token = %token, ring = %ring, "Walk started");
+    match tokio::time::timeout(Duration::from_secs(30), rx).await {
+        Ok(Ok(Ok(final_history))) => {
+            let segs: Vec<&str> = final_history.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, edges = segs.len(), "Walk finished");
+            for seg in segs {
+                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+            }
+            writer.write_all(b"OK\n").await?;
+        }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Walk aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Walk canceled");
+            writer.write_all(b"ERR walk canceled\n").await?;
+        }
+        Err(_) => {
+            tracing::warn!(token = %token, "Walk timed out");
+            writer.write_all(b"ERR walk timeout\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_walk_ring_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    ring: String,
+    start_addr: String,
+    history: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next_on(&ring).await else {
+        return Ok(());
+    };
+
+    let history = label_last_target(history, node.name());
+    let new_history = append_named_edge(history, node.name(), &node.port, &next_addr);
+
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_ring_walk_done(&start_addr, &token, &new_history)
+            .await
+    } else {
+        node.forward_ring_walk_hop(&ring, &token, &start_addr, &new_history)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            ring = %ring,
+            target = %next_addr,
+            error = ?e,
+            "TOPOLOGY RING-HOP forward failed"
+        );
+    }
+
+    Ok(())
+}
+
+async fn handle_walk_ring_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    history: String,
+) -> Result<(), RingError> {
+    let history = label_last_target(history, node.name());
+    let _ = node.finish_walk(&token, history).await;
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+/// Forward a partition probe, or end it at the start node. Like
+/// `TOPOLOGY RING-HOP` without the history: a node with no NEXT drops it,
+/// as does the node it reaches after `--max-walk-hops` on a ring that
+/// never gets back to the start node, and the start node's timeout
+/// reports the partition.
+async fn handle_probe_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    hop_count: u32,
+    start_addr: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        tracing::warn!(node = %node.port, token = %token, "No next node set, dropping TOPOLOGY PROBE-HOP");
+        return Ok(());
+    };
+    let max_hops = node.opts.max_walk_hops;
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_probe_done(&start_addr, &token).await
+    } else if max_hops > 0 && hop_count >= max_hops {
+        tracing::warn!(node = %node.port, token = %token, hop_count, max_hops, "Dropping TOPOLOGY PROBE-HOP past --max-walk-hops");
+        return Ok(());
+    } else {
+        node.forward_probe_hop(&token, hop_count.saturating_add(1), &start_addr)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, target = %next_addr, error = ?e, "TOPOLOGY PROBE-HOP forward failed");
+    }
+    Ok(())
+}
+
+async fn handle_probe_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+) -> Result<(), RingError> {
+    if !node.finish_probe(&token).await {
+        tracing::debug!(node = %node.port, token = %token, "Ignoring late or unknown probe");
+    }
+    let _ = writer.write_all(b"OK\n").await;
+    Ok(())
+}
+
+/// Every `--probe-interval-secs`, send a probe round the ring and wait up
+/// to the same interval for it to come back.
+async fn probe_loop(node: Arc<Node>) {
+    let interval = node.opts.probe_interval;
+    tracing::info!(node = %node.port, interval = ?interval, "Partition probes starting");
+    loop {
+        tokio::time::sleep(interval).await;
+        run_probe(&node, interval).await;
+    }
+}
+
+/// Every `--heartbeat-interval-secs`, starting at once, tell
+/// `--heartbeat-addr` this node is alive. A failed send is logged and the
+/// next one tried on schedule.
+async fn heartbeat_loop(node: Arc<Node>) {
+    let mut ticker = tokio::time::interval(node.heartbeat_interval());
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = node.send_heartbeat().await {
+            tracing::warn!(node = %node.port, error = ?e, "Heartbeat send failed");
+        }
+        if node.opts.routing == RoutingMode::ConsistentHash
+            && let Err(e) = node.refresh_hash_ring().await
+        {
+            tracing::warn!(node = %node.port, error = ?e, "Hash ring refresh failed");
+        }
+    }
+}
+
+/// Run `task` until it ends or the node's shutdown watch fires.
+async fn until_shutdown(node: Arc<Node>, task: impl Future<Output = ()>) {
+    tokio::select! {
+        _ = task => {}
+        _ = node.shutdown_requested() => {}
+    }
+}
+
+/// Every `--announce-interval-secs`, starting at once, send this node's
+/// `ANNOUNCE` to the `--announce` group. Failures are logged like a
+/// heartbeat's.
+async fn announce_loop(node: Arc<Node>, group: std::net::SocketAddr) {
+    let socket = match crate::announce::sender(group).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::warn!(node = %node.port, group = %group, error = ?e, "Cannot open announce socket");
+            return;
+        }
+    };
+    tracing::info!(node = %node.port, group = %group, "Announcing");
+    let mut ticker = tokio::time::interval(node.announce_interval());
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = crate::announce::announce(&socket, group, &node.announcement()).await {
+            tracing::warn!(node = %node.port, group = %group, error = ?e, "Announce send failed");
+        }
+    }
+}
+
+/// Every `--monitor-interval-secs`, count the ring and, when the count
+/// differs from the last one, log a `TOPOLOGY_CHANGE` and send it to
+/// `topology` subscribers. A count that fails leaves the last one standing.
+async fn monitor_loop(node: Arc<Node>) {
+    let interval = node.opts.monitor_interval;
+    tracing::info!(node = %node.port, interval = ?interval, "Topology monitor starting");
+    loop {
+        tokio::time::sleep(interval).await;
+        // The walk's DONE records the count; see `note_ring_size`.
+        if let Err(reason) = count_ring(&node, interval).await {
+            tracing::debug!(node = %node.port, reason = %reason, "Ring count failed");
         }
-        Err(e) => {
-            // 3. Node is DEAD -> Heal it, then forward
-            tracing::warn!(
-                node = %node.port,
-                target = %next_addr,
-                error = ?e,
-                "Heal walk: Node is dead, starting healing process."
-            );
-
-            // This blocks until the node is respawned and synced
-            if let Err(heal_err) = handle_node_death(node.clone(), next_addr.clone()).await {
-                tracing::error!(
-                    node = %node.port,
-                    target = %next_addr,
-                    error = ?heal_err,
-                    "Heal walk: `handle_node_death` failed. Stopping walk."
-                );
-                return Err(heal_err); // Stop the walk
-            }
+    }
+}
 
-            // 4. Forward the HEAL-HOP to the newly respawned node
-            tracing::info!(
-                node = %node.port,
-                target = %next_addr,
-                "Heal walk: Node healed, forwarding hop."
-            );
-            let mut s = TcpStream::connect(&next_addr).await?;
-            send_auth(&mut s, &node.auth_token).await?;
-            s.write_all(format!("NODE HEAL-HOP {} {}\n", token, start_addr).as_bytes())
-                .await?;
+/// Every `--split-brain-check-interval-secs`, run a `SPLIT-BRAIN CHECK`
+/// from this node; [`check_split_brain`] logs any split it finds.
+async fn split_brain_loop(node: Arc<Node>) {
+    let interval = node.opts.split_brain_check_interval;
+    tracing::info!(node = %node.port, interval = ?interval, "Split-brain check starting");
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Err(reason) = check_split_brain(&node, interval).await {
+            tracing::debug!(node = %node.port, reason = %reason, "Split-brain check failed");
         }
     }
-
-    Ok(())
 }
 
-async fn handle_ring_forward<W: AsyncWrite + Unpin>(
-    node: &Node,
-    writer: &mut W,
-    mut ttl: u32,
-    msg: String,
-) -> Result<(), AnyErr> {
-    tracing::debug!(node = %node.port, ttl, msg = %msg, "RING FORWARD");
+/// Cache the ring size a finished walk counted, for `RING ALL` and
+/// `NODE STAT`. A count that differs from the last one is logged as a
+/// `TOPOLOGY_CHANGE` and sent to `topology` subscribers.
+fn note_ring_size(node: &Node, size: u64) {
+    if let Some(old) = node.record_ring_size(size) {
+        tracing::warn!(node = %node.port, old, new = size, "TOPOLOGY_CHANGE");
+        node.publish(
+            EventKind::Topology,
+            &format!("TOPOLOGY_CHANGE old={old} new={size}"),
+        );
+    }
+}
 
-    if ttl > 0 {
-        ttl -= 1;
-        if let Some(next_addr) = node.get_next().await {
-            if let Err(e) = node.forward_ring_forward(ttl, &msg).await {
-                tracing::warn!(node = %node.port, target = %next_addr, error = ?e, "RING FORWARD failed");
-            }
-        } else {
-            tracing::warn!(node = %node.port, "No next node set, dropping RING FORWARD");
+/// Walk the default ring from this node and count its edges, one per
+/// node.
+async fn count_ring(node: &Node, timeout: Duration) -> Result<u64, String> {
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
+    let Some(history) = node.first_walk_history().await else {
+        node.abort_walk(&token).await;
+        return Err("no next hop set".into());
+    };
+    let res = node
+        .forward_topology_hop(DEFAULT_RING, &token, &node.port, &history, &node.port, None)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
+        return Err(format!("forward failed: {e}"));
+    }
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(Ok(Ok(history))) => Ok(history.split(';').filter(|s| !s.is_empty()).count() as u64),
+        Ok(_) => Err("walk canceled".into()),
+        Err(_) => {
+            node.abort_walk(&token).await;
+            Err(format!("no walk back within {timeout:?}"))
         }
     }
+}
 
-    writer.write_all(b"OK\n").await?;
-    Ok(())
+/// One probe round. An unwired node, or a ring of one, has nothing to
+/// probe.
+async fn run_probe(node: &Node, timeout: Duration) {
+    let Some(next_addr) = node.get_next().await else {
+        return;
+    };
+    if port_str(&next_addr) == port_str(&node.port) {
+        return;
+    }
+    let (round, token, rx) = node.start_probe().await;
+    let res = node.forward_probe_hop(&token, 1, &node.port).await;
+    record_forward(node, &res);
+    let outcome = match res {
+        Err(e) => Err(format!("forward to {next_addr} failed: {e}")),
+        Ok(()) => match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(Ok(_))) => Ok(()),
+            Ok(_) => Err("probe canceled".to_string()),
+            Err(_) => Err(format!("no probe back within {timeout:?}")),
+        },
+    };
+    match outcome {
+        Ok(()) => tracing::debug!(node = %node.port, round, "Probe came back"),
+        Err(reason) => {
+            node.abort_walk(&token).await;
+            tracing::warn!(node = %node.port, round, next = %next_addr, reason = %reason, "PARTITION_DETECTED");
+            node.metrics.record_partition();
+            node.publish(EventKind::Partition, &format!("PARTITION_DETECTED {round}"));
+        }
+    }
 }
 
-/// Handle "TOPOLOGY WALK" from the client on the start node.
-async fn handle_topology_walk<W: AsyncWrite + Unpin>(
+/// Handle "GATHER <key>" from the client on the start node.
+///
+/// Walks the ring once, each node appending `<addr>=<value>` for `key`.
+/// The reply is one `<addr>=<value>` line per node, starting with this
+/// one, then `OK`.
+async fn handle_gather_start<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
-    let token = node.make_walk_token();
-    let rx = node.register_walk(token.as_str()).await;
-
-    let Some(history) = node.first_walk_history().await else {
+    key: String,
+) -> Result<(), RingError> {
+    let Some(next_addr) = node.get_next().await else {
         writer.write_all(b"ERR no next hop set\n").await?;
         return Ok(());
     };
+    if port_str(&next_addr) == port_str(&node.port) {
+        // A ring of one: nothing to walk.
+        let results = node.append_gather_result(String::new(), &key).await;
+        writer
+            .write_all(format!("{results}\nOK\n").as_bytes())
+            .await?;
+        return Ok(());
+    }
+    let token = node.make_walk_token();
+    let rx = node.register_walk(token.as_str()).await;
 
-    if let Err(e) = node
-        .forward_topology_hop(&token, &node.port, &history)
-        .await
-    {
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let results = node.append_gather_result(String::new(), &key).await;
+    let res = node
+        .forward_gather_hop(DEFAULT_RING, &token, &node.port, &key, &results)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        node.abort_walk(&token).await;
         writer
             .write_all(format!("ERR forward failed: {e}\n").as_bytes())
             .await?;
         return Ok(());
     }
 
+    tracing::info!(token = %token, key = %key, "Gather started");
     match tokio::time::timeout(Duration::from_secs(30), rx).await {
-        Ok(Ok(final_history)) => {
-            for seg in final_history.split(';').filter(|s| !s.is_empty()) {
-                writer.write_all(format!("{seg}\n").as_bytes()).await?;
+        Ok(Ok(Ok(results))) => {
+            let entries: Vec<&str> = results.split(';').filter(|s| !s.is_empty()).collect();
+            tracing::info!(token = %token, nodes = entries.len(), "Gather finished");
+            for entry in entries {
+                writer.write_all(format!("{entry}\n").as_bytes()).await?;
             }
             writer.write_all(b"OK\n").await?;
         }
+        Ok(Ok(Err(WalkAborted))) => {
+            tracing::info!(token = %token, "Gather aborted");
+            writer.write_all(b"ERR walk aborted\n").await?;
+        }
         Ok(Err(_)) => {
+            tracing::warn!(token = %token, "Gather canceled");
             writer.write_all(b"ERR walk canceled\n").await?;
         }
         Err(_) => {
+            tracing::warn!(token = %token, "Gather timed out");
             writer.write_all(b"ERR walk timeout\n").await?;
         }
     }
@@ -845,78 +4963,276 @@ async fn handle_topology_walk<W: AsyncWrite + Unpin>(
     Ok(())
 }
 
-async fn handle_topology_hop<W: AsyncWrite + Unpin>(
+async fn handle_gather_hop<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     token: String,
     start_addr: String,
-    history: String,
-) -> Result<(), AnyErr> {
+    key: String,
+    results: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
     let Some(next_addr) = node.get_next().await else {
-        let _ = writer.write_all(b"OK\n").await;
         return Ok(());
     };
 
-    let new_history = append_edge(history, &node.port, &next_addr);
-
-    if port_str(&next_addr) == port_str(&start_addr) {
-        if let Err(e) = node
-            .send_topology_done(&start_addr, &token, &new_history)
-            .await
-        {
-            tracing::warn!(
-                node = %node.port,
-                target = %start_addr,
-                error = ?e,
-                "TOPOLOGY DONE send failed"
-            );
-        }
+    let results = node.append_gather_result(results, &key).await;
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_gather_done(&start_addr, &token, &results).await
     } else {
-        if let Err(e) = node
-            .forward_topology_hop(&token, &start_addr, &new_history)
+        node.forward_gather_hop(DEFAULT_RING, &token, &start_addr, &key, &results)
             .await
-        {
-            tracing::warn!(
-                node = %node.port,
-                target = %next_addr,
-                error = ?e,
-                "TOPOLOGY HOP forward failed"
-            );
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            target = %next_addr,
+            error = ?e,
+            "GATHER HOP forward failed"
+        );
+    }
+
+    Ok(())
+}
+
+async fn handle_gather_done<W: AsyncWrite + Unpin>(
+    node: &Node,
+    writer: &mut W,
+    token: String,
+    results: String,
+) -> Result<(), RingError> {
+    let _ = node.finish_walk(&token, results).await;
+    writer.write_all(b"OK\n").await?;
+    Ok(())
+}
+
+/// How long a `GATHER STREAM` waits for its client to connect and for
+/// the walk to come back.
+const GATHER_STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Handle "GATHER STREAM <key>" from the client on the start node.
+///
+/// Opens a listener on a spare port of this node's host and answers
+/// `STREAM_PORT <port>` and `OK` straight away; the walk then runs like
+/// `GATHER`'s, and [`serve_gather_stream`] passes each node's entry on to
+/// whoever connects to that port as it arrives.
+async fn handle_gather_stream<W: AsyncWrite + Unpin>(
+    node: &Arc<Node>,
+    writer: &mut W,
+    key: String,
+) -> Result<(), RingError> {
+    let Some(next_addr) = node.get_next().await else {
+        writer.write_all(b"ERR no next hop set\n").await?;
+        return Ok(());
+    };
+    let Ok(local) = node.port.parse::<std::net::SocketAddr>() else {
+        return handle_error(writer, "GATHER STREAM needs a TCP listener".into()).await;
+    };
+    let listener = match TcpListener::bind((local.ip(), 0)).await {
+        Ok(listener) => listener,
+        Err(e) => return handle_error(writer, format!("cannot open stream port: {e}")).await,
+    };
+    let stream_port = listener.local_addr()?.port();
+
+    let token = node.make_walk_token();
+    let done = node.register_walk(token.as_str()).await;
+    let data = node.open_gather_stream(&token).await;
+    node.walks_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let results = node.append_gather_result(String::new(), &key).await;
+    node.push_gather_stream(&token, results.clone()).await;
+    if port_str(&next_addr) == port_str(&node.port) {
+        // A ring of one: nothing to walk.
+        node.finish_walk(&token, results).await;
+    } else {
+        let res = node
+            .forward_gather_stream_hop(&token, &node.port, &key, &results)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
+            node.abort_walk(&token).await;
+            node.close_gather_stream(&token).await;
+            writer
+                .write_all(format!("ERR forward failed: {e}\n").as_bytes())
+                .await?;
+            return Ok(());
         }
     }
 
-    let _ = writer.write_all(b"OK\n").await;
+    tracing::info!(token = %token, key = %key, port = stream_port, "Gather stream started");
+    tokio::spawn(serve_gather_stream(
+        Arc::clone(node),
+        listener,
+        token,
+        data,
+        done,
+    ));
+    writer
+        .write_all(format!("STREAM_PORT {stream_port}\nOK\n").as_bytes())
+        .await?;
     Ok(())
 }
 
-async fn handle_topology_done<W: AsyncWrite + Unpin>(
+/// Accept one client on a `GATHER STREAM`'s port and stream the walk to
+/// it. Gives up after [`GATHER_STREAM_TIMEOUT`].
+async fn serve_gather_stream(
     node: Arc<Node>,
+    listener: TcpListener,
+    token: String,
+    data: tokio::sync::mpsc::UnboundedReceiver<String>,
+    done: tokio::sync::oneshot::Receiver<crate::walk_registry::WalkResult>,
+) {
+    let deadline = tokio::time::Instant::now() + GATHER_STREAM_TIMEOUT;
+    let res = match tokio::time::timeout_at(deadline, listener.accept()).await {
+        Ok(Ok((stream, _))) => {
+            drop(listener);
+            stream_gather(&node, Stream::Plain(stream), data, done, deadline).await
+        }
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err("no client connected".into()),
+    };
+    node.close_gather_stream(&token).await;
+    match res {
+        Ok(()) => tracing::info!(token = %token, "Gather stream finished"),
+        Err(e) => {
+            node.abort_walk(&token).await;
+            tracing::warn!(node = %node.port, token = %token, error = %e, "Gather stream failed");
+        }
+    }
+}
+
+/// After the same TLS, greeting and `AUTH` steps as a command connection,
+/// write a `DATA <addr>=<value>` line for each entry as it arrives, then
+/// `END` once `GATHER DONE` comes back. Entries carried only by the done
+/// line, whose own `STREAM-DATA` was lost or is still on its way, go out
+/// just before `END`.
+async fn stream_gather(
+    node: &Node,
+    stream: Stream,
+    mut data: tokio::sync::mpsc::UnboundedReceiver<String>,
+    mut done: tokio::sync::oneshot::Receiver<crate::walk_registry::WalkResult>,
+    deadline: tokio::time::Instant,
+) -> Result<(), RingError> {
+    let mut stream =
+        match tokio::time::timeout(Duration::from_secs(5), node.opts.tls.accept(stream)).await {
+            Ok(r) => r?,
+            Err(_) => return Err("TLS handshake timed out".into()),
+        };
+    if node.opts.hello {
+        stream
+            .write_all(PROTOCOL_VERSION.node_greeting(node.generation()).as_bytes())
+            .await?;
+    }
+    let (reader, mut writer) = tokio::io::split(stream);
+    if node.auth_token.is_enabled() {
+        let mut reader = LimitedLineReader::new(BufReader::new(reader), node.opts.max_line_bytes);
+        let mut auth_line = String::new();
+        let read =
+            tokio::time::timeout(Duration::from_secs(1), reader.read_line(&mut auth_line)).await;
+        if !matches!(read, Ok(Ok(n)) if n > 0) || !node.auth_token.verify_auth_line(&auth_line) {
+            let _ = writer.write_all(b"ERR auth required\n").await;
+            return Err("stream client failed AUTH".into());
+        }
+    }
+
+    let mut sent = std::collections::HashSet::new();
+    let outcome = loop {
+        tokio::select! {
+            Some(entry) = data.recv() => {
+                if sent.insert(entry.clone()) {
+                    writer.write_all(format!("DATA {entry}\n").as_bytes()).await?;
+                }
+            }
+            res = &mut done => break res,
+            _ = tokio::time::sleep_until(deadline) => {
+                writer.write_all(b"ERR walk timeout\n").await?;
+                return Err("walk timed out".into());
+            }
+        }
+    };
+    match outcome {
+        Ok(Ok(results)) => {
+            let queued = std::iter::from_fn(|| data.try_recv().ok());
+            let carried = results
+                .split(';')
+                .filter(|s| !s.is_empty())
+                .map(str::to_string);
+            for entry in queued.chain(carried) {
+                if sent.insert(entry.clone()) {
+                    writer
+                        .write_all(format!("DATA {entry}\n").as_bytes())
+                        .await?;
+                }
+            }
+            writer.write_all(b"END\n").await?;
+        }
+        Ok(Err(WalkAborted)) => writer.write_all(b"ERR walk aborted\n").await?,
+        Err(_) => writer.write_all(b"ERR walk canceled\n").await?,
+    }
+    let _ = writer.shutdown().await;
+    Ok(())
+}
+
+/// Handle "GATHER STREAM-HOP": send this node's entry straight back to the
+/// start node, then carry on as a `GATHER HOP` would.
+async fn handle_gather_stream_hop<W: AsyncWrite + Unpin>(
+    node: &Node,
     writer: &mut W,
     token: String,
-    history: String,
-) -> Result<(), AnyErr> {
-    // Finish the client walk if we are the start node
-    let _ = node.finish_walk(&token, history.clone()).await;
+    start_addr: String,
+    key: String,
+    results: String,
+) -> Result<(), RingError> {
+    let _ = ack_hop(writer).await;
+    let Some(next_addr) = node.get_next().await else {
+        return Ok(());
+    };
 
-    // Persist and broadcast the completed topology
-    node.set_topology_from_history(&history).await;
+    let entry = node.append_gather_result(String::new(), &key).await;
+    let res = node
+        .send_gather_stream_data(&start_addr, &token, &entry)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(node = %node.port, target = %start_addr, error = ?e, "GATHER STREAM-DATA send failed");
+    }
 
-    let node_clone = Arc::clone(&node);
-    tokio::spawn(async move {
-        node_clone.broadcast_topology_set().await;
-    });
+    let results = if results.is_empty() {
+        entry
+    } else {
+        format!("{results};{entry}")
+    };
+    let res = if port_str(&next_addr) == port_str(&start_addr) {
+        node.send_gather_done(&start_addr, &token, &results).await
+    } else {
+        node.forward_gather_stream_hop(&token, &start_addr, &key, &results)
+            .await
+    };
+    record_forward(node, &res);
+    if let Err(e) = res {
+        tracing::warn!(
+            node = %node.port,
+            target = %next_addr,
+            error = ?e,
+            "GATHER STREAM-HOP forward failed"
+        );
+    }
 
-    let _ = writer.write_all(b"OK\n").await;
     Ok(())
 }
 
-async fn handle_topology_set<W: AsyncWrite + Unpin>(
+/// Unlike `handle_topology_done`, the reverse history is never persisted
+/// or broadcast: `topology_map` is keyed by forward edges.
+async fn handle_topology_reverse_done<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
+    token: String,
     history: String,
-) -> Result<(), AnyErr> {
-    node.set_topology_from_history(&history).await;
-    writer.write_all(b"OK\n").await?;
+) -> Result<(), RingError> {
+    let history = label_last_target(history, node.name());
+    let _ = node.finish_walk(&token, history).await;
+    let _ = writer.write_all(b"OK\n").await;
     Ok(())
 }
 
@@ -925,7 +5241,7 @@ async fn handle_topology_set<W: AsyncWrite + Unpin>(
 async fn handle_netmap_discover<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let token = node.make_invest_token();
 
     let Some(_next) = node.get_next().await else {
@@ -935,7 +5251,11 @@ async fn handle_netmap_discover<W: AsyncWrite + Unpin>(
 
     // entries begins with "<node_port>=Alive"
     let entries = format!("{}=Alive", port_str(&node.port));
-    if let Err(e) = node.forward_netmap_hop(&token, &node.port, &entries).await {
+    let res = node
+        .forward_netmap_hop(DEFAULT_RING, &token, &node.port, &entries)
+        .await;
+    record_forward(node, &res);
+    if let Err(e) = res {
         writer
             .write_all(format!("ERR forward failed: {e}\n").as_bytes())
             .await?;
@@ -953,7 +5273,7 @@ async fn handle_netmap_hop<W: AsyncWrite + Unpin>(
     token: String,
     start_addr: String,
     entries: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let Some(next_addr) = node.get_next().await else {
         let _ = writer.write_all(b"OK\n").await;
         return Ok(());
@@ -962,10 +5282,11 @@ async fn handle_netmap_hop<W: AsyncWrite + Unpin>(
     let new_entries = node.entries_with_self(&entries);
 
     if port_str(&next_addr) == port_str(&start_addr) {
-        if let Err(e) = node
+        let res = node
             .send_netmap_done(&start_addr, &token, &new_entries)
-            .await
-        {
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
             tracing::warn!(
                 node = %node.port,
                 target = %start_addr,
@@ -974,10 +5295,11 @@ async fn handle_netmap_hop<W: AsyncWrite + Unpin>(
             );
         }
     } else {
-        if let Err(e) = node
-            .forward_netmap_hop(&token, &start_addr, &new_entries)
-            .await
-        {
+        let res = node
+            .forward_netmap_hop(DEFAULT_RING, &token, &start_addr, &new_entries)
+            .await;
+        record_forward(node, &res);
+        if let Err(e) = res {
             tracing::warn!(
                 node = %node.port,
                 target = %next_addr,
@@ -996,7 +5318,7 @@ async fn handle_netmap_done<W: AsyncWrite + Unpin>(
     writer: &mut W,
     _token: String,
     entries: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     // Persist locally, then broadcast to all nodes
     node.set_network_nodes_from_entries(&entries).await;
     node.broadcast_netmap(&entries).await;
@@ -1009,7 +5331,7 @@ async fn handle_netmap_set<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     entries: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     node.set_network_nodes_from_entries(&entries).await;
     let _ = writer.write_all(b"OK\n").await;
     Ok(())
@@ -1018,7 +5340,9 @@ async fn handle_netmap_set<W: AsyncWrite + Unpin>(
 async fn handle_netmap_get<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
+    cid: &CorrelationId,
+) -> Result<(), RingError> {
+    writer.write_all(cid.header().as_bytes()).await?;
     let lines = node.get_network_nodes_lines().await;
     if lines.is_empty() {
         writer.write_all(b"(empty)\n").await?;
@@ -1176,7 +5500,7 @@ async fn handle_file_push<R, W>(
     writer: &mut W,
     size: u64,
     name: String,
-) -> Result<(), AnyErr>
+) -> Result<(), RingError>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
@@ -1257,15 +5581,13 @@ where
     // If any connect fails we abort the whole push: the client gets ERR
     // and drains. Future work could fall back to a relay; not in scope.
     let connect_futures = target_addrs.iter().map(|addr| {
-        let addr = addr.clone();
-        let token = node.auth_token.clone();
+        let node = &node;
         async move {
-            let mut s = TcpStream::connect(&addr).await?;
-            send_auth(&mut s, &token).await?;
-            Ok::<(String, TcpStream), AnyErr>((addr, s))
+            let s = node.connect(addr).await?;
+            Ok::<(String, Stream), RingError>((addr.clone(), s))
         }
     });
-    let mut conns: Vec<(String, TcpStream)> =
+    let mut conns: Vec<(String, Stream)> =
         match futures::future::try_join_all(connect_futures).await {
             Ok(v) => v,
             Err(e) => {
@@ -1390,7 +5712,7 @@ async fn handle_file_push_chunk<R, W>(
     parts: u32,
     index: u32,
     start_port: u16,
-) -> Result<(), AnyErr>
+) -> Result<(), RingError>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
@@ -1439,7 +5761,7 @@ async fn handle_file_tags_set<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     entries: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     node.set_file_tags_from_entries(&entries).await;
     writer.write_all(b"OK\n").await?;
     Ok(())
@@ -1451,7 +5773,7 @@ async fn handle_file_pull<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     name: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let tags = node.file_tags.read().await;
     let Some(tag) = tags.get(&name) else {
         writer.write_all(b"ERR file not found\n").await?;
@@ -1480,7 +5802,7 @@ async fn handle_file_get_chunk<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     name: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let next = node.get_next().await.unwrap_or_else(|| node.port.clone());
 
     let chunk_path = node
@@ -1538,7 +5860,7 @@ async fn handle_file_backup_push<R, W>(
     writer: &mut W,
     name: String,
     size: u64,
-) -> Result<(), AnyErr>
+) -> Result<(), RingError>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
@@ -1568,7 +5890,7 @@ async fn handle_file_content_push<R, W>(
     writer: &mut W,
     name: String,
     size: u64,
-) -> Result<(), AnyErr>
+) -> Result<(), RingError>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
@@ -1596,7 +5918,7 @@ async fn handle_file_get_backup_chunk<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
     name: String,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     let next = node.get_next().await.unwrap_or_else(|| node.port.clone());
 
     let chunk_path = node
@@ -1641,7 +5963,7 @@ async fn pull_file_from_ring<W: AsyncWrite + Unpin>(
     parts: u32,
     file_size: u64,
     writer: &mut W,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     use futures::stream::{FuturesOrdered, StreamExt};
     use std::collections::{HashMap, HashSet};
     use tokio::sync::Semaphore;
@@ -1703,7 +6025,6 @@ async fn pull_file_from_ring<W: AsyncWrite + Unpin>(
         .into_iter()
         .map(|(i, chunk_name, owner_addr, owner_port)| {
             let sem = Arc::clone(&sem);
-            let token = node.auth_token.clone();
             async move {
                 // Acquire-on-spawn would defeat ordered pipelining; acquire
                 // inside the task so FuturesOrdered can buffer up to CAP
@@ -1717,7 +6038,7 @@ async fn pull_file_from_ring<W: AsyncWrite + Unpin>(
                     .acquire_owned()
                     .await
                     .expect("semaphore is local to pull_file_from_ring; never closed");
-                let r = request_chunk_from(&owner_addr, &chunk_name, &token).await;
+                let r = request_chunk_from(node, &owner_addr, &chunk_name).await;
                 (i, chunk_name, owner_addr, owner_port, r)
             }
         })
@@ -1763,7 +6084,7 @@ async fn pull_file_from_ring<W: AsyncWrite + Unpin>(
                     continue;
                 };
                 let pred_addr = format!("{}:{}", host, pred_port);
-                match request_backup_chunk_from(&pred_addr, &chunk_name, &node.auth_token).await {
+                match request_backup_chunk_from(node, &pred_addr, &chunk_name).await {
                     Ok((chunk_data, _)) => {
                         tracing::info!(
                             node = %node.port,
@@ -1835,17 +6156,16 @@ async fn pull_file_from_ring<W: AsyncWrite + Unpin>(
 }
 
 async fn request_chunk_from(
+    node: &Node,
     addr: &str,
     chunk_name: &str,
-    token: &AuthToken,
-) -> Result<(Vec<u8>, String), AnyErr> {
-    let mut s = TcpStream::connect(addr).await?;
-    send_auth(&mut s, token).await?;
+) -> Result<(Vec<u8>, String), RingError> {
+    let mut s = node.connect(addr).await?;
     s.write_all(format!("FILE GET-CHUNK {}\n", chunk_name).as_bytes())
         .await?;
 
-    let (r, mut w) = s.into_split();
-    let mut reader = BufReader::new(r);
+    let (r, mut w) = tokio::io::split(s);
+    let mut reader = LimitedLineReader::new(BufReader::new(r), node.opts.max_line_bytes);
 
     // Parse FILE RESP-CHUNK <next_addr> <size> <name>
     let mut header = String::new();
@@ -1864,7 +6184,7 @@ async fn request_chunk_from(
         .parse()
         .map_err(|_| "invalid chunk size".to_string())?;
     let mut buf = vec![0u8; size];
-    reader.read_exact(&mut buf).await?;
+    reader.get_mut().read_exact(&mut buf).await?;
 
     // Ensure the is writer not dropped too early
     let _ = w.shutdown().await;
@@ -1873,18 +6193,17 @@ async fn request_chunk_from(
 }
 
 async fn request_backup_chunk_from(
+    node: &Node,
     addr: &str,
     chunk_name: &str,
-    token: &AuthToken,
-) -> Result<(Vec<u8>, String), AnyErr> {
-    let mut s = TcpStream::connect(addr).await?;
-    send_auth(&mut s, token).await?;
+) -> Result<(Vec<u8>, String), RingError> {
+    let mut s = node.connect(addr).await?;
     // Send the new command
     s.write_all(format!("FILE GET-BACKUP-CHUNK {}\n", chunk_name).as_bytes())
         .await?;
 
-    let (r, mut w) = s.into_split();
-    let mut reader = BufReader::new(r);
+    let (r, mut w) = tokio::io::split(s);
+    let mut reader = LimitedLineReader::new(BufReader::new(r), node.opts.max_line_bytes);
 
     // Parse FILE RESP-CHUNK <next_addr> <size> <name>
     let mut header = String::new();
@@ -1903,7 +6222,7 @@ async fn request_backup_chunk_from(
         .parse()
         .map_err(|_| "invalid chunk size".to_string())?;
     let mut buf = vec![0u8; size];
-    reader.read_exact(&mut buf).await?;
+    reader.get_mut().read_exact(&mut buf).await?;
 
     // ensure writer not dropped too early
     let _ = w.shutdown().await;
@@ -1916,7 +6235,7 @@ async fn request_backup_chunk_from(
 async fn handle_file_list_csv<W: AsyncWrite + Unpin>(
     node: &Node,
     writer: &mut W,
-) -> Result<(), AnyErr> {
+) -> Result<(), RingError> {
     // Pure CSV output (header + rows)
     writer.write_all(b"name,start,size\n").await?;
 
@@ -1936,24 +6255,23 @@ async fn handle_file_list_csv<W: AsyncWrite + Unpin>(
 
 // --- Helpers
 
-/// Write the AUTH handshake line on a freshly-opened outbound stream.
-/// No-op when the token is disabled. Callers must invoke this BEFORE the
-/// first protocol command on every internal connection (gossip, fan-out,
-/// pull, backup-push, heal share, gateway → ring).
-async fn send_auth<W: AsyncWrite + Unpin>(writer: &mut W, token: &AuthToken) -> Result<(), AnyErr> {
-    if let Some(line) = token.make_auth_line() {
-        writer.write_all(line.as_bytes()).await?;
-    }
-    Ok(())
-}
-
-async fn handle_error<W: AsyncWrite + Unpin>(writer: &mut W, err: String) -> Result<(), AnyErr> {
+async fn handle_error<W: AsyncWrite + Unpin>(writer: &mut W, err: String) -> Result<(), RingError> {
     writer
         .write_all(format!("ERR {}\n", err).as_bytes())
         .await?;
     Ok(())
 }
 
+/// Count one outbound hop for `NODE STAT`: a forward on success, an
+/// error otherwise.
+fn record_forward<T, E>(node: &Node, res: &Result<T, E>) {
+    let counter = match res {
+        Ok(_) => &node.msgs_forwarded,
+        Err(_) => &node.errors_total,
+    };
+    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+}
+
 /// Minimal CSV escaping for names containing commas, quotes, or newlines.
 fn csv_escape(s: &str) -> String {
     let needs_quotes = s.chars().any(|c| matches!(c, ',' | '"' | '\n' | '\r'));
@@ -1972,11 +6290,12 @@ fn csv_escape(s: &str) -> String {
     out
 }
 
+/// Host part of `addr` (brackets kept for IPv6), or `127.0.0.1` when the
+/// address is a bare port.
 fn host_of(addr: &str) -> &str {
-    if addr.contains(':') {
-        addr.split(':').next().unwrap_or("127.0.0.1")
-    } else {
-        "127.0.0.1" // Assume localhost if no host is given
+    match addr.rsplit_once(':') {
+        Some((host, _)) if !host.is_empty() => host,
+        _ => "127.0.0.1", // Assume localhost if no host is given
     }
 }
 
@@ -2035,17 +6354,13 @@ async fn push_to_predecessor(node: Arc<Node>, chunk_name: String) {
     };
     let size = body.len() as u64;
 
-    let mut s = match TcpStream::connect(&pred_addr).await {
+    let mut s = match node.connect(&pred_addr).await {
         Ok(s) => s,
         Err(e) => {
             tracing::warn!(node = %node.port, predecessor = %pred_addr, chunk = %chunk_name, error = ?e, "Predecessor unreachable; skipping backup push.");
             return;
         }
     };
-    if let Err(e) = send_auth(&mut s, &node.auth_token).await {
-        tracing::warn!(node = %node.port, predecessor = %pred_addr, chunk = %chunk_name, error = ?e, "Failed to send AUTH on backup push.");
-        return;
-    }
 
     let header = format!("FILE BACKUP-PUSH {} {}\n", chunk_name, size);
     if let Err(e) = s.write_all(header.as_bytes()).await {
@@ -2114,16 +6429,15 @@ async fn spawn_gossip_loop(node: Arc<Node>) {
 }
 
 /// Tries to send "NODE PING" and expects "PONG"
-async fn check_node_health(node: Arc<Node>, addr: &str) -> Result<(), AnyErr> {
+async fn check_node_health(node: Arc<Node>, addr: &str) -> Result<(), RingError> {
     let timeout = Duration::from_secs(2);
 
     // Connect with timeout
-    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(addr)).await??;
-    send_auth(&mut stream, &node.auth_token).await?;
+    let mut stream = tokio::time::timeout(timeout, node.connect(addr)).await??;
     stream.write_all(b"NODE PING\n").await?;
 
     // Read response with timeout
-    let mut reader = BufReader::new(stream);
+    let mut reader = LimitedLineReader::new(BufReader::new(stream), node.opts.max_line_bytes);
     let mut buf = String::new();
     tokio::time::timeout(timeout, reader.read_line(&mut buf)).await??;
 
@@ -2135,7 +6449,7 @@ async fn check_node_health(node: Arc<Node>, addr: &str) -> Result<(), AnyErr> {
 }
 
 /// The healing process workflow
-async fn handle_node_death(node: Arc<Node>, dead_addr: String) -> Result<(), AnyErr> {
+async fn handle_node_death(node: Arc<Node>, dead_addr: String) -> Result<(), RingError> {
     tracing::info!(
         node = %node.port,
         dead_node = %dead_addr,
@@ -2180,7 +6494,35 @@ async fn handle_node_death(node: Arc<Node>, dead_addr: String) -> Result<(), Any
         // its own cwd and the on-disk content/ + backup/ directories the
         // dead process was writing to become orphaned. (NEXT_STEPS.md §1.5.)
         .arg("--storage-root")
-        .arg(&node.storage_root);
+        .arg(&node.storage_root)
+        .args(node.opts.tls.cli_args());
+    if let Some(name) = node.node_names.read().await.get(&dead_port) {
+        cmd.arg("--name").arg(name);
+    }
+    if node.opts.hello {
+        cmd.arg("--hello");
+    }
+    if node.opts.reuse_port {
+        cmd.arg("--reuse-port");
+    }
+    if node.opts.mux {
+        cmd.arg("--mux");
+    }
+    if let Some(group) = node.opts.announce {
+        cmd.arg("--announce").arg(group.to_string());
+    }
+    if node.opts.bind_backlog > 0 {
+        cmd.arg("--bind-backlog")
+            .arg(node.opts.bind_backlog.to_string());
+    }
+    if node.opts.recv_buf_size > 0 {
+        cmd.arg("--recv-buf-size")
+            .arg(node.opts.recv_buf_size.to_string());
+    }
+    if node.opts.send_buf_size > 0 {
+        cmd.arg("--send-buf-size")
+            .arg(node.opts.send_buf_size.to_string());
+    }
 
     // env_clear: don't leak our environment to the respawned child. Pass
     // through only what the child genuinely needs:
@@ -2241,13 +6583,12 @@ async fn handle_node_death(node: Arc<Node>, dead_addr: String) -> Result<(), Any
 }
 
 /// Sends all shared state to a newly spawned node
-async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<(), AnyErr> {
+async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<(), RingError> {
     let timeout = Duration::from_millis(500);
 
     // Share NETMAP
     let entries = node.get_network_nodes_entries().await;
-    let mut s_netmap = tokio::time::timeout(timeout, TcpStream::connect(new_node_addr)).await??;
-    send_auth(&mut s_netmap, &node.auth_token).await?;
+    let mut s_netmap = tokio::time::timeout(timeout, node.connect(new_node_addr)).await??;
     s_netmap
         .write_all(format!("NETMAP SET {}\n", entries).as_bytes())
         .await?;
@@ -2256,8 +6597,7 @@ async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<()
     // Share TOPOLOGY
     let history = node.get_topology_history().await;
     if !history.is_empty() {
-        let mut s_topo = tokio::time::timeout(timeout, TcpStream::connect(new_node_addr)).await??;
-        send_auth(&mut s_topo, &node.auth_token).await?;
+        let mut s_topo = tokio::time::timeout(timeout, node.connect(new_node_addr)).await??;
         s_topo
             .write_all(format!("TOPOLOGY SET {}\n", history).as_bytes())
             .await?;
@@ -2267,8 +6607,7 @@ async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<()
     // Share FILE TAGS
     let tags_entries = node.get_file_tags_entries().await;
     if !tags_entries.is_empty() {
-        let mut s_tags = tokio::time::timeout(timeout, TcpStream::connect(new_node_addr)).await??;
-        send_auth(&mut s_tags, &node.auth_token).await?;
+        let mut s_tags = tokio::time::timeout(timeout, node.connect(new_node_addr)).await??;
         s_tags
             .write_all(format!("FILE TAGS-SET {}\n", tags_entries).as_bytes())
             .await?;
@@ -2281,14 +6620,20 @@ async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<()
         // Reconstruct the full address from the healing node's host and the port
         let host = host_of(&node.port);
         let next_addr = format!("{}:{}", host, port);
-        let mut s_next = tokio::time::timeout(timeout, TcpStream::connect(new_node_addr)).await??;
-        send_auth(&mut s_next, &node.auth_token).await?;
+        let mut s_next = tokio::time::timeout(timeout, node.connect(new_node_addr)).await??;
         s_next
             .write_all(format!("NODE NEXT {}\n", next_addr).as_bytes())
             .await?;
         s_next.shutdown().await?;
     }
 
+    // We are the respawned node's predecessor, so we are its PREV.
+    let mut s_prev = tokio::time::timeout(timeout, node.connect(new_node_addr)).await??;
+    s_prev
+        .write_all(format!("NODE PREV {}\n", node.port).as_bytes())
+        .await?;
+    s_prev.shutdown().await?;
+
     // Anti-entropy refill (NEXT_STEPS.md §1.5b). The respawned node may
     // have lost its on-disk state if its storage_root was destroyed (disk
     // failure, accidental rm -rf). Walk our own backup/ — which holds
@@ -2315,7 +6660,7 @@ async fn share_data_with_new_node(node: &Node, new_node_addr: &str) -> Result<()
 /// `FILE CONTENT-PUSH`. The successor writes them into its content/
 /// directory. Skips orphan `*.partial` files (the startup janitor will
 /// also sweep these on the receiver side).
-async fn anti_entropy_refill_successor(node: &Node, successor_addr: &str) -> Result<(), AnyErr> {
+async fn anti_entropy_refill_successor(node: &Node, successor_addr: &str) -> Result<(), RingError> {
     let backup_dir = node.storage_root.join(port_str(&node.port)).join("backup");
     let mut entries = match fs::read_dir(&backup_dir).await {
         Ok(it) => it,
@@ -2377,9 +6722,13 @@ async fn anti_entropy_refill_successor(node: &Node, successor_addr: &str) -> Res
     Ok(())
 }
 
-async fn push_content_to(node: &Node, addr: &str, name: &str, body: &[u8]) -> Result<(), AnyErr> {
-    let mut s = TcpStream::connect(addr).await?;
-    send_auth(&mut s, &node.auth_token).await?;
+async fn push_content_to(
+    node: &Node,
+    addr: &str,
+    name: &str,
+    body: &[u8],
+) -> Result<(), RingError> {
+    let mut s = node.connect(addr).await?;
     let header = format!("FILE CONTENT-PUSH {} {}\n", name, body.len());
     s.write_all(header.as_bytes()).await?;
     if !body.is_empty() {
@@ -2569,11 +6918,10 @@ mod tests {
     }
 
     #[test]
-    fn host_of_ipv6_bracket_pin() {
-        // Current `split(':')` does not handle IPv6 brackets correctly;
-        // it stops at the first colon. Pin the behavior so we know if a
-        // future fix changes it.
-        assert_eq!(host_of("[::1]:7000"), "[");
+    fn host_of_ipv6_brackets() {
+        // Brackets are kept so `format!("{host}:{port}")` stays dialable.
+        assert_eq!(host_of("[::1]:7000"), "[::1]");
+        assert_eq!(format!("{}:{}", host_of("[::1]:7000"), 7001), "[::1]:7001");
     }
 
     // --- Additional edge-case anchors
@@ -2651,6 +6999,25 @@ mod tests {
         assert_eq!(host_of(""), "127.0.0.1");
     }
 
+    #[test]
+    fn forward_chain_counts_nested_forwards() {
+        assert_eq!(forward_chain_len("NODE PING"), 1);
+        assert_eq!(forward_chain_len("NODE FORWARD-TO a NODE PING"), 2);
+        assert_eq!(
+            forward_chain_len("NODE FORWARD-TO a NODE FORWARD-TO b KV GET k"),
+            3
+        );
+        assert_eq!(forward_chain_len("NODE FORWARD-TO a"), 1);
+    }
+
+    #[test]
+    fn command_label_keeps_noun_and_verb_only() {
+        assert_eq!(command_label("TOPOLOGY WALK\n"), "TOPOLOGY WALK");
+        assert_eq!(command_label("FILE PUSH 12 secret.txt\n"), "FILE PUSH");
+        assert_eq!(command_label("  NODE   PING "), "NODE PING");
+        assert_eq!(command_label(""), "");
+    }
+
     #[test]
     fn host_of_localhost_with_port() {
         // Named anchor for the literal-host-name case.
@@ -0,0 +1,168 @@
+//! Portable node state (`SNAPSHOT` / `RESTORE`, and the `snapshot` and
+//! `restore` subcommands).
+//!
+//! `SNAPSHOT` answers with a [`NodeSnapshot`] as one line of JSON, then
+//! `OK`. `RESTORE <json>` applies one to a node: its ring pointers, KV
+//! store, `INCR` counters, leader and role. A snapshot with a key missing,
+//! an unknown key, a version this build does not write, or a pointer or
+//! KV entry that `NODE NEXT` or `KV SET` would refuse is refused whole,
+//! before anything is applied. The pending walks, `NODE STAT`
+//! counters and generation are recorded for the reader only: walk replies
+//! would go to the old node, and the counters and generation describe the
+//! process that took the snapshot.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::RingError;
+use crate::node::NodeRole;
+
+/// The `version` written by this build and the only one `RESTORE` takes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Every key a snapshot must carry.
+const FIELDS: [&str; 14] = [
+    "version",
+    "addr",
+    "generation",
+    "next",
+    "prev",
+    "rings",
+    "next_multi",
+    "kv",
+    "counters",
+    "leader",
+    "role",
+    "walks",
+    "stats",
+    "taken_at_ms",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeSnapshot {
+    pub version: u32,
+    /// Where the node that took it was listening.
+    pub addr: String,
+    /// See [`crate::Node::generation`]. Not restored.
+    pub generation: u64,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+    /// `NODE NEXT-RING` pointers, by ring name.
+    pub rings: BTreeMap<String, String>,
+    /// `NODE NEXT-MULTI` hops; `next` is the first of them.
+    pub next_multi: Vec<String>,
+    pub kv: BTreeMap<String, String>,
+    /// This node's copy of each `INCR` counter.
+    pub counters: BTreeMap<String, u64>,
+    pub leader: Option<String>,
+    pub role: NodeRole,
+    /// Tokens of the walks waiting on the node. Not restored.
+    pub walks: Vec<String>,
+    /// `NODE STAT` counters. Not restored.
+    pub stats: BTreeMap<String, u64>,
+    pub taken_at_ms: u64,
+}
+
+impl NodeSnapshot {
+    /// The snapshot as one line of JSON, as `SNAPSHOT` sends it.
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("snapshot serializes")
+    }
+
+    /// Read and check a snapshot: every key present, none unknown, the
+    /// version this build writes, and `next` leading `next_multi`.
+    pub fn parse(json: &str) -> Result<Self, RingError> {
+        let bad = |msg: String| RingError::ParseError(format!("invalid snapshot: {msg}"));
+        let value: serde_json::Value =
+            serde_json::from_str(json.trim()).map_err(|e| bad(e.to_string()))?;
+        let Some(object) = value.as_object() else {
+            return Err(bad("not a JSON object".into()));
+        };
+        let missing: Vec<&str> = FIELDS
+            .into_iter()
+            .filter(|key| !object.contains_key(*key))
+            .collect();
+        if !missing.is_empty() {
+            return Err(bad(format!("missing {}", missing.join(", "))));
+        }
+        let snapshot: NodeSnapshot =
+            serde_json::from_value(value).map_err(|e| bad(e.to_string()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(bad(format!(
+                "version {} (this node reads {SNAPSHOT_VERSION})",
+                snapshot.version
+            )));
+        }
+        if let Some(first) = snapshot.next_multi.first()
+            && snapshot.next.as_ref() != Some(first)
+        {
+            return Err(bad(format!("next_multi starts with {first}, not next")));
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> NodeSnapshot {
+        NodeSnapshot {
+            version: SNAPSHOT_VERSION,
+            addr: "127.0.0.1:7000".into(),
+            generation: 3,
+            next: Some("127.0.0.1:7001".into()),
+            prev: Some("127.0.0.1:7002".into()),
+            rings: BTreeMap::from([("backup".into(), "127.0.0.1:7003".into())]),
+            next_multi: Vec::new(),
+            kv: BTreeMap::from([("color".into(), "dark blue".into())]),
+            counters: BTreeMap::from([("hits".into(), 4)]),
+            leader: Some("127.0.0.1:7002".into()),
+            role: NodeRole::Follower,
+            walks: vec!["7000-1".into()],
+            stats: BTreeMap::from([("WALK_COUNT".into(), 1)]),
+            taken_at_ms: 1_700_000_000_000,
+        }
+    }
+
+    #[test]
+    fn line_round_trips() {
+        let line = sample().to_line();
+        assert!(!line.contains('\n'));
+        assert_eq!(NodeSnapshot::parse(&line).unwrap(), sample());
+    }
+
+    #[test]
+    fn partial_or_malformed_snapshots_are_refused() {
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value.as_object_mut().unwrap().remove("prev");
+        let err = NodeSnapshot::parse(&value.to_string()).unwrap_err();
+        assert!(err.to_string().contains("missing prev"), "{err}");
+
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value["extra"] = 1.into();
+        assert!(NodeSnapshot::parse(&value.to_string()).is_err());
+
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value["role"] = "boss".into();
+        assert!(NodeSnapshot::parse(&value.to_string()).is_err());
+
+        let newer = NodeSnapshot {
+            version: SNAPSHOT_VERSION + 1,
+            ..sample()
+        };
+        assert!(NodeSnapshot::parse(&newer.to_line()).is_err());
+
+        let stray = NodeSnapshot {
+            next_multi: vec!["127.0.0.1:7009".into()],
+            ..sample()
+        };
+        assert!(NodeSnapshot::parse(&stray.to_line()).is_err());
+
+        for bad in ["", "[]", "{\"version\":1", "null"] {
+            assert!(NodeSnapshot::parse(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Persistent ring pointers and KV store (`--state-file`).
+//!
+//! A node started with a state file rewrites it whenever a `NODE NEXT*`,
+//! `NODE PREV` or `KV SET` changes its state, and reads it back in `bind`
+//! before the listener accepts anything, so a restarted process rejoins
+//! its rings without being re-wired. Each `bind` also bumps `generation`
+//! and saves it at once. Saves go to `<path>.tmp` and are renamed into
+//! place: a crash mid-write leaves the previous file intact.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeState {
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub prev: Option<String>,
+    /// `NODE NEXT-RING` pointers, by ring name; `next` is the default
+    /// ring's.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rings: BTreeMap<String, String>,
+    /// `NODE NEXT-MULTI` hops; `next` is the first of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_multi: Vec<String>,
+    /// `KV SET` entries; sorted so the file diffs cleanly.
+    #[serde(default)]
+    pub kv: BTreeMap<String, String>,
+    /// Generation of the process that last saved the file; see
+    /// [`crate::Node::generation`].
+    #[serde(default)]
+    pub generation: u64,
+}
+
+/// Read the state file. `Ok(None)` when it does not exist yet.
+pub async fn load_state(path: &Path) -> io::Result<Option<NodeState>> {
+    let raw = match fs::read(path).await {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let state =
+        serde_json::from_slice(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Some(state))
+}
+
+/// Atomically replace the state file: write and fsync `<path>.tmp`, then
+/// rename it over `path`.
+pub async fn save_state(path: &Path, state: &NodeState) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let body = serde_json::to_vec_pretty(state).map_err(io::Error::other)?;
+    {
+        let mut f = fs::File::create(&tmp).await?;
+        f.write_all(&body).await?;
+        f.sync_all().await?;
+    }
+    fs::rename(&tmp, path).await
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut os = path.as_os_str().to_owned();
+    os.push(".tmp");
+    PathBuf::from(os)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn missing_file_loads_as_none() {
+        let tmp = TempDir::new().unwrap();
+        let got = load_state(&tmp.path().join("state.json")).await.unwrap();
+        assert_eq!(got, None);
+    }
+
+    #[tokio::test]
+    async fn save_then_load_round_trips() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+        let state = NodeState {
+            next: Some("127.0.0.1:7001".into()),
+            prev: Some("127.0.0.1:7002".into()),
+            rings: BTreeMap::from([("backup".into(), "127.0.0.1:7003".into())]),
+            next_multi: vec!["127.0.0.1:7001".into(), "127.0.0.1:7004".into()],
+            kv: BTreeMap::from([("color".into(), "dark blue".into())]),
+            generation: 3,
+        };
+        save_state(&path, &state).await.unwrap();
+        assert_eq!(load_state(&path).await.unwrap(), Some(state));
+        assert!(!tmp_path(&path).exists(), "tmp file left behind");
+    }
+
+    #[tokio::test]
+    async fn save_overwrites_previous_state() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+        let first = NodeState {
+            next: Some("127.0.0.1:7001".into()),
+            prev: None,
+            ..Default::default()
+        };
+        let second = NodeState {
+            next: Some("127.0.0.1:7005".into()),
+            prev: None,
+            ..Default::default()
+        };
+        save_state(&path, &first).await.unwrap();
+        save_state(&path, &second).await.unwrap();
+        assert_eq!(load_state(&path).await.unwrap(), Some(second));
+    }
+
+    #[tokio::test]
+    async fn file_without_kv_loads_empty_store() {
+        // Written before the KV store existed.
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+        std::fs::write(&path, br#"{"next":"127.0.0.1:7001","prev":null}"#).unwrap();
+        let state = load_state(&path).await.unwrap().unwrap();
+        assert_eq!(state.next.as_deref(), Some("127.0.0.1:7001"));
+        assert!(state.kv.is_empty());
+        assert_eq!(state.generation, 0);
+    }
+
+    #[tokio::test]
+    async fn corrupt_file_is_invalid_data() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+        std::fs::write(&path, b"not json").unwrap();
+        let err = load_state(&path).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
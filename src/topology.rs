@@ -0,0 +1,323 @@
+//! Walk-history parsing and export for the `topology` subcommand.
+//!
+//! A `TOPOLOGY WALK` reply is one `from->to` edge per line (the same edges
+//! the ring passes around `;`-joined). Each endpoint is a bare port, a
+//! full `host:port`, or `name@<either>` for a named node. Endpoints are
+//! keyed by address; a name seen on any occurrence labels the node.
+//!
+//! [`merge_bidir`] folds a forward and a reverse walk from the same node
+//! into one undirected graph for `TOPOLOGY BIDIR-WALK`.
+//!
+//! [`ParallelTopology`] is the adjacency list `TOPOLOGY WALK-PARALLEL`
+//! answers with, built from the members' own `NODE STATUS` instead of a
+//! walk.
+
+use serde::{Deserialize, Serialize};
+
+/// One ring member as seen in the walk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopologyNode {
+    /// Address as it appears in the history (usually the bare port).
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Targets of this node's outgoing edges, in walk order.
+    pub next: Vec<String>,
+}
+
+impl TopologyNode {
+    /// The node's name when it has one, its address otherwise.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Parsed walk: nodes in first-seen order, so `nodes[0]` is the start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Topology {
+    pub start: String,
+    pub nodes: Vec<TopologyNode>,
+}
+
+/// Split `name@addr` into its parts. `@` never appears in an address, so
+/// the first one separates the name.
+fn parse_endpoint(raw: &str) -> Result<(Option<&str>, &str), String> {
+    let (name, addr) = match raw.split_once('@') {
+        Some((name, addr)) => (Some(name), addr),
+        None => (None, raw),
+    };
+    if addr.is_empty() || name.is_some_and(str::is_empty) {
+        return Err(format!("malformed endpoint: {raw:?}"));
+    }
+    Ok((name, addr))
+}
+
+/// Index of `addr` in `nodes`, appending it if new. A name fills in an
+/// unnamed entry but never replaces an existing one.
+fn upsert(nodes: &mut Vec<TopologyNode>, name: Option<&str>, addr: &str) -> usize {
+    let idx = match nodes.iter().position(|n| n.id == addr) {
+        Some(idx) => idx,
+        None => {
+            nodes.push(TopologyNode {
+                id: addr.to_string(),
+                name: None,
+                next: Vec::new(),
+            });
+            nodes.len() - 1
+        }
+    };
+    if let Some(name) = name {
+        nodes[idx].name.get_or_insert_with(|| name.to_string());
+    }
+    idx
+}
+
+/// Parse a walk history. Edges may be separated by `;` (wire form) or
+/// newlines (client reply); blank segments are skipped.
+pub fn parse_history(history: &str) -> Result<Topology, String> {
+    let mut nodes: Vec<TopologyNode> = Vec::new();
+    for seg in history.split([';', '\n']).map(str::trim) {
+        if seg.is_empty() {
+            continue;
+        }
+        let (from, to) = seg
+            .split_once("->")
+            .ok_or_else(|| format!("malformed edge: {seg:?}"))?;
+        let (from_name, from_addr) = parse_endpoint(from)?;
+        let (to_name, to_addr) = parse_endpoint(to)?;
+        let from_idx = upsert(&mut nodes, from_name, from_addr);
+        upsert(&mut nodes, to_name, to_addr);
+        nodes[from_idx].next.push(to_addr.to_string());
+    }
+
+    let start = nodes
+        .first()
+        .map(|n| n.id.clone())
+        .ok_or_else(|| "empty walk history".to_string())?;
+    Ok(Topology { start, nodes })
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Topology {
+    /// Graphviz `digraph`; the start node is filled.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph ring {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let style = if node.id == self.start {
+                ", style=filled, fillcolor=lightblue"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "    {} [label={}{style}];\n",
+                dot_quote(&node.id),
+                dot_quote(node.label())
+            ));
+        }
+        for node in &self.nodes {
+            for to in &node.next {
+                out.push_str(&format!(
+                    "    {} -> {};\n",
+                    dot_quote(&node.id),
+                    dot_quote(to)
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Adjacency list: `{"start": .., "nodes": [{"id", "name"?, "next"}]}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("topology serializes")
+    }
+}
+
+/// The heartbeat coordinator's members as `TOPOLOGY WALK-PARALLEL` found
+/// them, sorted by address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParallelTopology {
+    /// Members that answered, each with its NEXT.
+    pub nodes: Vec<TopologyNode>,
+    /// Some member failed or did not answer before the timeout.
+    pub partial: bool,
+    /// Those members' addresses.
+    pub unreachable: Vec<String>,
+}
+
+/// A forward and a reverse walk from the same node, merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BidirTopology {
+    pub start: String,
+    /// Every pair of neighbours either walk saw, once, in first-seen order.
+    pub edges: Vec<(String, String)>,
+    /// Both walks reached the same set of nodes.
+    pub symmetric: bool,
+}
+
+/// Merge the histories of a `TOPOLOGY WALK` and a `TOPOLOGY REVERSE-WALK`
+/// started on the same node. A forward `a->b` and a reverse `b->a` are
+/// the same undirected edge.
+pub fn merge_bidir(forward: &str, reverse: &str) -> Result<BidirTopology, String> {
+    let forward = parse_history(forward)?;
+    let reverse = parse_history(reverse)?;
+    let mut edges: Vec<(String, String)> = Vec::new();
+    for node in forward.nodes.iter().chain(&reverse.nodes) {
+        for to in &node.next {
+            let seen = edges
+                .iter()
+                .any(|(a, b)| (a, b) == (&node.id, to) || (a, b) == (to, &node.id));
+            if !seen {
+                edges.push((node.id.clone(), to.clone()));
+            }
+        }
+    }
+    let ids = |t: &Topology| {
+        let mut ids: Vec<&str> = t.nodes.iter().map(|n| n.id.as_str()).collect();
+        ids.sort_unstable();
+        ids.join(",")
+    };
+    let symmetric = ids(&forward) == ids(&reverse);
+    Ok(BidirTopology {
+        start: forward.start,
+        edges,
+        symmetric,
+    })
+}
+
+impl BidirTopology {
+    /// Graphviz undirected `graph`, labelled `symmetric` or `asymmetric`.
+    pub fn to_dot(&self) -> String {
+        let label = if self.symmetric {
+            "symmetric"
+        } else {
+            "asymmetric"
+        };
+        let mut out = format!("graph ring {{\n    label={};\n", dot_quote(label));
+        out.push_str(&format!(
+            "    {} [style=filled, fillcolor=lightblue];\n",
+            dot_quote(&self.start)
+        ));
+        for (a, b) in &self.edges {
+            out.push_str(&format!("    {} -- {};\n", dot_quote(a), dot_quote(b)));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(t: &Topology) -> Vec<&str> {
+        t.nodes.iter().map(|n| n.id.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_three_node_ring() {
+        let t = parse_history("7000->7001;7001->7002;7002->7000").unwrap();
+        assert_eq!(t.start, "7000");
+        assert_eq!(ids(&t), ["7000", "7001", "7002"]);
+        assert_eq!(t.nodes[2].next, ["7000"]);
+    }
+
+    #[test]
+    fn accepts_newline_separated_reply() {
+        let t = parse_history("7000->7001\n7001->7000\n").unwrap();
+        assert_eq!(ids(&t), ["7000", "7001"]);
+    }
+
+    #[test]
+    fn single_node_ring_points_at_itself() {
+        let t = parse_history("7000->7000").unwrap();
+        assert_eq!(ids(&t), ["7000"]);
+        assert_eq!(t.nodes[0].next, ["7000"]);
+    }
+
+    #[test]
+    fn names_label_nodes_from_any_occurrence() {
+        // The closing edge's target is left unlabelled by the ring.
+        let t = parse_history("node-00@7000->node-01@7001;node-01@7001->7000").unwrap();
+        assert_eq!(t.nodes[0].label(), "node-00");
+        assert_eq!(t.nodes[1].label(), "node-01");
+        assert_eq!(t.nodes[0].next, ["7001"]);
+    }
+
+    #[test]
+    fn addresses_with_colons() {
+        let t =
+            parse_history("127.0.0.1:7000->127.0.0.1:7001;127.0.0.1:7001->127.0.0.1:7000").unwrap();
+        assert_eq!(ids(&t), ["127.0.0.1:7000", "127.0.0.1:7001"]);
+
+        let t = parse_history("a@[::1]:7000->[::1]:7001;[::1]:7001->[::1]:7000").unwrap();
+        assert_eq!(ids(&t), ["[::1]:7000", "[::1]:7001"]);
+        assert_eq!(t.nodes[0].label(), "a");
+    }
+
+    #[test]
+    fn rejects_malformed_history() {
+        assert!(parse_history("").is_err());
+        assert!(parse_history("7000-7001").is_err());
+        assert!(parse_history("7000->").is_err());
+        assert!(parse_history("@7000->7001").is_err());
+    }
+
+    #[test]
+    fn dot_highlights_start_and_uses_names() {
+        let t = parse_history("n0@7000->7001;7001->7000").unwrap();
+        let dot = t.to_dot();
+        assert!(dot.starts_with("digraph ring {"), "{dot}");
+        assert!(
+            dot.contains("\"7000\" [label=\"n0\", style=filled"),
+            "{dot}"
+        );
+        assert!(dot.contains("\"7001\" [label=\"7001\"];"), "{dot}");
+        assert!(dot.contains("\"7000\" -> \"7001\";"), "{dot}");
+        assert!(dot.contains("\"7001\" -> \"7000\";"), "{dot}");
+    }
+
+    #[test]
+    fn json_is_an_adjacency_list() {
+        let t = parse_history("n0@7000->7001;7001->7000").unwrap();
+        let v: serde_json::Value = serde_json::from_str(&t.to_json()).unwrap();
+        assert_eq!(v["start"], "7000");
+        assert_eq!(v["nodes"][0]["name"], "n0");
+        assert_eq!(v["nodes"][0]["next"][0], "7001");
+        assert!(v["nodes"][1].get("name").is_none());
+    }
+
+    #[test]
+    fn bidir_merges_both_directions_into_undirected_edges() {
+        let t = merge_bidir(
+            "7000->7001;7001->7002;7002->7000",
+            "7000->7002;7002->7001;7001->7000",
+        )
+        .unwrap();
+        assert!(t.symmetric);
+        assert_eq!(
+            t.edges,
+            [("7000", "7001"), ("7001", "7002"), ("7002", "7000")]
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+        let dot = t.to_dot();
+        assert!(
+            dot.starts_with("graph ring {\n    label=\"symmetric\";"),
+            "{dot}"
+        );
+        assert!(dot.contains("\"7001\" -- \"7002\";"), "{dot}");
+    }
+
+    #[test]
+    fn bidir_walks_that_reach_different_nodes_are_asymmetric() {
+        // 7002's PREV skips 7001.
+        let t = merge_bidir("7000->7001;7001->7002;7002->7000", "7000->7002;7002->7000").unwrap();
+        assert!(!t.symmetric);
+        assert_eq!(t.edges.len(), 3);
+        assert!(t.to_dot().contains("label=\"asymmetric\""));
+        assert!(merge_bidir("7000->7001", "").is_err());
+    }
+}
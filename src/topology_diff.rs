@@ -0,0 +1,240 @@
+//! Config-driven rewiring (`dev-network --hot-reload`).
+//!
+//! [`watch`] polls the `--config` file and, whenever its text changes,
+//! [`diff`]s the new `[ring]` table against the one last applied and
+//! sends `NODE NEXT` to just the nodes whose NEXT moved. No node is
+//! started or stopped: one dropped from the file keeps its pointers, and
+//! a new entry must already be listening. A file that does not parse, or
+//! that `lint-ring` would reject, is logged and left unapplied.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use crate::client::{ClientOpts, RingClient};
+use crate::config::{Config, RingConfig, Section};
+use crate::lint;
+
+/// How often [`watch`] reads the file.
+pub const HOT_RELOAD_POLL: Duration = Duration::from_millis(500);
+
+/// One difference between two `[ring]` tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyChange {
+    /// `addr` now points at `next`; `old` is its NEXT in the old table,
+    /// `None` for an entry new to it.
+    SetNext {
+        addr: String,
+        old: Option<String>,
+        next: String,
+    },
+    /// `addr` is gone from the table. Nothing is sent to it.
+    Removed { addr: String },
+}
+
+impl fmt::Display for TopologyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyChange::SetNext { addr, old, next } => {
+                write!(f, "set-next {addr} -> {next}")?;
+                match old {
+                    Some(old) => write!(f, " (was {old})"),
+                    None => f.write_str(" (new)"),
+                }
+            }
+            TopologyChange::Removed { addr } => write!(f, "removed {addr}"),
+        }
+    }
+}
+
+/// The changes that take the wiring in `old` to the wiring in `new`: a
+/// [`TopologyChange::SetNext`] for each entry of `new` whose NEXT differs
+/// or that `old` lacks, in `new`'s order, then a
+/// [`TopologyChange::Removed`] for each entry only `old` has.
+pub fn diff(old: &RingConfig, new: &RingConfig) -> Vec<TopologyChange> {
+    let before: HashMap<&str, &str> = old
+        .nodes
+        .iter()
+        .map(|n| (n.addr.get_ref().as_str(), n.next.get_ref().as_str()))
+        .collect();
+    let after: HashSet<&str> = new
+        .nodes
+        .iter()
+        .map(|n| n.addr.get_ref().as_str())
+        .collect();
+    let mut changes: Vec<TopologyChange> = new
+        .nodes
+        .iter()
+        .filter_map(|n| {
+            let (addr, next) = (n.addr.get_ref(), n.next.get_ref());
+            match before.get(addr.as_str()) {
+                Some(&was) if was == next => None,
+                was => Some(TopologyChange::SetNext {
+                    addr: addr.clone(),
+                    old: was.map(|w| w.to_string()),
+                    next: next.clone(),
+                }),
+            }
+        })
+        .collect();
+    changes.extend(
+        old.nodes
+            .iter()
+            .map(|n| n.addr.get_ref())
+            .filter(|addr| !after.contains(addr.as_str()))
+            .map(|addr| TopologyChange::Removed { addr: addr.clone() }),
+    );
+    changes
+}
+
+/// Watch the config at `path`, reading it every `poll`, and apply each
+/// change to its `[ring]` table. The table the file holds when called is
+/// taken as already wired. Runs until the task is dropped.
+pub async fn watch(path: PathBuf, opts: ClientOpts, poll: Duration) {
+    let mut text = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+    let mut applied = Config::parse(&text, Section::Network)
+        .map(|c| c.ring)
+        .unwrap_or_default();
+    tracing::info!(config = %path.display(), nodes = applied.nodes.len(), "Watching config for ring changes");
+    let mut ticker = tokio::time::interval(poll);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        // Editors that save by renaming leave the file missing for a
+        // moment; the next poll picks it up.
+        let latest = match tokio::fs::read_to_string(&path).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                tracing::debug!(config = %path.display(), error = %e, "Config not readable");
+                continue;
+            }
+        };
+        if latest == text {
+            continue;
+        }
+        text = latest;
+        let config = match Config::parse(&text, Section::Network) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!(config = %path.display(), error = %e, "Config change not applied");
+                continue;
+            }
+        };
+        let problems = lint::lint(&config, &text);
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::warn!(config = %path.display(), %problem, "Config change not applied");
+            }
+            continue;
+        }
+        applied = apply(applied, config.ring, &opts).await;
+    }
+}
+
+/// Send what [`diff`] finds between `applied` and `new`. Returns the
+/// table to diff the next change against: `new`, or `applied` again if a
+/// node could not be rewired, so the next change to the file retries it.
+async fn apply(applied: RingConfig, new: RingConfig, opts: &ClientOpts) -> RingConfig {
+    let changes = diff(&applied, &new);
+    if changes.is_empty() {
+        return new;
+    }
+    let mut failed = false;
+    for change in &changes {
+        match change {
+            TopologyChange::SetNext { addr, next, .. } => {
+                match RingClient::with_opts(addr, opts.clone())
+                    .set_next(next)
+                    .await
+                {
+                    Ok(_) => tracing::info!(%change, "Applied ring change"),
+                    Err(e) => {
+                        tracing::error!(%change, error = ?e, "Failed to apply ring change");
+                        failed = true;
+                    }
+                }
+            }
+            TopologyChange::Removed { .. } => {
+                tracing::info!(%change, "Node left the config; its pointers are unchanged")
+            }
+        }
+    }
+    if failed { applied } else { new }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(edges: &[(&str, &str)]) -> RingConfig {
+        let mut raw = String::new();
+        for (addr, next) in edges {
+            raw.push_str(&format!(
+                "[[ring.nodes]]\naddr = \"{addr}\"\nnext = \"{next}\"\n"
+            ));
+        }
+        Config::parse(&raw, Section::Network).unwrap().ring
+    }
+
+    fn set_next(addr: &str, old: Option<&str>, next: &str) -> TopologyChange {
+        TopologyChange::SetNext {
+            addr: addr.into(),
+            old: old.map(Into::into),
+            next: next.into(),
+        }
+    }
+
+    #[test]
+    fn only_moved_pointers_are_sent() {
+        let old = ring(&[("a:1", "a:2"), ("a:2", "a:3"), ("a:3", "a:1")]);
+        assert!(diff(&old, &old).is_empty());
+
+        let reversed = ring(&[("a:1", "a:3"), ("a:2", "a:1"), ("a:3", "a:2")]);
+        assert_eq!(
+            diff(&old, &reversed),
+            [
+                set_next("a:1", Some("a:2"), "a:3"),
+                set_next("a:2", Some("a:3"), "a:1"),
+                set_next("a:3", Some("a:1"), "a:2"),
+            ]
+        );
+
+        // a:4 joins between a:3 and a:1: only a:3 and a:4 are touched.
+        let grown = ring(&[
+            ("a:1", "a:2"),
+            ("a:2", "a:3"),
+            ("a:3", "a:4"),
+            ("a:4", "a:1"),
+        ]);
+        assert_eq!(
+            diff(&old, &grown),
+            [
+                set_next("a:3", Some("a:1"), "a:4"),
+                set_next("a:4", None, "a:1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dropped_entries_are_reported_not_rewired() {
+        let old = ring(&[("a:1", "a:2"), ("a:2", "a:3"), ("a:3", "a:1")]);
+        let shrunk = ring(&[("a:1", "a:3"), ("a:3", "a:1")]);
+        let changes = diff(&old, &shrunk);
+        assert_eq!(
+            changes,
+            [
+                set_next("a:1", Some("a:2"), "a:3"),
+                TopologyChange::Removed { addr: "a:2".into() },
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "set-next a:1 -> a:3 (was a:2)");
+        assert_eq!(changes[1].to_string(), "removed a:2");
+        assert_eq!(
+            set_next("a:4", None, "a:1").to_string(),
+            "set-next a:4 -> a:1 (new)"
+        );
+    }
+}
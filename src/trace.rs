@@ -0,0 +1,535 @@
+//! Distributed tracing of ring traversals (`--otlp-endpoint`).
+//!
+//! `RING FORWARD` and `TOPOLOGY HOP` lines may end with
+//! ` X-Trace-Context: <traceparent>` (W3C Trace Context), so the spans each
+//! node records for one message or walk join into a single trace. The node
+//! that starts a traversal (a client's `RING FORWARD` or `TOPOLOGY WALK`)
+//! opens the root span; every hop opens a child of the context it received
+//! and forwards its own.
+//!
+//! Spans go to an OpenTelemetry collector as OTLP/HTTP JSON, posted in
+//! batches by a task that runs alongside the ring server. Without an
+//! endpoint nothing is recorded or generated; a context that arrives on a
+//! hop is forwarded unchanged so the nodes after this one still join the
+//! trace.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc, oneshot};
+
+use crate::protocol::{Command, CorrelationId};
+
+/// Separator between a protocol line and its trace context.
+pub const CONTEXT_FIELD: &str = " X-Trace-Context: ";
+
+/// How often buffered spans are posted to the collector.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Spans per request; a fuller buffer is posted right away.
+const MAX_BATCH: usize = 512;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+    static CURRENT_CID: CorrelationId;
+}
+
+/// A W3C `traceparent`: which trace a span belongs to, and which span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// A fresh trace.
+    pub fn root() -> Self {
+        let mut trace_id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut trace_id);
+        Self {
+            trace_id,
+            span_id: new_span_id(),
+            sampled: true,
+        }
+    }
+
+    /// A new span in the same trace.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: new_span_id(),
+            ..*self
+        }
+    }
+
+    /// Parse `00-<32 hex>-<16 hex>-<2 hex>`. All-zero ids are invalid.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('-');
+        let (version, trace, span, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || version != "00" || flags.len() != 2 {
+            return None;
+        }
+        let trace_id: [u8; 16] = decode_hex(trace)?.try_into().ok()?;
+        let span_id: [u8; 8] = decode_hex(span)?.try_into().ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            u8::from(self.sampled)
+        )
+    }
+}
+
+fn new_span_id() -> [u8; 8] {
+    let mut id = [0u8; 8];
+    while id == [0; 8] {
+        rand::thread_rng().fill_bytes(&mut id);
+    }
+    id
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Split a trailing trace context off `line`. A field that doesn't hold a
+/// valid `traceparent` is left in place as part of the line.
+pub fn split_context(line: &str) -> (&str, Option<TraceContext>) {
+    let trimmed = line.trim_end_matches(['\r', '\n']);
+    match trimmed.rsplit_once(CONTEXT_FIELD) {
+        Some((head, tp)) => match TraceContext::parse(tp) {
+            Some(ctx) => (head, Some(ctx)),
+            None => (line, None),
+        },
+        None => (line, None),
+    }
+}
+
+/// `line` with the context of the command being handled appended, if it
+/// has one. For outbound hop lines.
+pub fn with_context(line: String) -> String {
+    match CURRENT.try_with(|c| *c) {
+        Ok(ctx) => {
+            let head = line.trim_end_matches('\n');
+            format!("{head}{CONTEXT_FIELD}{ctx}\n")
+        }
+        Err(_) => line,
+    }
+}
+
+/// `line` behind the `CID:` header of the command being handled, if it
+/// came with one. For every outbound hop line.
+pub fn with_correlation_id(line: &str) -> Cow<'_, str> {
+    match CURRENT_CID.try_with(CorrelationId::header) {
+        Ok(header) if !header.is_empty() => Cow::Owned(header + line),
+        _ => Cow::Borrowed(line),
+    }
+}
+
+/// Run `fut` with `cid` as the ID [`with_correlation_id`] prepends.
+pub async fn cid_scope<F: Future>(cid: CorrelationId, fut: F) -> F::Output {
+    CURRENT_CID.scope(cid, fut).await
+}
+
+/// Run `fut` with `ctx` as the context [`with_context`] appends.
+pub async fn scope<F: Future>(ctx: Option<TraceContext>, fut: F) -> F::Output {
+    match ctx {
+        Some(ctx) => CURRENT.scope(ctx, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Where to post spans: an `http://host:port[/path]` URL. The path
+/// defaults to the OTLP `/v1/traces`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OtlpEndpoint {
+    authority: String,
+    path: String,
+}
+
+impl OtlpEndpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            format!("invalid OTLP endpoint '{url}': only http:// URLs are supported")
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            return Err(format!("invalid OTLP endpoint '{url}': missing host"));
+        }
+        let path = match path.trim_end_matches('/') {
+            "" => "/v1/traces".to_string(),
+            p => p.to_string(),
+        };
+        Ok(Self {
+            authority: authority.to_string(),
+            path,
+        })
+    }
+
+    /// `host:port` to connect to; port 80 when the URL has none.
+    fn connect_addr(&self) -> String {
+        match self.authority.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => self.authority.clone(),
+            _ => format!("{}:80", self.authority),
+        }
+    }
+}
+
+impl fmt::Display for OtlpEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority, self.path)
+    }
+}
+
+/// A span being timed by [`Tracer::start`].
+#[derive(Debug)]
+pub struct ActiveSpan {
+    /// The context hops sent while handling the command carry.
+    pub ctx: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start: SystemTime,
+    /// False for a context passed through by a node without an exporter.
+    recorded: bool,
+    /// Exported as the `ouroboros.cid` attribute.
+    pub cid: CorrelationId,
+}
+
+#[derive(Debug)]
+struct SpanData {
+    ctx: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    cid: CorrelationId,
+}
+
+/// Records spans for one node and hands them to the exporter task.
+#[derive(Debug, Default)]
+pub struct Tracer {
+    endpoint: Option<OtlpEndpoint>,
+    tx: Option<mpsc::UnboundedSender<SpanData>>,
+    rx: Mutex<Option<mpsc::UnboundedReceiver<SpanData>>>,
+}
+
+impl Tracer {
+    /// A tracer exporting to `endpoint`; `None` records nothing.
+    pub fn new(endpoint: Option<OtlpEndpoint>) -> Self {
+        let Some(endpoint) = endpoint else {
+            return Self::default();
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            endpoint: Some(endpoint),
+            tx: Some(tx),
+            rx: Mutex::new(Some(rx)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// Open a span for `cmd` if it is part of a traced traversal, as a
+    /// child of `parent` or as a new trace's root.
+    pub fn start(&self, cmd: &Command, parent: Option<TraceContext>) -> Option<ActiveSpan> {
+        let name = match cmd {
+            Command::RingForward { .. } => "RING FORWARD",
+            Command::TopologyWalk => "TOPOLOGY WALK",
+            Command::TopologyHop { .. } => "TOPOLOGY HOP",
+            _ => return None,
+        };
+        let recorded = self.is_enabled();
+        let ctx = match (parent, recorded) {
+            (Some(p), true) => p.child(),
+            (Some(p), false) => p,
+            (None, true) => TraceContext::root(),
+            (None, false) => return None,
+        };
+        Some(ActiveSpan {
+            ctx,
+            parent_span_id: parent.map(|p| p.span_id),
+            name,
+            start: SystemTime::now(),
+            recorded,
+            cid: CorrelationId::default(),
+        })
+    }
+
+    /// Close `span` and queue it for export.
+    pub fn end(&self, span: ActiveSpan) {
+        if let (Some(tx), true) = (&self.tx, span.recorded && span.ctx.sampled) {
+            let _ = tx.send(SpanData {
+                ctx: span.ctx,
+                parent_span_id: span.parent_span_id,
+                name: span.name,
+                start: span.start,
+                end: SystemTime::now(),
+                cid: span.cid,
+            });
+        }
+    }
+
+    /// Post recorded spans for `node` until `stop` fires, then flush what
+    /// is left. Returns at once if the tracer is disabled or already
+    /// exporting.
+    pub async fn export(&self, node: &str, mut stop: oneshot::Receiver<()>) {
+        let (Some(endpoint), Some(mut rx)) = (&self.endpoint, self.rx.lock().await.take()) else {
+            return;
+        };
+        tracing::info!(node = %node, endpoint = %endpoint, "Exporting trace spans");
+        let mut batch = Vec::new();
+        let mut tick = tokio::time::interval(EXPORT_INTERVAL);
+        loop {
+            tokio::select! {
+                span = rx.recv() => {
+                    let Some(span) = span else { break };
+                    batch.push(span);
+                    if batch.len() < MAX_BATCH {
+                        continue;
+                    }
+                }
+                _ = tick.tick() => {}
+                _ = &mut stop => break,
+            }
+            post_batch(endpoint, node, &mut batch).await;
+        }
+        while let Ok(span) = rx.try_recv() {
+            batch.push(span);
+        }
+        post_batch(endpoint, node, &mut batch).await;
+    }
+}
+
+async fn post_batch(endpoint: &OtlpEndpoint, node: &str, batch: &mut Vec<SpanData>) {
+    if batch.is_empty() {
+        return;
+    }
+    let body = otlp_json(node, batch).to_string();
+    let spans = batch.len();
+    batch.clear();
+    match tokio::time::timeout(EXPORT_TIMEOUT, post(endpoint, &body)).await {
+        Ok(Ok(status)) if status.starts_with('2') => {
+            tracing::debug!(node = %node, spans, "Exported trace spans");
+        }
+        Ok(Ok(status)) => {
+            tracing::warn!(node = %node, endpoint = %endpoint, status = %status, spans, "Collector rejected trace spans");
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(node = %node, endpoint = %endpoint, error = ?e, spans, "Trace export failed");
+        }
+        Err(_) => {
+            tracing::warn!(node = %node, endpoint = %endpoint, spans, "Trace export timed out");
+        }
+    }
+}
+
+/// POST `body` and return the response's status code.
+async fn post(endpoint: &OtlpEndpoint, body: &str) -> std::io::Result<String> {
+    let mut s = TcpStream::connect(endpoint.connect_addr()).await?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        endpoint.path,
+        endpoint.authority,
+        body.len()
+    );
+    s.write_all(request.as_bytes()).await?;
+    let mut resp = Vec::new();
+    let mut chunk = [0u8; 512];
+    while !resp.contains(&b'\n') {
+        let n = s.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        resp.extend_from_slice(&chunk[..n]);
+    }
+    let head = String::from_utf8_lossy(&resp);
+    Ok(head
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("no status")
+        .to_string())
+}
+
+fn unix_nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// An OTLP `ExportTraceServiceRequest` in its JSON encoding.
+fn otlp_json(node: &str, spans: &[SpanData]) -> serde_json::Value {
+    let spans: Vec<_> = spans
+        .iter()
+        .map(|s| {
+            let attributes: Vec<_> = s
+                .cid
+                .0
+                .iter()
+                .map(|id| json!({"key": "ouroboros.cid", "value": {"stringValue": id}}))
+                .collect();
+            json!({
+                "traceId": encode_hex(&s.ctx.trace_id),
+                "spanId": encode_hex(&s.ctx.span_id),
+                "parentSpanId": s.parent_span_id.map(|p| encode_hex(&p)).unwrap_or_default(),
+                "name": s.name,
+                // SPAN_KIND_SERVER: every span handles an inbound command.
+                "kind": 2,
+                "startTimeUnixNano": unix_nanos(s.start),
+                "endTimeUnixNano": unix_nanos(s.end),
+                "attributes": attributes,
+            })
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    {"key": "service.name", "value": {"stringValue": "ouroboros-fs"}},
+                    {"key": "service.instance.id", "value": {"stringValue": node}},
+                ]
+            },
+            "scopeSpans": [{
+                "scope": {"name": "ouroboros_fs"},
+                "spans": spans,
+            }]
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn traceparent_round_trips() {
+        let ctx = TraceContext::parse(TP).unwrap();
+        assert!(ctx.sampled);
+        assert_eq!(ctx.to_string(), TP);
+        assert_eq!(ctx.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        let child = ctx.child();
+        assert_eq!(child.trace_id, ctx.trace_id);
+        assert_ne!(child.span_id, ctx.span_id);
+    }
+
+    #[test]
+    fn malformed_traceparents_are_rejected() {
+        for bad in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e47-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-xx",
+            "00-4bf92f3577b34da6a3ce929d0e0e473g-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(TraceContext::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn split_context_strips_only_a_valid_field() {
+        let line = format!("RING FORWARD 2 hi there{CONTEXT_FIELD}{TP}\n");
+        let (head, ctx) = split_context(&line);
+        assert_eq!(head, "RING FORWARD 2 hi there");
+        assert_eq!(ctx.unwrap().to_string(), TP);
+
+        let line = format!("RING FORWARD 2 hi{CONTEXT_FIELD}not-a-context\n");
+        assert_eq!(split_context(&line), (line.as_str(), None));
+        assert_eq!(split_context("NODE PING\n"), ("NODE PING\n", None));
+    }
+
+    #[tokio::test]
+    async fn with_context_appends_the_current_context() {
+        let line = "TOPOLOGY HOP t a b c\n".to_string();
+        assert_eq!(with_context(line.clone()), line);
+
+        let ctx = TraceContext::parse(TP).unwrap();
+        let out = scope(Some(ctx), async { with_context(line) }).await;
+        assert_eq!(out, format!("TOPOLOGY HOP t a b c{CONTEXT_FIELD}{TP}\n"));
+    }
+
+    #[tokio::test]
+    async fn with_correlation_id_prepends_the_current_header() {
+        let line = "TOPOLOGY HOP t a b c\n";
+        assert_eq!(with_correlation_id(line), line);
+        let out = cid_scope(CorrelationId::default(), async {
+            with_correlation_id(line).into_owned()
+        })
+        .await;
+        assert_eq!(out, line);
+        let out = cid_scope(CorrelationId(Some("req-1".into())), async {
+            with_correlation_id(line).into_owned()
+        })
+        .await;
+        assert_eq!(out, format!("CID: req-1\n{line}"));
+    }
+
+    #[test]
+    fn disabled_tracer_passes_contexts_through() {
+        let tracer = Tracer::new(None);
+        assert!(tracer.start(&Command::TopologyWalk, None).is_none());
+        let parent = TraceContext::parse(TP).unwrap();
+        let span = tracer.start(&Command::TopologyWalk, Some(parent)).unwrap();
+        assert_eq!(span.ctx, parent);
+        assert!(tracer.start(&Command::NodePing, Some(parent)).is_none());
+    }
+
+    #[test]
+    fn endpoint_defaults_to_the_otlp_traces_path() {
+        let ep = OtlpEndpoint::parse("http://collector:4318").unwrap();
+        assert_eq!(ep.to_string(), "http://collector:4318/v1/traces");
+        assert_eq!(ep.connect_addr(), "collector:4318");
+        let ep = OtlpEndpoint::parse("http://collector/otel/traces/").unwrap();
+        assert_eq!(ep.to_string(), "http://collector/otel/traces");
+        assert_eq!(ep.connect_addr(), "collector:80");
+        assert!(OtlpEndpoint::parse("https://collector:4318").is_err());
+        assert!(OtlpEndpoint::parse("http:///v1/traces").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Optional TLS for ring TCP connections.
+//!
+//! A node configured with `--tls-cert` / `--tls-key` wraps every accepted
+//! connection in a rustls server session; one configured with `--tls-ca`
+//! wraps every outbound ring connection in a client session that verifies
+//! the peer against that CA. Without those flags both sides stay plain TCP
+//! and [`Stream::Plain`] is a zero-cost passthrough.
+//!
+//! Peer names are taken from the host part of the dialled address, so a
+//! ring wired by IP needs certificates with IP SANs.
+//!
+//! Co-located nodes can skip TCP entirely: an address of the form
+//! `unix:/path/to/sock` dials (and, via [`Transport::Unix`], listens on) a
+//! Unix domain socket. Those connections are never wrapped in TLS; access
+//! is governed by the socket file's permissions.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector, client, server};
+
+/// Listener- and dialer-side TLS settings. `Default` is plaintext on both.
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+    acceptor: Option<TlsAcceptor>,
+    connector: Option<TlsConnector>,
+    /// The PEM paths this config was loaded from, so the healer can hand
+    /// the same flags to a respawned neighbor.
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    ca: Option<PathBuf>,
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("server", &self.acceptor.is_some())
+            .field("client", &self.connector.is_some())
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Build from PEM files. `cert` and `key` must be given together; they
+    /// enable the server side. `ca` enables the client side.
+    pub fn from_pem_files(
+        cert: Option<&Path>,
+        key: Option<&Path>,
+        ca: Option<&Path>,
+    ) -> Result<Self, String> {
+        let acceptor = match (cert, key) {
+            (Some(cert), Some(key)) => Some(load_acceptor(cert, key)?),
+            (None, None) => None,
+            _ => return Err("--tls-cert and --tls-key must be given together".into()),
+        };
+        let connector = ca.map(load_connector).transpose()?;
+        Ok(Self {
+            acceptor,
+            connector,
+            cert: cert.map(Path::to_path_buf),
+            key: key.map(Path::to_path_buf),
+            ca: ca.map(Path::to_path_buf),
+        })
+    }
+
+    /// `--tls-cert/--tls-key/--tls-ca` flags reproducing this config in a
+    /// child process. Empty when TLS is disabled.
+    pub fn cli_args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        for (flag, path) in [
+            ("--tls-cert", &self.cert),
+            ("--tls-key", &self.key),
+            ("--tls-ca", &self.ca),
+        ] {
+            if let Some(path) = path {
+                args.push(flag.into());
+                args.push(path.clone().into_os_string());
+            }
+        }
+        args
+    }
+
+    pub fn is_server_enabled(&self) -> bool {
+        self.acceptor.is_some()
+    }
+
+    pub fn is_client_enabled(&self) -> bool {
+        self.connector.is_some()
+    }
+
+    /// Complete the server-side handshake on a freshly accepted socket.
+    /// Unix-socket connections pass through untouched.
+    pub async fn accept(&self, stream: Stream) -> io::Result<Stream> {
+        match (stream, &self.acceptor) {
+            (Stream::Plain(tcp), Some(acceptor)) => {
+                Ok(Stream::Server(Box::new(acceptor.accept(tcp).await?)))
+            }
+            (stream, _) => Ok(stream),
+        }
+    }
+
+    /// Dial `addr` and, if a CA is configured, complete the client-side
+    /// handshake against the address's host. A `unix:` address dials the
+    /// socket at that path instead, without TLS.
+    pub async fn connect(&self, addr: &str) -> io::Result<Stream> {
+        if let Some(path) = unix_path(addr) {
+            return connect_unix(path).await;
+        }
+        let tcp = TcpStream::connect(addr).await?;
+        let Some(connector) = &self.connector else {
+            return Ok(Stream::Plain(tcp));
+        };
+        let name = server_name(addr)?;
+        Ok(Stream::Client(Box::new(
+            connector.connect(name, tcp).await?,
+        )))
+    }
+}
+
+/// Scheme prefix marking a Unix domain socket address.
+pub const UNIX_SCHEME: &str = "unix:";
+
+/// The socket path of a `unix:/path/to/sock` address, `None` for TCP ones.
+pub fn unix_path(addr: &str) -> Option<&str> {
+    addr.strip_prefix(UNIX_SCHEME).filter(|p| !p.is_empty())
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &str) -> io::Result<Stream> {
+    Ok(Stream::Unix(UnixStream::connect(path).await?))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(path: &str) -> io::Result<Stream> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unix sockets are not supported on this platform: {path}"),
+    ))
+}
+
+/// A node's listening socket: TCP, or a Unix domain socket for nodes on
+/// the same host.
+pub enum Transport {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Transport {
+    /// Accept the next connection as a plain [`Stream`], with a printable
+    /// peer (`ip:port`, or `unix` for an unnamed socket client).
+    pub async fn accept(&self) -> io::Result<(Stream, String)> {
+        match self {
+            Transport::Tcp(l) => {
+                let (s, peer) = l.accept().await?;
+                Ok((Stream::Plain(s), peer.to_string()))
+            }
+            #[cfg(unix)]
+            Transport::Unix(l) => {
+                let (s, _) = l.accept().await?;
+                Ok((Stream::Unix(s), "unix".to_string()))
+            }
+        }
+    }
+}
+
+impl From<TcpListener> for Transport {
+    fn from(l: TcpListener) -> Self {
+        Transport::Tcp(l)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Transport {
+    fn from(l: UnixListener) -> Self {
+        Transport::Unix(l)
+    }
+}
+
+fn load_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|it| it.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("reading {}: {e}", cert.display()))?;
+    let key =
+        PrivateKeyDer::from_pem_file(key).map_err(|e| format!("reading {}: {e}", key.display()))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid TLS certificate/key: {e}"))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_connector(ca: &Path) -> Result<TlsConnector, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in
+        CertificateDer::pem_file_iter(ca).map_err(|e| format!("reading {}: {e}", ca.display()))?
+    {
+        let cert = cert.map_err(|e| format!("reading {}: {e}", ca.display()))?;
+        roots
+            .add(cert)
+            .map_err(|e| format!("invalid CA certificate in {}: {e}", ca.display()))?;
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+fn server_name(addr: &str) -> io::Result<ServerName<'static>> {
+    let host = match addr.rsplit_once(':') {
+        Some((host, _)) => host,
+        None => addr,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{host}: {e}")))
+}
+
+/// A ring connection, plaintext, TLS or Unix socket. Implements
+/// `AsyncRead` + `AsyncWrite` so handlers stay generic over the transport.
+pub enum Stream {
+    Plain(TcpStream),
+    Client(Box<client::TlsStream<TcpStream>>),
+    Server(Box<server::TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// The underlying TCP socket; `None` for a Unix-socket connection.
+    pub fn tcp(&self) -> Option<&TcpStream> {
+        match self {
+            Stream::Plain(s) => Some(s),
+            Stream::Client(s) => Some(s.get_ref().0),
+            Stream::Server(s) => Some(s.get_ref().0),
+            #[cfg(unix)]
+            Stream::Unix(_) => None,
+        }
+    }
+
+    /// Set `TCP_NODELAY` on the underlying socket; a no-op for a Unix
+    /// socket.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match self.tcp() {
+            Some(s) => s.set_nodelay(nodelay),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Client(s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Server(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Client(s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Server(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_flush(cx),
+            Stream::Client(s) => Pin::new(s).poll_flush(cx),
+            Stream::Server(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Client(s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Server(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_name_ipv4() {
+        assert!(matches!(
+            server_name("127.0.0.1:7000").unwrap(),
+            ServerName::IpAddress(_)
+        ));
+    }
+
+    #[test]
+    fn server_name_ipv6_brackets() {
+        assert!(matches!(
+            server_name("[::1]:7000").unwrap(),
+            ServerName::IpAddress(_)
+        ));
+    }
+
+    #[test]
+    fn server_name_hostname() {
+        assert!(matches!(
+            server_name("localhost:7000").unwrap(),
+            ServerName::DnsName(_)
+        ));
+    }
+
+    #[test]
+    fn cert_without_key_errs() {
+        let err = TlsConfig::from_pem_files(Some(Path::new("cert.pem")), None, None).unwrap_err();
+        assert!(err.contains("together"), "{err}");
+    }
+
+    #[test]
+    fn unix_path_strips_scheme() {
+        assert_eq!(unix_path("unix:/tmp/n0.sock"), Some("/tmp/n0.sock"));
+        assert_eq!(unix_path("unix:"), None);
+        assert_eq!(unix_path("127.0.0.1:7000"), None);
+    }
+
+    #[test]
+    fn default_is_plaintext() {
+        let tls = TlsConfig::default();
+        assert!(!tls.is_server_enabled());
+        assert!(!tls.is_client_enabled());
+        assert!(tls.cli_args().is_empty());
+    }
+}
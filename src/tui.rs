@@ -0,0 +1,576 @@
+//! Live terminal view of a ring for the `tui` subcommand.
+//!
+//! The view walks the ring from a seed node and shows every node it
+//! reached with its name, NEXT and `NODE STAT` counters, refreshed every
+//! `--refresh-ms`; the ring drawn as a loop of ports; and the seed's
+//! events as `SUBSCRIBE all` delivers them. Arrow keys (or `j`/`k`) pick
+//! a node, Enter pings it, `s` logs its full `NODE STAT`, `w` walks the
+//! ring again and `q`, Esc or Ctrl-C quits.
+//!
+//! The terminal is driven directly: raw mode through `termios`, the
+//! alternate screen and the whole frame redrawn with ANSI escapes after
+//! every change. [`render`] builds that frame as plain text, so layout is
+//! independent of the terminal.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{IsTerminal, Read, Write};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::client::{ClientOpts, RingClient, WalkResult};
+use crate::error::RingError;
+use crate::events::EventFilter;
+use crate::node::{normalize_addr_on, port_str, split_label};
+
+/// Default `--refresh-ms`.
+pub const DEFAULT_REFRESH: Duration = Duration::from_millis(500);
+
+/// Log lines kept for the events pane.
+const LOG_LINES: usize = 200;
+
+/// `NODE STAT` counters shown in the node list, with their column
+/// headings.
+const COLUMNS: [(&str, &str); 4] = [
+    ("MSGS_RECEIVED", "RECV"),
+    ("MSGS_FORWARDED", "FWD"),
+    ("ERRORS", "ERR"),
+    ("CONNECTIONS", "CONN"),
+];
+
+/// One node the walk reached.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeView {
+    /// Where the node is dialled.
+    pub addr: String,
+    pub name: Option<String>,
+    /// The walk's hop out of this node, as a port.
+    pub next: String,
+    /// The last `NODE STAT` counters; empty before the first answer.
+    pub stat: HashMap<String, String>,
+    /// Why the last `NODE STAT` failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Everything the view shows.
+#[derive(Debug, Clone, Default)]
+pub struct App {
+    pub seed: String,
+    pub refresh: Duration,
+    /// In walk order, starting at the seed.
+    pub nodes: Vec<NodeView>,
+    /// The walk closed back on the seed.
+    pub closed: bool,
+    pub selected: usize,
+    /// Oldest first.
+    pub log: VecDeque<String>,
+}
+
+impl App {
+    pub fn new(seed: &str, refresh: Duration) -> Self {
+        Self {
+            seed: seed.to_string(),
+            refresh,
+            ..Self::default()
+        }
+    }
+
+    /// Replace the node list with the nodes of `edges`, keeping the
+    /// counters of nodes that are still there.
+    pub fn set_walk(&mut self, edges: &WalkResult) {
+        let host = self
+            .seed
+            .rsplit_once(':')
+            .map_or(self.seed.as_str(), |(host, _)| host);
+        let mut old: HashMap<String, NodeView> =
+            self.nodes.drain(..).map(|n| (n.addr.clone(), n)).collect();
+        self.nodes = edges
+            .iter()
+            .map(|(from, to)| {
+                let (name, endpoint) = split_label(from);
+                let addr = normalize_addr_on(endpoint, host);
+                let prev = old.remove(&addr).unwrap_or_default();
+                NodeView {
+                    name: name.map(str::to_string),
+                    next: split_label(to).1.to_string(),
+                    addr,
+                    ..prev
+                }
+            })
+            .collect();
+        self.closed = edges
+            .last()
+            .is_some_and(|(_, to)| port_str(split_label(to).1) == port_str(&self.seed));
+        self.selected = self.selected.min(self.nodes.len().saturating_sub(1));
+    }
+
+    /// Move the selection by `delta` rows, stopping at either end.
+    pub fn select(&mut self, delta: isize) {
+        let last = self.nodes.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn log(&mut self, line: impl Into<String>) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line.into());
+    }
+
+    fn selected_addr(&self) -> Option<String> {
+        self.nodes.get(self.selected).map(|n| n.addr.clone())
+    }
+}
+
+/// A key the view acts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Enter,
+    Quit,
+    Char(char),
+}
+
+/// The keys in a chunk of raw-mode input. Escape sequences other than the
+/// arrows are skipped; a lone Esc quits.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x1b if bytes.get(i + 1) == Some(&b'[') => {
+                match bytes.get(i + 2) {
+                    Some(b'A') => keys.push(Key::Up),
+                    Some(b'B') => keys.push(Key::Down),
+                    _ => {}
+                }
+                i += 2;
+            }
+            0x1b | 0x03 | b'q' => keys.push(Key::Quit),
+            b'\r' | b'\n' => keys.push(Key::Enter),
+            b'k' => keys.push(Key::Up),
+            b'j' => keys.push(Key::Down),
+            b if b.is_ascii_graphic() => keys.push(Key::Char(b as char)),
+            _ => {}
+        }
+        i += 1;
+    }
+    keys
+}
+
+/// The frame for a `width` × `height` terminal, one string per row, with
+/// the selected node's row marked by `>`.
+pub fn render(app: &App, width: usize, height: usize) -> Vec<String> {
+    let inner = width.saturating_sub(4);
+    let mut rows = vec![fit(
+        &format!(
+            " ouroboros_fs | seed {} | {} nodes | refresh {} ms",
+            app.seed,
+            app.nodes.len(),
+            app.refresh.as_millis()
+        ),
+        width,
+    )];
+
+    let mut table = vec![format!(
+        "  {:<21} {:<10} {:<6}{}",
+        "NODE",
+        "NAME",
+        "NEXT",
+        COLUMNS
+            .iter()
+            .map(|(_, h)| format!(" {h:>7}"))
+            .collect::<String>()
+    )];
+    for (i, node) in app.nodes.iter().enumerate() {
+        let marker = if i == app.selected { '>' } else { ' ' };
+        let counters: String = match &node.error {
+            Some(e) => format!(" down: {e}"),
+            None => COLUMNS
+                .iter()
+                .map(|(k, _)| format!(" {:>7}", node.stat.get(*k).map_or("-", String::as_str)))
+                .collect(),
+        };
+        table.push(format!(
+            "{marker} {:<21} {:<10} {:<6}{counters}",
+            node.addr,
+            node.name.as_deref().unwrap_or("-"),
+            node.next,
+        ));
+    }
+    rows.extend(boxed("Nodes", &table, width, inner));
+    rows.extend(boxed("Ring", &ring_diagram(app), width, inner));
+
+    // The events pane takes what is left, less the help line.
+    let help = " ↑/↓ select  Enter ping  s stat  w walk  q quit";
+    let log_rows = height.saturating_sub(rows.len() + 3);
+    let skip = app.log.len().saturating_sub(log_rows);
+    let mut log: Vec<String> = app.log.iter().skip(skip).cloned().collect();
+    log.resize(log_rows, String::new());
+    rows.extend(boxed("Events", &log, width, inner));
+    rows.push(fit(help, width));
+    rows.truncate(height);
+    rows
+}
+
+/// The ring as a loop of ports, the selected one in brackets:
+///
+/// ```text
+/// ╭─▶ 7000 ─▶ [7001] ─▶ 7002 ─╮
+/// ╰───────────────────────────╯
+/// ```
+///
+/// A walk that did not close ends in an arrow to where it stopped.
+fn ring_diagram(app: &App) -> Vec<String> {
+    if app.nodes.is_empty() {
+        return vec!["(no walk yet)".into()];
+    }
+    let hops: Vec<String> = app
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let port = port_str(&n.addr);
+            if i == app.selected {
+                format!("[{port}]")
+            } else {
+                port.to_string()
+            }
+        })
+        .collect();
+    let chain = hops.join(" ─▶ ");
+    if app.closed {
+        let top = format!("╭─▶ {chain} ─╮");
+        let span = top.chars().count() - 2;
+        vec![top, format!("╰{}╯", "─".repeat(span))]
+    } else {
+        let end = app.nodes.last().map_or("", |n| n.next.as_str());
+        vec![format!("{chain} ─▶ {end} (open)")]
+    }
+}
+
+/// `lines` in a box `width` wide with `title` in its top edge.
+fn boxed(title: &str, lines: &[String], width: usize, inner: usize) -> Vec<String> {
+    let fill = width.saturating_sub(title.chars().count() + 5);
+    let mut rows = vec![format!("┌─ {title} {}┐", "─".repeat(fill))];
+    rows.extend(lines.iter().map(|l| format!("│ {} │", pad(l, inner))));
+    rows.push(format!("└{}┘", "─".repeat(width.saturating_sub(2))));
+    rows
+}
+
+/// `s` cut or padded to exactly `width` characters.
+fn pad(s: &str, width: usize) -> String {
+    format!("{:<width$}", fit(s, width))
+}
+
+/// `s` cut to at most `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+/// What the background tasks report back to the view.
+enum Update {
+    Walk(Result<WalkResult, RingError>),
+    Stat(String, Result<HashMap<String, String>, RingError>),
+    Log(String),
+}
+
+/// Raw mode and the alternate screen for as long as it lives.
+struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self, RingError> {
+        // SAFETY: tcgetattr fills the termios it is given.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut raw = saved;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) == -1 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        std::io::stdout().flush()?;
+        Ok(Self { saved })
+    }
+
+    /// Columns and rows, or 80 × 24 if the terminal won't say.
+    fn size() -> (usize, usize) {
+        // SAFETY: TIOCGWINSZ fills the winsize it is given.
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+        if ok && ws.ws_col > 0 && ws.ws_row > 0 {
+            (ws.ws_col as usize, ws.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+
+    fn draw(&self, app: &App) -> std::io::Result<()> {
+        let (width, height) = Self::size();
+        let mut out = String::from("\x1b[H");
+        for (i, row) in render(app, width, height).iter().enumerate() {
+            out.push_str(&format!("\x1b[{};1H", i + 1));
+            // The selected node's row is drawn in reverse video.
+            match row.strip_prefix("│ >") {
+                Some(rest) => out.push_str(&format!("│ \x1b[7m>{rest}\x1b[0m")),
+                None => out.push_str(row),
+            }
+            out.push_str("\x1b[K");
+        }
+        out.push_str("\x1b[J");
+        let mut stdout = std::io::stdout();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Run the view against the ring `seed` is on until the user quits.
+pub async fn run(seed: &str, opts: ClientOpts, refresh: Duration) -> Result<(), RingError> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err("tui needs a terminal".into());
+    }
+    let refresh = if refresh.is_zero() {
+        DEFAULT_REFRESH
+    } else {
+        refresh
+    };
+    let mut app = App::new(seed, refresh);
+    let (updates, mut rx) = mpsc::unbounded_channel();
+
+    // Blocking stdin reads stay off the runtime; the thread ends with
+    // the process.
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        let mut stdin = std::io::stdin();
+        while let Ok(n @ 1..) = stdin.read(&mut buf) {
+            for key in parse_keys(&buf[..n]) {
+                if keys_tx.send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let events = {
+        let client = RingClient::with_opts(seed, opts.clone());
+        let updates = updates.clone();
+        tokio::spawn(async move {
+            let mut stream = match client.subscribe(EventFilter::All).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = updates.send(Update::Log(format!("SUBSCRIBE failed: {e}")));
+                    return;
+                }
+            };
+            while let Ok(Some(line)) = stream.next_event().await {
+                if updates.send(Update::Log(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = updates.send(Update::Log("event stream closed".into()));
+        })
+    };
+
+    let terminal = Terminal::enter()?;
+    spawn_walk(seed, &opts, &updates);
+    let mut tick = tokio::time::interval(refresh);
+    let res = loop {
+        tokio::select! {
+            _ = tick.tick() => {
+                for node in &app.nodes {
+                    spawn_stat(&node.addr, &opts, &updates);
+                }
+            }
+            Some(update) = rx.recv() => match update {
+                Update::Walk(Ok(edges)) => {
+                    app.log(format!("WALK {} nodes", edges.len()));
+                    app.set_walk(&edges);
+                    for node in &app.nodes {
+                        spawn_stat(&node.addr, &opts, &updates);
+                    }
+                }
+                Update::Walk(Err(e)) => app.log(format!("WALK failed: {e}")),
+                Update::Stat(addr, res) => {
+                    if let Some(node) = app.nodes.iter_mut().find(|n| n.addr == addr) {
+                        match res {
+                            Ok(stat) => (node.stat, node.error) = (stat, None),
+                            Err(e) => node.error = Some(e.to_string()),
+                        }
+                    }
+                }
+                Update::Log(line) => app.log(line),
+            },
+            key = keys.recv() => match key {
+                None | Some(Key::Quit) => break Ok(()),
+                Some(Key::Up) => app.select(-1),
+                Some(Key::Down) => app.select(1),
+                Some(Key::Enter) => {
+                    if let Some(addr) = app.selected_addr() {
+                        spawn_ping(&addr, &opts, &updates);
+                    }
+                }
+                Some(Key::Char('s')) => {
+                    if let Some(addr) = app.selected_addr() {
+                        spawn_stat_log(&addr, &opts, &updates);
+                    }
+                }
+                Some(Key::Char('w')) => spawn_walk(seed, &opts, &updates),
+                Some(Key::Char(_)) => {}
+            },
+        }
+        if let Err(e) = terminal.draw(&app) {
+            break Err(e.into());
+        }
+    };
+    events.abort();
+    drop(terminal);
+    res
+}
+
+fn spawn_walk(seed: &str, opts: &ClientOpts, updates: &mpsc::UnboundedSender<Update>) {
+    let client = RingClient::with_opts(seed, opts.clone());
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let _ = updates.send(Update::Walk(client.walk().await));
+    });
+}
+
+fn spawn_stat(addr: &str, opts: &ClientOpts, updates: &mpsc::UnboundedSender<Update>) {
+    let client = RingClient::with_opts(addr, opts.clone());
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let res = client.stat().await;
+        let _ = updates.send(Update::Stat(client.addr().to_string(), res));
+    });
+}
+
+/// [`spawn_stat`], also logging every counter.
+fn spawn_stat_log(addr: &str, opts: &ClientOpts, updates: &mpsc::UnboundedSender<Update>) {
+    let client = RingClient::with_opts(addr, opts.clone());
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let res = client.stat().await;
+        let line = match &res {
+            Ok(stat) => {
+                let mut counters: Vec<String> =
+                    stat.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                counters.sort();
+                format!("STAT {} {}", client.addr(), counters.join(" "))
+            }
+            Err(e) => format!("STAT {} failed: {e}", client.addr()),
+        };
+        let _ = updates.send(Update::Log(line));
+        let _ = updates.send(Update::Stat(client.addr().to_string(), res));
+    });
+}
+
+fn spawn_ping(addr: &str, opts: &ClientOpts, updates: &mpsc::UnboundedSender<Update>) {
+    let client = RingClient::with_opts(addr, opts.clone());
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let start = std::time::Instant::now();
+        let line = match client.ping().await {
+            Ok(()) => format!("PING {} PONG in {:?}", client.addr(), start.elapsed()),
+            Err(e) => format!("PING {} failed: {e}", client.addr()),
+        };
+        let _ = updates.send(Update::Log(line));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walked(edges: &[(&str, &str)]) -> App {
+        let mut app = App::new("127.0.0.1:7000", DEFAULT_REFRESH);
+        let edges: WalkResult = edges
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+        app.set_walk(&edges);
+        app
+    }
+
+    #[test]
+    fn keys_from_raw_input() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[Bjk\rsw\x1b[C"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Down,
+                Key::Up,
+                Key::Enter,
+                Key::Char('s'),
+                Key::Char('w'),
+            ]
+        );
+        assert_eq!(parse_keys(b"\x1b"), [Key::Quit]);
+        assert_eq!(parse_keys(b"\x03"), [Key::Quit]);
+    }
+
+    #[test]
+    fn selection_stays_on_the_ring() {
+        let mut app = walked(&[("n0@7000", "7001"), ("7001", "7002"), ("7002", "7000")]);
+        app.select(-1);
+        assert_eq!(app.selected, 0);
+        app.select(5);
+        assert_eq!(app.selected, 2);
+
+        // A shorter walk pulls the selection back in.
+        app.set_walk(&vec![("7000".into(), "7000".into())]);
+        assert_eq!(app.selected, 0);
+    }
+
+    #[test]
+    fn frame_shows_nodes_ring_and_events() {
+        let mut app = walked(&[("n0@7000", "7001"), ("7001", "7000")]);
+        app.nodes[0]
+            .stat
+            .insert("MSGS_RECEIVED".into(), "12".into());
+        app.nodes[1].error = Some("connection refused".into());
+        app.select(1);
+        app.log("EVENT ring FORWARD 1 hi");
+
+        let frame = render(&app, 80, 20);
+        assert_eq!(frame.len(), 20);
+        assert!(
+            frame.iter().all(|row| row.chars().count() <= 80),
+            "{frame:#?}"
+        );
+        let text = frame.join("\n");
+        assert!(
+            text.contains("│   127.0.0.1:7000        n0         7001        12"),
+            "{text}"
+        );
+        assert!(text.contains("│ > 127.0.0.1:7001"), "{text}");
+        assert!(text.contains("down: connection refused"), "{text}");
+        assert!(text.contains("╭─▶ 7000 ─▶ [7001] ─╮"), "{text}");
+        assert!(text.contains("EVENT ring FORWARD 1 hi"), "{text}");
+    }
+
+    #[test]
+    fn open_walk_is_drawn_without_the_loop() {
+        let app = walked(&[("7000", "7001"), ("7001", "7002")]);
+        assert!(!app.closed);
+        assert_eq!(ring_diagram(&app), ["[7000] ─▶ 7001 ─▶ 7002 (open)"]);
+    }
+}
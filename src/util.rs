@@ -0,0 +1,95 @@
+//! Small helpers shared by the node and the CLI.
+
+use std::time::Duration;
+
+/// Exponential backoff: retry `n` (0-based) waits
+/// `initial_ms * multiplier^n`, capped at `max_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffConfig {
+    pub initial_ms: u64,
+    pub max_ms: u64,
+    pub multiplier: u32,
+}
+
+impl Default for BackoffConfig {
+    /// 5 ms doubling to 500 ms: quick on a fast machine, cheap on a slow one.
+    fn default() -> Self {
+        Self {
+            initial_ms: 5,
+            max_ms: 500,
+            multiplier: 2,
+        }
+    }
+}
+
+impl BackoffConfig {
+    /// Delay before retry `attempt`. Saturates rather than overflowing, so
+    /// any attempt count is safe.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = u64::from(self.multiplier).saturating_pow(attempt);
+        Duration::from_millis(self.initial_ms.saturating_mul(factor).min(self.max_ms))
+    }
+}
+
+/// Sleep for retry `attempt`'s delay and return it.
+pub async fn backoff_sleep(cfg: &BackoffConfig, attempt: u32) -> Duration {
+    let delay = cfg.delay(attempt);
+    tokio::time::sleep(delay).await;
+    delay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(cfg: &BackoffConfig, attempts: u32) -> Vec<u128> {
+        (0..attempts).map(|a| cfg.delay(a).as_millis()).collect()
+    }
+
+    #[test]
+    fn default_doubles_from_5ms_and_caps_at_500ms() {
+        assert_eq!(
+            ms(&BackoffConfig::default(), 10),
+            [5, 10, 20, 40, 80, 160, 320, 500, 500, 500]
+        );
+    }
+
+    #[test]
+    fn multiplier_one_is_constant() {
+        let cfg = BackoffConfig {
+            initial_ms: 50,
+            max_ms: 500,
+            multiplier: 1,
+        };
+        assert_eq!(ms(&cfg, 4), [50, 50, 50, 50]);
+    }
+
+    #[test]
+    fn huge_attempt_saturates_at_max() {
+        let cfg = BackoffConfig::default();
+        assert_eq!(cfg.delay(u32::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn initial_above_max_is_capped() {
+        let cfg = BackoffConfig {
+            initial_ms: 1_000,
+            max_ms: 200,
+            multiplier: 3,
+        };
+        assert_eq!(cfg.delay(0), Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn backoff_sleep_waits_the_delay() {
+        let cfg = BackoffConfig {
+            initial_ms: 10,
+            max_ms: 100,
+            multiplier: 2,
+        };
+        let start = std::time::Instant::now();
+        let slept = backoff_sleep(&cfg, 1).await;
+        assert_eq!(slept, Duration::from_millis(20));
+        assert!(start.elapsed() >= slept);
+    }
+}
@@ -0,0 +1,186 @@
+//! Vector clocks on `RING` messages.
+//!
+//! A client may send a `VC: <node>:<count>,...` header line ahead of a
+//! `RING` command, the way it sends `CID:`. The node that receives it
+//! merges the clock into its own, bumps its own entry, and sends the
+//! result ahead of every line it sends while handling the command, so the
+//! clock follows the message round the ring. A message whose clock is
+//! neither before nor after the previous one the node received is logged
+//! as `CONCURRENT_EVENTS`. `NODE STAT` reports the node's clock as
+//! `VECTOR_CLOCK`.
+//!
+//! Node entries are keyed by listen address; the count is split off at
+//! the last `:`, so `127.0.0.1:7000:3` reads as `127.0.0.1:7000` at 3.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::error::RingError;
+
+/// Prefix of the header line that carries a message's vector clock.
+pub const VC_HEADER: &str = "VC: ";
+
+tokio::task_local! {
+    static CURRENT_VC: VectorClock;
+}
+
+/// Events seen per node. A node with no entry has seen none.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VectorClock(pub HashMap<String, u64>);
+
+impl VectorClock {
+    /// Count one more event at `node`.
+    pub fn increment(&mut self, node: &str) {
+        *self.0.entry(node.to_string()).or_insert(0) += 1;
+    }
+
+    /// Take the larger count for every node in either clock.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (node, &count) in &other.0 {
+            let entry = self.0.entry(node.clone()).or_insert(0);
+            *entry = (*entry).max(count);
+        }
+    }
+
+    /// `Less` if this clock happened before `other`, `Greater` if after,
+    /// `Equal` if they match, `None` if they are concurrent.
+    pub fn compare(&self, other: &VectorClock) -> Option<Ordering> {
+        let get = |c: &VectorClock, node: &str| c.0.get(node).copied().unwrap_or(0);
+        let (mut less, mut greater) = (false, false);
+        for node in self.0.keys().chain(other.0.keys()) {
+            match get(self, node).cmp(&get(other, node)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+
+    /// `node:count` pairs joined by `,`, sorted by node; `-` when empty.
+    pub fn to_wire(&self) -> String {
+        if self.0.is_empty() {
+            return "-".to_string();
+        }
+        let mut entries: Vec<_> = self.0.iter().collect();
+        entries.sort();
+        entries
+            .iter()
+            .map(|(node, count)| format!("{node}:{count}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Read what [`VectorClock::to_wire`] writes.
+    pub fn from_wire(s: &str) -> Result<Self, RingError> {
+        let s = s.trim();
+        if s == "-" {
+            return Ok(Self::default());
+        }
+        let mut clock = HashMap::new();
+        for entry in s.split(',') {
+            let parsed = entry
+                .rsplit_once(':')
+                .filter(|(node, _)| !node.is_empty() && !node.contains(char::is_whitespace))
+                .and_then(|(node, count)| Some((node, count.parse::<u64>().ok()?)));
+            let Some((node, count)) = parsed else {
+                return Err(RingError::ParseError(format!(
+                    "invalid VC entry {entry:?}: want <node>:<count>"
+                )));
+            };
+            clock.insert(node.to_string(), count);
+        }
+        Ok(Self(clock))
+    }
+
+    /// The `VC: ...` line to send ahead of a command.
+    pub fn header(&self) -> String {
+        format!("{VC_HEADER}{}\n", self.to_wire())
+    }
+}
+
+/// `Some` when `line` is a `VC:` header: the clock, or why it was
+/// rejected.
+pub fn parse_vector_clock(line: &str) -> Option<Result<VectorClock, RingError>> {
+    let wire = line
+        .trim_end_matches(['\r', '\n'])
+        .strip_prefix(VC_HEADER.trim_end())?;
+    Some(VectorClock::from_wire(wire))
+}
+
+/// `line` behind the `VC:` header of the `RING` message being handled,
+/// if it came with one. For every outbound hop line.
+pub fn with_vector_clock(line: &str) -> Cow<'_, str> {
+    match CURRENT_VC.try_with(VectorClock::header) {
+        Ok(header) => Cow::Owned(header + line),
+        Err(_) => Cow::Borrowed(line),
+    }
+}
+
+/// Run `fut` with `clock`, if any, as the one [`with_vector_clock`]
+/// prepends.
+pub async fn scope<F: Future>(clock: Option<VectorClock>, fut: F) -> F::Output {
+    match clock {
+        Some(clock) => CURRENT_VC.scope(clock, fut).await,
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vc(entries: &[(&str, u64)]) -> VectorClock {
+        VectorClock(entries.iter().map(|(n, c)| (n.to_string(), *c)).collect())
+    }
+
+    #[test]
+    fn compare_orders_and_detects_concurrency() {
+        let a = vc(&[("x", 1), ("y", 2)]);
+        assert_eq!(a.compare(&a.clone()), Some(Ordering::Equal));
+        assert_eq!(a.compare(&vc(&[("x", 1), ("y", 3)])), Some(Ordering::Less));
+        assert_eq!(a.compare(&vc(&[("x", 1)])), Some(Ordering::Greater));
+        assert_eq!(a.compare(&vc(&[("x", 2), ("y", 1)])), None);
+        assert_eq!(a.compare(&vc(&[("z", 1)])), None);
+        assert_eq!(VectorClock::default().compare(&a), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn increment_and_merge() {
+        let mut a = vc(&[("x", 1)]);
+        a.increment("x");
+        a.increment("y");
+        a.merge(&vc(&[("x", 1), ("z", 4)]));
+        assert_eq!(a, vc(&[("x", 2), ("y", 1), ("z", 4)]));
+    }
+
+    #[test]
+    fn wire_round_trip() {
+        let a = vc(&[("127.0.0.1:7001", 2), ("127.0.0.1:7000", 3)]);
+        assert_eq!(a.to_wire(), "127.0.0.1:7000:3,127.0.0.1:7001:2");
+        assert_eq!(VectorClock::from_wire(&a.to_wire()).unwrap(), a);
+        assert_eq!(VectorClock::default().to_wire(), "-");
+        assert_eq!(VectorClock::from_wire("-").unwrap(), VectorClock::default());
+        for bad in ["", "x", "x:", ":1", "x:1,", "x:-1", "a b:1"] {
+            assert!(VectorClock::from_wire(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn header_lines_are_recognised() {
+        assert_eq!(
+            parse_vector_clock("VC: a:1,b:2\r\n").unwrap().unwrap(),
+            vc(&[("a", 1), ("b", 2)])
+        );
+        assert!(parse_vector_clock("VC: nope\n").unwrap().is_err());
+        assert!(parse_vector_clock("RING FORWARD 1 VC: a:1\n").is_none());
+        assert_eq!(vc(&[("a", 1)]).header(), "VC: a:1\n");
+    }
+}
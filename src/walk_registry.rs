@@ -0,0 +1,108 @@
+//! Pending walks on their start node, by token.
+//!
+//! A node that starts a walk (`TOPOLOGY WALK`, `GATHER`, a partition probe
+//! and the rest) registers the walk's token and waits on the receiver it
+//! gets back. The `DONE` line that ends the walk delivers the history to
+//! it; a client's `TOPOLOGY ABORT`, or the start node giving up, aborts it
+//! instead. Either way the token is forgotten, so a second `DONE` for it is
+//! ignored.
+
+use std::collections::HashMap;
+
+use tokio::sync::{Mutex, oneshot};
+
+/// Delivered to a walk's waiter by [`WalkRegistry::abort`] in place of the
+/// collected history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkAborted;
+
+/// What a registered walk's receiver yields: the `;`-joined history from
+/// [`WalkRegistry::deliver`], or [`WalkAborted`].
+pub type WalkResult = Result<String, WalkAborted>;
+
+#[derive(Debug, Default)]
+pub struct WalkRegistry {
+    pending: Mutex<HashMap<String, oneshot::Sender<WalkResult>>>,
+}
+
+impl WalkRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for walk `token`. Registering a token again replaces the
+    /// earlier waiter, whose receiver then errors.
+    pub async fn register(&self, token: &str) -> oneshot::Receiver<WalkResult> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(token.to_string(), tx);
+        rx
+    }
+
+    /// Hand `history` to walk `token`'s waiter. False if the token is
+    /// unknown: never registered, or already delivered or aborted.
+    pub async fn deliver(&self, token: &str, history: String) -> bool {
+        self.finish(token, Ok(history)).await
+    }
+
+    /// Cancel walk `token`: its waiter gets [`WalkAborted`]. False if the
+    /// token is unknown.
+    pub async fn abort(&self, token: &str) -> bool {
+        self.finish(token, Err(WalkAborted)).await
+    }
+
+    /// Whether walk `token` is still waiting for its `DONE`.
+    pub async fn is_pending(&self, token: &str) -> bool {
+        self.pending.lock().await.contains_key(token)
+    }
+
+    /// Tokens of the walks still waiting, sorted.
+    pub async fn tokens(&self) -> Vec<String> {
+        let mut tokens: Vec<String> = self.pending.lock().await.keys().cloned().collect();
+        tokens.sort();
+        tokens
+    }
+
+    async fn finish(&self, token: &str, result: WalkResult) -> bool {
+        match self.pending.lock().await.remove(token) {
+            Some(tx) => {
+                let _ = tx.send(result);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn deliver_reaches_the_waiter_once() {
+        let walks = WalkRegistry::new();
+        let rx = walks.register("7000-1").await;
+        assert!(walks.is_pending("7000-1").await);
+        assert!(walks.deliver("7000-1", "7000->7001".into()).await);
+        assert_eq!(rx.await.unwrap(), Ok("7000->7001".to_string()));
+        assert!(!walks.is_pending("7000-1").await);
+        assert!(!walks.deliver("7000-1", "late".into()).await);
+    }
+
+    #[tokio::test]
+    async fn abort_wakes_the_waiter_and_drops_the_token() {
+        let walks = WalkRegistry::new();
+        let rx = walks.register("7000-2").await;
+        assert!(walks.abort("7000-2").await);
+        assert_eq!(rx.await.unwrap(), Err(WalkAborted));
+        assert!(!walks.abort("7000-2").await);
+        assert!(!walks.deliver("7000-2", String::new()).await);
+    }
+
+    #[tokio::test]
+    async fn unknown_tokens_are_ignored() {
+        let walks = WalkRegistry::new();
+        let _rx = walks.register("7000-3").await;
+        assert!(!walks.deliver("7000-4", String::new()).await);
+        assert!(walks.is_pending("7000-3").await);
+    }
+}
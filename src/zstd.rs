@@ -0,0 +1,1316 @@
+//! Zstandard frames (RFC 8878), the format [`crate::codec`] puts on the
+//! wire.
+//!
+//! [`compress`] writes standard single-segment frames with their content
+//! size: compressed blocks whose literals are stored raw and whose
+//! sequences use the predefined FSE tables, or a raw block where that
+//! comes out no shorter. Any zstd decoder reads them. [`decompress`]
+//! reads any frame that does not need a dictionary, Huffman-coded
+//! literals, custom FSE tables and repeat offsets included, and checks
+//! the content checksum when the frame has one.
+
+use crate::codec::CodecError;
+
+type Result<T> = std::result::Result<T, CodecError>;
+
+const MAGIC: u32 = 0xFD2F_B528;
+/// Skippable frames have magic numbers `0x184D2A50..=0x184D2A5F`.
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50;
+
+/// Largest block, compressed or not, and largest block content.
+const BLOCK_MAX: usize = 128 << 10;
+
+const BLOCK_RAW: u32 = 0;
+const BLOCK_RLE: u32 = 1;
+const BLOCK_COMPRESSED: u32 = 2;
+
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 16;
+/// Farthest match the encoder takes: the predefined offset table codes
+/// offsets up to `2^29 - 4`.
+const MAX_OFFSET: usize = (1 << 29) - 4;
+
+/// Baseline and extra bits of every literal-length code.
+const LL_CODES: [(u32, u8); 36] = code_table(
+    0,
+    &[
+        (16, 1),
+        (18, 1),
+        (20, 1),
+        (22, 1),
+        (24, 2),
+        (28, 2),
+        (32, 3),
+        (40, 3),
+        (48, 4),
+        (64, 6),
+        (128, 7),
+        (256, 8),
+        (512, 9),
+        (1024, 10),
+        (2048, 11),
+        (4096, 12),
+        (8192, 13),
+        (16384, 14),
+        (32768, 15),
+        (65536, 16),
+    ],
+);
+
+/// Baseline and extra bits of every match-length code.
+const ML_CODES: [(u32, u8); 53] = code_table(
+    3,
+    &[
+        (35, 1),
+        (37, 1),
+        (39, 1),
+        (41, 1),
+        (43, 2),
+        (47, 2),
+        (51, 3),
+        (59, 3),
+        (67, 4),
+        (83, 4),
+        (99, 5),
+        (131, 7),
+        (259, 8),
+        (515, 9),
+        (1027, 10),
+        (2051, 11),
+        (4099, 12),
+        (8195, 13),
+        (16387, 14),
+        (32771, 15),
+        (65539, 16),
+    ],
+);
+
+/// Offset codes go up to 31: the code is the number of extra bits.
+const MAX_OF_CODE: usize = 31;
+
+/// `N` codes: the first ones stand for `first`, `first + 1`, ... with no
+/// extra bits, the rest are `tail`.
+const fn code_table<const N: usize>(first: u32, tail: &[(u32, u8)]) -> [(u32, u8); N] {
+    let mut table = [(0, 0); N];
+    let direct = N - tail.len();
+    let mut i = 0;
+    while i < N {
+        table[i] = if i < direct {
+            (first + i as u32, 0)
+        } else {
+            tail[i - direct]
+        };
+        i += 1;
+    }
+    table
+}
+
+// Predefined distributions, with their accuracy logs.
+const LL_DEFAULT: (&[i16], u8) = (
+    &[
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const ML_DEFAULT: (&[i16], u8) = (
+    &[
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const OF_DEFAULT: (&[i16], u8) = (
+    &[
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    5,
+);
+
+const LL_MAX_LOG: u8 = 9;
+const ML_MAX_LOG: u8 = 9;
+const OF_MAX_LOG: u8 = 8;
+const HUF_WEIGHT_MAX_LOG: u8 = 6;
+const HUF_MAX_BITS: u32 = 11;
+
+fn highbit(n: u32) -> u32 {
+    31 - n.leading_zeros()
+}
+
+// --- Compression
+
+/// `input` as one zstd frame.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_le_bytes().to_vec();
+    // Single segment, no checksum, no dictionary; the content size takes
+    // the smallest field that holds it.
+    let len = input.len();
+    if len < 256 {
+        out.push(0x20);
+        out.push(len as u8);
+    } else if len < 256 + (1 << 16) {
+        out.push(0x60);
+        out.extend_from_slice(&((len - 256) as u16).to_le_bytes());
+    } else if let Ok(len) = u32::try_from(len) {
+        out.push(0xA0);
+        out.extend_from_slice(&len.to_le_bytes());
+    } else {
+        out.push(0xE0);
+        out.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+    if input.is_empty() {
+        write_block_header(&mut out, true, BLOCK_RAW, 0);
+        return out;
+    }
+
+    let tables = [
+        FseEncoder::new(LL_DEFAULT),
+        FseEncoder::new(OF_DEFAULT),
+        FseEncoder::new(ML_DEFAULT),
+    ];
+    // Position + 1 of the last 4-byte sequence with each hash; 0 is empty.
+    let mut hashes = vec![0usize; 1 << HASH_BITS];
+    let mut start = 0;
+    while start < len {
+        let end = (start + BLOCK_MAX).min(len);
+        let sequences = find_sequences(input, start, end, &mut hashes);
+        let block = encode_block(&input[..end], start, &sequences, &tables);
+        if block.len() < end - start {
+            write_block_header(&mut out, end == len, BLOCK_COMPRESSED, block.len());
+            out.extend_from_slice(&block);
+        } else {
+            write_block_header(&mut out, end == len, BLOCK_RAW, end - start);
+            out.extend_from_slice(&input[start..end]);
+        }
+        start = end;
+    }
+    out
+}
+
+fn write_block_header(out: &mut Vec<u8>, last: bool, kind: u32, size: usize) {
+    let header = u32::from(last) | kind << 1 | (size as u32) << 3;
+    out.extend_from_slice(&header.to_le_bytes()[..3]);
+}
+
+/// A run of literals followed by a match.
+struct Sequence {
+    literals: usize,
+    offset: usize,
+    length: usize,
+}
+
+/// Greedy matches for `input[start..end]`, found through a single-entry
+/// hash table that carries over from earlier blocks, so matches reach
+/// back across them.
+fn find_sequences(input: &[u8], start: usize, end: usize, hashes: &mut [usize]) -> Vec<Sequence> {
+    let mut sequences = Vec::new();
+    let mut anchor = start;
+    let mut i = start;
+    while i + MIN_MATCH <= end {
+        let seq = u32::from_le_bytes([input[i], input[i + 1], input[i + 2], input[i + 3]]);
+        let h = (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+        let candidate = hashes[h];
+        hashes[h] = i + 1;
+        if let Some(c) = candidate.checked_sub(1)
+            && i - c <= MAX_OFFSET
+            && input[c..c + MIN_MATCH] == input[i..i + MIN_MATCH]
+        {
+            let mut length = MIN_MATCH;
+            while i + length < end && input[c + length] == input[i + length] {
+                length += 1;
+            }
+            sequences.push(Sequence {
+                literals: i - anchor,
+                offset: i - c,
+                length,
+            });
+            i += length;
+            anchor = i;
+            continue;
+        }
+        i += 1;
+    }
+    sequences
+}
+
+/// A compressed block for the content of `input` from `start` on: raw
+/// literals, then `sequences` coded with the predefined tables.
+fn encode_block(
+    input: &[u8],
+    start: usize,
+    sequences: &[Sequence],
+    [ll_table, of_table, ml_table]: &[FseEncoder; 3],
+) -> Vec<u8> {
+    let mut literals = Vec::new();
+    let mut pos = start;
+    for s in sequences {
+        literals.extend_from_slice(&input[pos..pos + s.literals]);
+        pos += s.literals + s.length;
+    }
+    literals.extend_from_slice(&input[pos..]);
+
+    let mut out = Vec::with_capacity(literals.len() + sequences.len() * 4 + 8);
+    let n = literals.len();
+    if n < 32 {
+        out.push((n << 3) as u8);
+    } else if n < 4096 {
+        out.extend_from_slice(&[0b0100 | (n << 4) as u8, (n >> 4) as u8]);
+    } else {
+        out.extend_from_slice(&[0b1100 | (n << 4) as u8, (n >> 4) as u8, (n >> 12) as u8]);
+    }
+    out.extend_from_slice(&literals);
+
+    let n = sequences.len();
+    if n < 128 {
+        out.push(n as u8);
+    } else if n < 0x7F00 {
+        out.extend_from_slice(&[(n >> 8) as u8 + 0x80, n as u8]);
+    } else {
+        out.push(0xFF);
+        out.extend_from_slice(&((n - 0x7F00) as u16).to_le_bytes());
+    }
+    let Some((last, rest)) = sequences.split_last() else {
+        return out;
+    };
+    // Predefined tables for all three codes.
+    out.push(0);
+
+    // The decoder reads the bitstream backwards, so the last sequence
+    // goes in first.
+    let mut bits = BitWriter::default();
+    let last = SequenceCodes::new(last);
+    let mut ml_state = ml_table.init(last.ml.0);
+    let mut of_state = of_table.init(last.of.0);
+    let mut ll_state = ll_table.init(last.ll.0);
+    last.write_extra(&mut bits);
+    for s in rest.iter().rev() {
+        let codes = SequenceCodes::new(s);
+        of_table.encode(&mut of_state, codes.of.0, &mut bits);
+        ml_table.encode(&mut ml_state, codes.ml.0, &mut bits);
+        ll_table.encode(&mut ll_state, codes.ll.0, &mut bits);
+        codes.write_extra(&mut bits);
+    }
+    ml_table.flush(ml_state, &mut bits);
+    of_table.flush(of_state, &mut bits);
+    ll_table.flush(ll_state, &mut bits);
+    out.extend_from_slice(&bits.finish());
+    out
+}
+
+/// A sequence's three codes, each with its extra bits: the value and
+/// how many there are.
+struct SequenceCodes {
+    ll: (usize, u32, u8),
+    of: (usize, u32, u8),
+    ml: (usize, u32, u8),
+}
+
+impl SequenceCodes {
+    fn new(s: &Sequence) -> Self {
+        fn code(table: &[(u32, u8)], value: u32) -> (usize, u32, u8) {
+            let code = table
+                .iter()
+                .rposition(|&(base, _)| base <= value)
+                .unwrap_or(0);
+            let (base, bits) = table[code];
+            (code, value - base, bits)
+        }
+        // Offsets above 3 are new offsets; 1 to 3 would name a repeat.
+        let of_value = s.offset as u32 + 3;
+        let of_code = highbit(of_value);
+        Self {
+            ll: code(&LL_CODES, s.literals as u32),
+            of: (of_code as usize, of_value - (1 << of_code), of_code as u8),
+            ml: code(&ML_CODES, s.length as u32),
+        }
+    }
+
+    fn write_extra(&self, bits: &mut BitWriter) {
+        for (_, value, n) in [self.ll, self.ml, self.of] {
+            bits.add(u64::from(value), n.into());
+        }
+    }
+}
+
+/// Bits written from the least significant end of each byte, closed by a
+/// single set bit.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl BitWriter {
+    fn add(&mut self, value: u64, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.acc |= (value & ((1 << n) - 1)) << self.n;
+        self.n += n;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.add(1, 1);
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// The encoding side of an FSE table.
+struct FseEncoder {
+    log: u8,
+    /// Next state, by symbol and then by position within the symbol's
+    /// cells.
+    states: Vec<u16>,
+    /// Per symbol: the bias that gives the number of bits to write for a
+    /// state, and where its entries start in `states`.
+    transforms: Vec<(u32, i32)>,
+}
+
+impl FseEncoder {
+    fn new((probs, log): (&[i16], u8)) -> Self {
+        let symbols = spread(probs, log).expect("predefined distributions are valid");
+        let size = 1usize << log;
+        let mut next = Vec::with_capacity(probs.len());
+        let mut start = 0;
+        for &p in probs {
+            next.push(start);
+            start += p.unsigned_abs() as usize;
+        }
+        let mut states = vec![0; size];
+        for (u, &s) in symbols.iter().enumerate() {
+            let slot = &mut next[s as usize];
+            states[*slot] = (size + u) as u16;
+            *slot += 1;
+        }
+
+        let log = u32::from(log);
+        let mut total = 0i32;
+        let transforms = probs
+            .iter()
+            .map(|&p| match p {
+                0 => (0, 0),
+                -1 | 1 => {
+                    let t = ((log << 16) - size as u32, total - 1);
+                    total += 1;
+                    t
+                }
+                p => {
+                    let p = p as u32;
+                    let max_bits = log - highbit(p - 1);
+                    let t = ((max_bits << 16) - (p << max_bits), total - p as i32);
+                    total += p as i32;
+                    t
+                }
+            })
+            .collect();
+        Self {
+            log: log as u8,
+            states,
+            transforms,
+        }
+    }
+
+    fn next(&self, state: u32, symbol: usize, bits: u32) -> u32 {
+        let (_, find) = self.transforms[symbol];
+        u32::from(self.states[((state >> bits) as i32 + find) as usize])
+    }
+
+    /// The state to start from when `symbol` is the first one encoded.
+    fn init(&self, symbol: usize) -> u32 {
+        let (delta, _) = self.transforms[symbol];
+        let bits = (delta + (1 << 15)) >> 16;
+        self.next((bits << 16) - delta, symbol, bits)
+    }
+
+    fn encode(&self, state: &mut u32, symbol: usize, out: &mut BitWriter) {
+        let (delta, _) = self.transforms[symbol];
+        let bits = (*state + delta) >> 16;
+        out.add(u64::from(*state), bits);
+        *state = self.next(*state, symbol, bits);
+    }
+
+    fn flush(&self, state: u32, out: &mut BitWriter) {
+        out.add(u64::from(state), self.log.into());
+    }
+}
+
+/// Which symbol each cell of an FSE table of accuracy `log` belongs to.
+/// Symbols of probability "less than one" (-1) take the last cells.
+fn spread(probs: &[i16], log: u8) -> Result<Vec<u8>> {
+    let size = 1usize << log;
+    let total: usize = probs.iter().map(|p| p.unsigned_abs() as usize).sum();
+    if total != size || probs.iter().any(|&p| p < -1) || probs.len() > 256 {
+        return Err(CodecError::Corrupt);
+    }
+    let mut symbols = vec![0u8; size];
+    let mut high = size;
+    for (s, &p) in probs.iter().enumerate() {
+        if p == -1 {
+            high -= 1;
+            symbols[high] = s as u8;
+        }
+    }
+    let step = (size >> 1) + (size >> 3) + 3;
+    let mask = size - 1;
+    let mut pos = 0;
+    for (s, &p) in probs.iter().enumerate() {
+        for _ in 0..p.max(0) {
+            symbols[pos] = s as u8;
+            pos = (pos + step) & mask;
+            while pos >= high {
+                pos = (pos + step) & mask;
+            }
+        }
+    }
+    if pos != 0 {
+        return Err(CodecError::Corrupt);
+    }
+    Ok(symbols)
+}
+
+// --- Decompression
+
+/// The content of the zstd frames in `input`, refused once it would pass
+/// `max_len` bytes.
+pub fn decompress(input: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    if input.is_empty() {
+        return Err(CodecError::Corrupt);
+    }
+    let mut out = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let magic = read_le(rest, 4)? as u32;
+        if magic & 0xFFFF_FFF0 == SKIPPABLE_MAGIC {
+            let size = read_le(&rest[4..], 4)? as usize;
+            rest = rest.get(8 + size..).ok_or(CodecError::Corrupt)?;
+        } else if magic == MAGIC {
+            rest = decode_frame(&rest[4..], &mut out, max_len)?;
+        } else {
+            return Err(CodecError::Corrupt);
+        }
+    }
+    Ok(out)
+}
+
+/// The little-endian number in the first `n` bytes of `input`.
+fn read_le(input: &[u8], n: usize) -> Result<u64> {
+    let bytes = input.get(..n).ok_or(CodecError::Corrupt)?;
+    Ok(bytes.iter().rev().fold(0, |v, &b| v << 8 | u64::from(b)))
+}
+
+/// What the blocks of one frame share.
+struct FrameState {
+    huffman: Option<Huffman>,
+    ll: Option<FseTable>,
+    of: Option<FseTable>,
+    ml: Option<FseTable>,
+    repeats: [usize; 3],
+    /// Where the frame's content starts in the output.
+    start: usize,
+}
+
+/// Decode one frame (after its magic number) onto `out`; the input left
+/// after it.
+fn decode_frame<'a>(input: &'a [u8], out: &mut Vec<u8>, max_len: usize) -> Result<&'a [u8]> {
+    let descriptor = *input.first().ok_or(CodecError::Corrupt)?;
+    let single_segment = descriptor & 0x20 != 0;
+    let checksum = descriptor & 0x04 != 0;
+    if descriptor & 0x08 != 0 {
+        return Err(CodecError::Corrupt);
+    }
+    let mut pos = 1;
+    if !single_segment {
+        // Window descriptor: offsets are checked against the output
+        // itself, so the window size is not needed.
+        pos += 1;
+    }
+    let dictionary_bytes = [0, 1, 2, 4][usize::from(descriptor & 0x03)];
+    if read_le(
+        input.get(pos..).ok_or(CodecError::Corrupt)?,
+        dictionary_bytes,
+    )? != 0
+    {
+        return Err(CodecError::Dictionary);
+    }
+    pos += dictionary_bytes;
+    let size_bytes = match descriptor >> 6 {
+        0 => usize::from(single_segment),
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let content_size = match size_bytes {
+        0 => None,
+        n => {
+            let size = read_le(input.get(pos..).ok_or(CodecError::Corrupt)?, n)?;
+            Some(if n == 2 { size + 256 } else { size })
+        }
+    };
+    pos += size_bytes;
+    if let Some(size) = content_size
+        && size > (max_len - out.len()) as u64
+    {
+        return Err(CodecError::TooLarge);
+    }
+
+    let mut frame = FrameState {
+        huffman: None,
+        ll: None,
+        of: None,
+        ml: None,
+        repeats: [1, 4, 8],
+        start: out.len(),
+    };
+    loop {
+        let header = read_le(input.get(pos..).ok_or(CodecError::Corrupt)?, 3)? as u32;
+        pos += 3;
+        let last = header & 1 != 0;
+        let size = (header >> 3) as usize;
+        let kind = header >> 1 & 3;
+        let body_len = if kind == BLOCK_RLE { 1 } else { size };
+        let body = input.get(pos..pos + body_len).ok_or(CodecError::Corrupt)?;
+        pos += body_len;
+        if size > BLOCK_MAX {
+            return Err(CodecError::Corrupt);
+        }
+        match kind {
+            BLOCK_RAW => {
+                reserve(out, size, max_len)?;
+                out.extend_from_slice(body);
+            }
+            BLOCK_RLE => {
+                reserve(out, size, max_len)?;
+                out.resize(out.len() + size, body[0]);
+            }
+            BLOCK_COMPRESSED => decode_block(body, &mut frame, out, max_len)?,
+            _ => return Err(CodecError::Corrupt),
+        }
+        if last {
+            break;
+        }
+    }
+
+    let content = &out[frame.start..];
+    if content_size.is_some_and(|size| size != content.len() as u64) {
+        return Err(CodecError::Corrupt);
+    }
+    if checksum {
+        let expected = read_le(input.get(pos..).ok_or(CodecError::Corrupt)?, 4)?;
+        if xxh64(content) as u32 as u64 != expected {
+            return Err(CodecError::Corrupt);
+        }
+        pos += 4;
+    }
+    Ok(&input[pos..])
+}
+
+/// Fail with [`CodecError::TooLarge`] unless `n` more bytes fit in `out`.
+fn reserve(out: &[u8], n: usize, max_len: usize) -> Result<()> {
+    if n > max_len - out.len() {
+        return Err(CodecError::TooLarge);
+    }
+    Ok(())
+}
+
+fn decode_block(
+    input: &[u8],
+    frame: &mut FrameState,
+    out: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<()> {
+    let (literals, mut pos) = decode_literals(input, &mut frame.huffman)?;
+
+    let byte = |i: usize| input.get(i).copied().ok_or(CodecError::Corrupt);
+    let count = match byte(pos)? {
+        0 => {
+            reserve(out, literals.len(), max_len)?;
+            out.extend_from_slice(&literals);
+            return Ok(());
+        }
+        n @ 1..128 => {
+            pos += 1;
+            usize::from(n)
+        }
+        255 => {
+            pos += 3;
+            read_le(&input[pos - 2..], 2)? as usize + 0x7F00
+        }
+        n => {
+            pos += 2;
+            (usize::from(n - 128) << 8) + usize::from(byte(pos - 1)?)
+        }
+    };
+    let modes = byte(pos)?;
+    pos += 1;
+    if modes & 0x03 != 0 {
+        return Err(CodecError::Corrupt);
+    }
+    for (table, mode, default, max_symbol, max_log) in [
+        (
+            &mut frame.ll,
+            modes >> 6,
+            LL_DEFAULT,
+            LL_CODES.len() - 1,
+            LL_MAX_LOG,
+        ),
+        (
+            &mut frame.of,
+            modes >> 4 & 3,
+            OF_DEFAULT,
+            MAX_OF_CODE,
+            OF_MAX_LOG,
+        ),
+        (
+            &mut frame.ml,
+            modes >> 2 & 3,
+            ML_DEFAULT,
+            ML_CODES.len() - 1,
+            ML_MAX_LOG,
+        ),
+    ] {
+        match mode {
+            0 => *table = Some(FseTable::new(default.0, default.1)?),
+            1 => {
+                let symbol = byte(pos)?;
+                pos += 1;
+                if usize::from(symbol) > max_symbol {
+                    return Err(CodecError::Corrupt);
+                }
+                *table = Some(FseTable::rle(symbol));
+            }
+            2 => {
+                let (t, used) = read_fse_table(&input[pos..], max_symbol, max_log)?;
+                pos += used;
+                *table = Some(t);
+            }
+            _ if table.is_none() => return Err(CodecError::Corrupt),
+            _ => {}
+        }
+    }
+    let (Some(ll), Some(of), Some(ml)) = (&frame.ll, &frame.of, &frame.ml) else {
+        return Err(CodecError::Corrupt);
+    };
+
+    let mut bits = BackBits::new(&input[pos..])?;
+    let mut ll_state = bits.read(ll.log)? as usize;
+    let mut of_state = bits.read(of.log)? as usize;
+    let mut ml_state = bits.read(ml.log)? as usize;
+    let mut lit = 0;
+    for i in 0..count {
+        let of_code = of.cells[of_state].symbol;
+        let (ml_base, ml_bits) = ML_CODES[usize::from(ml.cells[ml_state].symbol)];
+        let (ll_base, ll_bits) = LL_CODES[usize::from(ll.cells[ll_state].symbol)];
+        let of_value = (1u64 << of_code) + bits.read(of_code.into())?;
+        let length = (u64::from(ml_base) + bits.read(ml_bits.into())?) as usize;
+        let literal_len = (u64::from(ll_base) + bits.read(ll_bits.into())?) as usize;
+        if i + 1 < count {
+            ll_state = ll.next(ll_state, &mut bits)?;
+            ml_state = ml.next(ml_state, &mut bits)?;
+            of_state = of.next(of_state, &mut bits)?;
+        }
+
+        let repeats = &mut frame.repeats;
+        let offset = if of_value > 3 {
+            let offset = (of_value - 3) as usize;
+            *repeats = [offset, repeats[0], repeats[1]];
+            offset
+        } else {
+            // With no literals, the repeat offsets shift down by one.
+            match of_value as usize - usize::from(literal_len != 0) {
+                0 => repeats[0],
+                1 => {
+                    *repeats = [repeats[1], repeats[0], repeats[2]];
+                    repeats[0]
+                }
+                2 => {
+                    *repeats = [repeats[2], repeats[0], repeats[1]];
+                    repeats[0]
+                }
+                _ => {
+                    let offset = repeats[0].checked_sub(1).filter(|&o| o > 0);
+                    let offset = offset.ok_or(CodecError::Corrupt)?;
+                    *repeats = [offset, repeats[0], repeats[1]];
+                    offset
+                }
+            }
+        };
+
+        let run = literals
+            .get(lit..lit + literal_len)
+            .ok_or(CodecError::Corrupt)?;
+        lit += literal_len;
+        reserve(out, literal_len + length, max_len)?;
+        out.extend_from_slice(run);
+        if offset > out.len() - frame.start {
+            return Err(CodecError::Corrupt);
+        }
+        // Byte by byte: a match may overlap the bytes it produces.
+        let from = out.len() - offset;
+        for k in 0..length {
+            let b = out[from + k];
+            out.push(b);
+        }
+    }
+    if bits.left != 0 {
+        return Err(CodecError::Corrupt);
+    }
+    let rest = &literals[lit..];
+    reserve(out, rest.len(), max_len)?;
+    out.extend_from_slice(rest);
+    Ok(())
+}
+
+/// The literals section at the start of a compressed block, and the
+/// bytes it took.
+fn decode_literals(input: &[u8], huffman: &mut Option<Huffman>) -> Result<(Vec<u8>, usize)> {
+    let first = *input.first().ok_or(CodecError::Corrupt)?;
+    let kind = first & 3;
+    let format = first >> 2 & 3;
+    if kind < 2 {
+        let (size, header) = match format {
+            0 | 2 => (usize::from(first >> 3), 1),
+            1 => ((read_le(input, 2)? >> 4) as usize, 2),
+            _ => ((read_le(input, 3)? >> 4) as usize, 3),
+        };
+        if size > BLOCK_MAX {
+            return Err(CodecError::Corrupt);
+        }
+        return if kind == 0 {
+            let literals = input
+                .get(header..header + size)
+                .ok_or(CodecError::Corrupt)?;
+            Ok((literals.to_vec(), header + size))
+        } else {
+            let b = *input.get(header).ok_or(CodecError::Corrupt)?;
+            Ok((vec![b; size], header + 1))
+        };
+    }
+
+    let (header, size_bits, streams) = match format {
+        0 => (3, 10, 1),
+        1 => (3, 10, 4),
+        2 => (4, 14, 4),
+        _ => (5, 18, 4),
+    };
+    let fields = read_le(input, header)? >> 4;
+    let mask = (1 << size_bits) - 1;
+    let size = (fields & mask) as usize;
+    let compressed = (fields >> size_bits & mask) as usize;
+    if size > BLOCK_MAX {
+        return Err(CodecError::Corrupt);
+    }
+    let mut data = input
+        .get(header..header + compressed)
+        .ok_or(CodecError::Corrupt)?;
+    if kind == 2 {
+        let (table, used) = Huffman::read(data)?;
+        *huffman = Some(table);
+        data = &data[used..];
+    }
+    let table = huffman.as_ref().ok_or(CodecError::Corrupt)?;
+    let mut literals = Vec::with_capacity(size);
+    if streams == 1 {
+        table.decode(data, size, &mut literals)?;
+    } else {
+        let jump = data.get(..6).ok_or(CodecError::Corrupt)?;
+        let mut sizes = [0; 4];
+        for (k, s) in sizes.iter_mut().take(3).enumerate() {
+            *s = usize::from(u16::from_le_bytes([jump[2 * k], jump[2 * k + 1]]));
+        }
+        sizes[3] = (data.len() - 6)
+            .checked_sub(sizes[..3].iter().sum())
+            .ok_or(CodecError::Corrupt)?;
+        let segment = size.div_ceil(4);
+        let last = size.checked_sub(3 * segment).ok_or(CodecError::Corrupt)?;
+        let mut stream = &data[6..];
+        for (k, &len) in sizes.iter().enumerate() {
+            let count = if k == 3 { last } else { segment };
+            table.decode(&stream[..len], count, &mut literals)?;
+            stream = &stream[len..];
+        }
+    }
+    Ok((literals, header + compressed))
+}
+
+/// A Huffman decoding table: for every `max_bits`-bit prefix, the symbol
+/// it starts with and that symbol's code length.
+struct Huffman {
+    max_bits: u32,
+    table: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    /// Read a Huffman tree description; the table and the bytes it took.
+    fn read(input: &[u8]) -> Result<(Self, usize)> {
+        let header = usize::from(*input.first().ok_or(CodecError::Corrupt)?);
+        let (mut weights, used) = if header < 128 {
+            let data = input.get(1..1 + header).ok_or(CodecError::Corrupt)?;
+            (decode_weights(data)?, 1 + header)
+        } else {
+            let count = header - 127;
+            let data = input
+                .get(1..1 + count.div_ceil(2))
+                .ok_or(CodecError::Corrupt)?;
+            let weights = (0..count)
+                .map(|i| data[i / 2] >> (if i % 2 == 0 { 4 } else { 0 }) & 0x0F)
+                .collect();
+            (weights, 1 + count.div_ceil(2))
+        };
+        if weights.len() > 255 || weights.iter().any(|&w| u32::from(w) > HUF_MAX_BITS) {
+            return Err(CodecError::Corrupt);
+        }
+        // The last symbol's weight is whatever brings the total to the
+        // next power of two.
+        let total: u32 = weights
+            .iter()
+            .filter(|&&w| w > 0)
+            .map(|&w| 1 << (w - 1))
+            .sum();
+        if total == 0 {
+            return Err(CodecError::Corrupt);
+        }
+        let max_bits = highbit(total) + 1;
+        let rest = (1 << max_bits) - total;
+        if max_bits > HUF_MAX_BITS || !rest.is_power_of_two() {
+            return Err(CodecError::Corrupt);
+        }
+        weights.push(highbit(rest) as u8 + 1);
+
+        // Codes go out from the lowest weight (longest code) up, and in
+        // symbol order within a weight.
+        let mut table = Vec::with_capacity(1 << max_bits);
+        for w in 1..=max_bits {
+            for (s, _) in weights
+                .iter()
+                .enumerate()
+                .filter(|&(_, &ws)| u32::from(ws) == w)
+            {
+                let entry = (s as u8, (max_bits + 1 - w) as u8);
+                table.extend(std::iter::repeat_n(entry, 1 << (w - 1)));
+            }
+        }
+        Ok((Self { max_bits, table }, used))
+    }
+
+    /// Decode `count` symbols from one stream, which must use up exactly.
+    fn decode(&self, stream: &[u8], count: usize, out: &mut Vec<u8>) -> Result<()> {
+        let mut bits = BackBits::new(stream)?;
+        for _ in 0..count {
+            let (symbol, len) = self.table[bits.peek(self.max_bits) as usize];
+            if !bits.consume(len.into()) {
+                return Err(CodecError::Corrupt);
+            }
+            out.push(symbol);
+        }
+        if bits.left != 0 {
+            return Err(CodecError::Corrupt);
+        }
+        Ok(())
+    }
+}
+
+/// Huffman weights coded with two interleaved FSE states sharing one
+/// table; decoding runs until the bitstream is used up.
+fn decode_weights(input: &[u8]) -> Result<Vec<u8>> {
+    let (table, used) = read_fse_table(input, 255, HUF_WEIGHT_MAX_LOG)?;
+    let mut bits = BackBits::new(&input[used..])?;
+    let mut states = [
+        bits.read(table.log)? as usize,
+        bits.read(table.log)? as usize,
+    ];
+    let mut weights = Vec::new();
+    for k in (0..2).cycle() {
+        if weights.len() > 253 {
+            return Err(CodecError::Corrupt);
+        }
+        let cell = table.cells[states[k]];
+        weights.push(cell.symbol);
+        let (value, complete) = bits.read_padded(cell.bits.into());
+        states[k] = usize::from(cell.base) + value as usize;
+        if !complete {
+            weights.push(table.cells[states[1 - k]].symbol);
+            break;
+        }
+    }
+    Ok(weights)
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    symbol: u8,
+    bits: u8,
+    base: u16,
+}
+
+/// The decoding side of an FSE table.
+struct FseTable {
+    log: u32,
+    cells: Vec<Cell>,
+}
+
+impl FseTable {
+    fn new(probs: &[i16], log: u8) -> Result<Self> {
+        let symbols = spread(probs, log)?;
+        let size = 1u32 << log;
+        let mut next: Vec<u32> = probs.iter().map(|p| p.unsigned_abs().into()).collect();
+        let cells = symbols
+            .iter()
+            .map(|&symbol| {
+                let n = next[usize::from(symbol)];
+                next[usize::from(symbol)] += 1;
+                let bits = u32::from(log) - highbit(n);
+                Cell {
+                    symbol,
+                    bits: bits as u8,
+                    base: ((n << bits) - size) as u16,
+                }
+            })
+            .collect();
+        Ok(Self {
+            log: log.into(),
+            cells,
+        })
+    }
+
+    /// A table that decodes `symbol` every time, reading no bits.
+    fn rle(symbol: u8) -> Self {
+        Self {
+            log: 0,
+            cells: vec![Cell {
+                symbol,
+                bits: 0,
+                base: 0,
+            }],
+        }
+    }
+
+    fn next(&self, state: usize, bits: &mut BackBits<'_>) -> Result<usize> {
+        let cell = self.cells[state];
+        Ok(usize::from(cell.base) + bits.read(cell.bits.into())? as usize)
+    }
+}
+
+/// Read an FSE table description from the start of `input`; the table
+/// and the bytes it took.
+fn read_fse_table(input: &[u8], max_symbol: usize, max_log: u8) -> Result<(FseTable, usize)> {
+    let mut bits = ForwardBits {
+        data: input,
+        pos: 0,
+    };
+    let log = bits.read(4) as u8 + 5;
+    if log > max_log {
+        return Err(CodecError::Corrupt);
+    }
+    let mut remaining = (1i32 << log) + 1;
+    let mut threshold = 1i32 << log;
+    let mut width = u32::from(log) + 1;
+    let mut probs = Vec::new();
+    while remaining > 1 {
+        let max = 2 * threshold - 1 - remaining;
+        let low = bits.peek(width) as i32;
+        let value = if low & (threshold - 1) < max {
+            bits.pos += width as usize - 1;
+            low & (threshold - 1)
+        } else {
+            bits.pos += width as usize;
+            let v = low & (2 * threshold - 1);
+            if v >= threshold { v - max } else { v }
+        };
+        let p = value - 1;
+        remaining -= p.abs();
+        probs.push(p as i16);
+        if p == 0 {
+            // Runs of further zero probabilities, two bits at a time.
+            loop {
+                let repeat = bits.read(2);
+                probs.extend(std::iter::repeat_n(0, repeat as usize));
+                if repeat != 3 {
+                    break;
+                }
+            }
+        }
+        if probs.len() > max_symbol + 1 {
+            return Err(CodecError::Corrupt);
+        }
+        if remaining > 1 && remaining < threshold {
+            width = highbit(remaining as u32) + 1;
+            threshold = 1 << (width - 1);
+        }
+    }
+    let used = bits.pos.div_ceil(8);
+    if remaining != 1 || used > input.len() {
+        return Err(CodecError::Corrupt);
+    }
+    Ok((FseTable::new(&probs, log)?, used))
+}
+
+/// Bits read forwards from the least significant end of each byte; past
+/// the end of the data they read as zero.
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl ForwardBits<'_> {
+    fn peek(&self, n: u32) -> u64 {
+        let mut v = 0u64;
+        for k in 0..5 {
+            let b = self.data.get(self.pos / 8 + k).copied().unwrap_or(0);
+            v |= u64::from(b) << (8 * k);
+        }
+        (v >> (self.pos % 8)) & ((1 << n) - 1)
+    }
+
+    fn read(&mut self, n: u32) -> u64 {
+        let v = self.peek(n);
+        self.pos += n as usize;
+        v
+    }
+}
+
+/// Bits read backwards from the end of a stream closed by a set bit, the
+/// way FSE and Huffman streams are written.
+struct BackBits<'a> {
+    data: &'a [u8],
+    /// Bits not read yet.
+    left: usize,
+}
+
+impl<'a> BackBits<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let last = *data.last().ok_or(CodecError::Corrupt)?;
+        if last == 0 {
+            return Err(CodecError::Corrupt);
+        }
+        let left = data.len() * 8 - last.leading_zeros() as usize - 1;
+        Ok(Self { data, left })
+    }
+
+    /// Bits `start..start + n` of the stream, the first one least
+    /// significant.
+    fn bits_at(&self, start: usize, n: u32) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        let first = start / 8;
+        let last = (start + n as usize - 1) / 8;
+        let v = self.data[first..=last]
+            .iter()
+            .enumerate()
+            .fold(0u64, |v, (k, &b)| v | u64::from(b) << (8 * k));
+        (v >> (start % 8)) & ((1 << n) - 1)
+    }
+
+    /// The next `n` bits, padded with zeros once the stream runs out.
+    fn peek(&self, n: u32) -> u64 {
+        let n_ = n as usize;
+        if self.left >= n_ {
+            self.bits_at(self.left - n_, n)
+        } else {
+            self.bits_at(0, self.left as u32) << (n_ - self.left)
+        }
+    }
+
+    /// Skip `n` bits; false if there were not that many.
+    fn consume(&mut self, n: u32) -> bool {
+        let complete = self.left >= n as usize;
+        self.left = self.left.saturating_sub(n as usize);
+        complete
+    }
+
+    fn read(&mut self, n: u32) -> Result<u64> {
+        let (v, complete) = self.read_padded(n);
+        if complete {
+            Ok(v)
+        } else {
+            Err(CodecError::Corrupt)
+        }
+    }
+
+    /// The next `n` bits, zero-padded, and whether they were all there.
+    fn read_padded(&mut self, n: u32) -> (u64, bool) {
+        let v = self.peek(n);
+        (v, self.consume(n))
+    }
+}
+
+/// XXH64 with seed 0, whose low 32 bits are a frame's content checksum.
+fn xxh64(data: &[u8]) -> u64 {
+    const P1: u64 = 0x9E37_79B1_85EB_CA87;
+    const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+    const P3: u64 = 0x1656_67B1_9E37_79F9;
+    const P4: u64 = 0x85EB_CA77_C2B2_AE63;
+    const P5: u64 = 0x27D4_EB2F_1656_67C5;
+    let lane = |b: &[u8]| u64::from_le_bytes(b[..8].try_into().unwrap());
+    let round = |acc: u64, v: u64| {
+        acc.wrapping_add(v.wrapping_mul(P2))
+            .rotate_left(31)
+            .wrapping_mul(P1)
+    };
+
+    let mut stripes = data.chunks_exact(32);
+    let mut h = if data.len() >= 32 {
+        let mut acc = [P1.wrapping_add(P2), P2, 0, P1.wrapping_neg()];
+        for stripe in &mut stripes {
+            for (k, a) in acc.iter_mut().enumerate() {
+                *a = round(*a, lane(&stripe[8 * k..]));
+            }
+        }
+        let mut h = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        for a in acc {
+            h = (h ^ round(0, a)).wrapping_mul(P1).wrapping_add(P4);
+        }
+        h
+    } else {
+        P5
+    };
+    h = h.wrapping_add(data.len() as u64);
+
+    let mut tail = stripes.remainder();
+    while tail.len() >= 8 {
+        h ^= round(0, lane(tail));
+        h = h.rotate_left(27).wrapping_mul(P1).wrapping_add(P4);
+        tail = &tail[8..];
+    }
+    if tail.len() >= 4 {
+        h ^= u64::from(u32::from_le_bytes(tail[..4].try_into().unwrap())).wrapping_mul(P1);
+        h = h.rotate_left(23).wrapping_mul(P2).wrapping_add(P3);
+        tail = &tail[4..];
+    }
+    for &b in tail {
+        h ^= u64::from(b).wrapping_mul(P5);
+        h = h.rotate_left(11).wrapping_mul(P1);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(P2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(P3);
+    h ^ h >> 32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame written by the reference `zstd -19`: Huffman-coded
+    /// literals, sequences and a content checksum.
+    const REFERENCE_FRAME: &[&str] = &[
+        "28b52ffd64af01bd0400428b1d1590ab0d40b758bce6569108259226d2fe4075d71f73879ad7d47a",
+        "ef0b418577f931104105771395e781b846baa644f4347074f8e4995b77e9627c20d6f86a7090be69",
+        "abdee5e755635c1fc808eb1344366e65ba05d3bc77f50d537d62733a33f043aff3f3d51ad7f9bd85",
+        "8acdaafc879ff0ac9009010a00c76853408f5534c36bc28c1f7494800d09e2862ada5781412056ab",
+        "ce1b3ca734",
+    ];
+
+    fn reference_text() -> String {
+        "Each node keeps a pointer to the next one, and a message sent around the ring \
+         comes back to where it started. The walk visits every node once, records the \
+         edge it took, and reports the topology it found to the client that asked. "
+            .repeat(3)
+    }
+
+    fn reference_frame() -> Vec<u8> {
+        let hex = REFERENCE_FRAME.concat();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn predefined_distributions_fill_their_tables() {
+        for (probs, log) in [LL_DEFAULT, ML_DEFAULT, OF_DEFAULT] {
+            assert_eq!(spread(probs, log).unwrap().len(), 1 << log);
+        }
+        assert_eq!(LL_DEFAULT.0.len(), LL_CODES.len());
+        assert_eq!(ML_DEFAULT.0.len(), ML_CODES.len());
+    }
+
+    #[test]
+    fn round_trips_across_blocks() {
+        let varied: Vec<u8> = (0..300_000u32)
+            .flat_map(|i| (i * 7919).to_le_bytes())
+            .collect();
+        // The second half repeats the first from more than a block back.
+        let mut repeated = varied[..200_000].to_vec();
+        repeated.extend_from_within(..200_000);
+        for input in [
+            &b""[..],
+            b"a",
+            b"abcabcabcabcabcabc",
+            &[7; 1 << 20],
+            &varied,
+            &repeated,
+        ] {
+            let frame = compress(input);
+            assert_eq!(decompress(&frame, input.len()).unwrap(), input);
+        }
+        assert!(compress(&repeated).len() < repeated.len() * 2 / 3);
+    }
+
+    #[test]
+    fn reads_frames_from_the_reference_encoder() {
+        let text = reference_text();
+        let frame = reference_frame();
+        assert_eq!(decompress(&frame, text.len()).unwrap(), text.as_bytes());
+        // Two frames in a row, the second one ours, read as one.
+        let mut frames = frame.clone();
+        frames.extend(compress(b"!"));
+        assert_eq!(
+            decompress(&frames, usize::MAX).unwrap(),
+            format!("{text}!").as_bytes()
+        );
+    }
+
+    #[test]
+    fn bad_frames_are_rejected() {
+        let frame = reference_frame();
+        // The checksum is the last four bytes.
+        let mut bad_checksum = frame.clone();
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decompress(&bad_checksum, usize::MAX),
+            Err(CodecError::Corrupt)
+        );
+        assert_eq!(
+            decompress(&frame[..frame.len() - 10], usize::MAX),
+            Err(CodecError::Corrupt)
+        );
+        assert_eq!(decompress(b"", usize::MAX), Err(CodecError::Corrupt));
+        assert_eq!(
+            decompress(&frame, reference_text().len() - 1),
+            Err(CodecError::TooLarge)
+        );
+        // A dictionary id of 1.
+        let mut with_dictionary = MAGIC.to_le_bytes().to_vec();
+        with_dictionary.extend_from_slice(&[0x21, 1, 0]);
+        assert_eq!(
+            decompress(&with_dictionary, usize::MAX),
+            Err(CodecError::Dictionary)
+        );
+        // Every corruption of a frame is refused or misread, never a panic.
+        for i in 0..frame.len() * 8 {
+            let mut flipped = frame.clone();
+            flipped[i / 8] ^= 1 << (i % 8);
+            let _ = decompress(&flipped, 1 << 20);
+        }
+    }
+
+    #[test]
+    fn xxh64_matches_the_reference() {
+        assert_eq!(xxh64(b""), 0xEF46_DB37_51D8_E999);
+    }
+}
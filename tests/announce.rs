@@ -0,0 +1,49 @@
+//! `run --announce`: nodes send `ANNOUNCE` datagrams to a multicast group,
+//! and `discover` lists every node it hears there.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::announce::discover;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn discover_lists_every_announcing_node() {
+    // A port nothing else is using for the group.
+    let port = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let group = SocketAddr::from((Ipv4Addr::new(239, 255, 77, 1), port));
+    let listening = tokio::spawn(discover(group, Duration::from_millis(800)));
+    let ring = spin_up(RingOpts {
+        named: true,
+        announce: Some(group),
+        announce_interval: Duration::from_millis(100),
+        ..RingOpts::default()
+    })
+    .await;
+
+    let found = listening.await.unwrap().unwrap();
+    let mut expected: Vec<(String, String, u64)> = ring
+        .nodes
+        .iter()
+        .map(|n| {
+            (
+                n.node.port.clone(),
+                n.node.name().to_string(),
+                n.node.generation(),
+            )
+        })
+        .collect();
+    expected.sort();
+    let found: Vec<(String, String, u64)> = found
+        .into_iter()
+        .map(|a| (a.addr, a.name, a.generation))
+        .collect();
+    assert_eq!(found, expected);
+    shutdown(ring).await;
+}
@@ -72,6 +72,34 @@ async fn auth_with_wrong_secret_is_rejected() {
     shutdown(ring).await;
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn tampered_auth_line_is_rejected() {
+    let ring = spin_up(RingOpts {
+        n: 3,
+        auth_token: fixed_token(),
+        ..RingOpts::default()
+    })
+    .await;
+
+    // Flip one hex digit of an otherwise valid HMAC.
+    let mut line = fixed_token().make_auth_line().expect("enabled");
+    let idx = "AUTH ".len();
+    let flipped = if &line[idx..idx + 1] == "0" { "1" } else { "0" };
+    line.replace_range(idx..idx + 1, flipped);
+    assert!(!fixed_token().verify_auth_line(&line));
+
+    let mut s = TcpStream::connect(ring.addr(0)).await.unwrap();
+    s.write_all(line.as_bytes()).await.unwrap();
+    s.write_all(b"NODE STATUS\n").await.unwrap();
+    s.shutdown().await.ok();
+
+    let mut buf = String::new();
+    s.read_to_string(&mut buf).await.unwrap();
+    assert_eq!(buf, "ERR auth required\n");
+
+    shutdown(ring).await;
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn auth_silence_times_out_within_2s() {
     let ring = spin_up(RingOpts {
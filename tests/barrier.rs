@@ -0,0 +1,91 @@
+//! `BARRIER ENTER`: arrivals at any node are counted by every node, and
+//! `BARRIER WAIT` at any node returns once the count is reached.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waiters_on_every_node_are_released_together() {
+    let ring = spin_up(RingOpts::default()).await;
+    let clients: Vec<RingClient> = (0..3)
+        .map(|i| RingClient::new(ring.addr(i).to_string()))
+        .collect();
+    let waiters: Vec<_> = [1, 2]
+        .map(|i| {
+            let client = RingClient::new(ring.addr(i).to_string());
+            tokio::spawn(async move { client.barrier_wait("sync", 2).await })
+        })
+        .into();
+
+    clients[0].barrier("sync", 2).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(waiters.iter().all(|w| !w.is_finished()));
+
+    clients[2].barrier("sync", 2).await.unwrap();
+    for w in waiters {
+        tokio::time::timeout(Duration::from_secs(5), w)
+            .await
+            .expect("waiter not released")
+            .unwrap()
+            .unwrap();
+    }
+    for h in &ring.nodes {
+        assert_eq!(h.node.barrier_count("sync"), 2);
+    }
+
+    // Waiting on a barrier already reached returns at once.
+    clients[0].barrier_wait("sync", 2).await.unwrap();
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn barrier_done_releases_waiters_early() {
+    let ring = spin_up(RingOpts {
+        n: 1,
+        ..RingOpts::default()
+    })
+    .await;
+    let waiter = {
+        let client = RingClient::new(ring.addr(0).to_string());
+        tokio::spawn(async move { client.barrier_wait("b", 5).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(
+        RingClient::new(ring.addr(0).to_string())
+            .raw("BARRIER DONE b")
+            .await
+            .unwrap(),
+        ["OK"]
+    );
+    tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .expect("waiter not released")
+        .unwrap()
+        .unwrap();
+    shutdown(ring).await;
+}
+
+/// On a ring that never gets back to the start node (0 -> 1 -> 2 -> 1),
+/// an arrival is counted once per node and then stops.
+#[tokio::test(flavor = "multi_thread")]
+async fn arrival_on_a_miswired_ring_is_counted_once_per_node() {
+    let ring = spin_up(RingOpts::default()).await;
+    RingClient::new(ring.addr(2).to_string())
+        .set_next(&ring.addr(1).to_string())
+        .await
+        .unwrap();
+
+    RingClient::new(ring.addr(0).to_string())
+        .barrier("loop", 5)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    for h in &ring.nodes {
+        assert_eq!(h.node.barrier_count("loop"), 1);
+    }
+    shutdown(ring).await;
+}
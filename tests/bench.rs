@@ -0,0 +1,55 @@
+//! `bench`: echoes sent at a fixed rate all come back, and the report's
+//! counts and percentiles hang together.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::ClientOpts;
+use ouroboros_fs::bench::{BenchOpts, run_bench};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echoes_at_the_target_rate() {
+    let ring = spin_up(RingOpts::default()).await;
+    let opts = BenchOpts {
+        rps: 50,
+        duration: Duration::from_secs(1),
+        ttl: 3,
+        connections: 4,
+    };
+    let report = run_bench(&ring.addr(0).to_string(), ClientOpts::default(), &opts)
+        .await
+        .unwrap();
+
+    assert!((45..=51).contains(&report.sent), "sent {}", report.sent);
+    assert_eq!(report.ok, report.sent);
+    assert_eq!(report.errors, 0);
+    let l = &report.latency_ms;
+    assert!(l.min > 0.0);
+    assert!(l.min <= l.p50 && l.p50 <= l.p95 && l.p95 <= l.p99 && l.p99 <= l.max);
+    assert!(report.throughput > 0.0);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["ok"], report.ok);
+    assert!(json["latency_ms"]["p99"].is_number());
+    assert!(report.table().contains("latency p99"));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unreachable_node_fails_up_front() {
+    let opts = BenchOpts {
+        rps: 10,
+        duration: Duration::from_secs(30),
+        ttl: 1,
+        connections: 1,
+    };
+    let res = tokio::time::timeout(
+        Duration::from_secs(5),
+        run_bench("127.0.0.1:1", ClientOpts::default(), &opts),
+    )
+    .await
+    .expect("bench should not wait out its duration");
+    assert!(res.is_err());
+}
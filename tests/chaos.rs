@@ -0,0 +1,49 @@
+//! `CHAOS MODE`: a node in drop mode loses some of the `RING` hops it
+//! forwards, and `CHAOS OFF` brings them back.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::chaos::ChaosMode;
+
+/// Send `count` distinct one-hop messages through node 0 and return how
+/// many node 1 received.
+async fn delivered(ring: &common::Ring, client: &RingClient, tag: &str, count: u32) -> u64 {
+    let received = &ring.nodes[1].node.ring_msgs_total;
+    let before = received.load(Ordering::Relaxed);
+    for i in 0..count {
+        client.ring(1, &format!("{tag}-{i}")).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    received.load(Ordering::Relaxed) - before
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_mode_loses_some_ring_messages() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+
+    client
+        .chaos(Some(&"drop 0.5".parse::<ChaosMode>().unwrap()))
+        .await
+        .unwrap();
+    let got = delivered(&ring, &client, "half", 60).await;
+    assert!(0 < got && got < 60, "delivered {got} of 60");
+
+    client
+        .chaos(Some(&"drop 1".parse::<ChaosMode>().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(delivered(&ring, &client, "none", 10).await, 0);
+
+    client.chaos(None).await.unwrap();
+    assert_eq!(delivered(&ring, &client, "all", 10).await, 10);
+
+    let resp = client.raw("CHAOS MODE jitter 3").await;
+    assert!(resp.is_err(), "{resp:?}");
+    shutdown(ring).await;
+}
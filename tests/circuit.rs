@@ -0,0 +1,94 @@
+//! Circuit breaker on hop forwards: a dead NEXT trips the circuit after
+//! `cb_failure_threshold` failures, `NODE CIRCUIT` reports it, and the
+//! first probe after the reset timeout closes it again.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(addr: &str, storage: &Path) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        addr,
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            cb_failure_threshold: 2,
+            cb_reset_timeout: Duration::from_millis(300),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dead_next_trips_circuit_until_probe_succeeds() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, a_task) = start_node("127.0.0.1:0", tmp.path()).await;
+    let (_, b_addr, b_task) = start_node("127.0.0.1:0", tmp.path()).await;
+    send_line(a_addr, &format!("NODE NEXT {b_addr}\n")).await;
+
+    // Take B down: every forward from A is now refused.
+    b_task.abort();
+    let _ = b_task.await;
+
+    for _ in 0..2 {
+        assert_eq!(send_line(a_addr, "RING FORWARD 1 hi\n").await, "OK\n");
+    }
+    assert_eq!(
+        send_line(a_addr, "NODE CIRCUIT\n").await,
+        format!("{b_addr} OPEN 2\nOK\n")
+    );
+
+    // While open, forwards fail without touching the network and without
+    // adding to the failure count.
+    let errors = a.errors_total.load(std::sync::atomic::Ordering::Relaxed);
+    send_line(a_addr, "RING FORWARD 1 hi\n").await;
+    assert!(a.errors_total.load(std::sync::atomic::Ordering::Relaxed) > errors);
+    assert_eq!(
+        send_line(a_addr, "NODE CIRCUIT\n").await,
+        format!("{b_addr} OPEN 2\nOK\n")
+    );
+
+    // B comes back; after the reset timeout the probe forward succeeds and
+    // the circuit closes.
+    let (_, _, b_task) = start_node(&b_addr.to_string(), tmp.path()).await;
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    assert_eq!(
+        send_line(a_addr, "NODE CIRCUIT\n").await,
+        format!("{b_addr} HALF-OPEN 2\nOK\n")
+    );
+    send_line(a_addr, "RING FORWARD 1 hi\n").await;
+    assert_eq!(send_line(a_addr, "NODE CIRCUIT\n").await, "OK\n");
+
+    a_task.abort();
+    b_task.abort();
+}
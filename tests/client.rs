@@ -0,0 +1,173 @@
+//! `RingClient`: typed commands over one reused connection, `ERR` replies
+//! as `RingError::Remote`, and a transparent reconnect after the node
+//! drops an idle connection.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::client::NodeInfo;
+use ouroboros_fs::node::NodeRole;
+use ouroboros_fs::{AuthToken, ClientOpts, RingClient, RingError};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn typed_commands_share_one_connection() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+
+    let info = client.get().await.unwrap();
+    assert_eq!(
+        info,
+        NodeInfo {
+            port: ring.addr(0).to_string(),
+            name: None,
+            next: Some(ring.addr(1).to_string()),
+            prev: Some(ring.addr(2).to_string()),
+            leader: None,
+            fan_out: 1,
+            generation: ring.nodes[0].node.generation(),
+            role: NodeRole::Unknown,
+            uptime_secs: None,
+            ring_size: None,
+            msg_count: None,
+            walk_count: None,
+            connections: None,
+            version: None,
+        }
+    );
+
+    let count = |stats: &std::collections::HashMap<String, String>, key: &str| -> u64 {
+        stats[key].parse().unwrap()
+    };
+    let before = client.stat().await.unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port().to_string());
+    assert_eq!(
+        client.walk().await.unwrap(),
+        vec![
+            (p0.clone(), p1.clone()),
+            (p1.clone(), p2.clone()),
+            (p2.clone(), p0.clone())
+        ]
+    );
+    client.ring(0, "hello").await.unwrap();
+    client.ping().await.unwrap();
+    let after = client.stat().await.unwrap();
+    assert_eq!(
+        count(&after, "WALK_COUNT"),
+        count(&before, "WALK_COUNT") + 1
+    );
+    assert_eq!(
+        count(&after, "RING_COUNT"),
+        count(&before, "RING_COUNT") + 1
+    );
+
+    assert_eq!(client.echo(3, "round trip").await.unwrap(), "round trip");
+    client.ring_once("one lap").await.unwrap();
+
+    let gathered = client.gather("port").await.unwrap();
+    assert_eq!(gathered.len(), 3);
+    assert_eq!(gathered[0].0, ring.addr(0).to_string());
+
+    let collected = client.collect(1, "missing").await.unwrap();
+    let expected: Vec<(String, String)> = [0, 1]
+        .map(|i| (ring.addr(i).to_string(), "-".to_string()))
+        .into();
+    assert_eq!(collected, expected);
+
+    assert!(!client.ring_timed(Duration::ZERO, "stale").await.unwrap());
+    assert!(
+        client
+            .ring_timed(Duration::from_millis(50), "fresh")
+            .await
+            .unwrap()
+    );
+
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_info_answers_in_one_block() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    client.raw("ELECT START").await.unwrap();
+
+    let lines = client.raw("NODE INFO").await.unwrap();
+    let keys: Vec<&str> = lines
+        .iter()
+        .map(|l| l.split_once(' ').map_or(l.as_str(), |(k, _)| k))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "PORT",
+            "NEXT",
+            "PREV",
+            "NAME",
+            "GENERATION",
+            "UPTIME_SECS",
+            "ROLE",
+            "LEADER",
+            "RING_SIZE",
+            "MSG_COUNT",
+            "WALK_COUNT",
+            "CONNECTIONS",
+            "VERSION",
+            "OK"
+        ]
+    );
+
+    let info = client.node_info().await.unwrap();
+    let status = client.get().await.unwrap();
+    assert_eq!(info.port, status.port);
+    assert_eq!(info.next, status.next);
+    assert_eq!(info.prev, status.prev);
+    assert_eq!(info.role, status.role);
+    assert_ne!(info.role, NodeRole::Unknown);
+    assert_eq!(info.leader, status.leader);
+    assert!(info.leader.is_some());
+    assert!(info.msg_count.unwrap() >= 2);
+    assert!(info.connections.unwrap() >= 1);
+    assert_eq!(info.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn err_reply_is_a_remote_error() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    match client.gather("queue_depth").await {
+        Err(RingError::Remote { addr, msg }) => {
+            assert_eq!(addr, ring.addr(0).to_string());
+            assert!(msg.contains("msg_count"), "msg: {msg}");
+        }
+        other => panic!("expected Remote, got {other:?}"),
+    }
+    // The connection survives an ERR.
+    client.ping().await.unwrap();
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reconnects_after_idle_drop() {
+    let token = AuthToken::from_bytes([7; 32]);
+    let ring = spin_up(RingOpts {
+        n: 1,
+        idle_timeout: Duration::from_millis(200),
+        auth_token: token.clone(),
+        ..RingOpts::default()
+    })
+    .await;
+    let client = RingClient::with_opts(
+        ring.addr(0).to_string(),
+        ClientOpts {
+            auth_token: token,
+            ..Default::default()
+        },
+    );
+    client.ping().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    client.ping().await.unwrap();
+    assert_eq!(client.get().await.unwrap().port, ring.addr(0).to_string());
+    shutdown(ring).await;
+}
@@ -10,7 +10,9 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use ouroboros_fs::{AuthToken, FsyncMode, Node, bind, serve};
+use ouroboros_fs::chring::RoutingMode;
+use ouroboros_fs::trace::OtlpEndpoint;
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
 use rand::SeedableRng;
 use rand::rngs::StdRng;
 use rand_chacha::ChaCha20Rng;
@@ -51,7 +53,26 @@ pub struct RingOpts {
     pub fsync_mode: FsyncMode,
     pub auth_token: AuthToken,
     pub idle_timeout: Duration,
+    pub accept_timeout: Duration,
     pub max_conns: u32,
+    pub max_ring_ttl: u32,
+    pub max_ring_hops: u32,
+    pub max_walk_hops: u32,
+    pub forward_max_hops: u32,
+    pub named: bool,
+    pub pool_size: usize,
+    pub mux: bool,
+    pub otlp_endpoint: Option<OtlpEndpoint>,
+    pub repair_coordinator: Option<String>,
+    pub probe_interval: Duration,
+    pub walk_cache_ttl: Duration,
+    pub heartbeat_addr: Option<String>,
+    pub heartbeat_interval: Duration,
+    pub parallel_walk_timeout: Duration,
+    pub routing: RoutingMode,
+    pub announce: Option<std::net::SocketAddr>,
+    pub announce_interval: Duration,
+    pub monitor_interval: Duration,
 }
 
 impl Default for RingOpts {
@@ -67,8 +88,39 @@ impl Default for RingOpts {
             auth_token: AuthToken::disabled(),
             // Tests opt out of idle timeout; the timeout test opts in.
             idle_timeout: Duration::ZERO,
+            accept_timeout: Duration::ZERO,
             // Tests opt out of connection caps; the cap test opts in.
             max_conns: 0,
+            // No RING TTL, walk length or NODE FORWARD-TO chain caps; the
+            // limits tests opt in.
+            max_ring_ttl: 0,
+            max_ring_hops: 0,
+            max_walk_hops: 0,
+            forward_max_hops: 0,
+            // Nodes are unnamed unless a test asks for `node-NN` labels.
+            named: false,
+            // Hop forwards open a fresh connection unless a test opts in.
+            pool_size: 0,
+            mux: false,
+            // No span export unless a tracing test points at a collector.
+            otlp_endpoint: None,
+            // Failed forwards are only logged unless a repair test opts in.
+            repair_coordinator: None,
+            // No partition probes unless a test opts in.
+            probe_interval: Duration::ZERO,
+            // Every TOPOLOGY WALK walks unless the cache test opts in.
+            walk_cache_ttl: Duration::ZERO,
+            // No heartbeats unless a membership test opts in.
+            heartbeat_addr: None,
+            heartbeat_interval: Duration::ZERO,
+            parallel_walk_timeout: Duration::ZERO,
+            // RING KEY is refused unless a hash-ring test opts in.
+            routing: RoutingMode::Sequential,
+            // No multicast announcements unless the discovery test opts in.
+            announce: None,
+            announce_interval: Duration::ZERO,
+            // No ring size monitor unless a test opts in.
+            monitor_interval: Duration::ZERO,
         }
     }
 }
@@ -86,7 +138,12 @@ pub async fn spin_up(opts: RingOpts) -> Ring {
     let mut bound = Vec::with_capacity(opts.n);
     for i in 0..opts.n {
         let storage = tmp.path().join(format!("ring-{i}"));
-        let (node, listener, addr) = bind(
+        let name = if opts.named {
+            format!("node-{i:02}")
+        } else {
+            String::new()
+        };
+        let (node, listener, addr) = bind_with_opts(
             "127.0.0.1:0",
             opts.gossip_interval,
             opts.max_file_size,
@@ -96,6 +153,28 @@ pub async fn spin_up(opts: RingOpts) -> Ring {
             opts.auth_token.clone(),
             opts.idle_timeout,
             opts.max_conns,
+            NodeOpts {
+                name,
+                pool_size: opts.pool_size,
+                mux: opts.mux,
+                otlp_endpoint: opts.otlp_endpoint.clone(),
+                repair_coordinator: opts.repair_coordinator.clone(),
+                accept_timeout: opts.accept_timeout,
+                max_ring_ttl: opts.max_ring_ttl,
+                max_ring_hops: opts.max_ring_hops,
+                max_walk_hops: opts.max_walk_hops,
+                forward_max_hops: opts.forward_max_hops,
+                probe_interval: opts.probe_interval,
+                walk_cache_ttl: opts.walk_cache_ttl,
+                heartbeat_addr: opts.heartbeat_addr.clone(),
+                heartbeat_interval: opts.heartbeat_interval,
+                parallel_walk_timeout: opts.parallel_walk_timeout,
+                routing: opts.routing,
+                announce: opts.announce,
+                announce_interval: opts.announce_interval,
+                monitor_interval: opts.monitor_interval,
+                ..Default::default()
+            },
         )
         .await
         .expect("bind");
@@ -117,20 +196,17 @@ pub async fn spin_up(opts: RingOpts) -> Ring {
         });
     }
 
-    // 3. Wire `NODE NEXT` around the ring (i -> i+1, last -> 0).
-    if opts.n > 1 {
-        for i in 0..opts.n {
-            let from = nodes[i].addr;
-            let to = nodes[(i + 1) % opts.n].addr;
-            send_node_next(from, to, &opts.auth_token)
-                .await
-                .expect("NODE NEXT");
-        }
-    } else {
-        // Single-node ring still needs a next hop; point it at itself.
-        send_node_next(nodes[0].addr, nodes[0].addr, &opts.auth_token)
+    // 3. Wire `NODE NEXT` / `NODE PREV` around the ring (i -> i+1,
+    //    last -> 0). A single-node ring points both links at itself.
+    for i in 0..opts.n {
+        let from = nodes[i].addr;
+        let to = nodes[(i + 1) % opts.n].addr;
+        send_node_link(from, "NEXT", to, &opts.auth_token)
             .await
-            .expect("NODE NEXT (self)");
+            .expect("NODE NEXT");
+        send_node_link(to, "PREV", from, &opts.auth_token)
+            .await
+            .expect("NODE PREV");
     }
 
     // 4. Trigger NETMAP DISCOVER from node 0; poll every node's network_size
@@ -149,13 +225,18 @@ pub async fn spin_up(opts: RingOpts) -> Ring {
     .await
     .expect("netmap converged");
 
-    // 5. Trigger TOPOLOGY WALK; poll topology_map until it has every edge.
+    // 5. Trigger TOPOLOGY WALK; poll topology_map until it has every edge
+    //    the walk may report.
     fire_and_forget(nodes[0].addr, b"TOPOLOGY WALK\n", &opts.auth_token)
         .await
         .expect("TOPOLOGY WALK");
+    let edges = match opts.max_walk_hops as usize {
+        0 => opts.n,
+        max => opts.n.min(max),
+    };
     poll_until(Duration::from_secs(3), || async {
         for h in &nodes {
-            if h.node.topology_map.read().await.len() < opts.n {
+            if h.node.topology_map.read().await.len() < edges {
                 return false;
             }
         }
@@ -259,8 +340,9 @@ fn _stdrng_marker() -> StdRng {
 
 // ---------- internal ----------
 
-async fn send_node_next(
+async fn send_node_link(
     from: SocketAddr,
+    verb: &str,
     to: SocketAddr,
     token: &AuthToken,
 ) -> std::io::Result<()> {
@@ -268,7 +350,7 @@ async fn send_node_next(
     if let Some(line) = token.make_auth_line() {
         s.write_all(line.as_bytes()).await?;
     }
-    let line = format!("NODE NEXT {to}\n");
+    let line = format!("NODE {verb} {to}\n");
     s.write_all(line.as_bytes()).await?;
     let mut reader = BufReader::new(s);
     let mut buf = String::new();
@@ -0,0 +1,151 @@
+//! `--compress-threshold-bytes`: long `RING` messages go to the next node
+//! as `z:<base64>` (a zstd frame) and come out the other end as they went
+//! in.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::codec::{MAX_DECOMPRESSED_BYTES, compress_field, decompress_field};
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+const THRESHOLD: usize = 64;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(storage: &Path) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    start_node_with(
+        storage,
+        NodeOpts {
+            compress_threshold_bytes: THRESHOLD,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+async fn start_node_with(
+    storage: &Path,
+    opts: NodeOpts,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        opts,
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn long_messages_are_forwarded_compressed() {
+    let tmp = TempDir::new().unwrap();
+    let (node, addr, task) = start_node(tmp.path()).await;
+    let next = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    node.set_next(next.local_addr().unwrap().to_string()).await;
+
+    let long = "7000=alive;".repeat(50);
+    for msg in [long.as_str(), "short"] {
+        assert_eq!(
+            send_line(addr, &format!("RING FORWARD 1 {msg}\n")).await,
+            "OK\n"
+        );
+    }
+
+    // Without a pool each forward arrives on its own connection.
+    let mut lines = Vec::new();
+    for _ in 0..2 {
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), next.accept())
+            .await
+            .expect("no forward")
+            .unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let _ = stream.get_mut().write_all(b"OK\n").await;
+        lines.push(line);
+    }
+    // "short" sorts before "z:...", whichever arrived first.
+    lines.sort();
+
+    let field = lines[1]
+        .trim_end()
+        .strip_prefix("RING FORWARD-HOP 1 0 ")
+        .unwrap();
+    assert!(field.starts_with("z:"), "{field}");
+    assert!(field.len() < long.len());
+    assert_eq!(
+        decompress_field(field, MAX_DECOMPRESSED_BYTES).unwrap(),
+        long
+    );
+    assert_eq!(lines[0], "RING FORWARD-HOP 1 0 short\n");
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn echo_round_trips_a_compressed_message() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, a_task) = start_node(tmp.path()).await;
+    let (b, b_addr, b_task) = start_node(tmp.path()).await;
+    a.set_next(b_addr.to_string()).await;
+    b.set_next(a_addr.to_string()).await;
+
+    let long = "hello ring ".repeat(40);
+    let resp = send_line(a_addr, &format!("RING ECHO 2 {long}\n")).await;
+    assert_eq!(resp, format!("ECHO {long}\nOK\n"));
+    a_task.abort();
+    b_task.abort();
+}
+
+/// A compressed field expands no further than `--max-line-bytes`, and one
+/// that does not decode is refused rather than taken as plain text.
+#[tokio::test(flavor = "multi_thread")]
+async fn compressed_fields_are_checked_before_dispatch() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node_with(
+        tmp.path(),
+        NodeOpts {
+            compress_threshold_bytes: THRESHOLD,
+            max_line_bytes: 1024,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let bomb = compress_field(&"a".repeat(4096), THRESHOLD).into_owned();
+    assert!(bomb.len() < 1024);
+    assert_eq!(
+        send_line(addr, &format!("RING FORWARD 1 {bomb}\n")).await,
+        "ERR compressed field expands past the size limit\n"
+    );
+    assert_eq!(
+        send_line(addr, "RING FORWARD 1 z:hello\n").await,
+        "ERR compressed field is not valid base64\n"
+    );
+    task.abort();
+}
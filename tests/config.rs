@@ -141,3 +141,307 @@ fsync_mode = "none"
         "config addr {cfg_port} should NOT be bound when CLI overrides"
     );
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dev_network_cli_flag_overrides_network_table() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+    let exe = exe.canonicalize().unwrap();
+
+    let cli_port = pick_port() + 10;
+    let cfg_port = cli_port + 10;
+    let cwd = tempfile::tempdir().unwrap();
+    let mut cfg = NamedTempFile::new().unwrap();
+    writeln!(
+        cfg,
+        r#"
+[network]
+nodes = 2
+base_port = {cfg_port}
+no_block = true
+wait_time = 0
+"#
+    )
+    .unwrap();
+
+    // With `no_block` the parent wires the ring, then signals its own
+    // process group, so it exits on its own.
+    let out = tokio::time::timeout(
+        Duration::from_secs(20),
+        tokio::process::Command::new(&exe)
+            .args(["--log-format", "json", "dev-network", "--config"])
+            .arg(cfg.path())
+            .arg("--base-port")
+            .arg(cli_port.to_string())
+            .current_dir(cwd.path())
+            .output(),
+    )
+    .await
+    .expect("dev-network did not exit")
+    .expect("spawn");
+    let log =
+        String::from_utf8_lossy(&out.stdout).into_owned() + &String::from_utf8_lossy(&out.stderr);
+
+    assert!(
+        log.contains(&format!("\"base_port\":{cli_port}")),
+        "CLI --base-port should have won; log:\n{log}"
+    );
+    assert!(
+        log.contains("\"nodes\":2"),
+        "config nodes should apply; log:\n{log}"
+    );
+    assert!(
+        !log.contains(&format!("\"base_port\":{cfg_port}")),
+        "config base_port should NOT be used; log:\n{log}"
+    );
+}
+
+#[test]
+fn dev_network_dry_run_prints_the_plan_and_starts_nothing() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+    let exe = exe.canonicalize().unwrap();
+
+    let port = pick_port() + 30;
+    let cwd = tempfile::tempdir().unwrap();
+    let mut cfg = NamedTempFile::new().unwrap();
+    writeln!(cfg, "[network]\nnodes = 2\nbase_port = {port}\n").unwrap();
+    let out = Command::new(&exe)
+        .args([
+            "dev-network",
+            "--dry-run",
+            "--dry-run-format",
+            "json",
+            "--config",
+        ])
+        .arg(cfg.path())
+        .current_dir(cwd.path())
+        .output()
+        .expect("spawn");
+    assert!(out.status.success(), "{out:?}");
+
+    let plan: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let spawned: Vec<_> = plan
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|a| a["action"] == "spawn")
+        .map(|a| a["addr"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        spawned,
+        vec![
+            format!("127.0.0.1:{port}"),
+            format!("127.0.0.1:{}", port + 1)
+        ]
+    );
+    assert!(!cwd.path().join("nodes").exists());
+    assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+}
+
+#[test]
+fn invalid_config_is_reported_before_starting() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+    let mut cfg = NamedTempFile::new().unwrap();
+    writeln!(cfg, "[network]\nnodes = 0\n").unwrap();
+    let out = Command::new(&exe)
+        .args(["dev-network", "--config"])
+        .arg(cfg.path())
+        .output()
+        .expect("spawn");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("`nodes` must be at least 1"),
+        "stderr: {stderr}"
+    );
+}
+
+#[test]
+fn lint_ring_passes_the_sample_and_flags_a_broken_ring() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+    let out = Command::new(&exe)
+        .args(["lint-ring", "--config", "samples/config/ring.toml"])
+        .output()
+        .expect("spawn");
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "OK\n");
+
+    let mut cfg = NamedTempFile::new().unwrap();
+    writeln!(
+        cfg,
+        "[[ring.nodes]]\naddr = \"127.0.0.1:7000\"\nnext = \"127.0.0.1:7001\"\n\n\
+         [[ring.nodes]]\naddr = \"127.0.0.1:7001\"\nnext = \"127.0.0.1:7001\""
+    )
+    .unwrap();
+    let out = Command::new(&exe)
+        .arg("lint-ring")
+        .arg("--config")
+        .arg(cfg.path())
+        .output()
+        .expect("spawn");
+    assert!(!out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "line 2: no node points at 127.0.0.1:7000, so nothing reaches it\n\
+         line 7: 127.0.0.1:7001 points at itself\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn dev_network_detach_returns_once_wired_and_stop_ends_it() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+    let exe = exe.canonicalize().unwrap();
+
+    let port = pick_port() + 40;
+    let cwd = tempfile::tempdir().unwrap();
+    let pid_file = cwd.path().join("ring.pid");
+    let out = Command::new(&exe)
+        .args([
+            "dev-network",
+            "-n",
+            "2",
+            "-w",
+            "0",
+            "--detach",
+            "--pid-file",
+        ])
+        .arg(&pid_file)
+        .args(["--base-port", &port.to_string()])
+        .current_dir(cwd.path())
+        .output()
+        .expect("spawn");
+    assert!(out.status.success(), "{out:?}");
+    let pid: u32 = std::fs::read_to_string(&pid_file)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(
+        String::from_utf8_lossy(&out.stdout).contains(&format!("PID {pid}")),
+        "{out:?}"
+    );
+    // Wired before the terminal got control back.
+    std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    std::net::TcpStream::connect(("127.0.0.1", port + 1)).unwrap();
+
+    let out = Command::new(&exe)
+        .args(["stop", "--pid-file"])
+        .arg(&pid_file)
+        .output()
+        .expect("spawn");
+    assert!(out.status.success(), "{out:?}");
+    assert!(!pid_file.exists());
+    assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+
+    // A second stop finds nothing to stop.
+    let out = Command::new(&exe)
+        .args(["stop", "--pid-file"])
+        .arg(&pid_file)
+        .output()
+        .expect("spawn");
+    assert!(!out.status.success());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn startup_script_runs_before_serving_and_run_script_replays_one() {
+    let exe = release_bin();
+    if !exe.exists() {
+        eprintln!("skipping: {} not built", exe.display());
+        return;
+    }
+
+    let port = pick_port() + 50;
+    let storage = tempfile::tempdir().unwrap();
+    let mut startup = NamedTempFile::new().unwrap();
+    writeln!(startup, "# seed the store\nKV SET color blue\n").unwrap();
+    let mut child = Command::new(&exe)
+        .args([
+            "run",
+            "--addr",
+            &format!("127.0.0.1:{port}"),
+            "--storage-root",
+        ])
+        .arg(storage.path())
+        .arg("--script")
+        .arg(startup.path())
+        .spawn()
+        .expect("spawn");
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        assert!(tokio::time::Instant::now() < deadline, "node never bound");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let mut replay = NamedTempFile::new().unwrap();
+    writeln!(replay, "KV GET color\nKV BOGUS\nNODE PING").unwrap();
+    let run_script = |continue_on_error: bool| {
+        let mut cmd = Command::new(&exe);
+        cmd.args([
+            "run-script",
+            "--addr",
+            &format!("127.0.0.1:{port}"),
+            "--script",
+        ])
+        .arg(replay.path());
+        if continue_on_error {
+            cmd.arg("--continue-on-error");
+        }
+        cmd.output().expect("spawn")
+    };
+    let out = run_script(false);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success(), "{out:?}");
+    assert!(
+        stdout.contains("> KV GET color\nVALUE color blue\nOK"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("PONG"), "{stdout}");
+    let out = run_script(true);
+    assert!(!out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stdout).contains("> NODE PING\nPONG"));
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    // A failing startup script keeps the node from starting.
+    let mut bad = NamedTempFile::new().unwrap();
+    writeln!(bad, "KV BOGUS").unwrap();
+    let out = Command::new(&exe)
+        .args([
+            "run",
+            "--addr",
+            &format!("127.0.0.1:{}", port + 1),
+            "--storage-root",
+        ])
+        .arg(storage.path())
+        .arg("--script")
+        .arg(bad.path())
+        .output()
+        .expect("spawn");
+    assert!(!out.status.success(), "{out:?}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("line 1: KV BOGUS"),
+        "{out:?}"
+    );
+}
@@ -0,0 +1,75 @@
+//! `CID:` headers: a correlation ID sent ahead of a command is echoed by
+//! `GET` replies and travels with every hop the command causes.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn send(addr: std::net::SocketAddr, lines: &str) -> String {
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(lines.as_bytes()).await.unwrap();
+    s.shutdown().await.ok();
+    let mut resp = String::new();
+    tokio::time::timeout(Duration::from_secs(5), s.read_to_string(&mut resp))
+        .await
+        .expect("no reply")
+        .unwrap();
+    resp
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_replies_echo_the_cid() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send(ring.addr(0), "CID: req-42\nKV GET colour\nKV GET colour\n").await;
+    // The ID tags one command only.
+    assert_eq!(
+        resp,
+        "CID: req-42\nVALUE colour <nil>\nOK\nVALUE colour <nil>\nOK\n"
+    );
+
+    let resp = send(ring.addr(0), "CID: not valid!\nNODE PING\n").await;
+    assert!(resp.starts_with("ERR "), "{resp}");
+    assert!(resp.ends_with("PONG\n"), "{resp}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hops_carry_the_cid() {
+    let ring = spin_up(RingOpts::default()).await;
+    // All the way round: every node takes the header and handles the hop.
+    assert_eq!(
+        send(ring.addr(0), "CID: walk-1\nRING FORWARD 2 hi\n").await,
+        "OK\n"
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for h in &ring.nodes {
+        assert_eq!(h.node.ring_msgs_total.load(Ordering::Relaxed), 1);
+    }
+
+    // What node 0 actually puts on the wire.
+    let next = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    ring.nodes[0]
+        .node
+        .set_next(next.local_addr().unwrap().to_string())
+        .await;
+    assert_eq!(
+        send(ring.addr(0), "CID: walk-2\nRING FORWARD 1 hi\n").await,
+        "OK\n"
+    );
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(5), next.accept())
+        .await
+        .expect("no forward")
+        .unwrap();
+    let mut stream = BufReader::new(stream);
+    let (mut header, mut line) = (String::new(), String::new());
+    stream.read_line(&mut header).await.unwrap();
+    stream.read_line(&mut line).await.unwrap();
+    assert_eq!(header, "CID: walk-2\n");
+    assert_eq!(line, "RING FORWARD-HOP 1 0 hi\n");
+    shutdown(ring).await;
+}
@@ -0,0 +1,86 @@
+//! `--dedup-cache-size`: a node reached by the same `RING FORWARD` line
+//! twice, as when two nodes both point their NEXT at it, forwards it once.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    storage: &Path,
+    dedup_cache_size: usize,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            dedup_cache_size,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+/// Point `a` and `b` at `c`, send one line to each, and return how many
+/// `RING FORWARD`s `c` handled.
+async fn fan_in(dedup_cache_size: usize) -> u64 {
+    let tmp = TempDir::new().unwrap();
+    let (_, a_addr, a_task) = start_node(tmp.path(), dedup_cache_size).await;
+    let (_, b_addr, b_task) = start_node(tmp.path(), dedup_cache_size).await;
+    let (c, c_addr, c_task) = start_node(tmp.path(), dedup_cache_size).await;
+    for from in [a_addr, b_addr] {
+        send_line(from, &format!("NODE NEXT {c_addr}\n")).await;
+    }
+
+    for from in [a_addr, b_addr] {
+        assert_eq!(send_line(from, "RING FORWARD 1 storm\n").await, "OK\n");
+    }
+    // Forwards are acked on receipt; give the second one time to land.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let handled = c.ring_msgs_total.load(Ordering::Relaxed);
+
+    for task in [a_task, b_task, c_task] {
+        task.abort();
+    }
+    handled
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn repeated_line_is_forwarded_once() {
+    assert_eq!(fan_in(1000).await, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_cache_size_forwards_every_copy() {
+    assert_eq!(fan_in(0).await, 2);
+}
@@ -0,0 +1,51 @@
+//! `diagnose`: a wired ring with an elected leader checks out, and one
+//! whose NEXT pointers loop back short of the seed is reported.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::client::ClientOpts;
+use ouroboros_fs::diagnose::diagnose;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wired_ring_is_healthy() {
+    let ring = spin_up(RingOpts::default()).await;
+    let seed = ring.addr(0).to_string();
+    let diagnosis = diagnose(&seed, &ClientOpts::default()).await;
+    assert_eq!(
+        diagnosis.warnings,
+        ["no node has role LEADER; run ELECT START"]
+    );
+
+    RingClient::new(seed.clone())
+        .raw("ELECT START")
+        .await
+        .unwrap();
+    let diagnosis = diagnose(&seed, &ClientOpts::default()).await;
+    assert!(diagnosis.healthy, "{}", diagnosis.to_text());
+    let addrs: Vec<&str> = diagnosis.nodes.iter().map(|n| n.addr.as_str()).collect();
+    let expected = [0, 1, 2].map(|i| ring.addr(i).to_string());
+    assert_eq!(addrs, expected);
+    assert!(diagnosis.nodes.iter().all(|n| n.alive));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loop_that_skips_the_seed_is_reported() {
+    let ring = spin_up(RingOpts::default()).await;
+    RingClient::new(ring.addr(2).to_string())
+        .set_next(&ring.addr(1).to_string())
+        .await
+        .unwrap();
+
+    let seed = ring.addr(0).to_string();
+    let diagnosis = diagnose(&seed, &ClientOpts::default()).await;
+    assert!(!diagnosis.healthy, "{}", diagnosis.to_text());
+    assert!(
+        diagnosis.warnings[0].starts_with("ring is not closed"),
+        "{}",
+        diagnosis.to_text()
+    );
+    shutdown(ring).await;
+}
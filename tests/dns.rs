@@ -0,0 +1,146 @@
+//! `NODE NEXT` with a hostname: resolved to the `ip:port` that answers,
+//! kept verbatim under `--no-dns-resolve` or when nothing answers.
+//! Addresses that can't name a peer are refused, and so are unreachable
+//! ones under `--verify-next-reachable`.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    storage: &Path,
+    no_dns_resolve: bool,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    start_node_with(
+        storage,
+        NodeOpts {
+            no_dns_resolve,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+async fn start_node_with(
+    storage: &Path,
+    opts: NodeOpts,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        opts,
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hostname_next_is_stored_as_resolved_ip() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, a_task) = start_node(tmp.path(), false).await;
+    let (_, b_addr, b_task) = start_node(tmp.path(), false).await;
+
+    let resp = send_line(a_addr, &format!("NODE NEXT localhost:{}\n", b_addr.port())).await;
+    assert_eq!(resp, format!("OK next={b_addr}\n"));
+    assert_eq!(a.get_next().await, Some(b_addr.to_string()));
+
+    a_task.abort();
+    b_task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unresolvable_next_is_stored_verbatim() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, task) = start_node(tmp.path(), false).await;
+    let resp = send_line(a_addr, "NODE NEXT no-such-host.invalid:7000\n").await;
+    assert!(resp.starts_with("OK"), "resp: {resp:?}");
+    assert_eq!(
+        a.get_next().await.as_deref(),
+        Some("no-such-host.invalid:7000")
+    );
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_dns_resolve_keeps_hostname() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, a_task) = start_node(tmp.path(), true).await;
+    let (_, b_addr, b_task) = start_node(tmp.path(), true).await;
+
+    let next = format!("localhost:{}", b_addr.port());
+    send_line(a_addr, &format!("NODE NEXT {next}\n")).await;
+    assert_eq!(a.get_next().await, Some(next));
+
+    a_task.abort();
+    b_task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn next_must_name_a_peer() {
+    let tmp = TempDir::new().unwrap();
+    let (a, a_addr, task) = start_node(tmp.path(), false).await;
+
+    let resp = send_line(a_addr, "NODE NEXT 127.0.0.1:7001\n").await;
+    assert_eq!(resp, "OK next=127.0.0.1:7001\n");
+    let resp = send_line(a_addr, "NODE NEXT 7002\n").await;
+    assert_eq!(resp, "OK next=127.0.0.1:7002\n");
+
+    for bad in ["hello", "999.999.999.999:0", "127.0.0.1:0"] {
+        let resp = send_line(a_addr, &format!("NODE NEXT {bad}\n")).await;
+        assert_eq!(resp, format!("ERR invalid address: {bad}\n"));
+        let resp = send_line(a_addr, &format!("NODE NEXT-RING backup {bad}\n")).await;
+        assert_eq!(resp, format!("ERR invalid address: {bad}\n"));
+    }
+    assert_eq!(a.get_next().await.as_deref(), Some("127.0.0.1:7002"));
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn verify_next_reachable_refuses_a_dead_peer() {
+    let tmp = TempDir::new().unwrap();
+    let opts = NodeOpts {
+        verify_next_reachable: true,
+        ..Default::default()
+    };
+    let (a, a_addr, a_task) = start_node_with(tmp.path(), opts.clone()).await;
+    let (_, b_addr, b_task) = start_node_with(tmp.path(), opts).await;
+
+    let resp = send_line(a_addr, &format!("NODE NEXT {b_addr}\n")).await;
+    assert_eq!(resp, format!("OK next={b_addr}\n"));
+
+    b_task.abort();
+    let _ = b_task.await;
+    let resp = send_line(a_addr, &format!("NODE NEXT-RING backup {b_addr}\n")).await;
+    assert_eq!(resp, format!("ERR unreachable address: {b_addr}\n"));
+    assert_eq!(a.get_next_on("backup").await, None);
+    a_task.abort();
+}
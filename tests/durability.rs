@@ -233,6 +233,8 @@ async fn bind_sweeps_orphan_partials() {
         async move { serve(n, listener).await }
     });
     serve_task.abort();
+    // Wait for the aborted task to drop the listener before rebinding.
+    let _ = serve_task.await;
 
     // Drop a bogus orphan partial and a bogus orphan backup partial.
     let content_dir = storage.join(&port).join("content");
@@ -0,0 +1,90 @@
+//! `NODE FORWARD-TO`: a node runs a command on another for a client that can
+//! only reach it, passing the client's correlation ID along, and refuses
+//! chains longer than `--forward-max-hops`.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::{RingClient, RingError};
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn commands_run_on_the_target_with_the_callers_cid() {
+    let ring = spin_up(RingOpts {
+        forward_max_hops: 3,
+        ..RingOpts::default()
+    })
+    .await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let client = RingClient::new(addrs[0].clone());
+
+    let set = client
+        .forward_to(&addrs[2], "KV SET color dark blue")
+        .await
+        .unwrap();
+    assert_eq!(set, ["OK"]);
+    let direct = RingClient::new(addrs[2].clone());
+    assert_eq!(
+        direct.raw("KV GET color").await.unwrap(),
+        ["VALUE color dark blue", "OK"]
+    );
+    assert_eq!(
+        client.raw("KV GET color").await.unwrap(),
+        ["VALUE color <nil>", "OK"]
+    );
+
+    // The target sees the CID and echoes it ahead of its KV GET reply.
+    let lines = client
+        .raw(&format!(
+            "CID: req-7\nNODE FORWARD-TO {} KV GET color",
+            addrs[2]
+        ))
+        .await
+        .unwrap();
+    assert_eq!(lines, ["CID: req-7", "VALUE color dark blue", "OK"]);
+
+    // Three nodes may be passed through; a fourth is refused.
+    let chain = format!(
+        "NODE FORWARD-TO {} NODE FORWARD-TO {} NODE PING",
+        addrs[2], addrs[0]
+    );
+    assert_eq!(
+        client.forward_to(&addrs[1], &chain).await.unwrap(),
+        ["PONG"]
+    );
+    let longer = format!("NODE FORWARD-TO {} {chain}", addrs[1]);
+    match client.forward_to(&addrs[2], &longer).await {
+        Err(RingError::Remote { msg, .. }) => assert!(msg.contains("exceeds max 3"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failures_come_back_as_err() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    let target = ring.addr(1).to_string();
+
+    // The target's own ERR is relayed.
+    match client.forward_to(&target, "NODE BOGUS").await {
+        Err(RingError::Remote { msg, .. }) => assert!(!msg.contains("forward to"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+
+    // Nothing listening there.
+    let gone = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let gone_addr = gone.local_addr().unwrap().to_string();
+    drop(gone);
+    match client.forward_to(&gone_addr, "NODE PING").await {
+        Err(RingError::Remote { msg, .. }) => assert!(msg.contains("forward to"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+
+    match client.forward_to(&target, "FILE LIST").await {
+        Err(RingError::Remote { msg, .. }) => assert!(msg.contains("cannot forward"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+    shutdown(ring).await;
+}
@@ -0,0 +1,34 @@
+//! `GATHER STREAM`: the start node answers with a stream port at once and
+//! pushes each node's value there, ending with `END`.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_carries_every_node_once() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+
+    let mut stream = client.gather_stream("port").await.unwrap();
+    let mut streamed = Vec::new();
+    while let Some(entry) = stream.next_entry().await.unwrap() {
+        streamed.push(entry);
+    }
+    // The start node's own entry is ready before the walk leaves it.
+    assert_eq!(streamed[0].0, ring.nodes[0].node.port);
+
+    let mut gathered = client.gather("port").await.unwrap();
+    streamed.sort();
+    gathered.sort();
+    assert_eq!(streamed, gathered);
+
+    // The command connection is free again while a stream is open.
+    let _open = client.gather_stream("next").await.unwrap();
+    client.ping().await.unwrap();
+
+    let bad = client.raw("GATHER STREAM queue_depth").await;
+    assert!(bad.is_err(), "{bad:?}");
+    shutdown(ring).await;
+}
@@ -0,0 +1,115 @@
+//! `--heartbeat-addr`: nodes announce themselves to a heartbeat
+//! coordinator, which lists the live ones under `MEMBERS` and marks a node
+//! that stops sending dead.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::ClientOpts;
+use ouroboros_fs::heartbeat::{HeartbeatCoordinator, MembershipChange};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn members(addr: &str) -> String {
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(b"MEMBERS\n").await.unwrap();
+    s.shutdown().await.unwrap();
+    let mut reply = String::new();
+    s.read_to_string(&mut reply).await.unwrap();
+    reply
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn members_join_and_a_silent_one_is_marked_dead() {
+    let interval = Duration::from_millis(100);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let coordinator_addr = listener.local_addr().unwrap().to_string();
+    let coordinator = HeartbeatCoordinator::new(interval, ClientOpts::default());
+    let mut changes = coordinator.subscribe();
+    tokio::spawn(Arc::clone(&coordinator).serve(listener));
+
+    let ring = spin_up(RingOpts {
+        n: 2,
+        named: true,
+        heartbeat_addr: Some(coordinator_addr.clone()),
+        heartbeat_interval: interval,
+        ..RingOpts::default()
+    })
+    .await;
+    let addrs: Vec<String> = (0..2).map(|i| ring.addr(i).to_string()).collect();
+
+    // Both nodes join; their first heartbeats may predate the wiring.
+    let mut joined = Vec::new();
+    while joined.len() < 2 {
+        let change = tokio::time::timeout(Duration::from_secs(2), changes.recv())
+            .await
+            .expect("no join within 2 s")
+            .unwrap();
+        let MembershipChange::Joined(m) = change else {
+            panic!("unexpected {change:?}");
+        };
+        joined.push(m.addr);
+    }
+    joined.sort();
+    let mut expected = addrs.clone();
+    expected.sort();
+    assert_eq!(joined, expected);
+
+    tokio::time::sleep(interval * 2).await;
+    let generation = ring.nodes[0].node.generation();
+    let reply = members(&coordinator_addr).await;
+    assert!(
+        reply.contains(&format!(
+            "MEMBER {} node-00 {generation} {}\n",
+            addrs[0], addrs[1]
+        )),
+        "{reply:?}"
+    );
+    assert!(reply.ends_with("OK\n"), "{reply:?}");
+
+    // Node 1 shuts down and goes quiet.
+    let mut s = TcpStream::connect(&addrs[1]).await.unwrap();
+    s.write_all(b"NODE SHUTDOWN\n").await.unwrap();
+    let change = tokio::time::timeout(Duration::from_secs(2), changes.recv())
+        .await
+        .expect("node 1 was not marked dead")
+        .unwrap();
+    assert_eq!(change, MembershipChange::Dead(addrs[1].clone()));
+    let reply = members(&coordinator_addr).await;
+    assert!(
+        !reply.contains(&format!("MEMBER {} ", addrs[1])),
+        "{reply:?}"
+    );
+    assert!(
+        reply.starts_with(&format!("MEMBER {} ", addrs[0])),
+        "{reply:?}"
+    );
+
+    shutdown(ring).await;
+}
+
+#[tokio::test]
+async fn heartbeat_after_death_rejoins() {
+    let coordinator = HeartbeatCoordinator::new(Duration::from_millis(10), ClientOpts::default());
+    let member = ouroboros_fs::heartbeat::Member {
+        addr: "127.0.0.1:7000".into(),
+        name: "<unset>".into(),
+        generation: 1,
+        next: None,
+    };
+    assert!(coordinator.record(member.clone()).await.is_some());
+    assert!(coordinator.record(member.clone()).await.is_none());
+    assert!(coordinator.sweep().await.is_empty());
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(coordinator.sweep().await, vec![member.addr.clone()]);
+    assert!(coordinator.members().await.is_empty());
+    assert_eq!(
+        coordinator.record(member.clone()).await,
+        Some(MembershipChange::Joined(member.clone()))
+    );
+    assert_eq!(coordinator.members().await, vec![member]);
+}
@@ -0,0 +1,129 @@
+//! `--hello`: nodes greet every connection with their protocol version and
+//! refuse peers whose greeting is incompatible.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::protocol::{
+    MIN_SUPPORTED_VERSION, PROTOCOL_VERSION, ProtocolVersion, negotiate_version,
+};
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+async fn start_hello_node(storage: &Path) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            hello: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+/// Negotiate, send one line, and read everything after the greeting.
+async fn hello_send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        negotiate_version(&mut s, MIN_SUPPORTED_VERSION)
+            .await
+            .expect("compatible greeting");
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hello_node_greets_before_anything_else() {
+    let tmp = TempDir::new().unwrap();
+    let (node, addr, task) = start_hello_node(tmp.path()).await;
+
+    let mut reader = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    let mut greeting = String::new();
+    tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut greeting))
+        .await
+        .expect("greeting timed out")
+        .unwrap();
+    assert_eq!(greeting, PROTOCOL_VERSION.node_greeting(node.generation()));
+
+    assert_eq!(hello_send_line(addr, "NODE PING\n").await.trim(), "PONG");
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hello_ring_walk_completes() {
+    let tmp = TempDir::new().unwrap();
+    let mut addrs = Vec::new();
+    let mut tasks = Vec::new();
+    for i in 0..3 {
+        let (_, addr, task) = start_hello_node(&tmp.path().join(format!("n{i}"))).await;
+        addrs.push(addr);
+        tasks.push(task);
+    }
+    for i in 0..3 {
+        let next = addrs[(i + 1) % 3];
+        let resp = hello_send_line(addrs[i], &format!("NODE NEXT {next}\n")).await;
+        assert!(resp.starts_with("OK"), "resp: {resp:?}");
+    }
+
+    // Every hop goes through `Node::connect`, which negotiates first.
+    let resp = hello_send_line(addrs[0], "TOPOLOGY WALK\n").await;
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 3, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+
+    for t in tasks {
+        t.abort();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hello_node_refuses_newer_major_peer() {
+    let tmp = TempDir::new().unwrap();
+    let (node, _, task) = start_hello_node(tmp.path()).await;
+
+    // A stand-in peer announcing a major version this build doesn't speak.
+    let future = ProtocolVersion {
+        major: PROTOCOL_VERSION.major + 1,
+        minor: 0,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let peer = listener.local_addr().unwrap();
+    let fake = tokio::spawn(async move {
+        let (mut s, _) = listener.accept().await.unwrap();
+        s.write_all(future.greeting().as_bytes()).await.unwrap();
+        let mut sink = Vec::new();
+        let _ = s.read_to_end(&mut sink).await;
+    });
+
+    let err = match node.connect(&peer.to_string()).await {
+        Ok(_) => panic!("connected to an incompatible peer"),
+        Err(e) => e,
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("incompatible"), "{err}");
+
+    fake.abort();
+    task.abort();
+}
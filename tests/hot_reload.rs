@@ -0,0 +1,79 @@
+//! `dev-network --hot-reload`: edits to the `[ring]` table of a watched
+//! config reach the live nodes as `NODE NEXT`, and a config `lint-ring`
+//! rejects is left unapplied.
+
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::topology_diff::watch;
+use ouroboros_fs::{ClientOpts, RingClient};
+use tempfile::TempDir;
+
+fn write_ring(path: &Path, edges: &[(&str, &str)]) {
+    let mut raw = String::from("[network]\nnodes = 3\n");
+    for (addr, next) in edges {
+        raw.push_str(&format!(
+            "\n[[ring.nodes]]\naddr = \"{addr}\"\nnext = \"{next}\"\n"
+        ));
+    }
+    std::fs::write(path, raw).unwrap();
+}
+
+async fn next_of(addr: &str) -> Option<String> {
+    RingClient::new(addr).get().await.unwrap().next
+}
+
+/// Wait until every node in `edges` reports its NEXT as listed.
+async fn wait_for_wiring(edges: &[(&str, &str)]) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let mut wired = true;
+        for (addr, next) in edges {
+            wired &= next_of(addr).await.as_deref() == Some(*next);
+        }
+        if wired {
+            return;
+        }
+        assert!(Instant::now() < deadline, "ring never matched {edges:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_edits_rewire_the_ring() {
+    let ring = spin_up(RingOpts::default()).await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let (a, b, c) = (addrs[0].as_str(), addrs[1].as_str(), addrs[2].as_str());
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("ring.toml");
+    write_ring(&path, &[(a, b), (b, c), (c, a)]);
+
+    let watcher = tokio::spawn(watch(
+        path.clone(),
+        ClientOpts::default(),
+        Duration::from_millis(20),
+    ));
+    // The file as first read is taken as wired; let the watcher read it.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let reversed = [(a, c), (c, b), (b, a)];
+    write_ring(&path, &reversed);
+    wait_for_wiring(&reversed).await;
+
+    // A self-loop fails lint-ring, so nothing is sent.
+    write_ring(&path, &[(a, a), (b, c), (c, b)]);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(next_of(a).await.as_deref(), Some(c));
+    assert_eq!(next_of(b).await.as_deref(), Some(a));
+
+    // Back to the original order, from the last wiring applied.
+    let original = [(a, b), (b, c), (c, a)];
+    write_ring(&path, &original);
+    wait_for_wiring(&original).await;
+
+    watcher.abort();
+    shutdown(ring).await;
+}
@@ -0,0 +1,85 @@
+//! `INCR START`: an increment travels the whole ring, every node counts it, and
+//! the start node answers with the counter's value.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn increments_reach_every_node() {
+    let ring = spin_up(RingOpts::default()).await;
+    for (i, want) in [(0, 1), (1, 2), (2, 3)] {
+        let client = RingClient::new(ring.addr(i).to_string());
+        assert_eq!(client.incr("hits").await.unwrap(), want);
+    }
+    for h in &ring.nodes {
+        assert_eq!(h.node.counter_value("hits"), 3);
+        assert_eq!(h.node.counter_value("misses"), 0);
+    }
+
+    // The reply closes with OK like any other command's.
+    let client = RingClient::new(ring.addr(0).to_string());
+    assert_eq!(
+        client.raw("INCR START hits").await.unwrap(),
+        ["INCR_RESULT hits 4", "OK"]
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_increments_each_get_their_own_answer() {
+    let ring = spin_up(RingOpts::default()).await;
+    let tasks: Vec<_> = (0..20)
+        .map(|i| {
+            let client = RingClient::new(ring.addr(i % 3).to_string());
+            tokio::spawn(async move { client.incr("hits").await })
+        })
+        .collect();
+    let mut values = Vec::new();
+    for t in tasks {
+        values.push(t.await.unwrap().unwrap());
+    }
+    assert!(values.iter().all(|&v| (1..=20).contains(&v)), "{values:?}");
+    assert_eq!(values.iter().max(), Some(&20));
+    for h in &ring.nodes {
+        assert_eq!(h.node.counter_value("hits"), 20);
+    }
+    shutdown(ring).await;
+}
+
+/// On a ring that never gets back to the start node (0 -> 1 -> 2 -> 1),
+/// the increment stops counting at `--max-walk-hops`.
+#[tokio::test(flavor = "multi_thread")]
+async fn increment_on_a_miswired_ring_stops_at_max_walk_hops() {
+    let ring = spin_up(RingOpts {
+        max_walk_hops: 4,
+        ..RingOpts::default()
+    })
+    .await;
+    RingClient::new(ring.addr(2).to_string())
+        .set_next(&ring.addr(1).to_string())
+        .await
+        .unwrap();
+
+    // The start node never hears back, so don't wait for its reply.
+    let mut conn = TcpStream::connect(ring.addr(0)).await.unwrap();
+    conn.write_all(b"INCR START hits\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let counts = || -> Vec<u64> {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.counter_value("hits"))
+            .collect()
+    };
+    // Hops 1 to 4 alternate between nodes 1 and 2; node 1 drops hop 5.
+    assert_eq!(counts(), [1, 2, 2]);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(counts(), [1, 2, 2]);
+    drop(conn);
+    shutdown(ring).await;
+}
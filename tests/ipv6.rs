@@ -0,0 +1,95 @@
+//! IPv6 node addresses: rings bound on `[::1]`, alone and mixed with IPv4
+//! nodes. Skipped when the host has no IPv6 loopback.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind("[::1]:0").is_ok()
+}
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(bind: &str, storage: &Path) -> (SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        bind,
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts::default(),
+    )
+    .await
+    .expect("bind");
+    (addr, tokio::spawn(serve(node, listener)))
+}
+
+/// Start one node per bind address, wire them into a ring with NODE NEXT
+/// (using the bracketed form for IPv6 peers), and walk it.
+async fn walk_ring(binds: &[&str]) -> String {
+    let tmp = TempDir::new().unwrap();
+    let mut addrs = Vec::new();
+    let mut tasks = Vec::new();
+    for (i, bind) in binds.iter().enumerate() {
+        let (addr, task) = start_node(bind, &tmp.path().join(format!("n{i}"))).await;
+        addrs.push(addr);
+        tasks.push(task);
+    }
+    let n = addrs.len();
+    for i in 0..n {
+        let next = addrs[(i + 1) % n];
+        let resp = send_line(addrs[i], &format!("NODE NEXT {next}\n")).await;
+        assert_eq!(resp, format!("OK next={next}\n"));
+    }
+    let resp = send_line(addrs[0], "TOPOLOGY WALK\n").await;
+    for t in tasks {
+        t.abort();
+    }
+    resp
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ipv6_ring_walk_completes() {
+    if !ipv6_available() {
+        eprintln!("skipping: no IPv6 loopback");
+        return;
+    }
+    let resp = walk_ring(&["[::1]:0", "[::1]:0", "[::1]:0"]).await;
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 3, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mixed_ipv4_ipv6_ring_walk_completes() {
+    if !ipv6_available() {
+        eprintln!("skipping: no IPv6 loopback");
+        return;
+    }
+    let resp = walk_ring(&["127.0.0.1:0", "[::1]:0", "127.0.0.1:0", "[::1]:0"]).await;
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 4, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+}
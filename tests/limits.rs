@@ -1,10 +1,13 @@
-//! Series E hardening probes: idle-timeout and max-conns.
+//! Series E hardening probes: idle-timeout, accept-timeout, max-conns and
+//! the RING TTL / hop count / walk length caps.
 
 mod common;
 
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
 use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
@@ -65,6 +68,281 @@ async fn idle_timeout_does_not_break_legitimate_clients() {
     shutdown(ring).await;
 }
 
+/// The accept timeout only bounds the wait for the first command: a silent
+/// connection is dropped once it passes, but one that has sent a command
+/// may then sit idle for longer (here with no idle timeout at all).
+#[tokio::test(flavor = "multi_thread")]
+async fn accept_timeout_applies_to_the_first_line_only() {
+    let ring = spin_up(RingOpts {
+        n: 1,
+        accept_timeout: Duration::from_millis(300),
+        ..RingOpts::default()
+    })
+    .await;
+
+    let mut silent = TcpStream::connect(ring.addr(0)).await.unwrap();
+    let mut buf = String::new();
+    tokio::time::timeout(Duration::from_secs(2), silent.read_to_string(&mut buf))
+        .await
+        .expect("silent connection was not dropped")
+        .unwrap();
+    assert_eq!(buf, "ERR idle timeout\n");
+
+    let mut s = TcpStream::connect(ring.addr(0)).await.unwrap();
+    let mut pong = [0u8; 5];
+    s.write_all(b"NODE PING\n").await.unwrap();
+    s.read_exact(&mut pong).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    s.write_all(b"NODE PING\n").await.unwrap();
+    s.read_exact(&mut pong).await.unwrap();
+    assert_eq!(&pong, b"PONG\n");
+
+    shutdown(ring).await;
+}
+
+/// Send `line`, half-close, and read the reply to EOF.
+async fn exchange(addr: std::net::SocketAddr, line: &str) -> String {
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(line.as_bytes()).await.unwrap();
+    s.shutdown().await.ok();
+    let mut buf = String::new();
+    tokio::time::timeout(Duration::from_secs(5), s.read_to_string(&mut buf))
+        .await
+        .expect("no reply")
+        .unwrap();
+    buf
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_ttl_over_the_cap_is_refused() {
+    let ring = spin_up(RingOpts {
+        max_ring_ttl: 4,
+        ..RingOpts::default()
+    })
+    .await;
+    let forwarded = || ring.nodes[1].node.ring_msgs_total.load(Ordering::Relaxed);
+    let before = forwarded();
+
+    assert_eq!(
+        exchange(ring.addr(0), "RING FORWARD 5 hi\n").await,
+        "ERR ttl exceeds max 4\n"
+    );
+    assert_eq!(
+        exchange(ring.addr(0), "RING ACK 99 hi\n").await,
+        "ERR ttl exceeds max 4\n"
+    );
+    assert_eq!(forwarded(), before);
+
+    assert_eq!(exchange(ring.addr(0), "RING FORWARD 4 hi\n").await, "OK\n");
+    shutdown(ring).await;
+}
+
+/// A message that has taken more than `--max-ring-hops` hops is dropped
+/// however much TTL it has left.
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_messages_past_max_ring_hops_are_dropped() {
+    let ring = spin_up(RingOpts {
+        max_ring_hops: 2,
+        ..RingOpts::default()
+    })
+    .await;
+    let received = || -> Vec<u64> {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+            .collect()
+    };
+    let before = received();
+
+    // Hops 0, 1 and 2 are delivered; node 0 drops hop 3.
+    assert_eq!(
+        exchange(ring.addr(0), "RING FORWARD 9 storm\n").await,
+        "OK\n"
+    );
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let after: Vec<u64> = received().iter().zip(&before).map(|(a, b)| a - b).collect();
+    assert_eq!(after, [1, 1, 1]);
+
+    // A hop over the limit is still acked, but goes no further.
+    assert_eq!(
+        exchange(ring.addr(1), "RING FORWARD-HOP 3 5 late\n").await,
+        "OK\n"
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let after: Vec<u64> = received().iter().zip(&before).map(|(a, b)| a - b).collect();
+    assert_eq!(after, [1, 1, 1]);
+    shutdown(ring).await;
+}
+
+/// With the cap off, a hop count at `u32::MAX` is still forwarded rather
+/// than overflowing.
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_hop_count_at_u32_max_does_not_overflow() {
+    let ring = spin_up(RingOpts::default()).await;
+    let received = || -> u64 {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+            .sum()
+    };
+    let before = received();
+    assert_eq!(
+        exchange(ring.addr(0), "RING FORWARD-HOP 4294967295 1 edge\n").await,
+        "OK\n"
+    );
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(received() - before, 2);
+    shutdown(ring).await;
+}
+
+/// Named rings count hops too: a message sent round one with more TTL
+/// than `--max-ring-hops` allows raises `RING_STORM` where it is dropped.
+#[tokio::test(flavor = "multi_thread")]
+async fn looping_named_ring_raises_ring_storm() {
+    let ring = spin_up(RingOpts {
+        max_ring_hops: 2,
+        ..RingOpts::default()
+    })
+    .await;
+    for i in 0..3 {
+        let next = ring.addr((i + 1) % 3);
+        let reply = exchange(ring.addr(i), &format!("NODE NEXT-RING backup {next}\n")).await;
+        assert!(reply.starts_with("OK"), "{reply}");
+    }
+    let mut events = RingClient::new(ring.addr(0).to_string())
+        .subscribe("ring".parse().unwrap())
+        .await
+        .unwrap();
+
+    // Hops 0, 1 and 2 are delivered; node 0 drops hop 3.
+    assert_eq!(
+        exchange(ring.addr(0), "RING ON backup 9 storm\n").await,
+        "OK\n"
+    );
+    loop {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next_event())
+            .await
+            .expect("no RING_STORM event")
+            .unwrap()
+            .expect("stream closed");
+        if event.starts_with("EVENT ring RING_STORM") {
+            assert_eq!(event, "EVENT ring RING_STORM hop_count=3 max=2");
+            break;
+        }
+    }
+    let received: Vec<u64> = ring
+        .nodes
+        .iter()
+        .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+        .collect();
+    assert_eq!(received, [1, 1, 1]);
+    events.unsubscribe().await.unwrap();
+    shutdown(ring).await;
+}
+
+/// `RING ONCE` has no TTL, so on a ring that never gets back to the start
+/// node (0 -> 1 -> 2 -> 1) only `--max-ring-hops` stops it.
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_once_on_a_miswired_ring_stops_at_max_ring_hops() {
+    let ring = spin_up(RingOpts {
+        max_ring_hops: 4,
+        ..RingOpts::default()
+    })
+    .await;
+    let reply = exchange(ring.addr(2), &format!("NODE NEXT {}\n", ring.addr(1))).await;
+    assert!(reply.starts_with("OK"), "{reply}");
+    let received = || -> Vec<u64> {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+            .collect()
+    };
+    let before = received();
+
+    // The start node never hears back, so don't wait for its reply.
+    let mut conn = TcpStream::connect(ring.addr(0)).await.unwrap();
+    conn.write_all(b"RING ONCE lost\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    // Hops 1 to 4 alternate between nodes 1 and 2; node 1 drops hop 5.
+    let after: Vec<u64> = received().iter().zip(&before).map(|(a, b)| a - b).collect();
+    assert_eq!(after, [1, 2, 2]);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let after: Vec<u64> = received().iter().zip(&before).map(|(a, b)| a - b).collect();
+    assert_eq!(after, [1, 2, 2]);
+    drop(conn);
+    shutdown(ring).await;
+}
+
+/// A partition probe on a ring that never gets back to its start node
+/// (0 -> 1 -> 2 -> 1) is dropped at `--max-walk-hops`.
+#[tokio::test(flavor = "multi_thread")]
+async fn probe_on_a_miswired_ring_stops_at_max_walk_hops() {
+    let ring = spin_up(RingOpts {
+        max_walk_hops: 4,
+        ..RingOpts::default()
+    })
+    .await;
+    let reply = exchange(ring.addr(2), &format!("NODE NEXT {}\n", ring.addr(1))).await;
+    assert!(reply.starts_with("OK"), "{reply}");
+    let forwarded = || -> Vec<u64> {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.msgs_forwarded.load(Ordering::Relaxed))
+            .collect()
+    };
+    let before = forwarded();
+
+    let hop = format!("TOPOLOGY PROBE-HOP probe-1-x 1 {}\n", ring.addr(0));
+    assert_eq!(exchange(ring.addr(1), &hop).await, "OK\n");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    // Node 1 forwards hops 2 and 4, node 2 hop 3; node 2 drops hop 4.
+    let after: Vec<u64> = forwarded()
+        .iter()
+        .zip(&before)
+        .map(|(a, b)| a - b)
+        .collect();
+    assert_eq!(after, [0, 2, 1]);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let after: Vec<u64> = forwarded()
+        .iter()
+        .zip(&before)
+        .map(|(a, b)| a - b)
+        .collect();
+    assert_eq!(after, [0, 2, 1]);
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walks_stop_at_max_walk_hops() {
+    let ring = spin_up(RingOpts {
+        max_walk_hops: 2,
+        ..RingOpts::default()
+    })
+    .await;
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+
+    let reply = exchange(ring.addr(0), "TOPOLOGY WALK\n").await;
+    let edges: Vec<&str> = reply.lines().skip(1).collect();
+    assert_eq!(
+        edges,
+        [format!("{p0}->{p1}"), format!("{p1}->{p2}"), "OK".into()]
+    );
+
+    // A client's larger WALK-MAX limit is lowered to the node's.
+    let reply = exchange(ring.addr(0), "TOPOLOGY WALK-MAX 10\n").await;
+    let lines: Vec<&str> = reply.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        [
+            "WALK_TRUNCATED".into(),
+            format!("{p0}->{p1}"),
+            format!("{p1}->{p2}"),
+            "OK".into()
+        ]
+    );
+    shutdown(ring).await;
+}
+
 /// With max_conns=2, holding two connections open and opening a third
 /// should yield `ERR server busy`. (max_conns=1 would block the harness's
 /// own NETMAP DISCOVER + TOPOLOGY WALK during `spin_up`.)
@@ -101,6 +379,69 @@ async fn max_conns_saturated_returns_busy() {
     shutdown(ring).await;
 }
 
+/// `max` connections held open at once, the last of them reading
+/// `NODE STAT`, fill every permit; connection `max + 1` is turned away.
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_past_max_is_busy_and_stat_counts_the_rest() {
+    const MAX: usize = 4;
+    let ring = spin_up(RingOpts {
+        n: 1,
+        max_conns: MAX as u32,
+        ..RingOpts::default()
+    })
+    .await;
+
+    let mut held = Vec::with_capacity(MAX);
+    for _ in 0..MAX {
+        held.push(hold_admitted_conn(ring.addr(0)).await);
+    }
+    let last = held.last_mut().unwrap();
+    last.write_all(b"NODE STAT\n").await.unwrap();
+    let mut stat = Vec::new();
+    while !stat.ends_with(b"OK\n") {
+        let mut buf = [0u8; 256];
+        let n = last.read(&mut buf).await.unwrap();
+        assert!(n > 0, "closed mid-STAT: {stat:?}");
+        stat.extend_from_slice(&buf[..n]);
+    }
+    let stat = String::from_utf8(stat).unwrap();
+    assert!(
+        stat.lines().any(|l| l == format!("CONNECTIONS {MAX}")),
+        "{stat:?}"
+    );
+
+    let mut extra = TcpStream::connect(ring.addr(0)).await.unwrap();
+    let mut buf = String::new();
+    tokio::time::timeout(Duration::from_secs(2), extra.read_to_string(&mut buf))
+        .await
+        .expect("connection past the cap should be closed promptly")
+        .unwrap();
+    assert_eq!(buf, "ERR server busy\n");
+
+    drop(held);
+    shutdown(ring).await;
+}
+
+/// Open a connection the server has admitted. `spin_up`'s own TOPOLOGY
+/// WALK connection can still hold a permit briefly after it returns, so a
+/// connection refused with `ERR server busy` is retried.
+async fn hold_admitted_conn(addr: std::net::SocketAddr) -> TcpStream {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(3);
+    loop {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(b"NODE PING\n").await.unwrap();
+        let mut buf = [0u8; 5];
+        if s.read_exact(&mut buf).await.is_ok() && &buf == b"PONG\n" {
+            return s;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "connection never admitted"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
 /// Sanity: with the cap disabled (default), many concurrent connections work.
 #[tokio::test(flavor = "multi_thread")]
 async fn max_conns_zero_disables_cap() {
@@ -0,0 +1,92 @@
+//! `--max-line-bytes`: a line past the limit gets `ERR message too large`
+//! and the connection is closed before the node buffers the rest.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_bytes(addr: SocketAddr, bytes: &[u8]) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        // The node may close before reading everything; a reset on write is
+        // part of the behaviour under test.
+        let _ = s.write_all(bytes).await;
+        let _ = s.shutdown().await;
+        let mut resp = String::new();
+        let _ = s.read_to_string(&mut resp).await;
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    storage: &Path,
+    max_line_bytes: usize,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            max_line_bytes,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn over_long_line_is_rejected_and_closed() {
+    let tmp = TempDir::new().unwrap();
+    let (node, addr, task) = start_node(tmp.path(), 1024).await;
+
+    // A long line followed by a valid command: the command is never served.
+    let mut req = format!("NODE NEXT 127.0.0.1:{}\n", "9".repeat(2048)).into_bytes();
+    req.extend_from_slice(b"NODE PING\n");
+    let resp = send_bytes(addr, &req).await;
+    assert_eq!(resp, "ERR message too large\n");
+    assert_eq!(node.get_next().await, None);
+
+    // Lines within the limit are unaffected.
+    assert_eq!(send_bytes(addr, b"NODE PING\n").await, "PONG\n");
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn line_exactly_at_limit_is_accepted() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node(tmp.path(), 10).await;
+    assert_eq!(send_bytes(addr, b"NODE PING\n").await, "PONG\n");
+    assert_eq!(
+        send_bytes(addr, b"NODE  PING\n").await,
+        "ERR message too large\n"
+    );
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_disables_the_limit() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node(tmp.path(), 0).await;
+    let line = format!("NODE PING {}\n", "x".repeat(2 << 20));
+    let resp = send_bytes(addr, line.as_bytes()).await;
+    assert!(!resp.contains("too large"), "resp: {resp:?}");
+    task.abort();
+}
@@ -0,0 +1,156 @@
+//! `--metrics-port`: a node serves Prometheus text at `GET /metrics` on a
+//! second port, reflecting live counters, and stops serving it when the
+//! ring server shuts down.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, NodeOpts, bind_with_opts, serve_with_shutdown};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+async fn send_bytes(addr: SocketAddr, bytes: &[u8]) -> String {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(bytes).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn http_get(addr: SocketAddr, path: &str) -> String {
+    send_bytes(
+        addr,
+        format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes(),
+    )
+    .await
+}
+
+/// Value of the sample line starting with `series ` in `body`.
+fn sample(body: &str, series: &str) -> u64 {
+    body.lines()
+        .find_map(|l| l.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {series} in:\n{body}"))
+        .parse()
+        .unwrap()
+}
+
+struct Started {
+    addr: SocketAddr,
+    metrics: SocketAddr,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+/// A one-node ring (next and prev point at itself) with metrics on an
+/// ephemeral port.
+async fn start_node(storage: &Path) -> Started {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            metrics_port: Some(0),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let (stop, rx) = oneshot::channel();
+    let task = tokio::spawn(serve_with_shutdown(
+        Arc::clone(&node),
+        listener,
+        rx,
+        Duration::from_secs(5),
+    ));
+    let wire = format!("NODE NEXT {addr}\nNODE PREV {addr}\n");
+    assert_eq!(send_bytes(addr, wire.as_bytes()).await.lines().count(), 2);
+    let metrics = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(a) = node.metrics.addr() {
+                return a;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("metrics server never started");
+    Started {
+        addr,
+        metrics,
+        stop,
+        task,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrape_reports_commands_walks_and_hops() {
+    let tmp = TempDir::new().unwrap();
+    let n = start_node(tmp.path()).await;
+
+    send_bytes(n.addr, b"NODE STATUS\nNODE STATUS\nNODE PING\n").await;
+    let walk = send_bytes(n.addr, b"TOPOLOGY WALK\n").await;
+    assert!(walk.ends_with("OK\n"), "walk: {walk}");
+
+    let resp = http_get(n.metrics, "/metrics").await;
+    assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+    let body = resp.split_once("\r\n\r\n").unwrap().1;
+    assert_eq!(sample(body, "ouroboros_messages_total{command=\"GET\"}"), 2);
+    assert_eq!(
+        sample(body, "ouroboros_messages_total{command=\"WALK\"}"),
+        1
+    );
+    // NODE NEXT during setup.
+    assert_eq!(
+        sample(body, "ouroboros_messages_total{command=\"SET_NEXT\"}"),
+        1
+    );
+    assert_eq!(sample(body, "ouroboros_walk_duration_seconds_count"), 1);
+    assert_eq!(
+        sample(body, "ouroboros_walk_duration_seconds_bucket{le=\"+Inf\"}"),
+        1
+    );
+    assert!(sample(body, "ouroboros_ring_hops_total") >= 1);
+    assert!(body.contains("# TYPE ouroboros_active_connections gauge"));
+    assert!(body.contains("# TYPE ouroboros_walk_duration_seconds histogram"));
+
+    n.stop.send(()).unwrap();
+    n.task.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn other_paths_are_not_found_and_server_stops_with_node() {
+    let tmp = TempDir::new().unwrap();
+    let n = start_node(tmp.path()).await;
+
+    let resp = http_get(n.metrics, "/").await;
+    assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{resp}");
+    let resp = send_bytes(n.metrics, b"POST /metrics HTTP/1.1\r\n\r\n").await;
+    assert!(resp.starts_with("HTTP/1.1 405 "), "{resp}");
+
+    n.stop.send(()).unwrap();
+    n.task.await.unwrap();
+    // The aborted metrics task releases its listener.
+    let gone = tokio::time::timeout(Duration::from_secs(5), async {
+        while TcpStream::connect(n.metrics).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(gone.is_ok(), "metrics port still accepting after shutdown");
+}
@@ -0,0 +1,98 @@
+//! Named rings: `NODE NEXT-RING` wires a second ring over the same nodes,
+//! and `RING ON` / `TOPOLOGY RING-WALK` follow it without touching the
+//! default ring. `NODE NEXT-RINGS` rewires several rings in one go.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn named_ring_runs_beside_the_default_one() {
+    let ring = spin_up(RingOpts::default()).await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let clients: Vec<RingClient> = addrs.iter().map(RingClient::new).collect();
+
+    // "backup" runs the other way round: 0 -> 2 -> 1 -> 0.
+    for (from, to) in [(0, 2), (2, 1), (1, 0)] {
+        clients[from]
+            .set_next_ring("backup", &addrs[to])
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        clients[0].get_ring("backup").await.unwrap().as_deref(),
+        Some(addrs[2].as_str())
+    );
+    assert_eq!(
+        clients[0].get_ring("default").await.unwrap().as_deref(),
+        Some(addrs[1].as_str())
+    );
+    assert_eq!(clients[0].get_ring("missing").await.unwrap(), None);
+
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port().to_string());
+    assert_eq!(
+        clients[0].walk_ring("backup").await.unwrap(),
+        vec![
+            (p0.clone(), p2.clone()),
+            (p2.clone(), p1.clone()),
+            (p1.clone(), p0.clone())
+        ]
+    );
+    assert_eq!(
+        clients[0].walk().await.unwrap(),
+        vec![(p0.clone(), p1.clone()), (p1, p2.clone()), (p2, p0)]
+    );
+
+    // One hop on "backup" lands on node 2, not node 1.
+    clients[0].ring_on("backup", 1, "hi").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let handled: Vec<u64> = ring
+        .nodes
+        .iter()
+        .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+        .collect();
+    assert_eq!(handled, vec![1, 0, 1]);
+
+    let err = clients[1].walk_ring("missing").await.unwrap_err();
+    assert!(err.to_string().contains("no next hop"), "{err}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_next_rings_rewires_every_ring_or_none() {
+    let ring = spin_up(RingOpts::default()).await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let client = RingClient::new(&addrs[0]);
+
+    let set = client
+        .set_next_rings(&[("default", &addrs[2]), ("backup", &addrs[1])])
+        .await
+        .unwrap();
+    assert_eq!(set, 2);
+    assert_eq!(
+        client.get_ring("default").await.unwrap().as_deref(),
+        Some(addrs[2].as_str())
+    );
+    assert_eq!(
+        client.get_ring("backup").await.unwrap().as_deref(),
+        Some(addrs[1].as_str())
+    );
+
+    // One bad address and neither ring moves.
+    let err = client
+        .set_next_rings(&[("default", &addrs[1]), ("spare", "no-port")])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("partial_failure"), "{err}");
+    assert!(err.to_string().contains("spare=no-port"), "{err}");
+    assert_eq!(
+        client.get_ring("default").await.unwrap().as_deref(),
+        Some(addrs[2].as_str())
+    );
+    assert_eq!(client.get_ring("spare").await.unwrap(), None);
+    shutdown(ring).await;
+}
@@ -0,0 +1,154 @@
+//! `--mux`: hop forwards share one multiplexed connection per neighbor,
+//! and a node serves each `FRAME` stream like a connection of its own.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::auth::AuthToken;
+use ouroboros_fs::client::ClientOpts;
+use ouroboros_fs::mux::{read_frame, write_frame};
+use ouroboros_fs::{FsyncMode, NodeOpts, RingClient, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn frames_carry_independent_streams() {
+    let ring = spin_up(RingOpts {
+        mux: true,
+        ..RingOpts::default()
+    })
+    .await;
+    let mut conn = BufReader::new(TcpStream::connect(ring.addr(0)).await.unwrap());
+    conn.get_mut().write_all(b"MUX\n").await.unwrap();
+    let mut ok = String::new();
+    conn.read_line(&mut ok).await.unwrap();
+    assert_eq!(ok, "OK\n");
+
+    write_frame(conn.get_mut(), 1, b"KV SET colour blue\n")
+        .await
+        .unwrap();
+    write_frame(conn.get_mut(), 2, b"NODE PING\n")
+        .await
+        .unwrap();
+    let mut replies = Vec::new();
+    for _ in 0..2 {
+        replies.push(read_frame(&mut conn).await.unwrap().unwrap());
+    }
+    replies.sort();
+    assert_eq!(replies, [(1, b"OK\n".to_vec()), (2, b"PONG\n".to_vec())]);
+
+    // Closing stream 1 ends only that stream.
+    write_frame(conn.get_mut(), 1, b"").await.unwrap();
+    write_frame(conn.get_mut(), 2, b"KV GET colour\n")
+        .await
+        .unwrap();
+    let (id, reply) = loop {
+        let (id, reply) = read_frame(&mut conn).await.unwrap().unwrap();
+        if !reply.is_empty() {
+            break (id, reply);
+        }
+    };
+    assert_eq!(id, 2);
+    assert!(String::from_utf8(reply).unwrap().contains("blue"));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mux_is_refused_without_the_flag() {
+    let ring = spin_up(RingOpts {
+        n: 1,
+        ..RingOpts::default()
+    })
+    .await;
+    let mut conn = BufReader::new(TcpStream::connect(ring.addr(0)).await.unwrap());
+    conn.get_mut().write_all(b"MUX\nNODE PING\n").await.unwrap();
+    let mut lines = Vec::new();
+    for _ in 0..2 {
+        let mut line = String::new();
+        conn.read_line(&mut line).await.unwrap();
+        lines.push(line);
+    }
+    assert_eq!(lines, ["ERR mux not enabled\n", "PONG\n"]);
+    shutdown(ring).await;
+}
+
+/// Each channel takes a `max_conns` slot, like a connection would. A
+/// lone node, so no pooled connection of its own holds a slot.
+#[tokio::test(flavor = "multi_thread")]
+async fn channels_count_against_max_conns() {
+    let tmp = TempDir::new().unwrap();
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        tmp.path().to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        2,
+        NodeOpts {
+            mux: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(node, listener));
+
+    // The connection holds one slot and channel 1 the other.
+    let mut conn = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    conn.get_mut().write_all(b"MUX\n").await.unwrap();
+    let mut ok = String::new();
+    conn.read_line(&mut ok).await.unwrap();
+    assert_eq!(ok, "OK\n");
+    write_frame(conn.get_mut(), 1, b"NODE PING\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_frame(&mut conn).await.unwrap().unwrap(),
+        (1, b"PONG\n".to_vec())
+    );
+
+    write_frame(conn.get_mut(), 2, b"NODE PING\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_frame(&mut conn).await.unwrap().unwrap(),
+        (2, b"ERR server busy\n".to_vec())
+    );
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walks_share_one_connection_per_neighbor() {
+    let token = AuthToken::from_bytes([5; 32]);
+    let ring = spin_up(RingOpts {
+        mux: true,
+        auth_token: token.clone(),
+        ..RingOpts::default()
+    })
+    .await;
+    // Every node starts walks, so every node forwards hops.
+    for i in 0..3 {
+        let client = RingClient::with_opts(
+            ring.addr(i).to_string(),
+            ClientOpts {
+                auth_token: token.clone(),
+                ..ClientOpts::default()
+            },
+        );
+        for _ in 0..3 {
+            assert_eq!(client.walk().await.unwrap().len(), 3);
+        }
+    }
+    for n in &ring.nodes {
+        assert_eq!(n.node.mux_pool.opened.load(Ordering::Relaxed), 1);
+        assert_eq!(n.node.pool.reused.load(Ordering::Relaxed), 0);
+    }
+    shutdown(ring).await;
+}
@@ -0,0 +1,133 @@
+//! `--rate-limit-rps`: commands past a connection's budget get
+//! `ERR rate limited` and are dropped; other connections are unaffected.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::mux::{read_frame, write_frame};
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_bytes(addr: SocketAddr, bytes: &[u8]) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(bytes).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    storage: &Path,
+    rate_limit_rps: u32,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    start_node_with(
+        storage,
+        NodeOpts {
+            rate_limit_rps,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+async fn start_node_with(
+    storage: &Path,
+    opts: NodeOpts,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        opts,
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn commands_past_the_budget_are_dropped() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node(tmp.path(), 2).await;
+
+    let resp = send_bytes(addr, b"NODE PING\nNODE PING\nNODE PING\nNODE PING\n").await;
+    assert_eq!(resp, "PONG\nPONG\nERR rate limited\nERR rate limited\n");
+
+    // A fresh connection has its own full bucket.
+    assert_eq!(send_bytes(addr, b"NODE PING\n").await, "PONG\n");
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_push_skips_its_body() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node(tmp.path(), 1).await;
+
+    // The second command is dropped with its 5-byte body, so the line after
+    // it parses as a command (and is itself rate limited).
+    let resp = send_bytes(addr, b"NODE PING\nFILE PUSH 5 a.txt\nhelloNODE PING\n").await;
+    assert_eq!(resp, "PONG\nERR rate limited\nERR rate limited\n");
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_disables_the_limit() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node(tmp.path(), 0).await;
+    let resp = send_bytes(addr, "NODE PING\n".repeat(50).as_bytes()).await;
+    assert_eq!(resp, "PONG\n".repeat(50));
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mux_channels_share_their_connection_budget() {
+    let tmp = TempDir::new().unwrap();
+    let (_, addr, task) = start_node_with(
+        tmp.path(),
+        NodeOpts {
+            rate_limit_rps: 3,
+            mux: true,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    // `MUX` takes one of the three tokens; three channels share the rest.
+    let mut conn = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    conn.get_mut().write_all(b"MUX\n").await.unwrap();
+    let mut ok = String::new();
+    conn.read_line(&mut ok).await.unwrap();
+    assert_eq!(ok, "OK\n");
+    for id in 1..=3 {
+        write_frame(conn.get_mut(), id, b"NODE PING\n")
+            .await
+            .unwrap();
+    }
+    let mut replies = Vec::new();
+    while replies.len() < 3 {
+        let (_, reply) = read_frame(&mut conn).await.unwrap().unwrap();
+        if !reply.is_empty() {
+            replies.push(String::from_utf8(reply).unwrap());
+        }
+    }
+    replies.sort();
+    assert_eq!(replies, ["ERR rate limited\n", "PONG\n", "PONG\n"]);
+    task.abort();
+}
@@ -0,0 +1,79 @@
+//! `--repair-ring`: a node whose NEXT stops answering reports it with
+//! `PROBE`, and the repair coordinator points the dead node's neighbours at
+//! each other.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, kill_node, shutdown, spin_up};
+use ouroboros_fs::repair::{Repair, RepairCoordinator};
+use ouroboros_fs::{ClientOpts, RingClient};
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dead_next_is_stitched_around() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut ring = spin_up(RingOpts {
+        repair_coordinator: Some(listener.local_addr().unwrap().to_string()),
+        ..RingOpts::default()
+    })
+    .await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let coordinator = RepairCoordinator::new(addrs.clone(), ClientOpts::default());
+    tokio::spawn(std::sync::Arc::clone(&coordinator).serve(listener));
+
+    kill_node(&mut ring, 1).await;
+    // Node 0 can't reach node 1, gives up after its retries, and reports it.
+    let start = RingClient::new(addrs[0].clone());
+    start.ring(2, "hi").await.unwrap();
+
+    // The coordinator sets node 0's NEXT, then node 2's PREV; wait for both.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while ring.nodes[0].node.get_next().await.as_deref() != Some(addrs[2].as_str())
+        || ring.nodes[2].node.get_prev().await.as_deref() != Some(addrs[0].as_str())
+    {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "node 0 was not rewired"
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        coordinator.ring().await,
+        vec![addrs[0].clone(), addrs[2].clone()]
+    );
+
+    // The repaired ring carries messages again.
+    let before = ring.nodes[2].node.ring_msgs_total.load(Ordering::Relaxed);
+    start.ring(1, "again").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        ring.nodes[2].node.ring_msgs_total.load(Ordering::Relaxed),
+        before + 1
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn live_or_unknown_nodes_are_left_alone() {
+    let ring = spin_up(RingOpts::default()).await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    let coordinator = RepairCoordinator::new(addrs.clone(), ClientOpts::default());
+
+    assert_eq!(
+        coordinator.repair(&addrs[1]).await.unwrap(),
+        Repair::StillAlive
+    );
+    assert_eq!(
+        coordinator.repair("127.0.0.1:1").await.unwrap(),
+        Repair::Unknown
+    );
+    assert_eq!(coordinator.ring().await, addrs);
+    assert_eq!(
+        ring.nodes[0].node.get_next().await.as_deref(),
+        Some(addrs[1].as_str())
+    );
+    shutdown(ring).await;
+}
@@ -0,0 +1,55 @@
+//! `repl`: a scripted session against a live ring.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::{RingClient, repl};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn session_draws_walks_completes_and_replays() {
+    let ring = spin_up(RingOpts {
+        n: 2,
+        ..RingOpts::default()
+    })
+    .await;
+    let [p0, p1] = [0, 1].map(|i| ring.addr(i).port());
+    let client = RingClient::new(ring.addr(0).to_string());
+
+    let input = "NODE PING\n\
+                 topology walk-fr\t\n\
+                 TOPOLOGY WALK-FRESH\n\
+                 FILE LIST\n\
+                 NODE BOGUS\n\
+                 !1\n\
+                 !9\n\
+                 history\n\
+                 quit\n\
+                 NODE PING\n";
+    let mut output = Vec::new();
+    repl::run(&client, input.as_bytes(), &mut output, false)
+        .await
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let prompt = format!("{}> ", ring.addr(0));
+    let replies: Vec<&str> = output.split(&prompt).skip(1).collect();
+
+    assert_eq!(replies[0], "PONG\n");
+    assert_eq!(replies[1], "TOPOLOGY WALK-FRESH\n");
+    assert!(
+        replies[2].ends_with(&format!(
+            "+-> {p0}\n|   |\n|   v\n|   {p1}\n|   |\n+---+\nOK\n"
+        )),
+        "{output}"
+    );
+    assert_eq!(replies[3], "error: FILE is not supported in the repl\n");
+    assert!(replies[4].starts_with("ERR "), "{output}");
+    assert_eq!(replies[5], "NODE PING\nPONG\n");
+    assert_eq!(replies[6], "error: no command 9 in history\n");
+    assert_eq!(
+        replies[7],
+        "   1  NODE PING\n   2  TOPOLOGY WALK-FRESH\n   3  FILE LIST\n   4  NODE BOGUS\n   5  NODE PING\n"
+    );
+    // Nothing after `quit` is read.
+    assert_eq!(replies.len(), 9, "{output}");
+    shutdown(ring).await;
+}
@@ -0,0 +1,116 @@
+//! `--replay-log`: a node logs the `RING FORWARD`s it handles, and
+//! replaying that log once a partition heals reaches only the nodes that
+//! missed a message.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use ouroboros_fs::replay::{parse_log, replay_plan};
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    storage: &Path,
+    replay_log: Option<PathBuf>,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            dedup_cache_size: 1000,
+            replay_log,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+/// Wait until `path` holds `n` lines; the writer task appends shortly
+/// after the forward is acked.
+async fn read_log(path: &Path, n: usize) -> String {
+    for _ in 0..50 {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        if text.lines().count() >= n {
+            return text;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("replay log never reached {n} lines");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_reaches_only_the_nodes_that_missed_a_message() {
+    let tmp = TempDir::new().unwrap();
+    let log = tmp.path().join("replay.jsonl");
+    let (_, a_addr, a_task) = start_node(tmp.path(), Some(log.clone())).await;
+    let (b, b_addr, b_task) = start_node(tmp.path(), None).await;
+
+    send_line(a_addr, &format!("NODE NEXT {b_addr}\n")).await;
+    assert_eq!(send_line(a_addr, "RING FORWARD 1 before\n").await, "OK\n");
+
+    // Partition: A's NEXT stops answering.
+    let dead = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    send_line(a_addr, &format!("NODE NEXT {dead}\n")).await;
+    assert_eq!(send_line(a_addr, "RING FORWARD 1 during\n").await, "OK\n");
+    // A message that ends at A is logged but has nothing to replay.
+    assert_eq!(send_line(a_addr, "RING FORWARD 0 local\n").await, "OK\n");
+
+    let text = read_log(&log, 3).await;
+    let entries = parse_log(&text).unwrap();
+    let logged: Vec<(u32, &str)> = entries.iter().map(|e| (e.ttl, e.msg.as_str())).collect();
+    assert_eq!(logged, [(1, "before"), (1, "during"), (0, "local")]);
+    assert!(entries.iter().all(|e| e.ring.is_none() && e.ts > 0));
+    // Forwards are acked on receipt; give B time to count "before".
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(b.ring_msgs_total.load(Ordering::Relaxed), 1);
+
+    // Heal, then replay to A's NEXT: B already has "before".
+    send_line(a_addr, &format!("NODE NEXT {b_addr}\n")).await;
+    let plan = replay_plan(&entries, 0);
+    assert_eq!(plan.len(), 2);
+    for entry in &plan {
+        assert_eq!(
+            send_line(b_addr, &format!("{}\n", entry.line())).await,
+            "OK\n"
+        );
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(b.ring_msgs_total.load(Ordering::Relaxed), 2);
+
+    for task in [a_task, b_task] {
+        task.abort();
+    }
+}
@@ -0,0 +1,139 @@
+//! Listener socket options: a node can rebind its port right after the
+//! previous listener is gone, `reuse_port` lets two live listeners
+//! share it, and the buffer sizes reach the connections it accepts.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, RingError, bind_with_opts};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+
+async fn bind_node(
+    addr: &str,
+    storage: &Path,
+    reuse_port: bool,
+) -> Result<(Arc<Node>, TcpListener), RingError> {
+    bind_node_with(
+        addr,
+        storage,
+        NodeOpts {
+            reuse_port,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+async fn bind_node_with(
+    addr: &str,
+    storage: &Path,
+    opts: NodeOpts,
+) -> Result<(Arc<Node>, TcpListener), RingError> {
+    let (node, listener, _) = bind_with_opts(
+        addr,
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        opts,
+    )
+    .await?;
+    Ok((node, listener))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restart_rebinds_despite_lingering_connections() {
+    let tmp = TempDir::new().unwrap();
+    let (_, listener) = bind_node("127.0.0.1:0", tmp.path(), false).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Leave a connection open across the "crash".
+    let client = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (server_side, _) = listener.accept().await.unwrap();
+    drop(server_side);
+    drop(listener);
+
+    let (_, listener) = bind_node(&addr.to_string(), tmp.path(), false)
+        .await
+        .expect("rebind after restart");
+    assert_eq!(listener.local_addr().unwrap(), addr);
+    drop(client);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn live_port_is_shared_only_with_reuse_port() {
+    let tmp = TempDir::new().unwrap();
+    let (_, first) = bind_node("127.0.0.1:0", tmp.path(), false).await.unwrap();
+    let addr = first.local_addr().unwrap().to_string();
+    match bind_node(&addr, tmp.path(), false).await {
+        Err(RingError::BindFailed { addr: a, source }) => {
+            assert_eq!(a, addr);
+            assert_eq!(source.kind(), std::io::ErrorKind::AddrInUse);
+        }
+        other => panic!("expected BindFailed, got {:?}", other.map(|_| ())),
+    }
+    drop(first);
+
+    #[cfg(unix)]
+    {
+        let (_, first) = bind_node("127.0.0.1:0", tmp.path(), true).await.unwrap();
+        let addr = first.local_addr().unwrap().to_string();
+        let (_, second) = bind_node(&addr, tmp.path(), true)
+            .await
+            .expect("second listener with SO_REUSEPORT");
+        assert_eq!(second.local_addr().unwrap().to_string(), addr);
+    }
+}
+
+/// `SO_RCVBUF` or `SO_SNDBUF` of `sock`, as the kernel reports it.
+#[cfg(unix)]
+fn buf_size(sock: &impl std::os::fd::AsRawFd, opt: libc::c_int) -> usize {
+    let mut val: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `val` and `len` are valid for writes and sized for an int.
+    let rc = unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            libc::SOL_SOCKET,
+            opt,
+            (&mut val as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    assert_eq!(rc, 0, "getsockopt: {}", std::io::Error::last_os_error());
+    val as usize
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn buffer_sizes_reach_accepted_connections() {
+    let tmp = TempDir::new().unwrap();
+    let (_, listener) = bind_node_with(
+        "127.0.0.1:0",
+        tmp.path(),
+        NodeOpts {
+            bind_backlog: 16,
+            recv_buf_size: 8192,
+            send_buf_size: 16384,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let _client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+
+    // Linux doubles the value it was given to leave room for bookkeeping.
+    let rcv = buf_size(&accepted, libc::SO_RCVBUF);
+    let snd = buf_size(&accepted, libc::SO_SNDBUF);
+    assert!((8192..=2 * 8192).contains(&rcv), "SO_RCVBUF {rcv}");
+    assert!((16384..=2 * 16384).contains(&snd), "SO_SNDBUF {snd}");
+}
@@ -0,0 +1,36 @@
+//! `RING ALL`: a `RING FORWARD` whose TTL comes from the ring size the
+//! node last counted, or `--default-ring-ttl` before any count.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::node::DEFAULT_RING_TTL;
+
+async fn handled(ring: &common::Ring) -> u64 {
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    ring.nodes
+        .iter()
+        .map(|h| h.node.ring_msgs_total.swap(0, Ordering::Relaxed))
+        .sum()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_all_reaches_each_node_once_after_a_walk() {
+    let ring = spin_up(RingOpts::default()).await;
+    // `spin_up` walked from node 0, so node 1 has not counted the ring.
+    let client = RingClient::new(ring.addr(1).to_string());
+
+    // Unknown size: the default TTL, so the message laps the ring.
+    client.ring_all("early").await.unwrap();
+    assert_eq!(handled(&ring).await, u64::from(DEFAULT_RING_TTL) + 1);
+
+    client.walk().await.unwrap();
+    assert_eq!(ring.nodes[1].node.ring_size.load(Ordering::Relaxed), 3);
+    client.ring_all("counted").await.unwrap();
+    assert_eq!(handled(&ring).await, 3);
+    shutdown(ring).await;
+}
@@ -0,0 +1,139 @@
+//! End-to-end checks on 3-, 5- and 10-node rings started in-process on
+//! OS-assigned ports: walks, TTL-bounded forwards, and how a node answers
+//! commands it can't carry out.
+
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{Ring, RingOpts, shutdown, spin_up};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const SIZES: [usize; 3] = [3, 5, 10];
+
+async fn ring_of(n: usize) -> Ring {
+    spin_up(RingOpts {
+        n,
+        ..RingOpts::default()
+    })
+    .await
+}
+
+/// Send `line`, half-close, and read the reply to EOF.
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no reply to {line:?} from {addr}"))
+}
+
+fn ring_counts(ring: &Ring) -> Vec<u64> {
+    ring.nodes
+        .iter()
+        .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_returns_every_edge() {
+    for n in SIZES {
+        let ring = ring_of(n).await;
+        let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await;
+        let edges: Vec<&str> = resp.lines().filter(|l| l.contains("->")).collect();
+        let expected: Vec<String> = (0..n)
+            .map(|i| {
+                let (from, to) = (ring.addr(i).port(), ring.addr((i + 1) % n).port());
+                format!("{from}->{to}")
+            })
+            .collect();
+        assert_eq!(edges, expected, "n={n} resp: {resp:?}");
+        assert!(resp.trim_end().ends_with("OK"), "n={n} resp: {resp:?}");
+        shutdown(ring).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_with_ttl_n_reaches_every_node() {
+    for n in SIZES {
+        let ring = ring_of(n).await;
+        let before = ring_counts(&ring);
+        let resp = send_line(ring.addr(0), &format!("RING FORWARD {n} lap\n")).await;
+        assert_eq!(resp, "OK\n", "n={n}");
+
+        // Hops are acked on receipt; wait for the last one to land. TTL n
+        // is n hops past node 0, which brings the message back to it.
+        let mut expected = vec![1; n];
+        expected[0] = 2;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let seen: Vec<u64> = ring_counts(&ring)
+                .iter()
+                .zip(&before)
+                .map(|(after, before)| after - before)
+                .collect();
+            if seen == expected {
+                break;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "n={n}: per-node counts {seen:?}, want {expected:?}"
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        shutdown(ring).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_commands_get_err() {
+    for n in SIZES {
+        let ring = ring_of(n).await;
+        for line in ["BOGUS\n", "RING FORWARD notanumber hi\n", "TOPOLOGY\n"] {
+            let resp = send_line(ring.addr(n - 1), line).await;
+            assert!(resp.starts_with("ERR"), "n={n} {line:?}: {resp:?}");
+        }
+        // The node still serves afterwards.
+        let resp = send_line(ring.addr(n - 1), "NODE PING\n").await;
+        assert_eq!(resp.trim_end(), "PONG", "n={n}");
+        shutdown(ring).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn next_to_a_missing_node_fails_gracefully() {
+    for n in SIZES {
+        let ring = ring_of(n).await;
+        let resp = send_line(ring.addr(0), "NODE NEXT not an address\n").await;
+        assert!(resp.starts_with("ERR"), "n={n} resp: {resp:?}");
+
+        // A well-formed address nobody listens on is stored; the walk
+        // that then can't leave node 0 ends in ERR instead of hanging.
+        let dead = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let resp = send_line(ring.addr(0), &format!("NODE NEXT {dead}\n")).await;
+        assert_eq!(resp, format!("OK next={dead}\n"), "n={n}");
+        let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await;
+        assert!(
+            resp.lines().any(|l| l.starts_with("ERR")),
+            "n={n} resp: {resp:?}"
+        );
+        assert_eq!(
+            send_line(ring.addr(0), "NODE PING\n").await.trim_end(),
+            "PONG",
+            "n={n}"
+        );
+        shutdown(ring).await;
+    }
+}
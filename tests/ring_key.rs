@@ -0,0 +1,68 @@
+//! `RING KEY` with `--routing consistent-hash`: whichever node is asked,
+//! the message lands on the key's owner on the hash ring of the heartbeat
+//! members, and the client gets `KEY_ACK <key>`.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::chring::{ConsistentHashRing, RoutingMode};
+use ouroboros_fs::heartbeat::HeartbeatCoordinator;
+use ouroboros_fs::{ClientOpts, RingClient, RingError};
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keys_reach_their_owner_from_any_node() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let coordinator_addr = listener.local_addr().unwrap().to_string();
+    let coordinator = HeartbeatCoordinator::new(Duration::from_secs(1), ClientOpts::default());
+    tokio::spawn(Arc::clone(&coordinator).serve(listener));
+
+    let ring = spin_up(RingOpts {
+        heartbeat_addr: Some(coordinator_addr),
+        heartbeat_interval: Duration::from_millis(100),
+        routing: RoutingMode::ConsistentHash,
+        ..RingOpts::default()
+    })
+    .await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    while coordinator.members().await.len() < 3 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Every node refreshes its ring after the heartbeat that follows.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let hash_ring = ConsistentHashRing::new(addrs.clone());
+    let delivered = |i: usize| ring.nodes[i].node.ring_msgs_total.load(Ordering::Relaxed);
+    for k in 0..12 {
+        let key = format!("user:{k}");
+        let owner = hash_ring.owner(&key).unwrap();
+        let owner_idx = addrs.iter().position(|a| a == owner).unwrap();
+        for asked in &addrs {
+            let before = delivered(owner_idx);
+            RingClient::new(asked.clone())
+                .ring_key(&key, "hello there")
+                .await
+                .unwrap();
+            assert_eq!(delivered(owner_idx), before + 1, "{key} via {asked}");
+        }
+    }
+    let total: u64 = (0..3).map(delivered).sum();
+    assert_eq!(total, 36);
+
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sequential_routing_refuses_ring_key() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    match client.ring_key("k", "v").await {
+        Err(RingError::Remote { msg, .. }) => assert!(msg.contains("consistent-hash"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+    shutdown(ring).await;
+}
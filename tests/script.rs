@@ -0,0 +1,43 @@
+//! Command scripts: each line goes to the node in turn, and the first
+//! failure stops the run unless told to carry on.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::script;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripts_stop_at_the_first_error_unless_told_otherwise() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    let commands = script::parse(
+        "# set two keys, with a typo between them\nKV SET color red\nKV BOGUS\n\nKV SET shape round\n",
+    );
+
+    let mut replies = Vec::new();
+    let err = script::run(&client, &commands, false, |step, reply| {
+        replies.push((step.line, reply.is_ok()))
+    })
+    .await
+    .unwrap_err();
+    assert!(err.to_string().starts_with("line 3: KV BOGUS:"), "{err}");
+    assert_eq!(replies, [(2, true), (3, false)]);
+    assert_eq!(ring.nodes[0].node.kv_get("shape").await, None);
+
+    let err = script::run(&client, &commands, true, |_, _| {})
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "1 of 3 script commands failed");
+    assert_eq!(
+        ring.nodes[0].node.kv_get("shape").await.as_deref(),
+        Some("round")
+    );
+
+    // Commands whose replies never close are refused, not sent.
+    let err = script::run(&client, &script::parse("FILE LIST"), false, |_, _| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not supported"), "{err}");
+    shutdown(ring).await;
+}
@@ -7,6 +7,7 @@ mod common;
 use std::time::Duration;
 
 use common::{Ring, RingOpts, push_bytes, shutdown, spin_up};
+use ouroboros_fs::node::NodeRole;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
@@ -63,6 +64,131 @@ async fn node_status_self_loop_when_n_eq_one() {
     shutdown(ring).await;
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn node_status_reports_prev() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "NODE STATUS\n").await.unwrap();
+    let port2 = ring.addr(2).port();
+    assert!(
+        resp.contains(&format!("PREV 127.0.0.1:{port2}")),
+        "resp: {resp:?}"
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_prev_sets_pointer_and_acks() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "NODE PREV 127.0.0.1:9999\n")
+        .await
+        .unwrap();
+    assert_eq!(resp.trim_end(), "OK prev=127.0.0.1:9999");
+    assert_eq!(
+        ring.nodes[0].node.get_prev().await.as_deref(),
+        Some("127.0.0.1:9999")
+    );
+    shutdown(ring).await;
+}
+
+fn parse_stat(resp: &str) -> std::collections::HashMap<String, u64> {
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+    // Every counter is numeric; the vector clock is not a counter.
+    resp.lines()
+        .filter_map(|l| l.split_once(' '))
+        .filter(|(k, _)| *k != "VECTOR_CLOCK")
+        .map(|(k, v)| (k.to_string(), v.parse().expect("numeric stat")))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_stat_reports_all_counters() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "NODE STAT\n").await.unwrap();
+    let stats = parse_stat(&resp);
+    for key in [
+        "UPTIME_SECS",
+        "MSGS_RECEIVED",
+        "MSGS_FORWARDED",
+        "WALK_COUNT",
+        "WALK_CACHE_HITS",
+        "RING_COUNT",
+        "ERRORS",
+        "CONNECTIONS",
+        "RING_SIZE",
+    ] {
+        assert!(stats.contains_key(key), "missing {key}: {resp:?}");
+    }
+    assert!(resp.ends_with("VECTOR_CLOCK -\nOK\n"), "{resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_cache_serves_repeats_until_next_changes() {
+    let ring = spin_up(RingOpts {
+        walk_cache_ttl: Duration::from_secs(60),
+        ..RingOpts::default()
+    })
+    .await;
+    let edges = |resp: &str| -> Vec<String> {
+        resp.lines()
+            .filter(|l| l.contains("->"))
+            .map(str::to_string)
+            .collect()
+    };
+
+    let fresh = send_line(ring.addr(0), "TOPOLOGY WALK-FRESH\n")
+        .await
+        .unwrap();
+    assert!(fresh.starts_with("TOKEN "), "{fresh:?}");
+    let hits =
+        parse_stat(&send_line(ring.addr(0), "NODE STAT\n").await.unwrap())["WALK_CACHE_HITS"];
+
+    let cached = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    assert!(cached.starts_with("CACHED "), "{cached:?}");
+    assert!(cached.ends_with("OK\n"), "{cached:?}");
+    assert_eq!(edges(&cached), edges(&fresh));
+    let stats = parse_stat(&send_line(ring.addr(0), "NODE STAT\n").await.unwrap());
+    assert_eq!(stats["WALK_CACHE_HITS"], hits + 1);
+
+    // WALK-FRESH never reads the cache.
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK-FRESH\n")
+        .await
+        .unwrap();
+    assert!(resp.starts_with("TOKEN "), "{resp:?}");
+
+    // Re-setting NEXT, even to the same node, drops the cached result.
+    let next = ring.addr(1).to_string();
+    send_line(ring.addr(0), &format!("NODE NEXT {next}\n"))
+        .await
+        .unwrap();
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    assert!(resp.starts_with("TOKEN "), "{resp:?}");
+    assert_eq!(edges(&resp), edges(&fresh));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_stat_counts_walks_rings_and_errors() {
+    let ring = spin_up(RingOpts::default()).await;
+    let before = parse_stat(&send_line(ring.addr(0), "NODE STAT\n").await.unwrap());
+
+    send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    send_line(ring.addr(0), "RING FORWARD 0 hi\n")
+        .await
+        .unwrap();
+    send_line(ring.addr(0), "BOGUS\n").await.unwrap();
+
+    let after = parse_stat(&send_line(ring.addr(0), "NODE STAT\n").await.unwrap());
+    assert_eq!(after["WALK_COUNT"], before["WALK_COUNT"] + 1);
+    assert_eq!(after["RING_COUNT"], before["RING_COUNT"] + 1);
+    assert_eq!(after["ERRORS"], before["ERRORS"] + 1);
+    // The walk forwards one hop out of node 0; the four requests plus the
+    // closing TOPOLOGY DONE are all received here.
+    assert_eq!(after["MSGS_FORWARDED"], before["MSGS_FORWARDED"] + 1);
+    assert_eq!(after["MSGS_RECEIVED"], before["MSGS_RECEIVED"] + 5);
+    shutdown(ring).await;
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn node_ping_returns_pong() {
     let ring = spin_up(RingOpts::default()).await;
@@ -131,8 +257,456 @@ async fn ring_forward_ttl_one_acks() {
     shutdown(ring).await;
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_forward_fans_out_to_every_next_hop() {
+    use std::sync::atomic::Ordering;
+    let ring = spin_up(RingOpts::default()).await;
+    let handled = |i: usize| ring.nodes[i].node.ring_msgs_total.load(Ordering::Relaxed);
+
+    let line = format!("NODE NEXT-MULTI {} {}\n", ring.addr(1), ring.addr(2));
+    let resp = send_line(ring.addr(0), &line).await.unwrap();
+    assert_eq!(resp, format!("OK next={},{}\n", ring.addr(1), ring.addr(2)));
+    let resp = send_line(ring.addr(0), "NODE STATUS\n").await.unwrap();
+    assert!(
+        resp.contains(&format!("NEXT {}\n", ring.addr(1))),
+        "resp: {resp:?}"
+    );
+    assert!(resp.contains("FAN_OUT 2\n"), "resp: {resp:?}");
+
+    // TTL 1: both branches get the message and neither passes it on.
+    let resp = send_line(ring.addr(0), "RING FORWARD 1 branches\n")
+        .await
+        .unwrap();
+    assert_eq!(resp, "OK\n");
+    for _ in 0..50 {
+        if handled(1) == 1 && handled(2) == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!([handled(0), handled(1), handled(2)], [1, 1, 1]);
+
+    // A plain NODE NEXT ends the fan-out.
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT {}\n", ring.addr(1)))
+        .await
+        .unwrap();
+    assert!(resp.starts_with("OK"), "resp: {resp:?}");
+    let resp = send_line(ring.addr(0), "NODE STATUS\n").await.unwrap();
+    assert!(resp.contains("FAN_OUT 1\n"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_next_to_the_current_next_is_unchanged() {
+    let ring = spin_up(RingOpts::default()).await;
+    let next = ring.addr(1);
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT {next}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("OK next={next} (unchanged)\n"));
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT {}\n", ring.addr(2)))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("OK next={}\n", ring.addr(2)));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_next_if_swaps_only_from_the_expected_next() {
+    let ring = spin_up(RingOpts::default()).await;
+    let (a1, a2) = (ring.addr(1), ring.addr(2));
+
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT-IF {a2} {a1}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("CAS_FAIL current={a1}\n"));
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT-IF {a1} {a2}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("OK next={a2}\n"));
+    assert_eq!(ring.nodes[0].node.get_next().await, Some(a2.to_string()));
+    // The loser of a race sees the winner's NEXT.
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT-IF {a1} {a1}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("CAS_FAIL current={a2}\n"));
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT-IF {a2} {a2}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, format!("OK next={a2} (unchanged)\n"));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_timed_circulates_until_it_expires() {
+    use ouroboros_fs::protocol::now_unix_ms;
+    use std::sync::atomic::Ordering;
+    let ring = spin_up(RingOpts::default()).await;
+    let handled = || {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+            .collect::<Vec<_>>()
+    };
+
+    let resp = send_line(ring.addr(0), "RING TIMED 1 stale\n")
+        .await
+        .unwrap();
+    assert_eq!(resp, "OK EXPIRED\n");
+    assert_eq!(handled(), [0, 0, 0]);
+
+    let line = format!("RING TIMED {} fresh\n", now_unix_ms() + 300);
+    let resp = send_line(ring.addr(0), &line).await.unwrap();
+    assert_eq!(resp, "OK\n");
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    let after_expiry = handled();
+    assert!(after_expiry.iter().all(|&n| n > 0), "{after_expiry:?}");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(handled(), after_expiry, "still circulating after expiry");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_ack_full_lap_reports_every_hop() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "RING ACK 3 hello ring\n")
+        .await
+        .unwrap();
+    let seen: Vec<&str> = resp.lines().take_while(|l| *l != "OK").collect();
+    let expected: Vec<String> = [0, 1, 2, 0].map(|i| ring.addr(i).to_string()).into();
+    assert_eq!(seen, expected, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_ack_stops_where_ttl_runs_out() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(1), "RING ACK 1 hi\n").await.unwrap();
+    let addrs = [1, 2].map(|i| ring.addr(i).to_string());
+    assert_eq!(resp, format!("{}\n{}\nOK\n", addrs[0], addrs[1]));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_ack_ttl_zero_reports_only_self() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "RING ACK 0 hi\n").await.unwrap();
+    assert_eq!(resp, format!("{}\nOK\n", ring.addr(0)));
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_echo_returns_the_message_after_a_lap() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "RING ECHO 3 hello ring\n")
+        .await
+        .unwrap();
+    assert_eq!(resp, "ECHO hello ring\nOK\n");
+
+    // TTL runs out mid-ring: the echo comes back from there.
+    let resp = send_line(ring.addr(1), "RING ECHO 1 hi\n").await.unwrap();
+    assert_eq!(resp, "ECHO hi\nOK\n");
+
+    let resp = send_line(ring.addr(2), "RING ECHO 0 hi\n").await.unwrap();
+    assert_eq!(resp, "ECHO hi\nOK\n");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_once_visits_every_node_once_without_a_ttl() {
+    use std::sync::atomic::Ordering;
+    let ring = spin_up(RingOpts {
+        n: 5,
+        ..RingOpts::default()
+    })
+    .await;
+    let counts = |ring: &Ring| -> Vec<u64> {
+        ring.nodes
+            .iter()
+            .map(|h| h.node.ring_msgs_total.load(Ordering::Relaxed))
+            .collect()
+    };
+    let before = counts(&ring);
+    let resp = send_line(ring.addr(2), "RING ONCE hello ring\n")
+        .await
+        .unwrap();
+    assert_eq!(resp, "OK DONE\n");
+    // Each hop counts the message before passing it on, so the lap is
+    // fully counted by the time the start node answers.
+    let seen: Vec<u64> = counts(&ring)
+        .iter()
+        .zip(&before)
+        .map(|(a, b)| a - b)
+        .collect();
+    assert_eq!(seen, vec![1; 5]);
+    shutdown(ring).await;
+
+    let ring = spin_up(RingOpts {
+        n: 1,
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(0), "RING ONCE alone\n").await.unwrap();
+    assert_eq!(resp, "OK DONE\n");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_collect_gathers_kv_values_until_the_lap_or_ttl_ends() {
+    let ring = spin_up(RingOpts::default()).await;
+    for (i, color) in [(0, "red"), (2, "dark blue")] {
+        ring.nodes[i]
+            .node
+            .kv_set("color".into(), color.into())
+            .await
+            .unwrap();
+    }
+    let addrs = [0, 1, 2].map(|i| ring.addr(i).to_string());
+
+    // A large TTL still stops short of wrapping back to the start node.
+    let resp = send_line(ring.addr(0), "RING COLLECT 10 color\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        resp,
+        format!(
+            "COLLECT_RESULT\n{}=red\n{}=-\n{}=dark blue\nOK\n",
+            addrs[0], addrs[1], addrs[2]
+        )
+    );
+
+    let resp = send_line(ring.addr(1), "RING COLLECT 1 color\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        resp,
+        format!(
+            "COLLECT_RESULT\n{}=-\n{}=dark blue\nOK\n",
+            addrs[1], addrs[2]
+        )
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_req_hooks_reply_to_the_callback() {
+    use tokio::io::AsyncBufReadExt;
+
+    let ring = spin_up(RingOpts::default()).await;
+    for i in [1, 2] {
+        let port = ring.addr(i).port();
+        ring.nodes[i]
+            .node
+            .set_ring_req_hook(std::sync::Arc::new(move |msg| {
+                Some(format!("{port} saw {msg}"))
+            }))
+            .await;
+    }
+    let callback = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let cb = callback.local_addr().unwrap();
+
+    let resp = send_line(ring.addr(0), &format!("RING REQ {cb} 2 hello ring\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, "OK\n");
+
+    // Node 0 has no hook; nodes 1 and 2 answer.
+    let mut replies = Vec::new();
+    for _ in 0..2 {
+        let (s, _) = tokio::time::timeout(Duration::from_secs(5), callback.accept())
+            .await
+            .expect("no RING RESP")
+            .unwrap();
+        let mut line = String::new();
+        tokio::io::BufReader::new(s)
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        replies.push(line);
+    }
+    replies.sort();
+    let mut expected = [1, 2].map(|i| {
+        let port = ring.addr(i).port();
+        format!("RING RESP {cb} {port} saw hello ring\n")
+    });
+    expected.sort();
+    assert_eq!(replies, expected);
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn elect_picks_the_highest_address_and_tells_every_node() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "NODE STATUS\n").await.unwrap();
+    assert!(!resp.contains("LEADER"), "resp: {resp:?}");
+
+    let leader = [0, 1, 2]
+        .map(|i| ring.addr(i).to_string())
+        .into_iter()
+        .max()
+        .unwrap();
+    let resp = send_line(ring.addr(1), "ELECT START\n").await.unwrap();
+    assert_eq!(resp, format!("LEADER {leader}\nOK\n"));
+
+    // The announcement travels on after the reply; give it a moment.
+    for i in 0..3 {
+        let mut resp = String::new();
+        for _ in 0..50 {
+            resp = send_line(ring.addr(i), "NODE STATUS\n").await.unwrap();
+            if resp.contains("\nLEADER ") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            resp.contains(&format!("LEADER {leader}\nOK\n")),
+            "node {i}: {resp:?}"
+        );
+    }
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_the_leader_runs_leader_cmd() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "ELECT CMD NODE PING\n")
+        .await
+        .unwrap();
+    assert_eq!(resp, "ERR not leader, leader=<unset>\n");
+
+    let resp = send_line(ring.addr(0), "ELECT START\n").await.unwrap();
+    let leader = resp
+        .strip_prefix("LEADER ")
+        .and_then(|r| r.strip_suffix("\nOK\n"))
+        .unwrap()
+        .to_string();
+    let leader_i = (0..3)
+        .find(|&i| ring.addr(i).to_string() == leader)
+        .unwrap();
+    let follower_i = (leader_i + 1) % 3;
+    // The announcement travels on after the reply; give it a moment.
+    for _ in 0..50 {
+        if ring.nodes[leader_i].node.role().await == NodeRole::Leader
+            && ring.nodes[follower_i].node.role().await == NodeRole::Follower
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(ring.nodes[leader_i].node.role().await, NodeRole::Leader);
+    let resp = send_line(ring.addr(leader_i), "NODE STATUS\n")
+        .await
+        .unwrap();
+    assert!(resp.contains("\nROLE LEADER\n"), "resp: {resp:?}");
+
+    assert_eq!(
+        send_line(ring.addr(follower_i), "ELECT CMD NODE PING\n")
+            .await
+            .unwrap(),
+        format!("ERR not leader, leader={leader}\n")
+    );
+    assert_eq!(
+        send_line(ring.addr(leader_i), "ELECT CMD NODE PING\n")
+            .await
+            .unwrap(),
+        "PONG\n"
+    );
+    assert_eq!(
+        send_line(ring.addr(leader_i), "ELECT CMD KV SET mode rw\n")
+            .await
+            .unwrap(),
+        "OK\n"
+    );
+    assert_eq!(
+        ring.nodes[leader_i].node.kv_get("mode").await.as_deref(),
+        Some("rw")
+    );
+    assert!(
+        send_line(ring.addr(leader_i), "ELECT CMD BOGUS\n")
+            .await
+            .unwrap()
+            .starts_with("ERR ")
+    );
+    shutdown(ring).await;
+}
+
 // ---------- TOPOLOGY ----------
 
+fn walk_edges(resp: &str) -> usize {
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+    resp.lines().filter(|l| l.contains("->")).count()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_into_a_loop_that_skips_the_start_ends_early() {
+    let ring = spin_up(RingOpts::default()).await;
+    // 0 -> 1 -> 2 -> 1: the walk from 0 never comes back to 0.
+    let resp = send_line(ring.addr(2), &format!("NODE NEXT {}\n", ring.addr(1)))
+        .await
+        .unwrap();
+    assert!(resp.starts_with("OK"), "resp: {resp:?}");
+
+    let resp = tokio::time::timeout(
+        Duration::from_secs(10),
+        send_line(ring.addr(0), "TOPOLOGY WALK\n"),
+    )
+    .await
+    .expect("walk circled until its timeout")
+    .unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    let edges: Vec<&str> = resp.lines().filter(|l| l.contains("->")).collect();
+    assert_eq!(
+        edges,
+        vec![
+            format!("{p0}->{p1}"),
+            format!("{p1}->{p2}"),
+            format!("{p2}->{p1}"),
+        ],
+        "resp: {resp:?}"
+    );
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pooled_walks_reuse_hop_connections() {
+    use std::sync::atomic::Ordering;
+    let ring = spin_up(RingOpts {
+        pool_size: 4,
+        ..RingOpts::default()
+    })
+    .await;
+    let before = ring.nodes[0].node.pool.reused.load(Ordering::Relaxed);
+    for _ in 0..3 {
+        let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+        assert_eq!(walk_edges(&resp), 3);
+    }
+    let after = ring.nodes[0].node.pool.reused.load(Ordering::Relaxed);
+    assert!(after >= before + 3, "before={before} after={after}");
+    let next = ring.addr(1).to_string();
+    assert_eq!(ring.nodes[0].node.pool.idle_count(&next).await, 1);
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pooled_connection_closed_by_peer_is_replaced() {
+    // The peer's idle timeout closes every pooled connection between the
+    // two walks; the second walk must notice and reconnect.
+    let ring = spin_up(RingOpts {
+        pool_size: 4,
+        idle_timeout: Duration::from_millis(300),
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    assert_eq!(walk_edges(&resp), 3);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    assert_eq!(walk_edges(&resp), 3);
+    shutdown(ring).await;
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn topology_set_directly_populates_map() {
     let ring = spin_up(RingOpts::default()).await;
@@ -176,6 +750,368 @@ async fn topology_walk_end_to_end_returns_history() {
     // 3 nodes → 3 edges (closing the ring).
     let edges = resp.lines().filter(|l| l.contains("->")).count();
     assert_eq!(edges, 3, "edge count mismatch: {resp:?}");
+    // The walk token is echoed ahead of the edges.
+    assert!(resp.starts_with("TOKEN "), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_abort_cancels_stuck_walk() {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    let ring = spin_up(RingOpts::default()).await;
+
+    // Point node 0 at a peer that accepts hops and never answers, so the
+    // walk would otherwise sit out its 30 s timeout.
+    let sink = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let sink_addr = sink.local_addr().unwrap();
+    let sink_task = tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((s, _)) = sink.accept().await {
+            held.push(s);
+        }
+    });
+    let resp = send_line(ring.addr(0), &format!("NODE NEXT {sink_addr}\n"))
+        .await
+        .unwrap();
+    assert!(resp.starts_with("OK"), "resp: {resp:?}");
+
+    let mut walker = TcpStream::connect(ring.addr(0)).await.unwrap();
+    walker.write_all(b"TOPOLOGY WALK\n").await.unwrap();
+    let mut walker = BufReader::new(walker);
+    let mut first = String::new();
+    walker.read_line(&mut first).await.unwrap();
+    let token = first
+        .trim_end()
+        .strip_prefix("TOKEN ")
+        .unwrap_or_else(|| panic!("no token line: {first:?}"))
+        .to_string();
+
+    let resp = send_line(ring.addr(0), &format!("TOPOLOGY ABORT {token}\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp, "OK\n");
+
+    let mut rest = String::new();
+    tokio::time::timeout(Duration::from_secs(5), walker.read_line(&mut rest))
+        .await
+        .expect("walk was not aborted before its timeout")
+        .unwrap();
+    assert_eq!(rest, "ERR walk aborted\n");
+
+    // The token is gone: a second abort has nothing to cancel.
+    let resp = send_line(ring.addr(0), &format!("TOPOLOGY ABORT {token}\n"))
+        .await
+        .unwrap();
+    assert!(resp.starts_with("ERR unknown walk token"), "resp: {resp:?}");
+
+    sink_task.abort();
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_reverse_walk_follows_prev_pointers() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY REVERSE-WALK\n")
+        .await
+        .unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    let edges: Vec<&str> = resp.lines().filter(|l| l.contains("->")).collect();
+    assert_eq!(
+        edges,
+        vec![
+            format!("{p0}->{p2}"),
+            format!("{p2}->{p1}"),
+            format!("{p1}->{p0}"),
+        ],
+        "resp: {resp:?}"
+    );
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_bidir_walk_merges_both_directions() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY BIDIR-WALK\n")
+        .await
+        .unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    assert!(resp.starts_with("graph ring {\n"), "resp: {resp:?}");
+    assert!(resp.contains("label=\"symmetric\";"), "resp: {resp:?}");
+    let edges: Vec<&str> = resp.lines().filter(|l| l.contains(" -- ")).collect();
+    assert_eq!(
+        edges,
+        vec![
+            format!("    \"{p0}\" -- \"{p1}\";"),
+            format!("    \"{p1}\" -- \"{p2}\";"),
+            format!("    \"{p2}\" -- \"{p0}\";"),
+        ],
+        "resp: {resp:?}"
+    );
+    assert!(resp.ends_with("}\nOK\n"), "resp: {resp:?}");
+
+    // Node 2's PREV now skips node 1: the reverse walk misses it.
+    let resp = send_line(ring.addr(2), &format!("NODE PREV {}\n", ring.addr(0)))
+        .await
+        .unwrap();
+    assert!(resp.starts_with("OK"), "resp: {resp:?}");
+    let resp = send_line(ring.addr(0), "TOPOLOGY BIDIR-WALK\n")
+        .await
+        .unwrap();
+    assert!(resp.contains("label=\"asymmetric\";"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_pay_collects_a_value_from_every_node() {
+    let ring = spin_up(RingOpts {
+        named: true,
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY PAY-WALK NAME\n")
+        .await
+        .unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    assert!(resp.starts_with("TOKEN "), "resp: {resp:?}");
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 3, "resp: {resp:?}");
+    let pays: Vec<&str> = resp.lines().filter(|l| l.starts_with("PAY ")).collect();
+    assert_eq!(
+        pays,
+        vec![
+            format!("PAY {p0}=node-00"),
+            format!("PAY {p1}=node-01"),
+            format!("PAY {p2}=node-02"),
+        ],
+        "resp: {resp:?}"
+    );
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+
+    // Counters are read at each hop: only the start node counts walks.
+    let resp = send_line(ring.addr(0), "TOPOLOGY PAY-WALK walk_count\n")
+        .await
+        .unwrap();
+    assert!(!resp.contains(&format!("PAY {p0}=0\n")), "resp: {resp:?}");
+    assert!(resp.contains(&format!("PAY {p1}=0\n")), "resp: {resp:?}");
+
+    // No built-in value and no --walk-payload-cmd.
+    let resp = send_line(ring.addr(0), "TOPOLOGY PAY-WALK queue_depth\n")
+        .await
+        .unwrap();
+    assert!(resp.contains(&format!("PAY {p2}=-\n")), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gather_collects_a_builtin_value_from_every_node() {
+    let ring = spin_up(RingOpts::default()).await;
+    let [a0, a1, a2] = [0, 1, 2].map(|i| ring.addr(i));
+    let resp = send_line(a0, "GATHER next\n").await.unwrap();
+    assert_eq!(resp, format!("{a0}={a1}\n{a1}={a2}\n{a2}={a0}\nOK\n"));
+
+    let resp = send_line(a1, "GATHER PORT\n").await.unwrap();
+    assert_eq!(resp, format!("{a1}={a1}\n{a2}={a2}\n{a0}={a0}\nOK\n"));
+
+    let resp = send_line(a0, "GATHER queue_depth\n").await.unwrap();
+    assert!(resp.starts_with("ERR unknown GATHER key"), "resp: {resp:?}");
+    let resp = send_line(a0, "GATHER KEYS\n").await.unwrap();
+    assert_eq!(resp, "port\nnext\nuptime\nmsg_count\nOK\n");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_status_reports_name() {
+    let ring = spin_up(RingOpts {
+        named: true,
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(1), "NODE STATUS\n").await.unwrap();
+    assert!(resp.contains("NAME node-01\n"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unnamed_node_status_reports_unset_name() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(0), "NODE STATUS\n").await.unwrap();
+    assert!(resp.contains("NAME <unset>\n"), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_walk_labels_named_nodes() {
+    let ring = spin_up(RingOpts {
+        named: true,
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK\n").await.unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    let edges: Vec<&str> = resp.lines().filter(|l| l.contains("->")).collect();
+    assert_eq!(
+        edges,
+        vec![
+            format!("node-00@{p0}->node-01@{p1}"),
+            format!("node-01@{p1}->node-02@{p2}"),
+            format!("node-02@{p2}->node-00@{p0}"),
+        ],
+        "resp: {resp:?}"
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_max_stops_after_max_hops() {
+    let ring = spin_up(RingOpts {
+        n: 5,
+        ..RingOpts::default()
+    })
+    .await;
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK-MAX 3\n")
+        .await
+        .unwrap();
+    let p: Vec<u16> = (0..4).map(|i| ring.addr(i).port()).collect();
+    let lines: Vec<&str> = resp.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "WALK_TRUNCATED".to_string(),
+            format!("{}->{}", p[0], p[1]),
+            format!("{}->{}", p[1], p[2]),
+            format!("{}->{}", p[2], p[3]),
+            "OK".to_string(),
+        ],
+        "resp: {resp:?}"
+    );
+    // A cut-short walk leaves the full topology alone.
+    assert_eq!(ring.nodes[0].node.topology_map.read().await.len(), 5);
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_max_covering_the_ring_is_complete() {
+    let ring = spin_up(RingOpts::default()).await;
+    let resp = send_line(ring.addr(1), "TOPOLOGY WALK-MAX 3\n")
+        .await
+        .unwrap();
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    assert!(resp.starts_with("TOKEN "), "resp: {resp:?}");
+    assert!(!resp.contains("WALK_TRUNCATED"), "resp: {resp:?}");
+    let edges: Vec<&str> = resp.lines().filter(|l| l.contains("->")).collect();
+    assert_eq!(
+        edges,
+        vec![
+            format!("{p1}->{p2}"),
+            format!("{p2}->{p0}"),
+            format!("{p0}->{p1}"),
+        ],
+        "resp: {resp:?}"
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_sample_records_all_or_nothing_at_the_extremes() {
+    let ring = spin_up(RingOpts::default()).await;
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK-SAMPLE 1\n")
+        .await
+        .unwrap();
+    let lines: Vec<&str> = resp.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "SAMPLED total_hops=3 recorded=3".to_string(),
+            format!("{p0}->{p1}"),
+            format!("{p1}->{p2}"),
+            format!("{p2}->{p0}"),
+            "OK".to_string(),
+        ],
+        "resp: {resp:?}"
+    );
+
+    // Skipped edges collapse into one placeholder.
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK-SAMPLE 0\n")
+        .await
+        .unwrap();
+    let lines: Vec<&str> = resp.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        ["SAMPLED total_hops=3 recorded=0", "...", "OK"],
+        "resp: {resp:?}"
+    );
+
+    let resp = send_line(ring.addr(0), "TOPOLOGY WALK-SAMPLE 2\n")
+        .await
+        .unwrap();
+    assert!(resp.starts_with("ERR "), "resp: {resp:?}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_meta_annotates_each_endpoint_with_its_kv_value() {
+    let ring = spin_up(RingOpts::default()).await;
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    for (i, color) in [(0, "red"), (2, "dark blue")] {
+        ring.nodes[i]
+            .node
+            .kv_set("color".into(), color.into())
+            .await
+            .unwrap();
+    }
+
+    let resp = send_line(ring.addr(1), "TOPOLOGY WALK-META color\n")
+        .await
+        .unwrap();
+    let lines: Vec<&str> = resp.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("{p1}:?->{p2}:dark%20blue"),
+            format!("{p2}:dark%20blue->{p0}:red"),
+            format!("{p0}:red->{p1}:?"),
+            "OK".to_string(),
+        ],
+        "resp: {resp:?}"
+    );
+
+    // The annotated walk is not taken as the topology.
+    let resp = send_line(ring.addr(1), "TOPOLOGY WALK\n").await.unwrap();
+    assert!(
+        resp.lines().skip(1).all(|l| !l.contains(':')),
+        "resp: {resp:?}"
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_reverse_walk_without_prev_errs() {
+    let ring = spin_up(RingOpts::default()).await;
+    *ring.nodes[0].node.prev_port.write().await = None;
+    let resp = send_line(ring.addr(0), "TOPOLOGY REVERSE-WALK\n")
+        .await
+        .unwrap();
+    assert_eq!(resp.trim_end(), "ERR no prev hop set");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn topology_bidir_walk_names_the_missing_hop() {
+    let ring = spin_up(RingOpts::default()).await;
+    *ring.nodes[0].node.prev_port.write().await = None;
+    let resp = send_line(ring.addr(0), "TOPOLOGY BIDIR-WALK\n")
+        .await
+        .unwrap();
+    assert_eq!(resp.trim_end(), "ERR no prev hop set");
+
+    ring.nodes[1].node.next_ports.write().await.clear();
+    let resp = send_line(ring.addr(1), "TOPOLOGY BIDIR-WALK\n")
+        .await
+        .unwrap();
+    assert_eq!(resp.trim_end(), "ERR no next hop set");
     shutdown(ring).await;
 }
 
@@ -8,12 +8,13 @@
 mod common;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 use ouroboros_fs::{AuthToken, FsyncMode, Node, bind, serve_with_shutdown};
 use tempfile::TempDir;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
 
 /// Basic contract: firing the shutdown signal causes serve_with_shutdown
 /// to return. Without an in-flight handler this should be near-instant.
@@ -132,3 +133,118 @@ async fn shutdown_lets_quick_handler_finish() {
     let result = tokio::time::timeout(Duration::from_secs(2), serve_task).await;
     assert!(result.is_ok());
 }
+
+/// `NODE SHUTDOWN` acks, stops the accept loop, and closes connections
+/// idling between commands, so the drain finishes well before its
+/// deadline without the shutdown channel ever firing.
+#[tokio::test(flavor = "multi_thread")]
+async fn node_shutdown_command_drains_and_returns() {
+    let tmp = TempDir::new().unwrap();
+    let (node, listener, addr) = bind(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        tmp.path().to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+    )
+    .await
+    .unwrap();
+
+    let (_tx, rx) = tokio::sync::oneshot::channel();
+    let serve_task = tokio::spawn(async move {
+        serve_with_shutdown(node, listener, rx, Duration::from_secs(10)).await;
+    });
+
+    let mut idle = TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(b"NODE SHUTDOWN\n").await.unwrap();
+    let mut buf = String::new();
+    s.read_to_string(&mut buf).await.unwrap();
+    assert_eq!(buf, "OK SHUTDOWN\n");
+
+    let result = tokio::time::timeout(Duration::from_secs(2), serve_task).await;
+    assert!(result.is_ok(), "serve_with_shutdown didn't return");
+
+    let mut rest = Vec::new();
+    let n = tokio::time::timeout(Duration::from_secs(1), idle.read_to_end(&mut rest))
+        .await
+        .expect("idle connection left open")
+        .unwrap();
+    assert_eq!(n, 0);
+    assert!(
+        TcpStream::connect(addr).await.is_err(),
+        "listener still open"
+    );
+}
+
+/// `NODE SHUTDOWN` ends the gossip loop along with the accept loop: once
+/// `serve_with_shutdown` returns, the node's NEXT gets no more pings.
+#[tokio::test(flavor = "multi_thread")]
+async fn node_shutdown_stops_gossip() {
+    let tmp = TempDir::new().unwrap();
+    let (node, listener, addr) = bind(
+        "127.0.0.1:0",
+        Duration::from_millis(20),
+        1 << 20,
+        tmp.path().to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+    )
+    .await
+    .unwrap();
+
+    // A stand-in NEXT that answers every gossip PING and counts them.
+    let next = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    node.set_next(next.local_addr().unwrap().to_string()).await;
+    let pings = Arc::new(AtomicUsize::new(0));
+    let next_task = tokio::spawn({
+        let pings = Arc::clone(&pings);
+        async move {
+            loop {
+                let (mut s, _) = next.accept().await.unwrap();
+                let mut line = [0u8; 10];
+                if s.read_exact(&mut line).await.is_ok() {
+                    pings.fetch_add(1, Ordering::SeqCst);
+                    let _ = s.write_all(b"PONG\n").await;
+                }
+            }
+        }
+    });
+
+    let (_tx, rx) = tokio::sync::oneshot::channel();
+    let serve_task = tokio::spawn(async move {
+        serve_with_shutdown(node, listener, rx, Duration::from_secs(10)).await;
+    });
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+    while pings.load(Ordering::SeqCst) == 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "gossip never pinged"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(b"NODE SHUTDOWN\n").await.unwrap();
+    let mut buf = String::new();
+    s.read_to_string(&mut buf).await.unwrap();
+    assert_eq!(buf, "OK SHUTDOWN\n");
+    tokio::time::timeout(Duration::from_secs(2), serve_task)
+        .await
+        .expect("serve_with_shutdown didn't return")
+        .unwrap();
+
+    let seen = pings.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(pings.load(Ordering::SeqCst), seen, "gossip still running");
+    next_task.abort();
+}
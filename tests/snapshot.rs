@@ -0,0 +1,117 @@
+//! `SNAPSHOT` / `RESTORE`: one node's state taken as JSON and applied to
+//! another, and snapshots `RESTORE` refuses without touching the node.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::snapshot::NodeSnapshot;
+use ouroboros_fs::{RingClient, RingError};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restore_carries_one_nodes_state_to_another() {
+    let ring = spin_up(RingOpts::default()).await;
+    let a = RingClient::new(ring.addr(0).to_string());
+    let b = RingClient::new(ring.addr(1).to_string());
+
+    a.raw("KV SET color dark blue").await.unwrap();
+    assert_eq!(a.incr("hits").await.unwrap(), 1);
+    let taken = a.snapshot().await.unwrap();
+    assert_eq!(taken.addr, ring.addr(0).to_string());
+    assert_eq!(taken.kv.get("color").map(String::as_str), Some("dark blue"));
+    assert_eq!(taken.counters.get("hits"), Some(&1));
+    assert!(taken.next.is_some());
+
+    let before = b.snapshot().await.unwrap();
+    b.restore(&taken).await.unwrap();
+    let after = b.snapshot().await.unwrap();
+    assert_eq!(after.next, taken.next);
+    assert_eq!(after.prev, taken.prev);
+    assert_eq!(after.rings, taken.rings);
+    assert_eq!(after.kv, taken.kv);
+    assert_eq!(after.counters, taken.counters);
+    assert_eq!(after.leader, taken.leader);
+    assert_eq!(after.role, taken.role);
+    // What describes the process itself stays B's.
+    assert_eq!(after.addr, before.addr);
+    assert_eq!(after.generation, before.generation);
+
+    // Put B back so the ring shuts down as it was built.
+    b.restore(&before).await.unwrap();
+    assert_eq!(b.snapshot().await.unwrap().next, before.next);
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_restores_leave_the_node_alone() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    client.raw("KV SET color red").await.unwrap();
+    let before = client.snapshot().await.unwrap();
+
+    let mut partial = serde_json::to_value(&before).unwrap();
+    partial.as_object_mut().unwrap().remove("kv");
+    for line in [
+        "RESTORE".to_string(),
+        "RESTORE {\"version\":1".to_string(),
+        format!("RESTORE {partial}"),
+    ] {
+        match client.raw(&line).await {
+            Err(RingError::Remote { msg, .. }) => {
+                assert!(msg.contains("invalid snapshot"), "{line}: {msg}")
+            }
+            other => panic!("{line}: expected ERR, got {other:?}"),
+        }
+    }
+    let newer = NodeSnapshot {
+        version: before.version + 1,
+        kv: Default::default(),
+        ..before.clone()
+    };
+    assert!(client.restore(&newer).await.is_err());
+
+    let after = client.snapshot().await.unwrap();
+    assert_eq!(after.kv, before.kv);
+    assert_eq!(after.next, before.next);
+    shutdown(ring).await;
+}
+
+/// `RESTORE` makes the checks `NODE NEXT` and `KV SET` do, on every entry,
+/// and one bad entry refuses the whole snapshot.
+#[tokio::test(flavor = "multi_thread")]
+async fn restore_checks_pointers_and_kv_entries() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    client.raw("KV SET color red").await.unwrap();
+    let before = client.snapshot().await.unwrap();
+
+    let mut long_key = before.clone();
+    long_key.kv.insert("k".repeat(300), "v".into());
+    let mut bad_next = before.clone();
+    bad_next.next = Some("999.999.999.999:7000".into());
+    bad_next.next_multi.clear();
+    let mut bad_prev = before.clone();
+    bad_prev.prev = Some("nowhere".into());
+    let mut bad_ring = before.clone();
+    bad_ring.rings.insert("backup".into(), "127.0.0.1:0".into());
+    for (snapshot, want) in [
+        (long_key, "key too long"),
+        (bad_next, "next: invalid address"),
+        (bad_prev, "prev: invalid address"),
+        (bad_ring, "ring backup: invalid address"),
+    ] {
+        match client.restore(&snapshot).await {
+            Err(RingError::Remote { msg, .. }) => {
+                assert!(msg.contains("invalid snapshot"), "{msg}");
+                assert!(msg.contains(want), "{want}: {msg}");
+            }
+            other => panic!("{want}: expected ERR, got {other:?}"),
+        }
+    }
+
+    let after = client.snapshot().await.unwrap();
+    assert_eq!(after.kv, before.kv);
+    assert_eq!(after.next, before.next);
+    assert_eq!(after.prev, before.prev);
+    assert_eq!(after.rings, before.rings);
+    shutdown(ring).await;
+}
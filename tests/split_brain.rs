@@ -0,0 +1,63 @@
+//! `SPLIT-BRAIN CHECK`: the walk collects every node's LEADER and the
+//! start node reports a split when they name more than one.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::client::ClientOpts;
+use ouroboros_fs::diagnose::diagnose;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn two_leaders_are_reported() {
+    let ring = spin_up(RingOpts::default()).await;
+    let seed = ring.addr(0).to_string();
+    let client = RingClient::new(seed.clone());
+    assert_eq!(
+        client.raw("SPLIT-BRAIN CHECK").await.unwrap(),
+        ["OK leader=<unset>"]
+    );
+
+    let elected = client.raw("ELECT START").await.unwrap();
+    let leader = elected[0].strip_prefix("LEADER ").unwrap().to_string();
+    assert_eq!(
+        client.split_brain_check().await.unwrap(),
+        vec![leader.clone()]
+    );
+
+    // A second round that only reached one node.
+    let rival = (0..3)
+        .map(|i| ring.addr(i).to_string())
+        .find(|addr| *addr != leader)
+        .unwrap();
+    ring.nodes[1].node.set_leader(rival.clone()).await;
+    let mut both = vec![leader, rival];
+    both.sort();
+    assert_eq!(client.split_brain_check().await.unwrap(), both);
+    assert_eq!(
+        client.raw("SPLIT-BRAIN CHECK").await.unwrap(),
+        [format!("SPLIT_BRAIN detected leaders={}", both.join(","))]
+    );
+    let diagnosis = diagnose(&seed, &ClientOpts::default()).await;
+    assert!(
+        diagnosis
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("split brain: nodes name 2 leaders")),
+        "{}",
+        diagnosis.to_text()
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_loop_short_of_the_start_still_ends() {
+    let ring = spin_up(RingOpts::default()).await;
+    RingClient::new(ring.addr(2).to_string())
+        .set_next(&ring.addr(1).to_string())
+        .await
+        .unwrap();
+    let client = RingClient::new(ring.addr(0).to_string());
+    assert!(client.split_brain_check().await.unwrap().is_empty());
+    shutdown(ring).await;
+}
@@ -0,0 +1,174 @@
+//! `--state-file`: a node killed and restarted on the same address with
+//! the same state file rejoins the ring without being re-wired, and keeps
+//! its `KV SET` entries. Each restart bumps its generation, which
+//! `NODE NEXT-GEN` checks.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+async fn send_line(addr: SocketAddr, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(
+    addr: &str,
+    storage: &Path,
+    state_file: &Path,
+) -> (Arc<Node>, SocketAddr, JoinHandle<()>) {
+    let (node, listener, addr) = bind_with_opts(
+        addr,
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            state_file: Some(state_file.to_path_buf()),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, addr, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restarted_node_rejoins_ring_from_state_file() {
+    let tmp = TempDir::new().unwrap();
+    let state = |i: usize| tmp.path().join(format!("state-{i}.json"));
+
+    let mut addrs = Vec::new();
+    let mut tasks = Vec::new();
+    for i in 0..3 {
+        let (_, addr, task) = start_node("127.0.0.1:0", tmp.path(), &state(i)).await;
+        addrs.push(addr);
+        tasks.push(task);
+    }
+    for i in 0..3 {
+        let next = addrs[(i + 1) % 3];
+        let prev = addrs[(i + 2) % 3];
+        assert!(
+            send_line(addrs[i], &format!("NODE NEXT {next}\n"))
+                .await
+                .starts_with("OK")
+        );
+        assert!(
+            send_line(addrs[i], &format!("NODE PREV {prev}\n"))
+                .await
+                .starts_with("OK")
+        );
+    }
+
+    // Kill node 1 and bring it back on the same address, without rewiring.
+    tasks[1].abort();
+    let _ = (&mut tasks[1]).await;
+    let (node, _, task) = start_node(&addrs[1].to_string(), tmp.path(), &state(1)).await;
+    tasks[1] = task;
+    assert_eq!(node.get_next().await, Some(addrs[2].to_string()));
+    assert_eq!(node.get_prev().await, Some(addrs[0].to_string()));
+
+    let resp = send_line(addrs[0], "TOPOLOGY WALK\n").await;
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 3, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+
+    for t in tasks {
+        t.abort();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_without_state_file_starts_unwired() {
+    let tmp = TempDir::new().unwrap();
+    let (node, _, task) =
+        start_node("127.0.0.1:0", tmp.path(), &tmp.path().join("absent.json")).await;
+    assert_eq!(node.get_next().await, None);
+    assert_eq!(node.get_prev().await, None);
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn kv_entries_survive_restart() {
+    let tmp = TempDir::new().unwrap();
+    let state = tmp.path().join("state.json");
+    let (_, addr, task) = start_node("127.0.0.1:0", tmp.path(), &state).await;
+
+    assert_eq!(send_line(addr, "KV SET color dark blue\n").await, "OK\n");
+    assert_eq!(
+        send_line(addr, "KV GET color\n").await,
+        "VALUE color dark blue\nOK\n"
+    );
+    assert_eq!(
+        send_line(addr, "KV GET missing\n").await,
+        "VALUE missing <nil>\nOK\n"
+    );
+    let long = "k".repeat(300);
+    let resp = send_line(addr, &format!("KV SET {long} v\n")).await;
+    assert!(resp.starts_with("ERR key too long"), "resp: {resp:?}");
+
+    task.abort();
+    let _ = task.await;
+    let (node, addr, task) = start_node(&addr.to_string(), tmp.path(), &state).await;
+    assert_eq!(node.kv_get("color").await.as_deref(), Some("dark blue"));
+    assert_eq!(
+        send_line(addr, "KV GET color\n").await,
+        "VALUE color dark blue\nOK\n"
+    );
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn next_gen_refuses_a_restarted_target() {
+    let tmp = TempDir::new().unwrap();
+    let (_, a_addr, a_task) =
+        start_node("127.0.0.1:0", tmp.path(), &tmp.path().join("a.json")).await;
+    let b_state = tmp.path().join("b.json");
+    let (b, b_addr, b_task) = start_node("127.0.0.1:0", tmp.path(), &b_state).await;
+    assert_eq!(b.generation(), 1);
+    assert!(
+        send_line(b_addr, "NODE STATUS\n")
+            .await
+            .contains("GENERATION 1\n")
+    );
+    assert_eq!(
+        send_line(a_addr, &format!("NODE NEXT-GEN {b_addr} 1\n")).await,
+        format!("OK next={b_addr}\n")
+    );
+
+    b_task.abort();
+    let _ = b_task.await;
+    let (b, _, b_task) = start_node(&b_addr.to_string(), tmp.path(), &b_state).await;
+    assert_eq!(b.generation(), 2);
+    assert_eq!(
+        send_line(a_addr, &format!("NODE NEXT-GEN {b_addr} 1\n")).await,
+        format!("ERR stale generation: {b_addr} is 2, not 1\n")
+    );
+    assert_eq!(
+        send_line(a_addr, &format!("NODE NEXT-GEN {b_addr} 2\n")).await,
+        format!("OK next={b_addr}\n")
+    );
+
+    a_task.abort();
+    b_task.abort();
+}
@@ -0,0 +1,118 @@
+//! TLS transport: a two-node ring whose listeners and internal hops all
+//! run over rustls, using the fixture CA under `tests/fixtures/tls/`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, NodeOpts, TlsConfig, bind_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+fn node_tls() -> TlsConfig {
+    TlsConfig::from_pem_files(
+        Some(&fixture("node.pem")),
+        Some(&fixture("node.key")),
+        Some(&fixture("ca.pem")),
+    )
+    .expect("fixture certs load")
+}
+
+fn client_tls() -> TlsConfig {
+    TlsConfig::from_pem_files(None, None, Some(&fixture("ca.pem"))).expect("fixture CA loads")
+}
+
+async fn tls_send_line(addr: &str, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = client_tls().connect(addr).await.expect("TLS connect");
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("TLS request timed out")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn two_node_tls_ring_walk_completes() {
+    let tmp = TempDir::new().unwrap();
+    let mut addrs = Vec::new();
+    let mut tasks = Vec::new();
+    for i in 0..2 {
+        let (node, listener, addr) = bind_with_opts(
+            "127.0.0.1:0",
+            Duration::ZERO,
+            1 << 20,
+            tmp.path().join(format!("tls-{i}")),
+            false,
+            FsyncMode::None,
+            AuthToken::disabled(),
+            Duration::ZERO,
+            0,
+            NodeOpts {
+                tls: node_tls(),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("bind");
+        addrs.push(addr.to_string());
+        tasks.push(tokio::spawn(serve(node, listener)));
+    }
+
+    for (from, to) in [(0, 1), (1, 0)] {
+        let resp = tls_send_line(&addrs[from], &format!("NODE NEXT {}\n", addrs[to])).await;
+        assert!(resp.starts_with("OK next="), "resp: {resp:?}");
+    }
+
+    let resp = tls_send_line(&addrs[0], "TOPOLOGY WALK\n").await;
+    let edges = resp.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(edges, 2, "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+
+    for t in tasks {
+        t.abort();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plaintext_client_cannot_talk_to_tls_node() {
+    let tmp = TempDir::new().unwrap();
+    let (node, listener, addr) = bind_with_opts(
+        "127.0.0.1:0",
+        Duration::ZERO,
+        1 << 20,
+        tmp.path().to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts {
+            tls: node_tls(),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(node, listener));
+
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(b"NODE PING\n").await.unwrap();
+    let mut buf = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(5), s.read_to_end(&mut buf)).await;
+    assert!(
+        !String::from_utf8_lossy(&buf).contains("PONG"),
+        "plaintext request was served: {buf:?}"
+    );
+
+    task.abort();
+}
@@ -0,0 +1,183 @@
+//! `--otlp-endpoint`: a walk or ring message that crosses several nodes
+//! shows up at the collector as one trace, each hop's span a child of the
+//! previous node's.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::trace::OtlpEndpoint;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+#[derive(Debug)]
+struct Span {
+    node: String,
+    name: String,
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+}
+
+/// A stand-in OTLP/HTTP collector: answers every POST with 200 and sends
+/// the spans it carried down the returned channel.
+async fn collector() -> (OtlpEndpoint, mpsc::UnboundedReceiver<Span>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut req = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let body = loop {
+                    let n = s.read(&mut chunk).await.unwrap();
+                    assert!(n > 0, "request ended early");
+                    req.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&req);
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    assert!(head.starts_with("POST /v1/traces "), "{head}");
+                    let len: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= len {
+                        break body.to_string();
+                    }
+                };
+                s.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                    .await
+                    .unwrap();
+                let req: Value = serde_json::from_str(&body).unwrap();
+                for rs in req["resourceSpans"].as_array().unwrap() {
+                    let node = rs["resource"]["attributes"][1]["value"]["stringValue"]
+                        .as_str()
+                        .unwrap()
+                        .to_string();
+                    for span in rs["scopeSpans"][0]["spans"].as_array().unwrap() {
+                        let field = |k: &str| span[k].as_str().unwrap().to_string();
+                        let _ = tx.send(Span {
+                            node: node.clone(),
+                            name: field("name"),
+                            trace_id: field("traceId"),
+                            span_id: field("spanId"),
+                            parent_span_id: field("parentSpanId"),
+                        });
+                    }
+                }
+            });
+        }
+    });
+    (OtlpEndpoint::parse(&url).unwrap(), rx)
+}
+
+/// Wait for `n` spans; the nodes post every second.
+async fn collect(rx: &mut mpsc::UnboundedReceiver<Span>, n: usize) -> Vec<Span> {
+    let mut spans = Vec::new();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while spans.len() < n {
+            spans.push(rx.recv().await.unwrap());
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("got {} of {n} spans: {spans:?}", spans.len()));
+    spans
+}
+
+/// Group `spans` by trace, each ordered root first and followed by its
+/// child, asserting every trace forms one chain.
+fn traces(mut spans: Vec<Span>) -> Vec<Vec<Span>> {
+    let mut out = Vec::new();
+    while let Some(first) = spans.first() {
+        let trace_id = first.trace_id.clone();
+        let (mut trace, rest): (Vec<_>, Vec<_>) =
+            spans.into_iter().partition(|s| s.trace_id == trace_id);
+        spans = rest;
+        let mut ordered = Vec::new();
+        let mut parent = String::new();
+        while !trace.is_empty() {
+            let i = trace
+                .iter()
+                .position(|s| s.parent_span_id == parent)
+                .unwrap_or_else(|| panic!("no child of '{parent}' in {trace:?}"));
+            let span = trace.remove(i);
+            parent = span.span_id.clone();
+            ordered.push(span);
+        }
+        out.push(ordered);
+    }
+    out
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_is_one_trace_across_the_ring() {
+    let (endpoint, mut rx) = collector().await;
+    let ring = spin_up(RingOpts {
+        otlp_endpoint: Some(endpoint),
+        ..RingOpts::default()
+    })
+    .await;
+
+    RingClient::new(ring.addr(0).to_string())
+        .walk()
+        .await
+        .unwrap();
+
+    // `spin_up` walks the ring once too; both walks look the same.
+    let walks = traces(collect(&mut rx, 6).await);
+    assert_eq!(walks.len(), 2);
+    let addrs: Vec<_> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    for spans in walks {
+        let got: Vec<_> = spans
+            .iter()
+            .map(|s| (s.node.as_str(), s.name.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (addrs[0].as_str(), "TOPOLOGY WALK"),
+                (addrs[1].as_str(), "TOPOLOGY HOP"),
+                (addrs[2].as_str(), "TOPOLOGY HOP"),
+            ]
+        );
+    }
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_forward_hops_join_the_start_nodes_trace() {
+    let (endpoint, mut rx) = collector().await;
+    let ring = spin_up(RingOpts {
+        otlp_endpoint: Some(endpoint),
+        ..RingOpts::default()
+    })
+    .await;
+
+    RingClient::new(ring.addr(0).to_string())
+        .ring(2, "traced hello")
+        .await
+        .unwrap();
+
+    // Alongside the trace of `spin_up`'s walk.
+    let spans = traces(collect(&mut rx, 6).await)
+        .into_iter()
+        .find(|t| t[0].name == "RING FORWARD")
+        .expect("no RING FORWARD trace");
+    let nodes: Vec<_> = spans.iter().map(|s| s.node.clone()).collect();
+    assert_eq!(
+        nodes,
+        (0..3).map(|i| ring.addr(i).to_string()).collect::<Vec<_>>()
+    );
+    assert!(spans.iter().all(|s| s.name == "RING FORWARD"));
+    shutdown(ring).await;
+}
@@ -0,0 +1,130 @@
+//! Unix domain socket transport: co-located nodes bound with
+//! `bind_unix_with_opts` and wired with `unix:<path>` addresses.
+#![cfg(unix)]
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ouroboros_fs::{AuthToken, FsyncMode, Node, NodeOpts, bind_unix_with_opts, serve};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+
+async fn send_line(path: &Path, line: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut s = UnixStream::connect(path).await.unwrap();
+        s.write_all(line.as_bytes()).await.unwrap();
+        s.shutdown().await.ok();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).await.unwrap();
+        resp
+    })
+    .await
+    .expect("request timed out")
+}
+
+async fn start_node(path: &Path, storage: &Path) -> (Arc<Node>, JoinHandle<()>) {
+    let (node, listener) = bind_unix_with_opts(
+        path,
+        Duration::ZERO,
+        1 << 20,
+        storage.to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts::default(),
+    )
+    .await
+    .expect("bind");
+    let task = tokio::spawn(serve(Arc::clone(&node), listener));
+    (node, task)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn two_node_ring_walks_over_unix_sockets() {
+    let tmp = TempDir::new().unwrap();
+    let storage = tmp.path().join("nodes");
+    let paths = [tmp.path().join("n0.sock"), tmp.path().join("n1.sock")];
+    let addr = |i: usize| format!("unix:{}", paths[i].display());
+
+    let mut tasks = Vec::new();
+    for path in &paths {
+        let (node, task) = start_node(path, &storage).await;
+        assert_eq!(node.port, format!("unix:{}", path.display()));
+        tasks.push(task);
+    }
+    for (i, path) in paths.iter().enumerate() {
+        let other = addr(1 - i);
+        assert!(
+            send_line(path, &format!("NODE NEXT {other}\n"))
+                .await
+                .starts_with("OK")
+        );
+        assert!(
+            send_line(path, &format!("NODE PREV {other}\n"))
+                .await
+                .starts_with("OK")
+        );
+    }
+
+    let resp = send_line(&paths[0], "TOPOLOGY WALK\n").await;
+    assert!(resp.contains("n0.sock->n1.sock"), "resp: {resp:?}");
+    assert!(resp.contains("n1.sock->n0.sock"), "resp: {resp:?}");
+    assert!(resp.trim_end().ends_with("OK"), "resp: {resp:?}");
+
+    // Storage is keyed by the socket's file name.
+    assert!(storage.join("n0.sock").join("content").is_dir());
+    for t in tasks {
+        t.abort();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rebinding_replaces_stale_socket_file() {
+    let tmp = TempDir::new().unwrap();
+    let storage = tmp.path().join("nodes");
+    let path = tmp.path().join("n0.sock");
+    let (_, task) = start_node(&path, &storage).await;
+    task.abort();
+    let _ = task.await;
+
+    // The socket file outlives the aborted listener; a restart must not
+    // fail with "address in use".
+    assert!(path.exists());
+    let (_, task) = start_node(&path, &storage).await;
+    let resp = send_line(&path, "NODE STATUS\n").await;
+    assert!(
+        resp.starts_with(&format!("PORT unix:{}", path.display())),
+        "resp: {resp:?}"
+    );
+    task.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_to_replace_a_regular_file() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("not-a-socket");
+    std::fs::write(&path, b"keep me").unwrap();
+    let err = bind_unix_with_opts(
+        &path,
+        Duration::ZERO,
+        1 << 20,
+        tmp.path().to_path_buf(),
+        false,
+        FsyncMode::None,
+        AuthToken::disabled(),
+        Duration::ZERO,
+        0,
+        NodeOpts::default(),
+    )
+    .await;
+    let Err(err) = err else {
+        panic!("bind over a regular file must fail");
+    };
+    assert!(err.to_string().contains("not a socket"), "{err}");
+    assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+}
@@ -0,0 +1,97 @@
+//! `VC:` headers: a vector clock sent ahead of a `RING` message is merged
+//! and bumped by every node it passes, and reported by `NODE STAT`.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::vclock::VectorClock;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn send(addr: std::net::SocketAddr, lines: &str) -> String {
+    let mut s = TcpStream::connect(addr).await.unwrap();
+    s.write_all(lines.as_bytes()).await.unwrap();
+    s.shutdown().await.ok();
+    let mut resp = String::new();
+    tokio::time::timeout(Duration::from_secs(5), s.read_to_string(&mut resp))
+        .await
+        .expect("no reply")
+        .unwrap();
+    resp
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_messages_carry_and_bump_the_clock() {
+    let ring = spin_up(RingOpts::default()).await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    assert_eq!(
+        send(ring.addr(0), "VC: client:1\nRING FORWARD 2 hi\n").await,
+        "OK\n"
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Each node has seen the client's event and every node before it.
+    let mut expected = VectorClock::default();
+    expected.0.insert("client".into(), 1);
+    for (i, h) in ring.nodes.iter().enumerate() {
+        expected.0.insert(addrs[i].clone(), 1);
+        assert_eq!(h.node.vector_clock(), expected, "node {i}");
+    }
+    let stat = send(ring.addr(2), "NODE STAT\n").await;
+    assert!(
+        stat.contains(&format!("VECTOR_CLOCK {}\n", expected.to_wire())),
+        "{stat}"
+    );
+
+    // Commands outside the RING family leave the clock alone.
+    assert_eq!(
+        send(ring.addr(1), "VC: client:9\nNODE PING\n").await,
+        "PONG\n"
+    );
+    assert_eq!(ring.nodes[1].node.vector_clock().0["client"], 1);
+
+    let resp = send(ring.addr(0), "VC: client\nNODE PING\n").await;
+    assert!(resp.starts_with("ERR invalid VC entry"), "{resp}");
+    assert!(resp.ends_with("PONG\n"), "{resp}");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwarded_line_leads_with_the_bumped_clock() {
+    let ring = spin_up(RingOpts {
+        n: 1,
+        ..RingOpts::default()
+    })
+    .await;
+    let addr = ring.addr(0).to_string();
+    let next = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    ring.nodes[0]
+        .node
+        .set_next(next.local_addr().unwrap().to_string())
+        .await;
+    assert_eq!(
+        send(ring.addr(0), "VC: client:4\nRING FORWARD 1 hi\n").await,
+        "OK\n"
+    );
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(5), next.accept())
+        .await
+        .expect("no forward")
+        .unwrap();
+    let mut stream = BufReader::new(stream);
+    let (mut header, mut line) = (String::new(), String::new());
+    stream.read_line(&mut header).await.unwrap();
+    stream.read_line(&mut line).await.unwrap();
+    assert_eq!(header, format!("VC: {addr}:1,client:4\n"));
+    assert_eq!(line, "RING FORWARD-HOP 1 0 hi\n");
+
+    // A clock concurrent with the last one is still merged.
+    let merged = ring.nodes[0].node.observe_clock(VectorClock(
+        [("other".to_string(), 1)].into_iter().collect(),
+    ));
+    assert_eq!(merged.0[&addr], 2);
+    assert_eq!(merged.0["client"], 4);
+    assert_eq!(merged.0["other"], 1);
+    shutdown(ring).await;
+}
@@ -0,0 +1,71 @@
+//! `TOPOLOGY WALK-FIND`: the walk stops at the first node holding the
+//! key/value pair and reports the edges it took to get there.
+
+mod common;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_stops_at_the_first_match() {
+    let ring = spin_up(RingOpts::default()).await;
+    let [p0, p1, p2] = [0, 1, 2].map(|i| ring.addr(i).port());
+    for i in [1, 2] {
+        ring.nodes[i]
+            .node
+            .kv_set("role".into(), "primary db".into())
+            .await
+            .unwrap();
+    }
+    let client = RingClient::new(ring.addr(0).to_string());
+
+    let (addr, edges) = client
+        .walk_find("role", "primary db")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(addr, ring.nodes[1].node.port);
+    assert_eq!(edges, [(p0.to_string(), p1.to_string())]);
+
+    // From node 2 the match is the start node itself.
+    let (addr, edges) = RingClient::new(ring.addr(2).to_string())
+        .walk_find("role", "primary db")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(addr, ring.nodes[2].node.port);
+    assert!(edges.is_empty());
+
+    // Node 2 is reached after two edges when node 1 no longer matches.
+    ring.nodes[1]
+        .node
+        .kv_set("role".into(), "replica".into())
+        .await
+        .unwrap();
+    let reply = client
+        .raw("TOPOLOGY WALK-FIND role primary db")
+        .await
+        .unwrap();
+    assert_eq!(
+        reply,
+        [
+            format!("FOUND {}", ring.nodes[2].node.port),
+            format!("{p0}->{p1}"),
+            format!("{p1}->{p2}"),
+            "OK".into()
+        ]
+    );
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_match_after_a_full_lap() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    assert_eq!(client.walk_find("role", "primary").await.unwrap(), None);
+    assert_eq!(
+        client.raw("TOPOLOGY WALK-FIND role primary").await.unwrap(),
+        ["NOT FOUND", "OK"]
+    );
+    shutdown(ring).await;
+}
@@ -0,0 +1,74 @@
+//! `TOPOLOGY WALK-PARALLEL`: a node asks every member its heartbeat
+//! coordinator lists for its NEXT at once, and marks the result partial
+//! when one does not answer in time.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{RingOpts, shutdown, spin_up};
+use ouroboros_fs::heartbeat::{HeartbeatCoordinator, Member};
+use ouroboros_fs::{ClientOpts, RingClient, RingError};
+use tokio::net::TcpListener;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn members_are_probed_at_once_and_silent_ones_reported() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let coordinator_addr = listener.local_addr().unwrap().to_string();
+    let coordinator = HeartbeatCoordinator::new(Duration::from_secs(1), ClientOpts::default());
+    tokio::spawn(Arc::clone(&coordinator).serve(listener));
+
+    let ring = spin_up(RingOpts {
+        heartbeat_addr: Some(coordinator_addr),
+        heartbeat_interval: Duration::from_secs(1),
+        parallel_walk_timeout: Duration::from_millis(500),
+        ..RingOpts::default()
+    })
+    .await;
+    let addrs: Vec<String> = (0..3).map(|i| ring.addr(i).to_string()).collect();
+    while coordinator.members().await.len() < 3 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let client = RingClient::new(addrs[0].clone());
+    let topology = client.walk_parallel().await.unwrap();
+    assert!(!topology.partial, "{topology:?}");
+    assert!(topology.unreachable.is_empty());
+    let mut expected: Vec<(String, Vec<String>)> = (0..3)
+        .map(|i| (addrs[i].clone(), vec![addrs[(i + 1) % 3].clone()]))
+        .collect();
+    expected.sort();
+    let got: Vec<(String, Vec<String>)> =
+        topology.nodes.into_iter().map(|n| (n.id, n.next)).collect();
+    assert_eq!(got, expected);
+
+    // A member that accepts connections but never answers.
+    let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let silent_addr = silent.local_addr().unwrap().to_string();
+    coordinator
+        .record(Member {
+            addr: silent_addr.clone(),
+            name: "<unset>".into(),
+            generation: 1,
+            next: None,
+        })
+        .await;
+    let topology = client.walk_parallel().await.unwrap();
+    assert!(topology.partial, "{topology:?}");
+    assert_eq!(topology.unreachable, [silent_addr]);
+    assert_eq!(topology.nodes.len(), 3);
+
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn without_a_coordinator_it_is_an_error() {
+    let ring = spin_up(RingOpts::default()).await;
+    let client = RingClient::new(ring.addr(0).to_string());
+    match client.walk_parallel().await {
+        Err(RingError::Remote { msg, .. }) => assert!(msg.contains("--heartbeat-addr"), "{msg}"),
+        other => panic!("expected ERR, got {other:?}"),
+    }
+    shutdown(ring).await;
+}
@@ -0,0 +1,141 @@
+//! `SUBSCRIBE`: a subscribed connection receives an `EVENT` line for each
+//! matching command the node handles, and `UNSUBSCRIBE` hands it back to
+//! ordinary commands. `--probe-interval-secs` probes add `partition`
+//! events and `--monitor-topology` `topology` events.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{RingOpts, kill_node, shutdown, spin_up};
+use ouroboros_fs::RingClient;
+use ouroboros_fs::client::EventStream;
+use ouroboros_fs::events::EventFilter;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+async fn next(stream: &mut EventStream) -> String {
+    tokio::time::timeout(Duration::from_secs(5), stream.next_event())
+        .await
+        .expect("no event")
+        .unwrap()
+        .expect("stream closed")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscribers_see_the_events_they_asked_for() {
+    let ring = spin_up(RingOpts::default()).await;
+    let node1 = RingClient::new(ring.addr(1).to_string());
+    let mut ring_events = node1.subscribe("ring".parse().unwrap()).await.unwrap();
+    let mut all_events = node1.subscribe(EventFilter::All).await.unwrap();
+
+    let node0 = RingClient::new(ring.addr(0).to_string());
+    node0.ring(1, "hello there").await.unwrap();
+    assert_eq!(
+        next(&mut ring_events).await,
+        "EVENT ring FORWARD 0 hello there"
+    );
+    assert_eq!(
+        next(&mut all_events).await,
+        "EVENT ring FORWARD 0 hello there"
+    );
+
+    let next_addr = ring.addr(2).to_string();
+    node1.set_next(&next_addr).await.unwrap();
+    assert_eq!(
+        next(&mut all_events).await,
+        format!("EVENT set_next default {next_addr}")
+    );
+
+    // The ring-only subscriber skipped the set_next event.
+    node0.ring(1, "again").await.unwrap();
+    assert_eq!(next(&mut ring_events).await, "EVENT ring FORWARD 0 again");
+
+    ring_events.unsubscribe().await.unwrap();
+    all_events.unsubscribe().await.unwrap();
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unsubscribe_returns_the_connection_to_commands() {
+    let ring = spin_up(RingOpts::default()).await;
+    let mut conn = BufReader::new(TcpStream::connect(ring.addr(0)).await.unwrap());
+    let mut reply = String::new();
+    let mut send = async |conn: &mut BufReader<TcpStream>, line: &str| {
+        conn.get_mut().write_all(line.as_bytes()).await.unwrap();
+        reply.clear();
+        conn.read_line(&mut reply).await.unwrap();
+        reply.clone()
+    };
+
+    assert_eq!(
+        send(&mut conn, "UNSUBSCRIBE\n").await,
+        "ERR not subscribed\n"
+    );
+    assert_eq!(send(&mut conn, "SUBSCRIBE walk\n").await, "OK\n");
+    assert_eq!(
+        send(&mut conn, "NODE PING\n").await,
+        "ERR only UNSUBSCRIBE is accepted while subscribed\n"
+    );
+    assert_eq!(send(&mut conn, "UNSUBSCRIBE\n").await, "OK\n");
+    assert_eq!(send(&mut conn, "NODE PING\n").await, "PONG\n");
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn probes_report_a_broken_ring_as_a_partition() {
+    let mut ring = spin_up(RingOpts {
+        probe_interval: Duration::from_millis(100),
+        ..Default::default()
+    })
+    .await;
+    let node0 = RingClient::new(ring.addr(0).to_string());
+    let mut events = node0.subscribe("partition".parse().unwrap()).await.unwrap();
+
+    // A whole ring: every probe comes back.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(ring.nodes[0].node.metrics.partitions_detected(), 0);
+
+    kill_node(&mut ring, 2).await;
+    let event = next(&mut events).await;
+    assert!(
+        event.starts_with("EVENT partition PARTITION_DETECTED "),
+        "event: {event:?}"
+    );
+    assert!(ring.nodes[0].node.metrics.partitions_detected() >= 1);
+
+    events.unsubscribe().await.unwrap();
+    shutdown(ring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn monitor_reports_a_shrunk_ring_as_a_topology_change() {
+    use std::sync::atomic::Ordering;
+    let ring = spin_up(RingOpts {
+        monitor_interval: Duration::from_millis(100),
+        ..Default::default()
+    })
+    .await;
+    let ring_size = || ring.nodes[0].node.ring_size.load(Ordering::Relaxed);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while ring_size() != 3 {
+        assert!(tokio::time::Instant::now() < deadline, "ring never counted");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let node0 = RingClient::new(ring.addr(0).to_string());
+    let mut events = node0.subscribe("topology".parse().unwrap()).await.unwrap();
+
+    // Node 1 skips node 2: the ring is now 0 -> 1 -> 0.
+    RingClient::new(ring.addr(1).to_string())
+        .set_next(&ring.addr(0).to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        "EVENT topology TOPOLOGY_CHANGE old=3 new=2"
+    );
+    assert_eq!(ring_size(), 2);
+
+    events.unsubscribe().await.unwrap();
+    shutdown(ring).await;
+}